@@ -31,7 +31,7 @@ use gpui::{
 };
 use language::{Buffer, Language, LanguageRegistry};
 use language_model::{
-    LanguageModelRequestMessage, LanguageModelToolUseId, MessageContent, Role, StopReason,
+    Citation, LanguageModelRequestMessage, LanguageModelToolUseId, MessageContent, Role, StopReason,
 };
 use markdown::parser::{CodeBlockKind, CodeBlockMetadata};
 use markdown::{
@@ -1447,6 +1447,28 @@ impl ActiveThread {
                             &configured_model.model,
                             cx,
                         ),
+                        seed: AgentSettings::seed_for_model(&configured_model.model, cx),
+                        frequency_penalty: AgentSettings::frequency_penalty_for_model(
+                            &configured_model.model,
+                            cx,
+                        ),
+                        presence_penalty: AgentSettings::presence_penalty_for_model(
+                            &configured_model.model,
+                            cx,
+                        ),
+                        logit_bias: AgentSettings::logit_bias_for_model(
+                            &configured_model.model,
+                            cx,
+                        ),
+                        top_p: None,
+                        top_k: None,
+                        candidates: None,
+                        logprobs: None,
+                        response_schema: None,
+                        parallel_tool_calls: None,
+                        prediction: None,
+                        batch: false,
+                        workspace: None,
                     };
 
                     Some(configured_model.model.count_tokens(request, cx))
@@ -1804,6 +1826,7 @@ impl ActiveThread {
         }
 
         let message_creases = message.creases.clone();
+        let citations = message.citations.clone();
 
         let Some(rendered_message) = self.rendered_messages_by_id.get(&message_id) else {
             return Empty.into_any();
@@ -2139,6 +2162,9 @@ impl ActiveThread {
                     parent.children(tool_uses.into_iter().map(|tool_use| {
                         self.render_tool_use(tool_use, window, workspace.clone(), cx)
                     }))
+                })
+                .when(!citations.is_empty(), |parent| {
+                    parent.child(render_citations(ix, &citations))
                 }),
             Role::System => div().id(("message-container", ix)).py_1().px_2().child(
                 v_flex()
@@ -3508,6 +3534,47 @@ impl Render for ActiveThread {
     }
 }
 
+/// Links to the sources that an assistant message is grounded on, showing each URL once with
+/// the highest confidence that the model gave it.
+fn render_citations(ix: usize, citations: &[Citation]) -> impl IntoElement {
+    let mut sources: Vec<&Citation> = Vec::new();
+    for citation in citations {
+        match sources.iter_mut().find(|source| source.url == citation.url) {
+            Some(source) if citation.confidence > source.confidence => *source = citation,
+            Some(_) => {}
+            None => sources.push(citation),
+        }
+    }
+
+    h_flex()
+        .id(("citations", ix))
+        .gap_1()
+        .flex_wrap()
+        .child(
+            Label::new("Sources")
+                .size(LabelSize::Small)
+                .color(Color::Muted),
+        )
+        .children(sources.into_iter().enumerate().map(|(source_ix, source)| {
+            let url = source.url.clone();
+            let tooltip = match source.confidence {
+                Some(confidence) => format!("{url} ({:.0}% confidence)", confidence * 100.),
+                None => url.clone(),
+            };
+            Button::new(
+                ElementId::named_usize(format!("citation-{ix}"), source_ix),
+                source.title.clone().unwrap_or_else(|| url.clone()),
+            )
+            .icon(IconName::ArrowUpRight)
+            .icon_size(IconSize::XSmall)
+            .icon_position(IconPosition::End)
+            .icon_color(Color::Ignored)
+            .label_size(LabelSize::Small)
+            .tooltip(Tooltip::text(tooltip))
+            .on_click(move |_, _, cx| cx.open_url(&url))
+        }))
+}
+
 pub(crate) fn open_active_thread_as_markdown(
     thread: Entity<Thread>,
     workspace: Entity<Workspace>,
@@ -3636,6 +3703,10 @@ pub(crate) fn open_context(
         ),
 
         AgentContextHandle::Image(_) => {}
+
+        AgentContextHandle::Pdf(_) => {}
+
+        AgentContextHandle::Video(_) => {}
     }
 }
 
@@ -5,6 +5,7 @@ mod agent_model_selector;
 mod agent_panel;
 mod buffer_codegen;
 mod context;
+mod context_overflow;
 mod context_picker;
 mod context_server_configuration;
 mod context_server_tool;
@@ -14,8 +15,10 @@ mod debug;
 mod history_store;
 mod inline_assistant;
 mod inline_prompt_editor;
+mod language_model_warnings;
 mod message_editor;
 mod profile_selector;
+mod request_log_view;
 mod slash_command_settings;
 mod terminal_codegen;
 mod terminal_inline_assistant;
@@ -23,8 +26,10 @@ mod thread;
 mod thread_history;
 mod thread_store;
 mod tool_compatibility;
+mod tool_result_limit;
 mod tool_use;
 mod ui;
+mod usage_history_view;
 
 use std::sync::Arc;
 
@@ -153,6 +158,9 @@ pub fn init(
         cx,
     );
     indexed_docs::init(cx);
+    language_model_warnings::init(cx);
+    request_log_view::init(cx);
+    usage_history_view::init(cx);
     cx.observe_new(AddContextServerModal::register).detach();
     cx.observe_new(ManageProfilesModal::register).detach();
 }
@@ -138,11 +138,13 @@ impl AgentConfiguration {
             .get(&provider.id())
             .cloned();
 
-        let is_expanded = self
-            .expanded_provider_configurations
-            .get(&provider.id())
-            .copied()
-            .unwrap_or(false);
+        let is_enabled = LanguageModelRegistry::read_global(cx).is_provider_enabled(&provider.id());
+        let is_expanded = is_enabled
+            && self
+                .expanded_provider_configurations
+                .get(&provider.id())
+                .copied()
+                .unwrap_or(false);
 
         v_flex()
             .pt_3()
@@ -161,14 +163,17 @@ impl AgentConfiguration {
                                     .color(Color::Muted),
                             )
                             .child(Label::new(provider_name.clone()).size(LabelSize::Large))
-                            .when(provider.is_authenticated(cx) && !is_expanded, |parent| {
-                                parent.child(Icon::new(IconName::Check).color(Color::Success))
-                            }),
+                            .when(
+                                is_enabled && provider.is_authenticated(cx) && !is_expanded,
+                                |parent| {
+                                    parent.child(Icon::new(IconName::Check).color(Color::Success))
+                                },
+                            ),
                     )
                     .child(
                         h_flex()
                             .gap_1()
-                            .when(provider.is_authenticated(cx), |parent| {
+                            .when(is_enabled && provider.is_authenticated(cx), |parent| {
                                 parent.child(
                                     Button::new(
                                         SharedString::from(format!("new-thread-{provider_id}")),
@@ -190,26 +195,47 @@ impl AgentConfiguration {
                                 )
                             })
                             .child(
-                                Disclosure::new(
-                                    SharedString::from(format!(
-                                        "provider-disclosure-{provider_id}"
-                                    )),
-                                    is_expanded,
+                                Switch::new(
+                                    SharedString::from(format!("provider-enabled-{provider_id}")),
+                                    is_enabled.into(),
                                 )
-                                .opened_icon(IconName::ChevronUp)
-                                .closed_icon(IconName::ChevronDown)
-                                .on_click(cx.listener({
-                                    let provider_id = provider.id().clone();
-                                    move |this, _event, _window, _cx| {
-                                        let is_expanded = this
-                                            .expanded_provider_configurations
-                                            .entry(provider_id.clone())
-                                            .or_insert(false);
-
-                                        *is_expanded = !*is_expanded;
+                                .color(SwitchColor::Accent)
+                                .on_click({
+                                    let fs = self.fs.clone();
+                                    let provider_id = provider.id();
+                                    move |state, _window, cx| {
+                                        language_models::set_provider_enabled(
+                                            provider_id.clone(),
+                                            state == &ToggleState::Selected,
+                                            fs.clone(),
+                                            cx,
+                                        );
                                     }
-                                })),
-                            ),
+                                }),
+                            )
+                            .when(is_enabled, |parent| {
+                                parent.child(
+                                    Disclosure::new(
+                                        SharedString::from(format!(
+                                            "provider-disclosure-{provider_id}"
+                                        )),
+                                        is_expanded,
+                                    )
+                                    .opened_icon(IconName::ChevronUp)
+                                    .closed_icon(IconName::ChevronDown)
+                                    .on_click(cx.listener({
+                                        let provider_id = provider.id().clone();
+                                        move |this, _event, _window, _cx| {
+                                            let is_expanded = this
+                                                .expanded_provider_configurations
+                                                .entry(provider_id.clone())
+                                                .or_insert(false);
+
+                                            *is_expanded = !*is_expanded;
+                                        }
+                                    })),
+                                )
+                            }),
                     ),
             )
             .when(is_expanded, |parent| match configuration_view {
@@ -224,7 +250,7 @@ impl AgentConfiguration {
         &mut self,
         cx: &mut Context<Self>,
     ) -> impl IntoElement {
-        let providers = LanguageModelRegistry::read_global(cx).providers();
+        let providers = LanguageModelRegistry::read_global(cx).all_providers();
 
         v_flex()
             .p(DynamicSpacing::Base16.rems(cx))
@@ -1349,6 +1349,7 @@ impl AgentDiff {
             | ThreadEvent::Stopped(Ok(StopReason::EndTurn))
             | ThreadEvent::Stopped(Ok(StopReason::MaxTokens))
             | ThreadEvent::Stopped(Ok(StopReason::Refusal))
+            | ThreadEvent::Stopped(Ok(StopReason::ContentFilter))
             | ThreadEvent::Stopped(Err(_))
             | ThreadEvent::ShowError(_)
             | ThreadEvent::CompletionCanceled => {
@@ -31,6 +31,7 @@ use language::LanguageRegistry;
 use language_model::{
     LanguageModelProviderTosView, LanguageModelRegistry, RequestUsage, ZED_CLOUD_PROVIDER_ID,
 };
+use language_models::OverrideSpendLimits;
 use project::{Project, ProjectPath, Worktree};
 use prompt_store::{PromptBuilder, PromptStore, UserPromptId};
 use proto::Plan;
@@ -695,6 +696,8 @@ impl AgentPanel {
                         .clone()
                         .update(cx, |thread, cx| thread.get_or_init_configured_model(cx));
                 }
+                // Shows or hides the banner about the provider rejecting its API key.
+                language_model::Event::ProviderStateChanged => cx.notify(),
                 _ => {}
             },
         );
@@ -2718,6 +2721,49 @@ impl AgentPanel {
         Some(div().px_2().pb_2().child(banner).into_any_element())
     }
 
+    fn render_rejected_credentials(
+        &self,
+        window: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> Option<AnyElement> {
+        let provider = self
+            .thread
+            .read(cx)
+            .thread()
+            .read(cx)
+            .configured_model()?
+            .provider;
+        if !provider.credentials_rejected(cx) {
+            return None;
+        }
+
+        let focus_handle = self.focus_handle(cx);
+
+        let banner = Banner::new()
+            .severity(ui::Severity::Error)
+            .child(
+                Label::new(format!(
+                    "{} rejected your API key. Update it to keep using the provider.",
+                    provider.name().0
+                ))
+                .size(LabelSize::Small),
+            )
+            .action_slot(
+                Button::new("update-api-key", "Update Key")
+                    .style(ButtonStyle::Tinted(ui::TintColor::Error))
+                    .label_size(LabelSize::Small)
+                    .key_binding(
+                        KeyBinding::for_action_in(&OpenConfiguration, &focus_handle, window, cx)
+                            .map(|kb| kb.size(rems_from_px(12.))),
+                    )
+                    .on_click(|_event, window, cx| {
+                        window.dispatch_action(OpenConfiguration.boxed_clone(), cx)
+                    }),
+            );
+
+        Some(div().px_2().pb_2().child(banner).into_any_element())
+    }
+
     fn render_last_error(&self, cx: &mut Context<Self>) -> Option<AnyElement> {
         let last_error = self.thread.read(cx).last_error()?;
 
@@ -2736,6 +2782,9 @@ impl AgentPanel {
                     ThreadError::ModelRequestLimitReached { plan } => {
                         self.render_model_request_limit_reached_error(plan, cx)
                     }
+                    ThreadError::SpendLimitReached { message } => {
+                        self.render_spend_limit_reached_error(message, cx)
+                    }
                     ThreadError::Message { header, message } => {
                         self.render_error_message(header, message, cx)
                     }
@@ -2859,6 +2908,58 @@ impl AgentPanel {
             .into_any()
     }
 
+    fn render_spend_limit_reached_error(
+        &self,
+        message: SharedString,
+        cx: &mut Context<Self>,
+    ) -> AnyElement {
+        v_flex()
+            .gap_0p5()
+            .child(
+                h_flex()
+                    .gap_1p5()
+                    .items_center()
+                    .child(Icon::new(IconName::XCircle).color(Color::Error))
+                    .child(Label::new("Spend Limit Reached").weight(FontWeight::MEDIUM)),
+            )
+            .child(
+                div()
+                    .id("error-message")
+                    .max_h_24()
+                    .overflow_y_scroll()
+                    .child(Label::new(message.clone())),
+            )
+            .child(
+                h_flex()
+                    .justify_end()
+                    .mt_1()
+                    .gap_1()
+                    .child(self.create_copy_button(message.to_string()))
+                    .child(
+                        Button::new("override-spend-limits", "Allow for Today").on_click(
+                            cx.listener(|this, _, window, cx| {
+                                this.thread.update(cx, |this, _cx| {
+                                    this.clear_last_error();
+                                });
+
+                                window.dispatch_action(OverrideSpendLimits.boxed_clone(), cx);
+                                cx.notify();
+                            }),
+                        ),
+                    )
+                    .child(Button::new("dismiss", "Dismiss").on_click(cx.listener(
+                        |this, _, _, cx| {
+                            this.thread.update(cx, |this, _cx| {
+                                this.clear_last_error();
+                            });
+
+                            cx.notify();
+                        },
+                    ))),
+            )
+            .into_any()
+    }
+
     fn render_error_message(
         &self,
         header: SharedString,
@@ -3117,6 +3218,7 @@ impl Render for AgentPanel {
                     .relative()
                     .child(self.render_active_thread_or_empty_state(window, cx))
                     .children(self.render_tool_use_limit_reached(window, cx))
+                    .children(self.render_rejected_credentials(window, cx))
                     .child(h_flex().child(self.message_editor.clone()))
                     .children(self.render_last_error(cx))
                     .child(self.render_drag_target(cx)),
@@ -426,6 +426,9 @@ impl CodegenAlternative {
             anyhow::bail!("invalid transformation range");
         };
 
+        // When rewriting a selection, most of it is usually kept.
+        let prediction =
+            (!range.is_empty()).then(|| buffer.text_for_range(range.clone()).collect::<String>());
         let prompt = self
             .builder
             .generate_inline_transformation_prompt(user_prompt, language_name, buffer, range)
@@ -445,6 +448,14 @@ impl CodegenAlternative {
         });
 
         let temperature = AgentSettings::temperature_for_model(&model, cx);
+        let seed = AgentSettings::seed_for_model(&model, cx);
+        let frequency_penalty = AgentSettings::frequency_penalty_for_model(&model, cx);
+        let presence_penalty = AgentSettings::presence_penalty_for_model(&model, cx);
+        let logit_bias = AgentSettings::logit_bias_for_model(&model, cx);
+        let workspace = self
+            .project
+            .upgrade()
+            .and_then(|project| language_models::api_key::workspace_scope(project.read(cx), cx));
 
         Ok(cx.spawn(async move |_cx| {
             let mut request_message = LanguageModelRequestMessage {
@@ -471,6 +482,19 @@ impl CodegenAlternative {
                 tool_choice: None,
                 stop: Vec::new(),
                 temperature,
+                seed,
+                frequency_penalty,
+                presence_penalty,
+                logit_bias,
+                top_p: None,
+                top_k: None,
+                candidates: None,
+                logprobs: None,
+                response_schema: None,
+                parallel_tool_calls: None,
+                prediction,
+                batch: false,
+                workspace,
                 messages: vec![request_message],
             }
         }))
@@ -12,7 +12,10 @@ use futures::future;
 use futures::{FutureExt, future::Shared};
 use gpui::{App, AppContext as _, Entity, SharedString, Subscription, Task};
 use language::{Buffer, ParseStatus};
-use language_model::{LanguageModelImage, LanguageModelRequestMessage, MessageContent};
+use language_model::{
+    LanguageModelDocument, LanguageModelImage, LanguageModelPdf, LanguageModelRequestMessage,
+    LanguageModelVideo, MessageContent,
+};
 use project::{Project, ProjectEntryId, ProjectPath, Worktree};
 use prompt_store::{PromptStore, UserPromptId};
 use ref_cast::RefCast;
@@ -37,6 +40,8 @@ pub enum ContextKind {
     TextThread,
     Rules,
     Image,
+    Pdf,
+    Video,
 }
 
 impl ContextKind {
@@ -51,6 +56,8 @@ impl ContextKind {
             ContextKind::TextThread => IconName::MessageBubbles,
             ContextKind::Rules => RULES_ICON,
             ContextKind::Image => IconName::Image,
+            ContextKind::Pdf => IconName::FileDoc,
+            ContextKind::Video => IconName::Play,
         }
     }
 }
@@ -71,6 +78,8 @@ pub enum AgentContextHandle {
     TextThread(TextThreadContextHandle),
     Rules(RulesContextHandle),
     Image(ImageContext),
+    Pdf(PdfContext),
+    Video(VideoContext),
 }
 
 impl AgentContextHandle {
@@ -85,6 +94,8 @@ impl AgentContextHandle {
             Self::TextThread(context) => context.context_id,
             Self::Rules(context) => context.context_id,
             Self::Image(context) => context.context_id,
+            Self::Pdf(context) => context.context_id,
+            Self::Video(context) => context.context_id,
         }
     }
 
@@ -106,6 +117,8 @@ pub enum AgentContext {
     TextThread(TextThreadContext),
     Rules(RulesContext),
     Image(ImageContext),
+    Pdf(PdfContext),
+    Video(VideoContext),
 }
 
 impl AgentContext {
@@ -126,6 +139,8 @@ impl AgentContext {
             }
             AgentContext::Rules(context) => AgentContextHandle::Rules(context.handle.clone()),
             AgentContext::Image(context) => AgentContextHandle::Image(context.clone()),
+            AgentContext::Pdf(context) => AgentContextHandle::Pdf(context.clone()),
+            AgentContext::Video(context) => AgentContextHandle::Video(context.clone()),
         }
     }
 }
@@ -776,6 +791,82 @@ impl ImageContext {
     }
 }
 
+/// A PDF from the project, which models that can read PDFs get as a document.
+#[derive(Debug, Clone)]
+pub struct PdfContext {
+    pub project_path: ProjectPath,
+    pub full_path: Arc<Path>,
+    pub pdf_task: Shared<Task<Option<LanguageModelPdf>>>,
+    pub context_id: ContextId,
+}
+
+impl PdfContext {
+    pub fn eq_for_key(&self, other: &Self) -> bool {
+        self.project_path == other.project_path
+    }
+
+    pub fn hash_for_key<H: Hasher>(&self, state: &mut H) {
+        self.project_path.hash(state);
+    }
+
+    pub fn pdf(&self) -> Option<LanguageModelPdf> {
+        self.pdf_task.clone().now_or_never().flatten()
+    }
+
+    pub fn status(&self) -> ImageStatus {
+        match self.pdf_task.clone().now_or_never() {
+            None => ImageStatus::Loading,
+            Some(None) => ImageStatus::Error,
+            Some(Some(_)) => ImageStatus::Ready,
+        }
+    }
+
+    pub fn load(self, cx: &App) -> Task<Option<(AgentContext, Vec<Entity<Buffer>>)>> {
+        cx.background_spawn(async move {
+            self.pdf_task.clone().await;
+            Some((AgentContext::Pdf(self), vec![]))
+        })
+    }
+}
+
+/// A video clip from the project, for models that can watch video.
+#[derive(Debug, Clone)]
+pub struct VideoContext {
+    pub project_path: ProjectPath,
+    pub full_path: Arc<Path>,
+    pub video_task: Shared<Task<Option<LanguageModelVideo>>>,
+    pub context_id: ContextId,
+}
+
+impl VideoContext {
+    pub fn eq_for_key(&self, other: &Self) -> bool {
+        self.project_path == other.project_path
+    }
+
+    pub fn hash_for_key<H: Hasher>(&self, state: &mut H) {
+        self.project_path.hash(state);
+    }
+
+    pub fn video(&self) -> Option<LanguageModelVideo> {
+        self.video_task.clone().now_or_never().flatten()
+    }
+
+    pub fn status(&self) -> ImageStatus {
+        match self.video_task.clone().now_or_never() {
+            None => ImageStatus::Loading,
+            Some(None) => ImageStatus::Error,
+            Some(Some(_)) => ImageStatus::Ready,
+        }
+    }
+
+    pub fn load(self, cx: &App) -> Task<Option<(AgentContext, Vec<Entity<Buffer>>)>> {
+        cx.background_spawn(async move {
+            self.video_task.clone().await;
+            Some((AgentContext::Video(self), vec![]))
+        })
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct ContextLoadResult {
     pub loaded_context: LoadedContext,
@@ -787,11 +878,19 @@ pub struct LoadedContext {
     pub contexts: Vec<AgentContext>,
     pub text: String,
     pub images: Vec<LanguageModelImage>,
+    /// Fetched URLs, which are sent as documents so that models can cite them.
+    pub documents: Vec<LanguageModelDocument>,
+    pub pdfs: Vec<LanguageModelPdf>,
+    pub videos: Vec<LanguageModelVideo>,
 }
 
 impl LoadedContext {
     pub fn is_empty(&self) -> bool {
-        self.text.is_empty() && self.images.is_empty()
+        self.text.is_empty()
+            && self.images.is_empty()
+            && self.documents.is_empty()
+            && self.pdfs.is_empty()
+            && self.videos.is_empty()
     }
 
     pub fn add_to_request_message(&self, request_message: &mut LanguageModelRequestMessage) {
@@ -801,6 +900,24 @@ impl LoadedContext {
                 .push(MessageContent::Text(self.text.to_string()));
         }
 
+        for document in &self.documents {
+            request_message
+                .content
+                .push(MessageContent::Document(document.clone()));
+        }
+
+        for pdf in &self.pdfs {
+            request_message
+                .content
+                .push(MessageContent::Pdf(pdf.clone()));
+        }
+
+        for video in &self.videos {
+            request_message
+                .content
+                .push(MessageContent::Video(video.clone()));
+        }
+
         if !self.images.is_empty() {
             // Some providers only support image parts after an initial text part
             if request_message.content.is_empty() {
@@ -837,6 +954,8 @@ pub fn load_context(
             AgentContextHandle::TextThread(context) => context.load(cx),
             AgentContextHandle::Rules(context) => context.load(prompt_store, cx),
             AgentContextHandle::Image(context) => context.load(cx),
+            AgentContextHandle::Pdf(context) => context.load(cx),
+            AgentContextHandle::Video(context) => context.load(cx),
         })
         .collect();
 
@@ -858,32 +977,39 @@ pub fn load_context(
         let mut directory_context = Vec::new();
         let mut symbol_context = Vec::new();
         let mut selection_context = Vec::new();
-        let mut fetched_url_context = Vec::new();
+        let mut documents = Vec::new();
         let mut thread_context = Vec::new();
         let mut text_thread_context = Vec::new();
         let mut rules_context = Vec::new();
         let mut images = Vec::new();
+        let mut pdfs = Vec::new();
+        let mut videos = Vec::new();
         for context in &contexts {
             match context {
                 AgentContext::File(context) => file_context.push(context),
                 AgentContext::Directory(context) => directory_context.push(context),
                 AgentContext::Symbol(context) => symbol_context.push(context),
                 AgentContext::Selection(context) => selection_context.push(context),
-                AgentContext::FetchedUrl(context) => fetched_url_context.push(context),
+                AgentContext::FetchedUrl(context) => documents.push(LanguageModelDocument {
+                    source: context.url.to_string(),
+                    title: None,
+                    text: context.text.to_string(),
+                }),
                 AgentContext::Thread(context) => thread_context.push(context),
                 AgentContext::TextThread(context) => text_thread_context.push(context),
                 AgentContext::Rules(context) => rules_context.push(context),
                 AgentContext::Image(context) => images.extend(context.image()),
+                AgentContext::Pdf(context) => pdfs.extend(context.pdf()),
+                AgentContext::Video(context) => videos.extend(context.video()),
             }
         }
 
-        // Use empty text if there are no contexts that contribute to text (everything but image
-        // context).
+        // Use empty text if there are no contexts that contribute to text (everything but image, PDF,
+        // video, and fetched URL context).
         if file_context.is_empty()
             && directory_context.is_empty()
             && symbol_context.is_empty()
             && selection_context.is_empty()
-            && fetched_url_context.is_empty()
             && thread_context.is_empty()
             && text_thread_context.is_empty()
             && rules_context.is_empty()
@@ -893,6 +1019,9 @@ pub fn load_context(
                     contexts,
                     text,
                     images,
+                    documents,
+                    pdfs,
+                    videos,
                 },
                 referenced_buffers,
             };
@@ -940,15 +1069,6 @@ pub fn load_context(
             text.push_str("</selections>\n");
         }
 
-        if !fetched_url_context.is_empty() {
-            text.push_str("<fetched_urls>");
-            for context in fetched_url_context {
-                text.push('\n');
-                let _ = write!(text, "{context}");
-            }
-            text.push_str("</fetched_urls>\n");
-        }
-
         if !thread_context.is_empty() {
             text.push_str("<conversation_threads>");
             for context in thread_context {
@@ -986,6 +1106,9 @@ pub fn load_context(
                 contexts,
                 text,
                 images,
+                documents,
+                pdfs,
+                videos,
             },
             referenced_buffers,
         }
@@ -1088,6 +1211,16 @@ impl PartialEq for AgentContextKey {
                     return context.eq_for_key(other_context);
                 }
             }
+            AgentContextHandle::Pdf(context) => {
+                if let AgentContextHandle::Pdf(other_context) = &other.0 {
+                    return context.eq_for_key(other_context);
+                }
+            }
+            AgentContextHandle::Video(context) => {
+                if let AgentContextHandle::Video(other_context) = &other.0 {
+                    return context.eq_for_key(other_context);
+                }
+            }
         }
         false
     }
@@ -1105,6 +1238,8 @@ impl Hash for AgentContextKey {
             AgentContextHandle::TextThread(context) => context.hash_for_key(state),
             AgentContextHandle::Rules(context) => context.hash_for_key(state),
             AgentContextHandle::Image(context) => context.hash_for_key(state),
+            AgentContextHandle::Pdf(context) => context.hash_for_key(state),
+            AgentContextHandle::Video(context) => context.hash_for_key(state),
         }
     }
 }
@@ -0,0 +1,228 @@
+use std::hash::{DefaultHasher, Hash as _, Hasher as _};
+use std::sync::Arc;
+
+use agent_settings::ContextOverflowStrategy;
+use anyhow::{Result, anyhow};
+use futures::StreamExt as _;
+use gpui::AsyncApp;
+use language_model::{
+    LanguageModel, LanguageModelKnownError, LanguageModelRequest, LanguageModelRequestMessage,
+    MessageContent, Role,
+};
+use parking_lot::Mutex;
+use zed_llm_client::CompletionIntent;
+
+/// The tokens set aside for the summary of the turns that are left out, when summarizing them.
+const SUMMARY_TOKEN_RESERVE: usize = 2048;
+
+/// The summary of the turns that were last left out of a thread's requests, so that the following
+/// requests of the thread don't summarize them again.
+#[derive(Clone, Default)]
+pub struct OverflowSummaryCache(Arc<Mutex<Option<(u64, String)>>>);
+
+/// Fits `request` into `model`'s context window with `strategy`, by leaving out or summarizing
+/// the oldest turns of the conversation. Fails with
+/// [`LanguageModelKnownError::ContextWindowLimitExceeded`] when the request can't be made to fit.
+///
+/// With [`ContextOverflowStrategy::Error`], the request is sent as is, and the provider rejects it
+/// if it's too long, which saves counting the tokens of every request.
+pub async fn fit_to_context_window(
+    request: LanguageModelRequest,
+    model: &Arc<dyn LanguageModel>,
+    strategy: ContextOverflowStrategy,
+    summary_model: Option<Arc<dyn LanguageModel>>,
+    summary_cache: &OverflowSummaryCache,
+    cx: &AsyncApp,
+) -> Result<LanguageModelRequest> {
+    let summary_model = match strategy {
+        ContextOverflowStrategy::Error => return Ok(request),
+        ContextOverflowStrategy::DropOldest => None,
+        ContextOverflowStrategy::Summarize => summary_model,
+    };
+    let max_tokens = model.max_token_count();
+    let tokens = count_tokens(&request, model, cx).await?;
+    if tokens <= max_tokens {
+        return Ok(request);
+    }
+    let overflow_error = anyhow!(LanguageModelKnownError::ContextWindowLimitExceeded { tokens });
+
+    let budget = if summary_model.is_some() {
+        max_tokens.saturating_sub(SUMMARY_TOKEN_RESERVE)
+    } else {
+        max_tokens
+    };
+
+    // Find the fewest of the oldest turns that have to be left out for the request to fit. The
+    // last turn is always kept.
+    let starts = turn_starts(&request.messages);
+    let mut fits = None;
+    let (mut low, mut high) = (1, starts.len());
+    while low < high {
+        let mid = (low + high) / 2;
+        let candidate = without_turns(&request, starts[0]..starts[mid]);
+        if count_tokens(&candidate, model, cx).await? <= budget {
+            fits = Some((mid, candidate));
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    let Some((dropped_turns, mut fitted)) = fits else {
+        return Err(overflow_error);
+    };
+
+    let Some(summary_model) = summary_model else {
+        return Ok(fitted);
+    };
+    let dropped = &request.messages[starts[0]..starts[dropped_turns]];
+    match summarize_turns(dropped, &summary_model, summary_cache, cx).await {
+        Ok(summary) => {
+            fitted.messages.insert(
+                starts[0],
+                LanguageModelRequestMessage {
+                    role: Role::User,
+                    content: vec![MessageContent::Text(format!(
+                        "The start of this conversation was left out to fit the context window. \
+                         Here's a summary of it:\n\n{summary}"
+                    ))],
+                    cache: false,
+                },
+            );
+        }
+        Err(error) => {
+            log::error!("failed to summarize the turns left out of a request: {error:#}");
+        }
+    }
+    Ok(fitted)
+}
+
+pub(crate) async fn count_tokens(
+    request: &LanguageModelRequest,
+    model: &Arc<dyn LanguageModel>,
+    cx: &AsyncApp,
+) -> Result<usize> {
+    cx.update(|cx| model.count_tokens(request.clone(), cx))?
+        .await
+}
+
+/// The index of the message that each turn of the conversation starts at. A turn starts with a
+/// message from the user that isn't only returning tool results.
+fn turn_starts(messages: &[LanguageModelRequestMessage]) -> Vec<usize> {
+    messages
+        .iter()
+        .enumerate()
+        .filter(|(_, message)| {
+            message.role == Role::User
+                && !message
+                    .content
+                    .iter()
+                    .any(|content| matches!(content, MessageContent::ToolResult(_)))
+        })
+        .map(|(ix, _)| ix)
+        .collect()
+}
+
+fn without_turns(
+    request: &LanguageModelRequest,
+    range: std::ops::Range<usize>,
+) -> LanguageModelRequest {
+    let mut request = request.clone();
+    request.messages.drain(range);
+    request
+}
+
+async fn summarize_turns(
+    messages: &[LanguageModelRequestMessage],
+    model: &Arc<dyn LanguageModel>,
+    cache: &OverflowSummaryCache,
+    cx: &AsyncApp,
+) -> Result<String> {
+    let mut hasher = DefaultHasher::new();
+    messages.hash(&mut hasher);
+    let key = hasher.finish();
+    if let Some((cached_key, summary)) = cache.0.lock().as_ref() {
+        if *cached_key == key {
+            return Ok(summary.clone());
+        }
+    }
+
+    let mut request_messages = messages
+        .iter()
+        .filter_map(|message| {
+            let content = message
+                .content
+                .iter()
+                .filter(|content| matches!(content, MessageContent::Text(_)))
+                .cloned()
+                .collect::<Vec<_>>();
+            (!content.is_empty()).then(|| LanguageModelRequestMessage {
+                role: message.role,
+                content,
+                cache: false,
+            })
+        })
+        .collect::<Vec<_>>();
+    request_messages.push(LanguageModelRequestMessage {
+        role: Role::User,
+        content: vec![MessageContent::Text(
+            include_str!("./prompts/summarize_thread_detailed_prompt.txt").into(),
+        )],
+        cache: false,
+    });
+    let summary = summarize(request_messages, model, cx).await?;
+    *cache.0.lock() = Some((key, summary.clone()));
+    Ok(summary)
+}
+
+/// The summary that `model` writes of `messages`, which end with the instructions for it.
+pub(crate) async fn summarize(
+    messages: Vec<LanguageModelRequestMessage>,
+    model: &Arc<dyn LanguageModel>,
+    cx: &AsyncApp,
+) -> Result<String> {
+    let request = LanguageModelRequest {
+        intent: Some(CompletionIntent::ThreadContextSummarization),
+        messages,
+        ..Default::default()
+    };
+
+    let mut stream = model.stream_completion_text(request, cx).await?.stream;
+    let mut summary = String::new();
+    while let Some(chunk) = stream.next().await {
+        summary.push_str(&chunk?);
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: Role, content: MessageContent) -> LanguageModelRequestMessage {
+        LanguageModelRequestMessage {
+            role,
+            content: vec![content],
+            cache: false,
+        }
+    }
+
+    #[test]
+    fn test_turn_starts() {
+        let tool_result = MessageContent::ToolResult(language_model::LanguageModelToolResult {
+            tool_use_id: "tool_1".into(),
+            tool_name: "read_file".into(),
+            is_error: false,
+            content: "fn main() {}".into(),
+            output: None,
+        });
+        let messages = vec![
+            message(Role::System, "You are a coding agent.".into()),
+            message(Role::User, "Read main.rs".into()),
+            message(Role::Assistant, "Reading it.".into()),
+            message(Role::User, tool_result),
+            message(Role::Assistant, "It's empty.".into()),
+            message(Role::User, "Add a test".into()),
+        ];
+        assert_eq!(turn_starts(&messages), vec![1, 5]);
+    }
+}
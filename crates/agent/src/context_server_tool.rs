@@ -1,10 +1,14 @@
 use std::sync::Arc;
 
 use anyhow::{Result, anyhow, bail};
-use assistant_tool::{ActionLog, Tool, ToolResult, ToolSource};
+use assistant_tool::{
+    ActionLog, Tool, ToolResult, ToolResultContent, ToolResultOutput, ToolSource,
+};
 use context_server::{ContextServerId, types};
 use gpui::{AnyWindowHandle, App, Entity, Task};
-use language_model::{LanguageModel, LanguageModelRequest, LanguageModelToolSchemaFormat};
+use language_model::{
+    LanguageModel, LanguageModelImage, LanguageModelRequest, LanguageModelToolSchemaFormat,
+};
 use project::{Project, context_server_store::ContextServerStore};
 use ui::IconName;
 
@@ -103,20 +107,42 @@ impl Tool for ContextServerTool {
                 let response = protocol.run_tool(tool_name, arguments).await?;
 
                 let mut result = String::new();
+                let mut image = None;
                 for content in response.content {
                     match content {
                         types::ToolResponseContent::Text { text } => {
                             result.push_str(&text);
                         }
-                        types::ToolResponseContent::Image { .. } => {
-                            log::warn!("Ignoring image content from tool response");
+                        types::ToolResponseContent::Image { data, mime_type } => {
+                            if image.is_some() {
+                                log::warn!("Ignoring additional image content from tool response");
+                                continue;
+                            }
+                            match LanguageModelImage::from_base64(&data) {
+                                Ok(decoded) => image = Some(decoded),
+                                Err(error) => log::warn!(
+                                    "Ignoring {mime_type} image from tool response that couldn't be read: {error}"
+                                ),
+                            }
                         }
                         types::ToolResponseContent::Resource { .. } => {
                             log::warn!("Ignoring resource content from tool response");
                         }
                     }
                 }
-                Ok(result.into())
+
+                // A tool result holds either text or an image, and images like screenshots are
+                // usually the point of the tools that return them.
+                let Some(image) = image else {
+                    return Ok(result.into());
+                };
+                if !result.is_empty() {
+                    log::warn!("Ignoring text content from tool response with an image");
+                }
+                Ok(ToolResultOutput {
+                    content: ToolResultContent::Image(image),
+                    output: None,
+                })
             })
             .into()
         } else {
@@ -8,22 +8,37 @@ use collections::{HashSet, IndexSet};
 use futures::{self, FutureExt};
 use gpui::{App, Context, Entity, EventEmitter, Image, SharedString, Task, WeakEntity};
 use language::Buffer;
-use language_model::LanguageModelImage;
+use language_model::{LanguageModelImage, LanguageModelPdf, LanguageModelVideo};
 use project::image_store::is_image_file;
 use project::{Project, ProjectItem, ProjectPath, Symbol};
 use prompt_store::UserPromptId;
 use ref_cast::RefCast as _;
 use text::{Anchor, OffsetRangeExt};
+use util::ResultExt as _;
 
 use crate::ThreadStore;
 use crate::context::{
     AgentContextHandle, AgentContextKey, ContextId, DirectoryContextHandle, FetchedUrlContext,
-    FileContextHandle, ImageContext, RulesContextHandle, SelectionContextHandle,
-    SymbolContextHandle, TextThreadContextHandle, ThreadContextHandle,
+    FileContextHandle, ImageContext, PdfContext, RulesContextHandle, SelectionContextHandle,
+    SymbolContextHandle, TextThreadContextHandle, ThreadContextHandle, VideoContext,
 };
 use crate::context_strip::SuggestedContext;
 use crate::thread::{MessageId, Thread, ThreadId};
 
+fn is_pdf_file(path: &ProjectPath) -> bool {
+    path.path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"))
+}
+
+fn is_video_file(path: &ProjectPath) -> bool {
+    path.path.extension().is_some_and(|extension| {
+        ["mp4", "mov", "webm"]
+            .iter()
+            .any(|video_extension| extension.eq_ignore_ascii_case(video_extension))
+    })
+}
+
 pub struct ContextStore {
     project: WeakEntity<Project>,
     thread_store: Option<WeakEntity<ThreadStore>>,
@@ -99,6 +114,18 @@ impl ContextStore {
 
         if is_image_file(&project, &project_path, cx) {
             self.add_image_from_path(project_path, remove_if_exists, cx)
+        } else if is_pdf_file(&project_path) {
+            Task::ready(Ok(self.add_pdf_from_path(
+                project_path,
+                remove_if_exists,
+                cx,
+            )))
+        } else if is_video_file(&project_path) {
+            Task::ready(Ok(self.add_video_from_path(
+                project_path,
+                remove_if_exists,
+                cx,
+            )))
         } else {
             cx.spawn(async move |this, cx| {
                 let open_buffer_task = project.update(cx, |project, cx| {
@@ -345,6 +372,99 @@ impl ContextStore {
         Some(context)
     }
 
+    /// Adds a PDF from the project. It's read as-is, so PDFs are only supported in local projects.
+    pub fn add_pdf_from_path(
+        &mut self,
+        project_path: ProjectPath,
+        remove_if_exists: bool,
+        cx: &mut Context<ContextStore>,
+    ) -> Option<AgentContextHandle> {
+        let project = self.project.upgrade()?;
+        let project = project.read(cx);
+        let full_path: Arc<Path> = project
+            .worktree_for_id(project_path.worktree_id, cx)?
+            .read(cx)
+            .full_path(&project_path.path)
+            .into();
+        let abs_path = project.absolute_path(&project_path, cx);
+        let fs = project.fs().clone();
+        let name = full_path
+            .file_name()
+            .unwrap_or(full_path.as_os_str())
+            .to_string_lossy()
+            .into_owned();
+        let load_pdf = async move {
+            let abs_path = abs_path.context("PDFs can only be attached in local projects")?;
+            let bytes = fs.load_bytes(&abs_path).await?;
+            LanguageModelPdf::from_bytes(name, &bytes)
+        };
+        let pdf_task = cx
+            .background_spawn(async move { load_pdf.await.log_err() })
+            .shared();
+        let context = AgentContextHandle::Pdf(PdfContext {
+            project_path,
+            full_path,
+            pdf_task,
+            context_id: self.next_context_id.post_inc(),
+        });
+        if self.has_context(&context) {
+            if remove_if_exists {
+                self.remove_context(&context, cx);
+                return None;
+            }
+        }
+
+        self.insert_context(context.clone(), cx);
+        Some(context)
+    }
+
+    /// Adds an MP4, QuickTime, or WebM video from the project. Like PDFs, videos are only
+    /// supported in local projects.
+    pub fn add_video_from_path(
+        &mut self,
+        project_path: ProjectPath,
+        remove_if_exists: bool,
+        cx: &mut Context<ContextStore>,
+    ) -> Option<AgentContextHandle> {
+        let project = self.project.upgrade()?;
+        let project = project.read(cx);
+        let full_path: Arc<Path> = project
+            .worktree_for_id(project_path.worktree_id, cx)?
+            .read(cx)
+            .full_path(&project_path.path)
+            .into();
+        let abs_path = project.absolute_path(&project_path, cx);
+        let fs = project.fs().clone();
+        let name = full_path
+            .file_name()
+            .unwrap_or(full_path.as_os_str())
+            .to_string_lossy()
+            .into_owned();
+        let load_video = async move {
+            let abs_path = abs_path.context("videos can only be attached in local projects")?;
+            let bytes = fs.load_bytes(&abs_path).await?;
+            LanguageModelVideo::from_bytes(name, &bytes)
+        };
+        let video_task = cx
+            .background_spawn(async move { load_video.await.log_err() })
+            .shared();
+        let context = AgentContextHandle::Video(VideoContext {
+            project_path,
+            full_path,
+            video_task,
+            context_id: self.next_context_id.post_inc(),
+        });
+        if self.has_context(&context) {
+            if remove_if_exists {
+                self.remove_context(&context, cx);
+                return None;
+            }
+        }
+
+        self.insert_context(context.clone(), cx);
+        Some(context)
+    }
+
     pub fn add_selection(
         &mut self,
         buffer: Entity<Buffer>,
@@ -467,6 +587,12 @@ impl ContextStore {
             AgentContextHandle::Image(image_context) => {
                 FileInclusion::check_image(image_context, path)
             }
+            AgentContextHandle::Pdf(pdf_context) => {
+                (&pdf_context.project_path == path).then_some(FileInclusion::Direct)
+            }
+            AgentContextHandle::Video(video_context) => {
+                (&video_context.project_path == path).then_some(FileInclusion::Direct)
+            }
             AgentContextHandle::Directory(directory_context) => {
                 FileInclusion::check_directory(directory_context, path, project, cx)
             }
@@ -548,6 +674,8 @@ impl ContextStore {
                 | AgentContextHandle::TextThread(_)
                 | AgentContextHandle::Rules(_)
                 | AgentContextHandle::Image(_) => None,
+                AgentContextHandle::Pdf(pdf) => Some(pdf.project_path.clone()),
+                AgentContextHandle::Video(video) => Some(video.project_path.clone()),
             })
             .collect()
     }
@@ -0,0 +1,32 @@
+use gpui::App;
+use language_models::warnings::{LanguageModelWarning, LanguageModelWarnings};
+use ui::prelude::*;
+use workspace::notifications::simple_message_notification::MessageNotification;
+use workspace::notifications::{NotificationId, show_app_notification};
+
+struct SpendLimitNotification;
+struct ModerationNotification;
+
+/// Shows the warnings raised while sending language model requests as notifications.
+pub(crate) fn init(cx: &mut App) {
+    let Some(warnings) = LanguageModelWarnings::global(cx) else {
+        log::error!("language model warnings are shown before language models are initialized");
+        return;
+    };
+    cx.subscribe(&warnings, |_, warning, cx| {
+        let (id, message) = match warning {
+            LanguageModelWarning::NearSpendLimit { provider, message } => (
+                NotificationId::composite::<SpendLimitNotification>(*provider),
+                message.clone(),
+            ),
+            LanguageModelWarning::ModerationFlagged { message } => (
+                NotificationId::unique::<ModerationNotification>(),
+                message.clone(),
+            ),
+        };
+        show_app_notification(id, cx, move |cx| {
+            cx.new(|cx| MessageNotification::new(message.clone(), cx))
+        });
+    })
+    .detach();
+}
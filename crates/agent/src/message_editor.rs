@@ -1293,6 +1293,25 @@ impl MessageEditor {
                         tool_choice: None,
                         stop: vec![],
                         temperature: AgentSettings::temperature_for_model(&model.model, cx),
+                        seed: AgentSettings::seed_for_model(&model.model, cx),
+                        frequency_penalty: AgentSettings::frequency_penalty_for_model(
+                            &model.model,
+                            cx,
+                        ),
+                        presence_penalty: AgentSettings::presence_penalty_for_model(
+                            &model.model,
+                            cx,
+                        ),
+                        logit_bias: AgentSettings::logit_bias_for_model(&model.model, cx),
+                        top_p: None,
+                        top_k: None,
+                        candidates: None,
+                        logprobs: None,
+                        response_schema: None,
+                        parallel_tool_calls: None,
+                        prediction: None,
+                        batch: false,
+                        workspace: None,
                     };
 
                     Some(model.model.count_tokens(request, cx))
@@ -0,0 +1,221 @@
+use std::time::Duration;
+
+use collections::HashSet;
+use gpui::{
+    App, ClipboardItem, Entity, EventEmitter, FocusHandle, Focusable, ScrollHandle, Task, actions,
+};
+use language_models::request_log::{RequestLog, RequestLogEntry};
+use settings::Settings as _;
+use theme::ThemeSettings;
+use ui::{Disclosure, Tooltip, prelude::*};
+use workspace::{Workspace, item::Item};
+
+actions!(dev, [OpenLanguageModelRequestLog]);
+
+/// How often the view checks the log for new requests and events.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+pub(crate) fn init(cx: &mut App) {
+    cx.observe_new(|workspace: &mut Workspace, _, _| {
+        workspace.register_action(|workspace, _: &OpenLanguageModelRequestLog, window, cx| {
+            let log = RequestLog::global(cx);
+            let view = cx.new(|cx| RequestLogView::new(log, cx));
+            workspace.add_item_to_active_pane(Box::new(view), None, true, window, cx);
+        });
+    })
+    .detach();
+}
+
+/// Shows the requests in the [`RequestLog`], newest first, with the details of the expanded ones.
+pub struct RequestLogView {
+    log: RequestLog,
+    expanded: HashSet<usize>,
+    focus_handle: FocusHandle,
+    scroll_handle: ScrollHandle,
+    _refresh: Task<()>,
+}
+
+impl RequestLogView {
+    fn new(log: RequestLog, cx: &mut Context<Self>) -> Self {
+        let refresh = cx.spawn(async move |this, cx| {
+            let mut last_version = None;
+            loop {
+                cx.background_executor().timer(REFRESH_INTERVAL).await;
+                let updated = this.update(cx, |this, cx| {
+                    let version = this.log.version();
+                    if last_version != Some(version) {
+                        last_version = Some(version);
+                        cx.notify();
+                    }
+                });
+                if updated.is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            log,
+            expanded: HashSet::default(),
+            focus_handle: cx.focus_handle(),
+            scroll_handle: ScrollHandle::new(),
+            _refresh: refresh,
+        }
+    }
+
+    fn render_entry(&self, entry: &RequestLogEntry, cx: &mut Context<Self>) -> impl IntoElement {
+        let id = entry.id;
+        let is_expanded = self.expanded.contains(&id);
+        let (status, status_color): (SharedString, _) = match (&entry.error, entry.finished_after) {
+            (Some(_), _) => ("Failed".into(), Color::Error),
+            (None, Some(after)) => (format!("{:.1}s", after.as_secs_f32()).into(), Color::Muted),
+            (None, None) => ("Streaming…".into(), Color::Accent),
+        };
+
+        let header = h_flex()
+            .id(("request-log-entry", id))
+            .gap_2()
+            .cursor_pointer()
+            .child(Disclosure::new(("request-log-disclosure", id), is_expanded))
+            .child(
+                Label::new(entry.started_at.format("%H:%M:%S").to_string())
+                    .size(LabelSize::Small)
+                    .color(Color::Muted),
+            )
+            .child(Label::new(format!("{} / {}", entry.provider, entry.model)))
+            .child(
+                Label::new(status)
+                    .size(LabelSize::Small)
+                    .color(status_color),
+            )
+            .when(entry.replayed, |this| {
+                this.child(
+                    Label::new("replayed from the cache")
+                        .size(LabelSize::Small)
+                        .color(Color::Muted),
+                )
+            })
+            .when_some(entry.first_event_after, |this, after| {
+                this.child(
+                    Label::new(format!("first event after {:.1}s", after.as_secs_f32()))
+                        .size(LabelSize::Small)
+                        .color(Color::Muted),
+                )
+            })
+            .when_some(entry.usage, |this, usage| {
+                let mut label = format!(
+                    "{} input, {} output tokens",
+                    usage.input_tokens, usage.output_tokens
+                );
+                if let Some(cost) = entry.cost() {
+                    label.push_str(&format!(", about ${cost:.4}"));
+                }
+                this.child(Label::new(label).size(LabelSize::Small).color(Color::Muted))
+            })
+            .on_click(cx.listener(move |this, _, _, cx| {
+                if !this.expanded.remove(&id) {
+                    this.expanded.insert(id);
+                }
+                cx.notify();
+            }));
+
+        v_flex().gap_1().child(header).when(is_expanded, |this| {
+            let details = entry.details();
+            let buffer_font = ThemeSettings::get_global(cx).buffer_font.clone();
+            this.child(
+                v_flex()
+                    .pl_6()
+                    .gap_1()
+                    .items_start()
+                    .child(
+                        Button::new(("request-log-copy", id), "Copy")
+                            .icon(IconName::Copy)
+                            .icon_position(IconPosition::Start)
+                            .icon_size(IconSize::Small)
+                            .label_size(LabelSize::Small)
+                            .tooltip(Tooltip::text("Copy the request's details"))
+                            .on_click({
+                                let details = details.clone();
+                                move |_, _, cx| {
+                                    cx.write_to_clipboard(ClipboardItem::new_string(
+                                        details.clone(),
+                                    ))
+                                }
+                            }),
+                    )
+                    .child(div().font(buffer_font).text_xs().child(details)),
+            )
+        })
+    }
+}
+
+impl Render for RequestLogView {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let entries = self.log.read(|entries| {
+            entries
+                .iter()
+                .rev()
+                .map(|entry| self.render_entry(entry, cx).into_any_element())
+                .collect::<Vec<_>>()
+        });
+
+        v_flex()
+            .id("language-model-request-log")
+            .track_focus(&self.focus_handle)
+            .size_full()
+            .p_2()
+            .gap_2()
+            .overflow_y_scroll()
+            .track_scroll(&self.scroll_handle)
+            .bg(cx.theme().colors().editor_background)
+            .child(
+                h_flex()
+                    .justify_between()
+                    .child(
+                        Label::new(
+                            "The most recent requests sent to language models. API keys are masked.",
+                        )
+                        .color(Color::Muted),
+                    )
+                    .child(Button::new("request-log-clear", "Clear").on_click(cx.listener(
+                        |this, _, _, cx| {
+                            this.log.clear();
+                            this.expanded.clear();
+                            cx.notify();
+                        },
+                    ))),
+            )
+            .when(entries.is_empty(), |this| {
+                this.child(Label::new("No requests yet.").color(Color::Muted))
+            })
+            .children(entries)
+    }
+}
+
+impl EventEmitter<()> for RequestLogView {}
+
+impl Focusable for RequestLogView {
+    fn focus_handle(&self, _: &App) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl Item for RequestLogView {
+    type Event = ();
+
+    fn tab_content_text(&self, _detail: usize, _cx: &App) -> SharedString {
+        "Language Model Requests".into()
+    }
+
+    fn telemetry_event_text(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn clone_on_split(
+        &self,
+        _: Option<workspace::WorkspaceId>,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> Option<Entity<Self>> {
+        Some(cx.new(|cx| Self::new(self.log.clone(), cx)))
+    }
+}
@@ -263,16 +263,24 @@ impl TerminalInlineAssistant {
             .context()
             .cloned()
             .collect::<Vec<_>>();
-        let context_load_task = assist.workspace.update(cx, |workspace, cx| {
-            let project = workspace.project();
-            load_context(contexts, project, &assist.prompt_store, cx)
-        })?;
+        let (context_load_task, workspace_scope) =
+            assist.workspace.update(cx, |workspace, cx| {
+                let project = workspace.project();
+                (
+                    load_context(contexts, project, &assist.prompt_store, cx),
+                    language_models::api_key::workspace_scope(project.read(cx), cx),
+                )
+            })?;
 
         let ConfiguredModel { model, .. } = LanguageModelRegistry::read_global(cx)
             .inline_assistant_model()
             .context("No inline assistant model")?;
 
         let temperature = AgentSettings::temperature_for_model(&model, cx);
+        let seed = AgentSettings::seed_for_model(&model, cx);
+        let frequency_penalty = AgentSettings::frequency_penalty_for_model(&model, cx);
+        let presence_penalty = AgentSettings::presence_penalty_for_model(&model, cx);
+        let logit_bias = AgentSettings::logit_bias_for_model(&model, cx);
 
         Ok(cx.background_spawn(async move {
             let mut request_message = LanguageModelRequestMessage {
@@ -298,6 +306,19 @@ impl TerminalInlineAssistant {
                 tool_choice: None,
                 stop: Vec::new(),
                 temperature,
+                seed,
+                frequency_penalty,
+                presence_penalty,
+                logit_bias,
+                top_p: None,
+                top_k: None,
+                candidates: None,
+                logprobs: None,
+                response_schema: None,
+                parallel_tool_calls: None,
+                prediction: None,
+                batch: false,
+                workspace: workspace_scope,
             }
         }))
     }
@@ -19,12 +19,12 @@ use gpui::{
     WeakEntity,
 };
 use language_model::{
-    ConfiguredModel, LanguageModel, LanguageModelCompletionError, LanguageModelCompletionEvent,
-    LanguageModelId, LanguageModelKnownError, LanguageModelRegistry, LanguageModelRequest,
-    LanguageModelRequestMessage, LanguageModelRequestTool, LanguageModelToolResult,
-    LanguageModelToolResultContent, LanguageModelToolUseId, MessageContent,
-    ModelRequestLimitReachedError, PaymentRequiredError, RequestUsage, Role, SelectedModel,
-    StopReason, TokenUsage,
+    Citation, ConfiguredModel, LanguageModel, LanguageModelCompletionError,
+    LanguageModelCompletionEvent, LanguageModelId, LanguageModelKnownError, LanguageModelRegistry,
+    LanguageModelRequest, LanguageModelRequestMessage, LanguageModelRequestTool,
+    LanguageModelToolResult, LanguageModelToolResultContent, LanguageModelToolUseId,
+    MessageContent, ModelRequestLimitReachedError, PaymentRequiredError, RequestUsage, Role,
+    SelectedModel, StopReason, TokenUsage,
 };
 use postage::stream::Stream as _;
 use project::Project;
@@ -42,10 +42,12 @@ use zed_llm_client::{CompletionIntent, CompletionRequestStatus};
 
 use crate::ThreadStore;
 use crate::context::{AgentContext, AgentContextHandle, ContextLoadResult, LoadedContext};
+use crate::context_overflow::{OverflowSummaryCache, fit_to_context_window};
 use crate::thread_store::{
     SerializedCrease, SerializedLanguageModel, SerializedMessage, SerializedMessageSegment,
     SerializedThread, SerializedToolResult, SerializedToolUse, SharedProjectContext,
 };
+use crate::tool_result_limit::{ToolResultLimitCache, limit_tool_results};
 use crate::tool_use::{PendingToolUse, ToolUse, ToolUseMetadata, ToolUseState};
 
 #[derive(
@@ -116,6 +118,8 @@ pub struct Message {
     pub loaded_context: LoadedContext,
     pub creases: Vec<MessageCrease>,
     pub is_hidden: bool,
+    /// The sources that the message is grounded on.
+    pub citations: Vec<Citation>,
 }
 
 impl Message {
@@ -317,7 +321,14 @@ pub enum TokenUsageRatio {
 #[derive(Debug, Clone, Copy)]
 pub enum QueueState {
     Sending,
-    Queued { position: usize },
+    Queued {
+        position: usize,
+    },
+    /// Attachments too large to send inline with the request are being uploaded to the provider.
+    Uploading {
+        uploaded_bytes: u64,
+        total_bytes: u64,
+    },
     Started,
 }
 
@@ -349,6 +360,8 @@ pub struct Thread {
     request_token_usage: Vec<TokenUsage>,
     cumulative_token_usage: TokenUsage,
     exceeded_window_error: Option<ExceededWindowError>,
+    overflow_summary: OverflowSummaryCache,
+    tool_result_limits: ToolResultLimitCache,
     last_usage: Option<RequestUsage>,
     tool_use_limit_reached: bool,
     feedback: Option<ThreadFeedback>,
@@ -440,6 +453,8 @@ impl Thread {
             request_token_usage: Vec::new(),
             cumulative_token_usage: TokenUsage::default(),
             exceeded_window_error: None,
+            overflow_summary: OverflowSummaryCache::default(),
+            tool_result_limits: ToolResultLimitCache::default(),
             last_usage: None,
             tool_use_limit_reached: false,
             feedback: None,
@@ -528,6 +543,9 @@ impl Thread {
                         contexts: Vec::new(),
                         text: message.context,
                         images: Vec::new(),
+                        documents: message.documents,
+                        pdfs: Vec::new(),
+                        videos: Vec::new(),
                     },
                     creases: message
                         .creases
@@ -542,6 +560,7 @@ impl Thread {
                         })
                         .collect(),
                     is_hidden: message.is_hidden,
+                    citations: message.citations,
                 })
                 .collect(),
             next_message_id,
@@ -561,6 +580,8 @@ impl Thread {
             request_token_usage: serialized.request_token_usage,
             cumulative_token_usage: serialized.cumulative_token_usage,
             exceeded_window_error: None,
+            overflow_summary: OverflowSummaryCache::default(),
+            tool_result_limits: ToolResultLimitCache::default(),
             last_usage: None,
             tool_use_limit_reached: serialized.tool_use_limit_reached,
             feedback: None,
@@ -1012,6 +1033,7 @@ impl Thread {
             loaded_context,
             creases,
             is_hidden,
+            citations: Vec::new(),
         });
         self.touch_updated_at();
         cx.emit(ThreadEvent::MessageAdded(id));
@@ -1153,6 +1175,8 @@ impl Thread {
                             })
                             .collect(),
                         is_hidden: message.is_hidden,
+                        citations: message.citations.clone(),
+                        documents: message.loaded_context.documents.clone(),
                     })
                     .collect(),
                 initial_project_snapshot,
@@ -1227,6 +1251,19 @@ impl Thread {
             tool_choice: None,
             stop: Vec::new(),
             temperature: AgentSettings::temperature_for_model(&model, cx),
+            seed: AgentSettings::seed_for_model(&model, cx),
+            frequency_penalty: AgentSettings::frequency_penalty_for_model(&model, cx),
+            presence_penalty: AgentSettings::presence_penalty_for_model(&model, cx),
+            logit_bias: AgentSettings::logit_bias_for_model(&model, cx),
+            top_p: None,
+            top_k: None,
+            candidates: None,
+            logprobs: None,
+            response_schema: None,
+            parallel_tool_calls: None,
+            prediction: None,
+            batch: false,
+            workspace: language_models::api_key::workspace_scope(self.project.read(cx), cx),
         };
 
         let available_tools = self.available_tools(cx, model.clone());
@@ -1388,6 +1425,19 @@ impl Thread {
             tool_choice: None,
             stop: Vec::new(),
             temperature: AgentSettings::temperature_for_model(model, cx),
+            seed: AgentSettings::seed_for_model(model, cx),
+            frequency_penalty: AgentSettings::frequency_penalty_for_model(model, cx),
+            presence_penalty: AgentSettings::presence_penalty_for_model(model, cx),
+            logit_bias: AgentSettings::logit_bias_for_model(model, cx),
+            top_p: None,
+            top_k: None,
+            candidates: None,
+            logprobs: None,
+            response_schema: None,
+            parallel_tool_calls: None,
+            prediction: None,
+            batch: true,
+            workspace: language_models::api_key::workspace_scope(self.project.read(cx), cx),
         };
 
         for message in &self.messages {
@@ -1487,12 +1537,60 @@ impl Thread {
 
         self.last_received_chunk_at = Some(Instant::now());
 
+        let context_overflow = AgentSettings::get_global(cx).context_overflow;
+        let image_quality = AgentSettings::get_global(cx).image_quality;
+        let tool_result_max_tokens = AgentSettings::get_global(cx).tool_result_max_tokens;
+        let tool_result_overflow = AgentSettings::get_global(cx).tool_result_overflow;
+        let image_limits = model.image_limits();
+        let document_limits = model.document_limits();
+        let supports_images = model.supports_images();
+        let supports_audio = model.supports_audio();
+        let supports_video = model.supports_video();
+        let summary_model = LanguageModelRegistry::read_global(cx)
+            .thread_summary_model()
+            .map(|configured_model| configured_model.model);
+        let overflow_summary = self.overflow_summary.clone();
+        let tool_result_limits = self.tool_result_limits.clone();
+
         let task = cx.spawn(async move |thread, cx| {
-            let stream_completion_future = model.stream_completion(request, &cx);
             let initial_token_usage =
                 thread.read_with(cx, |thread, _cx| thread.cumulative_token_usage);
+            let mut guardrail_violations = Vec::new();
             let stream_completion = async {
-                let mut events = stream_completion_future.await?;
+                let mut request = cx
+                    .background_spawn(async move {
+                        let mut request = request;
+                        request.check_tool_result_images(supports_images);
+                        request.fit_images(image_limits, image_quality);
+                        request.limit_images(image_limits)?;
+                        request.check_pdfs(document_limits);
+                        request.check_audio(supports_audio);
+                        request.check_videos(supports_video);
+                        anyhow::Ok(request)
+                    })
+                    .await?;
+                if let Some(tool_result_max_tokens) = tool_result_max_tokens {
+                    limit_tool_results(
+                        &mut request,
+                        &model,
+                        tool_result_max_tokens,
+                        tool_result_overflow,
+                        summary_model.clone(),
+                        &tool_result_limits,
+                        cx,
+                    )
+                    .await?;
+                }
+                let request = fit_to_context_window(
+                    request,
+                    &model,
+                    context_overflow,
+                    summary_model,
+                    &overflow_summary,
+                    cx,
+                )
+                .await?;
+                let mut events = model.stream_completion(request, cx).await?;
 
                 let mut stop_reason = StopReason::EndTurn;
                 let mut current_token_usage = TokenUsage::default();
@@ -1533,9 +1631,12 @@ impl Thread {
                             Err(LanguageModelCompletionError::Other(error)) => {
                                 return Err(error);
                             }
+                            Err(error) => {
+                                return Err(error.into());
+                            }
                         };
 
-                        match event {
+                        match code_execution_as_text(event) {
                             LanguageModelCompletionEvent::StartMessage { .. } => {
                                 request_assistant_message_id =
                                     Some(thread.insert_assistant_message(
@@ -1546,6 +1647,30 @@ impl Thread {
                             LanguageModelCompletionEvent::Stop(reason) => {
                                 stop_reason = reason;
                             }
+                            LanguageModelCompletionEvent::Candidate { .. }
+                            | LanguageModelCompletionEvent::Logprobs(_)
+                            | LanguageModelCompletionEvent::ExecutableCode { .. }
+                            | LanguageModelCompletionEvent::CodeExecutionResult { .. } => {}
+                            LanguageModelCompletionEvent::GuardrailIntervention(violations) => {
+                                guardrail_violations.extend(violations);
+                            }
+                            LanguageModelCompletionEvent::Citations(citations) => {
+                                if let Some(message) = request_assistant_message_id
+                                    .and_then(|id| thread.messages.iter_mut().find(|m| m.id == id))
+                                {
+                                    message.citations.extend(citations);
+                                    cx.notify();
+                                }
+                            }
+                            LanguageModelCompletionEvent::Fallback { model, error } => {
+                                cx.emit(ThreadEvent::ShowError(ThreadError::Message {
+                                    header: format!("Switched to {model}").into(),
+                                    message: format!(
+                                        "The request was retried on a fallback model because it failed: {error}"
+                                    )
+                                    .into(),
+                                }));
+                            }
                             LanguageModelCompletionEvent::UsageUpdate(token_usage) => {
                                 thread.update_token_usage_at_last_message(token_usage);
                                 thread.cumulative_token_usage = thread.cumulative_token_usage
@@ -1612,6 +1737,27 @@ impl Thread {
                                     };
                                 }
                             }
+                            LanguageModelCompletionEvent::RedactedThinking { data } => {
+                                thread.received_chunk();
+
+                                if let Some(last_message) = thread.messages.last_mut() {
+                                    if last_message.role == Role::Assistant
+                                        && !thread.tool_use.has_tool_results(last_message.id)
+                                    {
+                                        last_message
+                                            .segments
+                                            .push(MessageSegment::RedactedThinking(data.into_bytes()));
+                                    } else {
+                                        request_assistant_message_id =
+                                            Some(thread.insert_assistant_message(
+                                                vec![MessageSegment::RedactedThinking(
+                                                    data.into_bytes(),
+                                                )],
+                                                cx,
+                                            ));
+                                    };
+                                }
+                            }
                             LanguageModelCompletionEvent::ToolUse(tool_use) => {
                                 let last_assistant_message_id = request_assistant_message_id
                                     .unwrap_or_else(|| {
@@ -1644,6 +1790,22 @@ impl Thread {
                                     });
                                 }
                             }
+                            LanguageModelCompletionEvent::UploadProgress {
+                                uploaded_bytes,
+                                total_bytes,
+                                ..
+                            } => {
+                                if let Some(completion) = thread
+                                    .pending_completions
+                                    .iter_mut()
+                                    .find(|completion| completion.id == pending_completion_id)
+                                {
+                                    completion.queue_state = QueueState::Uploading {
+                                        uploaded_bytes,
+                                        total_bytes,
+                                    };
+                                }
+                            }
                             LanguageModelCompletionEvent::StatusUpdate(status_update) => {
                                 if let Some(completion) = thread
                                     .pending_completions
@@ -1720,10 +1882,41 @@ impl Thread {
                                 let tool_uses = thread.use_pending_tools(window, cx, model.clone());
                                 cx.emit(ThreadEvent::UsePendingTools { tool_uses });
                             }
-                            StopReason::EndTurn | StopReason::MaxTokens  => {
+                            StopReason::EndTurn => {
+                                thread.project.update(cx, |project, cx| {
+                                    project.set_agent_location(None, cx);
+                                });
+                            }
+                            StopReason::MaxTokens => {
                                 thread.project.update(cx, |project, cx| {
                                     project.set_agent_location(None, cx);
                                 });
+                                cx.emit(ThreadEvent::ShowError(ThreadError::Message {
+                                    header: "Response cut off".into(),
+                                    message: "The model reached its maximum output length before finishing its response.".into(),
+                                }));
+                            }
+                            StopReason::ContentFilter => {
+                                thread.project.update(cx, |project, cx| {
+                                    project.set_agent_location(None, cx);
+                                });
+                                let message = if guardrail_violations.is_empty() {
+                                    "The provider's content filter stopped the response.".into()
+                                } else {
+                                    format!(
+                                        "A guardrail intervened because of: {}.",
+                                        guardrail_violations
+                                            .iter()
+                                            .map(|violation| violation.to_string())
+                                            .collect::<Vec<_>>()
+                                            .join(", ")
+                                    )
+                                    .into()
+                                };
+                                cx.emit(ThreadEvent::ShowError(ThreadError::Message {
+                                    header: "Response filtered".into(),
+                                    message,
+                                }));
                             }
                             StopReason::Refusal => {
                                 thread.project.update(cx, |project, cx| {
@@ -1789,7 +1982,107 @@ impl Thread {
                                         });
                                         cx.notify();
                                     }
+                                    LanguageModelKnownError::Timeout { .. } => {
+                                        cx.emit(ThreadEvent::ShowError(ThreadError::Message {
+                                            header: "Language model timed out".into(),
+                                            message: known_error.to_string().into(),
+                                        }));
+                                    }
+                                    LanguageModelKnownError::StreamInterrupted { .. } => {
+                                        cx.emit(ThreadEvent::ShowError(ThreadError::Message {
+                                            header: "Response interrupted".into(),
+                                            message: format!(
+                                                "{known_error}. The part of the response that arrived was kept."
+                                            )
+                                            .into(),
+                                        }));
+                                    }
+                                    LanguageModelKnownError::ModerationFlagged { .. } => {
+                                        cx.emit(ThreadEvent::ShowError(ThreadError::Message {
+                                            header: "Prompt blocked".into(),
+                                            message: format!("{known_error}.").into(),
+                                        }));
+                                    }
+                                    LanguageModelKnownError::TooManyImages { .. }
+                                    | LanguageModelKnownError::ImagesTooLarge { .. } => {
+                                        cx.emit(ThreadEvent::ShowError(ThreadError::Message {
+                                            header: "Too many images".into(),
+                                            message: format!(
+                                                "{known_error}. Remove some of them and try again."
+                                            )
+                                            .into(),
+                                        }));
+                                    }
+                                    LanguageModelKnownError::SpendLimitReached { .. } => {
+                                        cx.emit(ThreadEvent::ShowError(
+                                            ThreadError::SpendLimitReached {
+                                                message: format!(
+                                                    "{}: {known_error}.",
+                                                    model.provider_name().0
+                                                )
+                                                .into(),
+                                            },
+                                        ));
+                                    }
                                 }
+                            } else if let Some(
+                                LanguageModelCompletionError::ContextLengthExceeded,
+                            ) = error.downcast_ref()
+                            {
+                                thread.exceeded_window_error = Some(ExceededWindowError {
+                                    model_id: model.id(),
+                                    token_count: model.max_token_count(),
+                                });
+                                cx.notify();
+                            } else if let Some(completion_error) =
+                                error.downcast_ref::<LanguageModelCompletionError>()
+                            {
+                                let provider = model.provider_name().0;
+                                let (header, message) = match completion_error {
+                                    LanguageModelCompletionError::RateLimited {
+                                        retry_after: Some(after),
+                                    } => (
+                                        "Rate limit reached",
+                                        format!(
+                                            "{provider} is limiting requests. Try again in {}s.",
+                                            after.as_secs().max(1)
+                                        ),
+                                    ),
+                                    LanguageModelCompletionError::RateLimited {
+                                        retry_after: None,
+                                    } => (
+                                        "Rate limit reached",
+                                        format!(
+                                            "{provider} is limiting requests. Try again shortly."
+                                        ),
+                                    ),
+                                    LanguageModelCompletionError::InvalidApiKey => (
+                                        "Invalid API key",
+                                        format!(
+                                            "{provider} rejected the API key. Update it in the agent settings."
+                                        ),
+                                    ),
+                                    LanguageModelCompletionError::QuotaExceeded => (
+                                        "Out of credits",
+                                        format!(
+                                            "{provider} says the account has run out of credits or quota."
+                                        ),
+                                    ),
+                                    LanguageModelCompletionError::Overloaded => (
+                                        "Provider overloaded",
+                                        format!(
+                                            "{provider} is overloaded right now. Try again shortly or switch models."
+                                        ),
+                                    ),
+                                    _ => (
+                                        "Error interacting with language model",
+                                        completion_error.to_string(),
+                                    ),
+                                };
+                                cx.emit(ThreadEvent::ShowError(ThreadError::Message {
+                                    header: header.into(),
+                                    message: message.into(),
+                                }));
                             } else {
                                 let error_message = error
                                     .chain()
@@ -1831,6 +2124,7 @@ impl Thread {
                             output_tokens = usage.output_tokens,
                             cache_creation_input_tokens = usage.cache_creation_input_tokens,
                             cache_read_input_tokens = usage.cache_read_input_tokens,
+                            reasoning_tokens = usage.reasoning_tokens,
                         );
                     }
                 })
@@ -2565,6 +2859,18 @@ impl Thread {
                 )?;
             }
 
+            for document in &message.loaded_context.documents {
+                writeln!(markdown, "{}", document.to_text())?;
+            }
+
+            for pdf in &message.loaded_context.pdfs {
+                writeln!(markdown, "\n{} attached as context.\n", pdf.name)?;
+            }
+
+            for video in &message.loaded_context.videos {
+                writeln!(markdown, "\n{} attached as context.\n", video.name)?;
+            }
+
             for segment in &message.segments {
                 match segment {
                     MessageSegment::Text(text) => writeln!(markdown, "{}\n", text)?,
@@ -2791,12 +3097,36 @@ impl Thread {
     }
 }
 
+/// Turns code that the model ran with its provider's code execution tool, and the code's output,
+/// into text with a code block, so that they're shown as part of the assistant's message.
+fn code_execution_as_text(event: LanguageModelCompletionEvent) -> LanguageModelCompletionEvent {
+    match event {
+        LanguageModelCompletionEvent::ExecutableCode { language, code } => {
+            LanguageModelCompletionEvent::Text(format!(
+                "\n\n```{}\n{}\n```\n\n",
+                language.to_lowercase(),
+                code.trim_end()
+            ))
+        }
+        LanguageModelCompletionEvent::CodeExecutionResult { success, output } => {
+            let header = if success { "Output" } else { "Failed" };
+            LanguageModelCompletionEvent::Text(format!(
+                "{header}:\n\n```\n{}\n```\n\n",
+                output.trim_end()
+            ))
+        }
+        event => event,
+    }
+}
+
 #[derive(Debug, Clone, Error)]
 pub enum ThreadError {
     #[error("Payment required")]
     PaymentRequired,
     #[error("Model request limit reached")]
     ModelRequestLimitReached { plan: Plan },
+    #[error("Spend limit reached: {message}")]
+    SpendLimitReached { message: SharedString },
     #[error("Message {header}: {message}")]
     Message {
         header: SharedString,
@@ -3293,6 +3623,10 @@ fn main() {{
                         provider: Some(model.provider_id().0.to_string().into()),
                         model: Some(model.id().0.clone()),
                         temperature: Some(0.66),
+                        seed: Some(42),
+                        frequency_penalty: None,
+                        presence_penalty: None,
+                        logit_bias: None,
                     }],
                     ..AgentSettings::get_global(cx).clone()
                 },
@@ -3304,6 +3638,7 @@ fn main() {{
             thread.to_completion_request(model.clone(), CompletionIntent::UserPrompt, cx)
         });
         assert_eq!(request.temperature, Some(0.66));
+        assert_eq!(request.seed, Some(42));
 
         // Only model
         cx.update(|cx| {
@@ -3313,6 +3648,10 @@ fn main() {{
                         provider: None,
                         model: Some(model.id().0.clone()),
                         temperature: Some(0.66),
+                        seed: None,
+                        frequency_penalty: None,
+                        presence_penalty: None,
+                        logit_bias: None,
                     }],
                     ..AgentSettings::get_global(cx).clone()
                 },
@@ -3333,6 +3672,10 @@ fn main() {{
                         provider: Some(model.provider_id().0.to_string().into()),
                         model: None,
                         temperature: Some(0.66),
+                        seed: None,
+                        frequency_penalty: None,
+                        presence_penalty: None,
+                        logit_bias: None,
                     }],
                     ..AgentSettings::get_global(cx).clone()
                 },
@@ -3353,6 +3696,10 @@ fn main() {{
                         provider: Some("anthropic".into()),
                         model: Some(model.id().0.clone()),
                         temperature: Some(0.66),
+                        seed: None,
+                        frequency_penalty: None,
+                        presence_penalty: None,
+                        logit_bias: None,
                     }],
                     ..AgentSettings::get_global(cx).clone()
                 },
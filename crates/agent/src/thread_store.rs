@@ -19,7 +19,10 @@ use gpui::{
 };
 use heed::Database;
 use heed::types::SerdeBincode;
-use language_model::{LanguageModelToolResultContent, LanguageModelToolUseId, Role, TokenUsage};
+use language_model::{
+    Citation, LanguageModelDocument, LanguageModelToolResultContent, LanguageModelToolUseId, Role,
+    TokenUsage,
+};
 use project::context_server_store::{ContextServerStatus, ContextServerStore};
 use project::{Project, ProjectItem, ProjectPath, Worktree};
 use prompt_store::{
@@ -761,6 +764,10 @@ pub struct SerializedMessage {
     pub creases: Vec<SerializedCrease>,
     #[serde(default)]
     pub is_hidden: bool,
+    #[serde(default)]
+    pub citations: Vec<Citation>,
+    #[serde(default)]
+    pub documents: Vec<LanguageModelDocument>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -846,6 +853,8 @@ impl LegacySerializedMessage {
             context: String::new(),
             creases: Vec::new(),
             is_hidden: false,
+            citations: Vec::new(),
+            documents: Vec::new(),
         }
     }
 }
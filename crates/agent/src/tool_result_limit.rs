@@ -0,0 +1,250 @@
+use std::hash::{DefaultHasher, Hash as _, Hasher as _};
+use std::sync::Arc;
+
+use agent_settings::ToolResultOverflowStrategy;
+use anyhow::Result;
+use collections::HashMap;
+use gpui::AsyncApp;
+use language_model::{
+    LanguageModel, LanguageModelRequest, LanguageModelRequestMessage,
+    LanguageModelToolResultContent, MessageContent, Role,
+};
+use parking_lot::Mutex;
+
+use crate::context_overflow::{count_tokens, summarize};
+
+/// The shortened text of a thread's tool results, keyed by a hash of their text, so that the
+/// following requests of the thread don't count and shorten them again. Results that fit are
+/// stored as `None`.
+#[derive(Clone, Default)]
+pub struct ToolResultLimitCache(Arc<Mutex<HashMap<u64, Option<Arc<str>>>>>);
+
+/// Shortens the text of the tool results in `request` that take up more than `max_tokens`, with
+/// `strategy`, so that a single huge output, like that of a verbose command, can't fill the
+/// context window on its own.
+pub async fn limit_tool_results(
+    request: &mut LanguageModelRequest,
+    model: &Arc<dyn LanguageModel>,
+    max_tokens: usize,
+    strategy: ToolResultOverflowStrategy,
+    summary_model: Option<Arc<dyn LanguageModel>>,
+    cache: &ToolResultLimitCache,
+    cx: &AsyncApp,
+) -> Result<()> {
+    for message in &mut request.messages {
+        for content in &mut message.content {
+            let MessageContent::ToolResult(tool_result) = content else {
+                continue;
+            };
+            let LanguageModelToolResultContent::Text(text) = &tool_result.content else {
+                continue;
+            };
+            // Tokens are at least a byte long, so shorter texts can't be past the limit.
+            if text.len() <= max_tokens {
+                continue;
+            }
+            let text = text.clone();
+
+            let mut hasher = DefaultHasher::new();
+            (&text, model.id(), max_tokens, strategy).hash(&mut hasher);
+            let key = hasher.finish();
+            let cached = cache.0.lock().get(&key).cloned();
+            let shortened = match cached {
+                Some(shortened) => shortened,
+                None => {
+                    let shortened = shorten(
+                        &text,
+                        &tool_result.tool_name,
+                        model,
+                        max_tokens,
+                        strategy,
+                        summary_model.as_ref(),
+                        cx,
+                    )
+                    .await?;
+                    cache.0.lock().insert(key, shortened.clone());
+                    shortened
+                }
+            };
+            if let Some(shortened) = shortened {
+                tool_result.content = LanguageModelToolResultContent::Text(shortened);
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn shorten(
+    text: &str,
+    tool_name: &str,
+    model: &Arc<dyn LanguageModel>,
+    max_tokens: usize,
+    strategy: ToolResultOverflowStrategy,
+    summary_model: Option<&Arc<dyn LanguageModel>>,
+    cx: &AsyncApp,
+) -> Result<Option<Arc<str>>> {
+    let tokens = count_text_tokens(text, model, cx).await?;
+    if tokens <= max_tokens {
+        return Ok(None);
+    }
+    // Leave some room for the note about what was left out, and for the text that's kept being
+    // denser than the average.
+    let bytes_for = |max_tokens: usize| {
+        (text.len() as u64 * max_tokens as u64 * 9 / (tokens as u64 * 10)) as usize
+    };
+
+    let shortened = match (strategy, summary_model) {
+        (ToolResultOverflowStrategy::Truncate, _) => truncate(text, bytes_for(max_tokens)),
+        (ToolResultOverflowStrategy::Summarize, Some(summary_model)) => {
+            // The summary model might not fit the whole text either.
+            let input = middle_out(text, bytes_for(summary_model.max_token_count() / 2));
+            match summarize_output(&input, tool_name, max_tokens, summary_model, cx).await {
+                Ok(summary) => format!(
+                    "[This output was too long to include in full, so here's a summary of it]\n\n\
+                     {summary}"
+                ),
+                Err(error) => {
+                    log::error!("failed to summarize the result of {tool_name}: {error:#}");
+                    middle_out(text, bytes_for(max_tokens))
+                }
+            }
+        }
+        (ToolResultOverflowStrategy::MiddleOut | ToolResultOverflowStrategy::Summarize, _) => {
+            middle_out(text, bytes_for(max_tokens))
+        }
+    };
+    Ok(Some(shortened.into()))
+}
+
+async fn count_text_tokens(
+    text: &str,
+    model: &Arc<dyn LanguageModel>,
+    cx: &AsyncApp,
+) -> Result<usize> {
+    let request = LanguageModelRequest {
+        messages: vec![LanguageModelRequestMessage {
+            role: Role::User,
+            content: vec![MessageContent::Text(text.to_string())],
+            cache: false,
+        }],
+        ..Default::default()
+    };
+    count_tokens(&request, model, cx).await
+}
+
+async fn summarize_output(
+    text: &str,
+    tool_name: &str,
+    max_tokens: usize,
+    model: &Arc<dyn LanguageModel>,
+    cx: &AsyncApp,
+) -> Result<String> {
+    let messages = vec![LanguageModelRequestMessage {
+        role: Role::User,
+        content: vec![MessageContent::Text(format!(
+            "The following is the output of the `{tool_name}` tool, which is too long for the \
+             agent that called it to read in full. Summarize it in fewer than {} words. Quote \
+             errors, warnings, file paths, and other details that the agent might act on \
+             verbatim, and say what kinds of content you left out.\n\n{text}",
+            max_tokens / 2
+        ))],
+        cache: false,
+    }];
+    summarize(messages, model, cx).await
+}
+
+/// Keeps the lines at the start of `text` that fit in about `max_bytes`.
+fn truncate(text: &str, max_bytes: usize) -> String {
+    let head = prefix_len(text, max_bytes);
+    let mut truncated = text[..head].to_string();
+    push_omitted_note(&mut truncated, &text[head..]);
+    truncated
+}
+
+/// Keeps the lines at the start and at the end of `text` that fit in about `max_bytes`, leaving
+/// out the middle.
+fn middle_out(text: &str, max_bytes: usize) -> String {
+    let head = prefix_len(text, max_bytes / 2);
+    let tail = suffix_start(text, max_bytes / 2).max(head);
+    let mut shortened = text[..head].to_string();
+    push_omitted_note(&mut shortened, &text[head..tail]);
+    if head < tail {
+        shortened.push('\n');
+    }
+    shortened.push_str(&text[tail..]);
+    shortened
+}
+
+fn push_omitted_note(text: &mut String, omitted: &str) {
+    if omitted.is_empty() {
+        return;
+    }
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+    let lines = omitted.lines().count();
+    let noun = if lines == 1 { "line" } else { "lines" };
+    text.push_str(&format!(
+        "[{lines} {noun} left out because this output is too long]"
+    ));
+}
+
+/// The end of the last whole line within the first `max_bytes` of `text`, or of the last whole
+/// character when not even the first line fits.
+fn prefix_len(text: &str, max_bytes: usize) -> usize {
+    if max_bytes >= text.len() {
+        return text.len();
+    }
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text[..end].rfind('\n').map_or(end, |ix| ix + 1)
+}
+
+/// The start of the first whole line within the last `max_bytes` of `text`, or of the first
+/// whole character when not even the last line fits.
+fn suffix_start(text: &str, max_bytes: usize) -> usize {
+    if max_bytes >= text.len() {
+        return 0;
+    }
+    let mut start = text.len() - max_bytes;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    if text.as_bytes()[start - 1] == b'\n' {
+        return start;
+    }
+    match text[start..].find('\n') {
+        Some(ix) if start + ix + 1 < text.len() => start + ix + 1,
+        _ => start,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shorten_text() {
+        let text = (1..=10)
+            .map(|line| format!("line {line}\n"))
+            .collect::<String>();
+
+        assert_eq!(truncate(&text, 1000), text);
+        assert_eq!(
+            truncate(&text, 20),
+            "line 1\nline 2\n[8 lines left out because this output is too long]"
+        );
+        assert_eq!(
+            middle_out(&text, 30),
+            "line 1\nline 2\n[6 lines left out because this output is too long]\nline 9\nline 10\n"
+        );
+
+        // Text without line breaks is cut between characters.
+        assert_eq!(
+            truncate("ééééé", 5),
+            "éé\n[1 line left out because this output is too long]"
+        );
+    }
+}
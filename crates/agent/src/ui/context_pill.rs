@@ -15,9 +15,9 @@ use ui::{IconButtonShape, Tooltip, prelude::*, tooltip_container};
 use crate::context::{
     AgentContext, AgentContextHandle, ContextId, ContextKind, DirectoryContext,
     DirectoryContextHandle, FetchedUrlContext, FileContext, FileContextHandle, ImageContext,
-    ImageStatus, RulesContext, RulesContextHandle, SelectionContext, SelectionContextHandle,
-    SymbolContext, SymbolContextHandle, TextThreadContext, TextThreadContextHandle, ThreadContext,
-    ThreadContextHandle,
+    ImageStatus, PdfContext, RulesContext, RulesContextHandle, SelectionContext,
+    SelectionContextHandle, SymbolContext, SymbolContextHandle, TextThreadContext,
+    TextThreadContextHandle, ThreadContext, ThreadContextHandle, VideoContext,
 };
 
 #[derive(IntoElement)]
@@ -305,6 +305,8 @@ impl AddedContext {
             AgentContextHandle::TextThread(handle) => Some(Self::pending_text_thread(handle, cx)),
             AgentContextHandle::Rules(handle) => Self::pending_rules(handle, prompt_store, cx),
             AgentContextHandle::Image(handle) => Some(Self::image(handle)),
+            AgentContextHandle::Pdf(handle) => Some(Self::pdf(handle, cx)),
+            AgentContextHandle::Video(handle) => Some(Self::video(handle, cx)),
         }
     }
 
@@ -319,6 +321,8 @@ impl AddedContext {
             AgentContext::TextThread(context) => Self::attached_text_thread(context),
             AgentContext::Rules(context) => Self::attached_rules(context),
             AgentContext::Image(context) => Self::image(context.clone()),
+            AgentContext::Pdf(context) => Self::pdf(context.clone(), cx),
+            AgentContext::Video(context) => Self::video(context.clone(), cx),
         }
     }
 
@@ -637,6 +641,80 @@ impl AddedContext {
             handle: AgentContextHandle::Image(context),
         }
     }
+
+    fn pdf(context: PdfContext, cx: &App) -> AddedContext {
+        let full_path_string: SharedString =
+            context.full_path.to_string_lossy().into_owned().into();
+        let name = context
+            .full_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned().into())
+            .unwrap_or_else(|| full_path_string.clone());
+        let parent = context
+            .full_path
+            .parent()
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().into_owned().into());
+        let tooltip = match context.pdf() {
+            Some(pdf) => format!("{full_path_string} ({} pages)", pdf.page_count).into(),
+            None => full_path_string,
+        };
+        AddedContext {
+            kind: ContextKind::Pdf,
+            name,
+            parent,
+            tooltip: Some(tooltip),
+            icon_path: FileIcons::get_icon(&context.full_path, cx),
+            status: match context.status() {
+                ImageStatus::Loading => ContextStatus::Loading {
+                    message: "Loading…".into(),
+                },
+                ImageStatus::Error => ContextStatus::Error {
+                    message: "Failed to load PDF".into(),
+                },
+                ImageStatus::Ready => ContextStatus::Ready,
+            },
+            render_hover: None,
+            handle: AgentContextHandle::Pdf(context),
+        }
+    }
+
+    fn video(context: VideoContext, cx: &App) -> AddedContext {
+        let full_path_string: SharedString =
+            context.full_path.to_string_lossy().into_owned().into();
+        let name = context
+            .full_path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned().into())
+            .unwrap_or_else(|| full_path_string.clone());
+        let parent = context
+            .full_path
+            .parent()
+            .and_then(|p| p.file_name())
+            .map(|n| n.to_string_lossy().into_owned().into());
+        let tooltip = match context.video().and_then(|video| video.duration) {
+            Some(duration) => format!("{full_path_string} ({}s)", duration.as_secs()).into(),
+            None => full_path_string,
+        };
+        AddedContext {
+            kind: ContextKind::Video,
+            name,
+            parent,
+            tooltip: Some(tooltip),
+            icon_path: FileIcons::get_icon(&context.full_path, cx),
+            status: match context.status() {
+                ImageStatus::Loading => ContextStatus::Loading {
+                    message: "Loading…".into(),
+                },
+                ImageStatus::Error => ContextStatus::Error {
+                    message: "Failed to load video".into(),
+                },
+                ImageStatus::Ready => ContextStatus::Ready,
+            },
+            render_hover: None,
+            handle: AgentContextHandle::Video(context),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,291 @@
+use std::time::Duration;
+
+use chrono::{Datelike as _, Local};
+use collections::HashMap;
+use gpui::{App, Entity, EventEmitter, FocusHandle, Focusable, ScrollHandle, Task};
+use language_model::TokenUsage;
+use language_models::usage_history::{UsageExportFormat, UsageHistory, UsageRecord};
+use language_models::{ExportUsageHistory, OpenUsageHistory};
+use ui::prelude::*;
+use workspace::{Workspace, item::Item};
+
+/// How often the view checks the history for new requests.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// How many of the most recent requests are listed individually.
+const MAX_LISTED_RECORDS: usize = 100;
+
+pub(crate) fn init(cx: &mut App) {
+    cx.observe_new(|workspace: &mut Workspace, _, _| {
+        workspace.register_action(|workspace, _: &OpenUsageHistory, window, cx| {
+            let history = UsageHistory::global(cx);
+            let view = cx.new(|cx| UsageHistoryView::new(history, cx));
+            workspace.add_item_to_active_pane(Box::new(view), None, true, window, cx);
+        });
+    })
+    .detach();
+}
+
+#[derive(Default)]
+struct UsageTotal {
+    requests: usize,
+    usage: TokenUsage,
+    cost: f64,
+    /// Whether some of the requests used a model whose price isn't known, so that `cost` is too
+    /// low.
+    has_unknown_cost: bool,
+}
+
+impl UsageTotal {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += 1;
+        self.usage = self.usage + record.usage;
+        match record.cost {
+            Some(cost) => self.cost += cost,
+            None => self.has_unknown_cost = true,
+        }
+    }
+
+    fn label(&self) -> String {
+        let requests = if self.requests == 1 {
+            "1 request".to_string()
+        } else {
+            format!("{} requests", self.requests)
+        };
+        let cost = if self.has_unknown_cost {
+            format!("at least ${:.2}", self.cost)
+        } else {
+            format!("${:.2}", self.cost)
+        };
+        format!(
+            "{requests}, {} input and {} output tokens, {cost}",
+            self.usage.input_tokens + self.usage.cache_read_input_tokens,
+            self.usage.output_tokens
+        )
+    }
+}
+
+/// Sums up this month's requests in the [`UsageHistory`] by model and by feature, and lists the
+/// most recent ones.
+pub struct UsageHistoryView {
+    history: UsageHistory,
+    focus_handle: FocusHandle,
+    scroll_handle: ScrollHandle,
+    _refresh: Task<()>,
+}
+
+impl UsageHistoryView {
+    fn new(history: UsageHistory, cx: &mut Context<Self>) -> Self {
+        let refresh = cx.spawn(async move |this, cx| {
+            let mut last_version = None;
+            loop {
+                cx.background_executor().timer(REFRESH_INTERVAL).await;
+                let updated = this.update(cx, |this, cx| {
+                    let version = this.history.version();
+                    if last_version != Some(version) {
+                        last_version = Some(version);
+                        cx.notify();
+                    }
+                });
+                if updated.is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            history,
+            focus_handle: cx.focus_handle(),
+            scroll_handle: ScrollHandle::new(),
+            _refresh: refresh,
+        }
+    }
+
+    fn render_section(title: &'static str, totals: Vec<(String, UsageTotal)>) -> impl IntoElement {
+        v_flex()
+            .gap_1()
+            .child(Label::new(title).size(LabelSize::Small).color(Color::Muted))
+            .children(totals.into_iter().map(|(name, total)| {
+                h_flex().gap_2().child(Label::new(name)).child(
+                    Label::new(total.label())
+                        .size(LabelSize::Small)
+                        .color(Color::Muted),
+                )
+            }))
+    }
+
+    fn render_record(record: &UsageRecord) -> impl IntoElement {
+        let mut label = format!(
+            "{} input, {} output tokens",
+            record.usage.input_tokens + record.usage.cache_read_input_tokens,
+            record.usage.output_tokens
+        );
+        if let Some(cost) = record.cost {
+            label.push_str(&format!(", about ${cost:.4}"));
+        }
+        h_flex()
+            .gap_2()
+            .child(
+                Label::new(
+                    record
+                        .started_at
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M:%S")
+                        .to_string(),
+                )
+                .size(LabelSize::Small)
+                .color(Color::Muted),
+            )
+            .child(Label::new(format!(
+                "{} / {}",
+                record.provider, record.model
+            )))
+            .when_some(record.feature.clone(), |this, feature| {
+                this.child(
+                    Label::new(feature)
+                        .size(LabelSize::Small)
+                        .color(Color::Accent),
+                )
+            })
+            .child(Label::new(label).size(LabelSize::Small).color(Color::Muted))
+    }
+}
+
+fn sorted_by_cost(totals: HashMap<String, UsageTotal>) -> Vec<(String, UsageTotal)> {
+    let mut totals = totals.into_iter().collect::<Vec<_>>();
+    totals.sort_by(|(a_name, a), (b_name, b)| {
+        b.cost.total_cmp(&a.cost).then_with(|| a_name.cmp(b_name))
+    });
+    totals
+}
+
+impl Render for UsageHistoryView {
+    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let now = Local::now();
+        let (month_total, by_model, by_feature, records) = self.history.read(|records| {
+            let mut month_total = UsageTotal::default();
+            let mut by_model = HashMap::<String, UsageTotal>::default();
+            let mut by_feature = HashMap::<String, UsageTotal>::default();
+            for record in records {
+                let started_at = record.started_at.with_timezone(&Local);
+                if started_at.year() != now.year() || started_at.month() != now.month() {
+                    continue;
+                }
+                month_total.add(record);
+                by_model
+                    .entry(format!("{} / {}", record.provider, record.model))
+                    .or_default()
+                    .add(record);
+                by_feature
+                    .entry(record.feature.clone().unwrap_or_else(|| "other".into()))
+                    .or_default()
+                    .add(record);
+            }
+            let recent = records
+                .iter()
+                .rev()
+                .take(MAX_LISTED_RECORDS)
+                .map(|record| Self::render_record(record).into_any_element())
+                .collect::<Vec<_>>();
+            (month_total, by_model, by_feature, recent)
+        });
+
+        v_flex()
+            .id("language-model-usage-history")
+            .track_focus(&self.focus_handle)
+            .size_full()
+            .p_2()
+            .gap_3()
+            .overflow_y_scroll()
+            .track_scroll(&self.scroll_handle)
+            .bg(cx.theme().colors().editor_background)
+            .child(
+                h_flex()
+                    .justify_between()
+                    .child(
+                        Label::new(format!(
+                            "This month: {}. Costs are estimated from the models' prices.",
+                            month_total.label()
+                        ))
+                        .color(Color::Muted),
+                    )
+                    .child(
+                        h_flex()
+                            .gap_1()
+                            .child(
+                                Button::new("usage-history-export-csv", "Export CSV").on_click(
+                                    |_, window, cx| {
+                                        window.dispatch_action(
+                                            Box::new(ExportUsageHistory {
+                                                format: UsageExportFormat::Csv,
+                                            }),
+                                            cx,
+                                        )
+                                    },
+                                ),
+                            )
+                            .child(
+                                Button::new("usage-history-export-json", "Export JSON").on_click(
+                                    |_, window, cx| {
+                                        window.dispatch_action(
+                                            Box::new(ExportUsageHistory {
+                                                format: UsageExportFormat::Json,
+                                            }),
+                                            cx,
+                                        )
+                                    },
+                                ),
+                            ),
+                    ),
+            )
+            .when(records.is_empty(), |this| {
+                this.child(Label::new("No requests yet.").color(Color::Muted))
+            })
+            .when(month_total.requests > 0, |this| {
+                this.child(Self::render_section("By model", sorted_by_cost(by_model)))
+                    .child(Self::render_section(
+                        "By feature",
+                        sorted_by_cost(by_feature),
+                    ))
+            })
+            .when(!records.is_empty(), |this| {
+                this.child(
+                    v_flex()
+                        .gap_1()
+                        .child(
+                            Label::new("Recent requests")
+                                .size(LabelSize::Small)
+                                .color(Color::Muted),
+                        )
+                        .children(records),
+                )
+            })
+    }
+}
+
+impl EventEmitter<()> for UsageHistoryView {}
+
+impl Focusable for UsageHistoryView {
+    fn focus_handle(&self, _: &App) -> FocusHandle {
+        self.focus_handle.clone()
+    }
+}
+
+impl Item for UsageHistoryView {
+    type Event = ();
+
+    fn tab_content_text(&self, _detail: usize, _cx: &App) -> SharedString {
+        "Language Model Usage".into()
+    }
+
+    fn telemetry_event_text(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn clone_on_split(
+        &self,
+        _: Option<workspace::WorkspaceId>,
+        _: &mut Window,
+        cx: &mut Context<Self>,
+    ) -> Option<Entity<Self>> {
+        Some(cx.new(|cx| Self::new(self.history.clone(), cx)))
+    }
+}
@@ -1,5 +1,6 @@
 mod agent_profile;
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use ::open_ai::Model as OpenAiModel;
@@ -111,6 +112,10 @@ pub struct AgentSettings {
     pub model_parameters: Vec<LanguageModelParameters>,
     pub preferred_completion_mode: CompletionMode,
     pub enable_feedback: bool,
+    pub context_overflow: ContextOverflowStrategy,
+    pub image_quality: u8,
+    pub tool_result_max_tokens: Option<usize>,
+    pub tool_result_overflow: ToolResultOverflowStrategy,
 }
 
 impl AgentSettings {
@@ -119,8 +124,50 @@ impl AgentSettings {
         settings
             .model_parameters
             .iter()
-            .rfind(|setting| setting.matches(model))
-            .and_then(|m| m.temperature)
+            .rev()
+            .filter(|setting| setting.matches(model))
+            .find_map(|m| m.temperature)
+    }
+
+    pub fn seed_for_model(model: &Arc<dyn LanguageModel>, cx: &App) -> Option<u64> {
+        let settings = Self::get_global(cx);
+        settings
+            .model_parameters
+            .iter()
+            .rev()
+            .filter(|setting| setting.matches(model))
+            .find_map(|m| m.seed)
+    }
+
+    pub fn frequency_penalty_for_model(model: &Arc<dyn LanguageModel>, cx: &App) -> Option<f32> {
+        let settings = Self::get_global(cx);
+        settings
+            .model_parameters
+            .iter()
+            .rev()
+            .filter(|setting| setting.matches(model))
+            .find_map(|m| m.frequency_penalty)
+    }
+
+    pub fn presence_penalty_for_model(model: &Arc<dyn LanguageModel>, cx: &App) -> Option<f32> {
+        let settings = Self::get_global(cx);
+        settings
+            .model_parameters
+            .iter()
+            .rev()
+            .filter(|setting| setting.matches(model))
+            .find_map(|m| m.presence_penalty)
+    }
+
+    pub fn logit_bias_for_model(model: &Arc<dyn LanguageModel>, cx: &App) -> BTreeMap<u32, f32> {
+        let settings = Self::get_global(cx);
+        settings
+            .model_parameters
+            .iter()
+            .rev()
+            .filter(|setting| setting.matches(model))
+            .find_map(|m| m.logit_bias.clone())
+            .unwrap_or_default()
     }
 
     pub fn set_inline_assistant_model(&mut self, provider: String, model: String) {
@@ -150,6 +197,16 @@ pub struct LanguageModelParameters {
     pub provider: Option<LanguageModelProviderSetting>,
     pub model: Option<SharedString>,
     pub temperature: Option<f32>,
+    /// Used by providers that support it to make completions reproducible.
+    pub seed: Option<u64>,
+    /// Used by OpenAI-compatible providers to discourage repeating tokens, in proportion to how
+    /// often they already appear.
+    pub frequency_penalty: Option<f32>,
+    /// Used by OpenAI-compatible providers to discourage repeating tokens that already appear.
+    pub presence_penalty: Option<f32>,
+    /// Biases for token IDs, used by OpenAI-compatible providers to make tokens more or less
+    /// likely, or to ban them with a bias of -100.
+    pub logit_bias: Option<BTreeMap<u32, f32>>,
 }
 
 impl LanguageModelParameters {
@@ -278,6 +335,10 @@ impl AgentSettingsContent {
                     model_parameters: Vec::new(),
                     preferred_completion_mode: None,
                     enable_feedback: None,
+                    context_overflow: None,
+                    image_quality: None,
+                    tool_result_max_tokens: None,
+                    tool_result_overflow: None,
                     play_sound_when_agent_done: None,
                 },
                 VersionedAgentSettingsContent::V2(ref settings) => settings.clone(),
@@ -311,6 +372,10 @@ impl AgentSettingsContent {
                 model_parameters: Vec::new(),
                 preferred_completion_mode: None,
                 enable_feedback: None,
+                context_overflow: None,
+                image_quality: None,
+                tool_result_max_tokens: None,
+                tool_result_overflow: None,
                 play_sound_when_agent_done: None,
             },
             None => AgentSettingsContentV2::default(),
@@ -596,6 +661,10 @@ impl Default for VersionedAgentSettingsContent {
             model_parameters: Vec::new(),
             preferred_completion_mode: None,
             enable_feedback: None,
+            context_overflow: None,
+            image_quality: None,
+            tool_result_max_tokens: None,
+            tool_result_overflow: None,
             play_sound_when_agent_done: None,
         })
     }
@@ -682,6 +751,52 @@ pub struct AgentSettingsContentV2 {
     ///
     /// Default: true
     enable_feedback: Option<bool>,
+    /// What to do when a thread no longer fits in the model's context window.
+    ///
+    /// Default: error
+    context_overflow: Option<ContextOverflowStrategy>,
+    /// The JPEG quality, from 1 to 100, of images that are recompressed because they're larger
+    /// than the model accepts.
+    ///
+    /// Default: 85
+    image_quality: Option<u8>,
+    /// The most tokens that a tool result, like the output of a command, can take up in a
+    /// request before it's shortened with `tool_result_overflow`.
+    ///
+    /// Default: unlimited
+    tool_result_max_tokens: Option<usize>,
+    /// How tool results that are longer than `tool_result_max_tokens` are shortened.
+    ///
+    /// Default: middle_out
+    tool_result_overflow: Option<ToolResultOverflowStrategy>,
+}
+
+/// How a request that's too large for the model's context window is handled.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextOverflowStrategy {
+    /// Fail the request, so that the thread can be summarized into a new one.
+    #[default]
+    Error,
+    /// Leave out the oldest turns of the conversation until the request fits.
+    DropOldest,
+    /// Replace the oldest turns of the conversation with a summary written by the thread summary
+    /// model.
+    Summarize,
+}
+
+/// How a tool result that's too long to send in full is shortened.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolResultOverflowStrategy {
+    /// Keep the start of the result.
+    Truncate,
+    /// Keep the start and the end of the result, where errors and summaries of command output
+    /// usually are.
+    #[default]
+    MiddleOut,
+    /// Replace the result with a summary written by the thread summary model.
+    Summarize,
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Default)]
@@ -729,6 +844,7 @@ impl JsonSchema for LanguageModelProviderSetting {
                 "copilot_chat".into(),
                 "deepseek".into(),
                 "mistral".into(),
+                "alias".into(),
             ]),
             ..Default::default()
         }
@@ -896,6 +1012,15 @@ impl Settings for AgentSettings {
                 value.preferred_completion_mode,
             );
             merge(&mut settings.enable_feedback, value.enable_feedback);
+            merge(&mut settings.context_overflow, value.context_overflow);
+            merge(&mut settings.image_quality, value.image_quality);
+            settings.tool_result_max_tokens = value
+                .tool_result_max_tokens
+                .or(settings.tool_result_max_tokens.take());
+            merge(
+                &mut settings.tool_result_overflow,
+                value.tool_result_overflow,
+            );
 
             settings
                 .model_parameters
@@ -1031,6 +1156,10 @@ mod tests {
                             stream_edits: None,
                             single_file_review: None,
                             enable_feedback: None,
+                            context_overflow: None,
+                            image_quality: None,
+                            tool_result_max_tokens: None,
+                            tool_result_overflow: None,
                             model_parameters: Vec::new(),
                             preferred_completion_mode: None,
                         })),
@@ -9,6 +9,8 @@ use serde::{Deserialize, Serialize};
 use strum::{EnumIter, EnumString};
 use thiserror::Error;
 
+pub mod batches;
+
 pub const ANTHROPIC_API_URL: &str = "https://api.anthropic.com";
 
 #[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
@@ -265,6 +267,21 @@ impl Model {
 
     pub const DEFAULT_BETA_HEADERS: &[&str] = &["prompt-caching-2024-07-31"];
 
+    /// Lets the model think between tool calls, instead of only at the start of its turn.
+    /// https://docs.anthropic.com/en/docs/build-with-claude/extended-thinking#interleaved-thinking
+    pub const INTERLEAVED_THINKING_BETA_HEADER: &str = "interleaved-thinking-2025-05-14";
+
+    /// Whether the model can think between tool calls, which only Claude 4 models can.
+    pub fn supports_interleaved_thinking(&self) -> bool {
+        matches!(
+            self,
+            Self::ClaudeOpus4
+                | Self::ClaudeOpus4Thinking
+                | Self::ClaudeSonnet4
+                | Self::ClaudeSonnet4Thinking
+        )
+    }
+
     pub fn beta_headers(&self) -> String {
         let mut headers = Self::DEFAULT_BETA_HEADERS
             .into_iter()
@@ -306,6 +323,20 @@ impl Model {
     }
 }
 
+/// The beta headers for `request`, which include interleaved thinking when the request enables
+/// thinking on a model that supports it.
+fn request_beta_headers(request: &Request) -> String {
+    let Ok(model) = Model::from_id(&request.model) else {
+        return Model::DEFAULT_BETA_HEADERS.join(",");
+    };
+    let mut headers = model.beta_headers();
+    if request.thinking.is_some() && model.supports_interleaved_thinking() {
+        headers.push(',');
+        headers.push_str(Model::INTERLEAVED_THINKING_BETA_HEADER);
+    }
+    headers
+}
+
 pub async fn complete(
     client: &dyn HttpClient,
     api_url: &str,
@@ -313,9 +344,7 @@ pub async fn complete(
     request: Request,
 ) -> Result<Response, AnthropicError> {
     let uri = format!("{api_url}/v1/messages");
-    let beta_headers = Model::from_id(&request.model)
-        .map(|model| model.beta_headers())
-        .unwrap_or_else(|_err| Model::DEFAULT_BETA_HEADERS.join(","));
+    let beta_headers = request_beta_headers(&request);
     let request_builder = HttpRequest::builder()
         .method(Method::POST)
         .uri(uri)
@@ -461,9 +490,7 @@ pub async fn stream_completion_with_rate_limit_info(
         stream: true,
     };
     let uri = format!("{api_url}/v1/messages");
-    let beta_headers = Model::from_id(&request.base.model)
-        .map(|model| model.beta_headers())
-        .unwrap_or_else(|_err| Model::DEFAULT_BETA_HEADERS.join(","));
+    let beta_headers = request_beta_headers(&request.base);
     let request_builder = HttpRequest::builder()
         .method(Method::POST)
         .uri(uri)
@@ -537,20 +564,20 @@ pub struct CacheControl {
     pub cache_type: CacheControlType,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
     pub role: Role,
     pub content: Vec<RequestContent>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
     User,
     Assistant,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum RequestContent {
     #[serde(rename = "text")]
@@ -590,16 +617,55 @@ pub enum RequestContent {
         #[serde(skip_serializing_if = "Option::is_none")]
         cache_control: Option<CacheControl>,
     },
+    #[serde(rename = "document")]
+    Document {
+        source: DocumentSource,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        title: Option<String>,
+        /// Whether the response can cite the document. Citations have to be enabled for all of a
+        /// request's documents or for none of them.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        citations: Option<CitationsConfig>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentSource {
+    #[serde(rename = "type")]
+    pub source_type: String,
+    pub media_type: String,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CitationsConfig {
+    pub enabled: bool,
 }
 
+/// A part of a document that a text block of the response cites.
 #[derive(Debug, Serialize, Deserialize)]
+pub struct TextCitation {
+    /// How the cited part is located, e.g. `char_location` for text documents.
+    #[serde(rename = "type")]
+    pub citation_type: String,
+    pub cited_text: String,
+    /// The index of the cited document among all of the request's documents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_index: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_title: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ToolResultContent {
     Plain(String),
     Multipart(Vec<ToolResultPart>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ToolResultPart {
     Text { text: String },
@@ -610,7 +676,11 @@ pub enum ToolResultPart {
 #[serde(tag = "type")]
 pub enum ResponseContent {
     #[serde(rename = "text")]
-    Text { text: String },
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        citations: Option<Vec<TextCitation>>,
+    },
     #[serde(rename = "thinking")]
     Thinking { thinking: String },
     #[serde(rename = "redacted_thinking")]
@@ -623,7 +693,7 @@ pub enum ResponseContent {
     },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ImageSource {
     #[serde(rename = "type")]
     pub source_type: String,
@@ -631,36 +701,49 @@ pub struct ImageSource {
     pub data: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Tool {
     pub name: String,
     pub description: String,
     pub input_schema: serde_json::Value,
+    /// A cache breakpoint on the last tool caches all of the request's tools.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_control: Option<CacheControl>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ToolChoice {
-    Auto,
-    Any,
-    Tool { name: String },
+    Auto {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    Any {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    Tool {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
     None,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum Thinking {
     Enabled { budget_tokens: Option<u32> },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum StringOrContents {
     String(String),
     Content(Vec<RequestContent>),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Request {
     pub model: String,
     pub max_tokens: u32,
@@ -692,7 +775,7 @@ struct StreamingRequest {
     pub stream: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Metadata {
     pub user_id: Option<String>,
 }
@@ -759,6 +842,8 @@ pub enum ContentDelta {
     SignatureDelta { signature: String },
     #[serde(rename = "input_json_delta")]
     InputJsonDelta { partial_json: String },
+    #[serde(rename = "citations_delta")]
+    CitationsDelta { citation: TextCitation },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
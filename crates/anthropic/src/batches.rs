@@ -0,0 +1,233 @@
+use anyhow::{Context as _, Result, anyhow};
+use futures::AsyncReadExt;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AnthropicError, ApiError, ContentDelta, Event, MessageDelta, Request, Response,
+    ResponseContent, Usage, request_beta_headers,
+};
+
+#[derive(Debug, Serialize)]
+struct CreateBatch<'a> {
+    requests: Vec<BatchRequest<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchRequest<'a> {
+    custom_id: &'a str,
+    params: &'a Request,
+}
+
+/// A batch of requests that Anthropic completes within a day at a lower price.
+#[derive(Debug, Deserialize)]
+pub struct MessageBatch {
+    pub id: String,
+    /// One of `in_progress`, `canceling`, or `ended`.
+    pub processing_status: String,
+    /// Where the results can be downloaded from once processing has ended.
+    #[serde(default)]
+    pub results_url: Option<String>,
+}
+
+/// One line of a batch's results.
+#[derive(Debug, Deserialize)]
+pub struct BatchResult {
+    pub custom_id: String,
+    pub result: BatchResultOutcome,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BatchResultOutcome {
+    Succeeded { message: Response },
+    Errored { error: BatchResultError },
+    Canceled,
+    Expired,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchResultError {
+    pub error: ApiError,
+}
+
+impl BatchResultOutcome {
+    /// The message that the request was answered with, or why it wasn't.
+    pub fn into_result(self) -> Result<Response, AnthropicError> {
+        match self {
+            Self::Succeeded { message } => Ok(message),
+            Self::Errored { error } => Err(AnthropicError::ApiError(error.error)),
+            Self::Canceled => Err(anyhow!("the batch was canceled").into()),
+            Self::Expired => {
+                Err(anyhow!("the batch expired before the request was completed").into())
+            }
+        }
+    }
+}
+
+/// Starts a batch job that completes `requests`, by their custom IDs.
+pub async fn create_batch(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    requests: &[(String, Request)],
+) -> Result<MessageBatch> {
+    let mut beta_headers = Vec::new();
+    for (_, request) in requests {
+        for header in request_beta_headers(request).split(',') {
+            if !beta_headers.contains(&header.to_string()) {
+                beta_headers.push(header.to_string());
+            }
+        }
+    }
+    let create_batch = CreateBatch {
+        requests: requests
+            .iter()
+            .map(|(custom_id, params)| BatchRequest { custom_id, params })
+            .collect(),
+    };
+    let request = authorized(
+        Method::POST,
+        format!("{api_url}/v1/messages/batches"),
+        api_key,
+    )
+    .header("Anthropic-Beta", beta_headers.join(","))
+    .header("Content-Type", "application/json")
+    .body(AsyncBody::from(serde_json::to_string(&create_batch)?))?;
+    send(client, request)
+        .await
+        .context("failed to create batch")
+}
+
+pub async fn retrieve_batch(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    batch_id: &str,
+) -> Result<MessageBatch> {
+    let request = authorized(
+        Method::GET,
+        format!("{api_url}/v1/messages/batches/{batch_id}"),
+        api_key,
+    )
+    .body(AsyncBody::empty())?;
+    send(client, request).await
+}
+
+/// The results of a batch whose processing has ended.
+pub async fn batch_results(
+    client: &dyn HttpClient,
+    api_key: &str,
+    batch: &MessageBatch,
+) -> Result<Vec<BatchResult>> {
+    let results_url = batch
+        .results_url
+        .clone()
+        .context("the batch has no results yet")?;
+    let request = authorized(Method::GET, results_url, api_key).body(AsyncBody::empty())?;
+    let body = read_body(client, request).await?;
+    body.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::from_str(line).context("failed to parse batch results"))
+        .collect()
+}
+
+/// Turns a complete message into the events it would have streamed as, so that batched
+/// completions are handled like streaming ones.
+pub fn response_events(response: Response) -> Vec<Event> {
+    let Response {
+        id,
+        response_type,
+        role,
+        content,
+        model,
+        stop_reason,
+        stop_sequence,
+        usage,
+    } = response;
+    let mut events = vec![Event::MessageStart {
+        message: Response {
+            id,
+            response_type,
+            role,
+            content: Vec::new(),
+            model,
+            stop_reason: None,
+            stop_sequence: None,
+            usage,
+        },
+    }];
+    for (index, content_block) in content.into_iter().enumerate() {
+        match content_block {
+            ResponseContent::ToolUse { id, name, input } => {
+                events.push(Event::ContentBlockStart {
+                    index,
+                    content_block: ResponseContent::ToolUse {
+                        id,
+                        name,
+                        input: serde_json::Value::Object(Default::default()),
+                    },
+                });
+                events.push(Event::ContentBlockDelta {
+                    index,
+                    delta: ContentDelta::InputJsonDelta {
+                        partial_json: input.to_string(),
+                    },
+                });
+            }
+            content_block => events.push(Event::ContentBlockStart {
+                index,
+                content_block,
+            }),
+        }
+        events.push(Event::ContentBlockStop { index });
+    }
+    events.push(Event::MessageDelta {
+        delta: MessageDelta {
+            stop_reason,
+            stop_sequence,
+        },
+        usage: Usage::default(),
+    });
+    events.push(Event::MessageStop);
+    events
+}
+
+fn authorized(method: Method, uri: String, api_key: &str) -> http_client::http::request::Builder {
+    HttpRequest::builder()
+        .method(method)
+        .uri(uri)
+        .header("Anthropic-Version", "2023-06-01")
+        .header("X-Api-Key", api_key)
+}
+
+async fn send<T: DeserializeOwned>(
+    client: &dyn HttpClient,
+    request: HttpRequest<AsyncBody>,
+) -> Result<T> {
+    let body = read_body(client, request).await?;
+    serde_json::from_str(&body).context("failed to deserialize response body")
+}
+
+async fn read_body(client: &dyn HttpClient, request: HttpRequest<AsyncBody>) -> Result<String> {
+    let mut response = client
+        .send(request)
+        .await
+        .context("failed to send request to Anthropic")?;
+    let mut body = String::new();
+    response
+        .body_mut()
+        .read_to_string(&mut body)
+        .await
+        .context("failed to read response body")?;
+    if response.status().is_success() {
+        Ok(body)
+    } else {
+        Err(anyhow!(
+            "Failed to connect to API: {} {}",
+            response.status(),
+            body
+        ))
+    }
+}
@@ -2071,6 +2071,15 @@ impl AssistantContext {
                                 match event {
                                     LanguageModelCompletionEvent::StatusUpdate { .. } => {}
                                     LanguageModelCompletionEvent::StartMessage { .. } => {}
+                                    LanguageModelCompletionEvent::Fallback { .. } => {}
+                                    LanguageModelCompletionEvent::Candidate { .. } => {}
+                                    LanguageModelCompletionEvent::Logprobs(_) => {}
+                                    LanguageModelCompletionEvent::Citations(_) => {}
+                                    LanguageModelCompletionEvent::GuardrailIntervention(_) => {}
+                                    LanguageModelCompletionEvent::UploadProgress { .. } => {}
+                                    LanguageModelCompletionEvent::ExecutableCode { .. } => {}
+                                    LanguageModelCompletionEvent::CodeExecutionResult { .. } => {}
+                                    LanguageModelCompletionEvent::RedactedThinking { .. } => {}
                                     LanguageModelCompletionEvent::Stop(reason) => {
                                         stop_reason = reason;
                                     }
@@ -2216,6 +2225,7 @@ impl AssistantContext {
                             StopReason::EndTurn => {}
                             StopReason::MaxTokens => {}
                             StopReason::Refusal => {}
+                            StopReason::ContentFilter => {}
                         }
                     }
                 })
@@ -2280,6 +2290,23 @@ impl AssistantContext {
             tool_choice: None,
             stop: Vec::new(),
             temperature: model.and_then(|model| AgentSettings::temperature_for_model(model, cx)),
+            seed: model.and_then(|model| AgentSettings::seed_for_model(model, cx)),
+            frequency_penalty: model
+                .and_then(|model| AgentSettings::frequency_penalty_for_model(model, cx)),
+            presence_penalty: model
+                .and_then(|model| AgentSettings::presence_penalty_for_model(model, cx)),
+            logit_bias: model
+                .map(|model| AgentSettings::logit_bias_for_model(model, cx))
+                .unwrap_or_default(),
+            top_p: None,
+            top_k: None,
+            candidates: None,
+            logprobs: None,
+            response_schema: None,
+            parallel_tool_calls: None,
+            prediction: None,
+            batch: false,
+            workspace: None,
         };
         for message in self.messages(cx) {
             if message.status != MessageStatus::Done {
@@ -1,11 +1,12 @@
 use anyhow::Result;
-use serde_json::Value;
+use serde_json::{Map, Value, json};
 
 use crate::LanguageModelToolSchemaFormat;
 
 /// Tries to adapt a JSON schema representation to be compatible with the specified format.
 ///
 /// If the json cannot be made compatible with the specified format, an error is returned.
+/// Keywords that the format doesn't support, and that can only be left out, are logged.
 pub fn adapt_schema_to_format(
     json: &mut Value,
     format: LanguageModelToolSchemaFormat,
@@ -15,10 +16,232 @@ pub fn adapt_schema_to_format(
         obj.remove("title");
     }
 
+    let mut omitted = Vec::new();
     match format {
-        LanguageModelToolSchemaFormat::JsonSchema => Ok(()),
-        LanguageModelToolSchemaFormat::JsonSchemaSubset => adapt_to_json_schema_subset(json),
+        LanguageModelToolSchemaFormat::JsonSchema => {}
+        LanguageModelToolSchemaFormat::JsonSchemaSubset => adapt_to_json_schema_subset(json)?,
+        LanguageModelToolSchemaFormat::JsonSchemaStrict => {
+            if let Value::Object(obj) = json {
+                obj.entry("type").or_insert_with(|| "object".into());
+            }
+            anyhow::ensure!(
+                json.get("type") == Some(&Value::from("object")),
+                "Schema cannot be made compatible because it isn't an object"
+            );
+            adapt_to_strict_json_schema(json, &mut omitted)?
+        }
+        LanguageModelToolSchemaFormat::JsonSchemaDraft7 => {
+            adapt_to_json_schema_draft_7(json, &mut omitted)?
+        }
+    }
+
+    if !omitted.is_empty() {
+        omitted.sort_unstable();
+        omitted.dedup();
+        log::warn!(
+            "left {} out of a tool's input schema, since {format:?} doesn't support them",
+            omitted.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Calls `f` with each of the schema's subschemas, but not with the schemas nested within them.
+fn for_each_subschema(
+    schema: &mut Map<String, Value>,
+    f: &mut impl FnMut(&mut Value) -> Result<()>,
+) -> Result<()> {
+    for (key, value) in schema.iter_mut() {
+        match (key.as_str(), value) {
+            (
+                "properties" | "patternProperties" | "$defs" | "definitions" | "dependentSchemas"
+                | "dependencies",
+                Value::Object(subschemas),
+            ) => {
+                for subschema in subschemas.values_mut() {
+                    f(subschema)?;
+                }
+            }
+            ("anyOf" | "oneOf" | "allOf" | "prefixItems" | "items", Value::Array(subschemas)) => {
+                for subschema in subschemas {
+                    f(subschema)?;
+                }
+            }
+            (
+                "items"
+                | "additionalItems"
+                | "additionalProperties"
+                | "contains"
+                | "not"
+                | "if"
+                | "then"
+                | "else"
+                | "propertyNames"
+                | "unevaluatedItems"
+                | "unevaluatedProperties",
+                subschema @ Value::Object(_),
+            ) => f(subschema)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Tries to adapt the json schema to the subset that OpenAI supports in strict mode, see
+/// https://platform.openai.com/docs/guides/structured-outputs#supported-schemas
+///
+/// Strict mode requires every property, so optional properties are made nullable instead.
+fn adapt_to_strict_json_schema(json: &mut Value, omitted: &mut Vec<&'static str>) -> Result<()> {
+    let Value::Object(obj) = json else {
+        return Ok(());
+    };
+
+    anyhow::ensure!(
+        !obj.contains_key("allOf"),
+        "Schema cannot be made compatible because it contains \"allOf\""
+    );
+
+    const UNSUPPORTED_KEYS: [&str; 14] = [
+        "minLength",
+        "maxLength",
+        "patternProperties",
+        "unevaluatedProperties",
+        "propertyNames",
+        "minProperties",
+        "maxProperties",
+        "unevaluatedItems",
+        "contains",
+        "uniqueItems",
+        "not",
+        "if",
+        "then",
+        "else",
+    ];
+    for key in UNSUPPORTED_KEYS {
+        if obj.remove(key).is_some() {
+            omitted.push(key);
+        }
+    }
+
+    if let Some(subschemas) = obj.remove("oneOf") {
+        obj.insert("anyOf".to_string(), subschemas);
+    }
+
+    let is_object = obj.contains_key("properties")
+        || match obj.get("type") {
+            Some(Value::String(ty)) => ty == "object",
+            Some(Value::Array(types)) => types.contains(&Value::from("object")),
+            _ => false,
+        };
+    if is_object {
+        let required = match obj.get("required") {
+            Some(Value::Array(required)) => required.clone(),
+            _ => Vec::new(),
+        };
+        let properties = obj
+            .entry("properties")
+            .or_insert_with(|| Value::Object(Map::new()));
+        let mut names = Vec::new();
+        if let Value::Object(properties) = properties {
+            for (name, property) in properties.iter_mut() {
+                if !required.iter().any(|required| required == name) {
+                    make_nullable(property);
+                }
+                names.push(Value::from(name.as_str()));
+            }
+        }
+        obj.insert("required".to_string(), Value::Array(names));
+        match obj.insert("additionalProperties".to_string(), Value::Bool(false)) {
+            None | Some(Value::Bool(false)) => {}
+            Some(_) => omitted.push("additionalProperties"),
+        }
+    }
+
+    for_each_subschema(obj, &mut |subschema| {
+        adapt_to_strict_json_schema(subschema, omitted)
+    })
+}
+
+/// Lets the schema's value be `null` too.
+fn make_nullable(schema: &mut Value) {
+    let Value::Object(obj) = schema else {
+        return;
+    };
+    if let Some(Value::Array(values)) = obj.get_mut("enum") {
+        if !values.contains(&Value::Null) {
+            values.push(Value::Null);
+        }
+    }
+    match obj.get_mut("type") {
+        Some(Value::Array(types)) => {
+            if !types.contains(&Value::from("null")) {
+                types.push("null".into());
+            }
+        }
+        Some(ty @ Value::String(_)) => {
+            if *ty != "null" {
+                *ty = json!([ty.take(), "null"]);
+            }
+        }
+        _ => {
+            let subschema = schema.take();
+            *schema = json!({ "anyOf": [subschema, { "type": "null" }] });
+        }
+    }
+}
+
+/// Adapts the json schema to draft 7, replacing the keywords that later drafts added with their
+/// draft 7 equivalents, or leaving them out where there aren't any.
+fn adapt_to_json_schema_draft_7(json: &mut Value, omitted: &mut Vec<&'static str>) -> Result<()> {
+    let Value::Object(obj) = json else {
+        return Ok(());
+    };
+
+    const UNSUPPORTED_KEYS: [&str; 7] = [
+        "unevaluatedProperties",
+        "unevaluatedItems",
+        "minContains",
+        "maxContains",
+        "$anchor",
+        "$dynamicRef",
+        "$dynamicAnchor",
+    ];
+    for key in UNSUPPORTED_KEYS {
+        if obj.remove(key).is_some() {
+            omitted.push(key);
+        }
+    }
+
+    if let Some(definitions) = obj.remove("$defs") {
+        obj.entry("definitions").or_insert(definitions);
+    }
+    if let Some(Value::String(reference)) = obj.get_mut("$ref") {
+        if let Some(name) = reference.strip_prefix("#/$defs/") {
+            *reference = format!("#/definitions/{name}");
+        }
     }
+
+    if let Some(prefix_items) = obj.remove("prefixItems") {
+        if let Some(items) = obj.remove("items") {
+            obj.insert("additionalItems".to_string(), items);
+        }
+        obj.insert("items".to_string(), prefix_items);
+    }
+
+    for key in ["dependentRequired", "dependentSchemas"] {
+        if let Some(Value::Object(dependencies)) = obj.remove(key) {
+            if let Value::Object(merged) = obj
+                .entry("dependencies")
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                merged.extend(dependencies);
+            }
+        }
+    }
+
+    for_each_subschema(obj, &mut |subschema| {
+        adapt_to_json_schema_draft_7(subschema, omitted)
+    })
 }
 
 /// Tries to adapt the json schema so that it is compatible with https://ai.google.dev/api/caching#Schema
@@ -237,4 +460,111 @@ mod tests {
 
         assert!(adapt_to_json_schema_subset(&mut json).is_err());
     }
+
+    #[test]
+    fn test_transform_to_strict_json_schema() {
+        let mut json = json!({
+            "$schema": "http://json-schema.org/draft-07/schema#",
+            "title": "EditFile",
+            "type": "object",
+            "properties": {
+                "path": { "type": "string", "minLength": 1 },
+                "mode": { "type": "string", "enum": ["edit", "create"] },
+                "options": {
+                    "type": "object",
+                    "properties": {
+                        "backup": { "type": "boolean" }
+                    },
+                    "required": ["backup"],
+                    "additionalProperties": true
+                },
+                "target": {
+                    "oneOf": [
+                        { "type": "string" },
+                        { "type": "integer" }
+                    ]
+                }
+            },
+            "required": ["path"]
+        });
+
+        adapt_schema_to_format(&mut json, LanguageModelToolSchemaFormat::JsonSchemaStrict).unwrap();
+
+        assert_eq!(
+            json,
+            json!({
+                "type": "object",
+                "properties": {
+                    "path": { "type": "string" },
+                    "mode": { "type": ["string", "null"], "enum": ["edit", "create", null] },
+                    "options": {
+                        "type": ["object", "null"],
+                        "properties": {
+                            "backup": { "type": "boolean" }
+                        },
+                        "required": ["backup"],
+                        "additionalProperties": false
+                    },
+                    "target": {
+                        "anyOf": [
+                            {
+                                "anyOf": [
+                                    { "type": "string" },
+                                    { "type": "integer" }
+                                ]
+                            },
+                            { "type": "null" }
+                        ]
+                    }
+                },
+                "required": ["path", "mode", "options", "target"],
+                "additionalProperties": false
+            })
+        );
+
+        let mut json = json!({ "type": "string" });
+        assert!(
+            adapt_schema_to_format(&mut json, LanguageModelToolSchemaFormat::JsonSchemaStrict)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_transform_to_json_schema_draft_7() {
+        let mut json = json!({
+            "type": "object",
+            "properties": {
+                "range": {
+                    "type": "array",
+                    "prefixItems": [{ "type": "integer" }, { "type": "integer" }],
+                    "items": false,
+                    "unevaluatedItems": false
+                },
+                "location": { "$ref": "#/$defs/Location" }
+            },
+            "$defs": {
+                "Location": { "type": "string" }
+            }
+        });
+
+        adapt_schema_to_format(&mut json, LanguageModelToolSchemaFormat::JsonSchemaDraft7).unwrap();
+
+        assert_eq!(
+            json,
+            json!({
+                "type": "object",
+                "properties": {
+                    "range": {
+                        "type": "array",
+                        "items": [{ "type": "integer" }, { "type": "integer" }],
+                        "additionalItems": false
+                    },
+                    "location": { "$ref": "#/definitions/Location" }
+                },
+                "definitions": {
+                    "Location": { "type": "string" }
+                }
+            })
+        );
+    }
 }
@@ -107,8 +107,16 @@ impl EditAgent {
                 edit_description,
             }
             .render(&this.templates)?;
+            // When overwriting a file, much of its content is usually kept.
+            let prediction = (!snapshot.is_empty()).then(|| snapshot.text());
             let new_chunks = this
-                .request(conversation, CompletionIntent::CreateFile, prompt, cx)
+                .request(
+                    conversation,
+                    CompletionIntent::CreateFile,
+                    prompt,
+                    prediction,
+                    cx,
+                )
                 .await?;
 
             let (output, mut inner_events) = this.overwrite_with_chunks(buffer, new_chunks, cx);
@@ -217,7 +225,7 @@ impl EditAgent {
             }
             .render(&this.templates)?;
             let edit_chunks = this
-                .request(conversation, CompletionIntent::EditFile, prompt, cx)
+                .request(conversation, CompletionIntent::EditFile, prompt, None, cx)
                 .await?;
             this.apply_edit_chunks(buffer, edit_chunks, events_tx, cx)
                 .await
@@ -596,6 +604,7 @@ impl EditAgent {
         mut conversation: LanguageModelRequest,
         intent: CompletionIntent,
         prompt: String,
+        prediction: Option<String>,
         cx: &mut AsyncApp,
     ) -> Result<BoxStream<'static, Result<String, LanguageModelCompletionError>>> {
         let mut messages_iter = conversation.messages.iter_mut();
@@ -659,6 +668,19 @@ impl EditAgent {
             tools,
             stop: Vec::new(),
             temperature: None,
+            seed: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            logit_bias: Default::default(),
+            top_p: None,
+            top_k: None,
+            candidates: None,
+            logprobs: None,
+            response_schema: None,
+            parallel_tool_calls: None,
+            prediction,
+            batch: false,
+            workspace: None,
         };
 
         Ok(self.model.stream_completion_text(request, cx).await?.stream)
@@ -23,7 +23,9 @@ fn schema_to_json(
 
 fn root_schema_for<T: JsonSchema>(format: LanguageModelToolSchemaFormat) -> RootSchema {
     let mut generator = match format {
-        LanguageModelToolSchemaFormat::JsonSchema => schemars::SchemaGenerator::default(),
+        LanguageModelToolSchemaFormat::JsonSchema
+        | LanguageModelToolSchemaFormat::JsonSchemaStrict
+        | LanguageModelToolSchemaFormat::JsonSchemaDraft7 => schemars::SchemaGenerator::default(),
         LanguageModelToolSchemaFormat::JsonSchemaSubset => {
             schemars::r#gen::SchemaSettings::default()
                 .with(|settings| {
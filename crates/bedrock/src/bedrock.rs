@@ -8,9 +8,9 @@ use aws_sdk_bedrockruntime as bedrock;
 pub use aws_sdk_bedrockruntime as bedrock_client;
 pub use aws_sdk_bedrockruntime::types::{
     AnyToolChoice as BedrockAnyToolChoice, AutoToolChoice as BedrockAutoToolChoice,
-    ContentBlock as BedrockInnerContent, Tool as BedrockTool, ToolChoice as BedrockToolChoice,
-    ToolConfiguration as BedrockToolConfig, ToolInputSchema as BedrockToolInputSchema,
-    ToolSpecification as BedrockToolSpec,
+    ContentBlock as BedrockInnerContent, SpecificToolChoice as BedrockSpecificToolChoice,
+    Tool as BedrockTool, ToolChoice as BedrockToolChoice, ToolConfiguration as BedrockToolConfig,
+    ToolInputSchema as BedrockToolInputSchema, ToolSpecification as BedrockToolSpec,
 };
 pub use aws_smithy_types::Blob as BedrockBlob;
 use aws_smithy_types::{Document, Number as AwsNumber};
@@ -28,6 +28,7 @@ use futures::stream::{self, BoxStream, Stream};
 use serde::{Deserialize, Serialize};
 use serde_json::{Number, Value};
 use thiserror::Error;
+use util::defer;
 
 pub use crate::models::*;
 
@@ -36,68 +37,87 @@ pub async fn stream_completion(
     request: Request,
     handle: tokio::runtime::Handle,
 ) -> Result<BoxStream<'static, Result<BedrockStreamingResponse, BedrockError>>, Error> {
-    handle
-        .spawn(async move {
-            let mut response = bedrock::Client::converse_stream(&client)
-                .model_id(request.model.clone())
-                .set_messages(request.messages.into());
-
-            if let Some(Thinking::Enabled {
-                budget_tokens: Some(budget_tokens),
-            }) = request.thinking
-            {
-                response =
-                    response.additional_model_request_fields(Document::Object(HashMap::from([(
-                        "thinking".to_string(),
-                        Document::from(HashMap::from([
-                            ("type".to_string(), Document::String("enabled".to_string())),
-                            (
-                                "budget_tokens".to_string(),
-                                Document::Number(AwsNumber::PosInt(budget_tokens)),
-                            ),
-                        ])),
-                    )])));
-            }
+    let task = handle.spawn(async move {
+        let mut response = bedrock::Client::converse_stream(&client)
+            .model_id(request.model.clone())
+            .set_messages(request.messages.into());
 
-            if request.tools.is_some() && !request.tools.as_ref().unwrap().tools.is_empty() {
-                response = response.set_tool_config(request.tools);
-            }
+        if let Some(Thinking::Enabled {
+            budget_tokens: Some(budget_tokens),
+        }) = request.thinking
+        {
+            response =
+                response.additional_model_request_fields(Document::Object(HashMap::from([(
+                    "thinking".to_string(),
+                    Document::from(HashMap::from([
+                        ("type".to_string(), Document::String("enabled".to_string())),
+                        (
+                            "budget_tokens".to_string(),
+                            Document::Number(AwsNumber::PosInt(budget_tokens)),
+                        ),
+                    ])),
+                )])));
+        }
+
+        if request.tools.is_some() && !request.tools.as_ref().unwrap().tools.is_empty() {
+            response = response.set_tool_config(request.tools);
+        }
+
+        if let Some(guardrail) = request.guardrail {
+            response = response.guardrail_config(
+                bedrock::types::GuardrailStreamConfiguration::builder()
+                    .guardrail_identifier(guardrail.identifier)
+                    .guardrail_version(guardrail.version)
+                    .trace(bedrock::types::GuardrailTrace::Enabled)
+                    .build()
+                    .context("building the guardrail configuration")?,
+            );
+        }
 
-            let response = response.send().await;
-
-            match response {
-                Ok(output) => {
-                    let stream: Pin<
-                        Box<
-                            dyn Stream<Item = Result<BedrockStreamingResponse, BedrockError>>
-                                + Send,
-                        >,
-                    > = Box::pin(stream::unfold(output.stream, |mut stream| async move {
-                        match stream.recv().await {
-                            Ok(Some(output)) => Some(({ Ok(output) }, stream)),
-                            Ok(None) => None,
-                            Err(err) => {
-                                Some((
-                                    // TODO: Figure out how we can capture Throttling Exceptions
-                                    Err(BedrockError::ClientError(anyhow!(
-                                        "{:?}",
-                                        aws_sdk_bedrockruntime::error::DisplayErrorContext(err)
-                                    ))),
-                                    stream,
-                                ))
-                            }
+        if !request.stop_sequences.is_empty() || request.top_p.is_some() {
+            response = response.inference_config(
+                bedrock::types::InferenceConfiguration::builder()
+                    .set_stop_sequences(
+                        (!request.stop_sequences.is_empty()).then_some(request.stop_sequences),
+                    )
+                    .set_top_p(request.top_p)
+                    .build(),
+            );
+        }
+
+        let response = response.send().await;
+
+        match response {
+            Ok(output) => {
+                let stream: Pin<
+                    Box<dyn Stream<Item = Result<BedrockStreamingResponse, BedrockError>> + Send>,
+                > = Box::pin(stream::unfold(output.stream, |mut stream| async move {
+                    match stream.recv().await {
+                        Ok(Some(output)) => Some(({ Ok(output) }, stream)),
+                        Ok(None) => None,
+                        Err(err) => {
+                            Some((
+                                // TODO: Figure out how we can capture Throttling Exceptions
+                                Err(BedrockError::ClientError(anyhow!(
+                                    "{:?}",
+                                    aws_sdk_bedrockruntime::error::DisplayErrorContext(err)
+                                ))),
+                                stream,
+                            ))
                         }
-                    }));
-                    Ok(stream)
-                }
-                Err(err) => Err(anyhow!(
-                    "{:?}",
-                    aws_sdk_bedrockruntime::error::DisplayErrorContext(err)
-                )),
+                    }
+                }));
+                Ok(stream)
             }
-        })
-        .await
-        .context("spawning a task")?
+            Err(err) => Err(anyhow!(
+                "{:?}",
+                aws_sdk_bedrockruntime::error::DisplayErrorContext(err)
+            )),
+        }
+    });
+    let abort_handle = task.abort_handle();
+    let _abort_on_drop = defer(move || abort_handle.abort());
+    task.await.context("spawning a task")?
 }
 
 pub fn aws_document_to_value(document: &Document) -> Value {
@@ -162,6 +182,15 @@ pub struct Request {
     pub temperature: Option<f32>,
     pub top_k: Option<u32>,
     pub top_p: Option<f32>,
+    pub guardrail: Option<Guardrail>,
+}
+
+/// A guardrail to apply to a request and its response. Its trace is always requested, so that
+/// the response's metadata says what the guardrail detected.
+#[derive(Debug, Clone)]
+pub struct Guardrail {
+    pub identifier: String,
+    pub version: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -89,6 +89,10 @@ struct Args {
     /// Will attempt to give the correct command to run
     #[arg(long)]
     system_specs: bool,
+    /// Save an API key for a language model provider (e.g. `anthropic` or `openai`), entered in
+    /// the terminal or piped in through stdin, without opening Zed
+    #[arg(long, value_name = "PROVIDER")]
+    configure_language_model: Option<String>,
     /// Uninstall Zed from user system
     #[cfg(all(
         any(target_os = "linux", target_os = "macos"),
@@ -172,6 +176,18 @@ fn main() -> Result<()> {
         anyhow::bail!(msg.join("\n"));
     }
 
+    if let Some(provider) = &args.configure_language_model {
+        let mut command = std::process::Command::new(app.path());
+        command.arg("--configure-language-model").arg(provider);
+        if let Some(dir) = &user_data_dir {
+            command.arg("--user-data-dir").arg(dir);
+        }
+        let status = command
+            .status()
+            .with_context(|| format!("Failed to run {command:?}"))?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+
     #[cfg(all(
         any(target_os = "linux", target_os = "macos"),
         not(feature = "no-bundled-uninstall")
@@ -8,7 +8,8 @@ use http_client::Url;
 use http_proxy::{HttpProxyType, connect_http_proxy_stream, parse_http_proxy};
 use socks_proxy::{SocksVersion, connect_socks_proxy_stream, parse_socks_proxy};
 
-pub(crate) async fn connect_proxy_stream(
+/// Connects to `rpc_host` through the HTTP or SOCKS proxy at `proxy`.
+pub async fn connect_proxy_stream(
     proxy: &Url,
     rpc_host: (&str, u16),
 ) -> Result<Box<dyn AsyncReadWrite>> {
@@ -56,7 +57,7 @@ fn parse_proxy_type(proxy: &Url) -> Option<((String, u16), ProxyType)> {
     Some(((host, port), proxy_type))
 }
 
-pub(crate) trait AsyncReadWrite:
+pub trait AsyncReadWrite:
     tokio::io::AsyncRead + tokio::io::AsyncWrite + Unpin + Send + 'static
 {
 }
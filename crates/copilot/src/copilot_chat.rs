@@ -172,6 +172,10 @@ pub struct Request {
     pub tools: Vec<Tool>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<ToolChoice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -193,6 +197,19 @@ pub enum ToolChoice {
     Auto,
     Any,
     None,
+    #[serde(untagged)]
+    Named(NamedToolChoice),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum NamedToolChoice {
+    Function { function: NamedFunction },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NamedFunction {
+    pub name: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
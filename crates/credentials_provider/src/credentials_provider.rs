@@ -1,3 +1,5 @@
+mod encrypted_file;
+
 use std::collections::HashMap;
 use std::future::Future;
 use std::path::PathBuf;
@@ -6,9 +8,11 @@ use std::sync::{Arc, LazyLock};
 
 use anyhow::Result;
 use futures::FutureExt as _;
-use gpui::{App, AsyncApp};
+use gpui::{App, AsyncApp, Global, SharedString};
 use release_channel::ReleaseChannel;
 
+use crate::encrypted_file::EncryptedFileCredentialsProvider;
+
 /// An environment variable whose presence indicates that the system keychain
 /// should be used in development.
 ///
@@ -22,6 +26,14 @@ static ZED_DEVELOPMENT_USE_KEYCHAIN: LazyLock<bool> = LazyLock::new(|| {
     std::env::var("ZED_DEVELOPMENT_USE_KEYCHAIN").map_or(false, |value| !value.is_empty())
 });
 
+/// An environment variable that, when set to `file`, stores credentials in an encrypted file
+/// instead of the system keychain.
+///
+/// Without it, the encrypted file is only used when the keychain can't be accessed and
+/// `ZED_CREDENTIALS_PASSPHRASE` is set.
+static ZED_CREDENTIALS_STORE_IS_FILE: LazyLock<bool> =
+    LazyLock::new(|| std::env::var("ZED_CREDENTIALS_STORE").is_ok_and(|value| value == "file"));
+
 /// A provider for credentials.
 ///
 /// Used to abstract over reading and writing credentials to some form of
@@ -77,12 +89,34 @@ impl dyn CredentialsProvider {
 
         if use_development_provider {
             Arc::new(DevelopmentCredentialsProvider::new())
+        } else if *ZED_CREDENTIALS_STORE_IS_FILE {
+            Arc::new(EncryptedFileCredentialsProvider::new())
         } else {
-            Arc::new(KeychainCredentialsProvider)
+            let file = EncryptedFileCredentialsProvider::new();
+            if file.has_passphrase() {
+                Arc::new(KeychainWithFileFallbackCredentialsProvider {
+                    keychain: KeychainCredentialsProvider,
+                    file,
+                })
+            } else {
+                Arc::new(KeychainCredentialsProvider)
+            }
         }
     }
 }
 
+/// Set when credentials were read from or written to the encrypted file because the system
+/// keychain couldn't be accessed, so that the user can be told where their credentials are.
+#[derive(Clone, Debug)]
+pub struct KeychainFallback {
+    /// The encrypted file that was used instead of the keychain.
+    pub path: PathBuf,
+    /// Why the keychain couldn't be accessed.
+    pub error: SharedString,
+}
+
+impl Global for KeychainFallback {}
+
 /// A credentials provider that stores credentials in the system keychain.
 struct KeychainCredentialsProvider;
 
@@ -118,6 +152,97 @@ impl CredentialsProvider for KeychainCredentialsProvider {
     }
 }
 
+/// A credentials provider that uses the system keychain, but falls back to an encrypted file
+/// when the keychain can't be accessed, e.g. when no secret service is running on Linux.
+///
+/// Only used when the user has opted in by providing a passphrase for the file.
+struct KeychainWithFileFallbackCredentialsProvider {
+    keychain: KeychainCredentialsProvider,
+    file: EncryptedFileCredentialsProvider,
+}
+
+impl KeychainWithFileFallbackCredentialsProvider {
+    fn report_fallback(&self, error: &anyhow::Error, cx: &AsyncApp) {
+        let fallback = KeychainFallback {
+            path: self.file.path().to_path_buf(),
+            error: format!("{error:#}").into(),
+        };
+        if let Err(error) = cx.update(|cx| cx.set_global(fallback)) {
+            log::error!("failed to report the credentials file fallback: {error:?}");
+        }
+    }
+}
+
+impl CredentialsProvider for KeychainWithFileFallbackCredentialsProvider {
+    fn read_credentials<'a>(
+        &'a self,
+        url: &'a str,
+        cx: &'a AsyncApp,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<(String, Vec<u8>)>>> + 'a>> {
+        async move {
+            match self.keychain.read_credentials(url, cx).await {
+                Ok(Some(credentials)) => return Ok(Some(credentials)),
+                Ok(None) if !self.file.exists() => return Ok(None),
+                Ok(None) => {}
+                Err(error) if !self.file.exists() => return Err(error),
+                Err(error) => {
+                    log::warn!(
+                        "failed to read from the keychain, using the credentials file: {error:?}"
+                    );
+                    self.report_fallback(&error, cx);
+                }
+            }
+            self.file.read_credentials(url, cx).await
+        }
+        .boxed_local()
+    }
+
+    fn write_credentials<'a>(
+        &'a self,
+        url: &'a str,
+        username: &'a str,
+        password: &'a [u8],
+        cx: &'a AsyncApp,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + 'a>> {
+        async move {
+            match self
+                .keychain
+                .write_credentials(url, username, password, cx)
+                .await
+            {
+                Ok(()) => Ok(()),
+                Err(error) => {
+                    log::warn!(
+                        "failed to write to the keychain, saving credentials in an encrypted file instead: {error:?}"
+                    );
+                    self.file
+                        .write_credentials(url, username, password, cx)
+                        .await?;
+                    self.report_fallback(&error, cx);
+                    Ok(())
+                }
+            }
+        }
+        .boxed_local()
+    }
+
+    fn delete_credentials<'a>(
+        &'a self,
+        url: &'a str,
+        cx: &'a AsyncApp,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + 'a>> {
+        async move {
+            let keychain_result = self.keychain.delete_credentials(url, cx).await;
+            if self.file.exists() {
+                self.file.delete_credentials(url, cx).await
+            } else {
+                keychain_result
+            }
+        }
+        .boxed_local()
+    }
+}
+
 /// A credentials provider that stores credentials in a local file.
 ///
 /// This MUST only be used in development, as this is not a secure way of storing
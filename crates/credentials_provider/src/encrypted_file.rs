@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use anyhow::{Context as _, Result, anyhow};
+use argon2::Argon2;
+use base64::Engine as _;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::aead::{Aead as _, KeyInit as _};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use futures::FutureExt as _;
+use gpui::AsyncApp;
+use rand::RngCore as _;
+use serde::{Deserialize, Serialize};
+
+use crate::CredentialsProvider;
+
+/// An environment variable holding the passphrase that the credentials file is encrypted with.
+/// Setting it is what opts into storing credentials in the file.
+const PASSPHRASE_VAR: &str = "ZED_CREDENTIALS_PASSPHRASE";
+
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+
+/// Held while the file is read and rewritten, so that concurrent writes don't drop each other's
+/// credentials. Providers are created whenever they're needed, so it can't belong to one.
+static FILE_LOCK: Mutex<()> = Mutex::new(());
+
+type Credentials = HashMap<String, (String, Vec<u8>)>;
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedFile {
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// A credentials provider that stores credentials in a file encrypted with XChaCha20-Poly1305,
+/// using a key derived from the user's passphrase, for systems without a usable keychain, such as
+/// containers and minimal Linux installs.
+///
+/// Deriving the key is deliberately slow, so the file is only touched on the background executor.
+#[derive(Clone)]
+pub(crate) struct EncryptedFileCredentialsProvider {
+    path: PathBuf,
+    passphrase: Option<Arc<str>>,
+}
+
+impl EncryptedFileCredentialsProvider {
+    pub(crate) fn new() -> Self {
+        Self::with_passphrase(
+            paths::data_dir().join("credentials.enc"),
+            std::env::var(PASSPHRASE_VAR)
+                .ok()
+                .filter(|passphrase| !passphrase.is_empty()),
+        )
+    }
+
+    fn with_passphrase(path: PathBuf, passphrase: Option<String>) -> Self {
+        Self {
+            path,
+            passphrase: passphrase.map(Into::into),
+        }
+    }
+
+    pub(crate) fn has_passphrase(&self) -> bool {
+        self.passphrase.is_some()
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn exists(&self) -> bool {
+        self.path.exists()
+    }
+
+    fn read(&self, url: &str) -> Result<Option<(String, Vec<u8>)>> {
+        let _lock = FILE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        Ok(self
+            .load_credentials()?
+            .and_then(|(mut credentials, _, _)| credentials.remove(url)))
+    }
+
+    fn update(&self, update: impl FnOnce(&mut Credentials)) -> Result<()> {
+        let _lock = FILE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let (mut credentials, salt, key) = match self.load_credentials()? {
+            Some(loaded) => loaded,
+            None => {
+                let mut salt = [0; SALT_LEN];
+                rand::thread_rng().fill_bytes(&mut salt);
+                let key = self.derive_key(&salt)?;
+                (Credentials::default(), salt, key)
+            }
+        };
+        update(&mut credentials);
+        self.save_credentials(&credentials, salt, &key)
+    }
+
+    fn load_credentials(&self) -> Result<Option<(Credentials, [u8; SALT_LEN], [u8; 32])>> {
+        let contents = match std::fs::read(&self.path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error.into()),
+        };
+        let file: EncryptedFile = serde_json::from_slice(&contents)
+            .with_context(|| format!("invalid credentials file {}", self.path.display()))?;
+        let salt: [u8; SALT_LEN] = decode(&file.salt)?;
+        let nonce: [u8; NONCE_LEN] = decode(&file.nonce)?;
+        let ciphertext = BASE64.decode(&file.ciphertext)?;
+
+        let key = self.derive_key(&salt)?;
+        let plaintext = XChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| {
+                anyhow!(
+                    "failed to decrypt {}, check that {PASSPHRASE_VAR} is correct",
+                    self.path.display()
+                )
+            })?;
+        Ok(Some((serde_json::from_slice(&plaintext)?, salt, key)))
+    }
+
+    fn save_credentials(
+        &self,
+        credentials: &Credentials,
+        salt: [u8; SALT_LEN],
+        key: &[u8; 32],
+    ) -> Result<()> {
+        let mut nonce = [0; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = XChaCha20Poly1305::new(Key::from_slice(key))
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                serde_json::to_vec(credentials)?.as_slice(),
+            )
+            .map_err(|_| anyhow!("failed to encrypt credentials"))?;
+        let file = EncryptedFile {
+            salt: BASE64.encode(salt),
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        };
+        self.write_file(&serde_json::to_vec(&file)?)
+    }
+
+    /// Replaces the file with `contents` through a temporary file, which is only ever readable by
+    /// the user, so that the credentials are never briefly exposed or left half-written.
+    fn write_file(&self, contents: &[u8]) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let temp_path = self.path.with_extension("enc.tmp");
+        match std::fs::remove_file(&temp_path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt as _;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(&temp_path)
+            .with_context(|| format!("failed to create {}", temp_path.display()))?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        std::fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+
+    fn derive_key(&self, salt: &[u8]) -> Result<[u8; 32]> {
+        let passphrase = self
+            .passphrase
+            .as_ref()
+            .with_context(|| format!("{PASSPHRASE_VAR} is not set"))?;
+        let mut key = [0; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|error| anyhow!("failed to derive the credentials key: {error}"))?;
+        Ok(key)
+    }
+}
+
+impl CredentialsProvider for EncryptedFileCredentialsProvider {
+    fn read_credentials<'a>(
+        &'a self,
+        url: &'a str,
+        cx: &'a AsyncApp,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<(String, Vec<u8>)>>> + 'a>> {
+        let this = self.clone();
+        let url = url.to_string();
+        cx.background_executor()
+            .spawn(async move { this.read(&url) })
+            .boxed_local()
+    }
+
+    fn write_credentials<'a>(
+        &'a self,
+        url: &'a str,
+        username: &'a str,
+        password: &'a [u8],
+        cx: &'a AsyncApp,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + 'a>> {
+        let this = self.clone();
+        let url = url.to_string();
+        let credentials = (username.to_string(), password.to_vec());
+        cx.background_executor()
+            .spawn(async move {
+                this.update(|stored| {
+                    stored.insert(url, credentials);
+                })
+            })
+            .boxed_local()
+    }
+
+    fn delete_credentials<'a>(
+        &'a self,
+        url: &'a str,
+        cx: &'a AsyncApp,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + 'a>> {
+        let this = self.clone();
+        let url = url.to_string();
+        cx.background_executor()
+            .spawn(async move {
+                if !this.exists() {
+                    return Ok(());
+                }
+                this.update(|credentials| {
+                    credentials.remove(&url);
+                })
+            })
+            .boxed_local()
+    }
+}
+
+fn decode<const N: usize>(value: &str) -> Result<[u8; N]> {
+    BASE64
+        .decode(value)?
+        .try_into()
+        .map_err(|_| anyhow!("invalid credentials file"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provider(dir: &Path, passphrase: Option<&str>) -> EncryptedFileCredentialsProvider {
+        EncryptedFileCredentialsProvider::with_passphrase(
+            dir.join("credentials.enc"),
+            passphrase.map(ToString::to_string),
+        )
+    }
+
+    #[test]
+    fn test_credentials_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = provider(dir.path(), Some("correct horse"));
+        assert_eq!(file.read("https://api.openai.com/v1").unwrap(), None);
+
+        file.update(|credentials| {
+            credentials.insert(
+                "https://api.openai.com/v1".into(),
+                ("Bearer".into(), b"sk-one".to_vec()),
+            );
+        })
+        .unwrap();
+        assert_eq!(
+            file.read("https://api.openai.com/v1").unwrap(),
+            Some(("Bearer".to_string(), b"sk-one".to_vec()))
+        );
+        let contents = std::fs::read_to_string(file.path()).unwrap();
+        assert!(!contents.contains("sk-one"));
+
+        assert!(
+            provider(dir.path(), Some("wrong"))
+                .read("https://api.openai.com/v1")
+                .is_err()
+        );
+        assert!(
+            provider(dir.path(), None)
+                .read("https://api.openai.com/v1")
+                .is_err()
+        );
+
+        file.update(|credentials| {
+            credentials.remove("https://api.openai.com/v1");
+        })
+        .unwrap();
+        assert_eq!(file.read("https://api.openai.com/v1").unwrap(), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_credentials_file_is_private() {
+        use std::os::unix::fs::PermissionsExt as _;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = provider(dir.path(), Some("passphrase"));
+        file.update(|credentials| {
+            credentials.insert("url".into(), ("Bearer".into(), b"secret".to_vec()));
+        })
+        .unwrap();
+        let mode = std::fs::metadata(file.path()).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_concurrent_writes_keep_every_credential() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = provider(dir.path(), Some("passphrase"));
+        let threads = (0..4)
+            .map(|ix| {
+                let file = file.clone();
+                std::thread::spawn(move || {
+                    file.update(|credentials| {
+                        credentials.insert(format!("url-{ix}"), ("Bearer".into(), vec![ix]));
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+        for ix in 0..4 {
+            assert_eq!(
+                file.read(&format!("url-{ix}")).unwrap(),
+                Some(("Bearer".to_string(), vec![ix]))
+            );
+        }
+    }
+}
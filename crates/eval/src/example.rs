@@ -237,6 +237,10 @@ impl ExampleContext {
                         tx.try_send(Err(anyhow!("Model refused to generate content")))
                             .ok();
                     }
+                    Ok(StopReason::ContentFilter) => {
+                        tx.try_send(Err(anyhow!("Content filter stopped the response")))
+                            .ok();
+                    }
                     Err(err) => {
                         tx.try_send(Err(anyhow!(err.clone()))).ok();
                     }
@@ -583,6 +583,19 @@ impl ExampleInstance {
                     cache: false,
                 }],
                 temperature: None,
+                seed: None,
+                frequency_penalty: None,
+                presence_penalty: None,
+                logit_bias: Default::default(),
+                top_p: None,
+                top_k: None,
+                candidates: None,
+                logprobs: None,
+                response_schema: None,
+                parallel_tool_calls: None,
+                prediction: None,
+                batch: true,
+                workspace: None,
                 tools: Vec::new(),
                 tool_choice: None,
                 stop: Vec::new(),
@@ -931,6 +944,20 @@ impl RequestMarkdown {
                     MessageContent::Image(_) => {
                         messages.push_str("[IMAGE DATA]\n\n");
                     }
+                    MessageContent::Pdf(pdf) => {
+                        messages.push_str(&format!("**PDF**: {}\n\n", pdf.name));
+                    }
+                    MessageContent::Audio(_) => {
+                        messages.push_str("[AUDIO DATA]\n\n");
+                    }
+                    MessageContent::Video(video) => {
+                        messages.push_str(&format!("**Video**: {}\n\n", video.name));
+                    }
+                    MessageContent::Document(document) => {
+                        messages.push_str(&format!("**Document**: {}\n\n", document.source));
+                        messages.push_str(&document.text);
+                        messages.push_str("\n\n");
+                    }
                     MessageContent::Thinking { text, signature } => {
                         messages.push_str("**Thinking**:\n\n");
                         if let Some(sig) = signature {
@@ -1043,7 +1070,16 @@ pub fn response_events_to_markdown(
             Ok(
                 LanguageModelCompletionEvent::UsageUpdate(_)
                 | LanguageModelCompletionEvent::StartMessage { .. }
-                | LanguageModelCompletionEvent::StatusUpdate { .. },
+                | LanguageModelCompletionEvent::StatusUpdate { .. }
+                | LanguageModelCompletionEvent::Fallback { .. }
+                | LanguageModelCompletionEvent::Candidate { .. }
+                | LanguageModelCompletionEvent::Logprobs(_)
+                | LanguageModelCompletionEvent::Citations(_)
+                | LanguageModelCompletionEvent::GuardrailIntervention(_)
+                | LanguageModelCompletionEvent::UploadProgress { .. }
+                | LanguageModelCompletionEvent::ExecutableCode { .. }
+                | LanguageModelCompletionEvent::CodeExecutionResult { .. }
+                | LanguageModelCompletionEvent::RedactedThinking { .. },
             ) => {}
             Err(error) => {
                 flush_buffers(&mut response, &mut text_buffer, &mut thinking_buffer);
@@ -1116,10 +1152,23 @@ impl ThreadDialog {
                     });
                 }
 
+                Ok(LanguageModelCompletionEvent::RedactedThinking { data }) => {
+                    flush_text(&mut current_text, &mut content);
+                    content.push(MessageContent::RedactedThinking(data.clone().into_bytes()));
+                }
+
                 // Skip these
                 Ok(LanguageModelCompletionEvent::UsageUpdate(_))
                 | Ok(LanguageModelCompletionEvent::StatusUpdate { .. })
                 | Ok(LanguageModelCompletionEvent::StartMessage { .. })
+                | Ok(LanguageModelCompletionEvent::Fallback { .. })
+                | Ok(LanguageModelCompletionEvent::Candidate { .. })
+                | Ok(LanguageModelCompletionEvent::Logprobs(_))
+                | Ok(LanguageModelCompletionEvent::Citations(_))
+                | Ok(LanguageModelCompletionEvent::GuardrailIntervention(_))
+                | Ok(LanguageModelCompletionEvent::UploadProgress { .. })
+                | Ok(LanguageModelCompletionEvent::ExecutableCode { .. })
+                | Ok(LanguageModelCompletionEvent::CodeExecutionResult { .. })
                 | Ok(LanguageModelCompletionEvent::Stop(_)) => {}
 
                 Err(error) => {
@@ -1735,6 +1735,10 @@ impl GitPanel {
         });
 
         let temperature = AgentSettings::temperature_for_model(&model, cx);
+        let seed = AgentSettings::seed_for_model(&model, cx);
+        let frequency_penalty = AgentSettings::frequency_penalty_for_model(&model, cx);
+        let presence_penalty = AgentSettings::presence_penalty_for_model(&model, cx);
+        let logit_bias = AgentSettings::logit_bias_for_model(&model, cx);
 
         self.generate_commit_message_task = Some(cx.spawn(async move |this, cx| {
              async move {
@@ -1777,6 +1781,19 @@ impl GitPanel {
                     tool_choice: None,
                     stop: Vec::new(),
                     temperature,
+                    seed,
+                    frequency_penalty,
+                    presence_penalty,
+                    logit_bias,
+                    top_p: None,
+                    top_k: None,
+                    candidates: None,
+                    logprobs: None,
+                    response_schema: None,
+                    parallel_tool_calls: None,
+                    prediction: None,
+                    batch: false,
+                    workspace: None,
                 };
 
                 let stream = model.stream_completion_text(request, &cx);
@@ -2,15 +2,56 @@ use std::mem;
 
 use anyhow::{Result, anyhow, bail};
 use futures::{AsyncBufReadExt, AsyncReadExt, StreamExt, io::BufReader, stream::BoxStream};
-use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest, http};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 pub const API_URL: &str = "https://generativelanguage.googleapis.com";
 
+/// How requests to the Gemini API are authenticated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Authorization {
+    ApiKey(String),
+    /// An OAuth access token, e.g. one obtained from Application Default Credentials.
+    AccessToken {
+        token: String,
+        /// The project billed for the request, required for user credentials.
+        quota_project: Option<String>,
+    },
+}
+
+impl Authorization {
+    fn apply(
+        &self,
+        uri: String,
+        request_builder: http::request::Builder,
+    ) -> http::request::Builder {
+        match self {
+            Self::ApiKey(api_key) => {
+                let separator = if uri.contains('?') { '&' } else { '?' };
+                request_builder.uri(format!("{uri}{separator}key={api_key}"))
+            }
+            Self::AccessToken {
+                token,
+                quota_project,
+            } => {
+                let request_builder = request_builder
+                    .uri(uri)
+                    .header("Authorization", format!("Bearer {token}"));
+                match quota_project {
+                    Some(quota_project) => {
+                        request_builder.header("x-goog-user-project", quota_project)
+                    }
+                    None => request_builder,
+                }
+            }
+        }
+    }
+}
+
 pub async fn stream_generate_content(
     client: &dyn HttpClient,
     api_url: &str,
-    api_key: &str,
+    authorization: &Authorization,
     mut request: GenerateContentRequest,
 ) -> Result<BoxStream<'static, Result<GenerateContentResponse>>> {
     validate_generate_content_request(&request)?;
@@ -18,13 +59,14 @@ pub async fn stream_generate_content(
     // The `model` field is emptied as it is provided as a path parameter.
     let model_id = mem::take(&mut request.model.model_id);
 
-    let uri =
-        format!("{api_url}/v1beta/models/{model_id}:streamGenerateContent?alt=sse&key={api_key}",);
+    let uri = format!("{api_url}/v1beta/models/{model_id}:streamGenerateContent?alt=sse");
 
-    let request_builder = HttpRequest::builder()
-        .method(Method::POST)
-        .uri(uri)
-        .header("Content-Type", "application/json");
+    let request_builder = authorization.apply(
+        uri,
+        HttpRequest::builder()
+            .method(Method::POST)
+            .header("Content-Type", "application/json"),
+    );
 
     let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request)?))?;
     let mut response = client.send(request).await?;
@@ -64,21 +106,23 @@ pub async fn stream_generate_content(
 pub async fn count_tokens(
     client: &dyn HttpClient,
     api_url: &str,
-    api_key: &str,
+    authorization: &Authorization,
     request: CountTokensRequest,
 ) -> Result<CountTokensResponse> {
     validate_generate_content_request(&request.generate_content_request)?;
 
     let uri = format!(
-        "{api_url}/v1beta/models/{model_id}:countTokens?key={api_key}",
+        "{api_url}/v1beta/models/{model_id}:countTokens",
         model_id = &request.generate_content_request.model.model_id,
     );
 
     let request = serde_json::to_string(&request)?;
-    let request_builder = HttpRequest::builder()
-        .method(Method::POST)
-        .uri(&uri)
-        .header("Content-Type", "application/json");
+    let request_builder = authorization.apply(
+        uri,
+        HttpRequest::builder()
+            .method(Method::POST)
+            .header("Content-Type", "application/json"),
+    );
     let http_request = request_builder.body(AsyncBody::from(request))?;
 
     let mut response = client.send(http_request).await?;
@@ -93,6 +137,208 @@ pub async fn count_tokens(
     Ok(serde_json::from_str::<CountTokensResponse>(&text)?)
 }
 
+/// Embeds each of `texts` with `model_id`, an embedding model like `text-embedding-004`.
+pub async fn batch_embed_contents(
+    client: &dyn HttpClient,
+    api_url: &str,
+    authorization: &Authorization,
+    model_id: &str,
+    texts: &[String],
+) -> Result<BatchEmbedContentsResponse> {
+    let uri = format!("{api_url}/v1beta/models/{model_id}:batchEmbedContents");
+    let request = BatchEmbedContentsRequest {
+        requests: texts
+            .iter()
+            .map(|text| EmbedContentRequest {
+                model: ModelName {
+                    model_id: model_id.to_string(),
+                },
+                content: EmbedContent {
+                    parts: vec![TextPart { text: text.clone() }],
+                },
+            })
+            .collect(),
+    };
+
+    let request = serde_json::to_string(&request)?;
+    let request_builder = authorization.apply(
+        uri,
+        HttpRequest::builder()
+            .method(Method::POST)
+            .header("Content-Type", "application/json"),
+    );
+    let http_request = request_builder.body(AsyncBody::from(request))?;
+
+    let mut response = client.send(http_request).await?;
+    let mut text = String::new();
+    response.body_mut().read_to_string(&mut text).await?;
+    anyhow::ensure!(
+        response.status().is_success(),
+        "error during batchEmbedContents, status code: {:?}, body: {}",
+        response.status(),
+        text
+    );
+    Ok(serde_json::from_str::<BatchEmbedContentsResponse>(&text)?)
+}
+
+/// Caches the start of a conversation, so that later requests can refer to it by name with
+/// [`GenerateContentRequest::cached_content`] instead of sending it again.
+pub async fn create_cached_content(
+    client: &dyn HttpClient,
+    api_url: &str,
+    authorization: &Authorization,
+    request: CreateCachedContentRequest<'_>,
+) -> Result<CachedContent> {
+    let uri = format!("{api_url}/v1beta/cachedContents");
+    let request_builder = authorization.apply(
+        uri,
+        HttpRequest::builder()
+            .method(Method::POST)
+            .header("Content-Type", "application/json"),
+    );
+    let http_request = request_builder.body(AsyncBody::from(serde_json::to_string(&request)?))?;
+
+    let mut response = client.send(http_request).await?;
+    let mut text = String::new();
+    response.body_mut().read_to_string(&mut text).await?;
+    anyhow::ensure!(
+        response.status().is_success(),
+        "error creating cached content, status code: {:?}, body: {}",
+        response.status(),
+        text
+    );
+    Ok(serde_json::from_str::<CachedContent>(&text)?)
+}
+
+/// How much of a file is sent per request while uploading it. Must be a multiple of 256 KiB.
+const UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+/// A file uploaded to the Files API, which requests can refer to by its URI for 48 hours.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct File {
+    /// Like `files/abc-123`.
+    pub name: String,
+    pub uri: String,
+    pub mime_type: String,
+    #[serde(default)]
+    pub state: FileState,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum FileState {
+    #[default]
+    StateUnspecified,
+    /// Videos are processed for a while after they're uploaded, and can't be used until they're
+    /// active.
+    Processing,
+    Active,
+    Failed,
+}
+
+#[derive(Deserialize)]
+struct UploadFileResponse {
+    file: File,
+}
+
+/// Uploads a file with the resumable upload protocol, calling `on_progress` with the number of
+/// bytes uploaded after each chunk.
+pub async fn upload_file(
+    client: &dyn HttpClient,
+    api_url: &str,
+    authorization: &Authorization,
+    display_name: &str,
+    mime_type: &str,
+    bytes: &[u8],
+    mut on_progress: impl FnMut(usize),
+) -> Result<File> {
+    let request_builder = authorization.apply(
+        format!("{api_url}/upload/v1beta/files"),
+        HttpRequest::builder()
+            .method(Method::POST)
+            .header("X-Goog-Upload-Protocol", "resumable")
+            .header("X-Goog-Upload-Command", "start")
+            .header("X-Goog-Upload-Header-Content-Length", bytes.len())
+            .header("X-Goog-Upload-Header-Content-Type", mime_type)
+            .header("Content-Type", "application/json"),
+    );
+    let body = serde_json::json!({ "file": { "display_name": display_name } });
+    let mut response = client
+        .send(request_builder.body(AsyncBody::from(body.to_string()))?)
+        .await?;
+    if !response.status().is_success() {
+        let mut text = String::new();
+        response.body_mut().read_to_string(&mut text).await?;
+        bail!(
+            "error starting file upload, status code: {:?}, body: {}",
+            response.status(),
+            text
+        );
+    }
+    let upload_url = response
+        .headers()
+        .get("x-goog-upload-url")
+        .and_then(|url| url.to_str().ok())
+        .ok_or_else(|| anyhow!("file upload response is missing the upload URL"))?
+        .to_string();
+
+    let mut offset = 0;
+    loop {
+        let end = (offset + UPLOAD_CHUNK_SIZE).min(bytes.len());
+        let command = if end == bytes.len() {
+            "upload, finalize"
+        } else {
+            "upload"
+        };
+        let request = HttpRequest::builder()
+            .method(Method::POST)
+            .uri(&upload_url)
+            .header("X-Goog-Upload-Offset", offset)
+            .header("X-Goog-Upload-Command", command)
+            .body(AsyncBody::from(bytes[offset..end].to_vec()))?;
+        let mut response = client.send(request).await?;
+        let mut text = String::new();
+        response.body_mut().read_to_string(&mut text).await?;
+        anyhow::ensure!(
+            response.status().is_success(),
+            "error uploading file, status code: {:?}, body: {}",
+            response.status(),
+            text
+        );
+        offset = end;
+        on_progress(offset);
+        if offset == bytes.len() {
+            return Ok(serde_json::from_str::<UploadFileResponse>(&text)?.file);
+        }
+    }
+}
+
+/// Gets an uploaded file, e.g. to check whether it has finished processing.
+pub async fn get_file(
+    client: &dyn HttpClient,
+    api_url: &str,
+    authorization: &Authorization,
+    name: &str,
+) -> Result<File> {
+    let request_builder = authorization.apply(
+        format!("{api_url}/v1beta/{name}"),
+        HttpRequest::builder().method(Method::GET),
+    );
+    let mut response = client
+        .send(request_builder.body(AsyncBody::empty())?)
+        .await?;
+    let mut text = String::new();
+    response.body_mut().read_to_string(&mut text).await?;
+    anyhow::ensure!(
+        response.status().is_success(),
+        "error getting file, status code: {:?}, body: {}",
+        response.status(),
+        text
+    );
+    Ok(serde_json::from_str::<File>(&text)?)
+}
+
 pub fn validate_generate_content_request(request: &GenerateContentRequest) -> Result<()> {
     if request.model.is_empty() {
         bail!("Model must be specified");
@@ -129,7 +375,7 @@ pub enum Task {
     BatchEmbedContents,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GenerateContentRequest {
     #[serde(default, skip_serializing_if = "ModelName::is_empty")]
@@ -145,6 +391,34 @@ pub struct GenerateContentRequest {
     pub tools: Option<Vec<Tool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_config: Option<ToolConfig>,
+    /// The name of a cache holding the start of the conversation, which `contents` continue.
+    /// Requests that use a cache can't set `system_instruction`, `tools` or `tool_config`, since
+    /// those are part of the cache.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached_content: Option<String>,
+}
+
+/// The start of a conversation to cache, borrowed from the request it was taken from.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateCachedContentRequest<'a> {
+    pub model: &'a ModelName,
+    pub contents: &'a [Content],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<&'a SystemInstruction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<&'a [Tool]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_config: Option<&'a ToolConfig>,
+    /// How long the cache is kept, e.g. `"300s"`.
+    pub ttl: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachedContent {
+    /// The name to refer to the cache by, e.g. `cachedContents/abc123`.
+    pub name: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -172,9 +446,12 @@ pub struct GenerateContentCandidate {
     pub safety_ratings: Option<Vec<SafetyRating>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub citation_metadata: Option<CitationMetadata>,
+    /// The sources that the candidate is grounded on, when the request used Google Search.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grounding_metadata: Option<GroundingMetadata>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Content {
     #[serde(default)]
@@ -182,59 +459,116 @@ pub struct Content {
     pub role: Role,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SystemInstruction {
     pub parts: Vec<Part>,
 }
 
-#[derive(Debug, PartialEq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub enum Role {
     User,
     Model,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Part {
     TextPart(TextPart),
     InlineDataPart(InlineDataPart),
+    FileDataPart(FileDataPart),
     FunctionCallPart(FunctionCallPart),
     FunctionResponsePart(FunctionResponsePart),
+    ExecutableCodePart(ExecutableCodePart),
+    CodeExecutionResultPart(CodeExecutionResultPart),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TextPart {
     pub text: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct InlineDataPart {
     pub inline_data: GenerativeContentBlob,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GenerativeContentBlob {
     pub mime_type: String,
     pub data: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A file uploaded with [`upload_file`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileDataPart {
+    pub file_data: FileData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileData {
+    pub mime_type: String,
+    pub file_uri: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FunctionCallPart {
     pub function_call: FunctionCall,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FunctionResponsePart {
     pub function_response: FunctionResponse,
 }
 
+/// Code that the model wrote to run with the code execution tool.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutableCodePart {
+    pub executable_code: ExecutableCode,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutableCode {
+    /// The language of the code, e.g. `PYTHON`.
+    pub language: String,
+    pub code: String,
+}
+
+/// The result of running the code in the preceding [`ExecutableCodePart`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeExecutionResultPart {
+    pub code_execution_result: CodeExecutionResult,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeExecutionResult {
+    pub outcome: CodeExecutionOutcome,
+    /// What the code printed, or the error it failed with.
+    #[serde(default)]
+    pub output: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum CodeExecutionOutcome {
+    OutcomeUnspecified,
+    OutcomeOk,
+    OutcomeFailed,
+    OutcomeDeadlineExceeded,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CitationSource {
@@ -254,6 +588,56 @@ pub struct CitationMetadata {
     pub citation_sources: Vec<CitationSource>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingMetadata {
+    #[serde(default)]
+    pub grounding_chunks: Vec<GroundingChunk>,
+    #[serde(default)]
+    pub grounding_supports: Vec<GroundingSupport>,
+    #[serde(default)]
+    pub web_search_queries: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingChunk {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub web: Option<WebGroundingChunk>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebGroundingChunk {
+    pub uri: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// A part of the response and the grounding chunks that support it.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingSupport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub segment: Option<GroundingSegment>,
+    #[serde(default)]
+    pub grounding_chunk_indices: Vec<usize>,
+    /// How confident the model is in each of the chunks, from 0 to 1.
+    #[serde(default)]
+    pub confidence_scores: Vec<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroundingSegment {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_index: Option<usize>,
+    #[serde(default)]
+    pub text: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PromptFeedback {
@@ -281,7 +665,7 @@ pub struct UsageMetadata {
     pub total_token_count: Option<usize>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GenerationConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -296,16 +680,34 @@ pub struct GenerationConfig {
     pub top_p: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub top_k: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i32>,
+    /// The media type of the response, which is `application/json` for responses that follow a
+    /// `response_schema`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_mime_type: Option<String>,
+    /// An OpenAPI schema object that the response must conform to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking_config: Option<ThinkingConfig>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThinkingConfig {
+    /// The maximum number of tokens the model may spend thinking, where 0 turns thinking off.
+    pub thinking_budget: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SafetySetting {
     pub category: HarmCategory,
     pub threshold: HarmBlockThreshold,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum HarmCategory {
     #[serde(rename = "HARM_CATEGORY_UNSPECIFIED")]
     Unspecified,
@@ -331,7 +733,7 @@ pub enum HarmCategory {
     DangerousContent,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum HarmBlockThreshold {
     #[serde(rename = "HARM_BLOCK_THRESHOLD_UNSPECIFIED")]
@@ -372,31 +774,70 @@ pub struct CountTokensResponse {
     pub total_tokens: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
+pub struct BatchEmbedContentsRequest {
+    pub requests: Vec<EmbedContentRequest>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbedContentRequest {
+    pub model: ModelName,
+    pub content: EmbedContent,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbedContent {
+    pub parts: Vec<TextPart>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchEmbedContentsResponse {
+    pub embeddings: Vec<ContentEmbedding>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ContentEmbedding {
+    pub values: Vec<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionCall {
     pub name: String,
     pub args: serde_json::Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionResponse {
     pub name: String,
     pub response: serde_json::Value,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Tool {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub function_declarations: Vec<FunctionDeclaration>,
+    /// Lets the model search the web with Google Search, grounding its response on the results.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub google_search: Option<GoogleSearch>,
+    /// Lets the model write and run Python code, e.g. to do calculations.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code_execution: Option<CodeExecution>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GoogleSearch {}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CodeExecution {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ToolConfig {
     pub function_calling_config: FunctionCallingConfig,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct FunctionCallingConfig {
     pub mode: FunctionCallingMode,
@@ -404,7 +845,7 @@ pub struct FunctionCallingConfig {
     pub allowed_function_names: Option<Vec<String>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum FunctionCallingMode {
     Auto,
@@ -412,14 +853,14 @@ pub enum FunctionCallingMode {
     None,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunctionDeclaration {
     pub name: String,
     pub description: String,
     pub parameters: serde_json::Value,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct ModelName {
     pub model_id: String,
 }
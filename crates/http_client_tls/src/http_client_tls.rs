@@ -1,7 +1,11 @@
-use std::sync::OnceLock;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
 
-use rustls::ClientConfig;
-use rustls_platform_verifier::ConfigVerifierExt;
+use anyhow::{Context as _, Result};
+pub use rustls::ClientConfig;
+use rustls::pki_types::pem::PemObject as _;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls_platform_verifier::{ConfigVerifierExt, Verifier};
 
 static TLS_CONFIG: OnceLock<rustls::ClientConfig> = OnceLock::new();
 
@@ -19,3 +23,41 @@ pub fn tls_config() -> ClientConfig {
         })
         .clone()
 }
+
+/// Returns a TLS configuration that also trusts the certificates in `ca_cert`, such as an
+/// internal certificate authority, and that presents `client_cert` with its `client_key` to
+/// servers requiring mutual TLS. All files are PEM encoded.
+pub fn tls_config_with_certificates(
+    ca_cert: Option<&Path>,
+    client_cert: Option<&Path>,
+    client_key: Option<&Path>,
+) -> Result<ClientConfig> {
+    // Makes sure that the default crypto provider is installed.
+    tls_config();
+
+    let extra_roots = match ca_cert {
+        Some(path) => CertificateDer::pem_file_iter(path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .with_context(|| format!("failed to read CA certificate {}", path.display()))?,
+        None => Vec::new(),
+    };
+    let verifier = Verifier::new_with_extra_roots(extra_roots)?;
+    let builder = ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+
+    match (client_cert, client_key) {
+        (Some(cert_path), Some(key_path)) => {
+            let cert_chain = CertificateDer::pem_file_iter(cert_path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .with_context(|| {
+                    format!("failed to read client certificate {}", cert_path.display())
+                })?;
+            let key = PrivateKeyDer::from_pem_file(key_path)
+                .with_context(|| format!("failed to read client key {}", key_path.display()))?;
+            Ok(builder.with_client_auth_cert(cert_chain, key)?)
+        }
+        (None, None) => Ok(builder.with_no_client_auth()),
+        _ => anyhow::bail!("a client certificate and its key must be configured together"),
+    }
+}
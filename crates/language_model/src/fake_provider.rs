@@ -89,9 +89,13 @@ pub struct ToolUseRequest {
     pub schema: serde_json::Value,
 }
 
+type CompletionEventSender =
+    mpsc::UnboundedSender<Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>;
+
 #[derive(Default)]
 pub struct FakeLanguageModel {
-    current_completion_txs: Mutex<Vec<(LanguageModelRequest, mpsc::UnboundedSender<String>)>>,
+    current_completion_txs: Mutex<Vec<(LanguageModelRequest, CompletionEventSender)>>,
+    next_completion_error: Mutex<Option<anyhow::Error>>,
 }
 
 impl FakeLanguageModel {
@@ -111,6 +115,25 @@ impl FakeLanguageModel {
         &self,
         request: &LanguageModelRequest,
         chunk: impl Into<String>,
+    ) {
+        self.send_completion_event(
+            request,
+            Ok(LanguageModelCompletionEvent::Text(chunk.into())),
+        );
+    }
+
+    pub fn send_completion_error(
+        &self,
+        request: &LanguageModelRequest,
+        error: LanguageModelCompletionError,
+    ) {
+        self.send_completion_event(request, Err(error));
+    }
+
+    fn send_completion_event(
+        &self,
+        request: &LanguageModelRequest,
+        event: Result<LanguageModelCompletionEvent, LanguageModelCompletionError>,
     ) {
         let current_completion_txs = self.current_completion_txs.lock();
         let tx = current_completion_txs
@@ -118,7 +141,12 @@ impl FakeLanguageModel {
             .find(|(req, _)| req == request)
             .map(|(_, tx)| tx)
             .unwrap();
-        tx.unbounded_send(chunk.into()).unwrap();
+        tx.unbounded_send(event).unwrap();
+    }
+
+    /// Makes the next completion fail before it starts streaming.
+    pub fn fail_next_completion(&self, error: anyhow::Error) {
+        *self.next_completion_error.lock() = Some(error);
     }
 
     pub fn end_completion_stream(&self, request: &LanguageModelRequest) {
@@ -131,6 +159,10 @@ impl FakeLanguageModel {
         self.stream_completion_response(self.pending_completions().last().unwrap(), chunk);
     }
 
+    pub fn send_last_completion_error(&self, error: LanguageModelCompletionError) {
+        self.send_completion_error(self.pending_completions().last().unwrap(), error);
+    }
+
     pub fn end_last_completion_stream(&self) {
         self.end_completion_stream(self.pending_completions().last().unwrap());
     }
@@ -187,14 +219,12 @@ impl LanguageModel for FakeLanguageModel {
             BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
         >,
     > {
+        if let Some(error) = self.next_completion_error.lock().take() {
+            return futures::future::ready(Err(error)).boxed();
+        }
         let (tx, rx) = mpsc::unbounded();
         self.current_completion_txs.lock().push((request, tx));
-        async move {
-            Ok(rx
-                .map(|text| Ok(LanguageModelCompletionEvent::Text(text)))
-                .boxed())
-        }
-        .boxed()
+        async move { Ok(rx.boxed()) }.boxed()
     }
 
     fn as_fake(&self) -> &Self {
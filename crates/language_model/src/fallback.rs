@@ -0,0 +1,308 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream};
+use futures::{FutureExt as _, StreamExt as _};
+use gpui::{App, AsyncApp};
+
+use crate::{
+    DocumentLimits, ImageLimits, LanguageModel, LanguageModelCacheConfiguration,
+    LanguageModelCompletionError, LanguageModelCompletionEvent, LanguageModelId,
+    LanguageModelKnownError, LanguageModelName, LanguageModelProviderId, LanguageModelProviderName,
+    LanguageModelRequest, LanguageModelToolChoice, LanguageModelToolSchemaFormat, ModelPrice,
+    StructuredOutput,
+};
+
+/// A model that retries a request on its fallback models, in order, when the model fails to
+/// start a completion, such as when its provider is down or rate limits the request.
+///
+/// It presents itself as its primary model, and emits a
+/// [`LanguageModelCompletionEvent::Fallback`] when a completion comes from another model.
+pub struct FallbackLanguageModel {
+    primary: Arc<dyn LanguageModel>,
+    fallbacks: Vec<Arc<dyn LanguageModel>>,
+}
+
+impl FallbackLanguageModel {
+    pub fn new(primary: Arc<dyn LanguageModel>, fallbacks: Vec<Arc<dyn LanguageModel>>) -> Self {
+        Self { primary, fallbacks }
+    }
+}
+
+/// Whether a completion that failed with `error` before streaming anything should be retried on
+/// the next model.
+fn should_fall_back(error: &LanguageModelCompletionError) -> bool {
+    match error {
+        LanguageModelCompletionError::BadInputJson { .. } => false,
+        LanguageModelCompletionError::Other(error) => !is_spend_limit_error(error),
+        _ => true,
+    }
+}
+
+/// Whether the request was stopped by the user's spend limits, which would be circumvented by
+/// sending it to another model.
+fn is_spend_limit_error(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<LanguageModelKnownError>(),
+        Some(LanguageModelKnownError::SpendLimitReached { .. })
+    )
+}
+
+impl LanguageModel for FallbackLanguageModel {
+    fn id(&self) -> LanguageModelId {
+        self.primary.id()
+    }
+
+    fn name(&self) -> LanguageModelName {
+        self.primary.name()
+    }
+
+    fn provider_id(&self) -> LanguageModelProviderId {
+        self.primary.provider_id()
+    }
+
+    fn provider_name(&self) -> LanguageModelProviderName {
+        self.primary.provider_name()
+    }
+
+    fn telemetry_id(&self) -> String {
+        self.primary.telemetry_id()
+    }
+
+    fn api_key(&self, cx: &App) -> Option<String> {
+        self.primary.api_key(cx)
+    }
+
+    fn price(&self, cx: &App) -> Option<ModelPrice> {
+        self.primary.price(cx)
+    }
+
+    fn supports_images(&self) -> bool {
+        self.primary.supports_images()
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        self.primary.image_limits()
+    }
+
+    fn document_limits(&self) -> Option<DocumentLimits> {
+        self.primary.document_limits()
+    }
+
+    fn supports_audio(&self) -> bool {
+        self.primary.supports_audio()
+    }
+
+    fn supports_video(&self) -> bool {
+        self.primary.supports_video()
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.primary.supports_tools()
+    }
+
+    fn supports_tool_choice(&self, choice: LanguageModelToolChoice) -> bool {
+        self.primary.supports_tool_choice(choice)
+    }
+
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.primary.supports_parallel_tool_calls()
+    }
+
+    fn supports_thinking(&self) -> bool {
+        self.primary.supports_thinking()
+    }
+
+    fn supports_max_mode(&self) -> bool {
+        self.primary.supports_max_mode()
+    }
+
+    fn supports_assistant_prefill(&self) -> bool {
+        self.primary.supports_assistant_prefill()
+    }
+
+    fn supports_candidates(&self) -> bool {
+        self.primary.supports_candidates()
+    }
+
+    fn supports_logprobs(&self) -> bool {
+        self.primary.supports_logprobs()
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.primary.structured_output()
+    }
+
+    fn tool_input_format(&self) -> LanguageModelToolSchemaFormat {
+        self.primary.tool_input_format()
+    }
+
+    fn max_token_count(&self) -> usize {
+        self.primary.max_token_count()
+    }
+
+    fn max_output_tokens(&self) -> Option<u32> {
+        self.primary.max_output_tokens()
+    }
+
+    fn count_tokens(
+        &self,
+        request: LanguageModelRequest,
+        cx: &App,
+    ) -> BoxFuture<'static, Result<usize>> {
+        self.primary.count_tokens(request, cx)
+    }
+
+    fn stream_completion(
+        &self,
+        request: LanguageModelRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<
+        'static,
+        Result<
+            BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+        >,
+    > {
+        let models = std::iter::once(self.primary.clone())
+            .chain(self.fallbacks.iter().cloned())
+            .collect::<Vec<_>>();
+        // The models are called from the foreground, since `AsyncApp` can't be sent to another
+        // thread.
+        cx.spawn(async move |cx| {
+            let mut previous_error: Option<String> = None;
+            for (ix, model) in models.iter().enumerate() {
+                let is_last = ix + 1 == models.len();
+                let fallback = previous_error.take().map(|error| {
+                    Ok(LanguageModelCompletionEvent::Fallback {
+                        model: format!("{} / {}", model.provider_name().0, model.name().0),
+                        error,
+                    })
+                });
+                let mut events = match model.stream_completion(request.clone(), cx).await {
+                    Ok(events) => events,
+                    Err(error) if !is_last && !is_spend_limit_error(&error) => {
+                        previous_error = Some(format!("{error:#}"));
+                        continue;
+                    }
+                    Err(error) => return Err(error),
+                };
+                let first_event = events.next().await;
+                if let Some(Err(error)) = &first_event {
+                    if !is_last && should_fall_back(error) {
+                        previous_error = Some(error.to_string());
+                        continue;
+                    }
+                }
+                return Ok(stream::iter(fallback.into_iter().chain(first_event))
+                    .chain(events)
+                    .boxed());
+            }
+            Err(anyhow::anyhow!("no language model to send the request to"))
+        })
+        .boxed()
+    }
+
+    fn cache_configuration(&self) -> Option<LanguageModelCacheConfiguration> {
+        self.primary.cache_configuration()
+    }
+
+    #[cfg(any(test, feature = "test-support"))]
+    fn as_fake(&self) -> &crate::fake_provider::FakeLanguageModel {
+        self.primary.as_fake()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SpendLimitPeriod;
+    use crate::fake_provider::FakeLanguageModel;
+    use gpui::TestAppContext;
+
+    fn fallback_model() -> (
+        Arc<FakeLanguageModel>,
+        Arc<FakeLanguageModel>,
+        FallbackLanguageModel,
+    ) {
+        let primary = Arc::new(FakeLanguageModel::default());
+        let fallback = Arc::new(FakeLanguageModel::default());
+        let model = FallbackLanguageModel::new(primary.clone(), vec![fallback.clone()]);
+        (primary, fallback, model)
+    }
+
+    #[gpui::test]
+    async fn test_falls_back_after_first_event_error(cx: &mut TestAppContext) {
+        let (primary, fallback, model) = fallback_model();
+        let completion = model.stream_completion(LanguageModelRequest::default(), &cx.to_async());
+        cx.run_until_parked();
+        primary.send_last_completion_error(LanguageModelCompletionError::Overloaded);
+        cx.run_until_parked();
+        assert_eq!(fallback.completion_count(), 1);
+
+        fallback.stream_last_completion_response("hello");
+        fallback.stream_last_completion_response(" world");
+        fallback.end_last_completion_stream();
+        let events = completion
+            .await
+            .unwrap()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+        // Which model answered is announced before the answer itself.
+        assert_eq!(
+            events,
+            vec![
+                LanguageModelCompletionEvent::Fallback {
+                    model: "Fake / Fake".into(),
+                    error: "the provider is overloaded".into(),
+                },
+                LanguageModelCompletionEvent::Text("hello".into()),
+                LanguageModelCompletionEvent::Text(" world".into()),
+            ]
+        );
+    }
+
+    #[gpui::test]
+    async fn test_last_model_error_propagates(cx: &mut TestAppContext) {
+        let (primary, fallback, model) = fallback_model();
+        let completion = model.stream_completion(LanguageModelRequest::default(), &cx.to_async());
+        cx.run_until_parked();
+        primary.send_last_completion_error(LanguageModelCompletionError::Overloaded);
+        cx.run_until_parked();
+        fallback.send_last_completion_error(LanguageModelCompletionError::QuotaExceeded);
+        let mut events = completion.await.unwrap();
+        assert!(matches!(
+            events.next().await,
+            Some(Err(LanguageModelCompletionError::QuotaExceeded))
+        ));
+
+        fallback.fail_next_completion(anyhow::anyhow!("connection refused"));
+        primary.fail_next_completion(anyhow::anyhow!("connection refused"));
+        let error = model
+            .stream_completion(LanguageModelRequest::default(), &cx.to_async())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "connection refused");
+    }
+
+    #[gpui::test]
+    async fn test_does_not_fall_back_on_spend_limit(cx: &mut TestAppContext) {
+        let (primary, fallback, model) = fallback_model();
+        primary.fail_next_completion(anyhow::anyhow!(
+            LanguageModelKnownError::SpendLimitReached {
+                period: SpendLimitPeriod::Day,
+                spent: 10.,
+                limit: 10.,
+            }
+        ));
+        let error = model
+            .stream_completion(LanguageModelRequest::default(), &cx.to_async())
+            .await
+            .err()
+            .unwrap();
+        assert!(is_spend_limit_error(&error));
+        assert_eq!(fallback.completion_count(), 0);
+    }
+}
@@ -1,8 +1,10 @@
+mod fallback;
 mod model;
 mod rate_limiter;
 mod registry;
 mod request;
 mod role;
+mod structured_output;
 mod telemetry;
 
 #[cfg(any(test, feature = "test-support"))]
@@ -11,6 +13,7 @@ pub mod fake_provider;
 use anyhow::{Context as _, Result};
 use client::Client;
 use futures::FutureExt;
+use futures::channel::mpsc;
 use futures::{StreamExt, future::BoxFuture, stream::BoxStream};
 use gpui::{AnyElement, AnyView, App, AsyncApp, SharedString, Task, Window};
 use http_client::http::{HeaderMap, HeaderValue};
@@ -22,6 +25,7 @@ use std::fmt;
 use std::ops::{Add, Sub};
 use std::str::FromStr as _;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 use util::serde::is_default;
 use zed_llm_client::{
@@ -29,11 +33,13 @@ use zed_llm_client::{
     MODEL_REQUESTS_USAGE_LIMIT_HEADER_NAME, UsageLimit,
 };
 
+pub use crate::fallback::*;
 pub use crate::model::*;
 pub use crate::rate_limiter::*;
 pub use crate::registry::*;
 pub use crate::request::*;
 pub use crate::role::*;
+pub use crate::structured_output::*;
 pub use crate::telemetry::*;
 
 pub const ZED_CLOUD_PROVIDER_ID: &str = "zed.dev";
@@ -65,11 +71,124 @@ pub enum LanguageModelCompletionEvent {
         text: String,
         signature: Option<String>,
     },
+    /// Thinking that the provider encrypted, which has to be sent back to the model unchanged in
+    /// later requests, like the rest of its thinking.
+    RedactedThinking {
+        data: String,
+    },
     ToolUse(LanguageModelToolUse),
     StartMessage {
         message_id: String,
     },
     UsageUpdate(TokenUsage),
+    /// The model that the request was sent to failed with `error`, so the completion comes from
+    /// one of its fallbacks, named like `OpenAI / GPT-4.1`.
+    Fallback {
+        model: String,
+        error: String,
+    },
+    /// The events that follow, up to the next `Candidate`, belong to the alternative completion
+    /// at `index`. Events before the first `Candidate` belong to the first completion.
+    Candidate {
+        index: u32,
+    },
+    /// The log probabilities of the tokens of the text that was last streamed, when they were
+    /// requested with [`LanguageModelRequest::logprobs`].
+    Logprobs(Vec<TokenLogprob>),
+    /// The sources that the text streamed so far is grounded on, like the web pages that the
+    /// model found when searching the web.
+    Citations(Vec<Citation>),
+    /// Code that the model wrote and ran with its provider's code execution tool.
+    ExecutableCode {
+        language: String,
+        code: String,
+    },
+    /// The output of the code from the last `ExecutableCode` event.
+    CodeExecutionResult {
+        success: bool,
+        output: String,
+    },
+    /// What the provider's guardrails detected in the request or its response, when one of them
+    /// intervened.
+    GuardrailIntervention(Vec<GuardrailViolation>),
+    /// How much of an attachment named `name` has been uploaded to the provider, for attachments
+    /// too large to be sent inline with the request.
+    UploadProgress {
+        name: String,
+        uploaded_bytes: u64,
+        total_bytes: u64,
+    },
+}
+
+/// How likely the model was to generate a token.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TokenLogprob {
+    pub token: String,
+    /// The natural log of the token's probability.
+    pub logprob: f32,
+    /// The most likely tokens in the token's place, including the token itself if it's one of
+    /// them, from most to least likely.
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f32,
+}
+
+/// A source that a model's response is grounded on.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Citation {
+    pub url: String,
+    pub title: Option<String>,
+    /// The part of the response that the source supports, when the model says which.
+    pub cited_text: Option<String>,
+    /// How confident the model is that the source supports `cited_text`, from 0 to 1.
+    pub confidence: Option<f32>,
+}
+
+/// Something that a guardrail detected in a request or its response.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct GuardrailViolation {
+    pub policy: GuardrailPolicy,
+    /// What was detected, such as the name of a denied topic or the type of a content filter.
+    pub name: String,
+    pub action: GuardrailAction,
+}
+
+impl fmt::Display for GuardrailViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let policy = match self.policy {
+            GuardrailPolicy::Topic => "denied topic",
+            GuardrailPolicy::Content => "content filter",
+            GuardrailPolicy::Word => "word filter",
+            GuardrailPolicy::SensitiveInformation => "sensitive information",
+        };
+        let action = match self.action {
+            GuardrailAction::Blocked => "blocked",
+            GuardrailAction::Anonymized => "masked",
+            GuardrailAction::Detected => "detected",
+        };
+        write!(f, "{policy} \"{}\" ({action})", self.name)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum GuardrailPolicy {
+    Topic,
+    Content,
+    Word,
+    SensitiveInformation,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum GuardrailAction {
+    Blocked,
+    /// The detected text was masked.
+    Anonymized,
+    /// The guardrail only reported what it detected.
+    Detected,
 }
 
 #[derive(Error, Debug)]
@@ -81,6 +200,16 @@ pub enum LanguageModelCompletionError {
         raw_input: Arc<str>,
         json_parse_error: String,
     },
+    #[error("rate limit exceeded{}", retry_after.map(|after| format!(", retry after {}s", after.as_secs())).unwrap_or_default())]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("the request exceeds the model's context window")]
+    ContextLengthExceeded,
+    #[error("the provider rejected the API key")]
+    InvalidApiKey,
+    #[error("the account has run out of credits or quota")]
+    QuotaExceeded,
+    #[error("the provider is overloaded")]
+    Overloaded,
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -92,15 +221,41 @@ pub enum LanguageModelToolSchemaFormat {
     JsonSchema,
     /// A subset of an OpenAPI 3.0 schema object supported by Google AI, see https://ai.google.dev/api/caching#Schema
     JsonSchemaSubset,
+    /// The subset of JSON schema that OpenAI supports in strict mode, where every property is
+    /// required and objects don't take additional properties, see https://platform.openai.com/docs/guides/structured-outputs#supported-schemas
+    JsonSchemaStrict,
+    /// A JSON schema in draft 7, without the keywords that later drafts added, as xAI takes them.
+    JsonSchemaDraft7,
+}
+
+/// How a model makes its responses conform to a request's
+/// [`response_schema`](LanguageModelRequest::response_schema).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StructuredOutput {
+    /// The provider constrains the response to the schema.
+    Native,
+    /// The model is made to call a tool whose input schema is the response schema, and the tool's
+    /// input is streamed as the response's text. Models follow the schema closely this way, but
+    /// nothing guarantees that they do.
+    ToolCall,
+    /// The provider constrains the response to a JSON object, but not to the schema, which
+    /// [`complete_structured`] describes in the prompt instead.
+    JsonObject,
+    /// The schema is ignored.
+    Unsupported,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum StopReason {
+    /// The model finished its response, or generated one of the request's stop sequences.
     EndTurn,
+    /// The response was cut off because it reached the maximum number of output tokens.
     MaxTokens,
     ToolUse,
     Refusal,
+    /// The provider's content filter stopped the response.
+    ContentFilter,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -139,6 +294,9 @@ pub struct TokenUsage {
     pub cache_creation_input_tokens: u32,
     #[serde(default, skip_serializing_if = "is_default")]
     pub cache_read_input_tokens: u32,
+    /// The portion of `output_tokens` the model spent reasoning, when the provider reports it.
+    #[serde(default, skip_serializing_if = "is_default")]
+    pub reasoning_tokens: u32,
 }
 
 impl TokenUsage {
@@ -160,6 +318,7 @@ impl Add<TokenUsage> for TokenUsage {
             cache_creation_input_tokens: self.cache_creation_input_tokens
                 + other.cache_creation_input_tokens,
             cache_read_input_tokens: self.cache_read_input_tokens + other.cache_read_input_tokens,
+            reasoning_tokens: self.reasoning_tokens + other.reasoning_tokens,
         }
     }
 }
@@ -174,10 +333,33 @@ impl Sub<TokenUsage> for TokenUsage {
             cache_creation_input_tokens: self.cache_creation_input_tokens
                 - other.cache_creation_input_tokens,
             cache_read_input_tokens: self.cache_read_input_tokens - other.cache_read_input_tokens,
+            reasoning_tokens: self.reasoning_tokens - other.reasoning_tokens,
         }
     }
 }
 
+/// What a model costs, in US dollars per million tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    /// Defaults to the price of input tokens.
+    pub cache_write: Option<f64>,
+    /// Defaults to the price of input tokens.
+    pub cache_read: Option<f64>,
+}
+
+impl ModelPrice {
+    /// The cost in US dollars of the tokens in `usage`.
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let cost = usage.input_tokens as f64 * self.input
+            + usage.output_tokens as f64 * self.output
+            + usage.cache_creation_input_tokens as f64 * self.cache_write.unwrap_or(self.input)
+            + usage.cache_read_input_tokens as f64 * self.cache_read.unwrap_or(self.input);
+        cost / 1_000_000.
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub struct LanguageModelToolUseId(Arc<str>);
 
@@ -233,20 +415,87 @@ pub trait LanguageModel: Send + Sync {
         None
     }
 
+    /// What the model costs, when it's known.
+    fn price(&self, _cx: &App) -> Option<ModelPrice> {
+        None
+    }
+
+    /// The estimated cost in US dollars of a completion that used `usage`.
+    fn estimate_cost(&self, usage: &TokenUsage, cx: &App) -> Option<f64> {
+        Some(self.price(cx)?.cost(usage))
+    }
+
     /// Whether this model supports images
     fn supports_images(&self) -> bool;
 
+    /// The largest images this model accepts. Bigger images are downscaled and recompressed to
+    /// fit before they're sent.
+    fn image_limits(&self) -> ImageLimits {
+        ImageLimits::default()
+    }
+
+    /// The largest PDFs this model accepts, or `None` if it can't read PDFs. PDFs that don't fit
+    /// are left out of requests.
+    fn document_limits(&self) -> Option<DocumentLimits> {
+        None
+    }
+
+    /// Whether this model can listen to audio in requests, like voice notes.
+    fn supports_audio(&self) -> bool {
+        false
+    }
+
+    /// Whether this model can watch video clips in requests.
+    fn supports_video(&self) -> bool {
+        false
+    }
+
     /// Whether this model supports tools.
     fn supports_tools(&self) -> bool;
 
     /// Whether this model supports choosing which tool to use.
     fn supports_tool_choice(&self, choice: LanguageModelToolChoice) -> bool;
 
+    /// Whether this model can call several tools in one response, when a request's
+    /// [`LanguageModelRequest::parallel_tool_calls`] allows it.
+    fn supports_parallel_tool_calls(&self) -> bool {
+        false
+    }
+
+    /// Whether this model reasons before it responds, streaming its thinking.
+    fn supports_thinking(&self) -> bool {
+        false
+    }
+
     /// Returns whether this model supports "burn mode";
     fn supports_max_mode(&self) -> bool {
         false
     }
 
+    /// Whether the model continues an assistant message at the end of the request instead of
+    /// starting a new one, which lets [`Self::stream_continuation`] resume interrupted responses.
+    fn supports_assistant_prefill(&self) -> bool {
+        false
+    }
+
+    /// Whether the model can generate several alternative completions for one request, as set
+    /// by [`LanguageModelRequest::candidates`].
+    fn supports_candidates(&self) -> bool {
+        false
+    }
+
+    /// Whether the model can report the log probabilities of the tokens it generates, as
+    /// requested by [`LanguageModelRequest::logprobs`].
+    fn supports_logprobs(&self) -> bool {
+        false
+    }
+
+    /// How the model makes its responses conform to a request's
+    /// [`response_schema`](LanguageModelRequest::response_schema).
+    fn structured_output(&self) -> StructuredOutput {
+        StructuredOutput::Unsupported
+    }
+
     fn tool_input_format(&self) -> LanguageModelToolSchemaFormat {
         LanguageModelToolSchemaFormat::JsonSchema
     }
@@ -273,6 +522,27 @@ pub trait LanguageModel: Send + Sync {
         >,
     >;
 
+    /// Resumes a response to `request` that was interrupted after `partial_text`, such as by a
+    /// [`LanguageModelKnownError::StreamInterrupted`], by sending the request again with the
+    /// partial response prefilled. The events only cover the rest of the response.
+    fn stream_continuation(
+        &self,
+        request: LanguageModelRequest,
+        partial_text: &str,
+        cx: &AsyncApp,
+    ) -> BoxFuture<
+        'static,
+        Result<
+            BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+        >,
+    > {
+        if !self.supports_assistant_prefill() {
+            let error = anyhow::anyhow!("{} can't continue partial responses", self.name().0);
+            return futures::future::ready(Err(error)).boxed();
+        }
+        self.stream_completion(request.continuation(partial_text), cx)
+    }
+
     fn stream_completion_text(
         &self,
         request: LanguageModelRequest,
@@ -302,14 +572,32 @@ pub trait LanguageModel: Send + Sync {
             let stream = futures::stream::iter(first_item_text.map(Ok))
                 .chain(events.filter_map({
                     let last_token_usage = last_token_usage.clone();
+                    let mut candidate = 0;
                     move |result| {
                         let last_token_usage = last_token_usage.clone();
+                        if let Ok(LanguageModelCompletionEvent::Candidate { index }) = &result {
+                            candidate = *index;
+                        }
+                        let is_first_candidate = candidate == 0;
                         async move {
                             match result {
                                 Ok(LanguageModelCompletionEvent::StatusUpdate { .. }) => None,
                                 Ok(LanguageModelCompletionEvent::StartMessage { .. }) => None,
-                                Ok(LanguageModelCompletionEvent::Text(text)) => Some(Ok(text)),
+                                Ok(LanguageModelCompletionEvent::Fallback { .. }) => None,
+                                Ok(LanguageModelCompletionEvent::Candidate { .. }) => None,
+                                Ok(LanguageModelCompletionEvent::Logprobs(_)) => None,
+                                Ok(LanguageModelCompletionEvent::Citations(_)) => None,
+                                Ok(LanguageModelCompletionEvent::GuardrailIntervention(_)) => None,
+                                Ok(LanguageModelCompletionEvent::UploadProgress { .. }) => None,
+                                Ok(LanguageModelCompletionEvent::ExecutableCode { .. }) => None,
+                                Ok(LanguageModelCompletionEvent::CodeExecutionResult {
+                                    ..
+                                }) => None,
+                                Ok(LanguageModelCompletionEvent::Text(text)) => {
+                                    is_first_candidate.then_some(Ok(text))
+                                }
                                 Ok(LanguageModelCompletionEvent::Thinking { .. }) => None,
+                                Ok(LanguageModelCompletionEvent::RedactedThinking { .. }) => None,
                                 Ok(LanguageModelCompletionEvent::Stop(_)) => None,
                                 Ok(LanguageModelCompletionEvent::ToolUse(_)) => None,
                                 Ok(LanguageModelCompletionEvent::UsageUpdate(token_usage)) => {
@@ -332,6 +620,91 @@ pub trait LanguageModel: Send + Sync {
         .boxed()
     }
 
+    /// Streams the text of `candidates` alternative completions of `request`, which are generated
+    /// by a single request. Models that don't support candidates only stream one completion.
+    ///
+    /// The token usage is that of the whole request, and is shared by all of the streams.
+    fn stream_candidate_texts(
+        &self,
+        request: LanguageModelRequest,
+        candidates: u32,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Vec<LanguageModelTextStream>>> {
+        let candidates = if self.supports_candidates() {
+            candidates.max(1)
+        } else {
+            1
+        };
+        let future = self.stream_completion(
+            LanguageModelRequest {
+                candidates: Some(candidates),
+                ..request
+            },
+            cx,
+        );
+        let executor = cx.background_executor().clone();
+
+        async move {
+            let mut events = future.await?;
+            let last_token_usage = Arc::new(Mutex::new(TokenUsage::default()));
+            let (senders, streams): (Vec<_>, Vec<_>) = (0..candidates)
+                .map(|_| {
+                    let (sender, receiver) = mpsc::unbounded();
+                    let stream = LanguageModelTextStream {
+                        message_id: None,
+                        stream: receiver.boxed(),
+                        last_token_usage: last_token_usage.clone(),
+                    };
+                    (sender, stream)
+                })
+                .unzip();
+
+            executor
+                .spawn(async move {
+                    let mut candidate = 0;
+                    while let Some(event) = events.next().await {
+                        match event {
+                            Ok(LanguageModelCompletionEvent::Candidate { index }) => {
+                                candidate = index as usize;
+                            }
+                            Ok(LanguageModelCompletionEvent::Text(text)) => {
+                                if let Some(sender) = senders.get(candidate) {
+                                    sender.unbounded_send(Ok(text)).ok();
+                                }
+                            }
+                            Ok(LanguageModelCompletionEvent::UsageUpdate(token_usage)) => {
+                                *last_token_usage.lock() = token_usage;
+                            }
+                            Ok(_) => {}
+                            Err(error) => {
+                                let message = error.to_string();
+                                for (ix, sender) in senders.iter().enumerate() {
+                                    if ix != candidate {
+                                        sender
+                                            .unbounded_send(Err(
+                                                anyhow::anyhow!(message.clone()).into()
+                                            ))
+                                            .ok();
+                                    }
+                                }
+                                if let Some(sender) = senders.get(candidate) {
+                                    sender.unbounded_send(Err(error)).ok();
+                                }
+                                break;
+                            }
+                        }
+                        if senders.iter().all(|sender| sender.is_closed()) {
+                            break;
+                        }
+                    }
+                })
+                .detach();
+
+            Ok(streams)
+        }
+        .boxed()
+    }
+
     fn cache_configuration(&self) -> Option<LanguageModelCacheConfiguration> {
         None
     }
@@ -346,6 +719,76 @@ pub trait LanguageModel: Send + Sync {
 pub enum LanguageModelKnownError {
     #[error("Context window limit exceeded ({tokens})")]
     ContextWindowLimitExceeded { tokens: usize },
+    #[error("Timed out after {}s {kind}", after.as_secs())]
+    Timeout {
+        kind: LanguageModelTimeout,
+        after: Duration,
+    },
+    #[error("Reached the ${limit:.2} {period} spend limit, with ${spent:.2} spent")]
+    SpendLimitReached {
+        period: SpendLimitPeriod,
+        spent: f64,
+        limit: f64,
+    },
+    /// The response stopped partway through, because the connection dropped or stalled, after the
+    /// model had already generated `partial_text`.
+    #[error("Response interrupted: {reason}")]
+    StreamInterrupted {
+        partial_text: String,
+        reason: String,
+    },
+    /// The prompt was flagged by the `moderation` settings, so it wasn't sent.
+    #[error("Prompt blocked by moderation: {}", reasons.join(", "))]
+    ModerationFlagged { reasons: Vec<String> },
+    /// More images were attached to the last message than the model takes in one request, so it
+    /// wasn't sent.
+    #[error("{count} images are attached, but this model takes at most {limit} at once")]
+    TooManyImages { count: usize, limit: usize },
+    /// The images attached to the last message are larger together than the model takes in one
+    /// request, so it wasn't sent.
+    #[error(
+        "The attached images take up {} MB, but this model takes at most {} MB at once",
+        bytes.div_ceil(1024 * 1024),
+        limit / (1024 * 1024)
+    )]
+    ImagesTooLarge { bytes: usize, limit: usize },
+}
+
+/// The stage of a request at which a [`LanguageModelKnownError::Timeout`] occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LanguageModelTimeout {
+    /// The provider did not respond to the request.
+    Connect,
+    /// The provider responded but did not start generating.
+    FirstToken,
+    /// The response stopped making progress partway through.
+    Idle,
+}
+
+impl fmt::Display for LanguageModelTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connect => write!(f, "connecting to the provider"),
+            Self::FirstToken => write!(f, "waiting for the first token"),
+            Self::Idle => write!(f, "waiting for the response to continue"),
+        }
+    }
+}
+
+/// The period that a [`LanguageModelKnownError::SpendLimitReached`] limit applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpendLimitPeriod {
+    Day,
+    Month,
+}
+
+impl fmt::Display for SpendLimitPeriod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Day => write!(f, "daily"),
+            Self::Month => write!(f, "monthly"),
+        }
+    }
 }
 
 pub trait LanguageModelTool: 'static + DeserializeOwned + JsonSchema {
@@ -377,6 +820,11 @@ pub trait LanguageModelProvider: 'static {
     fn load_model(&self, _model: Arc<dyn LanguageModel>, _cx: &App) {}
     fn is_authenticated(&self, cx: &App) -> bool;
     fn authenticate(&self, cx: &mut App) -> Task<Result<(), AuthenticateError>>;
+    /// Whether the provider rejected the configured credentials, e.g. because the API key was
+    /// revoked, in which case the user should be asked to update them.
+    fn credentials_rejected(&self, _cx: &App) -> bool {
+        false
+    }
     fn configuration_view(&self, window: &mut Window, cx: &mut App) -> AnyView;
     fn must_accept_terms(&self, _cx: &App) -> bool {
         false
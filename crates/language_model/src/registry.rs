@@ -1,8 +1,8 @@
 use crate::{
-    LanguageModel, LanguageModelId, LanguageModelProvider, LanguageModelProviderId,
-    LanguageModelProviderState,
+    FallbackLanguageModel, LanguageModel, LanguageModelId, LanguageModelProvider,
+    LanguageModelProviderId, LanguageModelProviderState,
 };
-use collections::BTreeMap;
+use collections::{BTreeMap, HashMap, HashSet};
 use gpui::{App, Context, Entity, EventEmitter, Global, prelude::*};
 use std::{str::FromStr, sync::Arc};
 use util::maybe;
@@ -24,10 +24,15 @@ pub struct LanguageModelRegistry {
     commit_message_model: Option<ConfiguredModel>,
     thread_summary_model: Option<ConfiguredModel>,
     providers: BTreeMap<LanguageModelProviderId, Arc<dyn LanguageModelProvider>>,
+    /// Providers that are registered but turned off in the settings, which are left out of
+    /// everything but [`Self::all_providers`].
+    disabled_providers: HashSet<LanguageModelProviderId>,
     inline_alternatives: Vec<Arc<dyn LanguageModel>>,
+    /// The models to retry a request on, in order, when the model it was sent to fails.
+    fallback_models: HashMap<SelectedModel, Vec<SelectedModel>>,
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct SelectedModel {
     pub provider: LanguageModelProviderId,
     pub model: LanguageModelId,
@@ -136,7 +141,41 @@ impl LanguageModelRegistry {
         }
     }
 
+    /// Turns off the given providers and turns the rest back on, emitting the same events as
+    /// unregistering and registering them.
+    pub fn set_disabled_providers(
+        &mut self,
+        disabled_providers: HashSet<LanguageModelProviderId>,
+        cx: &mut Context<Self>,
+    ) {
+        let previously_disabled =
+            std::mem::replace(&mut self.disabled_providers, disabled_providers);
+        for id in self.providers.keys() {
+            match (
+                previously_disabled.contains(id),
+                self.disabled_providers.contains(id),
+            ) {
+                (false, true) => cx.emit(Event::RemovedProvider(id.clone())),
+                (true, false) => cx.emit(Event::AddedProvider(id.clone())),
+                _ => {}
+            }
+        }
+    }
+
+    pub fn is_provider_enabled(&self, id: &LanguageModelProviderId) -> bool {
+        !self.disabled_providers.contains(id)
+    }
+
+    /// The enabled providers, with Zed's own first.
     pub fn providers(&self) -> Vec<Arc<dyn LanguageModelProvider>> {
+        self.all_providers()
+            .into_iter()
+            .filter(|provider| self.is_provider_enabled(&provider.id()))
+            .collect()
+    }
+
+    /// Every registered provider, including disabled ones, for turning them back on.
+    pub fn all_providers(&self) -> Vec<Arc<dyn LanguageModelProvider>> {
         let zed_provider_id = LanguageModelProviderId("zed.dev".into());
         let mut providers = Vec::with_capacity(self.providers.len());
         if let Some(provider) = self.providers.get(&zed_provider_id) {
@@ -157,12 +196,16 @@ impl LanguageModelRegistry {
         cx: &'a App,
     ) -> impl Iterator<Item = Arc<dyn LanguageModel>> + 'a {
         self.providers
-            .values()
-            .flat_map(|provider| provider.provided_models(cx))
+            .iter()
+            .filter(|(id, _)| self.is_provider_enabled(id))
+            .flat_map(|(_, provider)| provider.provided_models(cx))
     }
 
     pub fn provider(&self, id: &LanguageModelProviderId) -> Option<Arc<dyn LanguageModelProvider>> {
-        self.providers.get(id).cloned()
+        self.providers
+            .get(id)
+            .filter(|_| self.is_provider_enabled(id))
+            .cloned()
     }
 
     pub fn select_default_model(&mut self, model: Option<&SelectedModel>, cx: &mut Context<Self>) {
@@ -224,9 +267,56 @@ impl LanguageModelRegistry {
             .iter()
             .find(|model| model.id() == selected_model.model)?
             .clone();
+        let fallbacks = self
+            .fallback_models
+            .get(selected_model)
+            .into_iter()
+            .flatten()
+            .filter_map(|fallback| {
+                self.provider(&fallback.provider)?
+                    .provided_models(cx)
+                    .into_iter()
+                    .find(|model| model.id() == fallback.model)
+            })
+            .collect::<Vec<_>>();
+        let model = if fallbacks.is_empty() {
+            model
+        } else {
+            Arc::new(FallbackLanguageModel::new(model, fallbacks))
+        };
         Some(ConfiguredModel { provider, model })
     }
 
+    /// Sets the models to retry requests on when the model they were sent to fails, and applies
+    /// them to the models that are already selected.
+    pub fn set_fallback_models(
+        &mut self,
+        fallback_models: HashMap<SelectedModel, Vec<SelectedModel>>,
+        cx: &mut Context<Self>,
+    ) {
+        if self.fallback_models == fallback_models {
+            return;
+        }
+        self.fallback_models = fallback_models;
+        self.default_model = self.reselect_model(self.default_model.clone(), cx);
+        self.inline_assistant_model = self.reselect_model(self.inline_assistant_model.clone(), cx);
+        self.commit_message_model = self.reselect_model(self.commit_message_model.clone(), cx);
+        self.thread_summary_model = self.reselect_model(self.thread_summary_model.clone(), cx);
+    }
+
+    fn reselect_model(
+        &mut self,
+        model: Option<ConfiguredModel>,
+        cx: &mut Context<Self>,
+    ) -> Option<ConfiguredModel> {
+        let model = model?;
+        let selected_model = SelectedModel {
+            provider: model.provider.id(),
+            model: model.model.id(),
+        };
+        self.select_model(&selected_model, cx).or(Some(model))
+    }
+
     pub fn set_default_model(&mut self, model: Option<ConfiguredModel>, cx: &mut Context<Self>) {
         match (self.default_model.as_ref(), model.as_ref()) {
             (Some(old), Some(new)) if old.is_same_as(new) => {}
@@ -359,4 +449,21 @@ mod tests {
         let providers = registry.read(cx).providers();
         assert!(providers.is_empty());
     }
+
+    #[gpui::test]
+    fn test_disable_providers(cx: &mut App) {
+        let registry = cx.new(|_| LanguageModelRegistry::default());
+        let provider_id = crate::fake_provider::provider_id();
+
+        registry.update(cx, |registry, cx| {
+            registry.register_provider(FakeLanguageModelProvider, cx);
+            registry.set_disabled_providers(HashSet::from_iter([provider_id.clone()]), cx);
+        });
+
+        let registry = registry.read(cx);
+        assert!(registry.providers().is_empty());
+        assert!(registry.provider(&provider_id).is_none());
+        assert_eq!(registry.available_models(cx).count(), 0);
+        assert_eq!(registry.all_providers().len(), 1);
+    }
 }
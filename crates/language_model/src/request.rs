@@ -1,22 +1,29 @@
+use std::collections::BTreeMap;
 use std::io::{Cursor, Write};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::role::Role;
-use crate::{LanguageModelToolUse, LanguageModelToolUseId};
-use anyhow::Result;
+use crate::{LanguageModelKnownError, LanguageModelToolUse, LanguageModelToolUseId};
+use anyhow::{Result, bail};
+use base64::Engine as _;
 use base64::write::EncoderWriter;
 use gpui::{
     App, AppContext as _, DevicePixels, Image, ImageFormat, ObjectFit, SharedString, Size, Task,
     point, px, size,
 };
+use image::codecs::jpeg::JpegEncoder;
 use image::codecs::png::PngEncoder;
+use image::imageops::FilterType;
 use serde::{Deserialize, Serialize};
 use util::ResultExt;
 use zed_llm_client::{CompletionIntent, CompletionMode};
 
 #[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
 pub struct LanguageModelImage {
-    /// A base64-encoded PNG image.
+    /// A base64-encoded image. Attached images are PNGs, but images from tools can be in any
+    /// format, and are converted for models that don't take theirs by
+    /// [`LanguageModelImage::fit_to_limits`].
     pub source: SharedString,
     pub size: Size<DevicePixels>,
 }
@@ -75,6 +82,50 @@ impl std::fmt::Debug for LanguageModelImage {
     }
 }
 
+/// The largest images a model accepts, in which formats, and how many of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageLimits {
+    /// The most pixels an image can have in either dimension.
+    pub max_dimension: u32,
+    /// The most bytes an image can take up once decoded from base64.
+    pub max_bytes: usize,
+    /// The most images a request can have, or `None` if there's no limit.
+    pub max_images: Option<usize>,
+    /// The most bytes all of a request's images can take up together once decoded from base64,
+    /// or `None` if there's no limit.
+    pub max_total_bytes: Option<usize>,
+    /// The formats that the model takes. Images in other formats are converted to PNG, or to
+    /// JPEG if the model doesn't take PNGs.
+    pub formats: &'static [ImageFormat],
+}
+
+impl Default for ImageLimits {
+    /// Anthropic's limits, which most providers accept.
+    fn default() -> Self {
+        Self {
+            max_dimension: 8000,
+            max_bytes: 5 * 1024 * 1024,
+            max_images: Some(100),
+            max_total_bytes: Some(32 * 1024 * 1024),
+            formats: &[
+                ImageFormat::Png,
+                ImageFormat::Jpeg,
+                ImageFormat::Gif,
+                ImageFormat::Webp,
+            ],
+        }
+    }
+}
+
+impl ImageLimits {
+    /// Whether the model takes images of the given MIME type.
+    pub fn accepts(&self, mime_type: &str) -> bool {
+        self.formats
+            .iter()
+            .any(|format| format.mime_type() == mime_type)
+    }
+}
+
 /// Anthropic wants uploaded images to be smaller than this in both dimensions.
 const ANTHROPIC_SIZE_LIMT: f32 = 1568.;
 
@@ -98,7 +149,11 @@ impl LanguageModelImage {
                     .and_then(image::DynamicImage::from_decoder),
                 ImageFormat::Gif => image::codecs::gif::GifDecoder::new(image_bytes)
                     .and_then(image::DynamicImage::from_decoder),
-                _ => return None,
+                ImageFormat::Bmp => image::codecs::bmp::BmpDecoder::new(image_bytes)
+                    .and_then(image::DynamicImage::from_decoder),
+                ImageFormat::Tiff => image::codecs::tiff::TiffDecoder::new(image_bytes)
+                    .and_then(image::DynamicImage::from_decoder),
+                ImageFormat::Svg => return None,
             }
             .log_err()?;
 
@@ -140,6 +195,19 @@ impl LanguageModelImage {
         })
     }
 
+    /// Wraps an image that's already base64-encoded, like one returned by an MCP tool, reading
+    /// its dimensions from its header. The image is kept in its own format.
+    pub fn from_base64(source: &str) -> Result<Self> {
+        let bytes = base64::engine::general_purpose::STANDARD.decode(source)?;
+        let (width, height) = image::ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()?
+            .into_dimensions()?;
+        Ok(Self {
+            source: source.to_string().into(),
+            size: size(DevicePixels(width as i32), DevicePixels(height as i32)),
+        })
+    }
+
     pub fn estimate_tokens(&self) -> usize {
         let width = self.size.width.0.unsigned_abs() as usize;
         let height = self.size.height.0.unsigned_abs() as usize;
@@ -150,8 +218,125 @@ impl LanguageModelImage {
         (width * height) / 750
     }
 
+    /// The size of the image once decoded from base64.
+    pub fn byte_len(&self) -> usize {
+        self.source.len() / 4 * 3
+    }
+
+    /// The image's MIME type, sniffed from its first bytes.
+    pub fn mime_type(&self) -> &'static str {
+        let prefix = &self.source.as_bytes()[..self.source.len().min(16)];
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(prefix)
+            .unwrap_or_default();
+        match bytes.as_slice() {
+            [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+            [b'G', b'I', b'F', b'8', ..] => "image/gif",
+            [b'B', b'M', ..] => "image/bmp",
+            [b'I', b'I', b'*', 0, ..] | [b'M', b'M', 0, b'*', ..] => "image/tiff",
+            [
+                _,
+                _,
+                _,
+                _,
+                b'f',
+                b't',
+                b'y',
+                b'p',
+                b'h',
+                b'e',
+                b'i',
+                b'c' | b'x',
+                ..,
+            ] => "image/heic",
+            [
+                b'R',
+                b'I',
+                b'F',
+                b'F',
+                _,
+                _,
+                _,
+                _,
+                b'W',
+                b'E',
+                b'B',
+                b'P',
+                ..,
+            ] => "image/webp",
+            _ => "image/png",
+        }
+    }
+
     pub fn to_base64_url(&self) -> String {
-        format!("data:image/png;base64,{}", self.source)
+        format!("data:{};base64,{}", self.mime_type(), self.source)
+    }
+
+    /// Converts the image to a format that the model takes, and downscales and recompresses it
+    /// until it fits within `limits`. Images that are still too large as PNGs, or that are for
+    /// models that don't take PNGs, are re-encoded as JPEGs of the given quality, from 1 to 100,
+    /// and halved in size until they fit.
+    pub fn fit_to_limits(&self, limits: ImageLimits, jpeg_quality: u8) -> Result<Self> {
+        let max_dimension = limits.max_dimension as i32;
+        let mime_type = self.mime_type();
+        if self.size.width.0 <= max_dimension
+            && self.size.height.0 <= max_dimension
+            && self.byte_len() <= limits.max_bytes
+            && limits.accepts(mime_type)
+        {
+            return Ok(self.clone());
+        }
+
+        if mime_type == "image/heic" {
+            bail!("HEIC images can't be converted");
+        }
+        let bytes = base64::engine::general_purpose::STANDARD.decode(self.source.as_bytes())?;
+        let mut image = image::load_from_memory(&bytes)?;
+        if image.width() > limits.max_dimension || image.height() > limits.max_dimension {
+            image = image.resize(
+                limits.max_dimension,
+                limits.max_dimension,
+                FilterType::Triangle,
+            );
+        }
+
+        let mut encoded = Vec::new();
+        let accepts_png = limits.accepts(ImageFormat::Png.mime_type());
+        if accepts_png {
+            image.write_with_encoder(PngEncoder::new(&mut encoded))?;
+        }
+        if !accepts_png || encoded.len() > limits.max_bytes {
+            // JPEGs have no alpha channel.
+            image = image::DynamicImage::ImageRgb8(image.to_rgb8());
+            loop {
+                encoded.clear();
+                image.write_with_encoder(JpegEncoder::new_with_quality(
+                    &mut encoded,
+                    jpeg_quality.clamp(1, 100),
+                ))?;
+                if encoded.len() <= limits.max_bytes {
+                    break;
+                }
+                if image.width() <= 1 && image.height() <= 1 {
+                    bail!("image can't be compressed below {} bytes", limits.max_bytes);
+                }
+                image = image.resize_exact(
+                    (image.width() / 2).max(1),
+                    (image.height() / 2).max(1),
+                    FilterType::Triangle,
+                );
+            }
+        }
+
+        Ok(Self {
+            source: base64::engine::general_purpose::STANDARD
+                .encode(encoded)
+                .into(),
+            size: size(
+                DevicePixels(image.width() as i32),
+                DevicePixels(image.height() as i32),
+            ),
+        })
     }
 }
 
@@ -174,6 +359,279 @@ fn encode_as_base64(data: Arc<Image>, image: image::DynamicImage) -> Result<Vec<
     Ok(base64_image)
 }
 
+/// A PDF attached to a message, for models that can read PDFs.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct LanguageModelPdf {
+    /// The file name of the PDF, which some providers show to the model.
+    pub name: String,
+    /// The base64-encoded PDF.
+    pub source: SharedString,
+    pub page_count: usize,
+}
+
+/// The largest PDFs a model accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DocumentLimits {
+    /// The most bytes a PDF can take up once decoded from base64.
+    pub max_bytes: usize,
+    pub max_pages: usize,
+}
+
+impl Default for DocumentLimits {
+    /// Anthropic's limits.
+    fn default() -> Self {
+        Self {
+            max_bytes: 32 * 1024 * 1024,
+            max_pages: 100,
+        }
+    }
+}
+
+impl LanguageModelPdf {
+    pub fn from_bytes(name: String, bytes: &[u8]) -> Result<Self> {
+        if !bytes.starts_with(b"%PDF-") {
+            bail!("{name} is not a PDF");
+        }
+        Ok(Self {
+            name,
+            source: base64::engine::general_purpose::STANDARD
+                .encode(bytes)
+                .into(),
+            page_count: count_pdf_pages(bytes),
+        })
+    }
+
+    /// The size of the PDF once decoded from base64.
+    pub fn byte_len(&self) -> usize {
+        self.source.len() / 4 * 3
+    }
+
+    pub fn to_base64_url(&self) -> String {
+        format!("data:application/pdf;base64,{}", self.source)
+    }
+
+    /// Checks that the PDF is within `limits`, describing why it isn't otherwise.
+    pub fn check_limits(&self, limits: DocumentLimits) -> Result<()> {
+        if self.byte_len() > limits.max_bytes {
+            bail!(
+                "{} is {} MB, but at most {} MB is supported",
+                self.name,
+                self.byte_len() / (1024 * 1024),
+                limits.max_bytes / (1024 * 1024)
+            );
+        }
+        if self.page_count > limits.max_pages {
+            bail!(
+                "{} has {} pages, but at most {} are supported",
+                self.name,
+                self.page_count,
+                limits.max_pages
+            );
+        }
+        Ok(())
+    }
+
+    /// A rough estimate of the tokens the PDF takes up, since providers send each page as both
+    /// its text and an image of it.
+    pub fn estimate_tokens(&self) -> usize {
+        self.page_count.max(1) * 2000
+    }
+}
+
+impl std::fmt::Debug for LanguageModelPdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LanguageModelPdf")
+            .field("name", &self.name)
+            .field("source", &format!("<{} bytes>", self.source.len()))
+            .field("page_count", &self.page_count)
+            .finish()
+    }
+}
+
+/// Counts the page objects in a PDF. This doesn't parse the PDF, so it can miss pages in
+/// compressed object streams, but it's close enough for checking limits.
+fn count_pdf_pages(bytes: &[u8]) -> usize {
+    const PAGE_TYPE: &[u8] = b"/Type";
+    let mut count = 0;
+    let mut rest = bytes;
+    while let Some(start) = rest
+        .windows(PAGE_TYPE.len())
+        .position(|window| window == PAGE_TYPE)
+    {
+        rest = &rest[start + PAGE_TYPE.len()..];
+        let value = rest.trim_ascii_start();
+        if value.starts_with(b"/Page") && !value.starts_with(b"/Pages") {
+            count += 1;
+        }
+    }
+    count
+}
+
+/// An audio clip attached to a message, like a voice note, for models that can listen to audio.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct LanguageModelAudio {
+    pub format: AudioFormat,
+    /// The base64-encoded audio.
+    pub source: SharedString,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioFormat {
+    Wav,
+    Mp3,
+}
+
+impl AudioFormat {
+    pub fn mime_type(self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "audio/wav",
+            AudioFormat::Mp3 => "audio/mp3",
+        }
+    }
+
+    /// The name of the format in OpenAI's API.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AudioFormat::Wav => "wav",
+            AudioFormat::Mp3 => "mp3",
+        }
+    }
+}
+
+impl LanguageModelAudio {
+    /// Encodes a WAV or MP3 file, whose format is sniffed from its first bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let format = match bytes {
+            [
+                b'R',
+                b'I',
+                b'F',
+                b'F',
+                _,
+                _,
+                _,
+                _,
+                b'W',
+                b'A',
+                b'V',
+                b'E',
+                ..,
+            ] => AudioFormat::Wav,
+            // MP3s start with an ID3 tag or with the sync bits of a frame header.
+            [b'I', b'D', b'3', ..] => AudioFormat::Mp3,
+            [0xFF, second, ..] if second & 0xE0 == 0xE0 => AudioFormat::Mp3,
+            _ => bail!("audio must be a WAV or MP3 file"),
+        };
+        Ok(Self {
+            format,
+            source: base64::engine::general_purpose::STANDARD
+                .encode(bytes)
+                .into(),
+        })
+    }
+
+    /// The size of the audio once decoded from base64.
+    pub fn byte_len(&self) -> usize {
+        self.source.len() / 4 * 3
+    }
+}
+
+impl std::fmt::Debug for LanguageModelAudio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LanguageModelAudio")
+            .field("format", &self.format)
+            .field("source", &format!("<{} bytes>", self.source.len()))
+            .finish()
+    }
+}
+
+/// How many tokens a second of video takes up in Gemini models, with its audio track.
+const VIDEO_TOKENS_PER_SECOND: usize = 300;
+/// How long a video is assumed to be when its duration can't be read from it.
+const ASSUMED_VIDEO_DURATION: Duration = Duration::from_secs(60);
+
+/// A short video clip attached to a message, for models that can watch video.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Hash)]
+pub struct LanguageModelVideo {
+    /// The file name of the video.
+    pub name: String,
+    pub mime_type: SharedString,
+    /// The base64-encoded video.
+    pub source: SharedString,
+    /// How long the video is, when it could be read from an MP4 or QuickTime file.
+    pub duration: Option<Duration>,
+}
+
+impl LanguageModelVideo {
+    /// Encodes an MP4, QuickTime, or WebM video, whose format is sniffed from its first bytes.
+    pub fn from_bytes(name: String, bytes: &[u8]) -> Result<Self> {
+        let mime_type = match bytes.get(4..12) {
+            Some(b"ftypqt  ") => "video/quicktime",
+            Some(brand) if brand.starts_with(b"ftyp") => "video/mp4",
+            _ if bytes.starts_with(&[0x1A, 0x45, 0xDF, 0xA3]) => "video/webm",
+            _ => bail!("{name} must be an MP4, QuickTime, or WebM video"),
+        };
+        Ok(Self {
+            name,
+            mime_type: mime_type.into(),
+            source: base64::engine::general_purpose::STANDARD
+                .encode(bytes)
+                .into(),
+            duration: mp4_duration(bytes),
+        })
+    }
+
+    /// The size of the video once decoded from base64.
+    pub fn byte_len(&self) -> usize {
+        self.source.len() / 4 * 3
+    }
+
+    /// Estimates the tokens the video takes up from its duration, since models sample a fixed
+    /// number of frames per second.
+    pub fn estimate_tokens(&self) -> usize {
+        let duration = self.duration.unwrap_or(ASSUMED_VIDEO_DURATION);
+        (duration.as_secs_f64().ceil() as usize).max(1) * VIDEO_TOKENS_PER_SECOND
+    }
+}
+
+impl std::fmt::Debug for LanguageModelVideo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LanguageModelVideo")
+            .field("name", &self.name)
+            .field("mime_type", &self.mime_type)
+            .field("source", &format!("<{} bytes>", self.source.len()))
+            .field("duration", &self.duration)
+            .finish()
+    }
+}
+
+/// Reads the duration from the movie header (`mvhd`) box of an MP4 or QuickTime file.
+fn mp4_duration(bytes: &[u8]) -> Option<Duration> {
+    const MOVIE_HEADER: &[u8] = b"mvhd";
+    let start = bytes
+        .windows(MOVIE_HEADER.len())
+        .position(|window| window == MOVIE_HEADER)?
+        + MOVIE_HEADER.len();
+    let header = bytes.get(start..)?;
+    let read_u32 = |offset: usize| {
+        Some(u32::from_be_bytes(header.get(offset..offset + 4)?.try_into().ok()?) as u64)
+    };
+    // The version is followed by three bytes of flags, then the creation and modification times,
+    // which are 64-bit in version 1 and 32-bit otherwise.
+    let (timescale, duration) = match header.first()? {
+        1 => {
+            let duration = header.get(24..32)?;
+            (read_u32(20)?, u64::from_be_bytes(duration.try_into().ok()?))
+        }
+        _ => (read_u32(12)?, read_u32(16)?),
+    };
+    if timescale == 0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(duration as f64 / timescale as f64))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
 pub struct LanguageModelToolResult {
     pub tool_use_id: LanguageModelToolUseId,
@@ -307,16 +765,25 @@ pub enum MessageContent {
     Image(LanguageModelImage),
     ToolUse(LanguageModelToolUse),
     ToolResult(LanguageModelToolResult),
+    Document(LanguageModelDocument),
+    Pdf(LanguageModelPdf),
+    Audio(LanguageModelAudio),
+    Video(LanguageModelVideo),
 }
 
 impl MessageContent {
     pub fn to_str(&self) -> Option<&str> {
         match self {
             MessageContent::Text(text) => Some(text.as_str()),
+            MessageContent::Document(document) => Some(document.text.as_str()),
             MessageContent::Thinking { text, .. } => Some(text.as_str()),
             MessageContent::RedactedThinking(_) => None,
             MessageContent::ToolResult(tool_result) => tool_result.content.to_str(),
-            MessageContent::ToolUse(_) | MessageContent::Image(_) => None,
+            MessageContent::ToolUse(_)
+            | MessageContent::Image(_)
+            | MessageContent::Pdf(_)
+            | MessageContent::Audio(_)
+            | MessageContent::Video(_) => None,
         }
     }
 
@@ -324,10 +791,14 @@ impl MessageContent {
         match self {
             MessageContent::Text(text) => text.chars().all(|c| c.is_whitespace()),
             MessageContent::Thinking { text, .. } => text.chars().all(|c| c.is_whitespace()),
+            MessageContent::Document(document) => document.text.chars().all(|c| c.is_whitespace()),
             MessageContent::ToolResult(tool_result) => tool_result.content.is_empty(),
             MessageContent::RedactedThinking(_)
             | MessageContent::ToolUse(_)
-            | MessageContent::Image(_) => false,
+            | MessageContent::Image(_)
+            | MessageContent::Pdf(_)
+            | MessageContent::Audio(_)
+            | MessageContent::Video(_) => false,
         }
     }
 }
@@ -344,6 +815,26 @@ impl From<&str> for MessageContent {
     }
 }
 
+/// A document attached to a message, like a fetched web page, which models that support citations
+/// can cite the parts of that their response is based on.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
+pub struct LanguageModelDocument {
+    /// Where the document is from, like its URL, which citations of the document link to.
+    pub source: String,
+    pub title: Option<String>,
+    pub text: String,
+}
+
+impl LanguageModelDocument {
+    /// The document as text, for models that don't take documents.
+    pub fn to_text(&self) -> String {
+        format!(
+            "<document source=\"{}\">\n{}\n</document>",
+            self.source, self.text
+        )
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Hash)]
 pub struct LanguageModelRequestMessage {
     pub role: Role,
@@ -354,8 +845,11 @@ pub struct LanguageModelRequestMessage {
 impl LanguageModelRequestMessage {
     pub fn string_contents(&self) -> String {
         let mut buffer = String::new();
-        for string in self.content.iter().filter_map(|content| content.to_str()) {
-            buffer.push_str(string);
+        for content in &self.content {
+            match content {
+                MessageContent::Document(document) => buffer.push_str(&document.to_text()),
+                content => buffer.push_str(content.to_str().unwrap_or_default()),
+            }
         }
 
         buffer
@@ -373,11 +867,37 @@ pub struct LanguageModelRequestTool {
     pub input_schema: serde_json::Value,
 }
 
+/// A JSON schema that the text of a response must conform to.
+///
+/// Providers that enforce schemas strictly require every object in the schema to list all of
+/// its properties as `required` and to set `additionalProperties` to `false`. Like tool input
+/// schemas, schemas should be in the model's
+/// [`tool_input_format`](crate::LanguageModel::tool_input_format).
+#[derive(Debug, PartialEq, Hash, Clone, Serialize, Deserialize)]
+pub struct ResponseSchema {
+    /// A name for the kind of response, like `commit_message`.
+    pub name: String,
+    pub description: Option<String>,
+    pub schema: serde_json::Value,
+}
+
 #[derive(Debug, PartialEq, Hash, Clone, Serialize, Deserialize)]
 pub enum LanguageModelToolChoice {
     Auto,
     Any,
     None,
+    /// The model must call the tool with this name.
+    Tool(String),
+}
+
+/// A workspace that can have its own API keys, which take precedence over the ones saved for
+/// every workspace, e.g. to bill a client's account while working on their project.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct WorkspaceScope {
+    pub name: SharedString,
+    /// Identifies the workspace in the credentials store. Derived from the workspace's root path
+    /// so that it stays the same across restarts.
+    pub id: String,
 }
 
 #[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
@@ -391,6 +911,265 @@ pub struct LanguageModelRequest {
     pub tool_choice: Option<LanguageModelToolChoice>,
     pub stop: Vec<String>,
     pub temperature: Option<f32>,
+    /// Makes sampling deterministic on providers that support it, so that the same request
+    /// produces the same completion.
+    pub seed: Option<u64>,
+    /// Penalizes tokens by how often they already appear in the completion, on providers that
+    /// support it.
+    pub frequency_penalty: Option<f32>,
+    /// Penalizes tokens that already appear in the completion at all, on providers that support
+    /// it.
+    pub presence_penalty: Option<f32>,
+    /// Only samples from the most likely tokens whose probabilities add up to `top_p`, on
+    /// providers that support it.
+    pub top_p: Option<f32>,
+    /// Only samples from the `top_k` most likely tokens, on providers that support it.
+    pub top_k: Option<u32>,
+    /// Adjusts how likely the model is to generate each token, by token ID, on providers that
+    /// support it. A bias of -100 bans the token.
+    pub logit_bias: BTreeMap<u32, f32>,
+    /// How many alternative completions to generate, on models that
+    /// [support it](crate::LanguageModel::supports_candidates). The events of each one follow a
+    /// [`LanguageModelCompletionEvent::Candidate`](crate::LanguageModelCompletionEvent::Candidate).
+    pub candidates: Option<u32>,
+    /// Reports the log probability of each generated token, along with those of the `logprobs`
+    /// most likely alternatives to it, as [`LanguageModelCompletionEvent::Logprobs`] on models
+    /// that [support it](crate::LanguageModel::supports_logprobs).
+    ///
+    /// [`LanguageModelCompletionEvent::Logprobs`]: crate::LanguageModelCompletionEvent::Logprobs
+    pub logprobs: Option<u32>,
+    /// Makes the response JSON that conforms to a schema, in the way that the model
+    /// [supports](crate::LanguageModel::structured_output). Send requests with one through
+    /// [`complete_structured`](crate::complete_structured) to get a response that's validated
+    /// against the schema, with the schema in the prompt for models that can't enforce it.
+    pub response_schema: Option<ResponseSchema>,
+    /// Whether the model may call several tools in one response. Set this to `false` when the
+    /// tools must run one at a time. When `None`, the provider's default is used.
+    pub parallel_tool_calls: Option<bool>,
+    /// Text that the response is expected to mostly repeat, like the current content of a file
+    /// that's being rewritten, which providers that support predicted outputs use to generate
+    /// the response faster.
+    pub prediction: Option<String>,
+    /// Whether the response can wait, so that it may be sent through a provider's batch API at a
+    /// lower price when batching is enabled. Only meant for requests that nobody is waiting on,
+    /// like summaries and eval judgements.
+    pub batch: bool,
+    /// The workspace that the request was made from, whose saved API keys providers use instead
+    /// of their own if it has any.
+    #[serde(skip)]
+    pub workspace: Option<WorkspaceScope>,
+}
+
+impl LanguageModelRequest {
+    /// Fits every image in the request within `limits`, as with
+    /// [`LanguageModelImage::fit_to_limits`]. Images that can't be made to fit are replaced with a
+    /// note, so that the rest of the request can still be sent.
+    pub fn fit_images(&mut self, limits: ImageLimits, jpeg_quality: u8) {
+        let omitted_image = |image: &LanguageModelImage| {
+            if limits.accepts(image.mime_type()) {
+                "[Image omitted because it's too large]".to_string()
+            } else {
+                format!(
+                    "[Image omitted because it couldn't be converted from {}]",
+                    image.mime_type()
+                )
+            }
+        };
+
+        for message in &mut self.messages {
+            for content in &mut message.content {
+                match content {
+                    MessageContent::Image(image) => {
+                        match image.fit_to_limits(limits, jpeg_quality).log_err() {
+                            Some(fitted) => *image = fitted,
+                            None => *content = MessageContent::Text(omitted_image(image)),
+                        }
+                    }
+                    MessageContent::ToolResult(tool_result) => {
+                        if let LanguageModelToolResultContent::Image(image) = &tool_result.content {
+                            tool_result.content =
+                                match image.fit_to_limits(limits, jpeg_quality).log_err() {
+                                    Some(fitted) => LanguageModelToolResultContent::Image(fitted),
+                                    None => LanguageModelToolResultContent::Text(
+                                        omitted_image(image).into(),
+                                    ),
+                                };
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /// Leaves out the oldest images that take the request past the `max_images` and
+    /// `max_total_bytes` of `limits`, replacing them with a note, so that long conversations
+    /// with many screenshots can still be sent.
+    ///
+    /// The images in the last message are never left out, since they're what the model is being
+    /// asked about. If they're past the limits on their own, this fails with
+    /// [`LanguageModelKnownError::TooManyImages`] or [`LanguageModelKnownError::ImagesTooLarge`]
+    /// instead, rather than sending a request that the provider would reject.
+    pub fn limit_images(&mut self, limits: ImageLimits) -> Result<(), LanguageModelKnownError> {
+        fn image_len(content: &MessageContent) -> Option<usize> {
+            match content {
+                MessageContent::Image(image) => Some(image.byte_len()),
+                MessageContent::ToolResult(LanguageModelToolResult {
+                    content: LanguageModelToolResultContent::Image(image),
+                    ..
+                }) => Some(image.byte_len()),
+                _ => None,
+            }
+        }
+
+        let (mut count, mut bytes) = self
+            .messages
+            .iter()
+            .flat_map(|message| &message.content)
+            .filter_map(image_len)
+            .fold((0, 0), |(count, bytes), len| (count + 1, bytes + len));
+        let within_limits = |count: usize, bytes: usize| {
+            limits
+                .max_images
+                .is_none_or(|max_images| count <= max_images)
+                && limits
+                    .max_total_bytes
+                    .is_none_or(|max_total_bytes| bytes <= max_total_bytes)
+        };
+        let Some((_, earlier_messages)) = self.messages.split_last_mut() else {
+            return Ok(());
+        };
+
+        const OMITTED_IMAGE: &str =
+            "[Image omitted because the conversation has more images than this model takes]";
+        for content in earlier_messages
+            .iter_mut()
+            .flat_map(|message| &mut message.content)
+        {
+            if within_limits(count, bytes) {
+                return Ok(());
+            }
+            let Some(len) = image_len(content) else {
+                continue;
+            };
+            count -= 1;
+            bytes -= len;
+            match content {
+                MessageContent::ToolResult(tool_result) => {
+                    tool_result.content = OMITTED_IMAGE.into()
+                }
+                _ => *content = OMITTED_IMAGE.into(),
+            }
+        }
+
+        match (limits.max_images, limits.max_total_bytes) {
+            (Some(limit), _) if count > limit => {
+                Err(LanguageModelKnownError::TooManyImages { count, limit })
+            }
+            (_, Some(limit)) if bytes > limit => {
+                Err(LanguageModelKnownError::ImagesTooLarge { bytes, limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Replaces PDFs that the model can't take with a note, so that the rest of the request can
+    /// still be sent. `limits` is `None` for models that don't support PDFs.
+    pub fn check_pdfs(&mut self, limits: Option<DocumentLimits>) {
+        for message in &mut self.messages {
+            for content in &mut message.content {
+                let MessageContent::Pdf(pdf) = content else {
+                    continue;
+                };
+                let note = match limits {
+                    None => format!("[{} omitted because this model can't read PDFs]", pdf.name),
+                    Some(limits) => match pdf.check_limits(limits) {
+                        Ok(()) => continue,
+                        Err(error) => format!("[PDF omitted: {error}]"),
+                    },
+                };
+                *content = MessageContent::Text(note);
+            }
+        }
+    }
+
+    /// Replaces images returned by tools with a note for models that can't see images, so that
+    /// the tool result itself can still be sent.
+    pub fn check_tool_result_images(&mut self, supports_images: bool) {
+        if supports_images {
+            return;
+        }
+        for message in &mut self.messages {
+            for content in &mut message.content {
+                if let MessageContent::ToolResult(tool_result) = content {
+                    if let LanguageModelToolResultContent::Image(_) = tool_result.content {
+                        tool_result.content = LanguageModelToolResultContent::Text(
+                            "[Image omitted because this model can't see images]".into(),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replaces audio with a note for models that can't listen to it, so that the rest of the
+    /// request can still be sent.
+    pub fn check_audio(&mut self, supports_audio: bool) {
+        if supports_audio {
+            return;
+        }
+        for message in &mut self.messages {
+            for content in &mut message.content {
+                if let MessageContent::Audio(_) = content {
+                    *content = MessageContent::Text(
+                        "[Audio omitted because this model can't hear it]".into(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Replaces videos with a note for models that can't watch them, so that the rest of the
+    /// request can still be sent.
+    pub fn check_videos(&mut self, supports_video: bool) {
+        if supports_video {
+            return;
+        }
+        for message in &mut self.messages {
+            for content in &mut message.content {
+                if let MessageContent::Video(video) = content {
+                    *content = MessageContent::Text(format!(
+                        "[{} omitted because this model can't watch videos]",
+                        video.name
+                    ));
+                }
+            }
+        }
+    }
+
+    /// The request to send to continue a response to this one that was interrupted after
+    /// `partial_text`, which ends with the partial response as the assistant's message for the
+    /// model to pick up from.
+    ///
+    /// Trailing whitespace is left out, since providers reject prefills that end with it, so the
+    /// continuation may start with it.
+    pub fn continuation(&self, partial_text: &str) -> Self {
+        let mut request = self.clone();
+        let partial_text = partial_text.trim_end();
+        if partial_text.is_empty() {
+            return request;
+        }
+        let content = MessageContent::Text(partial_text.to_string());
+        match request.messages.last_mut() {
+            Some(message) if message.role == Role::Assistant => message.content.push(content),
+            _ => request.messages.push(LanguageModelRequestMessage {
+                role: Role::Assistant,
+                content: vec![content],
+                cache: false,
+            }),
+        }
+        request
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
@@ -403,6 +1182,316 @@ pub struct LanguageModelResponseMessage {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_continuation() {
+        let request = LanguageModelRequest {
+            messages: vec![LanguageModelRequestMessage {
+                role: Role::User,
+                content: vec![MessageContent::Text("Write a poem".into())],
+                cache: true,
+            }],
+            ..Default::default()
+        };
+
+        let continuation = request.continuation("Roses are red,\n");
+        assert_eq!(continuation.messages.len(), 2);
+        assert_eq!(continuation.messages[0], request.messages[0]);
+        assert_eq!(continuation.messages[1].role, Role::Assistant);
+        assert_eq!(continuation.messages[1].string_contents(), "Roses are red,");
+
+        let continued_again = continuation.continuation(" violets are blue");
+        assert_eq!(continued_again.messages.len(), 2);
+        assert_eq!(
+            continued_again.messages[1].string_contents(),
+            "Roses are red, violets are blue"
+        );
+
+        assert_eq!(request.continuation("  \n"), request);
+    }
+
+    #[test]
+    fn test_fit_image_to_limits() {
+        let mut seed = 1u32;
+        let noise = image::RgbImage::from_fn(2000, 1000, |_, _| {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            let [r, g, b, _] = seed.to_be_bytes();
+            image::Rgb([r, g, b])
+        });
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(noise)
+            .write_with_encoder(PngEncoder::new(&mut png))
+            .unwrap();
+        let image = LanguageModelImage {
+            source: base64::engine::general_purpose::STANDARD.encode(png).into(),
+            size: size(DevicePixels(2000), DevicePixels(1000)),
+        };
+
+        let limits = ImageLimits {
+            max_dimension: 1000,
+            max_bytes: 200_000,
+            ..ImageLimits::default()
+        };
+        let fitted = image.fit_to_limits(limits, 85).unwrap();
+        assert_eq!(fitted.mime_type(), "image/jpeg");
+        assert!(fitted.byte_len() <= limits.max_bytes);
+        assert!(fitted.size.width.0 <= 1000);
+        assert_eq!(fitted.size.width.0, fitted.size.height.0 * 2);
+
+        // Images that already fit are left alone.
+        assert_eq!(fitted.fit_to_limits(limits, 85).unwrap(), fitted);
+    }
+
+    #[test]
+    fn test_convert_image_format() {
+        let mut bmp = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(40, 20))
+            .write_to(&mut Cursor::new(&mut bmp), image::ImageFormat::Bmp)
+            .unwrap();
+        let image = LanguageModelImage {
+            source: base64::engine::general_purpose::STANDARD.encode(bmp).into(),
+            size: size(DevicePixels(40), DevicePixels(20)),
+        };
+        assert_eq!(image.mime_type(), "image/bmp");
+
+        let converted = image.fit_to_limits(ImageLimits::default(), 85).unwrap();
+        assert_eq!(converted.mime_type(), "image/png");
+        assert_eq!(converted.size, image.size);
+
+        let jpeg_only = ImageLimits {
+            formats: &[ImageFormat::Jpeg],
+            ..ImageLimits::default()
+        };
+        let converted = converted.fit_to_limits(jpeg_only, 85).unwrap();
+        assert_eq!(converted.mime_type(), "image/jpeg");
+
+        let heic = LanguageModelImage {
+            source: base64::engine::general_purpose::STANDARD
+                .encode(b"\0\0\0\x18ftypheic\0\0\0\0")
+                .into(),
+            size: size(DevicePixels(40), DevicePixels(20)),
+        };
+        let mut request = LanguageModelRequest {
+            messages: vec![LanguageModelRequestMessage {
+                role: Role::User,
+                content: vec![MessageContent::Image(heic)],
+                cache: false,
+            }],
+            ..Default::default()
+        };
+        request.fit_images(ImageLimits::default(), 85);
+        assert_eq!(
+            request.messages[0].string_contents(),
+            "[Image omitted because it couldn't be converted from image/heic]"
+        );
+    }
+
+    #[test]
+    fn test_limit_images() {
+        let image = |bytes: usize| {
+            MessageContent::Image(LanguageModelImage {
+                source: "A".repeat(bytes / 3 * 4).into(),
+                size: size(DevicePixels(10), DevicePixels(10)),
+            })
+        };
+        let message = |role: Role, content: Vec<MessageContent>| LanguageModelRequestMessage {
+            role,
+            content,
+            cache: false,
+        };
+        let limits = ImageLimits {
+            max_images: Some(2),
+            max_total_bytes: Some(900),
+            ..ImageLimits::default()
+        };
+
+        let mut request = LanguageModelRequest {
+            messages: vec![
+                message(Role::User, vec![image(300), image(300)]),
+                message(Role::Assistant, vec!["Two screenshots.".into()]),
+                message(Role::User, vec![image(300)]),
+            ],
+            ..Default::default()
+        };
+        request.limit_images(limits).unwrap();
+        assert_eq!(
+            request.messages[0].content[0],
+            MessageContent::Text(
+                "[Image omitted because the conversation has more images than this model takes]"
+                    .into()
+            )
+        );
+        assert!(matches!(
+            request.messages[0].content[1],
+            MessageContent::Image(_)
+        ));
+        assert!(matches!(
+            request.messages[2].content[0],
+            MessageContent::Image(_)
+        ));
+
+        let mut request = LanguageModelRequest {
+            messages: vec![message(
+                Role::User,
+                vec![image(300), image(300), image(300)],
+            )],
+            ..Default::default()
+        };
+        assert!(matches!(
+            request.limit_images(limits),
+            Err(LanguageModelKnownError::TooManyImages { count: 3, limit: 2 })
+        ));
+
+        let mut request = LanguageModelRequest {
+            messages: vec![message(Role::User, vec![image(600), image(600)])],
+            ..Default::default()
+        };
+        assert!(matches!(
+            request.limit_images(limits),
+            Err(LanguageModelKnownError::ImagesTooLarge {
+                bytes: 1200,
+                limit: 900
+            })
+        ));
+    }
+
+    #[test]
+    fn test_check_pdfs() {
+        let bytes = b"%PDF-1.7\n1 0 obj << /Type /Pages /Kids [2 0 R 3 0 R] >> endobj\n\
+            2 0 obj << /Type /Page >> endobj\n3 0 obj << /Type/Page >> endobj\n%%EOF";
+        let pdf = LanguageModelPdf::from_bytes("spec.pdf".into(), bytes).unwrap();
+        assert_eq!(pdf.page_count, 2);
+        assert!(LanguageModelPdf::from_bytes("notes.txt".into(), b"notes").is_err());
+
+        let request = LanguageModelRequest {
+            messages: vec![LanguageModelRequestMessage {
+                role: Role::User,
+                content: vec![MessageContent::Pdf(pdf.clone())],
+                cache: false,
+            }],
+            ..Default::default()
+        };
+
+        let mut supported = request.clone();
+        supported.check_pdfs(Some(DocumentLimits::default()));
+        assert_eq!(supported, request);
+
+        let mut too_long = request.clone();
+        too_long.check_pdfs(Some(DocumentLimits {
+            max_bytes: 1024,
+            max_pages: 1,
+        }));
+        assert_eq!(
+            too_long.messages[0].string_contents(),
+            "[PDF omitted: spec.pdf has 2 pages, but at most 1 are supported]"
+        );
+
+        let mut unsupported = request.clone();
+        unsupported.check_pdfs(None);
+        assert_eq!(
+            unsupported.messages[0].string_contents(),
+            "[spec.pdf omitted because this model can't read PDFs]"
+        );
+    }
+
+    #[test]
+    fn test_tool_result_images() {
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(40, 20))
+            .write_with_encoder(PngEncoder::new(&mut png))
+            .unwrap();
+        let image =
+            LanguageModelImage::from_base64(&base64::engine::general_purpose::STANDARD.encode(png))
+                .unwrap();
+        assert_eq!(image.size, size(DevicePixels(40), DevicePixels(20)));
+        assert!(LanguageModelImage::from_base64("bm90IGFuIGltYWdl").is_err());
+
+        let request = LanguageModelRequest {
+            messages: vec![LanguageModelRequestMessage {
+                role: Role::User,
+                content: vec![MessageContent::ToolResult(LanguageModelToolResult {
+                    tool_use_id: LanguageModelToolUseId::from("screenshot-1"),
+                    tool_name: "screenshot".into(),
+                    is_error: false,
+                    content: LanguageModelToolResultContent::Image(image),
+                    output: None,
+                })],
+                cache: false,
+            }],
+            ..Default::default()
+        };
+
+        let mut supported = request.clone();
+        supported.check_tool_result_images(true);
+        assert_eq!(supported, request);
+
+        let mut unsupported = request.clone();
+        unsupported.check_tool_result_images(false);
+        let MessageContent::ToolResult(tool_result) = &unsupported.messages[0].content[0] else {
+            panic!("expected a tool result");
+        };
+        assert_eq!(
+            tool_result.content.to_str(),
+            Some("[Image omitted because this model can't see images]")
+        );
+    }
+
+    #[test]
+    fn test_audio_format() {
+        let wav = LanguageModelAudio::from_bytes(b"RIFF\0\0\0\0WAVEfmt ").unwrap();
+        assert_eq!(wav.format, AudioFormat::Wav);
+        let mp3 = LanguageModelAudio::from_bytes(b"ID3\x04\0\0").unwrap();
+        assert_eq!(mp3.format, AudioFormat::Mp3);
+        let mp3 = LanguageModelAudio::from_bytes(&[0xFF, 0xFB, 0x90, 0x44]).unwrap();
+        assert_eq!(mp3.format, AudioFormat::Mp3);
+        assert!(LanguageModelAudio::from_bytes(b"OggS").is_err());
+
+        let mut request = LanguageModelRequest {
+            messages: vec![LanguageModelRequestMessage {
+                role: Role::User,
+                content: vec![MessageContent::Audio(wav)],
+                cache: false,
+            }],
+            ..Default::default()
+        };
+        request.check_audio(false);
+        assert_eq!(
+            request.messages[0].string_contents(),
+            "[Audio omitted because this model can't hear it]"
+        );
+    }
+
+    #[test]
+    fn test_video_duration() {
+        let mut bytes = b"\0\0\0\x18ftypisom\0\0\0\0isommp41\0\0\0\x6cmoov\0\0\0\x64mvhd".to_vec();
+        bytes.extend_from_slice(&[0; 12]);
+        bytes.extend_from_slice(&1000u32.to_be_bytes());
+        bytes.extend_from_slice(&12_500u32.to_be_bytes());
+        let video = LanguageModelVideo::from_bytes("demo.mp4".into(), &bytes).unwrap();
+        assert_eq!(video.mime_type, "video/mp4");
+        assert_eq!(video.duration, Some(Duration::from_millis(12_500)));
+        assert_eq!(video.estimate_tokens(), 13 * VIDEO_TOKENS_PER_SECOND);
+
+        let webm =
+            LanguageModelVideo::from_bytes("demo.webm".into(), &[0x1A, 0x45, 0xDF, 0xA3]).unwrap();
+        assert_eq!(webm.mime_type, "video/webm");
+        assert_eq!(webm.duration, None);
+        assert!(LanguageModelVideo::from_bytes("demo.gif".into(), b"GIF89a").is_err());
+
+        let mut request = LanguageModelRequest {
+            messages: vec![LanguageModelRequestMessage {
+                role: Role::User,
+                content: vec![MessageContent::Video(video)],
+                cache: false,
+            }],
+            ..Default::default()
+        };
+        request.check_videos(false);
+        assert_eq!(
+            request.messages[0].string_contents(),
+            "[demo.mp4 omitted because this model can't watch videos]"
+        );
+    }
+
     #[test]
     fn test_language_model_tool_result_content_deserialization() {
         let json = r#""This is plain text""#;
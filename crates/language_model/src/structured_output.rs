@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result, anyhow};
+use futures::StreamExt as _;
+use gpui::AsyncApp;
+
+use crate::{
+    LanguageModel, LanguageModelRequest, LanguageModelRequestMessage, MessageContent,
+    ResponseSchema, Role, StructuredOutput,
+};
+
+/// How many responses are requested, in all, before giving up on one that conforms to the schema.
+const MAX_ATTEMPTS: usize = 3;
+
+/// Completes `request` with a JSON value that conforms to its
+/// [`response_schema`](LanguageModelRequest::response_schema), however much the model supports
+/// schemas.
+///
+/// Models that can't constrain their responses to the schema are given it in the prompt instead.
+/// Every response is validated against the schema, and responses that don't conform are sent back
+/// to the model with what's wrong with them, until it responds with one that does, or fails to
+/// [`MAX_ATTEMPTS`] times.
+pub async fn complete_structured(
+    model: &Arc<dyn LanguageModel>,
+    mut request: LanguageModelRequest,
+    cx: &AsyncApp,
+) -> Result<serde_json::Value> {
+    let response_schema = request
+        .response_schema
+        .clone()
+        .context("structured completions need a response schema")?;
+    let validator =
+        jsonschema::validator_for(&response_schema.schema).context("invalid response schema")?;
+
+    match model.structured_output() {
+        StructuredOutput::Native | StructuredOutput::ToolCall => {}
+        StructuredOutput::JsonObject => {
+            push_user_text(&mut request, schema_prompt(&response_schema)?);
+        }
+        StructuredOutput::Unsupported => {
+            request.response_schema = None;
+            push_user_text(&mut request, schema_prompt(&response_schema)?);
+        }
+    }
+
+    let mut problems = Vec::new();
+    for _ in 0..MAX_ATTEMPTS {
+        let response = complete_text(model, request.clone(), cx).await?;
+        problems = match parse_response(&response) {
+            Ok(value) => {
+                let problems = validator
+                    .iter_errors(&value)
+                    .map(|error| match error.instance_path.as_str() {
+                        "" => error.to_string(),
+                        path => format!("at {path}: {error}"),
+                    })
+                    .collect::<Vec<_>>();
+                if problems.is_empty() {
+                    return Ok(value);
+                }
+                problems
+            }
+            Err(error) => vec![format!("it isn't valid JSON: {error}")],
+        };
+
+        request.messages.push(LanguageModelRequestMessage {
+            role: Role::Assistant,
+            content: vec![MessageContent::Text(response)],
+            cache: false,
+        });
+        push_user_text(
+            &mut request,
+            format!(
+                "That response doesn't conform to the schema:\n\n{}\n\n\
+                 Respond again with only the corrected JSON.",
+                bullet_list(&problems)
+            ),
+        );
+    }
+    Err(anyhow!(
+        "the model's response didn't conform to the {} schema after {MAX_ATTEMPTS} attempts:\n{}",
+        response_schema.name,
+        bullet_list(&problems)
+    ))
+}
+
+async fn complete_text(
+    model: &Arc<dyn LanguageModel>,
+    request: LanguageModelRequest,
+    cx: &AsyncApp,
+) -> Result<String> {
+    let mut stream = model.stream_completion_text(request, cx).await?.stream;
+    let mut text = String::new();
+    while let Some(chunk) = stream.next().await {
+        text.push_str(&chunk?);
+    }
+    Ok(text)
+}
+
+fn schema_prompt(response_schema: &ResponseSchema) -> Result<String> {
+    let mut prompt = format!(
+        "Respond with only a JSON value, without any other text, that conforms to this JSON \
+         schema for a `{}`",
+        response_schema.name
+    );
+    if let Some(description) = &response_schema.description {
+        prompt.push_str(&format!(" ({description})"));
+    }
+    prompt.push_str(&format!(
+        ":\n\n```json\n{}\n```",
+        serde_json::to_string_pretty(&response_schema.schema)?
+    ));
+    Ok(prompt)
+}
+
+/// Parses a response as JSON, without the Markdown code fence that models often put around it
+/// when they aren't constrained to JSON.
+fn parse_response(response: &str) -> serde_json::Result<serde_json::Value> {
+    let mut json = response.trim();
+    if let Some(fenced) = json.strip_prefix("```") {
+        if let Some((_, rest)) = fenced.split_once('\n') {
+            json = rest.trim_end().strip_suffix("```").unwrap_or(rest);
+        }
+    }
+    serde_json::from_str(json)
+}
+
+/// Adds text to the last message of the request when it's from the user, so that the request
+/// doesn't have two user messages in a row, which some providers reject.
+fn push_user_text(request: &mut LanguageModelRequest, text: String) {
+    match request.messages.last_mut() {
+        Some(message) if message.role == Role::User => {
+            message.content.push(MessageContent::Text(text));
+        }
+        _ => request.messages.push(LanguageModelRequestMessage {
+            role: Role::User,
+            content: vec![MessageContent::Text(text)],
+            cache: false,
+        }),
+    }
+}
+
+fn bullet_list(items: &[String]) -> String {
+    items
+        .iter()
+        .map(|item| format!("- {item}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use gpui::TestAppContext;
+    use serde_json::json;
+
+    use super::*;
+    use crate::fake_provider::FakeLanguageModel;
+
+    #[test]
+    fn test_parse_response() {
+        assert_eq!(
+            parse_response(" {\"name\": \"red\"}\n").unwrap(),
+            json!({"name": "red"})
+        );
+        assert_eq!(
+            parse_response("```json\n{\"name\": \"red\"}\n```").unwrap(),
+            json!({"name": "red"})
+        );
+        assert!(parse_response("The color is red.").is_err());
+    }
+
+    #[gpui::test]
+    async fn test_complete_structured(cx: &mut TestAppContext) {
+        let fake_model = Arc::new(FakeLanguageModel::default());
+        let model: Arc<dyn LanguageModel> = fake_model.clone();
+        let request = LanguageModelRequest {
+            messages: vec![LanguageModelRequestMessage {
+                role: Role::User,
+                content: vec!["Name a color.".into()],
+                cache: false,
+            }],
+            response_schema: Some(ResponseSchema {
+                name: "color".into(),
+                description: None,
+                schema: json!({
+                    "type": "object",
+                    "properties": {"name": {"type": "string"}},
+                    "required": ["name"],
+                }),
+            }),
+            ..Default::default()
+        };
+        let task = cx.spawn(|cx| async move { complete_structured(&model, request, &cx).await });
+
+        // The fake model can't constrain its responses, so it's given the schema in the prompt.
+        cx.run_until_parked();
+        let first_request = fake_model.pending_completions().pop().unwrap();
+        assert_eq!(first_request.response_schema, None);
+        assert_eq!(first_request.messages.len(), 1);
+        assert!(
+            first_request.messages[0]
+                .string_contents()
+                .contains("\"required\"")
+        );
+        fake_model.stream_last_completion_response("{\"color\": \"red\"}");
+        fake_model.end_last_completion_stream();
+
+        cx.run_until_parked();
+        let second_request = fake_model.pending_completions().pop().unwrap();
+        assert_eq!(second_request.messages.len(), 3);
+        assert!(
+            second_request.messages[2]
+                .string_contents()
+                .contains("\"name\" is a required property")
+        );
+        fake_model.stream_last_completion_response("```json\n{\"name\": \"red\"}\n```");
+        fake_model.end_last_completion_stream();
+
+        assert_eq!(task.await.unwrap(), json!({"name": "red"}));
+    }
+}
@@ -0,0 +1,1638 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use anthropic::{AnthropicError, ApiErrorCode};
+use anyhow::{Context as _, Result};
+use collections::HashMap;
+use credentials_provider::CredentialsProvider;
+use fs::Fs;
+use futures::channel::mpsc;
+use futures::future::{self, BoxFuture, Shared};
+use futures::{AsyncReadExt as _, FutureExt as _, StreamExt as _};
+use gpui::{App, AsyncApp, Global, Task, WindowId};
+use http_client::{AsyncBody, HttpClient, Method, StatusCode, http};
+pub use language_model::WorkspaceScope;
+use language_model::{AuthenticateError, LanguageModelCompletionError};
+use parking_lot::Mutex;
+use project::Project;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use ui::{Checkbox, ToggleState, Tooltip, prelude::*};
+
+use crate::ProviderApiSettings;
+use crate::key_usage::{KeyUsageRecorder, render_key_usage};
+use crate::oauth::{self, OAuthToken};
+
+/// How a provider with several API keys chooses which one to use for a request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiKeyRotation {
+    /// Keep using the same key until it gets rate limited or runs out of quota, then move on to
+    /// the next one.
+    #[default]
+    Failover,
+    /// Use the next key for every request, spreading the load evenly across all keys.
+    RoundRobin,
+}
+
+/// The API keys configured for a provider.
+///
+/// Several keys can be stored at once by separating them with newlines or commas, both in the
+/// credentials store and in environment variables. Clones share the same position in the
+/// rotation, so in-flight requests can rotate keys without access to the provider's state.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeys {
+    keys: Arc<[String]>,
+    current: Arc<AtomicUsize>,
+    /// When short-lived keys, such as OAuth or STS tokens, expire, in seconds since the Unix
+    /// epoch.
+    expires_at: Option<u64>,
+    /// Where to report whether the provider accepted the keys, see [`ApiKeyStatus`].
+    status: Option<mpsc::UnboundedSender<bool>>,
+}
+
+/// What `api_key_command` can print instead of a bare key, so that short-lived keys are renewed
+/// before they expire.
+#[derive(Deserialize)]
+struct ApiKeyCommandOutput {
+    #[serde(alias = "token", alias = "access_token")]
+    api_key: String,
+    /// Seconds since the Unix epoch.
+    expires_at: Option<u64>,
+    /// Seconds from now.
+    expires_in: Option<u64>,
+}
+
+impl ApiKeys {
+    pub fn parse(text: &str) -> Self {
+        Self {
+            keys: text
+                .split(['\n', ','])
+                .map(str::trim)
+                .filter(|key| !key.is_empty())
+                .map(ToString::to_string)
+                .collect(),
+            current: Arc::default(),
+            expires_at: None,
+            status: None,
+        }
+    }
+
+    /// Parses what `api_key_command` printed, which is either the keys themselves or a JSON
+    /// object like `{"api_key": "...", "expires_in": 3600}`.
+    pub fn from_command_output(output: &str) -> Self {
+        match serde_json::from_str::<ApiKeyCommandOutput>(output) {
+            Ok(output) => Self::parse(&output.api_key).with_expiry(
+                output
+                    .expires_at
+                    .or_else(|| Some(oauth::unix_now() + output.expires_in?)),
+            ),
+            Err(_) => Self::parse(output),
+        }
+    }
+
+    /// Parses keys read from the credentials store, where they are saved either as plain text
+    /// or, for tokens that expire, as an OAuth token.
+    fn from_stored_credentials(username: &str, credentials: Vec<u8>) -> Result<Self> {
+        if username == oauth::OAUTH_CREDENTIALS_USERNAME {
+            let token: OAuthToken =
+                serde_json::from_slice(&credentials).context("invalid stored OAuth token")?;
+            return Ok(Self::parse(&token.access_token).with_expiry(token.expires_at));
+        }
+        let api_key = String::from_utf8(credentials).context("invalid API key")?;
+        Ok(Self::parse(&api_key))
+    }
+
+    pub fn with_expiry(mut self, expires_at: Option<u64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    /// Whether the keys have expired or are about to.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.map_or(false, oauth::is_expiring)
+    }
+
+    /// How long until the keys should be renewed, if they expire.
+    pub fn refresh_in(&self) -> Option<Duration> {
+        self.expires_at.map(oauth::refresh_delay)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(String::as_str)
+    }
+
+    /// Returns the keys in the format they are persisted in.
+    pub fn to_credentials(&self) -> String {
+        self.keys.join("\n")
+    }
+
+    /// The key in use, with all but its last four characters hidden.
+    pub fn masked(&self) -> Option<String> {
+        let masked = mask_api_key(self.current()?);
+        if self.len() > 1 {
+            let index = self.current.load(Ordering::Relaxed) % self.len();
+            Some(format!("{masked} (key {} of {})", index + 1, self.len()))
+        } else {
+            Some(masked)
+        }
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.keys
+            .get(self.current.load(Ordering::Relaxed) % self.keys.len().max(1))
+            .map(String::as_str)
+    }
+
+    /// Returns the key to use for the next request.
+    pub fn next_for_request(&self, rotation: ApiKeyRotation) -> Option<String> {
+        if self.keys.is_empty() {
+            return None;
+        }
+        let index = match rotation {
+            ApiKeyRotation::Failover => self.current.load(Ordering::Relaxed),
+            ApiKeyRotation::RoundRobin => self.current.fetch_add(1, Ordering::Relaxed),
+        };
+        Some(self.keys[index % self.keys.len()].clone())
+    }
+
+    /// Moves past `key` after it was rate limited, returning whether there is another key left
+    /// to try.
+    ///
+    /// Several in-flight requests can fail with the same key, so this only rotates if `key` is
+    /// still the current one.
+    pub fn rotate_after_failure(&self, key: &str) -> bool {
+        if self.keys.len() < 2 {
+            return false;
+        }
+        let index = self.current.load(Ordering::Relaxed);
+        if self.keys[index % self.keys.len()] == key {
+            self.current
+                .compare_exchange(index, index + 1, Ordering::Relaxed, Ordering::Relaxed)
+                .ok();
+        }
+        true
+    }
+
+    fn reporting_to(mut self, status: mpsc::UnboundedSender<bool>) -> Self {
+        self.status = Some(status);
+        self
+    }
+
+    fn report_rejected(&self, rejected: bool) {
+        if let Some(status) = &self.status {
+            if let Err(error) = status.unbounded_send(rejected) {
+                log::debug!("the provider's API key status is no longer observed: {error}");
+            }
+        }
+    }
+
+    /// Records that the provider accepted a request, which clears a rejection once requests go
+    /// through with a working key again.
+    pub fn record_success(&self) {
+        self.report_rejected(false);
+    }
+
+    /// Handles a request made with `key` failing with `error`. Rate limited keys are rotated
+    /// away from, and rejected ones are also reported, so that the provider asks for a new key
+    /// instead of failing every request.
+    pub fn record_error(&self, key: &str, error: &anyhow::Error) {
+        self.rotate_after_error(key, error);
+    }
+
+    /// Handles a request failing like [`Self::record_error`], and returns whether to send it
+    /// again with the key that replaced `key`. `attempt` counts the retries so far, so that a
+    /// request tries each key at most once.
+    pub fn retry_after_error(&self, key: &str, error: &anyhow::Error, attempt: usize) -> bool {
+        self.rotate_after_error(key, error) && attempt + 1 < self.len()
+    }
+
+    /// Sends a request with `send`, which is given the key to send it with, recording the key in
+    /// `usage`. A request that fails is sent again with the next key for as long as
+    /// [`Self::retry_after_error`] allows.
+    pub(crate) async fn with_rotation<T, F>(
+        &self,
+        rotation: ApiKeyRotation,
+        provider_name: &str,
+        usage: &KeyUsageRecorder,
+        mut send: impl FnMut(String) -> F,
+    ) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let api_key = self
+                .next_for_request(rotation)
+                .with_context(|| format!("Missing {provider_name} API Key"))?;
+            usage.set_api_key(&api_key);
+            let response = send(api_key.clone()).await;
+            if response.is_ok() {
+                self.record_success();
+            }
+            match response {
+                Err(error) if self.retry_after_error(&api_key, &error, attempt) => {
+                    attempt += 1;
+                }
+                response => return response,
+            }
+        }
+    }
+
+    fn rotate_after_error(&self, key: &str, error: &anyhow::Error) -> bool {
+        if is_rate_limit_error(error) {
+            let rotated = self.rotate_after_failure(key);
+            if rotated {
+                log::info!("API key was rate limited, rotating to the next key");
+            }
+            rotated
+        } else if is_unauthorized_error(error) {
+            self.report_rejected(true);
+            let rotated = self.rotate_after_failure(key);
+            if rotated {
+                log::warn!("API key was rejected, rotating to the next key");
+            } else {
+                log::warn!("API key was rejected");
+            }
+            rotated
+        } else {
+            false
+        }
+    }
+}
+
+/// Whether the provider rejected the API keys it was last sent, e.g. because they were revoked.
+///
+/// Kept on the provider's state, so that views showing it are notified when it changes. Requests
+/// in flight report to it through the [`ApiKeys`] returned by [`Self::track`].
+pub struct ApiKeyStatus {
+    rejected: bool,
+    updates: mpsc::UnboundedSender<bool>,
+    _task: Task<()>,
+}
+
+impl ApiKeyStatus {
+    pub fn new<T: 'static>(cx: &mut Context<T>, status: fn(&mut T) -> &mut Self) -> Self {
+        let (updates, mut rx) = mpsc::unbounded();
+        let _task = cx.spawn(async move |this, cx| {
+            while let Some(rejected) = rx.next().await {
+                let updated = this.update(cx, |this, cx| status(this).set_rejected(rejected, cx));
+                if updated.is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            rejected: false,
+            updates,
+            _task,
+        }
+    }
+
+    pub fn is_rejected(&self) -> bool {
+        self.rejected
+    }
+
+    pub fn set_rejected<T>(&mut self, rejected: bool, cx: &mut Context<T>) {
+        if self.rejected != rejected {
+            self.rejected = rejected;
+            cx.notify();
+        }
+    }
+
+    /// Returns `api_keys` reporting to this status when requests made with them are accepted or
+    /// rejected.
+    pub fn track(&self, api_keys: &ApiKeys) -> ApiKeys {
+        api_keys.clone().reporting_to(self.updates.clone())
+    }
+}
+
+/// Hides all but the last four characters of `api_key`, or all of it if it's too short for that
+/// to be safe.
+pub fn mask_api_key(api_key: &str) -> String {
+    let chars = api_key.chars().collect::<Vec<_>>();
+    let hint = if chars.len() > 8 {
+        chars[chars.len() - 4..].iter().collect()
+    } else {
+        String::new()
+    };
+    format!("••••{hint}")
+}
+
+/// Identifies `project` by its first folder, so that API keys saved for it are found again when
+/// the folder is reopened.
+pub fn workspace_scope(project: &Project, cx: &App) -> Option<WorkspaceScope> {
+    let worktree = project.visible_worktrees(cx).next()?;
+    let worktree = worktree.read(cx);
+    let path = worktree.abs_path().to_string_lossy().into_owned();
+    // Model requests are always made from this machine, but a remote project's path can also
+    // exist on other hosts, so keep keys saved for it separate per host.
+    let id = match project.ssh_connection_string(cx) {
+        Some(connection) => format!("ssh://{connection}{path}"),
+        None => path,
+    };
+    Some(WorkspaceScope {
+        name: worktree.root_name().to_string().into(),
+        id,
+    })
+}
+
+/// The workspace open in each window, so that a key entered in a window's configuration view can
+/// be saved for the workspace it belongs to.
+#[derive(Default)]
+struct WindowWorkspaceScopes(HashMap<WindowId, WorkspaceScope>);
+
+impl Global for WindowWorkspaceScopes {}
+
+/// Records which workspace is open in `window`, or that none is, e.g. once the window closed.
+pub fn set_window_workspace_scope(
+    window: WindowId,
+    workspace: Option<WorkspaceScope>,
+    cx: &mut App,
+) {
+    let scopes = &mut cx.default_global::<WindowWorkspaceScopes>().0;
+    match workspace {
+        Some(workspace) => {
+            scopes.insert(window, workspace);
+        }
+        None => {
+            scopes.remove(&window);
+        }
+    }
+}
+
+fn open_workspace_scopes(cx: &App) -> Vec<WorkspaceScope> {
+    cx.try_global::<WindowWorkspaceScopes>()
+        .map(|scopes| scopes.0.values().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Which of the saved API keys are in use: those in the selected credentials profile.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CredentialsScope {
+    /// A named set of keys, e.g. "work" or "personal", selected with `credentials_profile`.
+    pub profile: Option<SharedString>,
+}
+
+impl Global for CredentialsScope {}
+
+impl CredentialsScope {
+    pub fn global(cx: &App) -> Self {
+        cx.try_global::<Self>().cloned().unwrap_or_default()
+    }
+
+    /// Switches to another credentials profile, which makes providers reload keys from the
+    /// credentials store.
+    pub fn set_profile(profile: Option<SharedString>, cx: &mut App) {
+        let scope = Self::global(cx);
+        if scope.profile != profile {
+            cx.set_global(Self { profile });
+        }
+    }
+
+    /// The URL that keys saved for every workspace are stored under in the credentials store.
+    ///
+    /// Keys are namespaced by provider as well as by `api_url`, so that providers talking to the
+    /// same endpoint don't overwrite each other's keys.
+    fn credentials_url(&self, provider_id: &str, api_url: &str) -> String {
+        self.with_profile(format!("{api_url}#provider={provider_id}"))
+    }
+
+    /// Where keys were stored before they were namespaced by provider.
+    fn legacy_credentials_url(&self, api_url: &str) -> String {
+        self.with_profile(api_url.to_string())
+    }
+
+    fn with_profile(&self, url: String) -> String {
+        match &self.profile {
+            Some(profile) => format!("{url}#profile={profile}"),
+            None => url,
+        }
+    }
+
+    /// The URL that a key from `source` is saved under in the credentials store.
+    pub fn api_key_url(&self, source: &ApiKeySource, provider_id: &str, api_url: &str) -> String {
+        source.credentials_url(&self.credentials_url(provider_id, api_url))
+    }
+
+    /// The URL that a key from `source` was saved under before keys were namespaced by provider,
+    /// which needs to be cleared too when the key is reset so that it isn't migrated again.
+    pub fn legacy_api_key_url(&self, source: &ApiKeySource, api_url: &str) -> String {
+        source.credentials_url(&self.legacy_credentials_url(api_url))
+    }
+}
+
+/// Where a provider's API keys were loaded from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ApiKeySource {
+    /// The key was entered in Zed and saved in the system's credentials store.
+    #[default]
+    CredentialsStore,
+    /// The key was entered in Zed and saved for a single workspace.
+    WorkspaceCredentialsStore(WorkspaceScope),
+    EnvVar(String),
+    Command,
+    File(PathBuf),
+}
+
+impl ApiKeySource {
+    /// Whether the key is managed outside of Zed, in which case it can't be reset from the UI.
+    pub fn is_external(&self) -> bool {
+        !matches!(
+            self,
+            Self::CredentialsStore | Self::WorkspaceCredentialsStore(_)
+        )
+    }
+
+    /// The URL that the key is saved under in the credentials store, given the URL for keys
+    /// saved for every workspace.
+    pub fn credentials_url(&self, api_url: &str) -> String {
+        match self {
+            Self::WorkspaceCredentialsStore(scope) => format!("{api_url}#workspace={}", scope.id),
+            _ => api_url.to_string(),
+        }
+    }
+
+    /// The workspace that the key was saved for, if it was saved for a single one.
+    pub fn workspace(&self) -> Option<&WorkspaceScope> {
+        match self {
+            Self::WorkspaceCredentialsStore(workspace) => Some(workspace),
+            _ => None,
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            Self::CredentialsStore => "API key configured.".to_string(),
+            Self::WorkspaceCredentialsStore(scope) => {
+                format!("API key configured for the {} workspace.", scope.name)
+            }
+            Self::EnvVar(env_var) => format!("API key set in {env_var} environment variable."),
+            Self::Command => "API key provided by `api_key_command`.".to_string(),
+            Self::File(path) => format!("API key read from {}.", path.display()),
+        }
+    }
+
+    pub fn reset_hint(&self) -> String {
+        match self {
+            Self::CredentialsStore => "Remove the API key saved in Zed.".to_string(),
+            Self::WorkspaceCredentialsStore(scope) => {
+                format!("Remove the API key saved for the {} workspace.", scope.name)
+            }
+            Self::EnvVar(env_var) => {
+                format!("To reset your API key, unset the {env_var} environment variable.")
+            }
+            Self::Command => {
+                "To reset your API key, remove `api_key_command` from your settings.".to_string()
+            }
+            Self::File(_) => {
+                "To reset your API key, remove `api_key_file` from your settings.".to_string()
+            }
+        }
+    }
+}
+
+/// Loads a provider's API keys, looking at the environment variable first (`default_env_var`
+/// unless `api_key_env_var` names another one), then at the configured `api_key_command` and
+/// `api_key_file`, and finally at the system's credentials store. Keys saved for a single
+/// workspace are loaded separately, by [`WorkspaceApiKeys`].
+pub async fn load_api_keys(
+    default_env_var: &str,
+    settings: &ProviderApiSettings,
+    credentials_provider: Arc<dyn CredentialsProvider>,
+    provider_id: &str,
+    api_url: &str,
+    scope: CredentialsScope,
+    cx: &AsyncApp,
+) -> Result<(ApiKeys, ApiKeySource), AuthenticateError> {
+    let env_var = settings.env_var(default_env_var);
+    if let Ok(api_key) = std::env::var(env_var) {
+        return Ok((
+            ApiKeys::parse(&api_key),
+            ApiKeySource::EnvVar(env_var.to_string()),
+        ));
+    }
+
+    if let Some(command) = settings.api_key_command.as_ref() {
+        let output = run_api_key_command(command).await?;
+        return Ok((ApiKeys::from_command_output(&output), ApiKeySource::Command));
+    }
+
+    if let Some(path) = settings.api_key_file.as_ref() {
+        let path = expand_home_dir(path);
+        let api_key = smol::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("failed to read `api_key_file` {}", path.display()))?;
+        return Ok((ApiKeys::parse(&api_key), ApiKeySource::File(path)));
+    }
+
+    let source = ApiKeySource::CredentialsStore;
+    let api_keys = read_stored_api_keys(
+        credentials_provider.as_ref(),
+        &scope.api_key_url(&source, provider_id, api_url),
+        &scope.legacy_api_key_url(&source, api_url),
+        cx,
+    )
+    .await?
+    .ok_or(AuthenticateError::CredentialsNotFound)?;
+    if api_keys.is_expired() {
+        return Err(anyhow::anyhow!("the saved token has expired").into());
+    }
+    Ok((api_keys, source))
+}
+
+/// The keys of a service that isn't a language model provider, like a text-to-speech service,
+/// which are loaded from its environment variable or from the credentials store the first time
+/// they're needed, and again after the credentials profile changes.
+#[derive(Clone)]
+pub(crate) struct ServiceApiKeys {
+    env_var: &'static str,
+    service_id: &'static str,
+    api_url: &'static str,
+    loaded: Arc<Mutex<Option<LoadedServiceApiKeys>>>,
+}
+
+type LoadedServiceApiKeys = (
+    CredentialsScope,
+    Shared<Task<Result<ApiKeys, Arc<anyhow::Error>>>>,
+);
+
+impl ServiceApiKeys {
+    pub(crate) fn new(
+        env_var: &'static str,
+        service_id: &'static str,
+        api_url: &'static str,
+    ) -> Self {
+        Self {
+            env_var,
+            service_id,
+            api_url,
+            loaded: Arc::default(),
+        }
+    }
+
+    /// The service's keys. Failing to load them isn't remembered, so that keys that are saved
+    /// afterwards are picked up by the next request.
+    pub(crate) fn load(&self, cx: &AsyncApp) -> BoxFuture<'static, Result<ApiKeys>> {
+        let api_keys = cx.update(|cx| {
+            let scope = CredentialsScope::global(cx);
+            let mut loaded = self.loaded.lock();
+            if let Some((loaded_scope, api_keys)) = loaded.as_ref() {
+                if *loaded_scope == scope {
+                    return api_keys.clone();
+                }
+            }
+            let (env_var, service_id, api_url) = (self.env_var, self.service_id, self.api_url);
+            let api_keys = cx
+                .spawn(async move |cx| {
+                    load_service_api_keys(env_var, service_id, api_url, cx)
+                        .await
+                        .map_err(Arc::new)
+                })
+                .shared();
+            *loaded = Some((scope, api_keys.clone()));
+            api_keys
+        });
+        let loaded = self.loaded.clone();
+        async move {
+            let api_keys = api_keys?;
+            match api_keys.clone().await {
+                Ok(api_keys) => Ok(api_keys),
+                Err(error) => {
+                    let mut loaded = loaded.lock();
+                    if loaded
+                        .as_ref()
+                        .is_some_and(|(_, loading)| loading.ptr_eq(&api_keys))
+                    {
+                        *loaded = None;
+                    }
+                    Err(anyhow::anyhow!("{error:#}"))
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+async fn load_service_api_keys(
+    env_var: &str,
+    service_id: &str,
+    api_url: &str,
+    cx: &AsyncApp,
+) -> Result<ApiKeys> {
+    let (credentials_provider, scope) = cx.update(|cx| {
+        (
+            <dyn CredentialsProvider>::global(cx),
+            CredentialsScope::global(cx),
+        )
+    })?;
+    let (api_keys, _) = load_api_keys(
+        env_var,
+        &ProviderApiSettings::default(),
+        credentials_provider,
+        service_id,
+        api_url,
+        scope,
+        cx,
+    )
+    .await
+    .map_err(|error| match error {
+        AuthenticateError::CredentialsNotFound => {
+            anyhow::anyhow!("no API key found for {service_id}, set {env_var}")
+        }
+        AuthenticateError::Other(error) => error,
+    })?;
+    Ok(api_keys)
+}
+
+/// Reads keys from the credentials store, migrating them from where they were saved before keys
+/// were namespaced by provider.
+///
+/// The old entry is left in place, since other providers with the same `api_url` may not have
+/// migrated it yet.
+async fn read_stored_api_keys(
+    credentials_provider: &dyn CredentialsProvider,
+    credentials_url: &str,
+    legacy_credentials_url: &str,
+    cx: &AsyncApp,
+) -> Result<Option<ApiKeys>> {
+    let (username, api_key) = if let Some(credentials) = credentials_provider
+        .read_credentials(credentials_url, cx)
+        .await?
+    {
+        credentials
+    } else if let Some((username, api_key)) = credentials_provider
+        .read_credentials(legacy_credentials_url, cx)
+        .await?
+    {
+        credentials_provider
+            .write_credentials(credentials_url, &username, &api_key, cx)
+            .await
+            .context("failed to migrate API key")?;
+        log::info!("migrated API key saved for {legacy_credentials_url}");
+        (username, api_key)
+    } else {
+        return Ok(None);
+    };
+    ApiKeys::from_stored_credentials(&username, api_key).map(Some)
+}
+
+/// The API keys saved for single workspaces, which requests made from such a workspace use
+/// instead of the provider's own. Each workspace's keys are read from the credentials store the
+/// first time they're needed, and then reused by every request made from it.
+#[derive(Clone, Default)]
+pub struct WorkspaceApiKeys(Arc<Mutex<HashMap<String, Shared<Task<Option<ApiKeys>>>>>>);
+
+impl WorkspaceApiKeys {
+    fn load(
+        &self,
+        workspace: &WorkspaceScope,
+        provider_id: &str,
+        api_url: &str,
+        cx: &App,
+    ) -> Shared<Task<Option<ApiKeys>>> {
+        let mut loaded = self.0.lock();
+        if let Some(api_keys) = loaded.get(&workspace.id) {
+            return api_keys.clone();
+        }
+        let credentials_provider = <dyn CredentialsProvider>::global(cx);
+        let scope = CredentialsScope::global(cx);
+        let source = ApiKeySource::WorkspaceCredentialsStore(workspace.clone());
+        let credentials_url = scope.api_key_url(&source, provider_id, api_url);
+        let legacy_credentials_url = scope.legacy_api_key_url(&source, api_url);
+        let api_keys = cx
+            .spawn(async move |cx| {
+                let api_keys = read_stored_api_keys(
+                    credentials_provider.as_ref(),
+                    &credentials_url,
+                    &legacy_credentials_url,
+                    cx,
+                )
+                .await;
+                match api_keys {
+                    Ok(api_keys) => api_keys.filter(|api_keys| !api_keys.is_expired()),
+                    Err(error) => {
+                        log::error!("failed to load the API keys saved for a workspace: {error:?}");
+                        None
+                    }
+                }
+            })
+            .shared();
+        loaded.insert(workspace.id.clone(), api_keys.clone());
+        api_keys
+    }
+
+    /// Loads the keys saved for every open workspace, resolving to whether any of them has some.
+    pub fn load_open_workspaces(
+        &self,
+        provider_id: &str,
+        api_url: &str,
+        cx: &App,
+    ) -> impl Future<Output = bool> + 'static {
+        let loads = open_workspace_scopes(cx)
+            .iter()
+            .map(|workspace| self.load(workspace, provider_id, api_url, cx))
+            .collect::<Vec<_>>();
+        async move { future::join_all(loads).await.iter().any(Option::is_some) }
+    }
+
+    /// The keys saved for `workspace`, if they were loaded already.
+    pub fn get(&self, workspace: &WorkspaceScope) -> Option<ApiKeys> {
+        self.0.lock().get(&workspace.id)?.peek().cloned().flatten()
+    }
+
+    /// Whether any workspace that was loaded has keys saved, so that the provider can be used
+    /// from it even without keys of its own.
+    pub fn has_any(&self) -> bool {
+        self.0
+            .lock()
+            .values()
+            .any(|api_keys| matches!(api_keys.peek(), Some(Some(_))))
+    }
+
+    /// Records that `api_keys` were saved for `workspace`, or that its keys were removed.
+    pub fn set(&self, workspace: &WorkspaceScope, api_keys: Option<ApiKeys>) {
+        self.0
+            .lock()
+            .insert(workspace.id.clone(), Task::ready(api_keys).shared());
+    }
+
+    /// Forgets every workspace's keys, e.g. once the profile or `api_url` they were saved for
+    /// changed.
+    pub fn clear(&self) {
+        self.0.lock().clear();
+    }
+
+    /// Deletes the keys saved for `workspace`.
+    pub fn reset(
+        &self,
+        workspace: &WorkspaceScope,
+        provider_id: &str,
+        api_url: &str,
+        cx: &App,
+    ) -> Task<Result<()>> {
+        let credentials_provider = <dyn CredentialsProvider>::global(cx);
+        let scope = CredentialsScope::global(cx);
+        let source = ApiKeySource::WorkspaceCredentialsStore(workspace.clone());
+        let credentials_urls = [
+            scope.api_key_url(&source, provider_id, api_url),
+            scope.legacy_api_key_url(&source, api_url),
+        ];
+        self.set(workspace, None);
+        cx.spawn(async move |cx| {
+            for credentials_url in &credentials_urls {
+                credentials_provider
+                    .delete_credentials(credentials_url, cx)
+                    .await?;
+            }
+            Ok(())
+        })
+    }
+
+    /// The keys to make a request from `workspace` with: the ones saved for it, if there are
+    /// any, or otherwise the provider's own `api_keys`. Keys configured outside of Zed, like in
+    /// an environment variable, are always used as they were before keys could be saved per
+    /// workspace.
+    pub fn for_request(
+        &self,
+        api_keys: &ApiKeys,
+        status: &ApiKeyStatus,
+        source: &ApiKeySource,
+        workspace: Option<&WorkspaceScope>,
+        provider_id: &str,
+        api_url: &str,
+        cx: &App,
+    ) -> BoxFuture<'static, ApiKeys> {
+        let api_keys = status.track(api_keys);
+        let Some(workspace) = workspace.filter(|_| !source.is_external()) else {
+            return future::ready(api_keys).boxed();
+        };
+        let workspace_api_keys = self.load(workspace, provider_id, api_url, cx);
+        let updates = status.updates.clone();
+        async move {
+            match workspace_api_keys.await {
+                Some(workspace_api_keys) => workspace_api_keys.reporting_to(updates),
+                None => api_keys,
+            }
+        }
+        .boxed()
+    }
+
+    /// The keys that a configuration view opened from `workspace` shows, and where they're from.
+    pub fn for_display(
+        &self,
+        api_keys: &ApiKeys,
+        source: &ApiKeySource,
+        workspace: Option<&WorkspaceScope>,
+    ) -> (ApiKeys, ApiKeySource) {
+        let workspace_api_keys = workspace
+            .filter(|_| !source.is_external())
+            .and_then(|workspace| Some((workspace, self.get(workspace)?)));
+        match workspace_api_keys {
+            Some((workspace, api_keys)) => (
+                api_keys,
+                ApiKeySource::WorkspaceCredentialsStore(workspace.clone()),
+            ),
+            None => (api_keys.clone(), source.clone()),
+        }
+    }
+}
+
+/// Copies the key saved for a provider's previous `api_url` to its new one, so that changing the
+/// endpoint doesn't silently sign the provider out. A key that was already saved for the new
+/// `api_url` is kept.
+pub fn carry_over_api_key(
+    source: &ApiKeySource,
+    provider_id: &str,
+    previous_api_url: &str,
+    api_url: &str,
+    cx: &App,
+) -> Task<()> {
+    let credentials_provider = <dyn CredentialsProvider>::global(cx);
+    let scope = CredentialsScope::global(cx);
+    let previous_url = scope.api_key_url(source, provider_id, previous_api_url);
+    let url = scope.api_key_url(source, provider_id, api_url);
+    cx.spawn(async move |cx| {
+        let result = async {
+            if credentials_provider
+                .read_credentials(&url, cx)
+                .await?
+                .is_some()
+            {
+                return Ok(());
+            }
+            if let Some((username, api_key)) = credentials_provider
+                .read_credentials(&previous_url, cx)
+                .await?
+            {
+                credentials_provider
+                    .write_credentials(&url, &username, &api_key, cx)
+                    .await?;
+            }
+            anyhow::Ok(())
+        }
+        .await;
+        if let Err(error) = result {
+            log::error!("failed to carry API key over to {url}: {error:?}");
+        }
+    })
+}
+
+/// Runs the configured `api_key_command`, returning what it printed to stdout.
+pub async fn run_api_key_command(command: &[String]) -> Result<String> {
+    let (program, args) = command
+        .split_first()
+        .context("`api_key_command` must not be empty")?;
+    let output = util::command::new_smol_command(program)
+        .args(args)
+        .kill_on_drop(true)
+        .output()
+        .await
+        .with_context(|| format!("failed to run `api_key_command` {program:?}"))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "`api_key_command` exited with {}: {}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    let api_key = String::from_utf8(output.stdout)
+        .context("`api_key_command` printed invalid UTF-8")?
+        .trim()
+        .to_string();
+    anyhow::ensure!(!api_key.is_empty(), "`api_key_command` printed nothing");
+    Ok(api_key)
+}
+
+/// How long to wait before running `api_key_command` again after it failed to refresh a key.
+const COMMAND_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Keeps keys up to date in the background, so that they can be rotated without restarting Zed
+/// and short-lived tokens are renewed before requests start failing with them.
+///
+/// `api_key_command` is run again whenever its refresh interval elapses or the key it printed is
+/// about to expire, and `api_key_file` is reloaded whenever it changes. Tokens saved in the
+/// credentials store can't be renewed from here, so they are dropped once they expire, which makes
+/// the provider ask to authenticate again.
+///
+/// Returns `None` if the keys don't need refreshing.
+pub fn refresh_api_keys<T: 'static>(
+    source: &ApiKeySource,
+    api_keys: &ApiKeys,
+    api_settings: &ProviderApiSettings,
+    cx: &mut gpui::Context<T>,
+    set_api_keys: fn(&mut T, ApiKeys),
+) -> Option<Task<()>> {
+    match source {
+        ApiKeySource::Command => {
+            let command = api_settings.api_key_command.clone()?;
+            let interval = api_settings.api_key_command_refresh;
+            let mut refresh_in = next_command_refresh(interval, api_keys)?;
+            Some(cx.spawn(async move |this, cx| {
+                loop {
+                    cx.background_executor().timer(refresh_in).await;
+                    match run_api_key_command(&command).await {
+                        Ok(output) => {
+                            let api_keys = ApiKeys::from_command_output(&output);
+                            let next_refresh = next_command_refresh(interval, &api_keys);
+                            let updated = this.update(cx, |this, cx| {
+                                set_api_keys(this, api_keys);
+                                cx.notify();
+                            });
+                            match (updated, next_refresh) {
+                                (Ok(()), Some(next_refresh)) => refresh_in = next_refresh,
+                                _ => break,
+                            }
+                        }
+                        Err(error) => {
+                            log::error!("failed to refresh API key: {error:?}");
+                            refresh_in = COMMAND_RETRY_INTERVAL;
+                        }
+                    }
+                }
+            }))
+        }
+        ApiKeySource::File(path) => {
+            let mut contents = settings::watch_config_file(
+                cx.background_executor(),
+                <dyn Fs>::global(cx),
+                path.clone(),
+            );
+            Some(cx.spawn(async move |this, cx| {
+                // The first message is the file's current contents, which we've already loaded.
+                contents.next().await;
+                while let Some(api_key) = contents.next().await {
+                    let api_keys = ApiKeys::parse(&api_key);
+                    if api_keys.is_empty() {
+                        continue;
+                    }
+                    let updated = this.update(cx, |this, cx| {
+                        set_api_keys(this, api_keys);
+                        cx.notify();
+                    });
+                    if updated.is_err() {
+                        break;
+                    }
+                }
+            }))
+        }
+        ApiKeySource::CredentialsStore | ApiKeySource::WorkspaceCredentialsStore(_) => {
+            let expires_in = api_keys.refresh_in()?;
+            Some(cx.spawn(async move |this, cx| {
+                cx.background_executor().timer(expires_in).await;
+                log::info!("the saved token expired, authenticate again to keep using it");
+                this.update(cx, |this, cx| {
+                    set_api_keys(this, ApiKeys::default());
+                    cx.notify();
+                })
+                .ok();
+            }))
+        }
+        ApiKeySource::EnvVar(_) => None,
+    }
+}
+
+fn next_command_refresh(interval: Option<Duration>, api_keys: &ApiKeys) -> Option<Duration> {
+    match (interval, api_keys.refresh_in()) {
+        (Some(interval), Some(refresh_in)) => Some(interval.min(refresh_in)),
+        (interval, refresh_in) => interval.or(refresh_in),
+    }
+}
+
+pub(crate) fn expand_home_dir(path: &Path) -> PathBuf {
+    match path.strip_prefix("~") {
+        Ok(relative_path) => util::paths::home_dir().join(relative_path),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Checks that the provider doesn't reject any of the keys before they get persisted.
+///
+/// `build_request` should target a cheap authenticated endpoint, such as the provider's list of
+/// models. Only a 401 or 403 response fails the check, since an unreachable or misbehaving
+/// endpoint says nothing about the key. Those keys are saved anyway, with the returned warning to
+/// tell the user that they weren't checked.
+pub async fn validate_api_keys(
+    http_client: &dyn HttpClient,
+    api_keys: &ApiKeys,
+    build_request: impl Fn(&str) -> http::request::Builder,
+) -> Result<Option<SharedString>> {
+    anyhow::ensure!(!api_keys.is_empty(), "no API key was entered");
+
+    let mut warning = None;
+    for (ix, api_key) in api_keys.iter().enumerate() {
+        let key_name = if api_keys.len() > 1 {
+            format!("API key {} of {}", ix + 1, api_keys.len())
+        } else {
+            "API key".to_string()
+        };
+        let request = build_request(api_key)
+            .method(Method::GET)
+            .body(AsyncBody::default())?;
+        let mut response = match http_client.send(request).await {
+            Ok(response) => response,
+            Err(error) if is_unauthorized_error(&error) => {
+                anyhow::bail!("The {key_name} was rejected.");
+            }
+            Err(error) => {
+                log::warn!("couldn't check the API key: {error:#}");
+                warning = Some(format!(
+                    "Saved the {key_name} without checking it, since the provider couldn't be \
+                    reached."
+                ));
+                continue;
+            }
+        };
+        let status = response.status();
+        if status.is_success() {
+            continue;
+        }
+
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await.ok();
+        if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN {
+            log::error!("API key validation failed: {status} {body}");
+            anyhow::bail!("The {key_name} was rejected ({status}).");
+        }
+        log::warn!("couldn't check the API key: {status} {body}");
+        warning = Some(format!(
+            "Saved the {key_name} without checking it, since the provider responded with {status}."
+        ));
+    }
+
+    Ok(warning.map(Into::into))
+}
+
+/// The progress of validating an API key entered in a provider's configuration view.
+#[derive(Clone, Debug, Default)]
+pub enum ApiKeyValidation {
+    #[default]
+    Idle,
+    Validating,
+    /// The key was saved, but the provider couldn't tell whether it accepts it.
+    Unchecked(SharedString),
+    Failed(SharedString),
+}
+
+impl ApiKeyValidation {
+    pub fn from_result(result: &Result<Option<SharedString>>) -> Self {
+        match result {
+            Ok(None) => Self::Idle,
+            Ok(Some(warning)) => Self::Unchecked(warning.clone()),
+            Err(error) => Self::Failed(error.to_string().into()),
+        }
+    }
+
+    pub fn render(&self) -> Option<AnyElement> {
+        match self {
+            Self::Idle => None,
+            Self::Validating => Some(
+                Label::new("Checking API key…")
+                    .size(LabelSize::Small)
+                    .color(Color::Muted)
+                    .into_any_element(),
+            ),
+            Self::Unchecked(warning) => Some(
+                h_flex()
+                    .gap_1()
+                    .child(
+                        Icon::new(IconName::Warning)
+                            .size(IconSize::Small)
+                            .color(Color::Warning),
+                    )
+                    .child(
+                        Label::new(warning.clone())
+                            .size(LabelSize::Small)
+                            .color(Color::Warning),
+                    )
+                    .into_any_element(),
+            ),
+            Self::Failed(error) => Some(
+                h_flex()
+                    .gap_1()
+                    .child(
+                        Icon::new(IconName::XCircle)
+                            .size(IconSize::Small)
+                            .color(Color::Error),
+                    )
+                    .child(
+                        Label::new(error.clone())
+                            .size(LabelSize::Small)
+                            .color(Color::Error),
+                    )
+                    .into_any_element(),
+            ),
+        }
+    }
+}
+
+/// The [`LanguageModelCompletionError`] that `error` was caused by, which the provider's HTTP
+/// client reports for the error responses it recognizes, even when the provider's own client
+/// wraps it in its error type.
+fn completion_error(error: &anyhow::Error) -> Option<&LanguageModelCompletionError> {
+    error.chain().find_map(|error| error.downcast_ref())
+}
+
+/// Returns whether a request failed because the key it used was rate limited or ran out of quota.
+fn is_rate_limit_error(error: &anyhow::Error) -> bool {
+    if let Some(AnthropicError::ApiError(error)) = error.downcast_ref::<AnthropicError>() {
+        return error.is_rate_limit_error();
+    }
+    matches!(
+        completion_error(error),
+        Some(
+            LanguageModelCompletionError::RateLimited { .. }
+                | LanguageModelCompletionError::QuotaExceeded
+        )
+    )
+}
+
+/// Returns whether a request failed because the provider didn't accept the key it used.
+fn is_unauthorized_error(error: &anyhow::Error) -> bool {
+    if let Some(AnthropicError::ApiError(error)) = error.downcast_ref::<AnthropicError>() {
+        return matches!(
+            error.code(),
+            Some(ApiErrorCode::AuthenticationError | ApiErrorCode::PermissionError)
+        );
+    }
+    matches!(
+        completion_error(error),
+        Some(LanguageModelCompletionError::InvalidApiKey)
+    )
+}
+
+/// Whether a key entered in a configuration view is saved only for the workspace open in the
+/// view's window.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeySaveScope {
+    workspace: Option<WorkspaceScope>,
+    workspace_only: bool,
+}
+
+impl ApiKeySaveScope {
+    pub fn new(window: &Window, cx: &App) -> Self {
+        let workspace = cx
+            .try_global::<WindowWorkspaceScopes>()
+            .and_then(|scopes| scopes.0.get(&window.window_handle().window_id()).cloned());
+        Self {
+            workspace,
+            workspace_only: false,
+        }
+    }
+
+    /// The workspace open in the configuration view's window.
+    pub fn workspace(&self) -> Option<&WorkspaceScope> {
+        self.workspace.as_ref()
+    }
+
+    /// The workspace to save the key for, or `None` to save it for every workspace.
+    pub fn workspace_scope(&self) -> Option<WorkspaceScope> {
+        self.workspace.clone().filter(|_| self.workspace_only)
+    }
+
+    pub fn render<V: 'static>(
+        &self,
+        cx: &mut gpui::Context<V>,
+        scope: fn(&mut V) -> &mut ApiKeySaveScope,
+    ) -> Option<AnyElement> {
+        let workspace = self.workspace.as_ref()?;
+        Some(
+            Checkbox::new("save-for-workspace", self.workspace_only.into())
+                .label(format!(
+                    "Only use this key in the {} workspace",
+                    workspace.name
+                ))
+                .on_click(cx.listener(move |this, state: &ToggleState, _, cx| {
+                    scope(this).workspace_only = state.selected();
+                    cx.notify();
+                }))
+                .into_any_element(),
+        )
+    }
+}
+
+/// Shows which key an authenticated provider is using, masked unless revealed, and lets the user
+/// enter another one in its place.
+#[derive(Clone, Debug, Default)]
+pub struct ApiKeyDisplay {
+    revealed: bool,
+    replacing: bool,
+}
+
+impl ApiKeyDisplay {
+    /// Whether the user asked to enter a new key, in which case the configuration view shows its
+    /// editor even though the provider is authenticated.
+    pub fn is_replacing(&self) -> bool {
+        self.replacing
+    }
+
+    pub fn render<V: 'static>(
+        &self,
+        provider_id: &str,
+        api_keys: &ApiKeys,
+        rejected: bool,
+        source: &ApiKeySource,
+        cx: &mut gpui::Context<V>,
+        display: fn(&mut V) -> &mut ApiKeyDisplay,
+    ) -> AnyElement {
+        let key = if self.revealed {
+            api_keys.current().map(ToString::to_string)
+        } else {
+            api_keys.masked()
+        };
+        v_flex()
+            .child(Label::new(source.description()))
+            .when(rejected, |this| {
+                let message = if source.is_external() {
+                    "The provider rejected this key. It may have been revoked or expired."
+                } else {
+                    "The provider rejected this key. Replace it to keep using this provider."
+                };
+                this.child(
+                    h_flex()
+                        .gap_1()
+                        .child(
+                            Icon::new(IconName::Warning)
+                                .size(IconSize::Small)
+                                .color(Color::Warning),
+                        )
+                        .child(
+                            Label::new(message)
+                                .size(LabelSize::Small)
+                                .color(Color::Warning),
+                        ),
+                )
+            })
+            .when_some(key, |this, key| {
+                this.child(
+                    h_flex()
+                        .gap_1()
+                        .child(
+                            Label::new(key)
+                                .size(LabelSize::Small)
+                                .color(Color::Muted)
+                                .buffer_font(cx),
+                        )
+                        .child(
+                            IconButton::new("reveal-key", IconName::Eye)
+                                .icon_size(IconSize::Small)
+                                .toggle_state(self.revealed)
+                                .tooltip(Tooltip::text(if self.revealed {
+                                    "Hide Key"
+                                } else {
+                                    "Reveal Key"
+                                }))
+                                .on_click(cx.listener(move |this, _, _, cx| {
+                                    let display = display(this);
+                                    display.revealed = !display.revealed;
+                                    cx.notify();
+                                })),
+                        )
+                        .when(!source.is_external(), |this| {
+                            this.child(
+                                Button::new("replace-key", "Replace Key")
+                                    .label_size(LabelSize::Small)
+                                    .icon(Some(IconName::Pencil))
+                                    .icon_size(IconSize::Small)
+                                    .icon_position(IconPosition::Start)
+                                    .on_click(cx.listener(move |this, _, _, cx| {
+                                        let display = display(this);
+                                        display.replacing = true;
+                                        display.revealed = false;
+                                        cx.notify();
+                                    })),
+                            )
+                        }),
+                )
+            })
+            .children(render_key_usage(provider_id, api_keys, cx))
+            .into_any_element()
+    }
+
+    /// A button that returns to showing the current key, for the editor shown while replacing it.
+    pub fn render_cancel<V: 'static>(
+        &self,
+        cx: &mut gpui::Context<V>,
+        display: fn(&mut V) -> &mut ApiKeyDisplay,
+    ) -> Option<AnyElement> {
+        if !self.replacing {
+            return None;
+        }
+        Some(
+            Button::new("cancel-replace-key", "Keep Current Key")
+                .label_size(LabelSize::Small)
+                .on_click(cx.listener(move |this, _, _, cx| {
+                    display(this).replacing = false;
+                    cx.notify();
+                }))
+                .into_any_element(),
+        )
+    }
+
+    /// Called once a new key was saved.
+    pub fn finish_replacing(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_multiple_keys() {
+        let keys = ApiKeys::parse("sk-one\n sk-two ,sk-three,,\n");
+        assert_eq!(keys.len(), 3);
+        assert_eq!(keys.current(), Some("sk-one"));
+        assert_eq!(keys.to_credentials(), "sk-one\nsk-two\nsk-three");
+    }
+
+    #[test]
+    fn test_round_robin_rotation() {
+        let keys = ApiKeys::parse("a,b");
+        let rotation = ApiKeyRotation::RoundRobin;
+        assert_eq!(keys.next_for_request(rotation).as_deref(), Some("a"));
+        assert_eq!(keys.next_for_request(rotation).as_deref(), Some("b"));
+        assert_eq!(keys.next_for_request(rotation).as_deref(), Some("a"));
+    }
+
+    #[test]
+    fn test_failover_rotation() {
+        let keys = ApiKeys::parse("a,b,c");
+        let rotation = ApiKeyRotation::Failover;
+        assert_eq!(keys.next_for_request(rotation).as_deref(), Some("a"));
+        assert_eq!(keys.next_for_request(rotation).as_deref(), Some("a"));
+
+        assert!(keys.rotate_after_failure("a"));
+        // A second request that failed with the same key doesn't skip over "b".
+        assert!(keys.rotate_after_failure("a"));
+        assert_eq!(keys.next_for_request(rotation).as_deref(), Some("b"));
+
+        let single = ApiKeys::parse("a");
+        assert!(!single.rotate_after_failure("a"));
+    }
+
+    struct TestState {
+        status: ApiKeyStatus,
+    }
+
+    fn test_state(cx: &mut gpui::TestAppContext) -> gpui::Entity<TestState> {
+        cx.new(|cx| TestState {
+            status: ApiKeyStatus::new(cx, |state| &mut state.status),
+        })
+    }
+
+    #[gpui::test]
+    async fn test_rejected_api_key(cx: &mut gpui::TestAppContext) {
+        let state = test_state(cx);
+        let keys = state.read_with(cx, |state, _| state.status.track(&ApiKeys::parse("a,b")));
+        let rotation = ApiKeyRotation::Failover;
+        // Errors are classified by type, not by what their message happens to mention.
+        keys.record_error(
+            "a",
+            &anyhow::anyhow!("Failed to connect to API: 401 Unauthorized"),
+        );
+        cx.run_until_parked();
+        assert!(!state.read_with(cx, |state, _| state.status.is_rejected()));
+        assert_eq!(keys.next_for_request(rotation).as_deref(), Some("a"));
+
+        let error = anyhow::anyhow!(LanguageModelCompletionError::InvalidApiKey)
+            .context("failed to stream completion");
+        keys.clone().record_error("a", &error);
+        cx.run_until_parked();
+        assert!(state.read_with(cx, |state, _| state.status.is_rejected()));
+        assert_eq!(keys.next_for_request(rotation).as_deref(), Some("b"));
+
+        // Rotating to a key that works clears the rejection.
+        keys.record_success();
+        cx.run_until_parked();
+        assert!(!state.read_with(cx, |state, _| state.status.is_rejected()));
+    }
+
+    #[test]
+    fn test_retry_after_error() {
+        let keys = ApiKeys::parse("a,b");
+        let rotation = ApiKeyRotation::Failover;
+        let rate_limited =
+            anyhow::anyhow!(LanguageModelCompletionError::RateLimited { retry_after: None });
+        let overloaded = anyhow::anyhow!(LanguageModelCompletionError::Overloaded);
+
+        assert!(!keys.retry_after_error("a", &overloaded, 0));
+        assert_eq!(keys.next_for_request(rotation).as_deref(), Some("a"));
+
+        // A provider's client can wrap the error in its own type.
+        let wrapped = anyhow::anyhow!(AnthropicError::Other(anyhow::anyhow!(
+            LanguageModelCompletionError::QuotaExceeded
+        )));
+        assert!(keys.retry_after_error("a", &wrapped, 0));
+        assert_eq!(keys.next_for_request(rotation).as_deref(), Some("b"));
+
+        // Every key has been tried once.
+        assert!(!keys.retry_after_error("b", &rate_limited, 1));
+        assert_eq!(keys.next_for_request(rotation).as_deref(), Some("a"));
+
+        assert!(!ApiKeys::parse("a").retry_after_error("a", &rate_limited, 0));
+    }
+
+    #[gpui::test]
+    async fn test_with_rotation(cx: &mut gpui::TestAppContext) {
+        let keys = ApiKeys::parse("a,b");
+        let rotation = ApiKeyRotation::Failover;
+        let usage = KeyUsageRecorder::new("test", "model", None, &cx.to_async());
+
+        let mut sent_with = Vec::new();
+        let response = keys
+            .with_rotation(rotation, "Test", &usage, |api_key| {
+                sent_with.push(api_key.clone());
+                async move {
+                    if api_key == "a" {
+                        Err(anyhow::anyhow!(LanguageModelCompletionError::RateLimited {
+                            retry_after: None
+                        }))
+                    } else {
+                        Ok(api_key)
+                    }
+                }
+            })
+            .await;
+        assert_eq!(response.unwrap(), "b");
+        assert_eq!(sent_with, ["a", "b"]);
+
+        // Errors that another key wouldn't fix aren't retried.
+        let mut attempts = 0;
+        let response = keys
+            .with_rotation(rotation, "Test", &usage, |_| {
+                attempts += 1;
+                async { Err::<(), _>(anyhow::anyhow!(LanguageModelCompletionError::Overloaded)) }
+            })
+            .await;
+        assert!(response.is_err());
+        assert_eq!(attempts, 1);
+
+        let response = ApiKeys::parse("")
+            .with_rotation(rotation, "Test", &usage, |_| async { Ok(()) })
+            .await;
+        assert_eq!(response.unwrap_err().to_string(), "Missing Test API Key");
+    }
+
+    #[gpui::test]
+    async fn test_validate_api_keys() {
+        let http_client = http_client::FakeHttpClient::create(|request| async move {
+            let status = match request.headers()["authorization"].as_bytes() {
+                b"valid" => 200,
+                b"revoked" => 401,
+                b"outage" => 503,
+                _ => anyhow::bail!("connection refused"),
+            };
+            Ok(http_client::Response::builder()
+                .status(status)
+                .body(AsyncBody::default())?)
+        });
+        let validate = |api_keys: &str| {
+            let http_client = http_client.clone();
+            let api_keys = ApiKeys::parse(api_keys);
+            async move {
+                validate_api_keys(http_client.as_ref(), &api_keys, |api_key| {
+                    http::Request::builder()
+                        .uri("https://example.com/models")
+                        .header("Authorization", api_key)
+                })
+                .await
+            }
+        };
+
+        assert_eq!(validate("valid").await.unwrap(), None);
+        assert_eq!(
+            validate("valid,revoked").await.unwrap_err().to_string(),
+            "The API key 2 of 2 was rejected (401 Unauthorized)."
+        );
+        // Keys that couldn't be checked are saved with a warning instead of being refused.
+        assert_eq!(
+            validate("outage").await.unwrap().as_deref(),
+            Some(
+                "Saved the API key without checking it, since the provider responded with \
+                503 Service Unavailable."
+            )
+        );
+        assert_eq!(
+            validate("offline").await.unwrap().as_deref(),
+            Some("Saved the API key without checking it, since the provider couldn't be reached.")
+        );
+    }
+
+    #[test]
+    fn test_masked_api_key() {
+        assert_eq!(ApiKeys::parse("").masked(), None);
+        assert_eq!(ApiKeys::parse("short").masked().as_deref(), Some("••••"));
+        assert_eq!(
+            ApiKeys::parse("sk-ant-1234abcd").masked().as_deref(),
+            Some("••••abcd")
+        );
+        assert_eq!(
+            ApiKeys::parse("sk-one-aaaa\nsk-two-bbbb")
+                .masked()
+                .as_deref(),
+            Some("••••aaaa (key 1 of 2)")
+        );
+    }
+
+    #[test]
+    fn test_api_key_command_output() {
+        let keys = ApiKeys::from_command_output("sk-plain");
+        assert_eq!(keys.current(), Some("sk-plain"));
+        assert_eq!(keys.refresh_in(), None);
+
+        let keys =
+            ApiKeys::from_command_output(r#"{"token": "sk-short-lived", "expires_in": 3600}"#);
+        assert_eq!(keys.current(), Some("sk-short-lived"));
+        assert!(!keys.is_expired());
+        assert!(
+            keys.refresh_in()
+                .is_some_and(|refresh_in| refresh_in < Duration::from_secs(3600))
+        );
+
+        let keys = ApiKeys::from_command_output(r#"{"api_key": "sk-expired", "expires_at": 1}"#);
+        assert!(keys.is_expired());
+    }
+
+    #[test]
+    fn test_api_key_urls() {
+        let workspace = WorkspaceScope {
+            name: "zed".into(),
+            id: "1234".into(),
+        };
+        let scope = CredentialsScope::default();
+        let source = ApiKeySource::WorkspaceCredentialsStore(workspace);
+        assert_eq!(
+            scope.api_key_url(&source, "openai", "https://api.openai.com/v1"),
+            "https://api.openai.com/v1#provider=openai#workspace=1234"
+        );
+        assert_eq!(
+            scope.legacy_api_key_url(&source, "https://api.openai.com/v1"),
+            "https://api.openai.com/v1#workspace=1234"
+        );
+
+        let scope = CredentialsScope {
+            profile: Some("work".into()),
+        };
+        assert_eq!(
+            scope.api_key_url(
+                &ApiKeySource::CredentialsStore,
+                "grok",
+                "https://api.x.ai/v1"
+            ),
+            "https://api.x.ai/v1#provider=grok#profile=work"
+        );
+    }
+
+    #[gpui::test]
+    async fn test_workspace_api_keys_for_request(cx: &mut gpui::TestAppContext) {
+        let api_keys = ApiKeys::parse("sk-every-workspace");
+        let source = ApiKeySource::CredentialsStore;
+        let workspace = WorkspaceScope {
+            name: "client".into(),
+            id: "/client".into(),
+        };
+        let workspace_api_keys = WorkspaceApiKeys::default();
+        workspace_api_keys.set(&workspace, Some(ApiKeys::parse("sk-client")));
+        let state = test_state(cx);
+
+        let for_request = |workspace: Option<&WorkspaceScope>, source: &ApiKeySource| {
+            cx.update(|cx| {
+                let status = &state.read(cx).status;
+                workspace_api_keys
+                    .for_request(&api_keys, status, source, workspace, "openai", "", cx)
+            })
+        };
+        let keys = for_request(None, &source).await;
+        assert_eq!(keys.current(), Some("sk-every-workspace"));
+        let keys = for_request(Some(&workspace), &source).await;
+        assert_eq!(keys.current(), Some("sk-client"));
+        assert!(workspace_api_keys.has_any());
+        keys.record_error(
+            "sk-client",
+            &anyhow::anyhow!(LanguageModelCompletionError::InvalidApiKey),
+        );
+        cx.run_until_parked();
+        assert!(state.read_with(cx, |state, _| state.status.is_rejected()));
+
+        let env_var = ApiKeySource::EnvVar("OPENAI_API_KEY".into());
+        let keys = for_request(Some(&workspace), &env_var).await;
+        assert_eq!(keys.current(), Some("sk-every-workspace"));
+
+        workspace_api_keys.set(&workspace, None);
+        let keys = for_request(Some(&workspace), &source).await;
+        assert_eq!(keys.current(), Some("sk-every-workspace"));
+        assert!(!workspace_api_keys.has_any());
+    }
+}
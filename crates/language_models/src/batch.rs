@@ -0,0 +1,275 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use collections::HashMap;
+use futures::channel::oneshot;
+use futures::future::BoxFuture;
+use gpui::{AsyncApp, BackgroundExecutor};
+use language_model::LanguageModelRequest;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::AllLanguageModelSettings;
+
+const DEFAULT_GROUP_WINDOW_SECS: u64 = 10;
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 60;
+
+/// How many times in a row polling a batch can fail before its requests are failed too.
+const MAX_POLL_ERRORS: usize = 5;
+
+/// What batched requests cost compared to sending them one at a time.
+pub(crate) const BATCH_PRICE_FACTOR: f64 = 0.5;
+
+/// Sends requests whose responses aren't needed right away through providers' batch APIs, which
+/// complete them within a day at half the price.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct BatchSettings {
+    /// Whether to batch requests that can wait, like thread summaries and eval judgements.
+    ///
+    /// Default: false
+    pub enabled: Option<bool>,
+    /// How many seconds to wait for more requests before submitting a batch.
+    ///
+    /// Default: 10
+    pub group_window_secs: Option<u64>,
+    /// How many seconds to wait between checks on whether a batch has finished.
+    ///
+    /// Default: 60
+    pub poll_interval_secs: Option<u64>,
+}
+
+impl BatchSettings {
+    /// The settings to batch `request` with, if it can wait and batching is enabled.
+    pub(crate) fn for_request(request: &LanguageModelRequest, cx: &AsyncApp) -> Option<Self> {
+        if !request.batch {
+            return None;
+        }
+        AllLanguageModelSettings::try_read_global(cx, |settings| settings.batch)
+            .filter(|settings| settings.enabled.unwrap_or(false))
+    }
+
+    fn group_window(&self) -> Duration {
+        Duration::from_secs(self.group_window_secs.unwrap_or(DEFAULT_GROUP_WINDOW_SECS))
+    }
+
+    fn poll_interval(&self) -> Duration {
+        Duration::from_secs(
+            self.poll_interval_secs
+                .unwrap_or(DEFAULT_POLL_INTERVAL_SECS)
+                .max(1),
+        )
+    }
+}
+
+/// A provider's batch API.
+pub(crate) trait BatchApi: 'static + Send + Sync {
+    type Request: 'static + Send;
+    type Response: 'static + Send;
+
+    /// Submits `requests`, by their custom IDs, as a batch, returning the batch's ID.
+    fn submit(&self, requests: Vec<(String, Self::Request)>) -> BoxFuture<'static, Result<String>>;
+
+    /// The responses in a batch by custom ID, or `None` while the batch is still running.
+    fn results(
+        &self,
+        batch_id: &str,
+    ) -> BoxFuture<'static, Result<Option<HashMap<String, Result<Self::Response>>>>>;
+}
+
+struct QueuedRequest<A: BatchApi> {
+    request: A::Request,
+    response_tx: oneshot::Sender<Result<A::Response>>,
+}
+
+/// Groups requests that arrive close together into batches, and delivers each request's
+/// response once its batch finishes.
+///
+/// The batches being polled are only kept in memory, since their responses go to requests that
+/// don't outlive Zed, so batches that are still running when Zed quits are abandoned.
+pub(crate) struct BatchQueue<A: BatchApi> {
+    /// The requests waiting to be submitted, by the endpoint and credentials they're sent with.
+    groups: Arc<Mutex<HashMap<String, Vec<QueuedRequest<A>>>>>,
+}
+
+impl<A: BatchApi> Clone for BatchQueue<A> {
+    fn clone(&self) -> Self {
+        Self {
+            groups: self.groups.clone(),
+        }
+    }
+}
+
+impl<A: BatchApi> Default for BatchQueue<A> {
+    fn default() -> Self {
+        Self {
+            groups: Arc::default(),
+        }
+    }
+}
+
+impl<A: BatchApi> BatchQueue<A> {
+    /// Adds `request` to the batch for `group`, which is submitted through the `api` of the
+    /// group's first request once the group window passes, and waits for its response.
+    pub(crate) fn enqueue(
+        &self,
+        group: String,
+        api: A,
+        request: A::Request,
+        settings: BatchSettings,
+        executor: &BackgroundExecutor,
+    ) -> impl Future<Output = Result<A::Response>> + use<A> {
+        let (response_tx, response_rx) = oneshot::channel();
+        let is_new_group = {
+            let mut groups = self.groups.lock();
+            let queued = groups.entry(group.clone()).or_default();
+            queued.push(QueuedRequest {
+                request,
+                response_tx,
+            });
+            queued.len() == 1
+        };
+        if is_new_group {
+            let groups = self.groups.clone();
+            let executor = executor.clone();
+            executor
+                .clone()
+                .spawn(async move {
+                    executor.timer(settings.group_window()).await;
+                    let Some(queued) = groups.lock().remove(&group) else {
+                        return;
+                    };
+                    run_batch(api, queued, settings.poll_interval(), &executor).await;
+                })
+                .detach();
+        }
+        async move {
+            response_rx
+                .await
+                .map_err(|_| anyhow!("the batch was dropped before it finished"))?
+        }
+    }
+}
+
+async fn run_batch<A: BatchApi>(
+    api: A,
+    queued: Vec<QueuedRequest<A>>,
+    poll_interval: Duration,
+    executor: &BackgroundExecutor,
+) {
+    let mut requests = Vec::with_capacity(queued.len());
+    let mut response_txs = Vec::with_capacity(queued.len());
+    for (ix, queued) in queued.into_iter().enumerate() {
+        let custom_id = format!("request-{ix}");
+        requests.push((custom_id.clone(), queued.request));
+        response_txs.push((custom_id, queued.response_tx));
+    }
+
+    let fail_all = |response_txs: Vec<(String, oneshot::Sender<_>)>, error: anyhow::Error| {
+        for (_, response_tx) in response_txs {
+            response_tx.send(Err(anyhow!("{error:#}"))).ok();
+        }
+    };
+
+    let batch_id = match api.submit(requests).await {
+        Ok(batch_id) => batch_id,
+        Err(error) => return fail_all(response_txs, error),
+    };
+
+    let mut poll_errors = 0;
+    let mut results = loop {
+        executor.timer(poll_interval).await;
+        match api.results(&batch_id).await {
+            Ok(Some(results)) => break results,
+            Ok(None) => poll_errors = 0,
+            Err(error) => {
+                poll_errors += 1;
+                if poll_errors == MAX_POLL_ERRORS {
+                    return fail_all(response_txs, error);
+                }
+                log::warn!("failed to check on batch {batch_id}: {error:#}");
+            }
+        }
+    };
+
+    for (custom_id, response_tx) in response_txs {
+        let response = results
+            .remove(&custom_id)
+            .unwrap_or_else(|| Err(anyhow!("batch {batch_id} has no result for the request")));
+        response_tx.send(response).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::FutureExt as _;
+    use gpui::TestAppContext;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct FakeBatchApi {
+        submitted: Arc<Mutex<Vec<Vec<(String, String)>>>>,
+        polls: Arc<Mutex<usize>>,
+    }
+
+    impl BatchApi for FakeBatchApi {
+        type Request = String;
+        type Response = String;
+
+        fn submit(&self, requests: Vec<(String, String)>) -> BoxFuture<'static, Result<String>> {
+            self.submitted.lock().push(requests);
+            futures::future::ready(Ok("batch-1".into())).boxed()
+        }
+
+        fn results(
+            &self,
+            _batch_id: &str,
+        ) -> BoxFuture<'static, Result<Option<HashMap<String, Result<String>>>>> {
+            let mut polls = self.polls.lock();
+            *polls += 1;
+            let results = (*polls > 1).then(|| {
+                self.submitted.lock()[0]
+                    .iter()
+                    .map(|(custom_id, request)| (custom_id.clone(), Ok(request.to_uppercase())))
+                    .collect()
+            });
+            futures::future::ready(Ok(results)).boxed()
+        }
+    }
+
+    #[gpui::test]
+    async fn test_batch_queue(cx: &mut TestAppContext) {
+        let queue = BatchQueue::default();
+        let api = FakeBatchApi::default();
+        let settings = BatchSettings {
+            enabled: Some(true),
+            group_window_secs: Some(5),
+            poll_interval_secs: Some(30),
+        };
+        let executor = cx.executor();
+        let first = queue.enqueue("key".into(), api.clone(), "a".into(), settings, &executor);
+        let second = queue.enqueue("key".into(), api.clone(), "b".into(), settings, &executor);
+
+        executor.advance_clock(Duration::from_secs(5));
+        executor.run_until_parked();
+        assert_eq!(
+            *api.submitted.lock(),
+            vec![vec![
+                ("request-0".to_string(), "a".to_string()),
+                ("request-1".to_string(), "b".to_string()),
+            ]]
+        );
+
+        executor.advance_clock(Duration::from_secs(30));
+        executor.run_until_parked();
+        assert_eq!(*api.polls.lock(), 1);
+
+        executor.advance_clock(Duration::from_secs(30));
+        executor.run_until_parked();
+        assert_eq!(*api.polls.lock(), 2);
+        assert_eq!(first.await.unwrap(), "A");
+        assert_eq!(second.await.unwrap(), "B");
+    }
+}
@@ -0,0 +1,118 @@
+use anyhow::Result;
+use credentials_provider::CredentialsProvider;
+use gpui::{App, SharedString, Task};
+use http_client::http;
+use settings::Settings as _;
+
+use crate::api_key::{ApiKeySource, ApiKeys, CredentialsScope, validate_api_keys};
+use crate::provider::{anthropic, deepseek, google, grok, mistral, open_ai};
+use crate::provider_http_client::ProviderHttpClient;
+use crate::{AllLanguageModelSettings, ProviderApiSettings};
+
+/// A provider whose API key can be saved without going through its configuration view, e.g.
+/// with `zed --configure-language-model <provider>` on a headless machine.
+pub struct ConfigurableProvider {
+    pub id: &'static str,
+    pub name: &'static str,
+    api_url: fn(&AllLanguageModelSettings) -> &str,
+    api_settings: fn(&AllLanguageModelSettings) -> &ProviderApiSettings,
+    validation_request: fn(&str, &str) -> http::request::Builder,
+}
+
+pub const CONFIGURABLE_PROVIDERS: &[ConfigurableProvider] = &[
+    ConfigurableProvider {
+        id: anthropic::PROVIDER_ID,
+        name: anthropic::PROVIDER_NAME,
+        api_url: |settings| &settings.anthropic.api_url,
+        api_settings: |settings| &settings.anthropic.api,
+        validation_request: anthropic::api_key_validation_request,
+    },
+    ConfigurableProvider {
+        id: deepseek::PROVIDER_ID,
+        name: deepseek::PROVIDER_NAME,
+        api_url: |settings| &settings.deepseek.api_url,
+        api_settings: |settings| &settings.deepseek.api,
+        validation_request: deepseek::api_key_validation_request,
+    },
+    ConfigurableProvider {
+        id: google::PROVIDER_ID,
+        name: google::PROVIDER_NAME,
+        api_url: |settings| &settings.google.api_url,
+        api_settings: |settings| &settings.google.api,
+        validation_request: google::api_key_validation_request,
+    },
+    ConfigurableProvider {
+        id: grok::PROVIDER_ID,
+        name: grok::PROVIDER_NAME,
+        api_url: |settings| &settings.grok.api_url,
+        api_settings: |settings| &settings.grok.api,
+        validation_request: grok::api_key_validation_request,
+    },
+    ConfigurableProvider {
+        id: mistral::PROVIDER_ID,
+        name: mistral::PROVIDER_NAME,
+        api_url: |settings| &settings.mistral.api_url,
+        api_settings: |settings| &settings.mistral.api,
+        validation_request: mistral::api_key_validation_request,
+    },
+    ConfigurableProvider {
+        id: open_ai::PROVIDER_ID,
+        name: open_ai::PROVIDER_NAME,
+        api_url: |settings| &settings.openai.api_url,
+        api_settings: |settings| &settings.openai.api,
+        validation_request: open_ai::api_key_validation_request,
+    },
+];
+
+impl ConfigurableProvider {
+    pub(crate) fn api_url<'a>(&self, settings: &'a AllLanguageModelSettings) -> &'a str {
+        (self.api_url)(settings)
+    }
+
+    pub(crate) fn api_settings<'a>(
+        &self,
+        settings: &'a AllLanguageModelSettings,
+    ) -> &'a ProviderApiSettings {
+        (self.api_settings)(settings)
+    }
+
+    pub fn find(id: &str) -> Option<&'static Self> {
+        CONFIGURABLE_PROVIDERS
+            .iter()
+            .find(|provider| provider.id == id)
+    }
+
+    /// Checks the keys with the provider and saves them where its configuration view would, for
+    /// every workspace within the credentials profile selected in the settings. Resolves to a
+    /// warning if they were saved without being checked.
+    pub fn save_api_keys(
+        &self,
+        api_keys: ApiKeys,
+        cx: &mut App,
+    ) -> Task<Result<Option<SharedString>>> {
+        let settings = AllLanguageModelSettings::get_global(cx);
+        let api_url = (self.api_url)(settings).to_string();
+        let scope = CredentialsScope {
+            profile: settings.credentials_profile.clone().map(Into::into),
+        };
+        let credentials_url = scope.api_key_url(&ApiKeySource::CredentialsStore, self.id, &api_url);
+        let credentials_provider = <dyn CredentialsProvider>::global(cx);
+        let http_client = ProviderHttpClient::new(cx.http_client(), self.api_settings, cx);
+        let validation_request = self.validation_request;
+        cx.spawn(async move |cx| {
+            let warning = validate_api_keys(http_client.as_ref(), &api_keys, |api_key| {
+                validation_request(&api_url, api_key)
+            })
+            .await?;
+            credentials_provider
+                .write_credentials(
+                    &credentials_url,
+                    "Bearer",
+                    api_keys.to_credentials().as_bytes(),
+                    cx,
+                )
+                .await?;
+            Ok(warning)
+        })
+    }
+}
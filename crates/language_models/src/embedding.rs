@@ -0,0 +1,159 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use gpui::{App, Entity, Global};
+use http_client::HttpClient;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use semantic_index::{Embedding, EmbeddingProvider};
+use serde::{Deserialize, Serialize};
+use settings::{Settings as _, SettingsStore};
+
+use crate::AllLanguageModelSettings;
+use crate::provider::google::State as GoogleState;
+use crate::provider::grok::State as GrokState;
+use crate::provider::open_ai::State as OpenAiState;
+
+mod google;
+mod grok;
+mod ollama;
+mod open_ai;
+mod voyage;
+
+pub use google::GoogleEmbeddingProvider;
+pub use grok::GrokEmbeddingProvider;
+pub use ollama::OllamaEmbeddingProvider;
+pub use open_ai::OpenAiEmbeddingProvider;
+pub use voyage::VoyageEmbeddingProvider;
+
+/// Which service embeds text for semantic features, like searching a project by meaning.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct EmbeddingSettings {
+    /// The service to embed text with: `openai`, `grok`, `google`, `voyage`, or `ollama`.
+    ///
+    /// Default: "openai"
+    pub provider: Option<String>,
+    /// The model to embed text with, such as `text-embedding-3-small`, `text-embedding-004`, or
+    /// `nomic-embed-text`. Embeddings from different models can't be compared with each other.
+    ///
+    /// Default: the provider's default model
+    pub model: Option<String>,
+}
+
+/// The services that can embed text, by ID.
+#[derive(Clone, Default)]
+pub struct EmbeddingProviders {
+    providers: Vec<(&'static str, Arc<dyn EmbeddingProvider>)>,
+}
+
+impl Global for EmbeddingProviders {}
+
+impl EmbeddingProviders {
+    /// The provider chosen in the `embedding` settings, which embeds text with the model chosen
+    /// there.
+    pub fn chosen(cx: &App) -> Result<Arc<dyn EmbeddingProvider>> {
+        let id = AllLanguageModelSettings::get_global(cx)
+            .embedding
+            .provider
+            .clone()
+            .unwrap_or_else(|| open_ai::PROVIDER_ID.to_string());
+        cx.try_global::<Self>()
+            .and_then(|this| {
+                this.providers
+                    .iter()
+                    .find(|(provider_id, _)| *provider_id == id)
+            })
+            .map(|(_, provider)| provider.clone())
+            .with_context(|| format!("unknown embedding provider {id:?}"))
+    }
+}
+
+pub(crate) fn init(
+    open_ai_state: Entity<OpenAiState>,
+    google_state: Entity<GoogleState>,
+    grok_state: Entity<GrokState>,
+    http_client: Arc<dyn HttpClient>,
+    cx: &mut App,
+) {
+    let providers: Vec<(&'static str, Arc<dyn EmbeddingProvider>)> = vec![
+        (
+            open_ai::PROVIDER_ID,
+            Arc::new(OpenAiEmbeddingProvider::new(open_ai_state, cx)),
+        ),
+        (
+            grok::PROVIDER_ID,
+            Arc::new(GrokEmbeddingProvider::new(grok_state, cx)),
+        ),
+        (
+            google::PROVIDER_ID,
+            Arc::new(GoogleEmbeddingProvider::new(google_state, cx)),
+        ),
+        (
+            voyage::PROVIDER_ID,
+            Arc::new(VoyageEmbeddingProvider::new(http_client.clone(), cx)),
+        ),
+        (
+            ollama::PROVIDER_ID,
+            Arc::new(OllamaEmbeddingProvider::new(http_client, cx)),
+        ),
+    ];
+    cx.set_global(EmbeddingProviders { providers });
+}
+
+/// A value read from the settings, and from a language model provider's state when it depends on
+/// it, which is read again whenever they change. `semantic_index` embeds text on background
+/// threads, where neither can be read.
+struct Latest<T>(Arc<Mutex<Arc<T>>>);
+
+impl<T: Send + Sync + 'static> Latest<T> {
+    fn new(cx: &mut App, read: impl Fn(&App) -> T + 'static) -> Self {
+        let value = Arc::new(Mutex::new(Arc::new(read(cx))));
+        let weak_value = Arc::downgrade(&value);
+        cx.observe_global::<SettingsStore>(move |cx| {
+            if let Some(value) = weak_value.upgrade() {
+                *value.lock() = Arc::new(read(cx));
+            }
+        })
+        .detach();
+        Self(value)
+    }
+
+    fn of_state<S: 'static>(state: Entity<S>, cx: &mut App, read: fn(&S, &App) -> T) -> Self {
+        let this = Self::new(cx, {
+            let state = state.clone();
+            move |cx| read(state.read(cx), cx)
+        });
+        let weak_value = Arc::downgrade(&this.0);
+        cx.observe(&state, move |state, cx| {
+            if let Some(value) = weak_value.upgrade() {
+                *value.lock() = Arc::new(read(state.read(cx), cx));
+            }
+        })
+        .detach();
+        this
+    }
+
+    fn get(&self) -> Arc<T> {
+        self.0.lock().clone()
+    }
+}
+
+/// The model chosen in the `embedding` settings, or the provider's `default_model`.
+fn chosen_model(cx: &mut App, default_model: &'static str) -> Latest<String> {
+    Latest::new(cx, move |cx| {
+        AllLanguageModelSettings::get_global(cx)
+            .embedding
+            .model
+            .clone()
+            .unwrap_or_else(|| default_model.to_string())
+    })
+}
+
+/// The embeddings of an OpenAI-compatible `/embeddings` response, in the order of the texts.
+fn embeddings_in_order(response: ::open_ai::OpenAiEmbeddingResponse) -> Vec<Embedding> {
+    let mut data = response.data;
+    data.sort_by_key(|data| data.index);
+    data.into_iter()
+        .map(|data| Embedding::new(data.embedding))
+        .collect()
+}
@@ -0,0 +1,60 @@
+use anyhow::Result;
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::{App, Entity};
+use semantic_index::{Embedding, EmbeddingProvider, TextToEmbed};
+
+use super::{Latest, chosen_model};
+pub(super) use crate::provider::google::PROVIDER_ID;
+use crate::provider::google::{GoogleAuth, State};
+
+const DEFAULT_MODEL: &str = "text-embedding-004";
+
+/// Embeds text with Gemini's embedding models, using the Google AI provider's credentials.
+pub struct GoogleEmbeddingProvider {
+    auth: Latest<GoogleAuth>,
+    model: Latest<String>,
+}
+
+impl GoogleEmbeddingProvider {
+    pub fn new(state: Entity<State>, cx: &mut App) -> Self {
+        Self {
+            auth: Latest::of_state(state, cx, GoogleAuth::new),
+            model: chosen_model(cx, DEFAULT_MODEL),
+        }
+    }
+}
+
+impl EmbeddingProvider for GoogleEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        let auth = self.auth.get();
+        let model = self.model.get();
+        async move {
+            let texts = texts
+                .iter()
+                .map(|text| text.text.to_string())
+                .collect::<Vec<_>>();
+            let (authorization, api_key) = auth.next_authorization().await?;
+            let response = google_ai::batch_embed_contents(
+                auth.http_client.as_ref(),
+                &auth.api_url,
+                &authorization,
+                &model,
+                &texts,
+            )
+            .await
+            .inspect_err(|error| auth.record_error(api_key.as_deref(), error))?;
+            Ok(response
+                .embeddings
+                .into_iter()
+                .map(|embedding| Embedding::new(embedding.values))
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        // `batchEmbedContents` takes at most 100 requests.
+        100
+    }
+}
@@ -0,0 +1,53 @@
+use anyhow::Result;
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::{App, Entity};
+use open_ai::Account;
+use semantic_index::{Embedding, EmbeddingProvider, TextToEmbed};
+
+use super::{Latest, chosen_model, embeddings_in_order};
+pub(super) use crate::provider::grok::PROVIDER_ID;
+use crate::provider::grok::{GrokAuth, State};
+
+const DEFAULT_MODEL: &str = "v1";
+
+/// Embeds text with xAI's embedding model, using the Grok provider's credentials.
+pub struct GrokEmbeddingProvider {
+    auth: Latest<GrokAuth>,
+    model: Latest<String>,
+}
+
+impl GrokEmbeddingProvider {
+    pub fn new(state: Entity<State>, cx: &mut App) -> Self {
+        Self {
+            auth: Latest::of_state(state, cx, GrokAuth::new),
+            model: chosen_model(cx, DEFAULT_MODEL),
+        }
+    }
+}
+
+impl EmbeddingProvider for GrokEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        let auth = self.auth.get();
+        let model = self.model.get();
+        async move {
+            let api_key = auth.api_key()?;
+            let response = open_ai::embed(
+                auth.http_client.as_ref(),
+                &auth.api_url,
+                &api_key,
+                &Account::default(),
+                &model,
+                texts.iter().map(|text| text.text),
+            )
+            .await
+            .inspect_err(|error| auth.record_error(&api_key, error))?;
+            Ok(embeddings_in_order(response))
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        128
+    }
+}
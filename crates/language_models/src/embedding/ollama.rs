@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::App;
+use http_client::HttpClient;
+use semantic_index::{Embedding, EmbeddingProvider, TextToEmbed};
+use settings::Settings as _;
+
+use super::{Latest, chosen_model};
+use crate::AllLanguageModelSettings;
+
+pub(super) const PROVIDER_ID: &str = "ollama";
+const DEFAULT_MODEL: &str = "nomic-embed-text";
+
+/// Embeds text with an embedding model served by Ollama, at the Ollama provider's `api_url`.
+pub struct OllamaEmbeddingProvider {
+    http_client: Arc<dyn HttpClient>,
+    api_url: Latest<String>,
+    model: Latest<String>,
+}
+
+impl OllamaEmbeddingProvider {
+    pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut App) -> Self {
+        Self {
+            http_client,
+            api_url: Latest::new(cx, |cx| {
+                AllLanguageModelSettings::get_global(cx)
+                    .ollama
+                    .api_url
+                    .clone()
+            }),
+            model: chosen_model(cx, DEFAULT_MODEL),
+        }
+    }
+}
+
+impl EmbeddingProvider for OllamaEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        let api_url = self.api_url.get();
+        let model = self.model.get();
+        async move {
+            let texts = texts
+                .iter()
+                .map(|text| text.text.to_string())
+                .collect::<Vec<_>>();
+            let response =
+                ollama::embed(self.http_client.as_ref(), &api_url, &model, &texts).await?;
+            Ok(response
+                .embeddings
+                .into_iter()
+                .map(Embedding::new)
+                .collect())
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        64
+    }
+}
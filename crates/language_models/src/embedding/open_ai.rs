@@ -0,0 +1,53 @@
+use anyhow::Result;
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::{App, Entity};
+use open_ai::OpenAiEmbeddingModel;
+use semantic_index::{Embedding, EmbeddingProvider, TextToEmbed};
+
+use super::{Latest, chosen_model, embeddings_in_order};
+use crate::provider::open_ai::{OpenAiAuth, State};
+
+pub(crate) const PROVIDER_ID: &str = "openai";
+
+/// Embeds text with OpenAI's embedding models, using the OpenAI provider's credentials.
+pub struct OpenAiEmbeddingProvider {
+    auth: Latest<OpenAiAuth>,
+    model: Latest<String>,
+}
+
+impl OpenAiEmbeddingProvider {
+    pub fn new(state: Entity<State>, cx: &mut App) -> Self {
+        Self {
+            auth: Latest::of_state(state, cx, OpenAiAuth::new),
+            model: chosen_model(cx, OpenAiEmbeddingModel::TextEmbedding3Small.id()),
+        }
+    }
+}
+
+impl EmbeddingProvider for OpenAiEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        let auth = self.auth.get();
+        let model = self.model.get();
+        async move {
+            let token = auth.bearer_token().await?;
+            let response = open_ai::embed(
+                auth.http_client.as_ref(),
+                &auth.api_url,
+                &token,
+                &auth.account,
+                &model,
+                texts.iter().map(|text| text.text),
+            )
+            .await
+            .inspect_err(|error| auth.record_error(&token, error))?;
+            Ok(embeddings_in_order(response))
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        // From https://platform.openai.com/docs/api-reference/embeddings/create
+        2048
+    }
+}
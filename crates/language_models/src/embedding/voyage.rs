@@ -0,0 +1,152 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result, anyhow};
+use futures::future::BoxFuture;
+use futures::{AsyncReadExt as _, FutureExt as _};
+use gpui::App;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use parking_lot::Mutex;
+use semantic_index::{Embedding, EmbeddingProvider, TextToEmbed};
+use serde::{Deserialize, Serialize};
+use settings::SettingsStore;
+
+use super::{Latest, chosen_model};
+use crate::api_key::{ApiKeys, ServiceApiKeys};
+
+pub(super) const PROVIDER_ID: &str = "voyage";
+const API_URL: &str = "https://api.voyageai.com/v1";
+const API_KEY_VAR: &str = "VOYAGE_API_KEY";
+const DEFAULT_MODEL: &str = "voyage-3.5";
+
+#[derive(Serialize)]
+struct EmbeddingsRequest<'a> {
+    input: Vec<&'a str>,
+    model: &'a str,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    embedding: Vec<f32>,
+    index: usize,
+}
+
+/// Embeds text with Voyage AI, whose key is read from `VOYAGE_API_KEY` or the credentials
+/// store.
+pub struct VoyageEmbeddingProvider {
+    http_client: Arc<dyn HttpClient>,
+    /// The keys as of the last time they were loaded, which happens whenever the settings
+    /// change, since the credentials profile is chosen there.
+    api_keys: Arc<Mutex<Result<ApiKeys, Arc<anyhow::Error>>>>,
+    model: Latest<String>,
+}
+
+impl VoyageEmbeddingProvider {
+    pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut App) -> Self {
+        let api_keys = Arc::new(Mutex::new(Err(Arc::new(anyhow!(
+            "Missing Voyage AI API Key"
+        )))));
+        let service_api_keys = ServiceApiKeys::new(API_KEY_VAR, PROVIDER_ID, API_URL);
+        let weak_api_keys = Arc::downgrade(&api_keys);
+        let load_api_keys = move |cx: &mut App| {
+            let service_api_keys = service_api_keys.clone();
+            let weak_api_keys = weak_api_keys.clone();
+            cx.spawn(async move |cx| {
+                let loaded = service_api_keys.load(cx).await.map_err(Arc::new);
+                if let Some(api_keys) = weak_api_keys.upgrade() {
+                    *api_keys.lock() = loaded;
+                }
+            })
+            .detach();
+        };
+        load_api_keys(cx);
+        cx.observe_global::<SettingsStore>(load_api_keys).detach();
+
+        Self {
+            http_client,
+            api_keys,
+            model: chosen_model(cx, DEFAULT_MODEL),
+        }
+    }
+}
+
+impl EmbeddingProvider for VoyageEmbeddingProvider {
+    fn embed<'a>(&'a self, texts: &'a [TextToEmbed<'a>]) -> BoxFuture<'a, Result<Vec<Embedding>>> {
+        let api_keys = self.api_keys.lock().clone();
+        let model = self.model.get();
+        async move {
+            let api_keys = api_keys.map_err(|error| anyhow!("{error:#}"))?;
+            let api_key = api_keys
+                .current()
+                .ok_or_else(|| anyhow!("Missing Voyage AI API Key"))?;
+
+            let body = serde_json::to_string(&EmbeddingsRequest {
+                input: texts.iter().map(|text| text.text).collect(),
+                model: &model,
+            })?;
+            let http_request = HttpRequest::builder()
+                .method(Method::POST)
+                .uri(format!("{API_URL}/embeddings"))
+                .header("Content-Type", "application/json")
+                .header("Authorization", format!("Bearer {api_key}"))
+                .body(AsyncBody::from(body))?;
+            let mut response = self.http_client.send(http_request).await?;
+            let mut body = String::new();
+            response.body_mut().read_to_string(&mut body).await?;
+            anyhow::ensure!(
+                response.status().is_success(),
+                "Failed to embed text with Voyage AI: {} {}",
+                response.status(),
+                body,
+            );
+            embeddings_from_response(&body)
+        }
+        .boxed()
+    }
+
+    fn batch_size(&self) -> usize {
+        // From https://docs.voyageai.com/reference/embeddings-api
+        1000
+    }
+}
+
+fn embeddings_from_response(body: &str) -> Result<Vec<Embedding>> {
+    let mut response: EmbeddingsResponse =
+        serde_json::from_str(body).context("failed to parse Voyage AI response")?;
+    response.data.sort_by_key(|data| data.index);
+    Ok(response
+        .data
+        .into_iter()
+        .map(|data| Embedding::new(data.embedding))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embeddings_from_response() {
+        let body = r#"{
+            "object": "list",
+            "data": [
+                {"object": "embedding", "embedding": [0.5, 0.25], "index": 1},
+                {"object": "embedding", "embedding": [1.0, 0.0], "index": 0}
+            ],
+            "model": "voyage-3.5",
+            "usage": {"total_tokens": 10}
+        }"#;
+        assert_eq!(
+            embeddings_from_response(body).unwrap(),
+            vec![
+                Embedding::new(vec![1.0, 0.0]),
+                Embedding::new(vec![0.5, 0.25])
+            ]
+        );
+        assert!(embeddings_from_response("{}").is_err());
+    }
+}
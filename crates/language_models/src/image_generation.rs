@@ -0,0 +1,187 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use base64::Engine as _;
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::{App, AsyncApp, Entity};
+use http_client::HttpClient;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings as _;
+
+use crate::AllLanguageModelSettings;
+use crate::provider::grok::State as GrokState;
+use crate::provider::open_ai::State as OpenAiState;
+use crate::service_registry::{Service, ServiceRegistry};
+
+mod grok;
+mod open_ai;
+mod stability;
+
+pub use grok::GrokImageGenerationModel;
+pub use open_ai::OpenAiImageGenerationModel;
+pub use stability::StabilityImageGenerationModel;
+
+/// Which model generates images.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ImageGenerationSettings {
+    /// The model to generate images with, as `provider/model`, like `openai/gpt-image-1`,
+    /// `grok/grok-2-image`, or `stability/core`.
+    ///
+    /// Default: "openai/gpt-image-1"
+    pub model: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageGenerationRequest {
+    pub prompt: String,
+    /// How many images to generate. Models that generate one image per request are sent one
+    /// request per image.
+    pub count: u32,
+    /// The width and height of the images, for models that take a size.
+    pub size: Option<(u32, u32)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct GeneratedImage {
+    pub bytes: Vec<u8>,
+    pub mime_type: &'static str,
+    /// The prompt the image was generated from, for models that rewrite prompts.
+    pub revised_prompt: Option<String>,
+}
+
+/// A model that generates images from text.
+pub trait ImageGenerationModel: Service {
+    /// The ID of the provider, which is the same as that of the provider of its language models
+    /// when they share credentials.
+    fn provider_id(&self) -> &'static str;
+    fn generate(
+        &self,
+        request: ImageGenerationRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Vec<GeneratedImage>>>;
+}
+
+/// The models that can generate images.
+pub type ImageGenerationModels = ServiceRegistry<dyn ImageGenerationModel>;
+
+impl ImageGenerationModels {
+    /// Looks up a model given as `provider/model`.
+    pub fn model(&self, model: &str) -> Option<Arc<dyn ImageGenerationModel>> {
+        let (provider_id, model_id) = model.split_once('/')?;
+        self.services()
+            .iter()
+            .find(|model| model.provider_id() == provider_id && model.id() == model_id)
+            .cloned()
+    }
+
+    /// Generates images with the model chosen in the `image_generation` settings.
+    pub fn generate(
+        request: ImageGenerationRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Vec<GeneratedImage>>> {
+        let model = cx.update(|cx| {
+            let settings = &AllLanguageModelSettings::get_global(cx).image_generation;
+            let model = settings.model.as_deref().unwrap_or("openai/gpt-image-1");
+            Self::global(cx)
+                .model(model)
+                .with_context(|| format!("unknown image generation model {model:?}"))
+        });
+        match model.and_then(|model| model) {
+            Ok(model) => model.generate(request, cx),
+            Err(error) => futures::future::ready(Err(error)).boxed(),
+        }
+    }
+}
+
+pub(crate) fn init(
+    open_ai_state: Entity<OpenAiState>,
+    grok_state: Entity<GrokState>,
+    http_client: Arc<dyn HttpClient>,
+    cx: &mut App,
+) {
+    let mut models: Vec<Arc<dyn ImageGenerationModel>> = vec![
+        Arc::new(OpenAiImageGenerationModel::new(open_ai_state)),
+        Arc::new(GrokImageGenerationModel::new(grok_state)),
+    ];
+    let stability_api_keys = stability::api_keys();
+    models.extend(
+        stability::MODELS
+            .iter()
+            .map(|model| -> Arc<dyn ImageGenerationModel> {
+                Arc::new(StabilityImageGenerationModel::new(
+                    *model,
+                    http_client.clone(),
+                    stability_api_keys.clone(),
+                ))
+            }),
+    );
+    ImageGenerationModels::set_global(models, cx);
+}
+
+/// Decodes the images of an OpenAI-compatible `/images/generations` response.
+fn decode_images(
+    response: ::open_ai::images::ImageGenerationResponse,
+) -> Result<Vec<GeneratedImage>> {
+    response
+        .data
+        .into_iter()
+        .map(|image| {
+            let b64_json = image
+                .b64_json
+                .context("generated image is missing its data")?;
+            let bytes = base64::engine::general_purpose::STANDARD
+                .decode(b64_json)
+                .context("generated image isn't valid base64")?;
+            Ok(GeneratedImage {
+                mime_type: sniff_mime_type(&bytes),
+                bytes,
+                revised_prompt: image.revised_prompt,
+            })
+        })
+        .collect()
+}
+
+fn sniff_mime_type(bytes: &[u8]) -> &'static str {
+    match bytes {
+        [0xFF, 0xD8, 0xFF, ..] => "image/jpeg",
+        [
+            b'R',
+            b'I',
+            b'F',
+            b'F',
+            _,
+            _,
+            _,
+            _,
+            b'W',
+            b'E',
+            b'B',
+            b'P',
+            ..,
+        ] => "image/webp",
+        _ => "image/png",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_images() {
+        let response = serde_json::from_str(
+            r#"{"created": 1, "data": [{"b64_json": "/9j/4AAQ", "revised_prompt": "A red fox"}]}"#,
+        )
+        .unwrap();
+        let images = decode_images(response).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].mime_type, "image/jpeg");
+        assert_eq!(images[0].revised_prompt.as_deref(), Some("A red fox"));
+
+        let response =
+            serde_json::from_str(r#"{"data": [{"url": "https://example.com"}]}"#).unwrap();
+        assert!(decode_images(response).is_err());
+    }
+}
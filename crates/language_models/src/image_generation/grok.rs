@@ -0,0 +1,67 @@
+use anyhow::Result;
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::{AsyncApp, Entity};
+use open_ai::Account;
+
+use super::{GeneratedImage, ImageGenerationModel, ImageGenerationRequest, decode_images};
+use crate::provider::grok::{GrokAuth, PROVIDER_ID, State};
+use crate::service_registry::Service;
+
+/// Generates images with xAI's `grok-2-image`, using the Grok provider's credentials.
+pub struct GrokImageGenerationModel {
+    state: Entity<State>,
+}
+
+impl GrokImageGenerationModel {
+    pub fn new(state: Entity<State>) -> Self {
+        Self { state }
+    }
+}
+
+impl Service for GrokImageGenerationModel {
+    fn id(&self) -> &'static str {
+        "grok-2-image"
+    }
+
+    fn name(&self) -> &'static str {
+        "Grok 2 Image"
+    }
+}
+
+impl ImageGenerationModel for GrokImageGenerationModel {
+    fn provider_id(&self) -> &'static str {
+        PROVIDER_ID
+    }
+
+    fn generate(
+        &self,
+        request: ImageGenerationRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Vec<GeneratedImage>>> {
+        let auth = GrokAuth::read(&self.state, cx);
+        let model = self.id().to_string();
+        async move {
+            let auth = auth?;
+            let api_key = auth.api_key()?;
+            // xAI's images are always 1024x768, so the requested size is ignored.
+            let response = open_ai::images::create_image(
+                auth.http_client.as_ref(),
+                &auth.api_url,
+                &api_key,
+                &Account::default(),
+                open_ai::images::ImageGenerationRequest {
+                    model,
+                    prompt: request.prompt,
+                    n: Some(request.count),
+                    size: None,
+                    response_format: Some("b64_json".to_string()),
+                },
+            )
+            .await
+            .inspect_err(|error| auth.record_error(&api_key, error))?;
+            decode_images(response)
+        }
+        .boxed()
+    }
+}
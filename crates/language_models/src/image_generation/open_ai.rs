@@ -0,0 +1,67 @@
+use anyhow::Result;
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::{AsyncApp, Entity};
+
+use super::{GeneratedImage, ImageGenerationModel, ImageGenerationRequest, decode_images};
+use crate::provider::open_ai::{OpenAiAuth, PROVIDER_ID, State};
+use crate::service_registry::Service;
+
+/// Generates images with `gpt-image-1`, using the OpenAI provider's credentials.
+pub struct OpenAiImageGenerationModel {
+    state: Entity<State>,
+}
+
+impl OpenAiImageGenerationModel {
+    pub fn new(state: Entity<State>) -> Self {
+        Self { state }
+    }
+}
+
+impl Service for OpenAiImageGenerationModel {
+    fn id(&self) -> &'static str {
+        "gpt-image-1"
+    }
+
+    fn name(&self) -> &'static str {
+        "GPT Image 1"
+    }
+}
+
+impl ImageGenerationModel for OpenAiImageGenerationModel {
+    fn provider_id(&self) -> &'static str {
+        PROVIDER_ID
+    }
+
+    fn generate(
+        &self,
+        request: ImageGenerationRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Vec<GeneratedImage>>> {
+        let auth = OpenAiAuth::read(&self.state, cx);
+        let model = self.id().to_string();
+        async move {
+            let auth = auth?;
+            let token = auth.bearer_token().await?;
+            let response = open_ai::images::create_image(
+                auth.http_client.as_ref(),
+                &auth.api_url,
+                &token,
+                &auth.account,
+                open_ai::images::ImageGenerationRequest {
+                    model,
+                    prompt: request.prompt,
+                    n: Some(request.count),
+                    size: request
+                        .size
+                        .map(|(width, height)| format!("{width}x{height}")),
+                    response_format: None,
+                },
+            )
+            .await
+            .inspect_err(|error| auth.record_error(&token, error))?;
+            decode_images(response)
+        }
+        .boxed()
+    }
+}
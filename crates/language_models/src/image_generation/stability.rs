@@ -0,0 +1,205 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result, anyhow};
+use base64::Engine as _;
+use futures::future::BoxFuture;
+use futures::{AsyncReadExt as _, FutureExt as _};
+use gpui::AsyncApp;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::Deserialize;
+
+use super::{GeneratedImage, ImageGenerationModel, ImageGenerationRequest};
+use crate::api_key::ServiceApiKeys;
+use crate::service_registry::Service;
+
+const PROVIDER_ID: &str = "stability";
+const API_URL: &str = "https://api.stability.ai";
+const API_KEY_VAR: &str = "STABILITY_API_KEY";
+const FORM_BOUNDARY: &str = "zed-image-generation";
+/// The aspect ratios that Stability's models generate images in.
+const ASPECT_RATIOS: &[(u32, u32)] = &[
+    (21, 9),
+    (16, 9),
+    (3, 2),
+    (5, 4),
+    (1, 1),
+    (4, 5),
+    (2, 3),
+    (9, 16),
+    (9, 21),
+];
+
+#[derive(Clone, Copy)]
+pub(super) struct StabilityModel {
+    /// The model's name in the path of its endpoint.
+    id: &'static str,
+    name: &'static str,
+}
+
+/// The keys that every Stability model shares.
+pub(super) fn api_keys() -> ServiceApiKeys {
+    ServiceApiKeys::new(API_KEY_VAR, PROVIDER_ID, API_URL)
+}
+
+pub(super) const MODELS: &[StabilityModel] = &[
+    StabilityModel {
+        id: "core",
+        name: "Stable Image Core",
+    },
+    StabilityModel {
+        id: "ultra",
+        name: "Stable Image Ultra",
+    },
+    StabilityModel {
+        id: "sd3",
+        name: "Stable Diffusion 3.5",
+    },
+];
+
+#[derive(Deserialize)]
+struct GenerateResponse {
+    image: String,
+    /// `SUCCESS`, or `CONTENT_FILTERED` when the image was blurred by the content filter.
+    finish_reason: String,
+}
+
+/// Generates images with Stability AI's models, whose key is read from `STABILITY_API_KEY` or
+/// the credentials store.
+pub struct StabilityImageGenerationModel {
+    model: StabilityModel,
+    http_client: Arc<dyn HttpClient>,
+    api_keys: ServiceApiKeys,
+}
+
+impl StabilityImageGenerationModel {
+    pub(super) fn new(
+        model: StabilityModel,
+        http_client: Arc<dyn HttpClient>,
+        api_keys: ServiceApiKeys,
+    ) -> Self {
+        Self {
+            model,
+            http_client,
+            api_keys,
+        }
+    }
+}
+
+impl Service for StabilityImageGenerationModel {
+    fn id(&self) -> &'static str {
+        self.model.id
+    }
+
+    fn name(&self) -> &'static str {
+        self.model.name
+    }
+}
+
+impl ImageGenerationModel for StabilityImageGenerationModel {
+    fn provider_id(&self) -> &'static str {
+        PROVIDER_ID
+    }
+
+    fn generate(
+        &self,
+        request: ImageGenerationRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Vec<GeneratedImage>>> {
+        let http_client = self.http_client.clone();
+        let model_id = self.model.id;
+        let api_keys = self.api_keys.load(cx);
+        async move {
+            let api_keys = api_keys.await?;
+            let api_key = api_keys
+                .current()
+                .ok_or_else(|| anyhow!("Missing Stability API Key"))?;
+
+            let mut fields = vec![("prompt", request.prompt)];
+            fields.push(("output_format", "png".to_string()));
+            if let Some(size) = request.size {
+                let (width, height) = closest_aspect_ratio(size);
+                fields.push(("aspect_ratio", format!("{width}:{height}")));
+            }
+            let body = form_body(&fields);
+
+            // Each request generates a single image.
+            let mut images = Vec::new();
+            for _ in 0..request.count {
+                let http_request = HttpRequest::builder()
+                    .method(Method::POST)
+                    .uri(format!("{API_URL}/v2beta/stable-image/generate/{model_id}"))
+                    .header(
+                        "Content-Type",
+                        format!("multipart/form-data; boundary={FORM_BOUNDARY}"),
+                    )
+                    .header("Accept", "application/json")
+                    .header("Authorization", format!("Bearer {api_key}"))
+                    .body(AsyncBody::from(body.clone()))?;
+                let mut response = http_client.send(http_request).await?;
+                let mut body = String::new();
+                response.body_mut().read_to_string(&mut body).await?;
+                anyhow::ensure!(
+                    response.status().is_success(),
+                    "Failed to generate image with Stability: {} {}",
+                    response.status(),
+                    body,
+                );
+                let response: GenerateResponse =
+                    serde_json::from_str(&body).context("failed to parse Stability response")?;
+                if response.finish_reason == "CONTENT_FILTERED" {
+                    log::warn!("Stability blurred a generated image with its content filter");
+                }
+                images.push(GeneratedImage {
+                    bytes: base64::engine::general_purpose::STANDARD
+                        .decode(response.image)
+                        .context("generated image isn't valid base64")?,
+                    mime_type: "image/png",
+                    revised_prompt: None,
+                });
+            }
+            Ok(images)
+        }
+        .boxed()
+    }
+}
+
+fn closest_aspect_ratio((width, height): (u32, u32)) -> (u32, u32) {
+    let ratio = width as f64 / height.max(1) as f64;
+    ASPECT_RATIOS
+        .iter()
+        .copied()
+        .min_by(|(a_width, a_height), (b_width, b_height)| {
+            let a = (*a_width as f64 / *a_height as f64 - ratio).abs();
+            let b = (*b_width as f64 / *b_height as f64 - ratio).abs();
+            a.total_cmp(&b)
+        })
+        .unwrap_or((1, 1))
+}
+
+/// Encodes text fields as `multipart/form-data`, which is the only encoding Stability's API
+/// accepts.
+fn form_body(fields: &[(&str, String)]) -> String {
+    let mut body = String::new();
+    for (name, value) in fields {
+        body.push_str(&format!(
+            "--{FORM_BOUNDARY}\r\n\
+             Content-Disposition: form-data; name=\"{name}\"\r\n\r\n\
+             {value}\r\n"
+        ));
+    }
+    body.push_str(&format!("--{FORM_BOUNDARY}--\r\n"));
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_aspect_ratio() {
+        assert_eq!(closest_aspect_ratio((1024, 1024)), (1, 1));
+        assert_eq!(closest_aspect_ratio((1920, 1080)), (16, 9));
+        assert_eq!(closest_aspect_ratio((1024, 1536)), (2, 3));
+        assert_eq!(closest_aspect_ratio((100, 0)), (21, 9));
+    }
+}
@@ -0,0 +1,448 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use collections::HashMap;
+use fs::Fs;
+use futures::StreamExt as _;
+use futures::stream::BoxStream;
+use gpui::{AnyElement, App, AsyncApp, BackgroundExecutor, Global, SharedString};
+use language_model::{
+    LanguageModelCompletionError, LanguageModelCompletionEvent, ModelPrice, TokenUsage,
+};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use settings::Settings as _;
+use ui::prelude::*;
+
+use crate::AllLanguageModelSettings;
+use crate::api_key::{ApiKeys, CredentialsScope, WorkspaceScope, mask_api_key};
+use crate::batch::BATCH_PRICE_FACTOR;
+use crate::pricing::model_price;
+use crate::spend_limits::SpendLedger;
+
+/// The tokens that requests made with one API key used in a month, for one model.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeyUsageEntry {
+    /// The calendar month, like `2025-06`.
+    pub month: String,
+    pub provider: String,
+    /// The masked key, which is enough to tell keys apart without storing them.
+    pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    pub model: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_creation_input_tokens: u64,
+    pub cache_read_input_tokens: u64,
+}
+
+impl KeyUsageEntry {
+    fn is_same_bucket(&self, other: &Self) -> bool {
+        self.month == other.month
+            && self.provider == other.provider
+            && self.key == other.key
+            && self.profile == other.profile
+            && self.workspace == other.workspace
+            && self.model == other.model
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_creation_input_tokens += other.cache_creation_input_tokens;
+        self.cache_read_input_tokens += other.cache_read_input_tokens;
+    }
+
+    pub fn total_tokens(&self) -> u64 {
+        self.input_tokens
+            + self.output_tokens
+            + self.cache_creation_input_tokens
+            + self.cache_read_input_tokens
+    }
+
+    /// The estimated cost in US dollars, if the model's price is known.
+    pub fn cost(&self, cx: &App) -> Option<f64> {
+        let price = model_price(
+            AllLanguageModelSettings::get_global(cx),
+            &self.provider,
+            &self.model,
+        )?;
+        let cost = self.input_tokens as f64 * price.input
+            + self.output_tokens as f64 * price.output
+            + self.cache_creation_input_tokens as f64 * price.cache_write.unwrap_or(price.input)
+            + self.cache_read_input_tokens as f64 * price.cache_read.unwrap_or(price.input);
+        Some(cost / 1_000_000.)
+    }
+}
+
+#[derive(Default)]
+struct KeyUsageState {
+    entries: Vec<KeyUsageEntry>,
+    /// Whether the usage saved by previous sessions was read, before which nothing is saved so
+    /// that it isn't overwritten.
+    loaded: bool,
+}
+
+/// How many tokens each API key used, by month, so that spend can be attributed to the client or
+/// team that each key bills.
+#[derive(Clone, Default)]
+pub struct KeyUsageStore {
+    state: Arc<Mutex<KeyUsageState>>,
+    persistence: Option<(Arc<dyn Fs>, BackgroundExecutor)>,
+}
+
+struct GlobalKeyUsageStore(KeyUsageStore);
+
+impl Global for GlobalKeyUsageStore {}
+
+pub fn init(fs: Arc<dyn Fs>, cx: &mut App) {
+    let store = KeyUsageStore {
+        state: Arc::default(),
+        persistence: Some((fs.clone(), cx.background_executor().clone())),
+    };
+    cx.set_global(GlobalKeyUsageStore(store.clone()));
+    cx.background_spawn(async move {
+        let saved = match fs.load(&usage_file_path()).await {
+            Ok(text) => serde_json::from_str::<Vec<KeyUsageEntry>>(&text).unwrap_or_else(|error| {
+                log::error!("failed to read language model key usage: {error}");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        let mut state = store.state.lock();
+        let recorded = std::mem::replace(&mut state.entries, saved);
+        for entry in &recorded {
+            add_entry(&mut state.entries, entry);
+        }
+        state.loaded = true;
+        let should_save = !recorded.is_empty();
+        drop(state);
+        if should_save {
+            store.save();
+        }
+    })
+    .detach();
+}
+
+fn usage_file_path() -> PathBuf {
+    paths::data_dir().join("language_model_key_usage.json")
+}
+
+fn add_entry(entries: &mut Vec<KeyUsageEntry>, entry: &KeyUsageEntry) {
+    match entries
+        .iter_mut()
+        .find(|existing| existing.is_same_bucket(entry))
+    {
+        Some(existing) => existing.add(entry),
+        None => entries.push(entry.clone()),
+    }
+}
+
+impl KeyUsageStore {
+    pub fn global(cx: &App) -> Self {
+        cx.try_global::<GlobalKeyUsageStore>()
+            .map(|store| store.0.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn entries(&self) -> Vec<KeyUsageEntry> {
+        self.state.lock().entries.clone()
+    }
+
+    fn record(&self, entry: KeyUsageEntry) {
+        let mut state = self.state.lock();
+        add_entry(&mut state.entries, &entry);
+        let loaded = state.loaded;
+        drop(state);
+        if loaded {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let Some((fs, executor)) = self.persistence.clone() else {
+            return;
+        };
+        let text = match serde_json::to_string_pretty(&self.state.lock().entries) {
+            Ok(text) => text,
+            Err(error) => {
+                log::error!("failed to serialize language model key usage: {error}");
+                return;
+            }
+        };
+        executor
+            .spawn(async move {
+                if let Err(error) = fs.atomic_write(usage_file_path(), text).await {
+                    log::error!("failed to save language model key usage: {error}");
+                }
+            })
+            .detach();
+    }
+}
+
+fn to_csv(entries: &[KeyUsageEntry], costs: &[Option<f64>]) -> String {
+    let mut csv = String::from(
+        "month,provider,key,profile,workspace,model,requests,input_tokens,output_tokens,cache_creation_input_tokens,cache_read_input_tokens,cost_usd\n",
+    );
+    for (entry, cost) in entries.iter().zip(costs) {
+        let fields = [
+            entry.month.clone(),
+            entry.provider.clone(),
+            entry.key.clone(),
+            entry.profile.clone().unwrap_or_default(),
+            entry.workspace.clone().unwrap_or_default(),
+            entry.model.clone(),
+            entry.requests.to_string(),
+            entry.input_tokens.to_string(),
+            entry.output_tokens.to_string(),
+            entry.cache_creation_input_tokens.to_string(),
+            entry.cache_read_input_tokens.to_string(),
+            cost.map(|cost| format!("{cost:.4}")).unwrap_or_default(),
+        ];
+        let row = fields
+            .iter()
+            .map(|field| {
+                if field.contains([',', '"', '\n']) {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push_str(&row);
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Writes the usage of every API key to a CSV file and reveals it, for attributing a month's
+/// spend.
+pub fn export_key_usage(fs: Arc<dyn Fs>, cx: &mut App) {
+    let entries = KeyUsageStore::global(cx).entries();
+    let costs = entries
+        .iter()
+        .map(|entry| entry.cost(cx))
+        .collect::<Vec<_>>();
+    let csv = to_csv(&entries, &costs);
+    let path = paths::data_dir().join("language_model_key_usage.csv");
+    cx.spawn(async move |cx| {
+        fs.atomic_write(path.clone(), csv).await?;
+        cx.update(|cx| cx.reveal_path(&path))
+    })
+    .detach_and_log_err(cx);
+}
+
+/// Attributes the tokens used by one completion to the API key it was sent with, and adds what
+/// they cost to the provider's spend.
+#[derive(Clone)]
+pub(crate) struct KeyUsageRecorder {
+    store: KeyUsageStore,
+    spend: SpendLedger,
+    provider: &'static str,
+    model: String,
+    price: Option<ModelPrice>,
+    profile: Option<SharedString>,
+    /// The workspace that the request was made from.
+    workspace: Option<WorkspaceScope>,
+    key: Arc<Mutex<Option<String>>>,
+}
+
+impl KeyUsageRecorder {
+    pub(crate) fn new(
+        provider: &'static str,
+        model: impl Into<String>,
+        workspace: Option<WorkspaceScope>,
+        cx: &AsyncApp,
+    ) -> Self {
+        let model = model.into();
+        Self {
+            store: cx
+                .try_read_global(|store: &GlobalKeyUsageStore, _| store.0.clone())
+                .unwrap_or_default(),
+            spend: SpendLedger::read_global(cx),
+            provider,
+            price: AllLanguageModelSettings::try_read_global(cx, |settings| {
+                model_price(settings, provider, &model)
+            })
+            .flatten(),
+            model,
+            profile: cx
+                .try_read_global(|scope: &CredentialsScope, _| scope.profile.clone())
+                .flatten(),
+            workspace,
+            key: Arc::default(),
+        }
+    }
+
+    /// Prices the completion at the discount that batch APIs give.
+    pub(crate) fn batched(mut self) -> Self {
+        self.price = self.price.map(|price| ModelPrice {
+            input: price.input * BATCH_PRICE_FACTOR,
+            output: price.output * BATCH_PRICE_FACTOR,
+            cache_write: price.cache_write.map(|price| price * BATCH_PRICE_FACTOR),
+            cache_read: price.cache_read.map(|price| price * BATCH_PRICE_FACTOR),
+        });
+        self
+    }
+
+    /// Called with the key that the request is sent with, once it was chosen.
+    pub(crate) fn set_api_key(&self, api_key: &str) {
+        *self.key.lock() = Some(mask_api_key(api_key));
+    }
+
+    /// Records the last usage that `events` reported once the completion finishes or is dropped.
+    pub(crate) fn track(
+        self,
+        events: BoxStream<
+            'static,
+            Result<LanguageModelCompletionEvent, LanguageModelCompletionError>,
+        >,
+    ) -> BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>
+    {
+        let mut recording = UsageRecording {
+            recorder: self,
+            usage: None,
+        };
+        events
+            .inspect(move |event| {
+                if let Ok(LanguageModelCompletionEvent::UsageUpdate(usage)) = event {
+                    recording.usage = Some(*usage);
+                }
+            })
+            .boxed()
+    }
+}
+
+struct UsageRecording {
+    recorder: KeyUsageRecorder,
+    usage: Option<TokenUsage>,
+}
+
+impl Drop for UsageRecording {
+    fn drop(&mut self) {
+        let Some(usage) = self.usage else {
+            return;
+        };
+        let recorder = &self.recorder;
+        if let Some(price) = recorder.price {
+            recorder.spend.record(recorder.provider, price.cost(&usage));
+        }
+        let Some(key) = recorder.key.lock().clone() else {
+            return;
+        };
+        recorder.store.record(KeyUsageEntry {
+            month: chrono::Local::now().format("%Y-%m").to_string(),
+            provider: recorder.provider.to_string(),
+            key,
+            profile: recorder.profile.as_ref().map(ToString::to_string),
+            workspace: recorder
+                .workspace
+                .as_ref()
+                .map(|workspace| workspace.name.to_string()),
+            model: recorder.model.clone(),
+            requests: 1,
+            input_tokens: usage.input_tokens.into(),
+            output_tokens: usage.output_tokens.into(),
+            cache_creation_input_tokens: usage.cache_creation_input_tokens.into(),
+            cache_read_input_tokens: usage.cache_read_input_tokens.into(),
+        });
+    }
+}
+
+/// This month's usage of each of the provider's keys, for its configuration view.
+pub fn render_key_usage(provider: &str, api_keys: &ApiKeys, cx: &App) -> Option<AnyElement> {
+    let month = chrono::Local::now().format("%Y-%m").to_string();
+    let entries = KeyUsageStore::global(cx).entries();
+    let mut usage_by_key = HashMap::<String, (u64, Option<f64>)>::default();
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.month == month && entry.provider == provider)
+    {
+        let (tokens, cost) = usage_by_key
+            .entry(entry.key.clone())
+            .or_insert((0, Some(0.)));
+        *tokens += entry.total_tokens();
+        *cost = cost.zip(entry.cost(cx)).map(|(total, cost)| total + cost);
+    }
+
+    let lines = api_keys
+        .iter()
+        .filter_map(|api_key| {
+            let key = mask_api_key(api_key);
+            let (tokens, cost) = usage_by_key.get(&key)?;
+            let mut line = format!("{key}: {} tokens this month", format_tokens(*tokens));
+            if let Some(cost) = cost {
+                line.push_str(&format!(", about ${cost:.2}"));
+            }
+            Some(SharedString::from(line))
+        })
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        return None;
+    }
+    Some(
+        v_flex()
+            .children(
+                lines
+                    .into_iter()
+                    .map(|line| Label::new(line).size(LabelSize::Small).color(Color::Muted)),
+            )
+            .into_any_element(),
+    )
+}
+
+fn format_tokens(tokens: u64) -> String {
+    match tokens {
+        0..1_000 => tokens.to_string(),
+        1_000..1_000_000 => format!("{:.1}K", tokens as f64 / 1_000.),
+        _ => format!("{:.1}M", tokens as f64 / 1_000_000.),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_usage_entries() {
+        let entry = KeyUsageEntry {
+            month: "2025-06".into(),
+            provider: "anthropic".into(),
+            key: "••••abcd".into(),
+            profile: Some("client, inc".into()),
+            workspace: None,
+            model: "claude-sonnet-4".into(),
+            requests: 1,
+            input_tokens: 1_000,
+            output_tokens: 200,
+            cache_creation_input_tokens: 0,
+            cache_read_input_tokens: 0,
+        };
+        let mut entries = Vec::new();
+        add_entry(&mut entries, &entry);
+        add_entry(&mut entries, &entry);
+        add_entry(
+            &mut entries,
+            &KeyUsageEntry {
+                model: "claude-opus-4".into(),
+                ..entry.clone()
+            },
+        );
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].requests, 2);
+        assert_eq!(entries[0].total_tokens(), 2_400);
+
+        assert_eq!(
+            to_csv(&entries[..1], &[Some(0.012)]).lines().nth(1),
+            Some(
+                "2025-06,anthropic,••••abcd,\"client, inc\",,claude-sonnet-4,2,2000,400,0,0,0.0120"
+            )
+        );
+    }
+}
@@ -2,34 +2,282 @@ use std::sync::Arc;
 
 use client::{Client, UserStore};
 use fs::Fs;
-use gpui::{App, Context, Entity};
-use language_model::LanguageModelRegistry;
+use gpui::{App, Context, Entity, actions, impl_actions};
+use language_model::{
+    LanguageModelProviderId, LanguageModelRegistry, SelectedModel, ZED_CLOUD_PROVIDER_ID,
+};
 use provider::deepseek::DeepSeekLanguageModelProvider;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use settings::{Settings as _, SettingsStore, update_settings_file};
 
+pub mod api_key;
+mod batch;
+pub mod configure;
+pub mod embedding;
+pub mod image_generation;
+pub mod key_usage;
+mod live_usage;
+mod model_family;
+pub mod moderation;
+mod normalize_messages;
+pub mod oauth;
+mod output_tokens;
+mod partial_tool_use;
+pub mod pricing;
+mod prompt_template;
 pub mod provider;
+mod provider_http_client;
+pub mod realtime;
+mod reasoning;
+mod request_budget;
+pub mod request_log;
+pub mod rerank;
+mod response_cache;
+pub mod service_registry;
 mod settings;
+pub mod speech;
+pub mod spend_limits;
+mod stream_timeouts;
+pub mod transcription;
 pub mod ui;
+pub mod usage_history;
+pub mod warnings;
 
+use crate::api_key::CredentialsScope;
+use crate::provider::alias::AliasLanguageModelProvider;
 use crate::provider::anthropic::AnthropicLanguageModelProvider;
+use crate::provider::balanced::BalancedLanguageModelProvider;
 use crate::provider::bedrock::BedrockLanguageModelProvider;
 use crate::provider::cloud::CloudLanguageModelProvider;
 use crate::provider::copilot_chat::CopilotChatLanguageModelProvider;
 use crate::provider::google::GoogleLanguageModelProvider;
 use crate::provider::grok::GrokLanguageModelProvider;
 use crate::provider::lmstudio::LmStudioLanguageModelProvider;
+use crate::provider::local::LocalLanguageModelProvider;
 use crate::provider::mistral::MistralLanguageModelProvider;
 use crate::provider::ollama::OllamaLanguageModelProvider;
 use crate::provider::open_ai::OpenAiLanguageModelProvider;
+use crate::provider_http_client::ProviderHttpClient;
 pub use crate::settings::*;
+use crate::usage_history::UsageExportFormat;
+
+actions!(
+    language_models,
+    [
+        CycleApiKeyProfile,
+        ExportKeyUsage,
+        OpenUsageHistory,
+        OverrideSpendLimits
+    ]
+);
+
+/// Switches every provider to the API keys saved in Zed for another credentials profile.
+///
+/// Only keys entered in Zed's configuration views are kept per profile. Everything else about the
+/// providers, like their `api_url`, models, and keys read from environment variables,
+/// `api_key_command`, or `api_key_file`, stays the same.
+#[derive(Clone, Default, Debug, PartialEq, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SwitchApiKeyProfile {
+    /// The profile to switch to, or `null` for the keys saved outside of any profile.
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// Writes the usage of every request made in the last three months to a file.
+#[derive(Clone, Default, Debug, PartialEq, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ExportUsageHistory {
+    #[serde(default)]
+    pub format: UsageExportFormat,
+}
+
+impl_actions!(language_models, [ExportUsageHistory, SwitchApiKeyProfile]);
 
 pub fn init(user_store: Entity<UserStore>, client: Arc<Client>, fs: Arc<dyn Fs>, cx: &mut App) {
-    crate::settings::init(fs, cx);
+    crate::settings::init(fs.clone(), cx);
+    key_usage::init(fs.clone(), cx);
+    spend_limits::init(fs.clone(), cx);
+    usage_history::init(fs.clone(), cx);
+    response_cache::init(fs.clone(), cx);
+    request_log::init(cx);
+    warnings::init(cx);
+    cx.on_action({
+        let fs = fs.clone();
+        move |_: &ExportKeyUsage, cx| key_usage::export_key_usage(fs.clone(), cx)
+    });
+    cx.on_action({
+        let fs = fs.clone();
+        move |action: &ExportUsageHistory, cx| {
+            usage_history::export_usage_history(action.format, fs.clone(), cx)
+        }
+    });
+    init_credentials_profiles(fs, cx);
+    init_disabled_providers(cx);
+    init_fallback_models(cx);
     let registry = LanguageModelRegistry::global(cx);
     registry.update(cx, |registry, cx| {
         register_language_model_providers(registry, user_store, client, cx);
     });
 }
 
+fn init_fallback_models(cx: &mut App) {
+    fn parse_model(model: &str) -> Option<SelectedModel> {
+        model
+            .parse()
+            .map_err(|error| log::error!("invalid fallback model: {error}"))
+            .ok()
+    }
+
+    fn sync_fallback_models(cx: &mut App) {
+        let fallback_models = AllLanguageModelSettings::get_global(cx)
+            .fallback_models
+            .iter()
+            .filter_map(|(model, fallbacks)| {
+                let fallbacks = fallbacks
+                    .iter()
+                    .filter_map(|fallback| parse_model(fallback))
+                    .collect();
+                Some((parse_model(model)?, fallbacks))
+            })
+            .collect();
+        LanguageModelRegistry::global(cx).update(cx, |registry, cx| {
+            registry.set_fallback_models(fallback_models, cx);
+        });
+    }
+
+    sync_fallback_models(cx);
+    cx.observe_global::<SettingsStore>(sync_fallback_models)
+        .detach();
+}
+
+fn init_disabled_providers(cx: &mut App) {
+    fn sync_disabled_providers(cx: &mut App) {
+        let disabled_providers = AllLanguageModelSettings::get_global(cx)
+            .disabled_providers
+            .clone();
+        LanguageModelRegistry::global(cx).update(cx, |registry, cx| {
+            registry.set_disabled_providers(disabled_providers, cx);
+        });
+    }
+
+    sync_disabled_providers(cx);
+    cx.observe_global::<SettingsStore>(sync_disabled_providers)
+        .detach();
+}
+
+/// Turns a provider on or off by setting its `enabled` setting.
+pub fn set_provider_enabled(
+    provider_id: LanguageModelProviderId,
+    enabled: bool,
+    fs: Arc<dyn Fs>,
+    cx: &mut App,
+) {
+    update_settings_file::<AllLanguageModelSettings>(fs, cx, move |settings, _| {
+        let enabled = Some(enabled);
+        match &*provider_id.0 {
+            provider::anthropic::PROVIDER_ID => {
+                let (mut content, _) = settings
+                    .anthropic
+                    .take()
+                    .map(|content| content.upgrade())
+                    .unwrap_or_default();
+                content.enabled = enabled;
+                settings.anthropic = Some(AnthropicSettingsContent::Versioned(
+                    VersionedAnthropicSettingsContent::V1(content),
+                ));
+            }
+            provider::open_ai::PROVIDER_ID => {
+                let (mut content, _) = settings
+                    .openai
+                    .take()
+                    .map(|content| content.upgrade())
+                    .unwrap_or_default();
+                content.enabled = enabled;
+                settings.openai = Some(OpenAiSettingsContent::Versioned(
+                    VersionedOpenAiSettingsContent::V1(content),
+                ));
+            }
+            provider::bedrock::PROVIDER_ID => {
+                settings.bedrock.get_or_insert_default().enabled = enabled;
+            }
+            provider::ollama::PROVIDER_ID => {
+                settings.ollama.get_or_insert_default().enabled = enabled;
+            }
+            provider::lmstudio::PROVIDER_ID => {
+                settings.lmstudio.get_or_insert_default().enabled = enabled;
+            }
+            provider::local::PROVIDER_ID => {
+                settings.local.get_or_insert_default().enabled = enabled;
+            }
+            provider::deepseek::PROVIDER_ID => {
+                settings.deepseek.get_or_insert_default().enabled = enabled;
+            }
+            ZED_CLOUD_PROVIDER_ID => {
+                settings.zed_dot_dev.get_or_insert_default().enabled = enabled;
+            }
+            provider::google::PROVIDER_ID => {
+                settings.google.get_or_insert_default().enabled = enabled;
+            }
+            provider::copilot_chat::PROVIDER_ID => {
+                settings.copilot_chat.get_or_insert_default().enabled = enabled;
+            }
+            provider::mistral::PROVIDER_ID => {
+                settings.mistral.get_or_insert_default().enabled = enabled;
+            }
+            provider::grok::PROVIDER_ID => {
+                settings.grok.get_or_insert_default().enabled = enabled;
+            }
+            provider::balanced::PROVIDER_ID => {
+                settings.balanced.get_or_insert_default().enabled = enabled;
+            }
+            _ => log::error!("no enabled setting for language model provider {provider_id:?}"),
+        }
+    });
+}
+
+fn init_credentials_profiles(fs: Arc<dyn Fs>, cx: &mut App) {
+    fn sync_profile(cx: &mut App) {
+        let profile = AllLanguageModelSettings::get_global(cx)
+            .credentials_profile
+            .clone();
+        CredentialsScope::set_profile(profile.map(Into::into), cx);
+    }
+
+    sync_profile(cx);
+    cx.observe_global::<SettingsStore>(sync_profile).detach();
+
+    cx.on_action({
+        let fs = fs.clone();
+        move |action: &SwitchApiKeyProfile, cx| {
+            set_credentials_profile(action.name.clone(), fs.clone(), cx);
+        }
+    });
+    cx.on_action(move |_: &CycleApiKeyProfile, cx| {
+        let settings = AllLanguageModelSettings::get_global(cx);
+        let profiles = std::iter::once(None)
+            .chain(settings.credentials_profiles.iter().cloned().map(Some))
+            .collect::<Vec<_>>();
+        let next_ix = profiles
+            .iter()
+            .position(|profile| *profile == settings.credentials_profile)
+            .map_or(0, |ix| (ix + 1) % profiles.len());
+        let next = profiles.into_iter().nth(next_ix).flatten();
+        set_credentials_profile(next, fs.clone(), cx);
+    });
+}
+
+fn set_credentials_profile(profile: Option<String>, fs: Arc<dyn Fs>, cx: &mut App) {
+    log::info!(
+        "switching language model credentials profile to {}",
+        profile.as_deref().unwrap_or("the default profile")
+    );
+    update_settings_file::<AllLanguageModelSettings>(fs, cx, move |settings, _| {
+        settings.credentials_profile = profile;
+    });
+}
+
 fn register_language_model_providers(
     registry: &mut LanguageModelRegistry,
     user_store: Entity<UserStore>,
@@ -42,13 +290,21 @@ fn register_language_model_providers(
     );
 
     registry.register_provider(
-        AnthropicLanguageModelProvider::new(client.http_client(), cx),
+        AnthropicLanguageModelProvider::new(
+            ProviderHttpClient::new(client.http_client(), |settings| &settings.anthropic.api, cx),
+            cx,
+        ),
         cx,
     );
-    registry.register_provider(
-        OpenAiLanguageModelProvider::new(client.http_client(), cx),
+    let open_ai = OpenAiLanguageModelProvider::new(
+        ProviderHttpClient::new(client.http_client(), |settings| &settings.openai.api, cx),
         cx,
     );
+    let open_ai_state = open_ai.state();
+    speech::init(open_ai_state.clone(), client.http_client(), cx);
+    transcription::init(open_ai_state.clone(), client.http_client(), cx);
+    moderation::init(open_ai_state.clone(), cx);
+    registry.register_provider(open_ai, cx);
     registry.register_provider(
         OllamaLanguageModelProvider::new(client.http_client(), cx),
         cx,
@@ -58,21 +314,48 @@ fn register_language_model_providers(
         cx,
     );
     registry.register_provider(
-        DeepSeekLanguageModelProvider::new(client.http_client(), cx),
+        LocalLanguageModelProvider::new(client.http_client(), cx),
         cx,
     );
     registry.register_provider(
-        GoogleLanguageModelProvider::new(client.http_client(), cx),
+        DeepSeekLanguageModelProvider::new(
+            ProviderHttpClient::new(client.http_client(), |settings| &settings.deepseek.api, cx),
+            cx,
+        ),
+        cx,
+    );
+    let google = GoogleLanguageModelProvider::new(
+        ProviderHttpClient::new(client.http_client(), |settings| &settings.google.api, cx),
         cx,
     );
+    let google_state = google.state();
+    registry.register_provider(google, cx);
     registry.register_provider(
-        MistralLanguageModelProvider::new(client.http_client(), cx),
+        MistralLanguageModelProvider::new(
+            ProviderHttpClient::new(client.http_client(), |settings| &settings.mistral.api, cx),
+            cx,
+        ),
         cx,
     );
     registry.register_provider(
         BedrockLanguageModelProvider::new(client.http_client(), cx),
         cx,
     );
-    registry.register_provider(GrokLanguageModelProvider::new(client.http_client(), cx), cx);
+    let grok = GrokLanguageModelProvider::new(
+        ProviderHttpClient::new(client.http_client(), |settings| &settings.grok.api, cx),
+        cx,
+    );
+    embedding::init(
+        open_ai_state.clone(),
+        google_state,
+        grok.state(),
+        client.http_client(),
+        cx,
+    );
+    image_generation::init(open_ai_state, grok.state(), client.http_client(), cx);
+    rerank::init(client.http_client(), cx);
+    registry.register_provider(grok, cx);
     registry.register_provider(CopilotChatLanguageModelProvider::new(cx), cx);
+    registry.register_provider(BalancedLanguageModelProvider::new(cx), cx);
+    registry.register_provider(AliasLanguageModelProvider::new(cx), cx);
 }
@@ -0,0 +1,149 @@
+use futures::StreamExt as _;
+use futures::stream::{self, BoxStream};
+use language_model::{LanguageModelCompletionError, LanguageModelCompletionEvent, TokenUsage};
+
+/// A rough number of bytes per token, used to estimate output tokens before the provider
+/// reports them.
+const BYTES_PER_TOKEN: usize = 4;
+
+/// How many estimated tokens have to accumulate before another usage update is emitted.
+const ESTIMATE_STEP: u32 = 16;
+
+/// Interleaves estimated [`LanguageModelCompletionEvent::UsageUpdate`]s into a completion for
+/// providers that only report usage once the response is complete, so that token consumption can
+/// be shown while the response is still streaming.
+///
+/// The estimates build on the last usage the provider reported, and are replaced as soon as it
+/// reports usage again.
+pub(crate) fn estimate_live_usage(
+    events: BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+) -> BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>> {
+    let mut estimator = UsageEstimator::default();
+    events
+        .flat_map(move |event| {
+            let estimate = event
+                .as_ref()
+                .ok()
+                .and_then(|event| estimator.push_event(event));
+            stream::iter(
+                std::iter::once(event).chain(
+                    estimate.map(|usage| Ok(LanguageModelCompletionEvent::UsageUpdate(usage))),
+                ),
+            )
+        })
+        .boxed()
+}
+
+#[derive(Default)]
+struct UsageEstimator {
+    reported: TokenUsage,
+    output_bytes: usize,
+    reasoning_bytes: usize,
+    emitted_tokens: u32,
+}
+
+impl UsageEstimator {
+    fn push_event(&mut self, event: &LanguageModelCompletionEvent) -> Option<TokenUsage> {
+        match event {
+            LanguageModelCompletionEvent::UsageUpdate(usage) => {
+                *self = Self {
+                    reported: *usage,
+                    ..Self::default()
+                };
+                return None;
+            }
+            LanguageModelCompletionEvent::Text(text) => self.output_bytes += text.len(),
+            LanguageModelCompletionEvent::Thinking { text, .. } => {
+                self.output_bytes += text.len();
+                self.reasoning_bytes += text.len();
+            }
+            LanguageModelCompletionEvent::ToolUse(tool_use) if tool_use.is_input_complete => {
+                self.output_bytes += tool_use.name.len() + tool_use.raw_input.len();
+            }
+            LanguageModelCompletionEvent::ExecutableCode { code, .. } => {
+                self.output_bytes += code.len();
+            }
+            LanguageModelCompletionEvent::ToolUse(_)
+            | LanguageModelCompletionEvent::RedactedThinking { .. }
+            | LanguageModelCompletionEvent::StatusUpdate(_)
+            | LanguageModelCompletionEvent::Fallback { .. }
+            | LanguageModelCompletionEvent::Candidate { .. }
+            | LanguageModelCompletionEvent::Logprobs(_)
+            | LanguageModelCompletionEvent::Citations(_)
+            | LanguageModelCompletionEvent::GuardrailIntervention(_)
+            | LanguageModelCompletionEvent::UploadProgress { .. }
+            | LanguageModelCompletionEvent::CodeExecutionResult { .. }
+            | LanguageModelCompletionEvent::Stop(_)
+            | LanguageModelCompletionEvent::StartMessage { .. } => return None,
+        }
+
+        let tokens = (self.output_bytes / BYTES_PER_TOKEN) as u32;
+        if tokens < self.emitted_tokens + ESTIMATE_STEP {
+            return None;
+        }
+        self.emitted_tokens = tokens;
+        Some(TokenUsage {
+            output_tokens: self.reported.output_tokens + tokens,
+            reasoning_tokens: self.reported.reasoning_tokens
+                + (self.reasoning_bytes / BYTES_PER_TOKEN) as u32,
+            ..self.reported
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimates_build_on_reported_usage() {
+        let mut estimator = UsageEstimator::default();
+        let reported = TokenUsage {
+            input_tokens: 100,
+            output_tokens: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            estimator.push_event(&LanguageModelCompletionEvent::UsageUpdate(reported)),
+            None
+        );
+        assert_eq!(
+            estimator.push_event(&LanguageModelCompletionEvent::Text("a".repeat(40))),
+            None
+        );
+        assert_eq!(
+            estimator.push_event(&LanguageModelCompletionEvent::Thinking {
+                text: "a".repeat(40),
+                signature: None,
+            }),
+            Some(TokenUsage {
+                input_tokens: 100,
+                output_tokens: 21,
+                reasoning_tokens: 10,
+                ..Default::default()
+            })
+        );
+        assert_eq!(
+            estimator.push_event(&LanguageModelCompletionEvent::Text("a".repeat(40))),
+            None
+        );
+
+        let reported = TokenUsage {
+            input_tokens: 100,
+            output_tokens: 40,
+            ..Default::default()
+        };
+        assert_eq!(
+            estimator.push_event(&LanguageModelCompletionEvent::UsageUpdate(reported)),
+            None
+        );
+        assert_eq!(
+            estimator.push_event(&LanguageModelCompletionEvent::Text("a".repeat(64))),
+            Some(TokenUsage {
+                input_tokens: 100,
+                output_tokens: 56,
+                ..Default::default()
+            })
+        );
+    }
+}
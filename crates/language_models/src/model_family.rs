@@ -0,0 +1,394 @@
+/// A family of models that share a tokenizer, list prices, and capabilities, like the dated
+/// snapshots of a model, or the names that hosting services give to the same open-weight model.
+///
+/// Models configured in settings can have any name, so this is how Zed knows what a model like
+/// `grok-3-beta` or `llama-3.3-70b-versatile` is when nothing more specific describes it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct ModelFamily {
+    pub tokenizer: Tokenizer,
+    /// The built-in model whose list price models in this family have, when served by the
+    /// provider that offers it.
+    pub price_model: Option<&'static str>,
+    pub supports_tools: bool,
+    pub supports_images: bool,
+    pub supports_thinking: bool,
+}
+
+/// The tiktoken encodings that token counts can be estimated with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Tokenizer {
+    Cl100kBase,
+    O200kBase,
+}
+
+impl Tokenizer {
+    /// A model that `tiktoken_rs` counts tokens for with this encoding.
+    pub fn tiktoken_model(self) -> &'static str {
+        match self {
+            Tokenizer::Cl100kBase => "gpt-4",
+            Tokenizer::O200kBase => "gpt-4o",
+        }
+    }
+}
+
+const CL100K_BASE: ModelFamily = ModelFamily {
+    tokenizer: Tokenizer::Cl100kBase,
+    price_model: None,
+    supports_tools: true,
+    supports_images: false,
+    supports_thinking: false,
+};
+
+const O200K_BASE: ModelFamily = ModelFamily {
+    tokenizer: Tokenizer::O200kBase,
+    ..CL100K_BASE
+};
+
+/// The prefixes of the model names in each family. More specific prefixes come first, since the
+/// first family that matches is used.
+///
+/// Other vendors' tokenizers aren't available, so their families use the encoding whose token
+/// counts are closest: Llama 3 extends `cl100k_base`, and Grok's vocabulary is closer in size to
+/// `o200k_base`.
+const FAMILIES: &[(&[&str], ModelFamily)] = &[
+    (
+        &["gpt-4o-mini"],
+        ModelFamily {
+            price_model: Some("gpt-4o-mini"),
+            supports_images: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["gpt-4o", "chatgpt-4o"],
+        ModelFamily {
+            price_model: Some("gpt-4o"),
+            supports_images: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["gpt-4.1-nano"],
+        ModelFamily {
+            price_model: Some("gpt-4.1-nano"),
+            supports_images: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["gpt-4.1-mini"],
+        ModelFamily {
+            price_model: Some("gpt-4.1-mini"),
+            supports_images: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["gpt-4.1"],
+        ModelFamily {
+            price_model: Some("gpt-4.1"),
+            supports_images: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["gpt-4-turbo"],
+        ModelFamily {
+            price_model: Some("gpt-4-turbo"),
+            supports_images: true,
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &["gpt-4"],
+        ModelFamily {
+            price_model: Some("gpt-4"),
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &["gpt-3.5-turbo"],
+        ModelFamily {
+            price_model: Some("gpt-3.5-turbo"),
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &["o1-mini"],
+        ModelFamily {
+            price_model: Some("o1-mini"),
+            supports_tools: false,
+            supports_thinking: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["o1-preview"],
+        ModelFamily {
+            price_model: Some("o1-preview"),
+            supports_tools: false,
+            supports_thinking: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["o1"],
+        ModelFamily {
+            price_model: Some("o1"),
+            supports_images: true,
+            supports_thinking: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["o3-mini"],
+        ModelFamily {
+            price_model: Some("o3-mini"),
+            supports_thinking: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["o3"],
+        ModelFamily {
+            price_model: Some("o3"),
+            supports_images: true,
+            supports_thinking: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["o4-mini"],
+        ModelFamily {
+            price_model: Some("o4-mini"),
+            supports_images: true,
+            supports_thinking: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["grok-3-mini-fast"],
+        ModelFamily {
+            price_model: Some("grok-3-mini-fast-latest"),
+            supports_thinking: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["grok-3-mini"],
+        ModelFamily {
+            price_model: Some("grok-3-mini-latest"),
+            supports_thinking: true,
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["grok-3-fast"],
+        ModelFamily {
+            price_model: Some("grok-3-fast-latest"),
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["grok-3"],
+        ModelFamily {
+            price_model: Some("grok-3-latest"),
+            ..O200K_BASE
+        },
+    ),
+    (
+        &["grok-2-vision"],
+        ModelFamily {
+            supports_images: true,
+            ..O200K_BASE
+        },
+    ),
+    (&["grok-2"], O200K_BASE),
+    (
+        &["llama-4", "llama4"],
+        ModelFamily {
+            supports_images: true,
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &[
+            "llama-3.2-11b-vision",
+            "llama-3.2-90b-vision",
+            "llama3.2-vision",
+        ],
+        ModelFamily {
+            supports_images: true,
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &[
+            "llama-3",
+            "llama-3.",
+            "llama3",
+            "llama3.",
+            "llama-v3",
+            "llama-v3p1",
+            "llama-v3p2",
+            "llama-v3p3",
+        ],
+        CL100K_BASE,
+    ),
+    (
+        &["deepseek-reasoner", "deepseek-r1"],
+        ModelFamily {
+            price_model: Some("deepseek-reasoner"),
+            supports_thinking: true,
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &["deepseek-chat", "deepseek-v3"],
+        ModelFamily {
+            price_model: Some("deepseek-chat"),
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &["qwen3", "qwq"],
+        ModelFamily {
+            supports_thinking: true,
+            ..CL100K_BASE
+        },
+    ),
+    (&["qwen", "qwen2", "qwen2."], CL100K_BASE),
+    (
+        &["magistral"],
+        ModelFamily {
+            supports_thinking: true,
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &["codestral"],
+        ModelFamily {
+            price_model: Some("codestral-latest"),
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &["devstral-small"],
+        ModelFamily {
+            price_model: Some("devstral-small-latest"),
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &["mistral-large"],
+        ModelFamily {
+            price_model: Some("mistral-large-latest"),
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &["mistral-medium"],
+        ModelFamily {
+            price_model: Some("mistral-medium-latest"),
+            supports_images: true,
+            ..CL100K_BASE
+        },
+    ),
+    (
+        &["mistral-small"],
+        ModelFamily {
+            price_model: Some("mistral-small-latest"),
+            supports_images: true,
+            ..CL100K_BASE
+        },
+    ),
+];
+
+impl ModelFamily {
+    /// The family of the model with the given name, if Zed knows it.
+    ///
+    /// Names are matched case-insensitively, without the organization or path that some services
+    /// put before them, like `meta-llama/`, and fine-tuned models are matched by the model they
+    /// were trained from.
+    pub fn infer(model: &str) -> Option<Self> {
+        let model = model.to_lowercase();
+        let model = model.rsplit('/').next().unwrap_or(&model);
+        let model = open_ai::models::FineTunedModelId::parse(model)
+            .map_or(model, |fine_tuned| fine_tuned.base_model);
+        FAMILIES.iter().find_map(|(prefixes, family)| {
+            prefixes
+                .iter()
+                .any(|prefix| matches_prefix(model, prefix))
+                .then_some(*family)
+        })
+    }
+}
+
+/// Whether the model's name starts with the prefix, followed by a separator like `-` or `:`
+/// rather than more of a version, so that `gpt-4` doesn't match `gpt-4.1`. Prefixes ending in a
+/// separator, like `llama-3.`, match any version that follows.
+fn matches_prefix(model: &str, prefix: &str) -> bool {
+    let Some(rest) = model.strip_prefix(prefix) else {
+        return false;
+    };
+    !prefix.ends_with(|c: char| c.is_ascii_alphanumeric())
+        || !rest.starts_with(|c: char| c.is_ascii_alphanumeric() || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_model_family() {
+        let family = |model: &str| ModelFamily::infer(model).map(|family| family.price_model);
+        assert_eq!(family("gpt-4o-2024-08-06"), Some(Some("gpt-4o")));
+        assert_eq!(family("gpt-4o-mini-2024-07-18"), Some(Some("gpt-4o-mini")));
+        assert_eq!(family("gpt-4-0613"), Some(Some("gpt-4")));
+        assert_eq!(family("gpt-4.1-2025-04-14"), Some(Some("gpt-4.1")));
+        assert_eq!(family("gpt-4.5-preview"), None);
+        assert_eq!(family("o3-mini-2025-01-31"), Some(Some("o3-mini")));
+        assert_eq!(family("grok-3-beta"), Some(Some("grok-3-latest")));
+        assert_eq!(
+            family("grok-3-mini-fast-20250301"),
+            Some(Some("grok-3-mini-fast-latest"))
+        );
+        assert_eq!(
+            family("ft:gpt-4o-mini-2024-07-18:acme:support-bot:9xK2pLq1"),
+            Some(Some("gpt-4o-mini"))
+        );
+        assert_eq!(
+            family("mistral-large-2411"),
+            Some(Some("mistral-large-latest"))
+        );
+        assert_eq!(family("claude-3-7-sonnet"), None);
+
+        let llama = ModelFamily::infer("llama-3.3-70b-versatile").unwrap();
+        assert_eq!(llama.tokenizer, Tokenizer::Cl100kBase);
+        assert!(llama.supports_tools && !llama.supports_images);
+        assert_eq!(
+            ModelFamily::infer("meta-llama/Llama-3.1-8B-Instruct"),
+            Some(llama)
+        );
+        assert_eq!(ModelFamily::infer("llama3.1:8b"), Some(llama));
+        assert_eq!(
+            ModelFamily::infer("accounts/fireworks/models/llama-v3p3-70b-instruct"),
+            Some(llama)
+        );
+        assert!(
+            ModelFamily::infer("meta-llama/llama-4-scout-17b-16e-instruct")
+                .unwrap()
+                .supports_images
+        );
+        assert!(
+            ModelFamily::infer("Qwen/Qwen3-8B")
+                .unwrap()
+                .supports_thinking
+        );
+        assert!(
+            !ModelFamily::infer("qwen2.5-coder-32b-instruct")
+                .unwrap()
+                .supports_thinking
+        );
+    }
+}
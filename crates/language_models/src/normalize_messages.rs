@@ -0,0 +1,146 @@
+use language_model::{LanguageModelRequestMessage, MessageContent, Role};
+
+/// The user message that's added before a conversation that starts with the assistant, for
+/// providers that require the user to speak first.
+const PLACEHOLDER_USER_MESSAGE: &str = "Continue.";
+
+/// The rules that a provider puts on the order of the messages in a request.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct MessageConstraints {
+    /// Whether system messages are only allowed at the start of the conversation.
+    pub system_first: bool,
+    /// Whether user and assistant messages have to alternate.
+    pub alternating_roles: bool,
+    /// Whether the first message after the system messages has to be from the user.
+    pub starts_with_user: bool,
+}
+
+impl MessageConstraints {
+    /// The rules of providers that only accept a conversation that alternates between the user
+    /// and the assistant, starting with the user.
+    pub(crate) const STRICT: Self = Self {
+        system_first: true,
+        alternating_roles: true,
+        starts_with_user: true,
+    };
+}
+
+/// Rearranges `messages` to follow a provider's `constraints`, so that conversations built by
+/// the agent aren't rejected.
+///
+/// Empty messages are dropped. System messages are moved to the start and merged into one when
+/// they have to come first, consecutive messages with the same role are merged when roles have to
+/// alternate, and a placeholder user message is added when the conversation has to start with
+/// the user.
+pub(crate) fn normalize_messages(
+    messages: Vec<LanguageModelRequestMessage>,
+    constraints: MessageConstraints,
+) -> Vec<LanguageModelRequestMessage> {
+    let mut messages = messages
+        .into_iter()
+        .filter(|message| !message.contents_empty())
+        .collect::<Vec<_>>();
+
+    if constraints.system_first {
+        let (system_messages, conversation): (Vec<_>, Vec<_>) = messages
+            .into_iter()
+            .partition(|message| message.role == Role::System);
+        messages = merge_consecutive(system_messages);
+        messages.extend(conversation);
+    }
+
+    if constraints.alternating_roles {
+        messages = merge_consecutive(messages);
+    }
+
+    if constraints.starts_with_user {
+        let first_turn = messages
+            .iter()
+            .position(|message| message.role != Role::System);
+        if let Some(ix) = first_turn.filter(|&ix| messages[ix].role == Role::Assistant) {
+            messages.insert(
+                ix,
+                LanguageModelRequestMessage {
+                    role: Role::User,
+                    content: vec![MessageContent::Text(PLACEHOLDER_USER_MESSAGE.into())],
+                    cache: false,
+                },
+            );
+        }
+    }
+
+    messages
+}
+
+/// Merges each run of messages with the same role into one message.
+fn merge_consecutive(
+    messages: Vec<LanguageModelRequestMessage>,
+) -> Vec<LanguageModelRequestMessage> {
+    let mut merged: Vec<LanguageModelRequestMessage> = Vec::with_capacity(messages.len());
+    for message in messages {
+        match merged.last_mut() {
+            Some(last) if last.role == message.role => {
+                last.cache |= message.cache;
+                for content in message.content {
+                    match (last.content.last_mut(), content) {
+                        (Some(MessageContent::Text(last_text)), MessageContent::Text(text)) => {
+                            last_text.push_str("\n\n");
+                            last_text.push_str(&text);
+                        }
+                        (_, content) => last.content.push(content),
+                    }
+                }
+            }
+            _ => merged.push(message),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: Role, text: &str) -> LanguageModelRequestMessage {
+        LanguageModelRequestMessage {
+            role,
+            content: vec![MessageContent::Text(text.into())],
+            cache: false,
+        }
+    }
+
+    #[test]
+    fn test_normalize_messages() {
+        let messages = vec![
+            message(Role::Assistant, "Hi, how can I help?"),
+            message(Role::System, "Be brief."),
+            message(Role::User, "Fix the bug."),
+            message(Role::User, " "),
+            message(Role::System, "Use Rust."),
+            message(Role::User, "It's in main.rs."),
+            message(Role::Assistant, "Done."),
+        ];
+
+        assert_eq!(
+            normalize_messages(messages.clone(), MessageConstraints::STRICT),
+            vec![
+                message(Role::System, "Be brief.\n\nUse Rust."),
+                message(Role::User, PLACEHOLDER_USER_MESSAGE),
+                message(Role::Assistant, "Hi, how can I help?"),
+                message(Role::User, "Fix the bug.\n\nIt's in main.rs."),
+                message(Role::Assistant, "Done."),
+            ]
+        );
+        assert_eq!(
+            normalize_messages(messages, MessageConstraints::default()),
+            vec![
+                message(Role::Assistant, "Hi, how can I help?"),
+                message(Role::System, "Be brief."),
+                message(Role::User, "Fix the bug."),
+                message(Role::System, "Use Rust."),
+                message(Role::User, "It's in main.rs."),
+                message(Role::Assistant, "Done."),
+            ]
+        );
+    }
+}
@@ -0,0 +1,233 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context as _, Result, anyhow};
+use futures::AsyncReadExt as _;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::{Deserialize, Serialize};
+
+/// The username that OAuth tokens are stored under in the credentials store, which distinguishes
+/// them from plain API keys saved for the same URL.
+pub(crate) const OAUTH_CREDENTIALS_USERNAME: &str = "OAuth";
+
+/// Tokens are refreshed this long before they actually expire, so that a request doesn't start
+/// with a token that expires while it is in flight.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// The endpoints and client registration used to authenticate with an OAuth 2.0 provider.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OAuthConfig {
+    pub client_id: String,
+    /// Only required for providers that don't support public clients.
+    pub client_secret: Option<String>,
+    pub token_url: String,
+    pub scopes: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OAuthToken {
+    pub access_token: String,
+    pub refresh_token: Option<String>,
+    /// When the access token expires, in seconds since the Unix epoch.
+    pub expires_at: Option<u64>,
+}
+
+impl OAuthToken {
+    fn from_response(response: TokenResponse) -> Self {
+        let expires_at = response
+            .expires_in
+            .map(|expires_in| unix_now() + expires_in);
+        Self {
+            access_token: response.access_token,
+            refresh_token: response.refresh_token,
+            expires_at,
+        }
+    }
+
+    /// Whether the access token has expired or is about to.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.map_or(false, is_expiring)
+    }
+}
+
+/// Whether something that expires at `expires_at`, in seconds since the Unix epoch, has expired
+/// or is about to.
+pub(crate) fn is_expiring(expires_at: u64) -> bool {
+    unix_now() + EXPIRY_MARGIN.as_secs() >= expires_at
+}
+
+/// How long until something that expires at `expires_at` should be renewed.
+pub(crate) fn refresh_delay(expires_at: u64) -> Duration {
+    Duration::from_secs(expires_at.saturating_sub(unix_now() + EXPIRY_MARGIN.as_secs()))
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    refresh_token: Option<String>,
+    expires_in: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// Exchanges a refresh token for a new access token. Providers don't always issue a new refresh
+/// token, in which case the old one is kept.
+pub async fn refresh_token(
+    http_client: &dyn HttpClient,
+    config: &OAuthConfig,
+    token: &OAuthToken,
+) -> Result<OAuthToken> {
+    let refresh_token = token
+        .refresh_token
+        .as_deref()
+        .context("the OAuth token can't be refreshed")?;
+    let mut params = vec![
+        ("grant_type", "refresh_token"),
+        ("refresh_token", refresh_token),
+        ("client_id", config.client_id.as_str()),
+    ];
+    if let Some(client_secret) = config.client_secret.as_deref() {
+        params.push(("client_secret", client_secret));
+    }
+
+    let mut new_token = request_token(http_client, &config.token_url, &params)
+        .await?
+        .map_err(error_into_anyhow)?;
+    if new_token.refresh_token.is_none() {
+        new_token.refresh_token = token.refresh_token.clone();
+    }
+    Ok(new_token)
+}
+
+/// Requests a token for the client itself rather than on behalf of a user, as done by service
+/// principals.
+pub async fn client_credentials_token(
+    http_client: &dyn HttpClient,
+    config: &OAuthConfig,
+) -> Result<OAuthToken> {
+    let client_secret = config
+        .client_secret
+        .as_deref()
+        .context("the client credentials grant requires a client secret")?;
+    let scope = config.scopes.join(" ");
+    let params = [
+        ("grant_type", "client_credentials"),
+        ("client_id", config.client_id.as_str()),
+        ("client_secret", client_secret),
+        ("scope", scope.as_str()),
+    ];
+    request_token(http_client, &config.token_url, &params)
+        .await?
+        .map_err(error_into_anyhow)
+}
+
+/// Exchanges a signed JWT for an access token (RFC 7523), as done by service accounts.
+pub async fn exchange_jwt_assertion(
+    http_client: &dyn HttpClient,
+    token_url: &str,
+    assertion: &str,
+) -> Result<OAuthToken> {
+    let params = [
+        ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+        ("assertion", assertion),
+    ];
+    request_token(http_client, token_url, &params)
+        .await?
+        .map_err(error_into_anyhow)
+}
+
+/// Sends a request to an endpoint that answers with an OAuth token response, such as a cloud
+/// metadata server.
+pub async fn fetch_token(
+    http_client: &dyn HttpClient,
+    request: HttpRequest<AsyncBody>,
+) -> Result<OAuthToken> {
+    let mut response = http_client.send(request).await?;
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    anyhow::ensure!(
+        response.status().is_success(),
+        "token request failed: {} {body}",
+        response.status()
+    );
+    let response: TokenResponse =
+        serde_json::from_str(&body).context("invalid OAuth token response")?;
+    Ok(OAuthToken::from_response(response))
+}
+
+async fn request_token(
+    http_client: &dyn HttpClient,
+    token_url: &str,
+    params: &[(&str, &str)],
+) -> Result<Result<OAuthToken, ErrorResponse>> {
+    let request = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(token_url)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .body(AsyncBody::from(encode_form(params)))?;
+    let mut response = http_client.send(request).await?;
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+
+    if let Ok(error) = serde_json::from_str::<ErrorResponse>(&body) {
+        return Ok(Err(error));
+    }
+    anyhow::ensure!(
+        response.status().is_success(),
+        "token request failed: {} {body}",
+        response.status()
+    );
+    let response: TokenResponse =
+        serde_json::from_str(&body).context("invalid OAuth token response")?;
+    Ok(Ok(OAuthToken::from_response(response)))
+}
+
+fn encode_form(params: &[(&str, &str)]) -> String {
+    url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(params)
+        .finish()
+}
+
+fn error_into_anyhow(error: ErrorResponse) -> anyhow::Error {
+    match error.error_description {
+        Some(description) => anyhow!("{}: {description}", error.error),
+        None => anyhow!("{}", error.error),
+    }
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_expiry() {
+        let token = OAuthToken {
+            access_token: "token".into(),
+            refresh_token: None,
+            expires_at: Some(unix_now() + 30),
+        };
+        assert!(token.is_expired());
+
+        let token = OAuthToken {
+            expires_at: Some(unix_now() + 3600),
+            ..token
+        };
+        assert!(!token.is_expired());
+
+        let token = OAuthToken {
+            expires_at: None,
+            ..token
+        };
+        assert!(!token.is_expired());
+    }
+}
@@ -0,0 +1,71 @@
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::AsyncApp;
+use language_model::{LanguageModel, LanguageModelRequest};
+
+/// Shrinks the most tokens a response can have to what's left of the model's context window
+/// after the prompt, since providers reject requests whose prompt and output limit don't fit.
+pub(crate) struct OutputTokenClamp {
+    prompt_tokens: BoxFuture<'static, Option<usize>>,
+    max_token_count: usize,
+}
+
+impl OutputTokenClamp {
+    /// Starts counting the prompt tokens of `request`, before it's converted for the provider.
+    pub(crate) fn new(
+        model: &(impl LanguageModel + ?Sized),
+        request: &LanguageModelRequest,
+        cx: &AsyncApp,
+    ) -> Self {
+        let prompt_tokens = cx.update(|cx| model.count_tokens(request.clone(), cx)).ok();
+        Self {
+            prompt_tokens: async move {
+                prompt_tokens?
+                    .await
+                    .inspect_err(|error| log::warn!("failed to count prompt tokens: {error:#}"))
+                    .ok()
+            }
+            .boxed(),
+            max_token_count: model.max_token_count(),
+        }
+    }
+
+    /// Lowers `max_output_tokens` to the tokens left in the context window after the prompt.
+    pub(crate) async fn apply(self, max_output_tokens: u32) -> u32 {
+        clamp_output_tokens(
+            max_output_tokens,
+            self.prompt_tokens.await,
+            self.max_token_count,
+        )
+    }
+}
+
+/// Leaves `max_output_tokens` alone when the prompt tokens aren't known, or when the prompt
+/// doesn't leave any room at all, so that the provider reports the overflow.
+fn clamp_output_tokens(
+    max_output_tokens: u32,
+    prompt_tokens: Option<usize>,
+    max_token_count: usize,
+) -> u32 {
+    let Some(prompt_tokens) = prompt_tokens else {
+        return max_output_tokens;
+    };
+    match max_token_count.saturating_sub(prompt_tokens) {
+        0 => max_output_tokens,
+        remaining => max_output_tokens.min(u32::try_from(remaining).unwrap_or(u32::MAX)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clamp_output_tokens() {
+        assert_eq!(clamp_output_tokens(8192, Some(1000), 200_000), 8192);
+        assert_eq!(clamp_output_tokens(8192, Some(195_000), 200_000), 5000);
+        assert_eq!(clamp_output_tokens(8192, Some(200_000), 200_000), 8192);
+        assert_eq!(clamp_output_tokens(8192, Some(250_000), 200_000), 8192);
+        assert_eq!(clamp_output_tokens(8192, None, 200_000), 8192);
+    }
+}
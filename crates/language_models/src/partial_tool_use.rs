@@ -0,0 +1,53 @@
+use std::str::FromStr as _;
+
+use language_model::{LanguageModelCompletionEvent, LanguageModelToolUse};
+
+/// A [`LanguageModelToolUse`] event for the arguments of a tool call that have streamed in so
+/// far, so that the tool call can be shown while it's being generated.
+///
+/// Incomplete arguments are repaired into valid JSON, such as by closing unclosed strings and
+/// delimiters. Returns `None` until the tool call's id and name are known, and when the arguments
+/// can't be repaired.
+pub(crate) fn partial_tool_use(
+    id: &str,
+    name: &str,
+    arguments: &str,
+) -> Option<LanguageModelCompletionEvent> {
+    if id.is_empty() || name.is_empty() || arguments.trim().is_empty() {
+        return None;
+    }
+    let input = serde_json::Value::from_str(&partial_json_fixer::fix_json(arguments)).ok()?;
+    Some(LanguageModelCompletionEvent::ToolUse(
+        LanguageModelToolUse {
+            id: id.to_string().into(),
+            name: name.into(),
+            is_input_complete: false,
+            input,
+            raw_input: arguments.to_string(),
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_tool_use() {
+        let Some(LanguageModelCompletionEvent::ToolUse(tool_use)) = partial_tool_use(
+            "call_1",
+            "edit_file",
+            r#"{"path": "src/main.rs", "mode": "ed"#,
+        ) else {
+            panic!("expected a partial tool use");
+        };
+        assert!(!tool_use.is_input_complete);
+        assert_eq!(
+            tool_use.input,
+            serde_json::json!({"path": "src/main.rs", "mode": "ed"})
+        );
+
+        assert_eq!(partial_tool_use("", "edit_file", r#"{"path"#), None);
+        assert_eq!(partial_tool_use("call_1", "edit_file", ""), None);
+    }
+}
@@ -0,0 +1,151 @@
+use language_model::ModelPrice;
+
+use crate::AllLanguageModelSettings;
+use crate::configure::ConfigurableProvider;
+use crate::model_family::ModelFamily;
+
+/// What a provider's model costs: the price set in the provider's `model_prices` setting, or else
+/// the model's list price, if it's one that Zed knows. Fine-tuned models fall back to the list
+/// price of the model they were trained from, and other models to that of their family, like
+/// `gpt-4o` for `gpt-4o-2024-08-06`.
+pub fn model_price(
+    settings: &AllLanguageModelSettings,
+    provider: &str,
+    model: &str,
+) -> Option<ModelPrice> {
+    ConfigurableProvider::find(provider)
+        .and_then(|provider| provider.api_settings(settings).model_prices.get(model))
+        .copied()
+        .or_else(|| built_in_price(provider, model))
+        .or_else(|| {
+            // Fine-tuned models are priced like the models they were trained from, as far as we
+            // know. OpenAI and Mistral name them the same way.
+            let fine_tuned = open_ai::models::FineTunedModelId::parse(model)?;
+            built_in_price(provider, fine_tuned.base_model)
+                .or_else(|| built_in_price(provider, fine_tuned.base_model_family()))
+        })
+        .or_else(|| built_in_price(provider, ModelFamily::infer(model)?.price_model?))
+}
+
+/// The list prices of the models that providers offer out of the box.
+fn built_in_price(provider: &str, model: &str) -> Option<ModelPrice> {
+    let (input, output, cache_write, cache_read) = match (provider, model) {
+        (
+            "anthropic",
+            "claude-opus-4-latest" | "claude-opus-4-thinking-latest" | "claude-3-opus-latest",
+        ) => (15., 75., Some(18.75), Some(1.5)),
+        (
+            "anthropic",
+            "claude-sonnet-4-latest"
+            | "claude-sonnet-4-thinking-latest"
+            | "claude-3-7-sonnet-latest"
+            | "claude-3-7-sonnet-thinking-latest"
+            | "claude-3-5-sonnet-latest",
+        ) => (3., 15., Some(3.75), Some(0.3)),
+        ("anthropic", "claude-3-5-haiku-latest") => (0.8, 4., Some(1.), Some(0.08)),
+        ("anthropic", "claude-3-sonnet-20240229") => (3., 15., None, None),
+        ("anthropic", "claude-3-haiku-20240307") => (0.25, 1.25, Some(0.3), Some(0.03)),
+        ("openai", "gpt-3.5-turbo") => (0.5, 1.5, None, None),
+        ("openai", "gpt-4") => (30., 60., None, None),
+        ("openai", "gpt-4-turbo") => (10., 30., None, None),
+        ("openai", "gpt-4o") => (2.5, 10., None, Some(1.25)),
+        ("openai", "gpt-4o-mini") => (0.15, 0.6, None, Some(0.075)),
+        ("openai", "gpt-4.1" | "o3") => (2., 8., None, Some(0.5)),
+        ("openai", "gpt-4.1-mini") => (0.4, 1.6, None, Some(0.1)),
+        ("openai", "gpt-4.1-nano") => (0.1, 0.4, None, Some(0.025)),
+        ("openai", "o1" | "o1-preview") => (15., 60., None, Some(7.5)),
+        ("openai", "o1-mini" | "o3-mini") => (1.1, 4.4, None, Some(0.55)),
+        ("openai", "o4-mini") => (1.1, 4.4, None, Some(0.275)),
+        ("google", "gemini-1.5-pro") => (1.25, 5., None, None),
+        ("google", "gemini-1.5-flash" | "gemini-2.0-flash-lite-preview") => {
+            (0.075, 0.3, None, None)
+        }
+        ("google", "gemini-2.0-flash") => (0.1, 0.4, None, Some(0.025)),
+        ("google", "gemini-2.5-pro-preview-03-25") => (1.25, 10., None, Some(0.31)),
+        ("google", "gemini-2.5-flash-preview-04-17") => (0.15, 3.5, None, Some(0.0375)),
+        ("deepseek", "deepseek-chat") => (0.27, 1.1, None, Some(0.07)),
+        ("deepseek", "deepseek-reasoner") => (0.55, 2.19, None, Some(0.14)),
+        ("mistral", "codestral-latest") => (0.3, 0.9, None, None),
+        ("mistral", "mistral-large-latest") => (2., 6., None, None),
+        ("mistral", "mistral-medium-latest") => (0.4, 2., None, None),
+        ("mistral", "mistral-small-latest" | "devstral-small-latest") => (0.1, 0.3, None, None),
+        ("mistral", "open-mistral-nemo") => (0.15, 0.15, None, None),
+        ("grok", "grok-3-latest") => (3., 15., None, None),
+        ("grok", "grok-3-fast-latest") => (5., 25., None, None),
+        ("grok", "grok-3-mini-latest") => (0.3, 0.5, None, None),
+        ("grok", "grok-3-mini-fast-latest") => (0.6, 4., None, None),
+        _ => return None,
+    };
+    Some(ModelPrice {
+        input,
+        output,
+        cache_write,
+        cache_read,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use language_model::TokenUsage;
+
+    use super::*;
+
+    #[test]
+    fn test_built_in_prices() {
+        let price = built_in_price("anthropic", "claude-sonnet-4-latest").unwrap();
+        let usage = TokenUsage {
+            input_tokens: 1_000,
+            output_tokens: 1_000,
+            cache_creation_input_tokens: 1_000_000,
+            cache_read_input_tokens: 1_000_000,
+            reasoning_tokens: 0,
+        };
+        assert!((price.cost(&usage) - 4.068).abs() < 1e-9);
+
+        assert_eq!(built_in_price("anthropic", "gpt-4o"), None);
+        assert_eq!(
+            built_in_price("openai", "gpt-4o").unwrap().cache_write,
+            None
+        );
+    }
+
+    #[test]
+    fn test_fine_tuned_model_prices() {
+        let settings = AllLanguageModelSettings::default();
+        assert_eq!(
+            model_price(
+                &settings,
+                "openai",
+                "ft:gpt-4o-mini-2024-07-18:acme:support-bot:9xK2pLq1"
+            ),
+            built_in_price("openai", "gpt-4o-mini")
+        );
+        assert_eq!(
+            model_price(
+                &settings,
+                "mistral",
+                "ft:mistral-small-latest:587a6b29:20240514:7e773925"
+            ),
+            built_in_price("mistral", "mistral-small-latest")
+        );
+        assert_eq!(
+            model_price(&settings, "openai", "ft:davinci-002:acme::9xK2pLq1"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_model_family_prices() {
+        let settings = AllLanguageModelSettings::default();
+        assert_eq!(
+            model_price(&settings, "openai", "gpt-4o-2024-08-06"),
+            built_in_price("openai", "gpt-4o")
+        );
+        assert_eq!(
+            model_price(&settings, "grok", "grok-3-mini-beta"),
+            built_in_price("grok", "grok-3-mini-latest")
+        );
+        // Families are only priced by the provider that offers their models.
+        assert_eq!(model_price(&settings, "openai", "grok-3-beta"), None);
+    }
+}
@@ -0,0 +1,134 @@
+use anyhow::{Context as _, Result};
+use handlebars::Handlebars;
+use language_model::{LanguageModelRequest, Role};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const CHATML_TEMPLATE: &str = "{{#each messages}}<|im_start|>{{role}}\n{{content}}<|im_end|>\n{{/each}}<|im_start|>assistant\n";
+const LLAMA3_TEMPLATE: &str = "{{#each messages}}<|start_header_id|>{{role}}<|end_header_id|>\n\n{{content}}<|eot_id|>{{/each}}<|start_header_id|>assistant<|end_header_id|>\n\n";
+const ALPACA_TEMPLATE: &str = "{{#each messages}}{{#if (eq role \"system\")}}{{content}}\n\n{{/if}}{{#if (eq role \"user\")}}### Instruction:\n{{content}}\n\n{{/if}}{{#if (eq role \"assistant\")}}### Response:\n{{content}}\n\n{{/if}}{{/each}}### Response:\n";
+
+/// How the messages of a request are written into a single prompt, for models that are served
+/// from a raw completion endpoint rather than a chat one, like base models and older local ones.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PromptTemplate {
+    /// The `<|im_start|>role` format of Qwen, Yi, and many fine-tunes.
+    #[serde(rename = "chatml")]
+    ChatMl,
+    /// The `<|start_header_id|>role<|end_header_id|>` format of Llama 3.
+    #[serde(rename = "llama3")]
+    Llama3,
+    /// The `### Instruction:` and `### Response:` format of Alpaca and its fine-tunes.
+    Alpaca,
+    /// A Handlebars template that's rendered with `messages`, a list of `role` and `content`
+    /// pairs. It should end with whatever starts the assistant's response.
+    Custom {
+        template: String,
+        /// Sequences that end the assistant's response in this format.
+        #[serde(default)]
+        stop: Vec<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct TemplateMessage {
+    role: Role,
+    content: String,
+}
+
+impl PromptTemplate {
+    pub fn render(&self, request: &LanguageModelRequest) -> Result<String> {
+        let template = match self {
+            PromptTemplate::ChatMl => CHATML_TEMPLATE,
+            PromptTemplate::Llama3 => LLAMA3_TEMPLATE,
+            PromptTemplate::Alpaca => ALPACA_TEMPLATE,
+            PromptTemplate::Custom { template, .. } => template,
+        };
+        let messages = request
+            .messages
+            .iter()
+            .map(|message| TemplateMessage {
+                role: message.role,
+                content: message.string_contents(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut handlebars = Handlebars::new();
+        // Prompts aren't HTML.
+        handlebars.register_escape_fn(|text| text.into());
+        handlebars
+            .render_template(template, &serde_json::json!({ "messages": messages }))
+            .context("failed to render the prompt template")
+    }
+
+    /// The sequences that end the assistant's response, which models trained on the format
+    /// would otherwise continue past by writing the next message themselves.
+    pub fn stop_sequences(&self) -> Vec<String> {
+        match self {
+            PromptTemplate::ChatMl => vec!["<|im_end|>".into()],
+            PromptTemplate::Llama3 => vec!["<|eot_id|>".into()],
+            PromptTemplate::Alpaca => vec!["### Instruction:".into()],
+            PromptTemplate::Custom { stop, .. } => stop.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use language_model::LanguageModelRequestMessage;
+
+    use super::*;
+
+    #[test]
+    fn test_render_prompt_template() {
+        let request = LanguageModelRequest {
+            messages: vec![
+                LanguageModelRequestMessage {
+                    role: Role::System,
+                    content: vec!["Be brief.".into()],
+                    cache: false,
+                },
+                LanguageModelRequestMessage {
+                    role: Role::User,
+                    content: vec!["Is 1 < 2?".into()],
+                    cache: false,
+                },
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            PromptTemplate::ChatMl.render(&request).unwrap(),
+            "<|im_start|>system\nBe brief.<|im_end|>\n\
+             <|im_start|>user\nIs 1 < 2?<|im_end|>\n\
+             <|im_start|>assistant\n"
+        );
+        assert_eq!(
+            PromptTemplate::Llama3.render(&request).unwrap(),
+            "<|start_header_id|>system<|end_header_id|>\n\nBe brief.<|eot_id|>\
+             <|start_header_id|>user<|end_header_id|>\n\nIs 1 < 2?<|eot_id|>\
+             <|start_header_id|>assistant<|end_header_id|>\n\n"
+        );
+        assert_eq!(
+            PromptTemplate::Alpaca.render(&request).unwrap(),
+            "Be brief.\n\n### Instruction:\nIs 1 < 2?\n\n### Response:\n"
+        );
+
+        let custom = PromptTemplate::Custom {
+            template: "{{#each messages}}{{role}}: {{content}}\n{{/each}}assistant:".into(),
+            stop: vec!["\nuser:".into()],
+        };
+        assert_eq!(
+            custom.render(&request).unwrap(),
+            "system: Be brief.\nuser: Is 1 < 2?\nassistant:"
+        );
+        assert_eq!(custom.stop_sequences(), vec!["\nuser:".to_string()]);
+
+        let invalid = PromptTemplate::Custom {
+            template: "{{#each messages}}".into(),
+            stop: Vec::new(),
+        };
+        assert!(invalid.render(&request).is_err());
+    }
+}
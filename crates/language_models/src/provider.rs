@@ -1,4 +1,6 @@
+pub mod alias;
 pub mod anthropic;
+pub mod balanced;
 pub mod bedrock;
 pub mod cloud;
 pub mod copilot_chat;
@@ -6,6 +8,7 @@ pub mod deepseek;
 pub mod google;
 pub mod grok;
 pub mod lmstudio;
+pub mod local;
 pub mod mistral;
 pub mod ollama;
 pub mod open_ai;
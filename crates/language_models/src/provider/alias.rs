@@ -0,0 +1,366 @@
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use collections::HashMap;
+use futures::{FutureExt, future::BoxFuture, stream::BoxStream};
+use gpui::{AnyView, App, AsyncApp, Context, Entity, Subscription, Task};
+use language_model::{
+    AuthenticateError, DocumentLimits, ImageLimits, LanguageModel, LanguageModelCacheConfiguration,
+    LanguageModelCompletionError, LanguageModelCompletionEvent, LanguageModelId, LanguageModelName,
+    LanguageModelProvider, LanguageModelProviderId, LanguageModelProviderName,
+    LanguageModelProviderState, LanguageModelRegistry, LanguageModelRequest,
+    LanguageModelToolChoice, LanguageModelToolSchemaFormat, ModelPrice, SelectedModel,
+    StructuredOutput,
+};
+use settings::{Settings, SettingsStore};
+use ui::{List, prelude::*};
+
+use crate::AllLanguageModelSettings;
+use crate::ui::InstructionListItem;
+
+pub(crate) const PROVIDER_ID: &str = "alias";
+const PROVIDER_NAME: &str = "Aliases";
+
+/// The model that an alias is set to. Aliases can't refer to other aliases.
+fn alias_target(aliases: &HashMap<String, String>, alias: &str) -> Option<SelectedModel> {
+    aliases
+        .get(alias)?
+        .parse::<SelectedModel>()
+        .ok()
+        .filter(|selected_model| selected_model.provider.0 != PROVIDER_ID)
+}
+
+/// The model that an alias currently refers to, if its provider is enabled and offers it.
+fn resolve_alias(alias: &str, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+    let selected_model = alias_target(
+        &AllLanguageModelSettings::get_global(cx).model_aliases,
+        alias,
+    )?;
+    LanguageModelRegistry::read_global(cx)
+        .provider(&selected_model.provider)?
+        .provided_models(cx)
+        .into_iter()
+        .find(|model| model.id() == selected_model.model)
+}
+
+pub struct AliasLanguageModelProvider {
+    state: Entity<State>,
+}
+
+pub struct State {
+    _subscription: Subscription,
+}
+
+impl AliasLanguageModelProvider {
+    pub fn new(cx: &mut App) -> Self {
+        let state = cx.new(|cx| State {
+            _subscription: cx.observe_global::<SettingsStore>({
+                let mut aliases = AllLanguageModelSettings::get_global(cx)
+                    .model_aliases
+                    .clone();
+                move |_, cx| {
+                    let new_aliases = &AllLanguageModelSettings::get_global(cx).model_aliases;
+                    if &aliases != new_aliases {
+                        aliases = new_aliases.clone();
+                        cx.notify();
+                    }
+                }
+            }),
+        });
+        Self { state }
+    }
+}
+
+impl LanguageModelProviderState for AliasLanguageModelProvider {
+    type ObservableEntity = State;
+
+    fn observable_entity(&self) -> Option<Entity<Self::ObservableEntity>> {
+        Some(self.state.clone())
+    }
+}
+
+impl LanguageModelProvider for AliasLanguageModelProvider {
+    fn id(&self) -> LanguageModelProviderId {
+        LanguageModelProviderId(PROVIDER_ID.into())
+    }
+
+    fn name(&self) -> LanguageModelProviderName {
+        LanguageModelProviderName(PROVIDER_NAME.into())
+    }
+
+    fn default_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        None
+    }
+
+    fn default_fast_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        None
+    }
+
+    fn provided_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
+        let mut aliases = AllLanguageModelSettings::get_global(cx)
+            .model_aliases
+            .keys()
+            .collect::<Vec<_>>();
+        aliases.sort();
+        aliases
+            .into_iter()
+            .filter_map(|alias| {
+                let target = resolve_alias(alias, cx)?;
+                Some(Arc::new(AliasLanguageModel {
+                    alias: alias.clone(),
+                    target,
+                }) as Arc<dyn LanguageModel>)
+            })
+            .collect()
+    }
+
+    fn is_authenticated(&self, cx: &App) -> bool {
+        !AllLanguageModelSettings::get_global(cx)
+            .model_aliases
+            .is_empty()
+    }
+
+    fn authenticate(&self, _cx: &mut App) -> Task<Result<(), AuthenticateError>> {
+        Task::ready(Ok(()))
+    }
+
+    fn configuration_view(&self, _window: &mut Window, cx: &mut App) -> AnyView {
+        let state = self.state.clone();
+        cx.new(|cx| ConfigurationView::new(state, cx)).into()
+    }
+
+    fn reset_credentials(&self, _cx: &mut App) -> Task<Result<()>> {
+        Task::ready(Ok(()))
+    }
+}
+
+/// A stable name for another model, which requests are sent to as of when they're made.
+///
+/// Its capabilities are those of the model that the alias referred to when the models were last
+/// listed.
+pub struct AliasLanguageModel {
+    alias: String,
+    target: Arc<dyn LanguageModel>,
+}
+
+impl LanguageModel for AliasLanguageModel {
+    fn id(&self) -> LanguageModelId {
+        LanguageModelId::from(self.alias.clone())
+    }
+
+    fn name(&self) -> LanguageModelName {
+        LanguageModelName::from(format!("{} ({})", self.alias, self.target.name().0))
+    }
+
+    fn provider_id(&self) -> LanguageModelProviderId {
+        LanguageModelProviderId(PROVIDER_ID.into())
+    }
+
+    fn provider_name(&self) -> LanguageModelProviderName {
+        LanguageModelProviderName(PROVIDER_NAME.into())
+    }
+
+    fn telemetry_id(&self) -> String {
+        self.target.telemetry_id()
+    }
+
+    fn price(&self, cx: &App) -> Option<ModelPrice> {
+        resolve_alias(&self.alias, cx)?.price(cx)
+    }
+
+    fn supports_images(&self) -> bool {
+        self.target.supports_images()
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        self.target.image_limits()
+    }
+
+    fn document_limits(&self) -> Option<DocumentLimits> {
+        self.target.document_limits()
+    }
+
+    fn supports_audio(&self) -> bool {
+        self.target.supports_audio()
+    }
+
+    fn supports_video(&self) -> bool {
+        self.target.supports_video()
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.target.supports_tools()
+    }
+
+    fn supports_tool_choice(&self, choice: LanguageModelToolChoice) -> bool {
+        self.target.supports_tool_choice(choice)
+    }
+
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.target.supports_parallel_tool_calls()
+    }
+
+    fn supports_thinking(&self) -> bool {
+        self.target.supports_thinking()
+    }
+
+    fn supports_max_mode(&self) -> bool {
+        self.target.supports_max_mode()
+    }
+
+    fn supports_assistant_prefill(&self) -> bool {
+        self.target.supports_assistant_prefill()
+    }
+
+    fn supports_candidates(&self) -> bool {
+        self.target.supports_candidates()
+    }
+
+    fn supports_logprobs(&self) -> bool {
+        self.target.supports_logprobs()
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.target.structured_output()
+    }
+
+    fn tool_input_format(&self) -> LanguageModelToolSchemaFormat {
+        self.target.tool_input_format()
+    }
+
+    fn max_token_count(&self) -> usize {
+        self.target.max_token_count()
+    }
+
+    fn max_output_tokens(&self) -> Option<u32> {
+        self.target.max_output_tokens()
+    }
+
+    fn count_tokens(
+        &self,
+        request: LanguageModelRequest,
+        cx: &App,
+    ) -> BoxFuture<'static, Result<usize>> {
+        match resolve_alias(&self.alias, cx) {
+            Some(target) => target.count_tokens(request, cx),
+            None => futures::future::ready(Err(anyhow!(
+                "model alias {} doesn't refer to an available model",
+                self.alias
+            )))
+            .boxed(),
+        }
+    }
+
+    fn stream_completion(
+        &self,
+        request: LanguageModelRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<
+        'static,
+        Result<
+            BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+        >,
+    > {
+        let alias = self.alias.clone();
+        let cx = cx.clone();
+        async move {
+            let target = cx.update(|cx| resolve_alias(&alias, cx))?.ok_or_else(|| {
+                anyhow!("model alias {alias} doesn't refer to an available model")
+            })?;
+            target.stream_completion(request, &cx).await
+        }
+        .boxed()
+    }
+
+    fn cache_configuration(&self) -> Option<LanguageModelCacheConfiguration> {
+        self.target.cache_configuration()
+    }
+}
+
+struct ConfigurationView {
+    _subscription: Subscription,
+}
+
+impl ConfigurationView {
+    fn new(state: Entity<State>, cx: &mut Context<Self>) -> Self {
+        Self {
+            _subscription: cx.observe(&state, |_, _, cx| cx.notify()),
+        }
+    }
+}
+
+impl Render for ConfigurationView {
+    fn render(&mut self, _: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let mut aliases = AllLanguageModelSettings::get_global(cx)
+            .model_aliases
+            .iter()
+            .map(|(alias, target)| (alias.clone(), target.clone()))
+            .collect::<Vec<_>>();
+        aliases.sort();
+        let available = aliases
+            .iter()
+            .map(|(alias, _)| resolve_alias(alias, cx).is_some())
+            .collect::<Vec<_>>();
+
+        v_flex()
+            .gap_2()
+            .child(Label::new(
+                "Give models stable names to refer to them by in settings and prompts.",
+            ))
+            .child(
+                List::new()
+                    .child(InstructionListItem::text_only(
+                        "Add aliases as `\"name\": \"provider/model\"` to `language_models.model_aliases` in your settings.",
+                    ))
+                    .child(InstructionListItem::text_only(
+                        "Select an alias with the `alias` provider, like `{ \"provider\": \"alias\", \"model\": \"name\" }`.",
+                    )),
+            )
+            .children(aliases.into_iter().zip(available).map(
+                |((alias, target), is_available)| {
+                    h_flex()
+                        .gap_2()
+                        .child(Label::new(alias))
+                        .child(
+                            Label::new(target)
+                                .size(LabelSize::Small)
+                                .color(Color::Muted),
+                        )
+                        .when(!is_available, |this| {
+                            this.child(
+                                Label::new("unavailable")
+                                    .size(LabelSize::Small)
+                                    .color(Color::Error),
+                            )
+                        })
+                },
+            ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alias_target() {
+        let aliases = HashMap::from_iter([
+            (
+                "default-coder".to_string(),
+                "grok/grok-3-latest".to_string(),
+            ),
+            ("coder".to_string(), "alias/default-coder".to_string()),
+            ("broken".to_string(), "grok-3-latest".to_string()),
+        ]);
+        assert_eq!(
+            alias_target(&aliases, "default-coder"),
+            Some(SelectedModel {
+                provider: LanguageModelProviderId("grok".into()),
+                model: LanguageModelId("grok-3-latest".into()),
+            })
+        );
+        assert_eq!(alias_target(&aliases, "coder"), None);
+        assert_eq!(alias_target(&aliases, "broken"), None);
+        assert_eq!(alias_target(&aliases, "missing"), None);
+    }
+}
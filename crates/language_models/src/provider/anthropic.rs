@@ -1,10 +1,29 @@
-use crate::AllLanguageModelSettings;
+use crate::api_key::{
+    ApiKeyDisplay, ApiKeySaveScope, ApiKeySource, ApiKeyStatus, ApiKeyValidation, ApiKeys,
+    CredentialsScope, WorkspaceApiKeys, WorkspaceScope, carry_over_api_key, load_api_keys,
+    refresh_api_keys, validate_api_keys,
+};
+use crate::batch::{BatchApi, BatchQueue, BatchSettings};
+use crate::key_usage::KeyUsageRecorder;
+use crate::live_usage::estimate_live_usage;
+use crate::moderation::check_moderation;
+use crate::normalize_messages::{MessageConstraints, normalize_messages};
+use crate::output_tokens::OutputTokenClamp;
+use crate::partial_tool_use::partial_tool_use;
+use crate::pricing::model_price;
+use crate::reasoning::normalize_reasoning;
+use crate::request_log::RequestLogRecorder;
+use crate::spend_limits::check_spend_limits;
+use crate::stream_timeouts::StreamTimeouts;
 use crate::ui::InstructionListItem;
+use crate::{AllLanguageModelSettings, ProviderApiSettings, ReasoningSettings, SamplingParameters};
 use anthropic::{
-    AnthropicError, AnthropicModelMode, ContentDelta, Event, ResponseContent, ToolResultContent,
-    ToolResultPart, Usage,
+    AnthropicError, AnthropicModelMode, ApiErrorCode, ContentDelta, Event, ResponseContent,
+    ToolResultContent, ToolResultPart, Usage,
 };
 use anyhow::{Context as _, Result, anyhow};
+pub use cache_breakpoints::AutomaticCachingSettings;
+use cache_breakpoints::place_cache_breakpoints;
 use collections::{BTreeMap, HashMap};
 use credentials_provider::CredentialsProvider;
 use editor::{Editor, EditorElement, EditorStyle};
@@ -13,15 +32,17 @@ use futures::{FutureExt, StreamExt, future::BoxFuture, stream::BoxStream};
 use gpui::{
     AnyView, App, AsyncApp, Context, Entity, FontStyle, Subscription, Task, TextStyle, WhiteSpace,
 };
-use http_client::HttpClient;
+use http_client::{HttpClient, http};
 use language_model::{
-    AuthenticateError, LanguageModel, LanguageModelCacheConfiguration,
+    AuthenticateError, DocumentLimits, LanguageModel, LanguageModelCacheConfiguration,
     LanguageModelCompletionError, LanguageModelId, LanguageModelKnownError, LanguageModelName,
     LanguageModelProvider, LanguageModelProviderId, LanguageModelProviderName,
     LanguageModelProviderState, LanguageModelRequest, LanguageModelToolChoice,
-    LanguageModelToolResultContent, MessageContent, RateLimiter, Role,
+    LanguageModelToolResultContent, MessageContent, ModelPrice, RateLimiter, Role,
+};
+use language_model::{
+    Citation, LanguageModelCompletionEvent, LanguageModelToolUse, StopReason, StructuredOutput,
 };
-use language_model::{LanguageModelCompletionEvent, LanguageModelToolUse, StopReason};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use settings::{Settings, SettingsStore};
@@ -33,14 +54,32 @@ use theme::ThemeSettings;
 use ui::{Icon, IconName, List, Tooltip, prelude::*};
 use util::ResultExt;
 
-const PROVIDER_ID: &str = language_model::ANTHROPIC_PROVIDER_ID;
-const PROVIDER_NAME: &str = "Anthropic";
+pub(crate) const PROVIDER_ID: &str = language_model::ANTHROPIC_PROVIDER_ID;
+pub(crate) const PROVIDER_NAME: &str = "Anthropic";
+
+/// The tool that the model is made to call with its response when the request has a response
+/// schema, since Anthropic can't constrain text responses.
+const RESPONSE_TOOL_NAME: &str = "respond";
+
+/// The smallest thinking budget that the API accepts.
+const MIN_THINKING_BUDGET_TOKENS: u32 = 1024;
+
+pub(crate) fn api_key_validation_request(api_url: &str, api_key: &str) -> http::request::Builder {
+    http::Request::builder()
+        .uri(format!("{api_url}/v1/models"))
+        .header("x-api-key", api_key)
+        .header("anthropic-version", "2023-06-01")
+}
+
+mod cache_breakpoints;
 
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct AnthropicSettings {
     pub api_url: String,
+    pub api: ProviderApiSettings,
     /// Extend Zed's list of Anthropic models.
     pub available_models: Vec<AvailableModel>,
+    pub automatic_caching: AutomaticCachingSettings,
     pub needs_setting_migration: bool,
 }
 
@@ -62,6 +101,34 @@ pub struct AvailableModel {
     pub extra_beta_headers: Vec<String>,
     /// The model's mode (e.g. thinking)
     pub mode: Option<ModelMode>,
+    /// How much the model reasons before it responds.
+    #[serde(default)]
+    pub reasoning: ReasoningSettings,
+    /// Whether the model can call tools.
+    ///
+    /// Default: true
+    #[serde(default)]
+    pub supports_tools: Option<bool>,
+    /// Whether the model takes images as input, so that attached images are sent to it.
+    ///
+    /// Default: true
+    #[serde(default)]
+    pub supports_images: Option<bool>,
+    /// Whether the model supports extended thinking. When false, the thinking `mode` and
+    /// `reasoning` budget are ignored.
+    ///
+    /// Default: true
+    #[serde(default)]
+    pub supports_thinking: Option<bool>,
+    /// Whether the model can call several tools in one response. When false, requests ask the
+    /// model to call one tool at a time.
+    ///
+    /// Default: true
+    #[serde(default)]
+    pub supports_parallel_tool_calls: Option<bool>,
+    /// Sampling parameters for requests to this model.
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -96,57 +163,145 @@ impl From<AnthropicModelMode> for ModelMode {
 pub struct AnthropicLanguageModelProvider {
     http_client: Arc<dyn HttpClient>,
     state: gpui::Entity<State>,
+    batch_queue: BatchQueue<AnthropicBatchApi>,
 }
 
 const ANTHROPIC_API_KEY_VAR: &str = "ANTHROPIC_API_KEY";
 
 pub struct State {
-    api_key: Option<String>,
-    api_key_from_env: bool,
+    http_client: Arc<dyn HttpClient>,
+    api_url: String,
+    api_keys: ApiKeys,
+    api_key_source: ApiKeySource,
+    workspace_api_keys: WorkspaceApiKeys,
+    api_key_status: ApiKeyStatus,
+    _api_key_refresh_task: Option<Task<()>>,
     _subscription: Subscription,
+    _credentials_scope_subscription: Subscription,
 }
 
 impl State {
-    fn reset_api_key(&self, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn reset_api_key(
+        &self,
+        workspace: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<()>> {
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .anthropic
             .api_url
             .clone();
+        if let Some(workspace) = workspace {
+            let task = self
+                .workspace_api_keys
+                .reset(&workspace, PROVIDER_ID, &api_url, cx);
+            cx.notify();
+            return task;
+        }
+        let credentials_scope = CredentialsScope::global(cx);
+        let credentials_urls = [
+            credentials_scope.api_key_url(&self.api_key_source, PROVIDER_ID, &api_url),
+            credentials_scope.legacy_api_key_url(&self.api_key_source, &api_url),
+        ];
         cx.spawn(async move |this, cx| {
-            credentials_provider
-                .delete_credentials(&api_url, &cx)
-                .await
-                .ok();
+            for credentials_url in &credentials_urls {
+                credentials_provider
+                    .delete_credentials(credentials_url, &cx)
+                    .await
+                    .ok();
+            }
             this.update(cx, |this, cx| {
-                this.api_key = None;
-                this.api_key_from_env = false;
+                this.api_keys = ApiKeys::default();
+                this.api_key_status.set_rejected(false, cx);
+                this.api_key_source = ApiKeySource::CredentialsStore;
+                this._api_key_refresh_task = None;
                 cx.notify();
             })
         })
     }
 
-    fn set_api_key(&mut self, api_key: String, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn set_api_key(
+        &mut self,
+        api_key: String,
+        workspace_scope: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<Option<SharedString>>> {
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .anthropic
             .api_url
             .clone();
+        let http_client = self.http_client.clone();
+        let source = workspace_scope.map_or(
+            ApiKeySource::CredentialsStore,
+            ApiKeySource::WorkspaceCredentialsStore,
+        );
+        let credentials_url =
+            CredentialsScope::global(cx).api_key_url(&source, PROVIDER_ID, &api_url);
         cx.spawn(async move |this, cx| {
+            let warning =
+                validate_api_keys(http_client.as_ref(), &ApiKeys::parse(&api_key), |api_key| {
+                    api_key_validation_request(&api_url, api_key)
+                })
+                .await?;
+
             credentials_provider
-                .write_credentials(&api_url, "Bearer", api_key.as_bytes(), &cx)
+                .write_credentials(&credentials_url, "Bearer", api_key.as_bytes(), &cx)
                 .await
                 .ok();
 
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
+                match source.workspace() {
+                    Some(workspace) => this
+                        .workspace_api_keys
+                        .set(workspace, Some(ApiKeys::parse(&api_key))),
+                    None => {
+                        this.api_keys = ApiKeys::parse(&api_key);
+                        this.api_key_source = source;
+                    }
+                }
+                this.api_key_status.set_rejected(false, cx);
                 cx.notify();
-            })
+            })?;
+            Ok(warning)
         })
     }
 
     fn is_authenticated(&self) -> bool {
-        self.api_key.is_some()
+        !self.api_keys.is_empty() || self.workspace_api_keys.has_any()
+    }
+
+    /// Reloads the keys saved for a new `api_url`, carrying the current key over if none was
+    /// saved for it yet.
+    fn update_api_url(&mut self, cx: &mut Context<Self>) {
+        let api_url = AllLanguageModelSettings::get_global(cx)
+            .anthropic
+            .api_url
+            .clone();
+        if self.api_url == api_url {
+            return;
+        }
+        let previous_api_url = std::mem::replace(&mut self.api_url, api_url);
+        self.workspace_api_keys.clear();
+        if self.api_key_source.is_external() {
+            return;
+        }
+
+        let carry_over = carry_over_api_key(
+            &self.api_key_source,
+            PROVIDER_ID,
+            &previous_api_url,
+            &self.api_url,
+            cx,
+        );
+        self.api_keys = ApiKeys::default();
+        cx.spawn(async move |this, cx| {
+            carry_over.await;
+            if let Ok(task) = this.update(cx, |this, cx| this.authenticate(cx)) {
+                task.await.ok();
+            }
+        })
+        .detach();
     }
 
     fn authenticate(&self, cx: &mut Context<Self>) -> Task<Result<(), AuthenticateError>> {
@@ -159,24 +314,42 @@ impl State {
             .anthropic
             .api_url
             .clone();
+        let api_settings = AllLanguageModelSettings::get_global(cx)
+            .anthropic
+            .api
+            .clone();
+        let credentials_scope = CredentialsScope::global(cx);
+        let load_workspace_api_keys =
+            self.workspace_api_keys
+                .load_open_workspaces(PROVIDER_ID, &api_url, cx);
 
         cx.spawn(async move |this, cx| {
-            let (api_key, from_env) = if let Ok(api_key) = std::env::var(ANTHROPIC_API_KEY_VAR) {
-                (api_key, true)
-            } else {
-                let (_, api_key) = credentials_provider
-                    .read_credentials(&api_url, &cx)
-                    .await?
-                    .ok_or(AuthenticateError::CredentialsNotFound)?;
-                (
-                    String::from_utf8(api_key).context("invalid {PROVIDER_NAME} API key")?,
-                    false,
-                )
+            let api_keys = load_api_keys(
+                ANTHROPIC_API_KEY_VAR,
+                &api_settings,
+                credentials_provider,
+                PROVIDER_ID,
+                &api_url,
+                credentials_scope,
+                &cx,
+            )
+            .await;
+            let has_workspace_api_keys = load_workspace_api_keys.await;
+            let (api_keys, source) = match api_keys {
+                Err(AuthenticateError::CredentialsNotFound) if has_workspace_api_keys => {
+                    this.update(cx, |_, cx| cx.notify())?;
+                    return Ok(());
+                }
+                api_keys => api_keys?,
             };
 
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
-                this.api_key_from_env = from_env;
+                this._api_key_refresh_task =
+                    refresh_api_keys(&source, &api_keys, &api_settings, cx, |this, api_keys| {
+                        this.api_keys = api_keys
+                    });
+                this.api_keys = api_keys;
+                this.api_key_source = source;
                 cx.notify();
             })?;
 
@@ -188,23 +361,67 @@ impl State {
 impl AnthropicLanguageModelProvider {
     pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut App) -> Self {
         let state = cx.new(|cx| State {
-            api_key: None,
-            api_key_from_env: false,
-            _subscription: cx.observe_global::<SettingsStore>(|_, cx| {
+            http_client: http_client.clone(),
+            api_url: AllLanguageModelSettings::get_global(cx)
+                .anthropic
+                .api_url
+                .clone(),
+            api_keys: ApiKeys::default(),
+            api_key_source: ApiKeySource::default(),
+            workspace_api_keys: WorkspaceApiKeys::default(),
+            api_key_status: ApiKeyStatus::new(cx, |state| &mut state.api_key_status),
+            _api_key_refresh_task: None,
+            _subscription: cx.observe_global::<SettingsStore>(|this: &mut State, cx| {
+                this.update_api_url(cx);
                 cx.notify();
             }),
+            _credentials_scope_subscription: cx.observe_global::<CredentialsScope>(
+                |this: &mut State, cx| {
+                    this.workspace_api_keys.clear();
+                    if !this.api_key_source.is_external() {
+                        this.api_keys = ApiKeys::default();
+                        this.api_key_status.set_rejected(false, cx);
+                        this.authenticate(cx).detach();
+                        cx.notify();
+                    }
+                },
+            ),
         });
 
-        Self { http_client, state }
+        Self {
+            http_client,
+            state,
+            batch_queue: BatchQueue::default(),
+        }
     }
 
-    fn create_language_model(&self, model: anthropic::Model) -> Arc<dyn LanguageModel> {
+    fn create_language_model(&self, model: anthropic::Model, cx: &App) -> Arc<dyn LanguageModel> {
+        let available_model = AllLanguageModelSettings::get_global(cx)
+            .anthropic
+            .available_models
+            .iter()
+            .find(|available_model| available_model.name == model.id());
+        let supports_tools = available_model
+            .and_then(|available_model| available_model.supports_tools)
+            .unwrap_or(true);
+        let supports_images = available_model
+            .and_then(|available_model| available_model.supports_images)
+            .unwrap_or(true);
+        let supports_parallel_tool_calls = available_model
+            .and_then(|available_model| available_model.supports_parallel_tool_calls)
+            .unwrap_or(true);
         Arc::new(AnthropicModel {
             id: LanguageModelId::from(model.id().to_string()),
             model,
+            supports_tools,
+            supports_images,
+            supports_parallel_tool_calls,
             state: self.state.clone(),
             http_client: self.http_client.clone(),
-            request_limiter: RateLimiter::new(4),
+            batch_queue: self.batch_queue.clone(),
+            request_limiter: RateLimiter::new(
+                AllLanguageModelSettings::get_global(cx).max_concurrent_requests(PROVIDER_ID),
+            ),
         })
     }
 }
@@ -230,21 +447,21 @@ impl LanguageModelProvider for AnthropicLanguageModelProvider {
         IconName::AiAnthropic
     }
 
-    fn default_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(anthropic::Model::default()))
+    fn default_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(anthropic::Model::default(), cx))
     }
 
-    fn default_fast_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(anthropic::Model::default_fast()))
+    fn default_fast_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(anthropic::Model::default_fast(), cx))
     }
 
-    fn recommended_models(&self, _cx: &App) -> Vec<Arc<dyn LanguageModel>> {
+    fn recommended_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
         [
             anthropic::Model::ClaudeSonnet4,
             anthropic::Model::ClaudeSonnet4Thinking,
         ]
         .into_iter()
-        .map(|model| self.create_language_model(model))
+        .map(|model| self.create_language_model(model, cx))
         .collect()
     }
 
@@ -281,14 +498,23 @@ impl LanguageModelProvider for AnthropicLanguageModelProvider {
                     max_output_tokens: model.max_output_tokens,
                     default_temperature: model.default_temperature,
                     extra_beta_headers: model.extra_beta_headers.clone(),
-                    mode: model.mode.clone().unwrap_or_default().into(),
+                    mode: if model.supports_thinking == Some(false) {
+                        AnthropicModelMode::Default
+                    } else {
+                        match model.reasoning.budget_tokens {
+                            Some(budget_tokens) => AnthropicModelMode::Thinking {
+                                budget_tokens: Some(budget_tokens),
+                            },
+                            None => model.mode.clone().unwrap_or_default().into(),
+                        }
+                    },
                 },
             );
         }
 
         models
             .into_values()
-            .map(|model| self.create_language_model(model))
+            .map(|model| self.create_language_model(model, cx))
             .collect()
     }
 
@@ -296,6 +522,10 @@ impl LanguageModelProvider for AnthropicLanguageModelProvider {
         self.state.read(cx).is_authenticated()
     }
 
+    fn credentials_rejected(&self, cx: &App) -> bool {
+        self.state.read(cx).api_key_status.is_rejected()
+    }
+
     fn authenticate(&self, cx: &mut App) -> Task<Result<(), AuthenticateError>> {
         self.state.update(cx, |state, cx| state.authenticate(cx))
     }
@@ -306,18 +536,89 @@ impl LanguageModelProvider for AnthropicLanguageModelProvider {
     }
 
     fn reset_credentials(&self, cx: &mut App) -> Task<Result<()>> {
-        self.state.update(cx, |state, cx| state.reset_api_key(cx))
+        self.state
+            .update(cx, |state, cx| state.reset_api_key(None, cx))
     }
 }
 
 pub struct AnthropicModel {
     id: LanguageModelId,
     model: anthropic::Model,
+    supports_tools: bool,
+    supports_images: bool,
+    supports_parallel_tool_calls: bool,
     state: gpui::Entity<State>,
     http_client: Arc<dyn HttpClient>,
+    batch_queue: BatchQueue<AnthropicBatchApi>,
     request_limiter: RateLimiter,
 }
 
+/// Anthropic's Message Batches API, used with one API key.
+struct AnthropicBatchApi {
+    http_client: Arc<dyn HttpClient>,
+    api_url: String,
+    api_key: String,
+}
+
+impl BatchApi for AnthropicBatchApi {
+    type Request = anthropic::Request;
+    type Response = anthropic::Response;
+
+    fn submit(
+        &self,
+        requests: Vec<(String, anthropic::Request)>,
+    ) -> BoxFuture<'static, Result<String>> {
+        let http_client = self.http_client.clone();
+        let api_url = self.api_url.clone();
+        let api_key = self.api_key.clone();
+        async move {
+            let batch = anthropic::batches::create_batch(
+                http_client.as_ref(),
+                &api_url,
+                &api_key,
+                &requests,
+            )
+            .await?;
+            Ok(batch.id)
+        }
+        .boxed()
+    }
+
+    fn results(
+        &self,
+        batch_id: &str,
+    ) -> BoxFuture<'static, Result<Option<HashMap<String, Result<anthropic::Response>>>>> {
+        let http_client = self.http_client.clone();
+        let api_url = self.api_url.clone();
+        let api_key = self.api_key.clone();
+        let batch_id = batch_id.to_string();
+        async move {
+            let batch = anthropic::batches::retrieve_batch(
+                http_client.as_ref(),
+                &api_url,
+                &api_key,
+                &batch_id,
+            )
+            .await?;
+            if batch.processing_status != "ended" {
+                return Ok(None);
+            }
+            let results =
+                anthropic::batches::batch_results(http_client.as_ref(), &api_key, &batch).await?;
+            Ok(Some(
+                results
+                    .into_iter()
+                    .map(|result| {
+                        let response = result.result.into_result().map_err(anthropic_err_to_anyhow);
+                        (result.custom_id, response)
+                    })
+                    .collect(),
+            ))
+        }
+        .boxed()
+    }
+}
+
 pub fn count_anthropic_tokens(
     request: LanguageModelRequest,
     cx: &App,
@@ -337,6 +638,9 @@ pub fn count_anthropic_tokens(
                     MessageContent::Text(text) => {
                         string_contents.push_str(&text);
                     }
+                    MessageContent::Document(document) => {
+                        string_contents.push_str(&document.text);
+                    }
                     MessageContent::Thinking { .. } => {
                         // Thinking blocks are not included in the input token count.
                     }
@@ -346,6 +650,12 @@ pub fn count_anthropic_tokens(
                     MessageContent::Image(image) => {
                         tokens_from_images += image.estimate_tokens();
                     }
+                    MessageContent::Pdf(pdf) => {
+                        tokens_from_images += pdf.estimate_tokens();
+                    }
+                    MessageContent::Audio(_) | MessageContent::Video(_) => {
+                        // Anthropic models don't take audio or video.
+                    }
                     MessageContent::ToolUse(_tool_use) => {
                         // TODO: Estimate token usage from tool uses.
                     }
@@ -385,24 +695,108 @@ pub fn count_anthropic_tokens(
 impl AnthropicModel {
     fn stream_completion(
         &self,
-        request: anthropic::Request,
+        mut request: anthropic::Request,
+        output_tokens: OutputTokenClamp,
+        usage: KeyUsageRecorder,
+        workspace: Option<WorkspaceScope>,
         cx: &AsyncApp,
     ) -> BoxFuture<'static, Result<BoxStream<'static, Result<anthropic::Event, AnthropicError>>>>
     {
         let http_client = self.http_client.clone();
 
-        let Ok((api_key, api_url)) = cx.read_entity(&self.state, |state, cx| {
+        let Ok((api_keys, rotation, api_url)) = cx.read_entity(&self.state, |state, cx| {
             let settings = &AllLanguageModelSettings::get_global(cx).anthropic;
-            (state.api_key.clone(), settings.api_url.clone())
+            (
+                state.workspace_api_keys.for_request(
+                    &state.api_keys,
+                    &state.api_key_status,
+                    &state.api_key_source,
+                    workspace.as_ref(),
+                    PROVIDER_ID,
+                    &settings.api_url,
+                    cx,
+                ),
+                settings.api.api_key_rotation,
+                settings.api_url.clone(),
+            )
         }) else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
 
         async move {
-            let api_key = api_key.context("Missing Anthropic API Key")?;
-            let request =
-                anthropic::stream_completion(http_client.as_ref(), &api_url, &api_key, request);
-            request.await.context("failed to stream completion")
+            let api_keys = api_keys.await;
+            clamp_output_tokens(&mut request, output_tokens).await;
+            let (http_client, api_url, request) = (http_client.as_ref(), &api_url, &request);
+            api_keys
+                .with_rotation(rotation, "Anthropic", &usage, |api_key| async move {
+                    anthropic::stream_completion(http_client, api_url, &api_key, request.clone())
+                        .await
+                        .context("failed to stream completion")
+                })
+                .await
+        }
+        .boxed()
+    }
+
+    /// Sends `request` in the next batch, and streams its response once the batch finishes.
+    fn batch_completion(
+        &self,
+        mut request: anthropic::Request,
+        output_tokens: OutputTokenClamp,
+        settings: BatchSettings,
+        usage: KeyUsageRecorder,
+        workspace: Option<WorkspaceScope>,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<BoxStream<'static, Result<anthropic::Event, AnthropicError>>>>
+    {
+        let http_client = self.http_client.clone();
+        let batch_queue = self.batch_queue.clone();
+        let executor = cx.background_executor().clone();
+
+        let Ok((api_keys, rotation, api_url)) = cx.read_entity(&self.state, |state, cx| {
+            let settings = &AllLanguageModelSettings::get_global(cx).anthropic;
+            (
+                state.workspace_api_keys.for_request(
+                    &state.api_keys,
+                    &state.api_key_status,
+                    &state.api_key_source,
+                    workspace.as_ref(),
+                    PROVIDER_ID,
+                    &settings.api_url,
+                    cx,
+                ),
+                settings.api.api_key_rotation,
+                settings.api_url.clone(),
+            )
+        }) else {
+            return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
+        };
+
+        async move {
+            let api_keys = api_keys.await;
+            clamp_output_tokens(&mut request, output_tokens).await;
+            let (http_client, api_url, request) = (&http_client, &api_url, &request);
+            let (batch_queue, executor) = (&batch_queue, &executor);
+            let response = api_keys
+                .with_rotation(rotation, "Anthropic", &usage, |api_key| async move {
+                    let api = AnthropicBatchApi {
+                        http_client: http_client.clone(),
+                        api_url: api_url.clone(),
+                        api_key: api_key.clone(),
+                    };
+                    batch_queue
+                        .enqueue(
+                            format!("{api_url}\n{api_key}"),
+                            api,
+                            request.clone(),
+                            settings,
+                            executor,
+                        )
+                        .await
+                })
+                .await?;
+            let events = anthropic::batches::response_events(response);
+            Ok(futures::stream::iter(events.into_iter().map(Ok)).boxed())
         }
         .boxed()
     }
@@ -426,27 +820,57 @@ impl LanguageModel for AnthropicModel {
     }
 
     fn supports_tools(&self) -> bool {
-        true
+        self.supports_tools
     }
 
     fn supports_images(&self) -> bool {
-        true
+        self.supports_images
+    }
+
+    fn document_limits(&self) -> Option<DocumentLimits> {
+        Some(DocumentLimits::default())
     }
 
     fn supports_tool_choice(&self, choice: LanguageModelToolChoice) -> bool {
         match choice {
             LanguageModelToolChoice::Auto
             | LanguageModelToolChoice::Any
-            | LanguageModelToolChoice::None => true,
+            | LanguageModelToolChoice::None
+            | LanguageModelToolChoice::Tool(_) => self.supports_tools,
         }
     }
 
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.supports_parallel_tool_calls
+    }
+
+    fn supports_thinking(&self) -> bool {
+        matches!(self.model.mode(), AnthropicModelMode::Thinking { .. })
+    }
+
+    fn supports_assistant_prefill(&self) -> bool {
+        true
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        StructuredOutput::ToolCall
+    }
+
     fn telemetry_id(&self) -> String {
         format!("anthropic/{}", self.model.id())
     }
 
     fn api_key(&self, cx: &App) -> Option<String> {
-        self.state.read(cx).api_key.clone()
+        self.state
+            .read(cx)
+            .api_keys
+            .current()
+            .map(ToString::to_string)
+    }
+
+    fn price(&self, cx: &App) -> Option<ModelPrice> {
+        let settings = AllLanguageModelSettings::get_global(cx);
+        model_price(settings, PROVIDER_ID, &self.id.0)
     }
 
     fn max_token_count(&self) -> usize {
@@ -475,24 +899,92 @@ impl LanguageModel for AnthropicModel {
             BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
         >,
     > {
-        let request = into_anthropic(
+        let model_id = self.id();
+        let sampling = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings
+                .anthropic
+                .available_models
+                .iter()
+                .find(|model| model_id.0 == model.name)
+                .map(|model| model.sampling)
+        })
+        .flatten()
+        .unwrap_or_default();
+        let (system_prompt, automatic_caching) =
+            AllLanguageModelSettings::try_read_global(cx, |settings| {
+                (
+                    settings.anthropic.api.system_prompt.clone(),
+                    settings.anthropic.automatic_caching,
+                )
+            })
+            .unwrap_or_default();
+        let mut request = system_prompt.apply(sampling.apply(request));
+        if !self.supports_parallel_tool_calls {
+            request.parallel_tool_calls = Some(false);
+        }
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
+        let moderation = check_moderation(&request, cx);
+        let output_tokens = OutputTokenClamp::new(self, &request, cx);
+        let batch = BatchSettings::for_request(&request, cx);
+        let document_sources = document_sources(&request);
+        let workspace = request.workspace.clone();
+        let mut request = into_anthropic(
             request,
             self.model.request_id().into(),
             self.model.default_temperature(),
             self.model.max_output_tokens(),
             self.model.mode(),
         );
-        let request = self.stream_completion(request, cx);
-        let future = self.request_limiter.stream(async move {
-            let response = request
-                .await
-                .map_err(|err| match err.downcast::<AnthropicError>() {
-                    Ok(anthropic_err) => anthropic_err_to_anyhow(anthropic_err),
-                    Err(err) => anyhow!(err),
-                })?;
-            Ok(AnthropicEventMapper::new().map_stream(response))
-        });
-        async move { Ok(future.await?.boxed()) }.boxed()
+        if let Some(cache_configuration) = self.model.cache_configuration() {
+            if automatic_caching.enabled() {
+                let min_tokens =
+                    automatic_caching.min_tokens(cache_configuration.min_total_token as u64);
+                place_cache_breakpoints(&mut request, min_tokens);
+            }
+        }
+        let usage =
+            KeyUsageRecorder::new(PROVIDER_ID, self.id.0.to_string(), workspace.clone(), cx);
+        let timeouts = StreamTimeouts::new(|settings| &settings.anthropic.api, cx);
+        let spend_limits = check_spend_limits(PROVIDER_ID, cx);
+        let Some(batch) = batch else {
+            let request =
+                self.stream_completion(request, output_tokens, usage.clone(), workspace, cx);
+            let future = self.request_limiter.stream(async move {
+                spend_limits?;
+                moderation.await?;
+                let response =
+                    request
+                        .await
+                        .map_err(|err| match err.downcast::<AnthropicError>() {
+                            Ok(anthropic_err) => anthropic_err_to_anyhow(anthropic_err),
+                            Err(err) => anyhow!(err),
+                        })?;
+                let events = AnthropicEventMapper::new()
+                    .with_document_sources(document_sources)
+                    .map_stream(response)
+                    .boxed();
+                Ok(usage.track(timeouts.apply(estimate_live_usage(normalize_reasoning(events)))))
+            });
+            return request_log.track(async move { Ok(future.await?.boxed()) }.boxed());
+        };
+
+        // Batches can take hours, so they don't hold one of the model's concurrent requests.
+        let usage = usage.batched();
+        let request =
+            self.batch_completion(request, output_tokens, batch, usage.clone(), workspace, cx);
+        request_log.track(
+            async move {
+                spend_limits?;
+                moderation.await?;
+                let response = request.await?;
+                let events = AnthropicEventMapper::new()
+                    .with_document_sources(document_sources)
+                    .map_stream(response)
+                    .boxed();
+                Ok(usage.track(normalize_reasoning(events)))
+            }
+            .boxed(),
+        )
     }
 
     fn cache_configuration(&self) -> Option<LanguageModelCacheConfiguration> {
@@ -506,6 +998,40 @@ impl LanguageModel for AnthropicModel {
     }
 }
 
+/// Lowers the most tokens that `request`'s response can have to what's left of the context window,
+/// keeping the thinking budget below it as the API requires.
+async fn clamp_output_tokens(request: &mut anthropic::Request, output_tokens: OutputTokenClamp) {
+    request.max_tokens = output_tokens.apply(request.max_tokens).await;
+    if let Some(anthropic::Thinking::Enabled {
+        budget_tokens: Some(budget_tokens),
+    }) = &mut request.thinking
+    {
+        if *budget_tokens >= request.max_tokens {
+            if request.max_tokens > MIN_THINKING_BUDGET_TOKENS {
+                *budget_tokens = request.max_tokens - 1;
+            } else {
+                request.thinking = None;
+            }
+        }
+    }
+}
+
+/// The sources of the documents in `request`, in the order that Anthropic numbers them in
+/// citations.
+pub fn document_sources(request: &LanguageModelRequest) -> Vec<String> {
+    request
+        .messages
+        .iter()
+        .filter(|message| message.role != Role::System && !message.contents_empty())
+        .flat_map(|message| &message.content)
+        .filter_map(|content| match content {
+            MessageContent::Document(document) => Some(document.source.clone()),
+            MessageContent::Pdf(pdf) => Some(pdf.name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
 pub fn into_anthropic(
     request: LanguageModelRequest,
     model: String,
@@ -515,22 +1041,16 @@ pub fn into_anthropic(
 ) -> anthropic::Request {
     let mut new_messages: Vec<anthropic::Message> = Vec::new();
     let mut system_message = String::new();
+    let mut cache_system_message = false;
 
-    for message in request.messages {
+    for message in normalize_messages(request.messages, MessageConstraints::STRICT) {
         if message.contents_empty() {
             continue;
         }
 
         match message.role {
             Role::User | Role::Assistant => {
-                let cache_control = if message.cache {
-                    Some(anthropic::CacheControl {
-                        cache_type: anthropic::CacheControlType::Ephemeral,
-                    })
-                } else {
-                    None
-                };
-                let anthropic_message_content: Vec<anthropic::RequestContent> = message
+                let mut anthropic_message_content: Vec<anthropic::RequestContent> = message
                     .content
                     .into_iter()
                     .filter_map(|content| match content {
@@ -538,7 +1058,7 @@ pub fn into_anthropic(
                             if !text.is_empty() {
                                 Some(anthropic::RequestContent::Text {
                                     text,
-                                    cache_control,
+                                    cache_control: None,
                                 })
                             } else {
                                 None
@@ -548,11 +1068,14 @@ pub fn into_anthropic(
                             text: thinking,
                             signature,
                         } => {
+                            // Anthropic rejects thinking without the signature it was streamed
+                            // with, such as thinking from another provider's model.
+                            let signature = signature.filter(|signature| !signature.is_empty())?;
                             if !thinking.is_empty() {
                                 Some(anthropic::RequestContent::Thinking {
                                     thinking,
-                                    signature: signature.unwrap_or_default(),
-                                    cache_control,
+                                    signature,
+                                    cache_control: None,
                                 })
                             } else {
                                 None
@@ -567,20 +1090,43 @@ pub fn into_anthropic(
                                 None
                             }
                         }
+                        MessageContent::Document(document) => {
+                            Some(anthropic::RequestContent::Document {
+                                source: anthropic::DocumentSource {
+                                    source_type: "text".to_string(),
+                                    media_type: "text/plain".to_string(),
+                                    data: document.text,
+                                },
+                                title: Some(document.title.unwrap_or(document.source)),
+                                citations: Some(anthropic::CitationsConfig { enabled: true }),
+                                cache_control: None,
+                            })
+                        }
+                        MessageContent::Audio(_) | MessageContent::Video(_) => None,
+                        MessageContent::Pdf(pdf) => Some(anthropic::RequestContent::Document {
+                            source: anthropic::DocumentSource {
+                                source_type: "base64".to_string(),
+                                media_type: "application/pdf".to_string(),
+                                data: pdf.source.to_string(),
+                            },
+                            title: Some(pdf.name),
+                            citations: Some(anthropic::CitationsConfig { enabled: true }),
+                            cache_control: None,
+                        }),
                         MessageContent::Image(image) => Some(anthropic::RequestContent::Image {
                             source: anthropic::ImageSource {
                                 source_type: "base64".to_string(),
-                                media_type: "image/png".to_string(),
+                                media_type: image.mime_type().to_string(),
                                 data: image.source.to_string(),
                             },
-                            cache_control,
+                            cache_control: None,
                         }),
                         MessageContent::ToolUse(tool_use) => {
                             Some(anthropic::RequestContent::ToolUse {
                                 id: tool_use.id.to_string(),
                                 name: tool_use.name.to_string(),
                                 input: tool_use.input,
-                                cache_control,
+                                cache_control: None,
                             })
                         }
                         MessageContent::ToolResult(tool_result) => {
@@ -595,17 +1141,26 @@ pub fn into_anthropic(
                                         ToolResultContent::Multipart(vec![ToolResultPart::Image {
                                             source: anthropic::ImageSource {
                                                 source_type: "base64".to_string(),
-                                                media_type: "image/png".to_string(),
+                                                media_type: image.mime_type().to_string(),
                                                 data: image.source.to_string(),
                                             },
                                         }])
                                     }
                                 },
-                                cache_control,
+                                cache_control: None,
                             })
                         }
                     })
                     .collect();
+                if message.cache {
+                    if let Some(cache_control) = anthropic_message_content
+                        .iter_mut()
+                        .rev()
+                        .find_map(cache_control_mut)
+                    {
+                        *cache_control = Some(ephemeral_cache_control());
+                    }
+                }
                 let anthropic_role = match message.role {
                     Role::User => anthropic::Role::User,
                     Role::Assistant => anthropic::Role::Assistant,
@@ -627,9 +1182,68 @@ pub fn into_anthropic(
                     system_message.push_str("\n\n");
                 }
                 system_message.push_str(&message.string_contents());
+                cache_system_message |= message.cache;
             }
         }
     }
+    limit_cache_breakpoints(&mut new_messages, cache_system_message);
+
+    let mut tools = request
+        .tools
+        .into_iter()
+        .map(|tool| anthropic::Tool {
+            name: tool.name,
+            description: tool.description,
+            input_schema: tool.input_schema,
+            cache_control: None,
+        })
+        .collect::<Vec<_>>();
+    let disable_parallel_tool_use = request.parallel_tool_calls.map(|parallel| !parallel);
+    let mut tool_choice = match request.tool_choice {
+        Some(LanguageModelToolChoice::Auto) => Some(anthropic::ToolChoice::Auto {
+            disable_parallel_tool_use,
+        }),
+        Some(LanguageModelToolChoice::Any) => Some(anthropic::ToolChoice::Any {
+            disable_parallel_tool_use,
+        }),
+        Some(LanguageModelToolChoice::None) => Some(anthropic::ToolChoice::None),
+        Some(LanguageModelToolChoice::Tool(name)) => Some(anthropic::ToolChoice::Tool {
+            name,
+            disable_parallel_tool_use,
+        }),
+        None if disable_parallel_tool_use == Some(true) && !tools.is_empty() => {
+            Some(anthropic::ToolChoice::Auto {
+                disable_parallel_tool_use,
+            })
+        }
+        None => None,
+    };
+    // Anthropic only allows thinking when the model is free to choose whether to call a tool.
+    let forces_tool_use = matches!(
+        tool_choice,
+        Some(anthropic::ToolChoice::Any { .. } | anthropic::ToolChoice::Tool { .. })
+    );
+    let mut thinking = match mode {
+        AnthropicModelMode::Thinking { budget_tokens } if !forces_tool_use => {
+            Some(anthropic::Thinking::Enabled { budget_tokens })
+        }
+        _ => None,
+    };
+    if let Some(response_schema) = request.response_schema {
+        tools.push(anthropic::Tool {
+            name: RESPONSE_TOOL_NAME.into(),
+            description: response_schema
+                .description
+                .unwrap_or_else(|| format!("Respond with a {}.", response_schema.name)),
+            input_schema: response_schema.schema,
+            cache_control: None,
+        });
+        tool_choice = Some(anthropic::ToolChoice::Tool {
+            name: RESPONSE_TOOL_NAME.into(),
+            disable_parallel_tool_use: None,
+        });
+        thinking = None;
+    }
 
     anthropic::Request {
         model,
@@ -637,51 +1251,102 @@ pub fn into_anthropic(
         max_tokens: max_output_tokens,
         system: if system_message.is_empty() {
             None
+        } else if cache_system_message {
+            // Tools come before the system prompt in the cached prefix, so this caches them too.
+            Some(anthropic::StringOrContents::Content(vec![
+                anthropic::RequestContent::Text {
+                    text: system_message,
+                    cache_control: Some(ephemeral_cache_control()),
+                },
+            ]))
         } else {
             Some(anthropic::StringOrContents::String(system_message))
         },
-        thinking: if let AnthropicModelMode::Thinking { budget_tokens } = mode {
-            Some(anthropic::Thinking::Enabled { budget_tokens })
-        } else {
-            None
-        },
-        tools: request
-            .tools
-            .into_iter()
-            .map(|tool| anthropic::Tool {
-                name: tool.name,
-                description: tool.description,
-                input_schema: tool.input_schema,
-            })
-            .collect(),
-        tool_choice: request.tool_choice.map(|choice| match choice {
-            LanguageModelToolChoice::Auto => anthropic::ToolChoice::Auto,
-            LanguageModelToolChoice::Any => anthropic::ToolChoice::Any,
-            LanguageModelToolChoice::None => anthropic::ToolChoice::None,
-        }),
+        thinking,
+        tools,
+        tool_choice,
         metadata: None,
-        stop_sequences: Vec::new(),
+        stop_sequences: request.stop,
         temperature: request.temperature.or(Some(default_temperature)),
-        top_k: None,
-        top_p: None,
+        top_k: request.top_k,
+        top_p: request.top_p,
+    }
+}
+
+/// The most cache breakpoints that Anthropic accepts in a request.
+const MAX_CACHE_BREAKPOINTS: usize = 4;
+
+fn ephemeral_cache_control() -> anthropic::CacheControl {
+    anthropic::CacheControl {
+        cache_type: anthropic::CacheControlType::Ephemeral,
+    }
+}
+
+/// The cache breakpoint of a content block, unless it's a kind of block that can't have one.
+fn cache_control_mut(
+    content: &mut anthropic::RequestContent,
+) -> Option<&mut Option<anthropic::CacheControl>> {
+    match content {
+        anthropic::RequestContent::Text { cache_control, .. }
+        | anthropic::RequestContent::Image { cache_control, .. }
+        | anthropic::RequestContent::ToolUse { cache_control, .. }
+        | anthropic::RequestContent::ToolResult { cache_control, .. }
+        | anthropic::RequestContent::Document { cache_control, .. } => Some(cache_control),
+        anthropic::RequestContent::Thinking { .. }
+        | anthropic::RequestContent::RedactedThinking { .. } => None,
+    }
+}
+
+/// Drops the earliest cache breakpoints of the messages when there are more than Anthropic
+/// accepts, since the later ones cache more of the conversation.
+fn limit_cache_breakpoints(messages: &mut [anthropic::Message], cache_system_message: bool) {
+    let mut remaining = MAX_CACHE_BREAKPOINTS - cache_system_message as usize;
+    for content in messages
+        .iter_mut()
+        .rev()
+        .flat_map(|message| message.content.iter_mut().rev())
+    {
+        let Some(cache_control) = cache_control_mut(content) else {
+            continue;
+        };
+        if cache_control.is_some() {
+            if remaining == 0 {
+                *cache_control = None;
+            } else {
+                remaining -= 1;
+            }
+        }
     }
 }
 
 pub struct AnthropicEventMapper {
     tool_uses_by_index: HashMap<usize, RawToolUse>,
+    /// The index of the call to the response tool, whose input is streamed as text.
+    response_index: Option<usize>,
     usage: Usage,
     stop_reason: StopReason,
+    /// The sources of the request's documents, which citations refer to by index.
+    document_sources: Vec<String>,
 }
 
 impl AnthropicEventMapper {
     pub fn new() -> Self {
         Self {
             tool_uses_by_index: HashMap::default(),
+            response_index: None,
             usage: Usage::default(),
             stop_reason: StopReason::EndTurn,
+            document_sources: Vec::new(),
         }
     }
 
+    /// Links citations to the sources of the documents of the request, from
+    /// [`document_sources`].
+    pub fn with_document_sources(mut self, document_sources: Vec<String>) -> Self {
+        self.document_sources = document_sources;
+        self
+    }
+
     pub fn map_stream(
         mut self,
         events: Pin<Box<dyn Send + Stream<Item = Result<Event, AnthropicError>>>>,
@@ -690,11 +1355,26 @@ impl AnthropicEventMapper {
         events.flat_map(move |event| {
             futures::stream::iter(match event {
                 Ok(event) => self.map_event(event),
-                Err(error) => vec![Err(LanguageModelCompletionError::Other(anyhow!(error)))],
+                Err(error) => vec![Err(completion_error(error))],
             })
         })
     }
 
+    fn convert_citation(&self, citation: anthropic::TextCitation) -> Citation {
+        let source = citation
+            .document_index
+            .and_then(|ix| self.document_sources.get(ix));
+        Citation {
+            url: source
+                .or(citation.document_title.as_ref())
+                .cloned()
+                .unwrap_or_default(),
+            title: citation.document_title,
+            cited_text: Some(citation.cited_text),
+            confidence: None,
+        }
+    }
+
     pub fn map_event(
         &mut self,
         event: Event,
@@ -704,8 +1384,17 @@ impl AnthropicEventMapper {
                 index,
                 content_block,
             } => match content_block {
-                ResponseContent::Text { text } => {
-                    vec![Ok(LanguageModelCompletionEvent::Text(text))]
+                ResponseContent::Text { text, citations } => {
+                    let mut events = vec![Ok(LanguageModelCompletionEvent::Text(text))];
+                    if let Some(citations) = citations.filter(|citations| !citations.is_empty()) {
+                        events.push(Ok(LanguageModelCompletionEvent::Citations(
+                            citations
+                                .into_iter()
+                                .map(|citation| self.convert_citation(citation))
+                                .collect(),
+                        )));
+                    }
+                    events
                 }
                 ResponseContent::Thinking { thinking } => {
                     vec![Ok(LanguageModelCompletionEvent::Thinking {
@@ -713,9 +1402,14 @@ impl AnthropicEventMapper {
                         signature: None,
                     })]
                 }
-                ResponseContent::RedactedThinking { .. } => {
-                    // Redacted thinking is encrypted and not accessible to the user, see:
+                ResponseContent::RedactedThinking { data } => {
+                    // Redacted thinking is encrypted and not accessible to the user, but has to be
+                    // sent back with the rest of the turn, see:
                     // https://docs.anthropic.com/en/docs/build-with-claude/extended-thinking#suggestions-for-handling-redacted-thinking-in-production
+                    vec![Ok(LanguageModelCompletionEvent::RedactedThinking { data })]
+                }
+                ResponseContent::ToolUse { name, .. } if name == RESPONSE_TOOL_NAME => {
+                    self.response_index = Some(index);
                     Vec::new()
                 }
                 ResponseContent::ToolUse { id, name, .. } => {
@@ -746,29 +1440,25 @@ impl AnthropicEventMapper {
                         signature: Some(signature),
                     })]
                 }
+                ContentDelta::InputJsonDelta { partial_json }
+                    if self.response_index == Some(index) =>
+                {
+                    vec![Ok(LanguageModelCompletionEvent::Text(partial_json))]
+                }
+                ContentDelta::CitationsDelta { citation } => {
+                    vec![Ok(LanguageModelCompletionEvent::Citations(vec![
+                        self.convert_citation(citation),
+                    ]))]
+                }
                 ContentDelta::InputJsonDelta { partial_json } => {
-                    if let Some(tool_use) = self.tool_uses_by_index.get_mut(&index) {
-                        tool_use.input_json.push_str(&partial_json);
-
-                        // Try to convert invalid (incomplete) JSON into
-                        // valid JSON that serde can accept, e.g. by closing
-                        // unclosed delimiters. This way, we can update the
-                        // UI with whatever has been streamed back so far.
-                        if let Ok(input) = serde_json::Value::from_str(
-                            &partial_json_fixer::fix_json(&tool_use.input_json),
-                        ) {
-                            return vec![Ok(LanguageModelCompletionEvent::ToolUse(
-                                LanguageModelToolUse {
-                                    id: tool_use.id.clone().into(),
-                                    name: tool_use.name.clone().into(),
-                                    is_input_complete: false,
-                                    raw_input: tool_use.input_json.clone(),
-                                    input,
-                                },
-                            ))];
-                        }
-                    }
-                    return vec![];
+                    let Some(tool_use) = self.tool_uses_by_index.get_mut(&index) else {
+                        return Vec::new();
+                    };
+                    tool_use.input_json.push_str(&partial_json);
+                    partial_tool_use(&tool_use.id, &tool_use.name, &tool_use.input_json)
+                        .map(Ok)
+                        .into_iter()
+                        .collect()
                 }
             },
             Event::ContentBlockStop { index } => {
@@ -817,8 +1507,9 @@ impl AnthropicEventMapper {
                 update_usage(&mut self.usage, &usage);
                 if let Some(stop_reason) = delta.stop_reason.as_deref() {
                     self.stop_reason = match stop_reason {
-                        "end_turn" => StopReason::EndTurn,
+                        "end_turn" | "stop_sequence" => StopReason::EndTurn,
                         "max_tokens" => StopReason::MaxTokens,
+                        "tool_use" if self.response_index.is_some() => StopReason::EndTurn,
                         "tool_use" => StopReason::ToolUse,
                         "refusal" => StopReason::Refusal,
                         _ => {
@@ -860,6 +1551,23 @@ pub fn anthropic_err_to_anyhow(err: AnthropicError) -> anyhow::Error {
     anyhow!(err)
 }
 
+/// Maps an error that Anthropic sent partway through a response, such as an `overloaded_error`
+/// event, to the matching [`LanguageModelCompletionError`].
+fn completion_error(error: AnthropicError) -> LanguageModelCompletionError {
+    let code = match &error {
+        AnthropicError::ApiError(api_error) => api_error.code(),
+        AnthropicError::Other(_) => None,
+    };
+    match code {
+        Some(ApiErrorCode::OverloadedError) => LanguageModelCompletionError::Overloaded,
+        Some(ApiErrorCode::RateLimitError) => {
+            LanguageModelCompletionError::RateLimited { retry_after: None }
+        }
+        Some(ApiErrorCode::AuthenticationError) => LanguageModelCompletionError::InvalidApiKey,
+        _ => LanguageModelCompletionError::Other(anyhow!(error)),
+    }
+}
+
 /// Updates usage data by preferring counts from `new`.
 fn update_usage(usage: &mut Usage, new: &Usage) {
     if let Some(input_tokens) = new.input_tokens {
@@ -882,6 +1590,7 @@ fn convert_usage(usage: &Usage) -> language_model::TokenUsage {
         output_tokens: usage.output_tokens.unwrap_or(0),
         cache_creation_input_tokens: usage.cache_creation_input_tokens.unwrap_or(0),
         cache_read_input_tokens: usage.cache_read_input_tokens.unwrap_or(0),
+        reasoning_tokens: 0,
     }
 }
 
@@ -889,6 +1598,9 @@ struct ConfigurationView {
     api_key_editor: Entity<Editor>,
     state: gpui::Entity<State>,
     load_credentials_task: Option<Task<()>>,
+    validation: ApiKeyValidation,
+    save_scope: ApiKeySaveScope,
+    key_display: ApiKeyDisplay,
 }
 
 impl ConfigurationView {
@@ -926,6 +1638,9 @@ impl ConfigurationView {
             }),
             state,
             load_credentials_task,
+            validation: ApiKeyValidation::default(),
+            save_scope: ApiKeySaveScope::new(window, cx),
+            key_display: ApiKeyDisplay::default(),
         }
     }
 
@@ -935,11 +1650,22 @@ impl ConfigurationView {
             return;
         }
 
+        self.validation = ApiKeyValidation::Validating;
+        let workspace_scope = self.save_scope.workspace_scope();
         let state = self.state.clone();
-        cx.spawn_in(window, async move |_, cx| {
-            state
-                .update(cx, |state, cx| state.set_api_key(api_key, cx))?
-                .await
+        cx.spawn_in(window, async move |this, cx| {
+            let result = state
+                .update(cx, |state, cx| {
+                    state.set_api_key(api_key, workspace_scope, cx)
+                })?
+                .await;
+            this.update(cx, |this, cx| {
+                this.validation = ApiKeyValidation::from_result(&result);
+                if result.is_ok() {
+                    this.key_display.finish_replacing();
+                }
+                cx.notify();
+            })
         })
         .detach_and_log_err(cx);
 
@@ -950,9 +1676,12 @@ impl ConfigurationView {
         self.api_key_editor
             .update(cx, |editor, cx| editor.set_text("", window, cx));
 
+        let workspace = self.api_keys(cx).1.workspace().cloned();
         let state = self.state.clone();
         cx.spawn_in(window, async move |_, cx| {
-            state.update(cx, |state, cx| state.reset_api_key(cx))?.await
+            state
+                .update(cx, |state, cx| state.reset_api_key(workspace, cx))?
+                .await
         })
         .detach_and_log_err(cx);
 
@@ -984,14 +1713,31 @@ impl ConfigurationView {
         )
     }
 
+    /// The keys that the view shows, which are the workspace's own if it has any.
+    fn api_keys(&self, cx: &App) -> (ApiKeys, ApiKeySource) {
+        let state = self.state.read(cx);
+        state.workspace_api_keys.for_display(
+            &state.api_keys,
+            &state.api_key_source,
+            self.save_scope.workspace(),
+        )
+    }
+
     fn should_render_editor(&self, cx: &mut Context<Self>) -> bool {
-        !self.state.read(cx).is_authenticated()
+        !self.state.read(cx).is_authenticated() || self.key_display.is_replacing()
     }
 }
 
 impl Render for ConfigurationView {
     fn render(&mut self, _: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let env_var_set = self.state.read(cx).api_key_from_env;
+        let (api_keys, api_key_source) = self.api_keys(cx);
+        let rejected = self.state.read(cx).api_key_status.is_rejected();
+        let env_var = AllLanguageModelSettings::get_global(cx)
+            .anthropic
+            .api
+            .env_var(ANTHROPIC_API_KEY_VAR)
+            .to_string();
+        let key_is_external = api_key_source.is_external();
 
         if self.load_credentials_task.is_some() {
             div().child(Label::new("Loading credentials...")).into_any()
@@ -1025,9 +1771,12 @@ impl Render for ConfigurationView {
                         .rounded_sm()
                         .child(self.render_api_key_editor(cx)),
                 )
+                .children(self.save_scope.render(cx, |this| &mut this.save_scope))
+                .children(self.key_display.render_cancel(cx, |this| &mut this.key_display))
+                .children(self.validation.render())
                 .child(
                     Label::new(
-                        format!("You can also assign the {ANTHROPIC_API_KEY_VAR} environment variable and restart Zed."),
+                        format!("You can also assign the {env_var} environment variable and restart Zed."),
                     )
                     .size(LabelSize::Small)
                     .color(Color::Muted),
@@ -1046,11 +1795,14 @@ impl Render for ConfigurationView {
                     h_flex()
                         .gap_1()
                         .child(Icon::new(IconName::Check).color(Color::Success))
-                        .child(Label::new(if env_var_set {
-                            format!("API key set in {ANTHROPIC_API_KEY_VAR} environment variable.")
-                        } else {
-                            "API key configured.".to_string()
-                        })),
+                        .child(self.key_display.render(
+                            PROVIDER_ID,
+                            &api_keys,
+                            rejected,
+                            &api_key_source,
+                            cx,
+                            |this| &mut this.key_display,
+                        )),
                 )
                 .child(
                     Button::new("reset-key", "Reset Key")
@@ -1058,13 +1810,202 @@ impl Render for ConfigurationView {
                         .icon(Some(IconName::Trash))
                         .icon_size(IconSize::Small)
                         .icon_position(IconPosition::Start)
-                        .disabled(env_var_set)
-                        .when(env_var_set, |this| {
-                            this.tooltip(Tooltip::text(format!("To reset your API key, unset the {ANTHROPIC_API_KEY_VAR} environment variable.")))
+                        .disabled(key_is_external)
+                        .when(key_is_external, |this| {
+                            this.tooltip(Tooltip::text(api_key_source.reset_hint()))
                         })
-                        .on_click(cx.listener(|this, _, window, cx| this.reset_api_key(window, cx))),
+                        .on_click(
+                            cx.listener(|this, _, window, cx| this.reset_api_key(window, cx)),
+                        ),
                 )
                 .into_any()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use language_model::LanguageModelRequestMessage;
+
+    use super::*;
+
+    #[test]
+    fn test_cache_breakpoints() {
+        let message = |role: Role, text: &str| {
+            let mut content = Vec::new();
+            if role == Role::Assistant {
+                content.push(MessageContent::Thinking {
+                    text: "thinking".into(),
+                    signature: Some("signature".into()),
+                });
+            }
+            content.push(MessageContent::Text(text.into()));
+            LanguageModelRequestMessage {
+                role,
+                content,
+                cache: true,
+            }
+        };
+        let request = LanguageModelRequest {
+            messages: vec![
+                message(Role::System, "system"),
+                message(Role::User, "one"),
+                message(Role::Assistant, "two"),
+                message(Role::User, "three"),
+                message(Role::Assistant, "four"),
+                message(Role::User, "five"),
+            ],
+            ..Default::default()
+        };
+        let request = into_anthropic(
+            request,
+            "claude-sonnet-4-latest".into(),
+            1.,
+            1024,
+            AnthropicModelMode::Default,
+        );
+
+        let cached_texts = request
+            .messages
+            .iter()
+            .flat_map(|message| &message.content)
+            .filter_map(|content| match content {
+                anthropic::RequestContent::Text {
+                    text,
+                    cache_control: Some(_),
+                } => Some(text.as_str()),
+                anthropic::RequestContent::Text { .. }
+                | anthropic::RequestContent::Thinking {
+                    cache_control: None,
+                    ..
+                } => None,
+                _ => panic!("unexpected cache breakpoint on {content:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(cached_texts, ["three", "four", "five"]);
+        assert!(matches!(
+            request.system,
+            Some(anthropic::StringOrContents::Content(_))
+        ));
+    }
+
+    #[test]
+    fn test_thinking_replay() {
+        let request = LanguageModelRequest {
+            messages: vec![
+                LanguageModelRequestMessage {
+                    role: Role::User,
+                    content: vec![MessageContent::Text("list the files".into())],
+                    cache: false,
+                },
+                LanguageModelRequestMessage {
+                    role: Role::Assistant,
+                    content: vec![
+                        MessageContent::Thinking {
+                            text: "from another model".into(),
+                            signature: None,
+                        },
+                        MessageContent::Thinking {
+                            text: "I'll list them".into(),
+                            signature: Some("signature".into()),
+                        },
+                        MessageContent::RedactedThinking(b"encrypted".to_vec()),
+                        MessageContent::Text("Listing the files.".into()),
+                    ],
+                    cache: false,
+                },
+            ],
+            ..Default::default()
+        };
+        let mode = AnthropicModelMode::Thinking {
+            budget_tokens: Some(4096),
+        };
+
+        let anthropic_request = into_anthropic(
+            request.clone(),
+            "claude-sonnet-4-latest".into(),
+            1.,
+            8192,
+            mode.clone(),
+        );
+        assert!(matches!(
+            anthropic_request.messages[1].content.as_slice(),
+            [
+                anthropic::RequestContent::Thinking { thinking, signature, .. },
+                anthropic::RequestContent::RedactedThinking { data },
+                anthropic::RequestContent::Text { .. },
+            ] if thinking == "I'll list them" && signature == "signature" && data == "encrypted"
+        ));
+        assert!(anthropic_request.thinking.is_some());
+
+        let anthropic_request = into_anthropic(
+            LanguageModelRequest {
+                tool_choice: Some(LanguageModelToolChoice::Any),
+                ..request
+            },
+            "claude-sonnet-4-latest".into(),
+            1.,
+            8192,
+            mode,
+        );
+        assert!(anthropic_request.thinking.is_none());
+    }
+
+    #[test]
+    fn test_response_schema() {
+        let mut mapper = AnthropicEventMapper::new();
+        let mut events = Vec::new();
+        for event in [
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"tool_use","id":"toolu_1","name":"respond","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"{\"title\": "}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"input_json_delta","partial_json":"\"Fix\"}"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null},"usage":{"output_tokens":8}}"#,
+            r#"{"type":"message_stop"}"#,
+        ] {
+            let event = serde_json::from_str::<Event>(event).unwrap();
+            events.extend(mapper.map_event(event).into_iter().map(Result::unwrap));
+        }
+        let text = events
+            .iter()
+            .filter_map(|event| match event {
+                LanguageModelCompletionEvent::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect::<String>();
+        assert_eq!(text, r#"{"title": "Fix"}"#);
+        assert_eq!(
+            events.last(),
+            Some(&LanguageModelCompletionEvent::Stop(StopReason::EndTurn))
+        );
+        assert!(
+            !events
+                .iter()
+                .any(|event| matches!(event, LanguageModelCompletionEvent::ToolUse(_)))
+        );
+    }
+
+    #[test]
+    fn test_citations() {
+        let mut mapper =
+            AnthropicEventMapper::new().with_document_sources(vec!["https://zed.dev/docs".into()]);
+        let event = serde_json::from_str::<Event>(
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"citations_delta","citation":{"type":"char_location","cited_text":"Zed is a code editor.","document_index":0,"document_title":"Zed Docs","start_char_index":0,"end_char_index":21}}}"#,
+        )
+        .unwrap();
+        let events = mapper
+            .map_event(event)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![LanguageModelCompletionEvent::Citations(vec![Citation {
+                url: "https://zed.dev/docs".into(),
+                title: Some("Zed Docs".into()),
+                cited_text: Some("Zed is a code editor.".into()),
+                confidence: None,
+            }])]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use anthropic::{Request, RequestContent, StringOrContents};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::{MAX_CACHE_BREAKPOINTS, cache_control_mut, ephemeral_cache_control};
+use crate::request_budget::estimate_tokens;
+
+/// Settings for placing Anthropic's prompt cache breakpoints automatically.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AutomaticCachingSettings {
+    /// Whether to add cache breakpoints after the tools, the system prompt, the first message and
+    /// the last message of requests, on top of the ones Zed places itself.
+    ///
+    /// Default: false
+    pub enabled: Option<bool>,
+    /// The fewest tokens that have to come after the previous breakpoint for another one to be
+    /// placed. Anthropic doesn't cache prompts below a model-specific minimum, and every
+    /// breakpoint makes the cache more expensive to write.
+    ///
+    /// Default: the model's minimum cacheable prompt
+    pub min_tokens: Option<u64>,
+}
+
+impl AutomaticCachingSettings {
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled.unwrap_or(false)
+    }
+
+    pub(crate) fn min_tokens(&self, model_min_tokens: u64) -> u64 {
+        self.min_tokens.unwrap_or(model_min_tokens)
+    }
+}
+
+/// Adds cache breakpoints to `request` at the boundaries that usually stay the same between the
+/// requests of a conversation: after the tools, after the system prompt, after the first message
+/// and after the whole conversation so far. A breakpoint is only added when at least `min_tokens`
+/// come after the previous one, and never beyond the most breakpoints Anthropic accepts, which
+/// the breakpoints that are already in the request take precedence over.
+pub(crate) fn place_cache_breakpoints(request: &mut Request, min_tokens: u64) {
+    let existing = request
+        .system
+        .iter()
+        .flat_map(|system| match system {
+            StringOrContents::String(_) => &[][..],
+            StringOrContents::Content(contents) => contents.as_slice(),
+        })
+        .chain(request.messages.iter().flat_map(|message| &message.content))
+        .filter(|content| has_cache_breakpoint(content))
+        .count();
+    let mut placer = BreakpointPlacer {
+        remaining: MAX_CACHE_BREAKPOINTS.saturating_sub(existing),
+        min_tokens,
+        bytes: 0,
+        last_breakpoint_bytes: 0,
+    };
+
+    for tool in &request.tools {
+        placer.bytes += json_len(tool);
+    }
+    if let Some(tool) = request.tools.last_mut() {
+        if placer.should_place() {
+            tool.cache_control = Some(ephemeral_cache_control());
+        }
+    }
+
+    if let Some(system) = &mut request.system {
+        placer.bytes += json_len(system);
+        match system {
+            StringOrContents::Content(contents) if contents.iter().any(has_cache_breakpoint) => {
+                placer.last_breakpoint_bytes = placer.bytes;
+            }
+            StringOrContents::String(text) => {
+                if placer.should_place() {
+                    *system = StringOrContents::Content(vec![RequestContent::Text {
+                        text: std::mem::take(text),
+                        cache_control: Some(ephemeral_cache_control()),
+                    }]);
+                }
+            }
+            StringOrContents::Content(contents) => {
+                if let Some(cache_control) = contents.iter_mut().rev().find_map(cache_control_mut) {
+                    if placer.should_place() {
+                        *cache_control = Some(ephemeral_cache_control());
+                    }
+                }
+            }
+        }
+    }
+
+    let last_ix = request.messages.len().saturating_sub(1);
+    for (ix, message) in request.messages.iter_mut().enumerate() {
+        for content in &message.content {
+            placer.bytes += json_len(content);
+            if has_cache_breakpoint(content) {
+                placer.last_breakpoint_bytes = placer.bytes;
+            }
+        }
+        if ix != 0 && ix != last_ix {
+            continue;
+        }
+        if let Some(cache_control) = message.content.iter_mut().rev().find_map(cache_control_mut) {
+            if cache_control.is_none() && placer.should_place() {
+                *cache_control = Some(ephemeral_cache_control());
+            }
+        }
+    }
+}
+
+struct BreakpointPlacer {
+    remaining: usize,
+    min_tokens: u64,
+    /// The size of the request up to the current position when serialized, to estimate tokens.
+    bytes: usize,
+    last_breakpoint_bytes: usize,
+}
+
+impl BreakpointPlacer {
+    /// Whether to place a breakpoint at the current position, recording it if so.
+    fn should_place(&mut self) -> bool {
+        let tokens = estimate_tokens(self.bytes - self.last_breakpoint_bytes);
+        if self.remaining == 0 || tokens < self.min_tokens {
+            return false;
+        }
+        self.remaining -= 1;
+        self.last_breakpoint_bytes = self.bytes;
+        true
+    }
+}
+
+fn has_cache_breakpoint(content: &RequestContent) -> bool {
+    match content {
+        RequestContent::Text { cache_control, .. }
+        | RequestContent::Image { cache_control, .. }
+        | RequestContent::ToolUse { cache_control, .. }
+        | RequestContent::ToolResult { cache_control, .. }
+        | RequestContent::Document { cache_control, .. } => cache_control.is_some(),
+        RequestContent::Thinking { .. } | RequestContent::RedactedThinking { .. } => false,
+    }
+}
+
+fn json_len(value: &impl Serialize) -> usize {
+    serde_json::to_string(value).map_or(0, |json| json.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use anthropic::{Message, Role, Tool};
+
+    use super::*;
+
+    fn text(text: &str, cached: bool) -> RequestContent {
+        RequestContent::Text {
+            text: text.into(),
+            cache_control: cached.then(ephemeral_cache_control),
+        }
+    }
+
+    fn cached_texts(request: &Request) -> Vec<&str> {
+        request
+            .messages
+            .iter()
+            .flat_map(|message| &message.content)
+            .filter_map(|content| match content {
+                RequestContent::Text {
+                    text,
+                    cache_control: Some(_),
+                } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_place_cache_breakpoints() {
+        let long = "a".repeat(8192);
+        let mut request = Request {
+            model: "claude-sonnet-4-latest".into(),
+            max_tokens: 1024,
+            messages: vec![
+                Message {
+                    role: Role::User,
+                    content: vec![text(&long, false)],
+                },
+                Message {
+                    role: Role::Assistant,
+                    content: vec![text("short", false)],
+                },
+                Message {
+                    role: Role::User,
+                    content: vec![text(&long, false)],
+                },
+            ],
+            tools: vec![Tool {
+                name: "read_file".into(),
+                description: "Reads a file.".into(),
+                input_schema: serde_json::json!({"type": "object"}),
+                cache_control: None,
+            }],
+            thinking: None,
+            tool_choice: None,
+            system: Some(StringOrContents::String(long.clone())),
+            metadata: None,
+            stop_sequences: Vec::new(),
+            temperature: None,
+            top_k: None,
+            top_p: None,
+        };
+        place_cache_breakpoints(&mut request, 1024);
+
+        // The tools are too short to cache on their own.
+        assert!(request.tools[0].cache_control.is_none());
+        assert!(matches!(
+            &request.system,
+            Some(StringOrContents::Content(contents)) if has_cache_breakpoint(&contents[0])
+        ));
+        assert_eq!(cached_texts(&request), [long.as_str(), long.as_str()]);
+
+        // Breakpoints that are already in the request count towards the limit.
+        for message in &mut request.messages {
+            message.content = vec![text(&long, true)];
+        }
+        request.messages.push(Message {
+            role: Role::Assistant,
+            content: vec![text(&long, false)],
+        });
+        place_cache_breakpoints(&mut request, 1024);
+        assert_eq!(cached_texts(&request).len(), 3);
+    }
+}
@@ -0,0 +1,600 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use collections::HashMap;
+use futures::{FutureExt, future::BoxFuture, stream::BoxStream};
+use gpui::{AnyView, App, AsyncApp, Context, Entity, Subscription, Task};
+use language_model::{
+    AuthenticateError, DocumentLimits, ImageLimits, LanguageModel, LanguageModelCacheConfiguration,
+    LanguageModelCompletionError, LanguageModelCompletionEvent, LanguageModelId, LanguageModelName,
+    LanguageModelProvider, LanguageModelProviderId, LanguageModelProviderName,
+    LanguageModelProviderState, LanguageModelRegistry, LanguageModelRequest,
+    LanguageModelToolChoice, LanguageModelToolSchemaFormat, ModelPrice, SelectedModel,
+    StructuredOutput,
+};
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsStore};
+use ui::{List, prelude::*};
+
+use crate::AllLanguageModelSettings;
+use crate::ui::InstructionListItem;
+
+pub(crate) const PROVIDER_ID: &str = "balanced";
+const PROVIDER_NAME: &str = "Balanced";
+
+/// How long a backend is skipped after a request to it fails, unless every backend is failing.
+const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(60);
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct BalancedSettings {
+    pub available_models: Vec<AvailableModel>,
+}
+
+/// A model whose requests are spread across several backends that serve the same model, such as
+/// an open-weight model hosted by more than one provider.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AvailableModel {
+    /// The name that the model is selected by, like `llama-3.3-70b`.
+    pub name: String,
+    /// The name to show in the model picker.
+    pub display_name: Option<String>,
+    /// The context window that all of the backends support.
+    pub max_tokens: usize,
+    pub max_output_tokens: Option<u32>,
+    #[serde(default)]
+    pub supports_tools: bool,
+    #[serde(default)]
+    pub supports_images: bool,
+    #[serde(default)]
+    pub supports_thinking: bool,
+    #[serde(default)]
+    pub supports_parallel_tool_calls: bool,
+    /// The models to send requests to.
+    pub backends: Vec<Backend>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Backend {
+    /// The model to send requests to, as `provider/model`, like `openai/llama-3.3-70b`.
+    pub model: String,
+    /// The share of requests that this backend gets, relative to the other backends.
+    ///
+    /// Default: 1
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Default)]
+struct BackendState {
+    /// The backend's credit in the smooth weighted round-robin, which grows by its weight on
+    /// every pick and shrinks by the total weight when it's picked.
+    current_weight: i64,
+    unhealthy_until: Option<Instant>,
+}
+
+/// Picks the backends of balanced models in proportion to their weights, skipping backends that
+/// recently failed.
+#[derive(Default)]
+struct Balancer {
+    /// The state of each backend, by balanced model name and backend model.
+    backends: HashMap<(String, String), BackendState>,
+}
+
+impl Balancer {
+    /// Picks the next backend of `model` to send a request to, leaving out the backends at
+    /// `tried` indices.
+    fn pick(&mut self, model: &AvailableModel, tried: &[usize], now: Instant) -> Option<usize> {
+        let candidates = model
+            .backends
+            .iter()
+            .enumerate()
+            .filter(|(ix, backend)| !tried.contains(ix) && backend.weight > 0)
+            .collect::<Vec<_>>();
+        let is_healthy = |backend: &Backend, backends: &HashMap<(String, String), BackendState>| {
+            backends
+                .get(&(model.name.clone(), backend.model.clone()))
+                .and_then(|state| state.unhealthy_until)
+                .is_none_or(|until| until <= now)
+        };
+        let healthy = candidates
+            .iter()
+            .copied()
+            .filter(|(_, backend)| is_healthy(backend, &self.backends))
+            .collect::<Vec<_>>();
+        let pool = if healthy.is_empty() {
+            candidates
+        } else {
+            healthy
+        };
+
+        let total_weight = pool
+            .iter()
+            .map(|(_, backend)| backend.weight as i64)
+            .sum::<i64>();
+        let mut picked: Option<(usize, i64)> = None;
+        for (ix, backend) in pool {
+            let state = self
+                .backends
+                .entry((model.name.clone(), backend.model.clone()))
+                .or_default();
+            state.current_weight += backend.weight as i64;
+            if picked.is_none_or(|(_, weight)| state.current_weight > weight) {
+                picked = Some((ix, state.current_weight));
+            }
+        }
+
+        let (ix, _) = picked?;
+        let backend = &model.backends[ix];
+        if let Some(state) = self
+            .backends
+            .get_mut(&(model.name.clone(), backend.model.clone()))
+        {
+            state.current_weight -= total_weight;
+        }
+        Some(ix)
+    }
+
+    fn mark_unhealthy(&mut self, model: &str, backend: &str, now: Instant) {
+        self.backends
+            .entry((model.to_string(), backend.to_string()))
+            .or_default()
+            .unhealthy_until = Some(now + UNHEALTHY_COOLDOWN);
+    }
+
+    fn mark_healthy(&mut self, model: &str, backend: &str) {
+        if let Some(state) = self
+            .backends
+            .get_mut(&(model.to_string(), backend.to_string()))
+        {
+            state.unhealthy_until = None;
+        }
+    }
+
+    fn is_unhealthy(&self, model: &str, backend: &str, now: Instant) -> bool {
+        self.backends
+            .get(&(model.to_string(), backend.to_string()))
+            .and_then(|state| state.unhealthy_until)
+            .is_some_and(|until| until > now)
+    }
+}
+
+/// The model that a backend sends requests to, if its provider is enabled and offers it.
+fn resolve_backend(backend: &Backend, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+    let selected_model = backend.model.parse::<SelectedModel>().ok()?;
+    if selected_model.provider.0 == PROVIDER_ID {
+        return None;
+    }
+    LanguageModelRegistry::read_global(cx)
+        .provider(&selected_model.provider)?
+        .provided_models(cx)
+        .into_iter()
+        .find(|model| model.id() == selected_model.model)
+}
+
+pub struct BalancedLanguageModelProvider {
+    state: Entity<State>,
+    balancer: Arc<Mutex<Balancer>>,
+}
+
+pub struct State {
+    _subscription: Subscription,
+}
+
+impl BalancedLanguageModelProvider {
+    pub fn new(cx: &mut App) -> Self {
+        let state = cx.new(|cx| State {
+            _subscription: cx.observe_global::<SettingsStore>({
+                let mut settings = AllLanguageModelSettings::get_global(cx).balanced.clone();
+                move |_, cx| {
+                    let new_settings = &AllLanguageModelSettings::get_global(cx).balanced;
+                    if &settings != new_settings {
+                        settings = new_settings.clone();
+                        cx.notify();
+                    }
+                }
+            }),
+        });
+        Self {
+            state,
+            balancer: Arc::default(),
+        }
+    }
+}
+
+impl LanguageModelProviderState for BalancedLanguageModelProvider {
+    type ObservableEntity = State;
+
+    fn observable_entity(&self) -> Option<Entity<Self::ObservableEntity>> {
+        Some(self.state.clone())
+    }
+}
+
+impl LanguageModelProvider for BalancedLanguageModelProvider {
+    fn id(&self) -> LanguageModelProviderId {
+        LanguageModelProviderId(PROVIDER_ID.into())
+    }
+
+    fn name(&self) -> LanguageModelProviderName {
+        LanguageModelProviderName(PROVIDER_NAME.into())
+    }
+
+    fn default_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        None
+    }
+
+    fn default_fast_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        None
+    }
+
+    fn provided_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
+        AllLanguageModelSettings::get_global(cx)
+            .balanced
+            .available_models
+            .iter()
+            .map(|model| {
+                Arc::new(BalancedLanguageModel {
+                    model: model.clone(),
+                    representative: model
+                        .backends
+                        .iter()
+                        .find_map(|backend| resolve_backend(backend, cx)),
+                    balancer: self.balancer.clone(),
+                }) as Arc<dyn LanguageModel>
+            })
+            .collect()
+    }
+
+    fn is_authenticated(&self, cx: &App) -> bool {
+        !AllLanguageModelSettings::get_global(cx)
+            .balanced
+            .available_models
+            .is_empty()
+    }
+
+    fn authenticate(&self, _cx: &mut App) -> Task<Result<(), AuthenticateError>> {
+        Task::ready(Ok(()))
+    }
+
+    fn configuration_view(&self, _window: &mut Window, cx: &mut App) -> AnyView {
+        let state = self.state.clone();
+        let balancer = self.balancer.clone();
+        cx.new(|cx| ConfigurationView::new(state, balancer, cx))
+            .into()
+    }
+
+    fn reset_credentials(&self, _cx: &mut App) -> Task<Result<()>> {
+        Task::ready(Ok(()))
+    }
+}
+
+pub struct BalancedLanguageModel {
+    model: AvailableModel,
+    /// The first available backend. Since the backends serve the same model, the capabilities
+    /// that aren't configured in the settings are taken from it.
+    representative: Option<Arc<dyn LanguageModel>>,
+    balancer: Arc<Mutex<Balancer>>,
+}
+
+impl LanguageModel for BalancedLanguageModel {
+    fn id(&self) -> LanguageModelId {
+        LanguageModelId::from(self.model.name.clone())
+    }
+
+    fn name(&self) -> LanguageModelName {
+        LanguageModelName::from(
+            self.model
+                .display_name
+                .clone()
+                .unwrap_or_else(|| self.model.name.clone()),
+        )
+    }
+
+    fn provider_id(&self) -> LanguageModelProviderId {
+        LanguageModelProviderId(PROVIDER_ID.into())
+    }
+
+    fn provider_name(&self) -> LanguageModelProviderName {
+        LanguageModelProviderName(PROVIDER_NAME.into())
+    }
+
+    fn telemetry_id(&self) -> String {
+        format!("balanced/{}", self.model.name)
+    }
+
+    fn price(&self, cx: &App) -> Option<ModelPrice> {
+        self.representative.as_ref()?.price(cx)
+    }
+
+    fn supports_images(&self) -> bool {
+        self.model.supports_images
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        self.representative
+            .as_ref()
+            .map_or_else(ImageLimits::default, |model| model.image_limits())
+    }
+
+    fn document_limits(&self) -> Option<DocumentLimits> {
+        self.representative.as_ref()?.document_limits()
+    }
+
+    fn supports_audio(&self) -> bool {
+        self.representative
+            .as_ref()
+            .is_some_and(|model| model.supports_audio())
+    }
+
+    fn supports_video(&self) -> bool {
+        self.representative
+            .as_ref()
+            .is_some_and(|model| model.supports_video())
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.model.supports_tools
+    }
+
+    fn supports_tool_choice(&self, choice: LanguageModelToolChoice) -> bool {
+        match choice {
+            LanguageModelToolChoice::Auto
+            | LanguageModelToolChoice::Any
+            | LanguageModelToolChoice::None
+            | LanguageModelToolChoice::Tool(_) => self.model.supports_tools,
+        }
+    }
+
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.model.supports_parallel_tool_calls
+    }
+
+    fn supports_thinking(&self) -> bool {
+        self.model.supports_thinking
+    }
+
+    fn supports_max_mode(&self) -> bool {
+        self.representative
+            .as_ref()
+            .is_some_and(|model| model.supports_max_mode())
+    }
+
+    fn supports_assistant_prefill(&self) -> bool {
+        self.representative
+            .as_ref()
+            .is_some_and(|model| model.supports_assistant_prefill())
+    }
+
+    fn supports_candidates(&self) -> bool {
+        self.representative
+            .as_ref()
+            .is_some_and(|model| model.supports_candidates())
+    }
+
+    fn supports_logprobs(&self) -> bool {
+        self.representative
+            .as_ref()
+            .is_some_and(|model| model.supports_logprobs())
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        self.representative
+            .as_ref()
+            .map_or(StructuredOutput::Unsupported, |model| {
+                model.structured_output()
+            })
+    }
+
+    fn tool_input_format(&self) -> LanguageModelToolSchemaFormat {
+        self.representative
+            .as_ref()
+            .map_or(LanguageModelToolSchemaFormat::JsonSchema, |model| {
+                model.tool_input_format()
+            })
+    }
+
+    fn max_token_count(&self) -> usize {
+        self.model.max_tokens
+    }
+
+    fn max_output_tokens(&self) -> Option<u32> {
+        self.model.max_output_tokens
+    }
+
+    fn count_tokens(
+        &self,
+        request: LanguageModelRequest,
+        cx: &App,
+    ) -> BoxFuture<'static, Result<usize>> {
+        match self
+            .model
+            .backends
+            .iter()
+            .find_map(|backend| resolve_backend(backend, cx))
+        {
+            Some(backend) => backend.count_tokens(request, cx),
+            None => futures::future::ready(Err(anyhow!(
+                "none of the backends of {} are available",
+                self.model.name
+            )))
+            .boxed(),
+        }
+    }
+
+    fn stream_completion(
+        &self,
+        request: LanguageModelRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<
+        'static,
+        Result<
+            BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+        >,
+    > {
+        let model = self.model.clone();
+        let balancer = self.balancer.clone();
+        // The backends are called from the foreground, since `AsyncApp` can't be sent to another
+        // thread.
+        cx.spawn(async move |cx| {
+            let mut tried = Vec::new();
+            let mut last_error = None;
+            while let Some(ix) = balancer.lock().pick(&model, &tried, Instant::now()) {
+                tried.push(ix);
+                let backend = &model.backends[ix];
+                let result = match cx.update(|cx| resolve_backend(backend, cx))? {
+                    Some(backend_model) => {
+                        backend_model.stream_completion(request.clone(), cx).await
+                    }
+                    None => Err(anyhow!("{} isn't available", backend.model)),
+                };
+                match result {
+                    Ok(events) => {
+                        balancer.lock().mark_healthy(&model.name, &backend.model);
+                        return Ok(events);
+                    }
+                    Err(error) => {
+                        log::warn!(
+                            "request to {} for balanced model {} failed: {error:#}",
+                            backend.model,
+                            model.name
+                        );
+                        balancer
+                            .lock()
+                            .mark_unhealthy(&model.name, &backend.model, Instant::now());
+                        last_error = Some(error);
+                    }
+                }
+            }
+            Err(last_error.unwrap_or_else(|| anyhow!("{} has no backends", model.name)))
+        })
+        .boxed()
+    }
+
+    fn cache_configuration(&self) -> Option<LanguageModelCacheConfiguration> {
+        self.representative.as_ref()?.cache_configuration()
+    }
+}
+
+struct ConfigurationView {
+    balancer: Arc<Mutex<Balancer>>,
+    _subscription: Subscription,
+}
+
+impl ConfigurationView {
+    fn new(state: Entity<State>, balancer: Arc<Mutex<Balancer>>, cx: &mut Context<Self>) -> Self {
+        Self {
+            balancer,
+            _subscription: cx.observe(&state, |_, _, cx| cx.notify()),
+        }
+    }
+}
+
+impl Render for ConfigurationView {
+    fn render(&mut self, _: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let models = AllLanguageModelSettings::get_global(cx)
+            .balanced
+            .available_models
+            .clone();
+        let balancer = self.balancer.lock();
+        let now = Instant::now();
+
+        v_flex()
+            .gap_2()
+            .child(Label::new(
+                "Spread requests for a model across several providers that host it.",
+            ))
+            .child(
+                List::new()
+                    .child(InstructionListItem::text_only(
+                        "Add models with their backends to `language_models.balanced.available_models` in your settings.",
+                    ))
+                    .child(InstructionListItem::text_only(
+                        "Backends that fail are skipped for a minute, unless every backend is failing.",
+                    )),
+            )
+            .children(models.into_iter().map(|model| {
+                v_flex()
+                    .gap_1()
+                    .child(Label::new(
+                        model.display_name.clone().unwrap_or(model.name.clone()),
+                    ))
+                    .children(model.backends.iter().map(|backend| {
+                        let is_unhealthy = balancer.is_unhealthy(&model.name, &backend.model, now);
+                        h_flex()
+                            .gap_2()
+                            .pl_2()
+                            .child(Label::new(backend.model.clone()).size(LabelSize::Small))
+                            .child(
+                                Label::new(format!("weight {}", backend.weight))
+                                    .size(LabelSize::Small)
+                                    .color(Color::Muted),
+                            )
+                            .when(is_unhealthy, |this| {
+                                this.child(
+                                    Label::new("failing")
+                                        .size(LabelSize::Small)
+                                        .color(Color::Error),
+                                )
+                            })
+                    }))
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_balancer() {
+        let model = AvailableModel {
+            name: "llama-3.3-70b".into(),
+            display_name: None,
+            max_tokens: 128_000,
+            max_output_tokens: None,
+            supports_tools: true,
+            supports_images: false,
+            supports_thinking: false,
+            supports_parallel_tool_calls: false,
+            backends: vec![
+                Backend {
+                    model: "openai/llama-3.3-70b".into(),
+                    weight: 2,
+                },
+                Backend {
+                    model: "ollama/llama3.3:70b".into(),
+                    weight: 1,
+                },
+            ],
+        };
+        let mut balancer = Balancer::default();
+        let now = Instant::now();
+
+        let picks = (0..6)
+            .map(|_| balancer.pick(&model, &[], now))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            picks,
+            [Some(0), Some(1), Some(0), Some(0), Some(1), Some(0)]
+        );
+
+        assert_eq!(balancer.pick(&model, &[0], now), Some(1));
+        assert_eq!(balancer.pick(&model, &[0, 1], now), None);
+
+        balancer.mark_unhealthy(&model.name, "openai/llama-3.3-70b", now);
+        assert_eq!(balancer.pick(&model, &[], now), Some(1));
+        assert_eq!(balancer.pick(&model, &[], now), Some(1));
+        assert_eq!(balancer.pick(&model, &[1], now), Some(0));
+        assert_eq!(balancer.pick(&model, &[], now), Some(1));
+        assert_eq!(
+            balancer.pick(&model, &[], now + UNHEALTHY_COOLDOWN),
+            Some(0)
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::str::FromStr;
 use std::sync::Arc;
@@ -11,19 +12,21 @@ use aws_http_client::AwsHttpClient;
 use bedrock::bedrock_client::Client as BedrockClient;
 use bedrock::bedrock_client::config::timeout::TimeoutConfig;
 use bedrock::bedrock_client::types::{
-    ContentBlockDelta, ContentBlockStart, ConverseStreamOutput, ReasoningContentBlockDelta,
-    StopReason,
+    ContentBlockDelta, ContentBlockStart, ConverseStreamOutput, GuardrailAssessment,
+    GuardrailTraceAssessment, ReasoningContentBlockDelta, StopReason,
 };
 use bedrock::{
     BedrockAnyToolChoice, BedrockAutoToolChoice, BedrockBlob, BedrockError, BedrockInnerContent,
-    BedrockMessage, BedrockModelMode, BedrockStreamingResponse, BedrockThinkingBlock,
-    BedrockThinkingTextBlock, BedrockTool, BedrockToolChoice, BedrockToolConfig,
-    BedrockToolInputSchema, BedrockToolResultBlock, BedrockToolResultContentBlock,
-    BedrockToolResultStatus, BedrockToolSpec, BedrockToolUseBlock, Model, value_to_aws_document,
+    BedrockMessage, BedrockModelMode, BedrockSpecificToolChoice, BedrockStreamingResponse,
+    BedrockThinkingBlock, BedrockThinkingTextBlock, BedrockTool, BedrockToolChoice,
+    BedrockToolConfig, BedrockToolInputSchema, BedrockToolResultBlock,
+    BedrockToolResultContentBlock, BedrockToolResultStatus, BedrockToolSpec, BedrockToolUseBlock,
+    Model, value_to_aws_document,
 };
 use collections::{BTreeMap, HashMap};
 use credentials_provider::CredentialsProvider;
 use editor::{Editor, EditorElement, EditorStyle};
+use fs::Fs;
 use futures::{FutureExt, Stream, StreamExt, future::BoxFuture, stream::BoxStream};
 use gpui::{
     AnyView, App, AsyncApp, Context, Entity, FontStyle, FontWeight, Subscription, Task, TextStyle,
@@ -32,27 +35,32 @@ use gpui::{
 use gpui_tokio::Tokio;
 use http_client::HttpClient;
 use language_model::{
-    AuthenticateError, LanguageModel, LanguageModelCacheConfiguration,
-    LanguageModelCompletionError, LanguageModelCompletionEvent, LanguageModelId, LanguageModelName,
-    LanguageModelProvider, LanguageModelProviderId, LanguageModelProviderName,
-    LanguageModelProviderState, LanguageModelRequest, LanguageModelToolChoice,
-    LanguageModelToolResultContent, LanguageModelToolUse, MessageContent, RateLimiter, Role,
-    TokenUsage,
+    AuthenticateError, GuardrailAction, GuardrailPolicy, GuardrailViolation, LanguageModel,
+    LanguageModelCacheConfiguration, LanguageModelCompletionError, LanguageModelCompletionEvent,
+    LanguageModelId, LanguageModelName, LanguageModelProvider, LanguageModelProviderId,
+    LanguageModelProviderName, LanguageModelProviderState, LanguageModelRequest,
+    LanguageModelToolChoice, LanguageModelToolResultContent, LanguageModelToolUse, MessageContent,
+    RateLimiter, Role, TokenUsage,
 };
+use parking_lot::Mutex;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use settings::{Settings, SettingsStore};
-use smol::lock::OnceCell;
+use settings::{Settings, SettingsStore, update_settings_file};
 use strum::{EnumIter, IntoEnumIterator, IntoStaticStr};
 use theme::ThemeSettings;
 use tokio::runtime::Handle;
-use ui::{Icon, IconName, List, Tooltip, prelude::*};
-use util::{ResultExt, default};
+use ui::{ContextMenu, DropdownMenu, Icon, IconName, List, Tooltip, prelude::*};
+use util::{ResultExt, default, defer};
 
-use crate::AllLanguageModelSettings;
+use crate::live_usage::estimate_live_usage;
+use crate::normalize_messages::{MessageConstraints, normalize_messages};
+use crate::partial_tool_use::partial_tool_use;
+use crate::reasoning::normalize_reasoning;
+use crate::request_log::RequestLogRecorder;
+use crate::{AllLanguageModelSettings, SamplingParameters, SystemPromptOverrides};
 
-const PROVIDER_ID: &str = "amazon-bedrock";
+pub(crate) const PROVIDER_ID: &str = "amazon-bedrock";
 const PROVIDER_NAME: &str = "Amazon Bedrock";
 
 #[derive(Default, Clone, Deserialize, Serialize, PartialEq, Debug)]
@@ -71,6 +79,19 @@ pub struct AmazonBedrockSettings {
     pub profile_name: Option<String>,
     pub role_arn: Option<String>,
     pub authentication_method: Option<BedrockAuthMethod>,
+    pub system_prompt: SystemPromptOverrides,
+    pub guardrail: Option<BedrockGuardrail>,
+}
+
+/// A Bedrock guardrail to apply to every request, and to the responses to them.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct BedrockGuardrail {
+    /// The guardrail's ID or ARN.
+    pub id: String,
+    /// The version of the guardrail to use.
+    ///
+    /// Default: DRAFT
+    pub version: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, EnumIter, IntoStaticStr, JsonSchema)]
@@ -93,6 +114,24 @@ pub struct AvailableModel {
     pub max_output_tokens: Option<u32>,
     pub default_temperature: Option<f32>,
     pub mode: Option<ModelMode>,
+    /// Whether the model can call tools.
+    ///
+    /// Default: true for Bedrock's Claude, Nova, Jamba, and Command R models, false otherwise
+    #[serde(default)]
+    pub supports_tools: Option<bool>,
+    /// Whether the model reasons before it responds. When false, the thinking `mode` is ignored.
+    ///
+    /// Default: whether `mode` is `thinking`
+    #[serde(default)]
+    pub supports_thinking: Option<bool>,
+    /// Whether the model can call several tools in one response.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub supports_parallel_tool_calls: Option<bool>,
+    /// Sampling parameters for requests to this model.
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -139,6 +178,137 @@ const ZED_BEDROCK_REGION_VAR: &str = "ZED_AWS_REGION";
 const ZED_AWS_CREDENTIALS_VAR: &str = "ZED_AWS_CREDENTIALS";
 const ZED_AWS_ENDPOINT_VAR: &str = "ZED_AWS_ENDPOINT";
 
+/// A profile declared in the shared AWS config or credentials files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AwsProfile {
+    pub name: String,
+    pub uses_sso: bool,
+}
+
+/// Reads the profile names from the shared AWS config and credentials files,
+/// honoring `AWS_CONFIG_FILE` and `AWS_SHARED_CREDENTIALS_FILE`.
+pub async fn load_aws_profiles() -> Vec<AwsProfile> {
+    let aws_dir = paths::home_dir().join(".aws");
+    let config_path = std::env::var_os("AWS_CONFIG_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| aws_dir.join("config"));
+    let credentials_path = std::env::var_os("AWS_SHARED_CREDENTIALS_FILE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| aws_dir.join("credentials"));
+
+    let config = smol::fs::read_to_string(&config_path)
+        .await
+        .unwrap_or_default();
+    let credentials = smol::fs::read_to_string(&credentials_path)
+        .await
+        .unwrap_or_default();
+    parse_aws_profiles(&config, &credentials)
+}
+
+fn parse_aws_profiles(config: &str, credentials: &str) -> Vec<AwsProfile> {
+    let mut profiles: Vec<AwsProfile> = Vec::new();
+
+    for (contents, is_config_file) in [(config, true), (credentials, false)] {
+        let mut current: Option<usize> = None;
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if let Some(section) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                let section = section.trim();
+                // In the config file, every profile other than `default` is
+                // prefixed, and other sections (e.g. `sso-session`) aren't profiles.
+                let name = if !is_config_file || section == "default" {
+                    Some(section)
+                } else {
+                    section.strip_prefix("profile ").map(str::trim)
+                };
+                current = name.filter(|name| !name.is_empty()).map(|name| {
+                    profiles
+                        .iter()
+                        .position(|profile| profile.name == name)
+                        .unwrap_or_else(|| {
+                            profiles.push(AwsProfile {
+                                name: name.to_string(),
+                                uses_sso: false,
+                            });
+                            profiles.len() - 1
+                        })
+                });
+                continue;
+            }
+
+            if let Some(index) = current {
+                let key = line.split('=').next().unwrap_or_default().trim();
+                if key == "sso_session" || key == "sso_start_url" {
+                    profiles[index].uses_sso = true;
+                }
+            }
+        }
+    }
+
+    profiles
+}
+
+/// Everything needed to construct a Bedrock client. The client is rebuilt
+/// whenever this changes, e.g. after a different profile is selected.
+#[derive(Clone, Debug, PartialEq)]
+struct BedrockClientConfig {
+    auth_method: Option<BedrockAuthMethod>,
+    credentials: Option<BedrockCredentials>,
+    profile_name: Option<String>,
+    endpoint: Option<String>,
+    region: String,
+}
+
+impl BedrockClientConfig {
+    fn load(self, http_client: AwsHttpClient, handle: &Handle) -> BedrockClient {
+        let mut config_builder = aws_config::defaults(BehaviorVersion::latest())
+            .stalled_stream_protection(StalledStreamProtectionConfig::disabled())
+            .http_client(http_client)
+            .region(Region::new(self.region))
+            .timeout_config(TimeoutConfig::disabled());
+
+        if let Some(endpoint_url) = self.endpoint {
+            if !endpoint_url.is_empty() {
+                config_builder = config_builder.endpoint_url(endpoint_url);
+            }
+        }
+
+        match self.auth_method {
+            None => {
+                if let Some(creds) = self.credentials {
+                    let aws_creds = Credentials::new(
+                        creds.access_key_id,
+                        creds.secret_access_key,
+                        creds.session_token,
+                        None,
+                        "zed-bedrock-provider",
+                    );
+                    config_builder = config_builder.credentials_provider(aws_creds);
+                }
+            }
+            Some(BedrockAuthMethod::NamedProfile) | Some(BedrockAuthMethod::SingleSignOn) => {
+                // The profile's own configuration decides whether static keys, SSO,
+                // `credential_process` or an assumed role are used.
+                let profile_name = self.profile_name.unwrap_or_else(|| "default".to_string());
+
+                if !profile_name.is_empty() {
+                    config_builder = config_builder.profile_name(profile_name);
+                }
+            }
+            Some(BedrockAuthMethod::Automatic) => {
+                // Use default credential provider chain
+            }
+        }
+
+        let config = handle.block_on(config_builder.load());
+        BedrockClient::new(&config)
+    }
+}
+
 pub struct State {
     credentials: Option<BedrockCredentials>,
     settings: Option<AmazonBedrockSettings>,
@@ -157,7 +327,6 @@ impl State {
             this.update(cx, |this, cx| {
                 this.credentials = None;
                 this.credentials_from_env = false;
-                this.settings = None;
                 cx.notify();
             })
         })
@@ -185,14 +354,53 @@ impl State {
         })
     }
 
-    fn is_authenticated(&self) -> bool {
-        let derived = self
+    fn profile_name(&self) -> Option<String> {
+        self.settings
+            .as_ref()
+            .and_then(|s| s.profile_name.clone())
+            .filter(|profile| !profile.is_empty())
+            .or_else(|| {
+                std::env::var(ZED_AWS_PROFILE_VAR)
+                    .ok()
+                    .filter(|profile| !profile.is_empty())
+            })
+    }
+
+    /// The authentication method from the settings, falling back to the named
+    /// profile from `ZED_AWS_PROFILE` when no static credentials were stored.
+    fn auth_method(&self) -> Option<BedrockAuthMethod> {
+        self.settings
+            .as_ref()
+            .and_then(|s| s.authentication_method.clone())
+            .or_else(|| {
+                (self.credentials.is_none() && std::env::var(ZED_AWS_PROFILE_VAR).is_ok())
+                    .then_some(BedrockAuthMethod::NamedProfile)
+            })
+    }
+
+    fn client_config(&self) -> BedrockClientConfig {
+        let endpoint = self
+            .settings
+            .as_ref()
+            .and_then(|s| s.endpoint.clone())
+            .or_else(|| std::env::var(ZED_AWS_ENDPOINT_VAR).ok());
+        let region = self
             .settings
             .as_ref()
-            .and_then(|s| s.authentication_method.as_ref());
-        let creds = self.credentials.as_ref();
+            .and_then(|s| s.region.clone())
+            .unwrap_or(String::from("us-east-1"));
+
+        BedrockClientConfig {
+            auth_method: self.auth_method(),
+            credentials: self.credentials.clone(),
+            profile_name: self.profile_name(),
+            endpoint,
+            region,
+        }
+    }
 
-        derived.is_some() || creds.is_some()
+    fn is_authenticated(&self) -> bool {
+        self.auth_method().is_some() || self.credentials.is_some()
     }
 
     fn authenticate(&self, cx: &mut Context<Self>) -> Task<Result<(), AuthenticateError>> {
@@ -243,7 +451,11 @@ impl BedrockLanguageModelProvider {
             credentials: None,
             settings: Some(AllLanguageModelSettings::get_global(cx).bedrock.clone()),
             credentials_from_env: false,
-            _subscription: cx.observe_global::<SettingsStore>(|_, cx| {
+            _subscription: cx.observe_global::<SettingsStore>(|this: &mut State, cx| {
+                let settings = AllLanguageModelSettings::get_global(cx).bedrock.clone();
+                if this.settings.as_ref() != Some(&settings) {
+                    this.settings = Some(settings);
+                }
                 cx.notify();
             }),
         });
@@ -259,15 +471,40 @@ impl BedrockLanguageModelProvider {
         }
     }
 
-    fn create_language_model(&self, model: bedrock::Model) -> Arc<dyn LanguageModel> {
+    fn create_language_model(&self, model: bedrock::Model, cx: &App) -> Arc<dyn LanguageModel> {
+        let available_model = AllLanguageModelSettings::get_global(cx)
+            .bedrock
+            .available_models
+            .iter()
+            .find(|available_model| available_model.name == model.id());
+        let supports_tools = available_model
+            .and_then(|available_model| available_model.supports_tools)
+            .unwrap_or_else(|| model.supports_tool_use());
+        let mode = match available_model {
+            Some(available_model) if available_model.supports_thinking == Some(false) => {
+                BedrockModelMode::Default
+            }
+            Some(AvailableModel {
+                mode: Some(mode), ..
+            }) => mode.clone().into(),
+            _ => model.mode(),
+        };
+        let supports_parallel_tool_calls = available_model
+            .and_then(|available_model| available_model.supports_parallel_tool_calls)
+            .unwrap_or(false);
         Arc::new(BedrockModel {
             id: LanguageModelId::from(model.id().to_string()),
             model,
+            mode,
+            supports_tools,
+            supports_parallel_tool_calls,
             http_client: self.http_client.clone(),
             handler: self.handler.clone(),
             state: self.state.clone(),
-            client: OnceCell::new(),
-            request_limiter: RateLimiter::new(4),
+            client: Mutex::new(None),
+            request_limiter: RateLimiter::new(
+                AllLanguageModelSettings::get_global(cx).max_concurrent_requests(PROVIDER_ID),
+            ),
         })
     }
 }
@@ -285,12 +522,12 @@ impl LanguageModelProvider for BedrockLanguageModelProvider {
         IconName::AiBedrock
     }
 
-    fn default_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(bedrock::Model::default()))
+    fn default_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(bedrock::Model::default(), cx))
     }
 
-    fn default_fast_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(bedrock::Model::default_fast()))
+    fn default_fast_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(bedrock::Model::default_fast(), cx))
     }
 
     fn provided_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
@@ -323,7 +560,7 @@ impl LanguageModelProvider for BedrockLanguageModelProvider {
 
         models
             .into_values()
-            .map(|model| self.create_language_model(model))
+            .map(|model| self.create_language_model(model, cx))
             .collect()
     }
 
@@ -357,89 +594,30 @@ impl LanguageModelProviderState for BedrockLanguageModelProvider {
 struct BedrockModel {
     id: LanguageModelId,
     model: Model,
+    mode: BedrockModelMode,
+    supports_tools: bool,
+    supports_parallel_tool_calls: bool,
     http_client: AwsHttpClient,
     handler: tokio::runtime::Handle,
-    client: OnceCell<BedrockClient>,
+    client: Mutex<Option<(BedrockClientConfig, BedrockClient)>>,
     state: gpui::Entity<State>,
     request_limiter: RateLimiter,
 }
 
 impl BedrockModel {
-    fn get_or_init_client(&self, cx: &AsyncApp) -> anyhow::Result<&BedrockClient> {
-        self.client
-            .get_or_try_init_blocking(|| {
-                let (auth_method, credentials, endpoint, region, settings) =
-                    cx.read_entity(&self.state, |state, _cx| {
-                        let auth_method = state
-                            .settings
-                            .as_ref()
-                            .and_then(|s| s.authentication_method.clone());
-
-                        let endpoint = state.settings.as_ref().and_then(|s| s.endpoint.clone());
-
-                        let region = state
-                            .settings
-                            .as_ref()
-                            .and_then(|s| s.region.clone())
-                            .unwrap_or(String::from("us-east-1"));
-
-                        (
-                            auth_method,
-                            state.credentials.clone(),
-                            endpoint,
-                            region,
-                            state.settings.clone(),
-                        )
-                    })?;
-
-                let mut config_builder = aws_config::defaults(BehaviorVersion::latest())
-                    .stalled_stream_protection(StalledStreamProtectionConfig::disabled())
-                    .http_client(self.http_client.clone())
-                    .region(Region::new(region))
-                    .timeout_config(TimeoutConfig::disabled());
-
-                if let Some(endpoint_url) = endpoint {
-                    if !endpoint_url.is_empty() {
-                        config_builder = config_builder.endpoint_url(endpoint_url);
-                    }
-                }
-
-                match auth_method {
-                    None => {
-                        if let Some(creds) = credentials {
-                            let aws_creds = Credentials::new(
-                                creds.access_key_id,
-                                creds.secret_access_key,
-                                creds.session_token,
-                                None,
-                                "zed-bedrock-provider",
-                            );
-                            config_builder = config_builder.credentials_provider(aws_creds);
-                        }
-                    }
-                    Some(BedrockAuthMethod::NamedProfile)
-                    | Some(BedrockAuthMethod::SingleSignOn) => {
-                        // Currently NamedProfile and SSO behave the same way but only the instructions change
-                        // Until we support BearerAuth through SSO, this will not change.
-                        let profile_name = settings
-                            .and_then(|s| s.profile_name)
-                            .unwrap_or_else(|| "default".to_string());
-
-                        if !profile_name.is_empty() {
-                            config_builder = config_builder.profile_name(profile_name);
-                        }
-                    }
-                    Some(BedrockAuthMethod::Automatic) => {
-                        // Use default credential provider chain
-                    }
-                }
+    fn get_or_init_client(&self, cx: &AsyncApp) -> anyhow::Result<BedrockClient> {
+        let config = cx.read_entity(&self.state, |state, _cx| state.client_config())?;
 
-                let config = self.handler.block_on(config_builder.load());
-                anyhow::Ok(BedrockClient::new(&config))
-            })
-            .context("initializing Bedrock client")?;
+        let mut client = self.client.lock();
+        if let Some((cached_config, cached_client)) = client.as_ref() {
+            if *cached_config == config {
+                return Ok(cached_client.clone());
+            }
+        }
 
-        self.client.get().context("Bedrock client not initialized")
+        let new_client = config.clone().load(self.http_client.clone(), &self.handler);
+        *client = Some((config, new_client.clone()));
+        Ok(new_client)
     }
 
     fn stream_completion(
@@ -451,8 +629,7 @@ impl BedrockModel {
     > {
         let runtime_client = self
             .get_or_init_client(cx)
-            .cloned()
-            .context("Bedrock client not initialized")?;
+            .context("initializing Bedrock client")?;
         let owned_handle = self.handler.clone();
 
         Ok(async move {
@@ -483,7 +660,7 @@ impl LanguageModel for BedrockModel {
     }
 
     fn supports_tools(&self) -> bool {
-        self.model.supports_tool_use()
+        self.supports_tools
     }
 
     fn supports_images(&self) -> bool {
@@ -492,13 +669,21 @@ impl LanguageModel for BedrockModel {
 
     fn supports_tool_choice(&self, choice: LanguageModelToolChoice) -> bool {
         match choice {
-            LanguageModelToolChoice::Auto | LanguageModelToolChoice::Any => {
-                self.model.supports_tool_use()
-            }
+            LanguageModelToolChoice::Auto
+            | LanguageModelToolChoice::Any
+            | LanguageModelToolChoice::Tool(_) => self.supports_tools,
             LanguageModelToolChoice::None => false,
         }
     }
 
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.supports_parallel_tool_calls
+    }
+
+    fn supports_thinking(&self) -> bool {
+        matches!(self.mode, BedrockModelMode::Thinking { .. })
+    }
+
     fn telemetry_id(&self) -> String {
         format!("bedrock/{}", self.model.id())
     }
@@ -529,6 +714,27 @@ impl LanguageModel for BedrockModel {
             BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
         >,
     > {
+        let model_id = self.id();
+        let sampling = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings
+                .bedrock
+                .available_models
+                .iter()
+                .find(|model| model_id.0 == model.name)
+                .map(|model| model.sampling)
+        })
+        .flatten()
+        .unwrap_or_default();
+        let (system_prompt, guardrail) =
+            AllLanguageModelSettings::try_read_global(cx, |settings| {
+                (
+                    settings.bedrock.system_prompt.clone(),
+                    settings.bedrock.guardrail.clone(),
+                )
+            })
+            .unwrap_or_default();
+        let request = system_prompt.apply(sampling.apply(request));
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
         let Ok(region) = cx.read_entity(&self.state, |state, _cx| {
             // Get region - from credentials or directly from settings
             let region = state
@@ -539,41 +745,46 @@ impl LanguageModel for BedrockModel {
 
             region
         }) else {
-            return async move {
-                anyhow::bail!("App State Dropped");
-            }
-            .boxed();
+            return request_log.track(
+                async move {
+                    anyhow::bail!("App State Dropped");
+                }
+                .boxed(),
+            );
         };
 
         let model_id = match self.model.cross_region_inference_id(&region) {
             Ok(s) => s,
             Err(e) => {
-                return async move { Err(e) }.boxed();
+                return request_log.track(async move { Err(e) }.boxed());
             }
         };
 
-        let request = match into_bedrock(
+        let mut request = match into_bedrock(
             request,
             model_id,
             self.model.default_temperature(),
             self.model.max_output_tokens(),
-            self.model.mode(),
+            self.mode.clone(),
         ) {
             Ok(request) => request,
-            Err(err) => return futures::future::ready(Err(err)).boxed(),
+            Err(err) => return request_log.track(futures::future::ready(Err(err)).boxed()),
         };
+        request.guardrail = guardrail.map(|guardrail| bedrock::Guardrail {
+            identifier: guardrail.id,
+            version: guardrail.version.unwrap_or_else(|| "DRAFT".into()),
+        });
 
         let owned_handle = self.handler.clone();
 
         let request = self.stream_completion(request, cx);
         let future = self.request_limiter.stream(async move {
             let response = request.map_err(|err| anyhow!(err))?.await;
-            Ok(map_to_language_model_completion_events(
-                response,
-                owned_handle,
-            ))
+            Ok(estimate_live_usage(normalize_reasoning(
+                map_to_language_model_completion_events(response, owned_handle).boxed(),
+            )))
         });
-        async move { Ok(future.await?.boxed()) }.boxed()
+        request_log.track(async move { Ok(future.await?.boxed()) }.boxed())
     }
 
     fn cache_configuration(&self) -> Option<LanguageModelCacheConfiguration> {
@@ -591,7 +802,7 @@ pub fn into_bedrock(
     let mut new_messages: Vec<BedrockMessage> = Vec::new();
     let mut system_message = String::new();
 
-    for message in request.messages {
+    for message in normalize_messages(request.messages, MessageConstraints::STRICT) {
         if message.contents_empty() {
             continue;
         }
@@ -609,6 +820,9 @@ pub fn into_bedrock(
                                 None
                             }
                         }
+                        MessageContent::Document(document) => {
+                            Some(BedrockInnerContent::Text(document.to_text()))
+                        }
                         MessageContent::Thinking { text, signature } => {
                             let thinking = BedrockThinkingTextBlock::builder()
                                 .text(text)
@@ -716,6 +930,9 @@ pub fn into_bedrock(
         Some(LanguageModelToolChoice::Any) => {
             BedrockToolChoice::Any(BedrockAnyToolChoice::builder().build())
         }
+        Some(LanguageModelToolChoice::Tool(name)) => {
+            BedrockToolChoice::Tool(BedrockSpecificToolChoice::builder().name(name).build()?)
+        }
         Some(LanguageModelToolChoice::None) => {
             anyhow::bail!("LanguageModelToolChoice::None is not supported");
         }
@@ -737,10 +954,11 @@ pub fn into_bedrock(
             None
         },
         metadata: None,
-        stop_sequences: Vec::new(),
+        stop_sequences: request.stop,
         temperature: request.temperature.or(Some(default_temperature)),
-        top_k: None,
-        top_p: None,
+        top_k: request.top_k,
+        top_p: request.top_p,
+        guardrail: None,
     })
 }
 
@@ -766,10 +984,17 @@ pub fn get_bedrock_tokens(
                         MessageContent::Text(text) | MessageContent::Thinking { text, .. } => {
                             string_contents.push_str(&text);
                         }
+                        MessageContent::Document(document) => {
+                            string_contents.push_str(&document.to_text());
+                        }
                         MessageContent::RedactedThinking(_) => {}
                         MessageContent::Image(image) => {
                             tokens_from_images += image.estimate_tokens();
                         }
+                        MessageContent::Pdf(pdf) => {
+                            tokens_from_images += pdf.estimate_tokens();
+                        }
+                        MessageContent::Audio(_) | MessageContent::Video(_) => {}
                         MessageContent::ToolUse(_tool_use) => {
                             // TODO: Estimate token usage from tool uses.
                         }
@@ -819,208 +1044,321 @@ pub fn map_to_language_model_completion_events(
     struct State {
         events: Pin<Box<dyn Send + Stream<Item = Result<BedrockStreamingResponse, BedrockError>>>>,
         tool_uses_by_index: HashMap<i32, RawToolUse>,
+        /// An event to emit before reading the next one, when a single Bedrock event maps to two.
+        pending_event: Option<LanguageModelCompletionEvent>,
     }
 
     futures::stream::unfold(
         State {
             events,
             tool_uses_by_index: HashMap::default(),
+            pending_event: None,
         },
         move |mut state: State| {
             let inner_handle = handle.clone();
             async move {
-                inner_handle
-                    .spawn(async {
-                        while let Some(event) = state.events.next().await {
-                            match event {
-                                Ok(event) => match event {
-                                    ConverseStreamOutput::ContentBlockDelta(cb_delta) => {
-                                        match cb_delta.delta {
-                                            Some(ContentBlockDelta::Text(text_out)) => {
-                                                let completion_event =
-                                                    LanguageModelCompletionEvent::Text(text_out);
-                                                return Some((Some(Ok(completion_event)), state));
-                                            }
+                if let Some(event) = state.pending_event.take() {
+                    return Some((Some(Ok(event)), state));
+                }
+                let task = inner_handle.spawn(async {
+                    while let Some(event) = state.events.next().await {
+                        match event {
+                            Ok(event) => match event {
+                                ConverseStreamOutput::ContentBlockDelta(cb_delta) => {
+                                    match cb_delta.delta {
+                                        Some(ContentBlockDelta::Text(text_out)) => {
+                                            let completion_event =
+                                                LanguageModelCompletionEvent::Text(text_out);
+                                            return Some((Some(Ok(completion_event)), state));
+                                        }
 
-                                            Some(ContentBlockDelta::ToolUse(text_out)) => {
-                                                if let Some(tool_use) = state
-                                                    .tool_uses_by_index
-                                                    .get_mut(&cb_delta.content_block_index)
-                                                {
+                                        Some(ContentBlockDelta::ToolUse(text_out)) => {
+                                            let partial = state
+                                                .tool_uses_by_index
+                                                .get_mut(&cb_delta.content_block_index)
+                                                .and_then(|tool_use| {
                                                     tool_use.input_json.push_str(text_out.input());
-                                                }
+                                                    partial_tool_use(
+                                                        &tool_use.id,
+                                                        &tool_use.name,
+                                                        &tool_use.input_json,
+                                                    )
+                                                });
+                                            if let Some(partial) = partial {
+                                                return Some((Some(Ok(partial)), state));
                                             }
+                                        }
 
-                                            Some(ContentBlockDelta::ReasoningContent(thinking)) => {
-                                                match thinking {
-                                                    ReasoningContentBlockDelta::RedactedContent(
-                                                        redacted,
-                                                    ) => {
-                                                        let thinking_event =
-                                                            LanguageModelCompletionEvent::Thinking {
-                                                                text: String::from_utf8(
-                                                                    redacted.into_inner(),
-                                                                )
-                                                                .unwrap_or("REDACTED".to_string()),
-                                                                signature: None,
-                                                            };
-
-                                                        return Some((
-                                                            Some(Ok(thinking_event)),
-                                                            state,
-                                                        ));
-                                                    }
-                                                    ReasoningContentBlockDelta::Signature(
-                                                        signature,
-                                                    ) => {
-                                                        return Some((
-                                                            Some(Ok(LanguageModelCompletionEvent::Thinking {
-                                                                text: "".to_string(),
-                                                                signature: Some(signature)
-                                                            })),
-                                                            state,
-                                                        ));
-                                                    }
-                                                    ReasoningContentBlockDelta::Text(thoughts) => {
-                                                        let thinking_event =
-                                                            LanguageModelCompletionEvent::Thinking {
-                                                                text: thoughts.to_string(),
-                                                                signature: None
-                                                            };
-
-                                                        return Some((
-                                                            Some(Ok(thinking_event)),
-                                                            state,
-                                                        ));
-                                                    }
-                                                    _ => {}
+                                        Some(ContentBlockDelta::ReasoningContent(thinking)) => {
+                                            match thinking {
+                                                ReasoningContentBlockDelta::RedactedContent(
+                                                    redacted,
+                                                ) => {
+                                                    let thinking_event =
+                                                        LanguageModelCompletionEvent::Thinking {
+                                                            text: String::from_utf8(
+                                                                redacted.into_inner(),
+                                                            )
+                                                            .unwrap_or("REDACTED".to_string()),
+                                                            signature: None,
+                                                        };
+
+                                                    return Some((
+                                                        Some(Ok(thinking_event)),
+                                                        state,
+                                                    ));
                                                 }
+                                                ReasoningContentBlockDelta::Signature(
+                                                    signature,
+                                                ) => {
+                                                    return Some((
+                                                        Some(Ok(LanguageModelCompletionEvent::Thinking {
+                                                            text: "".to_string(),
+                                                            signature: Some(signature)
+                                                        })),
+                                                        state,
+                                                    ));
+                                                }
+                                                ReasoningContentBlockDelta::Text(thoughts) => {
+                                                    let thinking_event =
+                                                        LanguageModelCompletionEvent::Thinking {
+                                                            text: thoughts.to_string(),
+                                                            signature: None
+                                                        };
+
+                                                    return Some((
+                                                        Some(Ok(thinking_event)),
+                                                        state,
+                                                    ));
+                                                }
+                                                _ => {}
                                             }
-                                            _ => {}
-                                        }
-                                    }
-                                    ConverseStreamOutput::ContentBlockStart(cb_start) => {
-                                        if let Some(ContentBlockStart::ToolUse(text_out)) =
-                                            cb_start.start
-                                        {
-                                            let tool_use = RawToolUse {
-                                                id: text_out.tool_use_id,
-                                                name: text_out.name,
-                                                input_json: String::new(),
-                                            };
-
-                                            state
-                                                .tool_uses_by_index
-                                                .insert(cb_start.content_block_index, tool_use);
-                                        }
-                                    }
-                                    ConverseStreamOutput::ContentBlockStop(cb_stop) => {
-                                        if let Some(tool_use) = state
-                                            .tool_uses_by_index
-                                            .remove(&cb_stop.content_block_index)
-                                        {
-                                            let tool_use_event = LanguageModelToolUse {
-                                                id: tool_use.id.into(),
-                                                name: tool_use.name.into(),
-                                                is_input_complete: true,
-                                                raw_input: tool_use.input_json.clone(),
-                                                input: if tool_use.input_json.is_empty() {
-                                                    Value::Null
-                                                } else {
-                                                    serde_json::Value::from_str(
-                                                        &tool_use.input_json,
-                                                    )
-                                                    .map_err(|err| anyhow!(err))
-                                                    .unwrap()
-                                                },
-                                            };
-
-                                            return Some((
-                                                Some(Ok(LanguageModelCompletionEvent::ToolUse(
-                                                    tool_use_event,
-                                                ))),
-                                                state,
-                                            ));
                                         }
+                                        _ => {}
                                     }
+                                }
+                                ConverseStreamOutput::ContentBlockStart(cb_start) => {
+                                    if let Some(ContentBlockStart::ToolUse(text_out)) =
+                                        cb_start.start
+                                    {
+                                        let tool_use = RawToolUse {
+                                            id: text_out.tool_use_id,
+                                            name: text_out.name,
+                                            input_json: String::new(),
+                                        };
 
-                                    ConverseStreamOutput::Metadata(cb_meta) => {
-                                        if let Some(metadata) = cb_meta.usage {
-                                            let completion_event =
-                                                LanguageModelCompletionEvent::UsageUpdate(
-                                                    TokenUsage {
-                                                        input_tokens: metadata.input_tokens as u32,
-                                                        output_tokens: metadata.output_tokens
-                                                            as u32,
-                                                        cache_creation_input_tokens: default(),
-                                                        cache_read_input_tokens: default(),
-                                                    },
-                                                );
-                                            return Some((Some(Ok(completion_event)), state));
-                                        }
+                                        state
+                                            .tool_uses_by_index
+                                            .insert(cb_start.content_block_index, tool_use);
                                     }
-                                    ConverseStreamOutput::MessageStop(message_stop) => {
-                                        let reason = match message_stop.stop_reason {
-                                            StopReason::ContentFiltered => {
-                                                LanguageModelCompletionEvent::Stop(
-                                                    language_model::StopReason::EndTurn,
-                                                )
-                                            }
-                                            StopReason::EndTurn => {
-                                                LanguageModelCompletionEvent::Stop(
-                                                    language_model::StopReason::EndTurn,
-                                                )
-                                            }
-                                            StopReason::GuardrailIntervened => {
-                                                LanguageModelCompletionEvent::Stop(
-                                                    language_model::StopReason::EndTurn,
-                                                )
-                                            }
-                                            StopReason::MaxTokens => {
-                                                LanguageModelCompletionEvent::Stop(
-                                                    language_model::StopReason::EndTurn,
-                                                )
-                                            }
-                                            StopReason::StopSequence => {
-                                                LanguageModelCompletionEvent::Stop(
-                                                    language_model::StopReason::EndTurn,
+                                }
+                                ConverseStreamOutput::ContentBlockStop(cb_stop) => {
+                                    if let Some(tool_use) = state
+                                        .tool_uses_by_index
+                                        .remove(&cb_stop.content_block_index)
+                                    {
+                                        let tool_use_event = LanguageModelToolUse {
+                                            id: tool_use.id.into(),
+                                            name: tool_use.name.into(),
+                                            is_input_complete: true,
+                                            raw_input: tool_use.input_json.clone(),
+                                            input: if tool_use.input_json.is_empty() {
+                                                Value::Null
+                                            } else {
+                                                serde_json::Value::from_str(
+                                                    &tool_use.input_json,
                                                 )
-                                            }
-                                            StopReason::ToolUse => {
-                                                LanguageModelCompletionEvent::Stop(
-                                                    language_model::StopReason::ToolUse,
-                                                )
-                                            }
-                                            _ => LanguageModelCompletionEvent::Stop(
-                                                language_model::StopReason::EndTurn,
-                                            ),
+                                                .map_err(|err| anyhow!(err))
+                                                .unwrap()
+                                            },
                                         };
-                                        return Some((Some(Ok(reason)), state));
-                                    }
-                                    _ => {}
-                                },
 
-                                Err(err) => return Some((Some(Err(anyhow!(err).into())), state)),
-                            }
+                                        return Some((
+                                            Some(Ok(LanguageModelCompletionEvent::ToolUse(
+                                                tool_use_event,
+                                            ))),
+                                            state,
+                                        ));
+                                    }
+                                }
+
+                                ConverseStreamOutput::Metadata(cb_meta) => {
+                                    state.pending_event = cb_meta
+                                        .trace
+                                        .and_then(|trace| trace.guardrail)
+                                        .map(|assessment| guardrail_violations(&assessment))
+                                        .filter(|violations| !violations.is_empty())
+                                        .map(LanguageModelCompletionEvent::GuardrailIntervention);
+                                    if let Some(metadata) = cb_meta.usage {
+                                        let completion_event =
+                                            LanguageModelCompletionEvent::UsageUpdate(
+                                                TokenUsage {
+                                                    input_tokens: metadata.input_tokens as u32,
+                                                    output_tokens: metadata.output_tokens
+                                                        as u32,
+                                                    cache_creation_input_tokens: default(),
+                                                    cache_read_input_tokens: default(),
+                                                    reasoning_tokens: default(),
+                                                },
+                                            );
+                                        return Some((Some(Ok(completion_event)), state));
+                                    }
+                                    if let Some(event) = state.pending_event.take() {
+                                        return Some((Some(Ok(event)), state));
+                                    }
+                                }
+                                ConverseStreamOutput::MessageStop(message_stop) => {
+                                    let reason = match message_stop.stop_reason {
+                                        StopReason::ContentFiltered => {
+                                            LanguageModelCompletionEvent::Stop(
+                                                language_model::StopReason::ContentFilter,
+                                            )
+                                        }
+                                        StopReason::EndTurn => {
+                                            LanguageModelCompletionEvent::Stop(
+                                                language_model::StopReason::EndTurn,
+                                            )
+                                        }
+                                        StopReason::GuardrailIntervened => {
+                                            LanguageModelCompletionEvent::Stop(
+                                                language_model::StopReason::ContentFilter,
+                                            )
+                                        }
+                                        StopReason::MaxTokens => {
+                                            LanguageModelCompletionEvent::Stop(
+                                                language_model::StopReason::MaxTokens,
+                                            )
+                                        }
+                                        StopReason::StopSequence => {
+                                            LanguageModelCompletionEvent::Stop(
+                                                language_model::StopReason::EndTurn,
+                                            )
+                                        }
+                                        StopReason::ToolUse => {
+                                            LanguageModelCompletionEvent::Stop(
+                                                language_model::StopReason::ToolUse,
+                                            )
+                                        }
+                                        _ => LanguageModelCompletionEvent::Stop(
+                                            language_model::StopReason::EndTurn,
+                                        ),
+                                    };
+                                    return Some((Some(Ok(reason)), state));
+                                }
+                                _ => {}
+                            },
+
+                            Err(err) => return Some((Some(Err(anyhow!(err).into())), state)),
                         }
-                        None
-                    })
-                    .await
-                    .log_err()
-                    .flatten()
+                    }
+                    None
+                });
+                let abort_handle = task.abort_handle();
+                let _abort_on_drop = defer(move || abort_handle.abort());
+                task.await.log_err().flatten()
             }
         },
     )
     .filter_map(|event| async move { event })
 }
 
+/// What a guardrail detected in the request and the response, according to its trace.
+fn guardrail_violations(trace: &GuardrailTraceAssessment) -> Vec<GuardrailViolation> {
+    let assessments = trace
+        .input_assessment
+        .iter()
+        .flat_map(|assessments| assessments.values())
+        .chain(
+            trace
+                .output_assessments
+                .iter()
+                .flat_map(|assessments| assessments.values().flatten()),
+        );
+
+    let mut violations = Vec::new();
+    for assessment in assessments {
+        for violation in assessment_violations(assessment) {
+            if !violations.contains(&violation) {
+                violations.push(violation);
+            }
+        }
+    }
+    violations
+}
+
+fn assessment_violations(assessment: &GuardrailAssessment) -> Vec<GuardrailViolation> {
+    let violation = |policy, name: &str, action: &str| GuardrailViolation {
+        policy,
+        name: name.to_string(),
+        action: match action {
+            "BLOCKED" => GuardrailAction::Blocked,
+            "ANONYMIZED" => GuardrailAction::Anonymized,
+            _ => GuardrailAction::Detected,
+        },
+    };
+
+    let mut violations = Vec::new();
+    if let Some(policy) = &assessment.topic_policy {
+        violations.extend(
+            policy
+                .topics
+                .iter()
+                .map(|topic| violation(GuardrailPolicy::Topic, &topic.name, topic.action.as_str())),
+        );
+    }
+    if let Some(policy) = &assessment.content_policy {
+        violations.extend(policy.filters.iter().map(|filter| {
+            violation(
+                GuardrailPolicy::Content,
+                filter.r#type.as_str(),
+                filter.action.as_str(),
+            )
+        }));
+    }
+    if let Some(policy) = &assessment.word_policy {
+        violations.extend(
+            policy
+                .custom_words
+                .iter()
+                .map(|word| violation(GuardrailPolicy::Word, &word.r#match, word.action.as_str())),
+        );
+        violations.extend(policy.managed_word_lists.iter().map(|word| {
+            violation(
+                GuardrailPolicy::Word,
+                word.r#type.as_str(),
+                word.action.as_str(),
+            )
+        }));
+    }
+    if let Some(policy) = &assessment.sensitive_information_policy {
+        // Name the type of the sensitive information rather than repeating it.
+        violations.extend(policy.pii_entities.iter().map(|entity| {
+            violation(
+                GuardrailPolicy::SensitiveInformation,
+                entity.r#type.as_str(),
+                entity.action.as_str(),
+            )
+        }));
+        violations.extend(policy.regexes.iter().map(|regex| {
+            violation(
+                GuardrailPolicy::SensitiveInformation,
+                regex.name.as_deref().unwrap_or("regex"),
+                regex.action.as_str(),
+            )
+        }));
+    }
+    violations
+}
+
 struct ConfigurationView {
     access_key_id_editor: Entity<Editor>,
     secret_access_key_editor: Entity<Editor>,
     session_token_editor: Entity<Editor>,
     region_editor: Entity<Editor>,
+    profiles: Vec<AwsProfile>,
     state: gpui::Entity<State>,
     load_credentials_task: Option<Task<()>>,
+    _load_profiles_task: Task<()>,
 }
 
 impl ConfigurationView {
@@ -1054,6 +1392,15 @@ impl ConfigurationView {
             }
         }));
 
+        let load_profiles_task = cx.spawn(async move |this, cx| {
+            let profiles = load_aws_profiles().await;
+            this.update(cx, |this, cx| {
+                this.profiles = profiles;
+                cx.notify();
+            })
+            .log_err();
+        });
+
         Self {
             access_key_id_editor: cx.new(|cx| {
                 let mut editor = Editor::single_line(window, cx);
@@ -1075,11 +1422,26 @@ impl ConfigurationView {
                 editor.set_placeholder_text(Self::PLACEHOLDER_REGION, cx);
                 editor
             }),
+            profiles: Vec::new(),
             state,
             load_credentials_task,
+            _load_profiles_task: load_profiles_task,
         }
     }
 
+    fn set_authentication_method(
+        method: Option<BedrockAuthMethod>,
+        profile: Option<String>,
+        cx: &mut App,
+    ) {
+        let fs = <dyn Fs>::global(cx);
+        update_settings_file::<AllLanguageModelSettings>(fs, cx, move |settings, _| {
+            let bedrock = settings.bedrock.get_or_insert_default();
+            bedrock.authentication_method = method;
+            bedrock.profile = profile;
+        });
+    }
+
     fn save_credentials(
         &mut self,
         _: &menu::Confirm,
@@ -1203,12 +1565,17 @@ impl ConfigurationView {
 }
 
 impl Render for ConfigurationView {
-    fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let env_var_set = self.state.read(cx).credentials_from_env;
-        let bedrock_settings = self.state.read(cx).settings.as_ref();
-        let bedrock_method = bedrock_settings
+    fn render(&mut self, window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        let state = self.state.read(cx);
+        let env_var_set = state.credentials_from_env;
+        let bedrock_method = state.auth_method();
+        let method_from_settings = state
+            .settings
             .as_ref()
-            .and_then(|s| s.authentication_method.clone());
+            .is_some_and(|s| s.authentication_method.is_some());
+        let profile_name = state
+            .profile_name()
+            .unwrap_or_else(|| "default".to_string());
 
         if self.load_credentials_task.is_some() {
             return div().child(Label::new("Loading credentials...")).into_any();
@@ -1231,16 +1598,23 @@ impl Render for ConfigurationView {
                             format!("Access Key ID is set in {ZED_BEDROCK_ACCESS_KEY_ID_VAR}, Secret Key is set in {ZED_BEDROCK_SECRET_ACCESS_KEY_VAR}, Region is set in {ZED_BEDROCK_REGION_VAR} environment variables.")
                         } else {
                             match bedrock_method {
-                                Some(BedrockAuthMethod::Automatic) => "You are using automatic credentials".into(),
+                                Some(BedrockAuthMethod::Automatic) => "You are using the default AWS credential chain".into(),
                                 Some(BedrockAuthMethod::NamedProfile) => {
-                                    "You are using named profile".into()
+                                    format!("You are using the \"{profile_name}\" profile")
                                 },
-                                Some(BedrockAuthMethod::SingleSignOn) => "You are using a single sign on profile".into(),
+                                Some(BedrockAuthMethod::SingleSignOn) => format!("You are using the \"{profile_name}\" single sign on profile"),
                                 None => "You are using static credentials".into(),
                             }
                         })),
                 )
-                .child(
+                .child(if method_from_settings {
+                    Button::new("reset-method", "Change Method")
+                        .icon(Some(IconName::Undo))
+                        .icon_size(IconSize::Small)
+                        .icon_position(IconPosition::Start)
+                        .tooltip(Tooltip::text("Remove the authentication method from your settings and pick another one"))
+                        .on_click(|_, _, cx| Self::set_authentication_method(None, None, cx))
+                } else {
                     Button::new("reset-key", "Reset Key")
                         .icon(Some(IconName::Trash))
                         .icon_size(IconSize::Small)
@@ -1250,10 +1624,10 @@ impl Render for ConfigurationView {
                             this.tooltip(Tooltip::text(format!("To reset your credentials, unset the {ZED_BEDROCK_ACCESS_KEY_ID_VAR}, {ZED_BEDROCK_SECRET_ACCESS_KEY_VAR}, and {ZED_BEDROCK_REGION_VAR} environment variables.")))
                         })
                         .when(bedrock_method.is_some(), |this| {
-                            this.tooltip(Tooltip::text("You cannot reset credentials as they're being derived, check Zed settings to understand how"))
+                            this.tooltip(Tooltip::text(format!("To stop using this profile, unset the {ZED_AWS_PROFILE_VAR} environment variable.")))
                         })
-                        .on_click(cx.listener(|this, _, window, cx| this.reset_credentials(window, cx))),
-                )
+                        .on_click(cx.listener(|this, _, window, cx| this.reset_credentials(window, cx)))
+                })
                 .into_any();
         }
 
@@ -1279,6 +1653,7 @@ impl Render for ConfigurationView {
                         )
                     )
             )
+            .child(self.render_profile_ui(window, cx))
             .child(self.render_static_credentials_ui(cx))
             .child(self.render_common_fields(cx))
             .child(
@@ -1357,6 +1732,78 @@ impl ConfigurationView {
         )
     }
 
+    fn render_profile_ui(&self, window: &mut Window, cx: &mut Context<Self>) -> AnyElement {
+        let profiles = self.profiles.clone();
+
+        v_flex()
+            .my_2()
+            .gap_1p5()
+            .child(
+                Label::new("AWS Profile")
+                    .size(LabelSize::Default)
+                    .weight(FontWeight::BOLD),
+            )
+            .child(Label::new(
+                "Use a profile from your AWS CLI configuration. Profiles may use SSO, a credential process, or an assumed role, so no long-lived keys need to be stored in Zed.",
+            ))
+            .child(
+                Label::new("For SSO profiles, run `aws sso login --profile <name>` before using the assistant.")
+                    .size(LabelSize::Small)
+                    .color(Color::Muted),
+            )
+            .child(
+                h_flex()
+                    .gap_2()
+                    .map(|this| {
+                        if profiles.is_empty() {
+                            this.child(
+                                Label::new("No profiles found in ~/.aws/config or ~/.aws/credentials")
+                                    .size(LabelSize::Small)
+                                    .color(Color::Muted),
+                            )
+                        } else {
+                            this.child(DropdownMenu::new(
+                                "aws-profile",
+                                "Select a profile",
+                                ContextMenu::build(window, cx, move |mut menu, _window, _cx| {
+                                    for profile in profiles {
+                                        let method = if profile.uses_sso {
+                                            BedrockAuthMethod::SingleSignOn
+                                        } else {
+                                            BedrockAuthMethod::NamedProfile
+                                        };
+                                        let name = profile.name.clone();
+                                        menu = menu.entry(profile.name, None, move |_, cx| {
+                                            Self::set_authentication_method(
+                                                Some(method.clone()),
+                                                Some(name.clone()),
+                                                cx,
+                                            )
+                                        });
+                                    }
+                                    menu
+                                }),
+                            ))
+                        }
+                    })
+                    .child(
+                        Button::new("use-default-chain", "Use Default Credential Chain")
+                            .style(ButtonStyle::Subtle)
+                            .tooltip(Tooltip::text(
+                                "Resolve credentials from AWS environment variables, web identity, or container and instance metadata",
+                            ))
+                            .on_click(|_, _, cx| {
+                                Self::set_authentication_method(
+                                    Some(BedrockAuthMethod::Automatic),
+                                    None,
+                                    cx,
+                                )
+                            }),
+                    ),
+            )
+            .into_any_element()
+    }
+
     fn render_static_credentials_ui(&self, cx: &mut Context<Self>) -> AnyElement {
         v_flex()
             .my_2()
@@ -1428,3 +1875,54 @@ impl ConfigurationView {
             .into_any_element()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_aws_profiles() {
+        let config = r#"
+[default]
+region = us-east-1
+
+[profile work]
+sso_session = company
+sso_account_id = 123456789012
+
+[sso-session company]
+sso_start_url = https://example.awsapps.com/start
+
+[profile ci]
+credential_process = /usr/local/bin/get-creds
+"#;
+        let credentials = r#"
+[default]
+aws_access_key_id = AKIA
+[personal]
+aws_access_key_id = AKIA
+"#;
+
+        assert_eq!(
+            parse_aws_profiles(config, credentials),
+            vec![
+                AwsProfile {
+                    name: "default".into(),
+                    uses_sso: false,
+                },
+                AwsProfile {
+                    name: "work".into(),
+                    uses_sso: true,
+                },
+                AwsProfile {
+                    name: "ci".into(),
+                    uses_sso: false,
+                },
+                AwsProfile {
+                    name: "personal".into(),
+                    uses_sso: false,
+                },
+            ]
+        );
+    }
+}
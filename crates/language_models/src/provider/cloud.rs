@@ -9,12 +9,12 @@ use gpui::{
 };
 use http_client::{AsyncBody, HttpClient, Method, Response, StatusCode};
 use language_model::{
-    AuthenticateError, LanguageModel, LanguageModelCacheConfiguration,
+    AuthenticateError, DocumentLimits, ImageLimits, LanguageModel, LanguageModelCacheConfiguration,
     LanguageModelCompletionError, LanguageModelId, LanguageModelKnownError, LanguageModelName,
     LanguageModelProviderId, LanguageModelProviderName, LanguageModelProviderState,
     LanguageModelProviderTosView, LanguageModelRequest, LanguageModelToolChoice,
     LanguageModelToolSchemaFormat, ModelRequestLimitReachedError, RateLimiter, RequestUsage,
-    ZED_CLOUD_PROVIDER_ID,
+    StructuredOutput, ZED_CLOUD_PROVIDER_ID,
 };
 use language_model::{
     LanguageModelCompletionEvent, LanguageModelProvider, LlmApiToken, PaymentRequiredError,
@@ -24,7 +24,7 @@ use proto::Plan;
 use release_channel::AppVersion;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use settings::SettingsStore;
+use settings::{Settings as _, SettingsStore};
 use smol::Timer;
 use smol::io::{AsyncReadExt, BufReader};
 use std::pin::Pin;
@@ -42,9 +42,17 @@ use zed_llm_client::{
     TOOL_USE_LIMIT_REACHED_HEADER_NAME, ZED_VERSION_HEADER_NAME,
 };
 
-use crate::provider::anthropic::{AnthropicEventMapper, count_anthropic_tokens, into_anthropic};
-use crate::provider::google::{GoogleEventMapper, into_google};
-use crate::provider::open_ai::{OpenAiEventMapper, count_open_ai_tokens, into_open_ai};
+use crate::AllLanguageModelSettings;
+use crate::provider::anthropic::{
+    AnthropicEventMapper, count_anthropic_tokens, document_sources, into_anthropic,
+};
+use crate::provider::google::{
+    GOOGLE_DOCUMENT_LIMITS, GOOGLE_IMAGE_LIMITS, GoogleEventMapper, into_google,
+};
+use crate::provider::open_ai::{
+    MAX_IMAGE_BYTES, OPEN_AI_IMAGE_LIMITS, OpenAiEventMapper, count_open_ai_tokens, into_open_ai,
+};
+use crate::request_log::RequestLogRecorder;
 
 pub const PROVIDER_NAME: &str = "Zed";
 
@@ -329,13 +337,17 @@ impl CloudLanguageModelProvider {
         &self,
         model: Arc<zed_llm_client::LanguageModel>,
         llm_api_token: LlmApiToken,
+        cx: &App,
     ) -> Arc<dyn LanguageModel> {
         Arc::new(CloudLanguageModel {
             id: LanguageModelId(SharedString::from(model.id.0.clone())),
             model,
             llm_api_token: llm_api_token.clone(),
             client: self.client.clone(),
-            request_limiter: RateLimiter::new(4),
+            request_limiter: RateLimiter::new(
+                AllLanguageModelSettings::get_global(cx)
+                    .max_concurrent_requests(ZED_CLOUD_PROVIDER_ID),
+            ),
         })
     }
 }
@@ -364,13 +376,13 @@ impl LanguageModelProvider for CloudLanguageModelProvider {
     fn default_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
         let default_model = self.state.read(cx).default_model.clone()?;
         let llm_api_token = self.state.read(cx).llm_api_token.clone();
-        Some(self.create_language_model(default_model, llm_api_token))
+        Some(self.create_language_model(default_model, llm_api_token, cx))
     }
 
     fn default_fast_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
         let default_fast_model = self.state.read(cx).default_fast_model.clone()?;
         let llm_api_token = self.state.read(cx).llm_api_token.clone();
-        Some(self.create_language_model(default_fast_model, llm_api_token))
+        Some(self.create_language_model(default_fast_model, llm_api_token, cx))
     }
 
     fn recommended_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
@@ -380,7 +392,7 @@ impl LanguageModelProvider for CloudLanguageModelProvider {
             .recommended_models
             .iter()
             .cloned()
-            .map(|model| self.create_language_model(model, llm_api_token.clone()))
+            .map(|model| self.create_language_model(model, llm_api_token.clone(), cx))
             .collect()
     }
 
@@ -391,7 +403,7 @@ impl LanguageModelProvider for CloudLanguageModelProvider {
             .models
             .iter()
             .cloned()
-            .map(|model| self.create_language_model(model, llm_api_token.clone()))
+            .map(|model| self.create_language_model(model, llm_api_token.clone(), cx))
             .collect()
     }
 
@@ -686,18 +698,63 @@ impl LanguageModel for CloudLanguageModel {
         self.model.supports_images
     }
 
+    fn image_limits(&self) -> ImageLimits {
+        match self.model.provider {
+            zed_llm_client::LanguageModelProvider::OpenAi => OPEN_AI_IMAGE_LIMITS,
+            zed_llm_client::LanguageModelProvider::Anthropic => ImageLimits::default(),
+            zed_llm_client::LanguageModelProvider::Google => GOOGLE_IMAGE_LIMITS,
+        }
+    }
+
+    fn document_limits(&self) -> Option<DocumentLimits> {
+        match self.model.provider {
+            zed_llm_client::LanguageModelProvider::Anthropic => Some(DocumentLimits::default()),
+            zed_llm_client::LanguageModelProvider::Google => Some(GOOGLE_DOCUMENT_LIMITS),
+            zed_llm_client::LanguageModelProvider::OpenAi => {
+                self.model.supports_images.then(DocumentLimits::default)
+            }
+        }
+    }
+
+    fn supports_audio(&self) -> bool {
+        matches!(
+            self.model.provider,
+            zed_llm_client::LanguageModelProvider::Google
+        )
+    }
+
+    fn supports_video(&self) -> bool {
+        matches!(
+            self.model.provider,
+            zed_llm_client::LanguageModelProvider::Google
+        )
+    }
+
     fn supports_tool_choice(&self, choice: LanguageModelToolChoice) -> bool {
         match choice {
             LanguageModelToolChoice::Auto
             | LanguageModelToolChoice::Any
-            | LanguageModelToolChoice::None => true,
+            | LanguageModelToolChoice::None
+            | LanguageModelToolChoice::Tool(_) => true,
         }
     }
 
+    fn supports_thinking(&self) -> bool {
+        self.model.id.0.ends_with("-thinking")
+    }
+
     fn supports_max_mode(&self) -> bool {
         self.model.supports_max_mode
     }
 
+    fn structured_output(&self) -> StructuredOutput {
+        match self.model.provider {
+            zed_llm_client::LanguageModelProvider::Anthropic => StructuredOutput::ToolCall,
+            zed_llm_client::LanguageModelProvider::OpenAi
+            | zed_llm_client::LanguageModelProvider::Google => StructuredOutput::Native,
+        }
+    }
+
     fn telemetry_id(&self) -> String {
         format!("zed.dev/{}", self.model.id)
     }
@@ -807,6 +864,7 @@ impl LanguageModel for CloudLanguageModel {
             BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
         >,
     > {
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
         let thread_id = request.thread_id.clone();
         let prompt_id = request.prompt_id.clone();
         let intent = request.intent;
@@ -814,6 +872,7 @@ impl LanguageModel for CloudLanguageModel {
         let app_version = cx.update(|cx| AppVersion::global(cx)).ok();
         match self.model.provider {
             zed_llm_client::LanguageModelProvider::Anthropic => {
+                let document_sources = document_sources(&request);
                 let request = into_anthropic(
                     request,
                     self.model.id.to_string(),
@@ -866,7 +925,8 @@ impl LanguageModel for CloudLanguageModel {
                         Err(err) => anyhow!(err),
                     })?;
 
-                    let mut mapper = AnthropicEventMapper::new();
+                    let mut mapper =
+                        AnthropicEventMapper::new().with_document_sources(document_sources);
                     Ok(map_cloud_completion_events(
                         Box::pin(
                             response_lines(response, includes_status_messages)
@@ -876,15 +936,15 @@ impl LanguageModel for CloudLanguageModel {
                         move |event| mapper.map_event(event),
                     ))
                 });
-                async move { Ok(future.await?.boxed()) }.boxed()
+                request_log.track(async move { Ok(future.await?.boxed()) }.boxed())
             }
             zed_llm_client::LanguageModelProvider::OpenAi => {
                 let client = self.client.clone();
                 let model = match open_ai::Model::from_id(&self.model.id.0) {
                     Ok(model) => model,
-                    Err(err) => return async move { Err(anyhow!(err)) }.boxed(),
+                    Err(err) => return request_log.track(async move { Err(anyhow!(err)) }.boxed()),
                 };
-                let request = into_open_ai(request, &model, None);
+                let request = into_open_ai(request, &model, None, MAX_IMAGE_BYTES);
                 let llm_api_token = self.llm_api_token.clone();
                 let future = self.request_limiter.stream(async move {
                     let PerformLlmCompletionResponse {
@@ -918,7 +978,7 @@ impl LanguageModel for CloudLanguageModel {
                         move |event| mapper.map_event(event),
                     ))
                 });
-                async move { Ok(future.await?.boxed()) }.boxed()
+                request_log.track(async move { Ok(future.await?.boxed()) }.boxed())
             }
             zed_llm_client::LanguageModelProvider::Google => {
                 let client = self.client.clone();
@@ -956,7 +1016,7 @@ impl LanguageModel for CloudLanguageModel {
                         move |event| mapper.map_event(event),
                     ))
                 });
-                async move { Ok(future.await?.boxed()) }.boxed()
+                request_log.track(async move { Ok(future.await?.boxed()) }.boxed())
             }
         }
     }
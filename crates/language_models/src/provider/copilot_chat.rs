@@ -25,7 +25,7 @@ use language_model::{
     LanguageModelToolSchemaFormat, LanguageModelToolUse, MessageContent, RateLimiter, Role,
     StopReason,
 };
-use settings::SettingsStore;
+use settings::{Settings as _, SettingsStore};
 use std::time::Duration;
 use ui::prelude::*;
 use util::debug_panic;
@@ -34,7 +34,13 @@ use super::anthropic::count_anthropic_tokens;
 use super::google::count_google_tokens;
 use super::open_ai::count_open_ai_tokens;
 
-const PROVIDER_ID: &str = "copilot_chat";
+use crate::AllLanguageModelSettings;
+use crate::live_usage::estimate_live_usage;
+use crate::partial_tool_use::partial_tool_use;
+use crate::provider::open_ai::stop_reason_for_finish_reason;
+use crate::request_log::RequestLogRecorder;
+
+pub(crate) const PROVIDER_ID: &str = "copilot_chat";
 const PROVIDER_NAME: &str = "GitHub Copilot Chat";
 
 #[derive(Default, Clone, Debug, PartialEq)]
@@ -73,10 +79,12 @@ impl CopilotChatLanguageModelProvider {
         Self { state }
     }
 
-    fn create_language_model(&self, model: CopilotChatModel) -> Arc<dyn LanguageModel> {
+    fn create_language_model(&self, model: CopilotChatModel, cx: &App) -> Arc<dyn LanguageModel> {
         Arc::new(CopilotChatLanguageModel {
             model,
-            request_limiter: RateLimiter::new(4),
+            request_limiter: RateLimiter::new(
+                AllLanguageModelSettings::get_global(cx).max_concurrent_requests(PROVIDER_ID),
+            ),
         })
     }
 }
@@ -106,7 +114,7 @@ impl LanguageModelProvider for CopilotChatLanguageModelProvider {
         let models = CopilotChat::global(cx).and_then(|m| m.read(cx).models())?;
         models
             .first()
-            .map(|model| self.create_language_model(model.clone()))
+            .map(|model| self.create_language_model(model.clone(), cx))
     }
 
     fn default_fast_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
@@ -121,7 +129,7 @@ impl LanguageModelProvider for CopilotChatLanguageModelProvider {
         };
         models
             .iter()
-            .map(|model| self.create_language_model(model.clone()))
+            .map(|model| self.create_language_model(model.clone(), cx))
             .collect()
     }
 
@@ -218,7 +226,8 @@ impl LanguageModel for CopilotChatLanguageModel {
         match choice {
             LanguageModelToolChoice::Auto
             | LanguageModelToolChoice::Any
-            | LanguageModelToolChoice::None => self.supports_tools(),
+            | LanguageModelToolChoice::None
+            | LanguageModelToolChoice::Tool(_) => self.supports_tools(),
         }
     }
 
@@ -255,11 +264,13 @@ impl LanguageModel for CopilotChatLanguageModel {
             BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
         >,
     > {
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
         if let Some(message) = request.messages.last() {
             if message.contents_empty() {
                 const EMPTY_PROMPT_MSG: &str =
                     "Empty prompts aren't allowed. Please provide a non-empty prompt.";
-                return futures::future::ready(Err(anyhow::anyhow!(EMPTY_PROMPT_MSG))).boxed();
+                return request_log
+                    .track(futures::future::ready(Err(anyhow::anyhow!(EMPTY_PROMPT_MSG))).boxed());
             }
 
             // Copilot Chat has a restriction that the final message must be from the user.
@@ -267,13 +278,14 @@ impl LanguageModel for CopilotChatLanguageModel {
             // and provide a more helpful error message.
             if !matches!(message.role, Role::User) {
                 const USER_ROLE_MSG: &str = "The final message must be from the user. To provide a system prompt, you must provide the system prompt followed by a user prompt.";
-                return futures::future::ready(Err(anyhow::anyhow!(USER_ROLE_MSG))).boxed();
+                return request_log
+                    .track(futures::future::ready(Err(anyhow::anyhow!(USER_ROLE_MSG))).boxed());
             }
         }
 
         let copilot_request = match into_copilot_chat(&self.model, request) {
             Ok(request) => request,
-            Err(err) => return futures::future::ready(Err(err)).boxed(),
+            Err(err) => return request_log.track(futures::future::ready(Err(err)).boxed()),
         };
         let is_streaming = copilot_request.stream;
 
@@ -283,14 +295,13 @@ impl LanguageModel for CopilotChatLanguageModel {
             request_limiter
                 .stream(async move {
                     let response = request.await?;
-                    Ok(map_to_language_model_completion_events(
-                        response,
-                        is_streaming,
+                    Ok(estimate_live_usage(
+                        map_to_language_model_completion_events(response, is_streaming).boxed(),
                     ))
                 })
                 .await
         });
-        async move { Ok(future.await?.boxed()) }.boxed()
+        request_log.track(async move { Ok(future.await?.boxed()) }.boxed())
     }
 }
 
@@ -359,18 +370,21 @@ pub fn map_to_language_model_completion_events(
                                     entry.name = name;
                                 }
 
-                                if let Some(arguments) = function.arguments.clone() {
-                                    entry.arguments.push_str(&arguments);
+                                if let Some(arguments) = function
+                                    .arguments
+                                    .as_deref()
+                                    .filter(|args| !args.is_empty())
+                                {
+                                    entry.arguments.push_str(arguments);
+                                    events.extend(
+                                        partial_tool_use(&entry.id, &entry.name, &entry.arguments)
+                                            .map(Ok),
+                                    );
                                 }
                             }
                         }
 
                         match choice.finish_reason.as_deref() {
-                            Some("stop") => {
-                                events.push(Ok(LanguageModelCompletionEvent::Stop(
-                                    StopReason::EndTurn,
-                                )));
-                            }
                             Some("tool_calls") => {
                                 events.extend(state.tool_calls_by_index.drain().map(
                                     |(_, tool_call)| {
@@ -409,11 +423,15 @@ pub fn map_to_language_model_completion_events(
                                     StopReason::ToolUse,
                                 )));
                             }
-                            Some(stop_reason) => {
-                                log::error!("Unexpected Copilot Chat stop_reason: {stop_reason:?}");
-                                events.push(Ok(LanguageModelCompletionEvent::Stop(
-                                    StopReason::EndTurn,
-                                )));
+                            Some(finish_reason) => {
+                                let stop_reason = stop_reason_for_finish_reason(finish_reason)
+                                    .unwrap_or_else(|| {
+                                        log::error!(
+                                            "Unexpected Copilot Chat stop_reason: {finish_reason:?}"
+                                        );
+                                        StopReason::EndTurn
+                                    });
+                                events.push(Ok(LanguageModelCompletionEvent::Stop(stop_reason)));
                             }
                             None => {}
                         }
@@ -496,6 +514,11 @@ fn into_copilot_chat(
                                 });
                             }
                         }
+                        MessageContent::Document(document) => {
+                            content_parts.push(ChatMessagePart::Text {
+                                text: document.to_text(),
+                            });
+                        }
                         MessageContent::Image(image) if model.supports_vision() => {
                             content_parts.push(ChatMessagePart::Image {
                                 image_url: ImageUrl {
@@ -539,7 +562,11 @@ fn into_copilot_chat(
                         MessageContent::ToolUse(_)
                         | MessageContent::RedactedThinking(_)
                         | MessageContent::ToolResult(_)
-                        | MessageContent::Image(_) => None,
+                        | MessageContent::Image(_)
+                        | MessageContent::Document(_)
+                        | MessageContent::Pdf(_)
+                        | MessageContent::Audio(_)
+                        | MessageContent::Video(_) => None,
                     }) {
                         buffer.push_str(string);
                     }
@@ -597,12 +624,21 @@ fn into_copilot_chat(
         temperature: 0.1,
         model: model.id().to_string(),
         messages,
+        parallel_tool_calls: request
+            .parallel_tool_calls
+            .filter(|_| model.supports_parallel_tool_calls() && !tools.is_empty()),
         tools,
         tool_choice: request.tool_choice.map(|choice| match choice {
             LanguageModelToolChoice::Auto => copilot::copilot_chat::ToolChoice::Auto,
             LanguageModelToolChoice::Any => copilot::copilot_chat::ToolChoice::Any,
             LanguageModelToolChoice::None => copilot::copilot_chat::ToolChoice::None,
+            LanguageModelToolChoice::Tool(name) => copilot::copilot_chat::ToolChoice::Named(
+                copilot::copilot_chat::NamedToolChoice::Function {
+                    function: copilot::copilot_chat::NamedFunction { name },
+                },
+            ),
         }),
+        stop: request.stop,
     })
 }
 
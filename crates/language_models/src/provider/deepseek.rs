@@ -1,4 +1,4 @@
-use anyhow::{Context as _, Result, anyhow};
+use anyhow::{Result, anyhow};
 use collections::BTreeMap;
 use credentials_provider::CredentialsProvider;
 use editor::{Editor, EditorElement, EditorStyle};
@@ -7,12 +7,12 @@ use gpui::{
     AnyView, AppContext as _, AsyncApp, Entity, FontStyle, Subscription, Task, TextStyle,
     WhiteSpace,
 };
-use http_client::HttpClient;
+use http_client::{HttpClient, http};
 use language_model::{
     AuthenticateError, LanguageModel, LanguageModelCompletionError, LanguageModelCompletionEvent,
     LanguageModelId, LanguageModelName, LanguageModelProvider, LanguageModelProviderId,
     LanguageModelProviderName, LanguageModelProviderState, LanguageModelRequest,
-    LanguageModelToolChoice, RateLimiter, Role,
+    LanguageModelToolChoice, ModelPrice, RateLimiter, Role, StructuredOutput,
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -22,15 +22,39 @@ use theme::ThemeSettings;
 use ui::{Icon, IconName, List, prelude::*};
 use util::ResultExt;
 
-use crate::{AllLanguageModelSettings, ui::InstructionListItem};
+use crate::api_key::{
+    ApiKeyDisplay, ApiKeySaveScope, ApiKeySource, ApiKeyStatus, ApiKeyValidation, ApiKeys,
+    CredentialsScope, WorkspaceApiKeys, WorkspaceScope, carry_over_api_key, load_api_keys,
+    refresh_api_keys, validate_api_keys,
+};
+use crate::key_usage::KeyUsageRecorder;
+use crate::live_usage::estimate_live_usage;
+use crate::moderation::check_moderation;
+use crate::normalize_messages::{MessageConstraints, normalize_messages};
+use crate::output_tokens::OutputTokenClamp;
+use crate::pricing::model_price;
+use crate::reasoning::normalize_reasoning;
+use crate::request_log::RequestLogRecorder;
+use crate::spend_limits::check_spend_limits;
+use crate::stream_timeouts::StreamTimeouts;
+use crate::{
+    AllLanguageModelSettings, ProviderApiSettings, SamplingParameters, ui::InstructionListItem,
+};
 
-const PROVIDER_ID: &str = "deepseek";
-const PROVIDER_NAME: &str = "DeepSeek";
+pub(crate) const PROVIDER_ID: &str = "deepseek";
+pub(crate) const PROVIDER_NAME: &str = "DeepSeek";
 const DEEPSEEK_API_KEY_VAR: &str = "DEEPSEEK_API_KEY";
 
+pub(crate) fn api_key_validation_request(api_url: &str, api_key: &str) -> http::request::Builder {
+    http::Request::builder()
+        .uri(format!("{api_url}/models"))
+        .header("Authorization", format!("Bearer {api_key}"))
+}
+
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct DeepSeekSettings {
     pub api_url: String,
+    pub api: ProviderApiSettings,
     pub available_models: Vec<AvailableModel>,
 }
 
@@ -40,6 +64,14 @@ pub struct AvailableModel {
     pub display_name: Option<String>,
     pub max_tokens: usize,
     pub max_output_tokens: Option<u32>,
+    /// Whether the model reasons before it responds, like `deepseek-reasoner`.
+    ///
+    /// Default: whether the model is `deepseek-reasoner`
+    #[serde(default)]
+    pub supports_thinking: Option<bool>,
+    /// Sampling parameters for requests to this model.
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
 }
 
 pub struct DeepSeekLanguageModelProvider {
@@ -48,50 +80,137 @@ pub struct DeepSeekLanguageModelProvider {
 }
 
 pub struct State {
-    api_key: Option<String>,
-    api_key_from_env: bool,
+    http_client: Arc<dyn HttpClient>,
+    api_url: String,
+    api_keys: ApiKeys,
+    api_key_source: ApiKeySource,
+    workspace_api_keys: WorkspaceApiKeys,
+    api_key_status: ApiKeyStatus,
+    _api_key_refresh_task: Option<Task<()>>,
     _subscription: Subscription,
+    _credentials_scope_subscription: Subscription,
 }
 
 impl State {
     fn is_authenticated(&self) -> bool {
-        self.api_key.is_some()
+        !self.api_keys.is_empty() || self.workspace_api_keys.has_any()
     }
 
-    fn reset_api_key(&self, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn reset_api_key(
+        &self,
+        workspace: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<()>> {
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .deepseek
             .api_url
             .clone();
+        if let Some(workspace) = workspace {
+            let task = self
+                .workspace_api_keys
+                .reset(&workspace, PROVIDER_ID, &api_url, cx);
+            cx.notify();
+            return task;
+        }
+        let credentials_scope = CredentialsScope::global(cx);
+        let credentials_urls = [
+            credentials_scope.api_key_url(&self.api_key_source, PROVIDER_ID, &api_url),
+            credentials_scope.legacy_api_key_url(&self.api_key_source, &api_url),
+        ];
         cx.spawn(async move |this, cx| {
-            credentials_provider
-                .delete_credentials(&api_url, &cx)
-                .await
-                .log_err();
+            for credentials_url in &credentials_urls {
+                credentials_provider
+                    .delete_credentials(credentials_url, &cx)
+                    .await
+                    .log_err();
+            }
             this.update(cx, |this, cx| {
-                this.api_key = None;
-                this.api_key_from_env = false;
+                this.api_keys = ApiKeys::default();
+                this.api_key_status.set_rejected(false, cx);
+                this.api_key_source = ApiKeySource::CredentialsStore;
+                this._api_key_refresh_task = None;
                 cx.notify();
             })
         })
     }
 
-    fn set_api_key(&mut self, api_key: String, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn set_api_key(
+        &mut self,
+        api_key: String,
+        workspace_scope: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<Option<SharedString>>> {
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .deepseek
             .api_url
             .clone();
+        let http_client = self.http_client.clone();
+        let source = workspace_scope.map_or(
+            ApiKeySource::CredentialsStore,
+            ApiKeySource::WorkspaceCredentialsStore,
+        );
+        let credentials_url =
+            CredentialsScope::global(cx).api_key_url(&source, PROVIDER_ID, &api_url);
         cx.spawn(async move |this, cx| {
+            let warning =
+                validate_api_keys(http_client.as_ref(), &ApiKeys::parse(&api_key), |api_key| {
+                    api_key_validation_request(&api_url, api_key)
+                })
+                .await?;
+
             credentials_provider
-                .write_credentials(&api_url, "Bearer", api_key.as_bytes(), &cx)
+                .write_credentials(&credentials_url, "Bearer", api_key.as_bytes(), &cx)
                 .await?;
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
+                match source.workspace() {
+                    Some(workspace) => this
+                        .workspace_api_keys
+                        .set(workspace, Some(ApiKeys::parse(&api_key))),
+                    None => {
+                        this.api_keys = ApiKeys::parse(&api_key);
+                        this.api_key_source = source;
+                    }
+                }
+                this.api_key_status.set_rejected(false, cx);
                 cx.notify();
-            })
+            })?;
+            Ok(warning)
+        })
+    }
+
+    /// Reloads the keys saved for a new `api_url`, carrying the current key over if none was
+    /// saved for it yet.
+    fn update_api_url(&mut self, cx: &mut Context<Self>) {
+        let api_url = AllLanguageModelSettings::get_global(cx)
+            .deepseek
+            .api_url
+            .clone();
+        if self.api_url == api_url {
+            return;
+        }
+        let previous_api_url = std::mem::replace(&mut self.api_url, api_url);
+        self.workspace_api_keys.clear();
+        if self.api_key_source.is_external() {
+            return;
+        }
+
+        let carry_over = carry_over_api_key(
+            &self.api_key_source,
+            PROVIDER_ID,
+            &previous_api_url,
+            &self.api_url,
+            cx,
+        );
+        self.api_keys = ApiKeys::default();
+        cx.spawn(async move |this, cx| {
+            carry_over.await;
+            if let Ok(task) = this.update(cx, |this, cx| this.authenticate(cx)) {
+                task.await.ok();
+            }
         })
+        .detach();
     }
 
     fn authenticate(&self, cx: &mut Context<Self>) -> Task<Result<(), AuthenticateError>> {
@@ -104,23 +223,41 @@ impl State {
             .deepseek
             .api_url
             .clone();
+        let api_settings = AllLanguageModelSettings::get_global(cx)
+            .deepseek
+            .api
+            .clone();
+        let credentials_scope = CredentialsScope::global(cx);
+        let load_workspace_api_keys =
+            self.workspace_api_keys
+                .load_open_workspaces(PROVIDER_ID, &api_url, cx);
         cx.spawn(async move |this, cx| {
-            let (api_key, from_env) = if let Ok(api_key) = std::env::var(DEEPSEEK_API_KEY_VAR) {
-                (api_key, true)
-            } else {
-                let (_, api_key) = credentials_provider
-                    .read_credentials(&api_url, &cx)
-                    .await?
-                    .ok_or(AuthenticateError::CredentialsNotFound)?;
-                (
-                    String::from_utf8(api_key).context("invalid {PROVIDER_NAME} API key")?,
-                    false,
-                )
+            let api_keys = load_api_keys(
+                DEEPSEEK_API_KEY_VAR,
+                &api_settings,
+                credentials_provider,
+                PROVIDER_ID,
+                &api_url,
+                credentials_scope,
+                &cx,
+            )
+            .await;
+            let has_workspace_api_keys = load_workspace_api_keys.await;
+            let (api_keys, source) = match api_keys {
+                Err(AuthenticateError::CredentialsNotFound) if has_workspace_api_keys => {
+                    this.update(cx, |_, cx| cx.notify())?;
+                    return Ok(());
+                }
+                api_keys => api_keys?,
             };
 
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
-                this.api_key_from_env = from_env;
+                this._api_key_refresh_task =
+                    refresh_api_keys(&source, &api_keys, &api_settings, cx, |this, api_keys| {
+                        this.api_keys = api_keys
+                    });
+                this.api_keys = api_keys;
+                this.api_key_source = source;
                 cx.notify();
             })?;
 
@@ -132,23 +269,53 @@ impl State {
 impl DeepSeekLanguageModelProvider {
     pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut App) -> Self {
         let state = cx.new(|cx| State {
-            api_key: None,
-            api_key_from_env: false,
-            _subscription: cx.observe_global::<SettingsStore>(|_this: &mut State, cx| {
+            http_client: http_client.clone(),
+            api_url: AllLanguageModelSettings::get_global(cx)
+                .deepseek
+                .api_url
+                .clone(),
+            api_keys: ApiKeys::default(),
+            api_key_source: ApiKeySource::default(),
+            workspace_api_keys: WorkspaceApiKeys::default(),
+            api_key_status: ApiKeyStatus::new(cx, |state| &mut state.api_key_status),
+            _api_key_refresh_task: None,
+            _subscription: cx.observe_global::<SettingsStore>(|this: &mut State, cx| {
+                this.update_api_url(cx);
                 cx.notify();
             }),
+            _credentials_scope_subscription: cx.observe_global::<CredentialsScope>(
+                |this: &mut State, cx| {
+                    this.workspace_api_keys.clear();
+                    if !this.api_key_source.is_external() {
+                        this.api_keys = ApiKeys::default();
+                        this.api_key_status.set_rejected(false, cx);
+                        this.authenticate(cx).detach();
+                        cx.notify();
+                    }
+                },
+            ),
         });
 
         Self { http_client, state }
     }
 
-    fn create_language_model(&self, model: deepseek::Model) -> Arc<dyn LanguageModel> {
+    fn create_language_model(&self, model: deepseek::Model, cx: &App) -> Arc<dyn LanguageModel> {
+        let supports_thinking = AllLanguageModelSettings::get_global(cx)
+            .deepseek
+            .available_models
+            .iter()
+            .find(|available_model| available_model.name == model.id())
+            .and_then(|available_model| available_model.supports_thinking)
+            .unwrap_or_else(|| model.id() == "deepseek-reasoner");
         Arc::new(DeepSeekLanguageModel {
             id: LanguageModelId::from(model.id().to_string()),
             model,
+            supports_thinking,
             state: self.state.clone(),
             http_client: self.http_client.clone(),
-            request_limiter: RateLimiter::new(4),
+            request_limiter: RateLimiter::new(
+                AllLanguageModelSettings::get_global(cx).max_concurrent_requests(PROVIDER_ID),
+            ),
         }) as Arc<dyn LanguageModel>
     }
 }
@@ -174,12 +341,12 @@ impl LanguageModelProvider for DeepSeekLanguageModelProvider {
         IconName::AiDeepSeek
     }
 
-    fn default_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(deepseek::Model::default()))
+    fn default_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(deepseek::Model::default(), cx))
     }
 
-    fn default_fast_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(deepseek::Model::default_fast()))
+    fn default_fast_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(deepseek::Model::default_fast(), cx))
     }
 
     fn provided_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
@@ -206,7 +373,7 @@ impl LanguageModelProvider for DeepSeekLanguageModelProvider {
 
         models
             .into_values()
-            .map(|model| self.create_language_model(model))
+            .map(|model| self.create_language_model(model, cx))
             .collect()
     }
 
@@ -214,6 +381,10 @@ impl LanguageModelProvider for DeepSeekLanguageModelProvider {
         self.state.read(cx).is_authenticated()
     }
 
+    fn credentials_rejected(&self, cx: &App) -> bool {
+        self.state.read(cx).api_key_status.is_rejected()
+    }
+
     fn authenticate(&self, cx: &mut App) -> Task<Result<(), AuthenticateError>> {
         self.state.update(cx, |state, cx| state.authenticate(cx))
     }
@@ -224,13 +395,15 @@ impl LanguageModelProvider for DeepSeekLanguageModelProvider {
     }
 
     fn reset_credentials(&self, cx: &mut App) -> Task<Result<()>> {
-        self.state.update(cx, |state, cx| state.reset_api_key(cx))
+        self.state
+            .update(cx, |state, cx| state.reset_api_key(None, cx))
     }
 }
 
 pub struct DeepSeekLanguageModel {
     id: LanguageModelId,
     model: deepseek::Model,
+    supports_thinking: bool,
     state: Entity<State>,
     http_client: Arc<dyn HttpClient>,
     request_limiter: RateLimiter,
@@ -239,23 +412,44 @@ pub struct DeepSeekLanguageModel {
 impl DeepSeekLanguageModel {
     fn stream_completion(
         &self,
-        request: deepseek::Request,
+        mut request: deepseek::Request,
+        output_tokens: OutputTokenClamp,
+        usage: KeyUsageRecorder,
+        workspace: Option<WorkspaceScope>,
         cx: &AsyncApp,
     ) -> BoxFuture<'static, Result<BoxStream<'static, Result<deepseek::StreamResponse>>>> {
         let http_client = self.http_client.clone();
-        let Ok((api_key, api_url)) = cx.read_entity(&self.state, |state, cx| {
+        let Ok((api_keys, rotation, api_url)) = cx.read_entity(&self.state, |state, cx| {
             let settings = &AllLanguageModelSettings::get_global(cx).deepseek;
-            (state.api_key.clone(), settings.api_url.clone())
+            (
+                state.workspace_api_keys.for_request(
+                    &state.api_keys,
+                    &state.api_key_status,
+                    &state.api_key_source,
+                    workspace.as_ref(),
+                    PROVIDER_ID,
+                    &settings.api_url,
+                    cx,
+                ),
+                settings.api.api_key_rotation,
+                settings.api_url.clone(),
+            )
         }) else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
 
         let future = self.request_limiter.stream(async move {
-            let api_key = api_key.context("Missing DeepSeek API Key")?;
-            let request =
-                deepseek::stream_completion(http_client.as_ref(), &api_url, &api_key, request);
-            let response = request.await?;
-            Ok(response)
+            let api_keys = api_keys.await;
+            if let Some(max_tokens) = request.max_tokens {
+                request.max_tokens = Some(output_tokens.apply(max_tokens).await);
+            }
+            let (http_client, api_url, request) = (http_client.as_ref(), &api_url, &request);
+            api_keys
+                .with_rotation(rotation, "DeepSeek", &usage, |api_key| async move {
+                    deepseek::stream_completion(http_client, api_url, &api_key, request.clone())
+                        .await
+                })
+                .await
         });
 
         async move { Ok(future.await?.boxed()) }.boxed()
@@ -291,10 +485,28 @@ impl LanguageModel for DeepSeekLanguageModel {
         false
     }
 
+    fn supports_thinking(&self) -> bool {
+        self.supports_thinking
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        // The reasoner doesn't support JSON output.
+        if self.model.id() == "deepseek-reasoner" {
+            StructuredOutput::Unsupported
+        } else {
+            StructuredOutput::JsonObject
+        }
+    }
+
     fn telemetry_id(&self) -> String {
         format!("deepseek/{}", self.model.id())
     }
 
+    fn price(&self, cx: &App) -> Option<ModelPrice> {
+        let settings = AllLanguageModelSettings::get_global(cx);
+        model_price(settings, PROVIDER_ID, &self.id.0)
+    }
+
     fn max_token_count(&self) -> usize {
         self.model.max_token_count()
     }
@@ -339,38 +551,100 @@ impl LanguageModel for DeepSeekLanguageModel {
             BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
         >,
     > {
+        let model_id = self.id();
+        let sampling = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings
+                .deepseek
+                .available_models
+                .iter()
+                .find(|model| model_id.0 == model.name)
+                .map(|model| model.sampling)
+        })
+        .flatten()
+        .unwrap_or_default();
+        let system_prompt = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings.deepseek.api.system_prompt.clone()
+        })
+        .unwrap_or_default();
+        let request = system_prompt.apply(sampling.apply(request));
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
+        let moderation = check_moderation(&request, cx);
+        let output_tokens = OutputTokenClamp::new(self, &request, cx);
+        let workspace = request.workspace.clone();
         let request = into_deepseek(
             request,
             self.model.id().to_string(),
             self.max_output_tokens(),
         );
-        let stream = self.stream_completion(request, cx);
-
-        async move {
-            let stream = stream.await?;
-            Ok(stream
-                .map(|result| {
-                    result
-                        .and_then(|response| {
-                            response
-                                .choices
-                                .first()
-                                .context("Empty response")
-                                .map(|choice| {
-                                    choice
-                                        .delta
-                                        .content
-                                        .clone()
-                                        .unwrap_or_default()
-                                        .map(LanguageModelCompletionEvent::Text)
-                                })
-                        })
-                        .map_err(LanguageModelCompletionError::Other)
-                })
-                .boxed())
+        let usage =
+            KeyUsageRecorder::new(PROVIDER_ID, self.id.0.to_string(), workspace.clone(), cx);
+        let timeouts = StreamTimeouts::new(|settings| &settings.deepseek.api, cx);
+        let spend_limits = check_spend_limits(PROVIDER_ID, cx);
+        let stream = self.stream_completion(request, output_tokens, usage.clone(), workspace, cx);
+
+        request_log.track(
+            async move {
+                spend_limits?;
+                moderation.await?;
+                let stream = stream.await?;
+                let events = stream.flat_map(|result| {
+                    futures::stream::iter(match result {
+                        Ok(response) => map_deepseek_response(response),
+                        Err(error) => vec![Err(LanguageModelCompletionError::Other(error))],
+                    })
+                });
+                Ok(usage.track(
+                    timeouts.apply(estimate_live_usage(normalize_reasoning(events.boxed()))),
+                ))
+            }
+            .boxed(),
+        )
+    }
+}
+
+fn map_deepseek_response(
+    response: deepseek::StreamResponse,
+) -> Vec<Result<LanguageModelCompletionEvent, LanguageModelCompletionError>> {
+    let mut events = Vec::new();
+    if let Some(usage) = response.usage {
+        events.push(Ok(LanguageModelCompletionEvent::UsageUpdate(
+            language_model::TokenUsage {
+                input_tokens: usage
+                    .prompt_tokens
+                    .saturating_sub(usage.prompt_cache_hit_tokens),
+                output_tokens: usage.completion_tokens,
+                cache_creation_input_tokens: 0,
+                cache_read_input_tokens: usage.prompt_cache_hit_tokens,
+                reasoning_tokens: usage
+                    .completion_tokens_details
+                    .as_ref()
+                    .map_or(0, |details| details.reasoning_tokens),
+            },
+        )));
+    }
+    match response.choices.first() {
+        Some(choice) => {
+            if let Some(reasoning) = choice
+                .delta
+                .reasoning_content
+                .clone()
+                .filter(|text| !text.is_empty())
+            {
+                events.push(Ok(LanguageModelCompletionEvent::Thinking {
+                    text: reasoning,
+                    signature: None,
+                }));
+            }
+            events.push(Ok(LanguageModelCompletionEvent::Text(
+                choice.delta.content.clone().unwrap_or_default(),
+            )));
         }
-        .boxed()
+        None if events.is_empty() => events.push(Err(LanguageModelCompletionError::Other(
+            anyhow!("Empty response"),
+        ))),
+        None => {}
     }
+    events
 }
 
 pub fn into_deepseek(
@@ -379,73 +653,48 @@ pub fn into_deepseek(
     max_output_tokens: Option<u32>,
 ) -> deepseek::Request {
     let is_reasoner = model == "deepseek-reasoner";
-
-    let len = request.messages.len();
-    let merged_messages =
-        request
-            .messages
-            .into_iter()
-            .fold(Vec::with_capacity(len), |mut acc, msg| {
-                let role = msg.role;
-                let content = msg.string_contents();
-
-                if is_reasoner {
-                    if let Some(last_msg) = acc.last_mut() {
-                        match (last_msg, role) {
-                            (deepseek::RequestMessage::User { content: last }, Role::User) => {
-                                last.push(' ');
-                                last.push_str(&content);
-                                return acc;
-                            }
-
-                            (
-                                deepseek::RequestMessage::Assistant {
-                                    content: last_content,
-                                    ..
-                                },
-                                Role::Assistant,
-                            ) => {
-                                *last_content = last_content
-                                    .take()
-                                    .map(|c| {
-                                        let mut s =
-                                            String::with_capacity(c.len() + content.len() + 1);
-                                        s.push_str(&c);
-                                        s.push(' ');
-                                        s.push_str(&content);
-                                        s
-                                    })
-                                    .or(Some(content));
-
-                                return acc;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-
-                acc.push(match role {
-                    Role::User => deepseek::RequestMessage::User { content },
-                    Role::Assistant => deepseek::RequestMessage::Assistant {
-                        content: Some(content),
-                        tool_calls: Vec::new(),
-                    },
-                    Role::System => deepseek::RequestMessage::System { content },
-                });
-                acc
-            });
+    let json_object = request.response_schema.is_some() && !is_reasoner;
+
+    // The reasoner rejects conversations that don't alternate between the user and the
+    // assistant.
+    let constraints = if is_reasoner {
+        MessageConstraints::STRICT
+    } else {
+        MessageConstraints::default()
+    };
+    let messages = normalize_messages(request.messages, constraints)
+        .into_iter()
+        .map(|message| {
+            let content = message.string_contents();
+            match message.role {
+                Role::User => deepseek::RequestMessage::User { content },
+                Role::Assistant => deepseek::RequestMessage::Assistant {
+                    content: Some(content),
+                    tool_calls: Vec::new(),
+                },
+                Role::System => deepseek::RequestMessage::System { content },
+            }
+        })
+        .collect();
 
     deepseek::Request {
         model,
-        messages: merged_messages,
+        messages,
         stream: true,
+        stream_options: Some(deepseek::StreamOptions {
+            include_usage: true,
+        }),
         max_tokens: max_output_tokens,
         temperature: if is_reasoner {
             None
         } else {
             request.temperature
         },
-        response_format: None,
+        top_p: request.top_p,
+        frequency_penalty: request.frequency_penalty,
+        presence_penalty: request.presence_penalty,
+        response_format: json_object.then_some(deepseek::ResponseFormat::JsonObject),
+        stop: request.stop,
         tools: request
             .tools
             .into_iter()
@@ -464,6 +713,9 @@ struct ConfigurationView {
     api_key_editor: Entity<Editor>,
     state: Entity<State>,
     load_credentials_task: Option<Task<()>>,
+    validation: ApiKeyValidation,
+    save_scope: ApiKeySaveScope,
+    key_display: ApiKeyDisplay,
 }
 
 impl ConfigurationView {
@@ -501,20 +753,34 @@ impl ConfigurationView {
             api_key_editor,
             state,
             load_credentials_task,
+            validation: ApiKeyValidation::default(),
+            save_scope: ApiKeySaveScope::new(window, cx),
+            key_display: ApiKeyDisplay::default(),
         }
     }
 
-    fn save_api_key(&mut self, _: &menu::Confirm, _window: &mut Window, cx: &mut Context<Self>) {
+    fn save_api_key(&mut self, _: &menu::Confirm, window: &mut Window, cx: &mut Context<Self>) {
         let api_key = self.api_key_editor.read(cx).text(cx);
         if api_key.is_empty() {
             return;
         }
 
+        self.validation = ApiKeyValidation::Validating;
+        let workspace_scope = self.save_scope.workspace_scope();
         let state = self.state.clone();
-        cx.spawn(async move |_, cx| {
-            state
-                .update(cx, |state, cx| state.set_api_key(api_key, cx))?
-                .await
+        cx.spawn_in(window, async move |this, cx| {
+            let result = state
+                .update(cx, |state, cx| {
+                    state.set_api_key(api_key, workspace_scope, cx)
+                })?
+                .await;
+            this.update(cx, |this, cx| {
+                this.validation = ApiKeyValidation::from_result(&result);
+                if result.is_ok() {
+                    this.key_display.finish_replacing();
+                }
+                cx.notify();
+            })
         })
         .detach_and_log_err(cx);
 
@@ -525,9 +791,14 @@ impl ConfigurationView {
         self.api_key_editor
             .update(cx, |editor, cx| editor.set_text("", window, cx));
 
+        let workspace = self.api_keys(cx).1.workspace().cloned();
         let state = self.state.clone();
-        cx.spawn(async move |_, cx| state.update(cx, |state, cx| state.reset_api_key(cx))?.await)
-            .detach_and_log_err(cx);
+        cx.spawn(async move |_, cx| {
+            state
+                .update(cx, |state, cx| state.reset_api_key(workspace, cx))?
+                .await
+        })
+        .detach_and_log_err(cx);
 
         cx.notify();
     }
@@ -560,14 +831,31 @@ impl ConfigurationView {
         )
     }
 
+    /// The keys that the view shows, which are the workspace's own if it has any.
+    fn api_keys(&self, cx: &App) -> (ApiKeys, ApiKeySource) {
+        let state = self.state.read(cx);
+        state.workspace_api_keys.for_display(
+            &state.api_keys,
+            &state.api_key_source,
+            self.save_scope.workspace(),
+        )
+    }
+
     fn should_render_editor(&self, cx: &mut Context<Self>) -> bool {
-        !self.state.read(cx).is_authenticated()
+        !self.state.read(cx).is_authenticated() || self.key_display.is_replacing()
     }
 }
 
 impl Render for ConfigurationView {
     fn render(&mut self, _window: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let env_var_set = self.state.read(cx).api_key_from_env;
+        let (api_keys, api_key_source) = self.api_keys(cx);
+        let rejected = self.state.read(cx).api_key_status.is_rejected();
+        let env_var = AllLanguageModelSettings::get_global(cx)
+            .deepseek
+            .api
+            .env_var(DEEPSEEK_API_KEY_VAR)
+            .to_string();
+        let key_is_external = api_key_source.is_external();
 
         if self.load_credentials_task.is_some() {
             div().child(Label::new("Loading credentials...")).into_any()
@@ -599,13 +887,16 @@ impl Render for ConfigurationView {
                         .rounded_sm()
                         .child(self.render_api_key_editor(cx)),
                 )
+                .children(self.save_scope.render(cx, |this| &mut this.save_scope))
+                .children(
+                    self.key_display
+                        .render_cancel(cx, |this| &mut this.key_display),
+                )
+                .children(self.validation.render())
                 .child(
-                    Label::new(format!(
-                        "Or set the {} environment variable.",
-                        DEEPSEEK_API_KEY_VAR
-                    ))
-                    .size(LabelSize::Small)
-                    .color(Color::Muted),
+                    Label::new(format!("Or set the {env_var} environment variable."))
+                        .size(LabelSize::Small)
+                        .color(Color::Muted),
                 )
                 .into_any()
         } else {
@@ -621,11 +912,14 @@ impl Render for ConfigurationView {
                     h_flex()
                         .gap_1()
                         .child(Icon::new(IconName::Check).color(Color::Success))
-                        .child(Label::new(if env_var_set {
-                            format!("API key set in {}", DEEPSEEK_API_KEY_VAR)
-                        } else {
-                            "API key configured".to_string()
-                        })),
+                        .child(self.key_display.render(
+                            PROVIDER_ID,
+                            &api_keys,
+                            rejected,
+                            &api_key_source,
+                            cx,
+                            |this| &mut this.key_display,
+                        )),
                 )
                 .child(
                     Button::new("reset-key", "Reset Key")
@@ -633,7 +927,7 @@ impl Render for ConfigurationView {
                         .icon(Some(IconName::Trash))
                         .icon_size(IconSize::Small)
                         .icon_position(IconPosition::Start)
-                        .disabled(env_var_set)
+                        .disabled(key_is_external)
                         .on_click(
                             cx.listener(|this, _, window, cx| this.reset_api_key(window, cx)),
                         ),
@@ -2,23 +2,24 @@ use anyhow::{Context as _, Result, anyhow};
 use collections::BTreeMap;
 use credentials_provider::CredentialsProvider;
 use editor::{Editor, EditorElement, EditorStyle};
-use futures::{FutureExt, Stream, StreamExt, future::BoxFuture};
+use futures::{FutureExt, Stream, StreamExt, channel::mpsc, future::BoxFuture};
 use google_ai::{
     FunctionDeclaration, GenerateContentResponse, Part, SystemInstruction, UsageMetadata,
 };
 use gpui::{
-    AnyView, App, AsyncApp, Context, Entity, FontStyle, Subscription, Task, TextStyle, WhiteSpace,
+    AnyView, App, AsyncApp, Context, Entity, FontStyle, ImageFormat, Subscription, Task, TextStyle,
+    WhiteSpace,
 };
-use http_client::HttpClient;
+use http_client::{HttpClient, http};
 use language_model::{
-    AuthenticateError, LanguageModelCompletionError, LanguageModelCompletionEvent,
-    LanguageModelToolChoice, LanguageModelToolSchemaFormat, LanguageModelToolUse,
-    LanguageModelToolUseId, MessageContent, StopReason,
+    AuthenticateError, Citation, DocumentLimits, ImageLimits, LanguageModelCompletionError,
+    LanguageModelCompletionEvent, LanguageModelToolChoice, LanguageModelToolSchemaFormat,
+    LanguageModelToolUse, LanguageModelToolUseId, MessageContent, StopReason, StructuredOutput,
 };
 use language_model::{
     LanguageModel, LanguageModelId, LanguageModelName, LanguageModelProvider,
     LanguageModelProviderId, LanguageModelProviderName, LanguageModelProviderState,
-    LanguageModelRequest, RateLimiter, Role,
+    LanguageModelRequest, LanguageModelVideo, ModelPrice, RateLimiter, Role,
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -33,16 +34,53 @@ use theme::ThemeSettings;
 use ui::{Icon, IconName, List, Tooltip, prelude::*};
 use util::ResultExt;
 
-use crate::AllLanguageModelSettings;
+use crate::api_key::{
+    ApiKeyDisplay, ApiKeyRotation, ApiKeySaveScope, ApiKeySource, ApiKeyStatus, ApiKeyValidation,
+    ApiKeys, CredentialsScope, WorkspaceApiKeys, WorkspaceScope, carry_over_api_key, load_api_keys,
+    refresh_api_keys, validate_api_keys,
+};
+use crate::key_usage::KeyUsageRecorder;
+use crate::moderation::check_moderation;
+use crate::pricing::model_price;
+use crate::request_log::RequestLogRecorder;
+use crate::spend_limits::check_spend_limits;
+use crate::stream_timeouts::StreamTimeouts;
 use crate::ui::InstructionListItem;
+use crate::{AllLanguageModelSettings, ProviderApiSettings, ReasoningSettings, SamplingParameters};
+use adc::ApplicationDefaultCredentials;
+use context_cache::ContextCaches;
+pub use context_cache::ContextCachingSettings;
+
+pub(crate) const PROVIDER_ID: &str = "google";
+pub(crate) const PROVIDER_NAME: &str = "Google AI";
+
+pub(crate) fn api_key_validation_request(api_url: &str, api_key: &str) -> http::request::Builder {
+    http::Request::builder().uri(format!("{api_url}/v1beta/models?key={api_key}"))
+}
 
-const PROVIDER_ID: &str = "google";
-const PROVIDER_NAME: &str = "Google AI";
+mod adc;
+mod context_cache;
+mod files;
 
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct GoogleSettings {
     pub api_url: String,
+    pub api: ProviderApiSettings,
+    pub authentication: GoogleAuthentication,
     pub available_models: Vec<AvailableModel>,
+    pub context_caching: ContextCachingSettings,
+}
+
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum GoogleAuthentication {
+    /// Use an API key created in Google AI Studio.
+    #[default]
+    ApiKey,
+    /// Use Application Default Credentials: the file named by `GOOGLE_APPLICATION_CREDENTIALS`,
+    /// the credentials from `gcloud auth application-default login`, or the metadata server
+    /// when running on Google Cloud.
+    ApplicationDefaultCredentials,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -50,90 +88,261 @@ pub struct AvailableModel {
     name: String,
     display_name: Option<String>,
     max_tokens: usize,
+    /// How much the model reasons before it responds.
+    #[serde(default)]
+    reasoning: ReasoningSettings,
+    /// Sampling parameters for requests to this model.
+    #[serde(flatten)]
+    sampling: SamplingParameters,
+    /// Whether the model can search the web with Google Search, citing the pages its response is
+    /// grounded on.
+    #[serde(default)]
+    google_search: bool,
+    /// Whether the model can write and run Python code with Gemini's code execution tool.
+    #[serde(default)]
+    code_execution: bool,
+    /// Whether the model can call tools.
+    ///
+    /// Default: true
+    #[serde(default)]
+    supports_tools: Option<bool>,
+    /// Whether the model takes images as input, so that attached images are sent to it.
+    ///
+    /// Default: true
+    #[serde(default)]
+    supports_images: Option<bool>,
+    /// Whether the model thinks before it responds. The `reasoning` budget is only sent to models
+    /// that do, since other models reject it.
+    ///
+    /// Default: whether the model is a Gemini 2.5 model, or `reasoning` is set
+    #[serde(default)]
+    supports_thinking: Option<bool>,
+    /// Whether the model can call several tools in one response.
+    ///
+    /// Default: true
+    #[serde(default)]
+    supports_parallel_tool_calls: Option<bool>,
 }
 
 pub struct GoogleLanguageModelProvider {
     http_client: Arc<dyn HttpClient>,
     state: gpui::Entity<State>,
+    context_caches: ContextCaches,
 }
 
 pub struct State {
-    api_key: Option<String>,
-    api_key_from_env: bool,
+    http_client: Arc<dyn HttpClient>,
+    api_url: String,
+    api_keys: ApiKeys,
+    api_key_source: ApiKeySource,
+    workspace_api_keys: WorkspaceApiKeys,
+    api_key_status: ApiKeyStatus,
+    application_default_credentials: Option<Arc<ApplicationDefaultCredentials>>,
+    authentication: GoogleAuthentication,
+    _api_key_refresh_task: Option<Task<()>>,
     _subscription: Subscription,
+    _credentials_scope_subscription: Subscription,
 }
 
 const GOOGLE_AI_API_KEY_VAR: &str = "GOOGLE_AI_API_KEY";
 
 impl State {
     fn is_authenticated(&self) -> bool {
-        self.api_key.is_some()
+        match self.authentication {
+            GoogleAuthentication::ApiKey => {
+                !self.api_keys.is_empty() || self.workspace_api_keys.has_any()
+            }
+            GoogleAuthentication::ApplicationDefaultCredentials => {
+                self.application_default_credentials.is_some()
+            }
+        }
+    }
+
+    fn authorization(&self) -> GoogleAuthorization {
+        match (
+            self.authentication,
+            self.application_default_credentials.clone(),
+        ) {
+            (GoogleAuthentication::ApplicationDefaultCredentials, Some(credentials)) => {
+                GoogleAuthorization::ApplicationDefaultCredentials(credentials)
+            }
+            _ => GoogleAuthorization::ApiKeys(self.api_key_status.track(&self.api_keys)),
+        }
     }
 
-    fn reset_api_key(&self, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn reset_api_key(
+        &self,
+        workspace: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<()>> {
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .google
             .api_url
             .clone();
+        if let Some(workspace) = workspace {
+            let task = self
+                .workspace_api_keys
+                .reset(&workspace, PROVIDER_ID, &api_url, cx);
+            cx.notify();
+            return task;
+        }
+        let credentials_scope = CredentialsScope::global(cx);
+        let credentials_urls = [
+            credentials_scope.api_key_url(&self.api_key_source, PROVIDER_ID, &api_url),
+            credentials_scope.legacy_api_key_url(&self.api_key_source, &api_url),
+        ];
         cx.spawn(async move |this, cx| {
-            credentials_provider
-                .delete_credentials(&api_url, &cx)
-                .await
-                .log_err();
+            for credentials_url in &credentials_urls {
+                credentials_provider
+                    .delete_credentials(credentials_url, &cx)
+                    .await
+                    .log_err();
+            }
             this.update(cx, |this, cx| {
-                this.api_key = None;
-                this.api_key_from_env = false;
+                this.api_keys = ApiKeys::default();
+                this.api_key_status.set_rejected(false, cx);
+                this.api_key_source = ApiKeySource::CredentialsStore;
+                this._api_key_refresh_task = None;
                 cx.notify();
             })
         })
     }
 
-    fn set_api_key(&mut self, api_key: String, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn set_api_key(
+        &mut self,
+        api_key: String,
+        workspace_scope: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<Option<SharedString>>> {
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .google
             .api_url
             .clone();
+        let http_client = self.http_client.clone();
+        let source = workspace_scope.map_or(
+            ApiKeySource::CredentialsStore,
+            ApiKeySource::WorkspaceCredentialsStore,
+        );
+        let credentials_url =
+            CredentialsScope::global(cx).api_key_url(&source, PROVIDER_ID, &api_url);
         cx.spawn(async move |this, cx| {
+            let warning =
+                validate_api_keys(http_client.as_ref(), &ApiKeys::parse(&api_key), |api_key| {
+                    api_key_validation_request(&api_url, api_key)
+                })
+                .await?;
+
             credentials_provider
-                .write_credentials(&api_url, "Bearer", api_key.as_bytes(), &cx)
+                .write_credentials(&credentials_url, "Bearer", api_key.as_bytes(), &cx)
                 .await?;
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
+                match source.workspace() {
+                    Some(workspace) => this
+                        .workspace_api_keys
+                        .set(workspace, Some(ApiKeys::parse(&api_key))),
+                    None => {
+                        this.api_keys = ApiKeys::parse(&api_key);
+                        this.api_key_source = source;
+                    }
+                }
+                this.api_key_status.set_rejected(false, cx);
                 cx.notify();
-            })
+            })?;
+            Ok(warning)
         })
     }
 
+    /// Reloads the keys saved for a new `api_url`, carrying the current key over if none was
+    /// saved for it yet.
+    fn update_api_url(&mut self, cx: &mut Context<Self>) {
+        let api_url = AllLanguageModelSettings::get_global(cx)
+            .google
+            .api_url
+            .clone();
+        if self.api_url == api_url {
+            return;
+        }
+        let previous_api_url = std::mem::replace(&mut self.api_url, api_url);
+        self.workspace_api_keys.clear();
+        if self.api_key_source.is_external() {
+            return;
+        }
+
+        let carry_over = carry_over_api_key(
+            &self.api_key_source,
+            PROVIDER_ID,
+            &previous_api_url,
+            &self.api_url,
+            cx,
+        );
+        self.api_keys = ApiKeys::default();
+        cx.spawn(async move |this, cx| {
+            carry_over.await;
+            if let Ok(task) = this.update(cx, |this, cx| this.authenticate(cx)) {
+                task.await.ok();
+            }
+        })
+        .detach();
+    }
+
     fn authenticate(&self, cx: &mut Context<Self>) -> Task<Result<(), AuthenticateError>> {
         if self.is_authenticated() {
             return Task::ready(Ok(()));
         }
 
+        if self.authentication == GoogleAuthentication::ApplicationDefaultCredentials {
+            let http_client = self.http_client.clone();
+            return cx.spawn(async move |this, cx| {
+                let credentials =
+                    ApplicationDefaultCredentials::discover(http_client.as_ref()).await?;
+                this.update(cx, |this, cx| {
+                    this.application_default_credentials = Some(Arc::new(credentials));
+                    cx.notify();
+                })?;
+                Ok(())
+            });
+        }
+
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .google
             .api_url
             .clone();
+        let api_settings = AllLanguageModelSettings::get_global(cx).google.api.clone();
+        let credentials_scope = CredentialsScope::global(cx);
+        let load_workspace_api_keys =
+            self.workspace_api_keys
+                .load_open_workspaces(PROVIDER_ID, &api_url, cx);
 
         cx.spawn(async move |this, cx| {
-            let (api_key, from_env) = if let Ok(api_key) = std::env::var(GOOGLE_AI_API_KEY_VAR) {
-                (api_key, true)
-            } else {
-                let (_, api_key) = credentials_provider
-                    .read_credentials(&api_url, &cx)
-                    .await?
-                    .ok_or(AuthenticateError::CredentialsNotFound)?;
-                (
-                    String::from_utf8(api_key).context("invalid {PROVIDER_NAME} API key")?,
-                    false,
-                )
+            let api_keys = load_api_keys(
+                GOOGLE_AI_API_KEY_VAR,
+                &api_settings,
+                credentials_provider,
+                PROVIDER_ID,
+                &api_url,
+                credentials_scope,
+                &cx,
+            )
+            .await;
+            let has_workspace_api_keys = load_workspace_api_keys.await;
+            let (api_keys, source) = match api_keys {
+                Err(AuthenticateError::CredentialsNotFound) if has_workspace_api_keys => {
+                    this.update(cx, |_, cx| cx.notify())?;
+                    return Ok(());
+                }
+                api_keys => api_keys?,
             };
 
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
-                this.api_key_from_env = from_env;
+                this._api_key_refresh_task =
+                    refresh_api_keys(&source, &api_keys, &api_settings, cx, |this, api_keys| {
+                        this.api_keys = api_keys
+                    });
+                this.api_keys = api_keys;
+                this.api_key_source = source;
                 cx.notify();
             })?;
 
@@ -145,23 +354,93 @@ impl State {
 impl GoogleLanguageModelProvider {
     pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut App) -> Self {
         let state = cx.new(|cx| State {
-            api_key: None,
-            api_key_from_env: false,
-            _subscription: cx.observe_global::<SettingsStore>(|_, cx| {
+            http_client: http_client.clone(),
+            api_url: AllLanguageModelSettings::get_global(cx)
+                .google
+                .api_url
+                .clone(),
+            api_keys: ApiKeys::default(),
+            api_key_source: ApiKeySource::default(),
+            workspace_api_keys: WorkspaceApiKeys::default(),
+            api_key_status: ApiKeyStatus::new(cx, |state| &mut state.api_key_status),
+            application_default_credentials: None,
+            authentication: AllLanguageModelSettings::get_global(cx)
+                .google
+                .authentication,
+            _api_key_refresh_task: None,
+            _subscription: cx.observe_global::<SettingsStore>(|this: &mut State, cx| {
+                let authentication = AllLanguageModelSettings::get_global(cx)
+                    .google
+                    .authentication;
+                if this.authentication != authentication {
+                    this.authentication = authentication;
+                    this.application_default_credentials = None;
+                    this.authenticate(cx).detach();
+                }
+                this.update_api_url(cx);
                 cx.notify();
             }),
+            _credentials_scope_subscription: cx.observe_global::<CredentialsScope>(
+                |this: &mut State, cx| {
+                    this.workspace_api_keys.clear();
+                    if !this.api_key_source.is_external() {
+                        this.api_keys = ApiKeys::default();
+                        this.api_key_status.set_rejected(false, cx);
+                        this.authenticate(cx).detach();
+                        cx.notify();
+                    }
+                },
+            ),
         });
 
-        Self { http_client, state }
+        Self {
+            http_client,
+            state,
+            context_caches: ContextCaches::default(),
+        }
+    }
+
+    /// The provider's state, whose credentials other Gemini API services share.
+    pub(crate) fn state(&self) -> Entity<State> {
+        self.state.clone()
     }
 
-    fn create_language_model(&self, model: google_ai::Model) -> Arc<dyn LanguageModel> {
+    fn create_language_model(&self, model: google_ai::Model, cx: &App) -> Arc<dyn LanguageModel> {
+        let available_model = AllLanguageModelSettings::get_global(cx)
+            .google
+            .available_models
+            .iter()
+            .find(|available_model| available_model.name == model.id());
+        let supports_tools = available_model
+            .and_then(|available_model| available_model.supports_tools)
+            .unwrap_or(true);
+        let supports_images = available_model
+            .and_then(|available_model| available_model.supports_images)
+            .unwrap_or(true);
+        let supports_thinking = available_model
+            .and_then(|available_model| available_model.supports_thinking)
+            .unwrap_or_else(|| {
+                model.id().contains("2.5")
+                    || available_model.is_some_and(|available_model| {
+                        available_model.reasoning.budget_tokens.is_some()
+                    })
+            });
+        let supports_parallel_tool_calls = available_model
+            .and_then(|available_model| available_model.supports_parallel_tool_calls)
+            .unwrap_or(true);
         Arc::new(GoogleLanguageModel {
             id: LanguageModelId::from(model.id().to_string()),
             model,
+            supports_tools,
+            supports_images,
+            supports_thinking,
+            supports_parallel_tool_calls,
             state: self.state.clone(),
             http_client: self.http_client.clone(),
-            request_limiter: RateLimiter::new(4),
+            context_caches: self.context_caches.clone(),
+            request_limiter: RateLimiter::new(
+                AllLanguageModelSettings::get_global(cx).max_concurrent_requests(PROVIDER_ID),
+            ),
         })
     }
 }
@@ -187,12 +466,12 @@ impl LanguageModelProvider for GoogleLanguageModelProvider {
         IconName::AiGoogle
     }
 
-    fn default_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(google_ai::Model::default()))
+    fn default_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(google_ai::Model::default(), cx))
     }
 
-    fn default_fast_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(google_ai::Model::default_fast()))
+    fn default_fast_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(google_ai::Model::default_fast(), cx))
     }
 
     fn provided_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
@@ -222,15 +501,7 @@ impl LanguageModelProvider for GoogleLanguageModelProvider {
 
         models
             .into_values()
-            .map(|model| {
-                Arc::new(GoogleLanguageModel {
-                    id: LanguageModelId::from(model.id().to_string()),
-                    model,
-                    state: self.state.clone(),
-                    http_client: self.http_client.clone(),
-                    request_limiter: RateLimiter::new(4),
-                }) as Arc<dyn LanguageModel>
-            })
+            .map(|model| self.create_language_model(model, cx))
             .collect()
     }
 
@@ -238,6 +509,10 @@ impl LanguageModelProvider for GoogleLanguageModelProvider {
         self.state.read(cx).is_authenticated()
     }
 
+    fn credentials_rejected(&self, cx: &App) -> bool {
+        self.state.read(cx).api_key_status.is_rejected()
+    }
+
     fn authenticate(&self, cx: &mut App) -> Task<Result<(), AuthenticateError>> {
         self.state.update(cx, |state, cx| state.authenticate(cx))
     }
@@ -248,45 +523,216 @@ impl LanguageModelProvider for GoogleLanguageModelProvider {
     }
 
     fn reset_credentials(&self, cx: &mut App) -> Task<Result<()>> {
-        self.state.update(cx, |state, cx| state.reset_api_key(cx))
+        self.state
+            .update(cx, |state, cx| state.reset_api_key(None, cx))
+    }
+}
+
+/// The credentials used for a request, captured from the provider's state.
+enum GoogleAuthorization {
+    ApiKeys(ApiKeys),
+    ApplicationDefaultCredentials(Arc<ApplicationDefaultCredentials>),
+}
+
+impl GoogleAuthorization {
+    async fn current(&self, http_client: &dyn HttpClient) -> Result<google_ai::Authorization> {
+        match self {
+            Self::ApiKeys(api_keys) => Ok(google_ai::Authorization::ApiKey(
+                api_keys
+                    .current()
+                    .context("Missing Google API key")?
+                    .to_string(),
+            )),
+            Self::ApplicationDefaultCredentials(credentials) => {
+                credentials.authorization(http_client).await
+            }
+        }
+    }
+}
+
+/// The Google provider's credentials, for the Gemini API's other services.
+pub(crate) struct GoogleAuth {
+    pub http_client: Arc<dyn HttpClient>,
+    pub api_url: String,
+    authorization: GoogleAuthorization,
+    rotation: ApiKeyRotation,
+}
+
+impl GoogleAuth {
+    pub fn read(state: &Entity<State>, cx: &AsyncApp) -> Result<Self> {
+        cx.read_entity(state, Self::new)
+    }
+
+    pub fn new(state: &State, cx: &App) -> Self {
+        let settings = &AllLanguageModelSettings::get_global(cx).google;
+        Self {
+            http_client: state.http_client.clone(),
+            api_url: settings.api_url.clone(),
+            authorization: state.authorization(),
+            rotation: settings.api.api_key_rotation,
+        }
+    }
+
+    /// The authorization for the next request, along with its API key when it uses one.
+    pub async fn next_authorization(&self) -> Result<(google_ai::Authorization, Option<String>)> {
+        match &self.authorization {
+            GoogleAuthorization::ApiKeys(api_keys) => {
+                let api_key = api_keys
+                    .next_for_request(self.rotation)
+                    .context("Missing Google API key")?;
+                Ok((
+                    google_ai::Authorization::ApiKey(api_key.clone()),
+                    Some(api_key),
+                ))
+            }
+            GoogleAuthorization::ApplicationDefaultCredentials(credentials) => Ok((
+                credentials.authorization(self.http_client.as_ref()).await?,
+                None,
+            )),
+        }
+    }
+
+    pub fn record_error(&self, api_key: Option<&str>, error: &anyhow::Error) {
+        if let (GoogleAuthorization::ApiKeys(api_keys), Some(api_key)) =
+            (&self.authorization, api_key)
+        {
+            api_keys.record_error(api_key, error);
+        }
     }
 }
 
 pub struct GoogleLanguageModel {
     id: LanguageModelId,
     model: google_ai::Model,
+    supports_tools: bool,
+    supports_images: bool,
+    supports_thinking: bool,
+    supports_parallel_tool_calls: bool,
     state: gpui::Entity<State>,
     http_client: Arc<dyn HttpClient>,
+    context_caches: ContextCaches,
     request_limiter: RateLimiter,
 }
 
 impl GoogleLanguageModel {
+    /// Streams a completion of `request`, first uploading `videos` to the Files API and
+    /// reporting their progress to `upload_progress`.
     fn stream_completion(
         &self,
-        request: google_ai::GenerateContentRequest,
+        mut request: google_ai::GenerateContentRequest,
+        videos: Vec<LanguageModelVideo>,
+        upload_progress: mpsc::UnboundedSender<LanguageModelCompletionEvent>,
+        usage: KeyUsageRecorder,
+        workspace: Option<WorkspaceScope>,
         cx: &AsyncApp,
     ) -> BoxFuture<
         'static,
         Result<futures::stream::BoxStream<'static, Result<GenerateContentResponse>>>,
     > {
         let http_client = self.http_client.clone();
+        let context_caches = self.context_caches.clone();
+        let executor = cx.background_executor().clone();
 
-        let Ok((api_key, api_url)) = cx.read_entity(&self.state, |state, cx| {
-            let settings = &AllLanguageModelSettings::get_global(cx).google;
-            (state.api_key.clone(), settings.api_url.clone())
-        }) else {
+        let Ok((authorization, api_keys, rotation, api_url, context_caching)) =
+            cx.read_entity(&self.state, |state, cx| {
+                let settings = &AllLanguageModelSettings::get_global(cx).google;
+                (
+                    state.authorization(),
+                    state.workspace_api_keys.for_request(
+                        &state.api_keys,
+                        &state.api_key_status,
+                        &state.api_key_source,
+                        workspace.as_ref(),
+                        PROVIDER_ID,
+                        &settings.api_url,
+                        cx,
+                    ),
+                    settings.api.api_key_rotation,
+                    settings.api_url.clone(),
+                    settings.context_caching,
+                )
+            })
+        else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
 
         async move {
-            let api_key = api_key.context("Missing Google API key")?;
-            let request = google_ai::stream_generate_content(
-                http_client.as_ref(),
-                &api_url,
-                &api_key,
-                request,
-            );
-            request.await.context("failed to stream completion")
+            match authorization {
+                GoogleAuthorization::ApiKeys(_) => {
+                    let api_keys = api_keys.await;
+                    // Uploaded videos belong to the key's project, so a retry with another key
+                    // uploads them again.
+                    let (http_client, api_url) = (http_client.as_ref(), &api_url);
+                    let (request, videos, upload_progress) = (&request, &videos, &upload_progress);
+                    let (context_caches, executor) = (&context_caches, &executor);
+                    api_keys
+                        .with_rotation(rotation, "Google", &usage, |api_key| async move {
+                            let authorization = google_ai::Authorization::ApiKey(api_key.clone());
+                            let mut request = request.clone();
+                            files::upload_videos(
+                                &mut request,
+                                videos.clone(),
+                                upload_progress,
+                                http_client,
+                                api_url,
+                                &authorization,
+                                executor,
+                            )
+                            .await?;
+                            context_caches
+                                .apply(
+                                    &mut request,
+                                    &api_key,
+                                    context_caching,
+                                    http_client,
+                                    api_url,
+                                    &authorization,
+                                )
+                                .await;
+                            google_ai::stream_generate_content(
+                                http_client,
+                                api_url,
+                                &authorization,
+                                request,
+                            )
+                            .await
+                            .context("failed to stream completion")
+                        })
+                        .await
+                }
+                GoogleAuthorization::ApplicationDefaultCredentials(credentials) => {
+                    let authorization = credentials.authorization(http_client.as_ref()).await?;
+                    files::upload_videos(
+                        &mut request,
+                        videos,
+                        &upload_progress,
+                        http_client.as_ref(),
+                        &api_url,
+                        &authorization,
+                        &executor,
+                    )
+                    .await?;
+                    drop(upload_progress);
+                    context_caches
+                        .apply(
+                            &mut request,
+                            "application_default_credentials",
+                            context_caching,
+                            http_client.as_ref(),
+                            &api_url,
+                            &authorization,
+                        )
+                        .await;
+                    google_ai::stream_generate_content(
+                        http_client.as_ref(),
+                        &api_url,
+                        &authorization,
+                        request,
+                    )
+                    .await
+                    .context("failed to stream completion")
+                }
+            }
         }
         .boxed()
     }
@@ -310,10 +756,26 @@ impl LanguageModel for GoogleLanguageModel {
     }
 
     fn supports_tools(&self) -> bool {
-        true
+        self.supports_tools
     }
 
     fn supports_images(&self) -> bool {
+        self.supports_images
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        GOOGLE_IMAGE_LIMITS
+    }
+
+    fn document_limits(&self) -> Option<DocumentLimits> {
+        Some(GOOGLE_DOCUMENT_LIMITS)
+    }
+
+    fn supports_audio(&self) -> bool {
+        true
+    }
+
+    fn supports_video(&self) -> bool {
         true
     }
 
@@ -321,10 +783,23 @@ impl LanguageModel for GoogleLanguageModel {
         match choice {
             LanguageModelToolChoice::Auto
             | LanguageModelToolChoice::Any
-            | LanguageModelToolChoice::None => true,
+            | LanguageModelToolChoice::None
+            | LanguageModelToolChoice::Tool(_) => self.supports_tools,
         }
     }
 
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.supports_parallel_tool_calls
+    }
+
+    fn supports_thinking(&self) -> bool {
+        self.supports_thinking
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        StructuredOutput::Native
+    }
+
     fn tool_input_format(&self) -> LanguageModelToolSchemaFormat {
         LanguageModelToolSchemaFormat::JsonSchemaSubset
     }
@@ -333,35 +808,52 @@ impl LanguageModel for GoogleLanguageModel {
         format!("google/{}", self.model.id())
     }
 
+    fn price(&self, cx: &App) -> Option<ModelPrice> {
+        let settings = AllLanguageModelSettings::get_global(cx);
+        model_price(settings, PROVIDER_ID, &self.id.0)
+    }
+
     fn max_token_count(&self) -> usize {
         self.model.max_token_count()
     }
 
     fn count_tokens(
         &self,
-        request: LanguageModelRequest,
+        mut request: LanguageModelRequest,
         cx: &App,
     ) -> BoxFuture<'static, Result<usize>> {
         let model_id = self.model.id().to_string();
+        // Videos are estimated from their duration rather than sent to be counted, since large
+        // ones would have to be uploaded first.
+        let mut video_tokens = 0;
+        for message in &mut request.messages {
+            message.content.retain(|content| match content {
+                MessageContent::Video(video) => {
+                    video_tokens += video.estimate_tokens();
+                    false
+                }
+                _ => true,
+            });
+        }
         let request = into_google(request, model_id.clone());
         let http_client = self.http_client.clone();
-        let api_key = self.state.read(cx).api_key.clone();
+        let authorization = self.state.read(cx).authorization();
 
         let settings = &AllLanguageModelSettings::get_global(cx).google;
         let api_url = settings.api_url.clone();
 
         async move {
-            let api_key = api_key.context("Missing Google API key")?;
+            let authorization = authorization.current(http_client.as_ref()).await?;
             let response = google_ai::count_tokens(
                 http_client.as_ref(),
                 &api_url,
-                &api_key,
+                &authorization,
                 google_ai::CountTokensRequest {
                     generate_content_request: request,
                 },
             )
             .await?;
-            Ok(response.total_tokens)
+            Ok(response.total_tokens + video_tokens)
         }
         .boxed()
     }
@@ -379,18 +871,117 @@ impl LanguageModel for GoogleLanguageModel {
             >,
         >,
     > {
-        let request = into_google(request, self.model.id().to_string());
-        let request = self.stream_completion(request, cx);
+        let model_id = self.id();
+        let (sampling, reasoning, google_search, code_execution) =
+            AllLanguageModelSettings::try_read_global(cx, |settings| {
+                settings
+                    .google
+                    .available_models
+                    .iter()
+                    .find(|model| model_id.0 == model.name)
+                    .map(|model| {
+                        (
+                            model.sampling,
+                            model.reasoning,
+                            model.google_search,
+                            model.code_execution,
+                        )
+                    })
+            })
+            .flatten()
+            .unwrap_or_default();
+        let system_prompt = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings.google.api.system_prompt.clone()
+        })
+        .unwrap_or_default();
+        let request = system_prompt.apply(sampling.apply(request));
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
+        let moderation = check_moderation(&request, cx);
+        let videos = files::videos_to_upload(&request);
+        let workspace = request.workspace.clone();
+        let mut request = into_google(request, self.model.id().to_string());
+        if let Some((generation_config, thinking_budget)) = request
+            .generation_config
+            .as_mut()
+            .zip(reasoning.budget_tokens.filter(|_| self.supports_thinking))
+        {
+            generation_config.thinking_config = Some(google_ai::ThinkingConfig { thinking_budget });
+        }
+        if google_search {
+            request.tools.get_or_insert_default().push(google_ai::Tool {
+                function_declarations: Vec::new(),
+                google_search: Some(google_ai::GoogleSearch::default()),
+                code_execution: None,
+            });
+        }
+        if code_execution {
+            request.tools.get_or_insert_default().push(google_ai::Tool {
+                function_declarations: Vec::new(),
+                google_search: None,
+                code_execution: Some(google_ai::CodeExecution::default()),
+            });
+        }
+        let usage =
+            KeyUsageRecorder::new(PROVIDER_ID, self.id.0.to_string(), workspace.clone(), cx);
+        let timeouts = StreamTimeouts::new(|settings| &settings.google.api, cx);
+        let spend_limits = check_spend_limits(PROVIDER_ID, cx);
+        let uploads_videos = !videos.is_empty();
+        let (upload_progress_tx, upload_progress_rx) = mpsc::unbounded();
+        let request = self.stream_completion(
+            request,
+            videos,
+            upload_progress_tx,
+            usage.clone(),
+            workspace,
+            cx,
+        );
         let future = self.request_limiter.stream(async move {
+            spend_limits?;
+            moderation.await?;
+            if !uploads_videos {
+                let response = request
+                    .await
+                    .map_err(|err| LanguageModelCompletionError::Other(anyhow!(err)))?;
+                return Ok(usage
+                    .track(timeouts.apply(GoogleEventMapper::new().map_stream(response).boxed())));
+            }
+
+            // Uploads can take a while, so the completion starts streaming their progress right
+            // away, and the first token timeout only starts once the request is sent.
             let response = request
-                .await
-                .map_err(|err| LanguageModelCompletionError::Other(anyhow!(err)))?;
-            Ok(GoogleEventMapper::new().map_stream(response))
+                .map(move |response| match response {
+                    Ok(response) => {
+                        timeouts.apply(GoogleEventMapper::new().map_stream(response).boxed())
+                    }
+                    Err(error) => futures::stream::once(async move {
+                        Err(LanguageModelCompletionError::Other(error))
+                    })
+                    .boxed(),
+                })
+                .flatten_stream();
+            let events = futures::stream::select(upload_progress_rx.map(Ok), response);
+            Ok(usage.track(events.boxed()))
         });
-        async move { Ok(future.await?.boxed()) }.boxed()
+        request_log.track(async move { Ok(future.await?.boxed()) }.boxed())
     }
 }
 
+/// Gemini scales images down to fit within 3072x3072 pixels, and doesn't take GIFs. Requests can
+/// have up to 3000 images, but inline data is limited to 20 MB per request.
+pub(crate) const GOOGLE_IMAGE_LIMITS: ImageLimits = ImageLimits {
+    max_dimension: 3072,
+    max_bytes: 20 * 1024 * 1024,
+    max_images: Some(3000),
+    max_total_bytes: Some(20 * 1024 * 1024),
+    formats: &[ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Webp],
+};
+
+/// Gemini reads PDFs of up to 1000 pages, but inline data is limited to 20 MB per request.
+pub(crate) const GOOGLE_DOCUMENT_LIMITS: DocumentLimits = DocumentLimits {
+    max_bytes: 20 * 1024 * 1024,
+    max_pages: 1000,
+};
+
 pub fn into_google(
     mut request: LanguageModelRequest,
     model_id: String,
@@ -407,6 +998,11 @@ pub fn into_google(
                         vec![]
                     }
                 }
+                language_model::MessageContent::Document(document) => {
+                    vec![Part::TextPart(google_ai::TextPart {
+                        text: document.to_text(),
+                    })]
+                }
                 language_model::MessageContent::RedactedThinking(_) => vec![],
                 language_model::MessageContent::Image(image) => {
                     vec![Part::InlineDataPart(google_ai::InlineDataPart {
@@ -416,6 +1012,32 @@ pub fn into_google(
                         },
                     })]
                 }
+                language_model::MessageContent::Audio(audio) => {
+                    vec![Part::InlineDataPart(google_ai::InlineDataPart {
+                        inline_data: google_ai::GenerativeContentBlob {
+                            mime_type: audio.format.mime_type().to_string(),
+                            data: audio.source.to_string(),
+                        },
+                    })]
+                }
+                language_model::MessageContent::Pdf(pdf) => {
+                    vec![Part::InlineDataPart(google_ai::InlineDataPart {
+                        inline_data: google_ai::GenerativeContentBlob {
+                            mime_type: "application/pdf".to_string(),
+                            data: pdf.source.to_string(),
+                        },
+                    })]
+                }
+                // Videos too large to send inline are swapped for uploaded files before the
+                // request is sent.
+                language_model::MessageContent::Video(video) => {
+                    vec![Part::InlineDataPart(google_ai::InlineDataPart {
+                        inline_data: google_ai::GenerativeContentBlob {
+                            mime_type: video.mime_type.to_string(),
+                            data: video.source.to_string(),
+                        },
+                    })]
+                }
                 language_model::MessageContent::ToolUse(tool_use) => {
                     vec![Part::FunctionCallPart(google_ai::FunctionCallPart {
                         function_call: google_ai::FunctionCall {
@@ -504,8 +1126,17 @@ pub fn into_google(
             stop_sequences: Some(request.stop),
             max_output_tokens: None,
             temperature: request.temperature.map(|t| t as f64).or(Some(1.0)),
-            top_p: None,
-            top_k: None,
+            top_p: request.top_p.map(|top_p| top_p as f64),
+            top_k: request.top_k.map(|top_k| top_k as usize),
+            seed: request.seed.map(|seed| seed as i32),
+            response_mime_type: request
+                .response_schema
+                .as_ref()
+                .map(|_| "application/json".to_string()),
+            response_schema: request
+                .response_schema
+                .map(|response_schema| response_schema.schema),
+            thinking_config: None,
         }),
         safety_settings: None,
         tools: (request.tools.len() > 0).then(|| {
@@ -519,18 +1150,27 @@ pub fn into_google(
                         parameters: tool.input_schema,
                     })
                     .collect(),
+                google_search: None,
+                code_execution: None,
             }]
         }),
-        tool_config: request.tool_choice.map(|choice| google_ai::ToolConfig {
-            function_calling_config: google_ai::FunctionCallingConfig {
-                mode: match choice {
-                    LanguageModelToolChoice::Auto => google_ai::FunctionCallingMode::Auto,
-                    LanguageModelToolChoice::Any => google_ai::FunctionCallingMode::Any,
-                    LanguageModelToolChoice::None => google_ai::FunctionCallingMode::None,
+        tool_config: request.tool_choice.map(|choice| {
+            let (mode, allowed_function_names) = match choice {
+                LanguageModelToolChoice::Auto => (google_ai::FunctionCallingMode::Auto, None),
+                LanguageModelToolChoice::Any => (google_ai::FunctionCallingMode::Any, None),
+                LanguageModelToolChoice::None => (google_ai::FunctionCallingMode::None, None),
+                LanguageModelToolChoice::Tool(name) => {
+                    (google_ai::FunctionCallingMode::Any, Some(vec![name]))
+                }
+            };
+            google_ai::ToolConfig {
+                function_calling_config: google_ai::FunctionCallingConfig {
+                    mode,
+                    allowed_function_names,
                 },
-                allowed_function_names: None,
-            },
+            }
         }),
+        cached_content: None,
     }
 }
 
@@ -586,6 +1226,9 @@ impl GoogleEventMapper {
                     self.stop_reason = match finish_reason {
                         "STOP" => StopReason::EndTurn,
                         "MAX_TOKENS" => StopReason::MaxTokens,
+                        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" => {
+                            StopReason::ContentFilter
+                        }
                         _ => {
                             log::error!("Unexpected google finish_reason: {finish_reason}");
                             StopReason::EndTurn
@@ -601,6 +1244,7 @@ impl GoogleEventMapper {
                             events.push(Ok(LanguageModelCompletionEvent::Text(text_part.text)))
                         }
                         Part::InlineDataPart(_) => {}
+                        Part::FileDataPart(_) => {}
                         Part::FunctionCallPart(function_call_part) => {
                             wants_to_use_tool = true;
                             let name: Arc<str> = function_call_part.function_call.name.into();
@@ -620,7 +1264,26 @@ impl GoogleEventMapper {
                             )));
                         }
                         Part::FunctionResponsePart(_) => {}
+                        Part::ExecutableCodePart(part) => {
+                            events.push(Ok(LanguageModelCompletionEvent::ExecutableCode {
+                                language: part.executable_code.language,
+                                code: part.executable_code.code,
+                            }))
+                        }
+                        Part::CodeExecutionResultPart(part) => {
+                            events.push(Ok(LanguageModelCompletionEvent::CodeExecutionResult {
+                                success: part.code_execution_result.outcome
+                                    == google_ai::CodeExecutionOutcome::OutcomeOk,
+                                output: part.code_execution_result.output,
+                            }))
+                        }
                     });
+                if let Some(grounding_metadata) = candidate.grounding_metadata {
+                    let citations = grounding_citations(grounding_metadata);
+                    if !citations.is_empty() {
+                        events.push(Ok(LanguageModelCompletionEvent::Citations(citations)));
+                    }
+                }
             }
         }
 
@@ -634,6 +1297,42 @@ impl GoogleEventMapper {
     }
 }
 
+/// The web pages in Gemini's grounding metadata, cited once for each part of the response they
+/// support, or once on their own when they don't support any part in particular.
+fn grounding_citations(metadata: google_ai::GroundingMetadata) -> Vec<Citation> {
+    let mut is_cited = vec![false; metadata.grounding_chunks.len()];
+    let mut citations = Vec::new();
+    for support in &metadata.grounding_supports {
+        for (ix, &chunk_ix) in support.grounding_chunk_indices.iter().enumerate() {
+            let Some(web) = metadata
+                .grounding_chunks
+                .get(chunk_ix)
+                .and_then(|chunk| chunk.web.as_ref())
+            else {
+                continue;
+            };
+            is_cited[chunk_ix] = true;
+            citations.push(Citation {
+                url: web.uri.clone(),
+                title: web.title.clone(),
+                cited_text: support.segment.as_ref().map(|segment| segment.text.clone()),
+                confidence: support.confidence_scores.get(ix).copied(),
+            });
+        }
+    }
+    for (chunk, is_cited) in metadata.grounding_chunks.into_iter().zip(is_cited) {
+        if let Some(web) = chunk.web.filter(|_| !is_cited) {
+            citations.push(Citation {
+                url: web.uri,
+                title: web.title,
+                cited_text: None,
+                confidence: None,
+            });
+        }
+    }
+    citations
+}
+
 pub fn count_google_tokens(
     request: LanguageModelRequest,
     cx: &App,
@@ -685,11 +1384,16 @@ fn update_usage(usage: &mut UsageMetadata, new: &UsageMetadata) {
 }
 
 fn convert_usage(usage: &UsageMetadata) -> language_model::TokenUsage {
+    let reasoning_tokens = usage.thoughts_token_count.unwrap_or(0) as u32;
+    // The prompt token count includes the tokens read from the cache.
+    let cache_read_input_tokens = usage.cached_content_token_count.unwrap_or(0) as u32;
     language_model::TokenUsage {
-        input_tokens: usage.prompt_token_count.unwrap_or(0) as u32,
-        output_tokens: usage.candidates_token_count.unwrap_or(0) as u32,
-        cache_read_input_tokens: usage.cached_content_token_count.unwrap_or(0) as u32,
+        input_tokens: (usage.prompt_token_count.unwrap_or(0) as u32)
+            .saturating_sub(cache_read_input_tokens),
+        output_tokens: usage.candidates_token_count.unwrap_or(0) as u32 + reasoning_tokens,
+        cache_read_input_tokens,
         cache_creation_input_tokens: 0,
+        reasoning_tokens,
     }
 }
 
@@ -697,6 +1401,9 @@ struct ConfigurationView {
     api_key_editor: Entity<Editor>,
     state: gpui::Entity<State>,
     load_credentials_task: Option<Task<()>>,
+    validation: ApiKeyValidation,
+    save_scope: ApiKeySaveScope,
+    key_display: ApiKeyDisplay,
 }
 
 impl ConfigurationView {
@@ -732,6 +1439,9 @@ impl ConfigurationView {
             }),
             state,
             load_credentials_task,
+            validation: ApiKeyValidation::default(),
+            save_scope: ApiKeySaveScope::new(window, cx),
+            key_display: ApiKeyDisplay::default(),
         }
     }
 
@@ -741,11 +1451,22 @@ impl ConfigurationView {
             return;
         }
 
+        self.validation = ApiKeyValidation::Validating;
+        let workspace_scope = self.save_scope.workspace_scope();
         let state = self.state.clone();
-        cx.spawn_in(window, async move |_, cx| {
-            state
-                .update(cx, |state, cx| state.set_api_key(api_key, cx))?
-                .await
+        cx.spawn_in(window, async move |this, cx| {
+            let result = state
+                .update(cx, |state, cx| {
+                    state.set_api_key(api_key, workspace_scope, cx)
+                })?
+                .await;
+            this.update(cx, |this, cx| {
+                this.validation = ApiKeyValidation::from_result(&result);
+                if result.is_ok() {
+                    this.key_display.finish_replacing();
+                }
+                cx.notify();
+            })
         })
         .detach_and_log_err(cx);
 
@@ -756,9 +1477,12 @@ impl ConfigurationView {
         self.api_key_editor
             .update(cx, |editor, cx| editor.set_text("", window, cx));
 
+        let workspace = self.api_keys(cx).1.workspace().cloned();
         let state = self.state.clone();
         cx.spawn_in(window, async move |_, cx| {
-            state.update(cx, |state, cx| state.reset_api_key(cx))?.await
+            state
+                .update(cx, |state, cx| state.reset_api_key(workspace, cx))?
+                .await
         })
         .detach_and_log_err(cx);
 
@@ -790,17 +1514,60 @@ impl ConfigurationView {
         )
     }
 
+    /// The keys that the view shows, which are the workspace's own if it has any.
+    fn api_keys(&self, cx: &App) -> (ApiKeys, ApiKeySource) {
+        let state = self.state.read(cx);
+        state.workspace_api_keys.for_display(
+            &state.api_keys,
+            &state.api_key_source,
+            self.save_scope.workspace(),
+        )
+    }
+
     fn should_render_editor(&self, cx: &mut Context<Self>) -> bool {
-        !self.state.read(cx).is_authenticated()
+        !self.state.read(cx).is_authenticated() || self.key_display.is_replacing()
     }
 }
 
 impl Render for ConfigurationView {
     fn render(&mut self, _: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let env_var_set = self.state.read(cx).api_key_from_env;
+        let (api_keys, api_key_source) = self.api_keys(cx);
+        let rejected = self.state.read(cx).api_key_status.is_rejected();
+        let state = self.state.read(cx);
+        let env_var = AllLanguageModelSettings::get_global(cx)
+            .google
+            .api
+            .env_var(GOOGLE_AI_API_KEY_VAR)
+            .to_string();
+        let key_is_external = api_key_source.is_external();
+        let authentication = state.authentication;
+        let adc_source = state
+            .application_default_credentials
+            .as_ref()
+            .map(|credentials| credentials.source().clone());
 
         if self.load_credentials_task.is_some() {
             div().child(Label::new("Loading credentials...")).into_any()
+        } else if authentication == GoogleAuthentication::ApplicationDefaultCredentials {
+            h_flex()
+                .mt_1()
+                .p_1()
+                .gap_1()
+                .rounded_md()
+                .border_1()
+                .border_color(cx.theme().colors().border)
+                .bg(cx.theme().colors().background)
+                .map(|this| match adc_source {
+                    Some(source) => this
+                        .child(Icon::new(IconName::Check).color(Color::Success))
+                        .child(Label::new(source.description())),
+                    None => this
+                        .child(Icon::new(IconName::Warning).color(Color::Warning))
+                        .child(Label::new(
+                            "No Application Default Credentials were found. Run `gcloud auth application-default login` or set GOOGLE_APPLICATION_CREDENTIALS.",
+                        )),
+                })
+                .into_any()
         } else if self.should_render_editor(cx) {
             v_flex()
                 .size_full()
@@ -829,9 +1596,12 @@ impl Render for ConfigurationView {
                         .rounded_sm()
                         .child(self.render_api_key_editor(cx)),
                 )
+                .children(self.save_scope.render(cx, |this| &mut this.save_scope))
+                .children(self.key_display.render_cancel(cx, |this| &mut this.key_display))
+                .children(self.validation.render())
                 .child(
                     Label::new(
-                        format!("You can also assign the {GOOGLE_AI_API_KEY_VAR} environment variable and restart Zed."),
+                        format!("You can also assign the {env_var} environment variable and restart Zed."),
                     )
                     .size(LabelSize::Small).color(Color::Muted),
                 )
@@ -849,11 +1619,14 @@ impl Render for ConfigurationView {
                     h_flex()
                         .gap_1()
                         .child(Icon::new(IconName::Check).color(Color::Success))
-                        .child(Label::new(if env_var_set {
-                            format!("API key set in {GOOGLE_AI_API_KEY_VAR} environment variable.")
-                        } else {
-                            "API key configured.".to_string()
-                        })),
+                        .child(self.key_display.render(
+                            PROVIDER_ID,
+                            &api_keys,
+                            rejected,
+                            &api_key_source,
+                            cx,
+                            |this| &mut this.key_display,
+                        )),
                 )
                 .child(
                     Button::new("reset-key", "Reset Key")
@@ -861,13 +1634,94 @@ impl Render for ConfigurationView {
                         .icon(Some(IconName::Trash))
                         .icon_size(IconSize::Small)
                         .icon_position(IconPosition::Start)
-                        .disabled(env_var_set)
-                        .when(env_var_set, |this| {
-                            this.tooltip(Tooltip::text(format!("To reset your API key, unset the {GOOGLE_AI_API_KEY_VAR} environment variable.")))
+                        .disabled(key_is_external)
+                        .when(key_is_external, |this| {
+                            this.tooltip(Tooltip::text(api_key_source.reset_hint()))
                         })
-                        .on_click(cx.listener(|this, _, window, cx| this.reset_api_key(window, cx))),
+                        .on_click(
+                            cx.listener(|this, _, window, cx| this.reset_api_key(window, cx)),
+                        ),
                 )
                 .into_any()
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grounding_citations() {
+        let metadata: google_ai::GroundingMetadata = serde_json::from_value(serde_json::json!({
+            "webSearchQueries": ["rust 2024 edition"],
+            "groundingChunks": [
+                { "web": { "uri": "https://blog.rust-lang.org", "title": "rust-lang.org" } },
+                { "web": { "uri": "https://doc.rust-lang.org", "title": "doc.rust-lang.org" } }
+            ],
+            "groundingSupports": [
+                {
+                    "segment": { "startIndex": 0, "endIndex": 28, "text": "Rust 2024 shipped in 1.85." },
+                    "groundingChunkIndices": [0],
+                    "confidenceScores": [0.9]
+                }
+            ]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            grounding_citations(metadata),
+            vec![
+                Citation {
+                    url: "https://blog.rust-lang.org".into(),
+                    title: Some("rust-lang.org".into()),
+                    cited_text: Some("Rust 2024 shipped in 1.85.".into()),
+                    confidence: Some(0.9),
+                },
+                Citation {
+                    url: "https://doc.rust-lang.org".into(),
+                    title: Some("doc.rust-lang.org".into()),
+                    cited_text: None,
+                    confidence: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_code_execution_parts() {
+        let response: GenerateContentResponse = serde_json::from_value(serde_json::json!({
+            "candidates": [{
+                "content": {
+                    "role": "model",
+                    "parts": [
+                        { "executableCode": { "language": "PYTHON", "code": "print(2 ** 10)" } },
+                        { "codeExecutionResult": { "outcome": "OUTCOME_OK", "output": "1024\n" } },
+                        { "text": "It's 1024." }
+                    ]
+                }
+            }]
+        }))
+        .unwrap();
+
+        let events = GoogleEventMapper::new()
+            .map_event(response)
+            .into_iter()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            events,
+            vec![
+                LanguageModelCompletionEvent::ExecutableCode {
+                    language: "PYTHON".into(),
+                    code: "print(2 ** 10)".into(),
+                },
+                LanguageModelCompletionEvent::CodeExecutionResult {
+                    success: true,
+                    output: "1024\n".into(),
+                },
+                LanguageModelCompletionEvent::Text("It's 1024.".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context as _, Result, anyhow};
+use futures::FutureExt as _;
+use futures::lock::Mutex;
+use google_ai::Authorization;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+
+use crate::oauth::{self, OAuthConfig, OAuthToken};
+
+const CREDENTIALS_FILE_VAR: &str = "GOOGLE_APPLICATION_CREDENTIALS";
+const GOOGLE_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const METADATA_TOKEN_URL: &str =
+    "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+/// Outside of Google Cloud the metadata server doesn't exist, so don't wait on it for long.
+const METADATA_TIMEOUT: Duration = Duration::from_secs(3);
+const SCOPES: &[&str] = &[
+    "https://www.googleapis.com/auth/cloud-platform",
+    "https://www.googleapis.com/auth/generative-language",
+];
+
+/// Where the Application Default Credentials were found.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdcSource {
+    /// The file named by `GOOGLE_APPLICATION_CREDENTIALS`.
+    EnvVar(PathBuf),
+    /// The file written by `gcloud auth application-default login`.
+    Gcloud(PathBuf),
+    MetadataServer,
+}
+
+impl AdcSource {
+    pub fn description(&self) -> String {
+        match self {
+            Self::EnvVar(path) => format!(
+                "Using credentials from {} ({CREDENTIALS_FILE_VAR}).",
+                path.display()
+            ),
+            Self::Gcloud(path) => format!("Using gcloud credentials from {}.", path.display()),
+            Self::MetadataServer => {
+                "Using the service account of this Google Cloud instance.".to_string()
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum CredentialsFile {
+    AuthorizedUser {
+        client_id: String,
+        client_secret: String,
+        refresh_token: String,
+        quota_project_id: Option<String>,
+    },
+    ServiceAccount {
+        client_email: String,
+        private_key: String,
+        private_key_id: Option<String>,
+        token_uri: Option<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct ServiceAccountClaims<'a> {
+    iss: &'a str,
+    scope: String,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+enum Credentials {
+    File(CredentialsFile),
+    MetadataServer,
+}
+
+/// Credentials resolved the way Google's client libraries do it, which hand out short-lived
+/// access tokens that are refreshed as they expire.
+pub struct ApplicationDefaultCredentials {
+    source: AdcSource,
+    credentials: Credentials,
+    token: Mutex<Option<OAuthToken>>,
+}
+
+impl ApplicationDefaultCredentials {
+    /// Looks for credentials in `GOOGLE_APPLICATION_CREDENTIALS`, then in gcloud's configuration
+    /// directory, and finally asks the metadata server, fetching an initial token to make sure
+    /// they work.
+    pub async fn discover(http_client: &dyn HttpClient) -> Result<Self> {
+        let (source, credentials) = if let Some(path) = std::env::var_os(CREDENTIALS_FILE_VAR) {
+            let path = PathBuf::from(path);
+            let credentials = read_credentials_file(&path).await?;
+            (AdcSource::EnvVar(path), Credentials::File(credentials))
+        } else {
+            let path = gcloud_credentials_path();
+            if smol::fs::metadata(&path).await.is_ok() {
+                let credentials = read_credentials_file(&path).await?;
+                (AdcSource::Gcloud(path), Credentials::File(credentials))
+            } else {
+                (AdcSource::MetadataServer, Credentials::MetadataServer)
+            }
+        };
+
+        let this = Self {
+            source,
+            credentials,
+            token: Mutex::new(None),
+        };
+        this.authorization(http_client)
+            .await
+            .context("failed to obtain a token from Application Default Credentials")?;
+        Ok(this)
+    }
+
+    pub fn source(&self) -> &AdcSource {
+        &self.source
+    }
+
+    /// Returns the authorization for a request, refreshing the access token if it's about to
+    /// expire.
+    pub async fn authorization(&self, http_client: &dyn HttpClient) -> Result<Authorization> {
+        let mut token = self.token.lock().await;
+        let access_token = match token.as_ref() {
+            Some(token) if !token.is_expired() => token.access_token.clone(),
+            _ => {
+                let new_token = self.fetch_token(http_client).await?;
+                let access_token = new_token.access_token.clone();
+                *token = Some(new_token);
+                access_token
+            }
+        };
+
+        let quota_project = match &self.credentials {
+            Credentials::File(CredentialsFile::AuthorizedUser {
+                quota_project_id, ..
+            }) => quota_project_id.clone(),
+            _ => None,
+        };
+        Ok(Authorization::AccessToken {
+            token: access_token,
+            quota_project,
+        })
+    }
+
+    async fn fetch_token(&self, http_client: &dyn HttpClient) -> Result<OAuthToken> {
+        match &self.credentials {
+            Credentials::File(CredentialsFile::AuthorizedUser {
+                client_id,
+                client_secret,
+                refresh_token,
+                ..
+            }) => {
+                let config = OAuthConfig {
+                    client_id: client_id.clone(),
+                    client_secret: Some(client_secret.clone()),
+                    token_url: GOOGLE_TOKEN_URL.to_string(),
+                    scopes: Vec::new(),
+                };
+                let token = OAuthToken {
+                    access_token: String::new(),
+                    refresh_token: Some(refresh_token.clone()),
+                    expires_at: None,
+                };
+                oauth::refresh_token(http_client, &config, &token).await
+            }
+            Credentials::File(CredentialsFile::ServiceAccount {
+                client_email,
+                private_key,
+                private_key_id,
+                token_uri,
+            }) => {
+                let token_url = token_uri.as_deref().unwrap_or(GOOGLE_TOKEN_URL);
+                let issued_at = oauth::unix_now();
+                let claims = ServiceAccountClaims {
+                    iss: client_email,
+                    scope: SCOPES.join(" "),
+                    aud: token_url,
+                    iat: issued_at,
+                    exp: issued_at + 3600,
+                };
+                let mut header = Header::new(Algorithm::RS256);
+                header.kid = private_key_id.clone();
+                let key = EncodingKey::from_rsa_pem(private_key.as_bytes())
+                    .context("invalid service account private key")?;
+                let assertion = jsonwebtoken::encode(&header, &claims, &key)?;
+                oauth::exchange_jwt_assertion(http_client, token_url, &assertion).await
+            }
+            Credentials::MetadataServer => {
+                let request = HttpRequest::builder()
+                    .method(Method::GET)
+                    .uri(format!("{METADATA_TOKEN_URL}?scopes={}", SCOPES.join(",")))
+                    .header("Metadata-Flavor", "Google")
+                    .body(AsyncBody::empty())?;
+                futures::select_biased! {
+                    token = oauth::fetch_token(http_client, request).fuse() => token,
+                    _ = smol::Timer::after(METADATA_TIMEOUT).fuse() => {
+                        Err(anyhow!("no Application Default Credentials were found"))
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn read_credentials_file(path: &Path) -> Result<CredentialsFile> {
+    let contents = smol::fs::read_to_string(path)
+        .await
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("unsupported credentials file {}", path.display()))
+}
+
+fn gcloud_credentials_path() -> PathBuf {
+    let config_dir = if let Some(config_dir) = std::env::var_os("CLOUDSDK_CONFIG") {
+        PathBuf::from(config_dir)
+    } else if cfg!(target_os = "windows") {
+        std::env::var_os("APPDATA")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| paths::home_dir().clone())
+            .join("gcloud")
+    } else {
+        paths::home_dir().join(".config").join("gcloud")
+    };
+    config_dir.join("application_default_credentials.json")
+}
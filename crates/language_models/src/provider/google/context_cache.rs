@@ -0,0 +1,251 @@
+use std::hash::{DefaultHasher, Hash as _, Hasher as _};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use google_ai::{Authorization, CreateCachedContentRequest, GenerateContentRequest};
+use http_client::HttpClient;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::request_budget::estimate_tokens;
+
+const DEFAULT_MIN_TOKENS: u64 = 4096;
+const DEFAULT_TTL_SECS: u64 = 600;
+
+/// How long before a cache expires to stop using it, so that requests don't refer to a cache
+/// that expires while they're sent.
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// How many caches to keep track of. The oldest are forgotten first, and expire on their own.
+const MAX_CACHES: usize = 16;
+
+/// Settings for Gemini's explicit context caching.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ContextCachingSettings {
+    /// Whether to cache the start of long conversations, so that the following requests of the
+    /// conversation refer to the cache instead of sending it again.
+    ///
+    /// Default: false
+    pub enabled: Option<bool>,
+    /// The fewest tokens that have to be added to a conversation since it was last cached to
+    /// cache it again. Gemini rejects caches below a model-specific minimum.
+    ///
+    /// Default: 4096
+    pub min_tokens: Option<u64>,
+    /// How many seconds caches are kept for. Google charges for storing a cache until it expires.
+    ///
+    /// Default: 600
+    pub ttl_secs: Option<u64>,
+}
+
+impl ContextCachingSettings {
+    fn enabled(&self) -> bool {
+        self.enabled.unwrap_or(false)
+    }
+
+    fn min_tokens(&self) -> u64 {
+        self.min_tokens.unwrap_or(DEFAULT_MIN_TOKENS)
+    }
+
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_secs.unwrap_or(DEFAULT_TTL_SECS))
+    }
+}
+
+struct ContextCache {
+    /// The key of the prefix of the conversation that's cached.
+    key: u64,
+    /// How many of the conversation's contents are cached.
+    contents_len: usize,
+    name: String,
+    expires_at: Instant,
+}
+
+/// The caches of the start of conversations with Gemini models, which later requests of the
+/// same conversation refer to instead of sending it again.
+#[derive(Clone, Default)]
+pub(crate) struct ContextCaches(Arc<Mutex<Vec<ContextCache>>>);
+
+impl ContextCaches {
+    /// Replaces the start of `request` with the longest cache of it, first caching everything but
+    /// the newest content when at least `min_tokens` of it aren't cached yet. Caches are only
+    /// shared between requests with the same `scope`, which identifies the credentials they're
+    /// sent with, since a cache belongs to the project that created it.
+    ///
+    /// Failing to create a cache isn't an error, the request is sent without it.
+    pub(crate) async fn apply(
+        &self,
+        request: &mut GenerateContentRequest,
+        scope: &str,
+        settings: ContextCachingSettings,
+        http_client: &dyn HttpClient,
+        api_url: &str,
+        authorization: &Authorization,
+    ) {
+        if !settings.enabled() || request.contents.len() < 2 {
+            return;
+        }
+        let prefixes = match prefix_keys(scope, request) {
+            Ok(prefixes) => prefixes,
+            Err(error) => {
+                log::error!("failed to compute the context cache key of a request: {error:#}");
+                return;
+            }
+        };
+        let cacheable_len = request.contents.len() - 1;
+
+        let now = Instant::now();
+        let mut cached = {
+            let mut caches = self.0.lock();
+            caches.retain(|cache| cache.expires_at > now);
+            caches
+                .iter()
+                .filter(|cache| {
+                    cache.contents_len <= cacheable_len
+                        && prefixes[cache.contents_len].key == cache.key
+                })
+                .max_by_key(|cache| cache.contents_len)
+                .map(|cache| (cache.name.clone(), cache.contents_len))
+        };
+
+        let cached_len = cached.as_ref().map(|(_, contents_len)| *contents_len);
+        let cached_bytes = cached_len.map_or(0, |contents_len| prefixes[contents_len].bytes);
+        let uncached_tokens = estimate_tokens(prefixes[cacheable_len].bytes - cached_bytes);
+        if cached_len != Some(cacheable_len) && uncached_tokens >= settings.min_tokens() {
+            let created = google_ai::create_cached_content(
+                http_client,
+                api_url,
+                authorization,
+                CreateCachedContentRequest {
+                    model: &request.model,
+                    contents: &request.contents[..cacheable_len],
+                    system_instruction: request.system_instruction.as_ref(),
+                    tools: request.tools.as_deref(),
+                    tool_config: request.tool_config.as_ref(),
+                    ttl: format!("{}s", settings.ttl().as_secs()),
+                },
+            )
+            .await;
+            match created {
+                Ok(cache) => {
+                    let mut caches = self.0.lock();
+                    if caches.len() == MAX_CACHES {
+                        caches.remove(0);
+                    }
+                    caches.push(ContextCache {
+                        key: prefixes[cacheable_len].key,
+                        contents_len: cacheable_len,
+                        name: cache.name.clone(),
+                        expires_at: now + settings.ttl().saturating_sub(EXPIRY_MARGIN),
+                    });
+                    cached = Some((cache.name, cacheable_len));
+                }
+                Err(error) => log::warn!("failed to create a Gemini context cache: {error:#}"),
+            }
+        }
+
+        if let Some((name, contents_len)) = cached {
+            request.contents.drain(..contents_len);
+            request.system_instruction = None;
+            request.tools = None;
+            request.tool_config = None;
+            request.cached_content = Some(name);
+        }
+    }
+}
+
+struct PrefixKey {
+    key: u64,
+    /// The size of the prefix when serialized, to estimate its tokens.
+    bytes: usize,
+}
+
+/// The key of each prefix of `request`'s contents, from the one without any contents to the one
+/// with all of them. A prefix's key covers everything that's cached along with it.
+fn prefix_keys(scope: &str, request: &GenerateContentRequest) -> Result<Vec<PrefixKey>> {
+    let mut hasher = DefaultHasher::new();
+    scope.hash(&mut hasher);
+    request.model.model_id.hash(&mut hasher);
+    let mut bytes = 0;
+    for json in [
+        serde_json::to_string(&request.system_instruction)?,
+        serde_json::to_string(&request.tools)?,
+        serde_json::to_string(&request.tool_config)?,
+    ] {
+        bytes += json.len();
+        json.hash(&mut hasher);
+    }
+
+    let mut prefixes = Vec::with_capacity(request.contents.len() + 1);
+    prefixes.push(PrefixKey {
+        key: hasher.finish(),
+        bytes,
+    });
+    for content in &request.contents {
+        let json = serde_json::to_string(content)?;
+        bytes += json.len();
+        json.hash(&mut hasher);
+        prefixes.push(PrefixKey {
+            key: hasher.finish(),
+            bytes,
+        });
+    }
+    Ok(prefixes)
+}
+
+#[cfg(test)]
+mod tests {
+    use google_ai::{Content, ModelName, Part, Role, SystemInstruction, TextPart};
+
+    use super::*;
+
+    fn request(texts: &[&str]) -> GenerateContentRequest {
+        GenerateContentRequest {
+            model: ModelName {
+                model_id: "gemini-2.5-pro".into(),
+            },
+            contents: texts
+                .iter()
+                .map(|text| Content {
+                    parts: vec![Part::TextPart(TextPart {
+                        text: text.to_string(),
+                    })],
+                    role: Role::User,
+                })
+                .collect(),
+            system_instruction: Some(SystemInstruction {
+                parts: vec![Part::TextPart(TextPart {
+                    text: "You are a coding agent.".into(),
+                })],
+            }),
+            generation_config: None,
+            safety_settings: None,
+            tools: None,
+            tool_config: None,
+            cached_content: None,
+        }
+    }
+
+    #[test]
+    fn test_prefix_keys() {
+        let keys = |scope, request| {
+            prefix_keys(scope, &request)
+                .unwrap()
+                .into_iter()
+                .map(|prefix| prefix.key)
+                .collect::<Vec<_>>()
+        };
+        let short = keys("key-1", request(&["a", "b"]));
+        let long = keys("key-1", request(&["a", "b", "c"]));
+        assert_eq!(short.len(), 3);
+        assert_eq!(short[..], long[..3]);
+        assert_ne!(keys("key-2", request(&["a", "b"])), short);
+        assert_ne!(keys("key-1", request(&["a", "x"]))[2], short[2]);
+
+        let bytes = prefix_keys("key-1", &request(&["a", "b"])).unwrap();
+        assert!(bytes[0].bytes > 0);
+        assert!(bytes[1].bytes > bytes[0].bytes);
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Context as _, Result, anyhow, bail};
+use base64::Engine as _;
+use futures::channel::mpsc;
+use google_ai::{Authorization, FileState, GenerateContentRequest, Part};
+use gpui::BackgroundExecutor;
+use http_client::HttpClient;
+use language_model::{LanguageModelCompletionEvent, LanguageModelRequest, LanguageModelVideo};
+
+/// The largest video that's sent inline with a request. Inline data is limited to 20 MB per
+/// request, and base64 makes videos a third larger, so anything bigger goes through the Files API.
+const MAX_INLINE_VIDEO_BYTES: usize = 15 * 1024 * 1024;
+
+const PROCESSING_POLL_INTERVAL: Duration = Duration::from_secs(2);
+/// How long to wait for an uploaded video to be processed before giving up on the request.
+const PROCESSING_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// The videos in `request` that are too large to be sent inline.
+pub(crate) fn videos_to_upload(request: &LanguageModelRequest) -> Vec<LanguageModelVideo> {
+    request
+        .messages
+        .iter()
+        .flat_map(|message| &message.content)
+        .filter_map(|content| match content {
+            language_model::MessageContent::Video(video)
+                if video.byte_len() > MAX_INLINE_VIDEO_BYTES =>
+            {
+                Some(video.clone())
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Uploads `videos` to the Files API and replaces their inline data in `request` with the
+/// uploaded files, reporting how much of each has been uploaded to `progress`.
+pub(crate) async fn upload_videos(
+    request: &mut GenerateContentRequest,
+    videos: Vec<LanguageModelVideo>,
+    progress: &mpsc::UnboundedSender<LanguageModelCompletionEvent>,
+    http_client: &dyn HttpClient,
+    api_url: &str,
+    authorization: &Authorization,
+    executor: &BackgroundExecutor,
+) -> Result<()> {
+    for video in videos {
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(video.source.as_bytes())
+            .with_context(|| format!("failed to decode {}", video.name))?;
+        let total_bytes = bytes.len() as u64;
+        let mut file = google_ai::upload_file(
+            http_client,
+            api_url,
+            authorization,
+            &video.name,
+            &video.mime_type,
+            &bytes,
+            |uploaded_bytes| {
+                progress
+                    .unbounded_send(LanguageModelCompletionEvent::UploadProgress {
+                        name: video.name.clone(),
+                        uploaded_bytes: uploaded_bytes as u64,
+                        total_bytes,
+                    })
+                    .ok();
+            },
+        )
+        .await
+        .with_context(|| format!("failed to upload {}", video.name))?;
+
+        let started_at = Instant::now();
+        loop {
+            match file.state {
+                FileState::Active => break,
+                FileState::Failed => bail!("Gemini failed to process {}", video.name),
+                FileState::Processing | FileState::StateUnspecified => {}
+            }
+            if started_at.elapsed() > PROCESSING_TIMEOUT {
+                return Err(anyhow!(
+                    "timed out waiting for {} to be processed",
+                    video.name
+                ));
+            }
+            executor.timer(PROCESSING_POLL_INTERVAL).await;
+            file = google_ai::get_file(http_client, api_url, authorization, &file.name).await?;
+        }
+
+        let parts = request
+            .system_instruction
+            .iter_mut()
+            .flat_map(|instruction| &mut instruction.parts)
+            .chain(
+                request
+                    .contents
+                    .iter_mut()
+                    .flat_map(|content| &mut content.parts),
+            );
+        for part in parts {
+            if let Part::InlineDataPart(inline) = part {
+                if inline.inline_data.data.as_str() == video.source.as_ref() {
+                    *part = Part::FileDataPart(google_ai::FileDataPart {
+                        file_data: google_ai::FileData {
+                            mime_type: file.mime_type.clone(),
+                            file_uri: file.uri.clone(),
+                        },
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use language_model::{LanguageModelRequestMessage, MessageContent, Role};
+
+    #[test]
+    fn test_videos_to_upload() {
+        let video = |name: &str, len: usize| {
+            MessageContent::Video(LanguageModelVideo {
+                name: name.into(),
+                mime_type: "video/mp4".into(),
+                source: "A".repeat(len / 3 * 4).into(),
+                duration: None,
+            })
+        };
+        let request = LanguageModelRequest {
+            messages: vec![LanguageModelRequestMessage {
+                role: Role::User,
+                content: vec![
+                    video("small.mp4", 1024),
+                    video("large.mp4", MAX_INLINE_VIDEO_BYTES + 3),
+                ],
+                cache: false,
+            }],
+            ..Default::default()
+        };
+        let names = videos_to_upload(&request)
+            .into_iter()
+            .map(|video| video.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["large.mp4"]);
+    }
+}
@@ -7,15 +7,16 @@ use editor::{Editor, EditorElement, EditorStyle};
 use futures::future::BoxFuture;
 use futures::{FutureExt, StreamExt};
 use gpui::{
-    AnyView, App, AsyncApp, Context, Entity, FontStyle, Task, TextStyle, WhiteSpace, Window,
-    prelude::*, relative, rems,
+    AnyView, App, AsyncApp, Context, Entity, FontStyle, ImageFormat, Task, TextStyle, WhiteSpace,
+    Window, prelude::*, relative, rems,
 };
-use http_client::HttpClient;
+use http_client::{HttpClient, http};
 use language_model::{
-    AuthenticateError, LanguageModel, LanguageModelCompletionError, LanguageModelCompletionEvent,
-    LanguageModelId, LanguageModelName, LanguageModelProvider, LanguageModelProviderId,
-    LanguageModelProviderName, LanguageModelProviderState, LanguageModelRequest,
-    LanguageModelToolChoice, RateLimiter,
+    AuthenticateError, ImageLimits, LanguageModel, LanguageModelCompletionError,
+    LanguageModelCompletionEvent, LanguageModelId, LanguageModelName, LanguageModelProvider,
+    LanguageModelProviderId, LanguageModelProviderName, LanguageModelProviderState,
+    LanguageModelRequest, LanguageModelToolChoice, LanguageModelToolSchemaFormat, ModelPrice,
+    RateLimiter, StructuredOutput,
 };
 use menu::Confirm;
 use open_ai::{ResponseStreamEvent, stream_completion};
@@ -23,20 +24,48 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use settings::{Settings, SettingsStore};
 use theme::ThemeSettings;
-use ui::{Button, Color, IconName, Label, LabelCommon, LabelSize, List, prelude::*};
+use ui::{Button, Color, Icon, IconName, Label, LabelCommon, LabelSize, List, prelude::*};
 use util::ResultExt;
 
-use crate::AllLanguageModelSettings;
+use crate::api_key::{
+    ApiKeyDisplay, ApiKeyRotation, ApiKeySaveScope, ApiKeySource, ApiKeyStatus, ApiKeyValidation,
+    ApiKeys, CredentialsScope, WorkspaceApiKeys, WorkspaceScope, carry_over_api_key, load_api_keys,
+    refresh_api_keys, validate_api_keys,
+};
+use crate::key_usage::KeyUsageRecorder;
+use crate::live_usage::estimate_live_usage;
+use crate::moderation::check_moderation;
+use crate::output_tokens::OutputTokenClamp;
+use crate::pricing::model_price;
 use crate::provider::open_ai::{OpenAiEventMapper, count_open_ai_tokens, into_open_ai};
+use crate::reasoning::normalize_reasoning;
+use crate::request_log::RequestLogRecorder;
+use crate::spend_limits::check_spend_limits;
+use crate::stream_timeouts::StreamTimeouts;
 use crate::ui::InstructionListItem;
+use crate::{AllLanguageModelSettings, ProviderApiSettings, ReasoningSettings, SamplingParameters};
 
-const PROVIDER_ID: &str = "grok";
-const PROVIDER_NAME: &str = "Grok";
+pub(crate) const PROVIDER_ID: &str = "grok";
+pub(crate) const PROVIDER_NAME: &str = "Grok";
 const XAI_API_KEY_VAR: &str = "XAI_API_KEY";
+/// The largest image that xAI accepts.
+const MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
+
+pub(crate) fn api_key_validation_request(api_url: &str, api_key: &str) -> http::request::Builder {
+    let api_url = if api_url.is_empty() {
+        "https://api.x.ai/v1"
+    } else {
+        api_url
+    };
+    http::Request::builder()
+        .uri(format!("{api_url}/models"))
+        .header("Authorization", format!("Bearer {api_key}"))
+}
 
 #[derive(Clone, Default)]
 pub struct GrokSettings {
     pub api_url: String,
+    pub api: ProviderApiSettings,
     pub available_models: Vec<AvailableModel>,
 }
 
@@ -47,6 +76,39 @@ pub struct AvailableModel {
     pub max_tokens: usize,
     pub max_output_tokens: Option<u32>,
     pub max_completion_tokens: Option<u32>,
+    /// How much the model reasons before it responds.
+    #[serde(default)]
+    pub reasoning: ReasoningSettings,
+    /// Parameters to add to the body of every request to this model, for features that Zed
+    /// doesn't support directly, like xAI's `search_parameters`. They replace any parameter of
+    /// the same name.
+    #[serde(default)]
+    pub extra_body: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Whether the model can call tools.
+    ///
+    /// Default: true
+    #[serde(default)]
+    pub supports_tools: Option<bool>,
+    /// Whether the model takes images as input, so that attached images are sent to it.
+    ///
+    /// Default: whether the model's name says it's a vision model
+    #[serde(default)]
+    pub supports_images: Option<bool>,
+    /// Whether the model reasons before it responds. The `reasoning` settings are only sent to
+    /// models that do, since other models reject them.
+    ///
+    /// Default: whether the model is a Grok 3 Mini model, or `reasoning` is set
+    #[serde(default)]
+    pub supports_thinking: Option<bool>,
+    /// Whether the model can call several tools in one response. When set, the
+    /// `parallel_tool_calls` parameter is sent with requests that include tools.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub supports_parallel_tool_calls: Option<bool>,
+    /// Sampling parameters for requests to this model.
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
 }
 
 pub struct GrokLanguageModelProvider {
@@ -55,45 +117,182 @@ pub struct GrokLanguageModelProvider {
 }
 
 pub struct State {
-    api_key: Option<String>,
-    api_key_from_env: bool,
+    http_client: Arc<dyn HttpClient>,
+    api_url: String,
+    api_keys: ApiKeys,
+    api_key_source: ApiKeySource,
+    workspace_api_keys: WorkspaceApiKeys,
+    api_key_status: ApiKeyStatus,
+    _api_key_refresh_task: Option<Task<()>>,
     _subscription: gpui::Subscription,
+    _credentials_scope_subscription: gpui::Subscription,
+}
+
+/// The credentials of the Grok provider, for xAI's APIs other than chat completions, like image
+/// generation, to authenticate with.
+#[derive(Clone)]
+pub(crate) struct GrokAuth {
+    pub http_client: Arc<dyn HttpClient>,
+    pub api_url: String,
+    api_keys: ApiKeys,
+    rotation: ApiKeyRotation,
+}
+
+impl GrokAuth {
+    pub fn read(state: &Entity<State>, cx: &AsyncApp) -> Result<Self> {
+        cx.read_entity(state, Self::new)
+    }
+
+    pub fn new(state: &State, cx: &App) -> Self {
+        let settings = &AllLanguageModelSettings::get_global(cx).grok;
+        let api_url = if settings.api_url.is_empty() {
+            "https://api.x.ai/v1".to_string()
+        } else {
+            settings.api_url.clone()
+        };
+        Self {
+            http_client: state.http_client.clone(),
+            api_url,
+            api_keys: state.api_key_status.track(&state.api_keys),
+            rotation: settings.api.api_key_rotation,
+        }
+    }
+
+    pub fn api_key(&self) -> Result<String> {
+        self.api_keys
+            .next_for_request(self.rotation)
+            .context("Missing Grok API Key")
+    }
+
+    pub fn record_error(&self, api_key: &str, error: &anyhow::Error) {
+        self.api_keys.record_error(api_key, error);
+    }
 }
 
 impl State {
     fn is_authenticated(&self) -> bool {
-        self.api_key.is_some()
+        !self.api_keys.is_empty() || self.workspace_api_keys.has_any()
     }
 
-    fn reset_api_key(&self, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn reset_api_key(
+        &self,
+        workspace: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<()>> {
+        let credentials_provider = <dyn CredentialsProvider>::global(cx);
+        let api_url = AllLanguageModelSettings::get_global(cx)
+            .grok
+            .api_url
+            .clone();
+        if let Some(workspace) = workspace {
+            let task = self
+                .workspace_api_keys
+                .reset(&workspace, PROVIDER_ID, &api_url, cx);
+            cx.notify();
+            return task;
+        }
+        let credentials_scope = CredentialsScope::global(cx);
+        let credentials_urls = [
+            credentials_scope.api_key_url(&self.api_key_source, PROVIDER_ID, &api_url),
+            credentials_scope.legacy_api_key_url(&self.api_key_source, &api_url),
+        ];
         cx.spawn(async move |this, cx| {
+            for credentials_url in &credentials_urls {
+                credentials_provider
+                    .delete_credentials(credentials_url, &cx)
+                    .await
+                    .log_err();
+            }
             this.update(cx, |this, cx| {
-                this.api_key = None;
-                this.api_key_from_env = false;
+                this.api_keys = ApiKeys::default();
+                this.api_key_status.set_rejected(false, cx);
+                this.api_key_source = ApiKeySource::CredentialsStore;
+                this._api_key_refresh_task = None;
                 cx.notify();
             })
         })
     }
 
-    fn set_api_key(&mut self, api_key: String, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn set_api_key(
+        &mut self,
+        api_key: String,
+        workspace_scope: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<Option<SharedString>>> {
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .grok
             .api_url
             .clone();
+        let http_client = self.http_client.clone();
+        let source = workspace_scope.map_or(
+            ApiKeySource::CredentialsStore,
+            ApiKeySource::WorkspaceCredentialsStore,
+        );
+        let credentials_url =
+            CredentialsScope::global(cx).api_key_url(&source, PROVIDER_ID, &api_url);
         cx.spawn(async move |this, cx| {
+            let warning =
+                validate_api_keys(http_client.as_ref(), &ApiKeys::parse(&api_key), |api_key| {
+                    api_key_validation_request(&api_url, api_key)
+                })
+                .await?;
+
             credentials_provider
-                .write_credentials(&api_url, "Bearer", api_key.as_bytes(), &cx)
+                .write_credentials(&credentials_url, "Bearer", api_key.as_bytes(), &cx)
                 .await
                 .log_err();
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
-                this.api_key_from_env = false;
+                match source.workspace() {
+                    Some(workspace) => this
+                        .workspace_api_keys
+                        .set(workspace, Some(ApiKeys::parse(&api_key))),
+                    None => {
+                        this.api_keys = ApiKeys::parse(&api_key);
+                        this.api_key_source = source;
+                    }
+                }
+                this._api_key_refresh_task = None;
+                this.api_key_status.set_rejected(false, cx);
                 cx.notify();
-            })
+            })?;
+            Ok(warning)
         })
     }
 
+    /// Reloads the keys saved for a new `api_url`, carrying the current key over if none was
+    /// saved for it yet.
+    fn update_api_url(&mut self, cx: &mut Context<Self>) {
+        let api_url = AllLanguageModelSettings::get_global(cx)
+            .grok
+            .api_url
+            .clone();
+        if self.api_url == api_url {
+            return;
+        }
+        let previous_api_url = std::mem::replace(&mut self.api_url, api_url);
+        self.workspace_api_keys.clear();
+        if self.api_key_source.is_external() {
+            return;
+        }
+
+        let carry_over = carry_over_api_key(
+            &self.api_key_source,
+            PROVIDER_ID,
+            &previous_api_url,
+            &self.api_url,
+            cx,
+        );
+        self.api_keys = ApiKeys::default();
+        cx.spawn(async move |this, cx| {
+            carry_over.await;
+            if let Ok(task) = this.update(cx, |this, cx| this.authenticate(cx)) {
+                task.await.ok();
+            }
+        })
+        .detach();
+    }
+
     fn authenticate(&self, cx: &mut Context<Self>) -> Task<Result<(), AuthenticateError>> {
         if self.is_authenticated() {
             return Task::ready(Ok(()));
@@ -104,23 +303,38 @@ impl State {
             .grok
             .api_url
             .clone();
+        let api_settings = AllLanguageModelSettings::get_global(cx).grok.api.clone();
+        let credentials_scope = CredentialsScope::global(cx);
+        let load_workspace_api_keys =
+            self.workspace_api_keys
+                .load_open_workspaces(PROVIDER_ID, &api_url, cx);
         cx.spawn(async move |this, cx| {
-            let (api_key, from_env) = if let Ok(api_key) = std::env::var(XAI_API_KEY_VAR) {
-                (api_key, true)
-            } else {
-                let (_, api_key) = credentials_provider
-                    .read_credentials(&api_url, &cx)
-                    .await?
-                    .ok_or(AuthenticateError::CredentialsNotFound)?;
-                (
-                    String::from_utf8(api_key).context("invalid Grok API key")?,
-                    false,
-                )
+            let api_keys = load_api_keys(
+                XAI_API_KEY_VAR,
+                &api_settings,
+                credentials_provider,
+                PROVIDER_ID,
+                &api_url,
+                credentials_scope,
+                &cx,
+            )
+            .await;
+            let has_workspace_api_keys = load_workspace_api_keys.await;
+            let (api_keys, source) = match api_keys {
+                Err(AuthenticateError::CredentialsNotFound) if has_workspace_api_keys => {
+                    this.update(cx, |_, cx| cx.notify())?;
+                    return Ok(());
+                }
+                api_keys => api_keys?,
             };
 
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
-                this.api_key_from_env = from_env;
+                this._api_key_refresh_task =
+                    refresh_api_keys(&source, &api_keys, &api_settings, cx, |this, api_keys| {
+                        this.api_keys = api_keys
+                    });
+                this.api_keys = api_keys;
+                this.api_key_source = source;
                 cx.notify();
             })?;
 
@@ -132,23 +346,76 @@ impl State {
 impl GrokLanguageModelProvider {
     pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut App) -> Self {
         let state = cx.new(|cx| State {
-            api_key: None,
-            api_key_from_env: false,
-            _subscription: cx.observe_global::<SettingsStore>(|_this: &mut State, cx| {
+            http_client: http_client.clone(),
+            api_url: AllLanguageModelSettings::get_global(cx)
+                .grok
+                .api_url
+                .clone(),
+            api_keys: ApiKeys::default(),
+            api_key_source: ApiKeySource::default(),
+            workspace_api_keys: WorkspaceApiKeys::default(),
+            api_key_status: ApiKeyStatus::new(cx, |state| &mut state.api_key_status),
+            _api_key_refresh_task: None,
+            _subscription: cx.observe_global::<SettingsStore>(|this: &mut State, cx| {
+                this.update_api_url(cx);
                 cx.notify();
             }),
+            _credentials_scope_subscription: cx.observe_global::<CredentialsScope>(
+                |this: &mut State, cx| {
+                    this.workspace_api_keys.clear();
+                    if !this.api_key_source.is_external() {
+                        this.api_keys = ApiKeys::default();
+                        this.api_key_status.set_rejected(false, cx);
+                        this.authenticate(cx).detach();
+                        cx.notify();
+                    }
+                },
+            ),
         });
 
         Self { http_client, state }
     }
 
-    fn create_language_model(&self, model: open_ai::Model) -> Arc<dyn LanguageModel> {
+    /// The provider's state, whose credentials other xAI services share.
+    pub(crate) fn state(&self) -> Entity<State> {
+        self.state.clone()
+    }
+
+    fn create_language_model(&self, model: open_ai::Model, cx: &App) -> Arc<dyn LanguageModel> {
+        let available_model = AllLanguageModelSettings::get_global(cx)
+            .grok
+            .available_models
+            .iter()
+            .find(|available_model| available_model.name == model.id());
+        let supports_tools = available_model
+            .and_then(|available_model| available_model.supports_tools)
+            .unwrap_or(true);
+        // Only Grok 2 Vision models support images.
+        let supports_images = available_model
+            .and_then(|available_model| available_model.supports_images)
+            .unwrap_or_else(|| model.id().contains("vision"));
+        let supports_thinking = available_model
+            .and_then(|available_model| available_model.supports_thinking)
+            .unwrap_or_else(|| {
+                model.id().starts_with("grok-3-mini")
+                    || available_model
+                        .is_some_and(|available_model| available_model.reasoning.effort.is_some())
+            });
+        let supports_parallel_tool_calls = available_model
+            .and_then(|available_model| available_model.supports_parallel_tool_calls)
+            .unwrap_or(false);
         Arc::new(GrokLanguageModel {
             id: LanguageModelId::from(model.id().to_string()),
             model,
+            supports_tools,
+            supports_images,
+            supports_thinking,
+            supports_parallel_tool_calls,
             state: self.state.clone(),
             http_client: self.http_client.clone(),
-            request_limiter: RateLimiter::new(4),
+            request_limiter: RateLimiter::new(
+                AllLanguageModelSettings::get_global(cx).max_concurrent_requests(PROVIDER_ID),
+            ),
         })
     }
 }
@@ -174,24 +441,32 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
         IconName::ZedAssistant
     }
 
-    fn default_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(open_ai::Model::Custom {
-            name: "grok-3-latest".to_string(),
-            display_name: Some("Grok 3".to_string()),
-            max_tokens: 131072,
-            max_output_tokens: Some(4096),
-            max_completion_tokens: Some(4096),
-        }))
+    fn default_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(
+            open_ai::Model::Custom {
+                name: "grok-3-latest".to_string(),
+                display_name: Some("Grok 3".to_string()),
+                max_tokens: 131072,
+                max_output_tokens: Some(4096),
+                max_completion_tokens: Some(4096),
+                system_prompt_role: None,
+            },
+            cx,
+        ))
     }
 
-    fn default_fast_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(open_ai::Model::Custom {
-            name: "grok-3-fast-latest".to_string(),
-            display_name: Some("Grok 3 Fast".to_string()),
-            max_tokens: 131072,
-            max_output_tokens: Some(4096),
-            max_completion_tokens: Some(4096),
-        }))
+    fn default_fast_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(
+            open_ai::Model::Custom {
+                name: "grok-3-fast-latest".to_string(),
+                display_name: Some("Grok 3 Fast".to_string()),
+                max_tokens: 131072,
+                max_output_tokens: Some(4096),
+                max_completion_tokens: Some(4096),
+                system_prompt_role: None,
+            },
+            cx,
+        ))
     }
 
     fn provided_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
@@ -206,6 +481,7 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
                 max_tokens: 131072,
                 max_output_tokens: Some(4096),
                 max_completion_tokens: Some(4096),
+                system_prompt_role: None,
             },
         );
         
@@ -217,6 +493,7 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
                 max_tokens: 131072,
                 max_output_tokens: Some(4096),
                 max_completion_tokens: Some(4096),
+                system_prompt_role: None,
             },
         );
 
@@ -229,6 +506,7 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
                 max_tokens: 131072,
                 max_output_tokens: Some(4096),
                 max_completion_tokens: Some(4096),
+                system_prompt_role: None,
             },
         );
         
@@ -240,6 +518,7 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
                 max_tokens: 131072,
                 max_output_tokens: Some(4096),
                 max_completion_tokens: Some(4096),
+                system_prompt_role: None,
             },
         );
 
@@ -252,6 +531,7 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
                 max_tokens: 131072,
                 max_output_tokens: Some(4096),
                 max_completion_tokens: Some(4096),
+                system_prompt_role: None,
             },
         );
         
@@ -263,6 +543,7 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
                 max_tokens: 131072,
                 max_output_tokens: Some(4096),
                 max_completion_tokens: Some(4096),
+                system_prompt_role: None,
             },
         );
 
@@ -275,6 +556,7 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
                 max_tokens: 131072,
                 max_output_tokens: Some(4096),
                 max_completion_tokens: Some(4096),
+                system_prompt_role: None,
             },
         );
 
@@ -287,6 +569,7 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
                 max_tokens: 32768,
                 max_output_tokens: Some(4096),
                 max_completion_tokens: Some(4096),
+                system_prompt_role: None,
             },
         );
 
@@ -303,13 +586,14 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
                     max_tokens: model.max_tokens,
                     max_output_tokens: model.max_output_tokens,
                     max_completion_tokens: model.max_completion_tokens,
+                    system_prompt_role: None,
                 },
             );
         }
 
         models
             .into_values()
-            .map(|model| self.create_language_model(model))
+            .map(|model| self.create_language_model(model, cx))
             .collect()
     }
 
@@ -317,6 +601,10 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
         self.state.read(cx).is_authenticated()
     }
 
+    fn credentials_rejected(&self, cx: &App) -> bool {
+        self.state.read(cx).api_key_status.is_rejected()
+    }
+
     fn authenticate(&self, cx: &mut App) -> Task<Result<(), AuthenticateError>> {
         self.state.update(cx, |state, cx| state.authenticate(cx))
     }
@@ -327,13 +615,18 @@ impl LanguageModelProvider for GrokLanguageModelProvider {
     }
 
     fn reset_credentials(&self, cx: &mut App) -> Task<Result<()>> {
-        self.state.update(cx, |state, cx| state.reset_api_key(cx))
+        self.state
+            .update(cx, |state, cx| state.reset_api_key(None, cx))
     }
 }
 
 pub struct GrokLanguageModel {
     id: LanguageModelId,
     model: open_ai::Model,
+    supports_tools: bool,
+    supports_images: bool,
+    supports_thinking: bool,
+    supports_parallel_tool_calls: bool,
     state: gpui::Entity<State>,
     http_client: Arc<dyn HttpClient>,
     request_limiter: RateLimiter,
@@ -342,28 +635,49 @@ pub struct GrokLanguageModel {
 impl GrokLanguageModel {
     fn stream_completion(
         &self,
-        request: open_ai::Request,
+        mut request: open_ai::Request,
+        output_tokens: OutputTokenClamp,
+        usage: KeyUsageRecorder,
+        workspace: Option<WorkspaceScope>,
         cx: &AsyncApp,
     ) -> BoxFuture<'static, Result<futures::stream::BoxStream<'static, Result<ResponseStreamEvent>>>>
     {
         let http_client = self.http_client.clone();
-        let Ok((api_key, api_url)) = cx.read_entity(&self.state, |state, cx| {
+        let Ok((api_keys, rotation, api_url)) = cx.read_entity(&self.state, |state, cx| {
             let settings = &AllLanguageModelSettings::get_global(cx).grok;
-            (state.api_key.clone(), settings.api_url.clone())
+            (
+                state.workspace_api_keys.for_request(
+                    &state.api_keys,
+                    &state.api_key_status,
+                    &state.api_key_source,
+                    workspace.as_ref(),
+                    PROVIDER_ID,
+                    &settings.api_url,
+                    cx,
+                ),
+                settings.api.api_key_rotation,
+                settings.api_url.clone(),
+            )
         }) else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
 
         let future = self.request_limiter.stream(async move {
-            let api_key = api_key.context("Missing Grok API Key")?;
+            let api_keys = api_keys.await;
+            if let Some(max_tokens) = request.max_tokens {
+                request.max_tokens = Some(output_tokens.apply(max_tokens).await);
+            }
             let api_url = if api_url.is_empty() {
                 "https://api.x.ai/v1".to_string()
             } else {
                 api_url
             };
-            let request = stream_completion(http_client.as_ref(), &api_url, &api_key, request);
-            let response = request.await?;
-            Ok(response)
+            let (http_client, api_url, request) = (http_client.as_ref(), &api_url, &request);
+            api_keys
+                .with_rotation(rotation, "Grok", &usage, |api_key| async move {
+                    stream_completion(http_client, api_url, &api_key, request.clone()).await
+                })
+                .await
         });
 
         async move { Ok(future.await?.boxed()) }.boxed()
@@ -388,26 +702,55 @@ impl LanguageModel for GrokLanguageModel {
     }
 
     fn supports_tools(&self) -> bool {
-        true
+        self.supports_tools
     }
 
     fn supports_images(&self) -> bool {
-        // Only Grok 2 Vision models support images
-        self.model.id().contains("vision")
+        self.supports_images
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        ImageLimits {
+            max_bytes: MAX_IMAGE_BYTES,
+            formats: &[ImageFormat::Png, ImageFormat::Jpeg],
+            ..ImageLimits::default()
+        }
     }
 
     fn supports_tool_choice(&self, choice: LanguageModelToolChoice) -> bool {
         match choice {
-            LanguageModelToolChoice::Auto => true,
-            LanguageModelToolChoice::Any => true,
-            LanguageModelToolChoice::None => true,
+            LanguageModelToolChoice::Auto
+            | LanguageModelToolChoice::Any
+            | LanguageModelToolChoice::None
+            | LanguageModelToolChoice::Tool(_) => self.supports_tools,
         }
     }
 
+    fn tool_input_format(&self) -> LanguageModelToolSchemaFormat {
+        LanguageModelToolSchemaFormat::JsonSchemaDraft7
+    }
+
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.supports_parallel_tool_calls
+    }
+
+    fn supports_thinking(&self) -> bool {
+        self.supports_thinking
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        StructuredOutput::Native
+    }
+
     fn telemetry_id(&self) -> String {
         format!("grok/{}", self.model.id())
     }
 
+    fn price(&self, cx: &App) -> Option<ModelPrice> {
+        let settings = AllLanguageModelSettings::get_global(cx);
+        model_price(settings, PROVIDER_ID, &self.id.0)
+    }
+
     fn max_token_count(&self) -> usize {
         self.model.max_token_count()
     }
@@ -437,14 +780,59 @@ impl LanguageModel for GrokLanguageModel {
             >,
         >,
     > {
-        let request = into_open_ai(request, &self.model, self.max_output_tokens());
-        let completions = self.stream_completion(request, cx);
-        async move {
-            let mapper = OpenAiEventMapper::new();
-            let stream = mapper.map_stream(completions.await?);
-            Ok(stream.boxed())
+        let model_id = self.id();
+        let (sampling, reasoning, extra_body) =
+            AllLanguageModelSettings::try_read_global(cx, |settings| {
+                settings
+                    .grok
+                    .available_models
+                    .iter()
+                    .find(|model| model_id.0 == model.name)
+                    .map(|model| (model.sampling, model.reasoning, model.extra_body.clone()))
+            })
+            .flatten()
+            .unwrap_or_default();
+        let system_prompt = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings.grok.api.system_prompt.clone()
+        })
+        .unwrap_or_default();
+        let request = system_prompt.apply(sampling.apply(request));
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
+        let moderation = check_moderation(&request, cx);
+        let output_tokens = OutputTokenClamp::new(self, &request, cx);
+        let requested_parallel_tool_calls = request.parallel_tool_calls;
+        let workspace = request.workspace.clone();
+        let mut request = into_open_ai(
+            request,
+            &self.model,
+            self.max_output_tokens(),
+            MAX_IMAGE_BYTES,
+        );
+        if self.supports_thinking {
+            request.reasoning_effort = reasoning.effort.map(Into::into);
+        }
+        if self.supports_parallel_tool_calls && !request.tools.is_empty() {
+            request.parallel_tool_calls = Some(requested_parallel_tool_calls.unwrap_or(false));
         }
-        .boxed()
+        request.extra_body = extra_body.unwrap_or_default();
+        let usage =
+            KeyUsageRecorder::new(PROVIDER_ID, self.id.0.to_string(), workspace.clone(), cx);
+        let timeouts = StreamTimeouts::new(|settings| &settings.grok.api, cx);
+        let spend_limits = check_spend_limits(PROVIDER_ID, cx);
+        let completions =
+            self.stream_completion(request, output_tokens, usage.clone(), workspace, cx);
+        request_log.track(
+            async move {
+                spend_limits?;
+                moderation.await?;
+                let mapper = OpenAiEventMapper::new();
+                let stream = mapper.map_stream(completions.await?);
+                Ok(usage.track(
+                    timeouts.apply(estimate_live_usage(normalize_reasoning(stream.boxed()))),
+                ))
+            }
+            .boxed(),
+        )
     }
 }
 
@@ -452,6 +840,9 @@ struct ConfigurationView {
     api_key_editor: Entity<Editor>,
     state: gpui::Entity<State>,
     load_credentials_task: Option<Task<()>>,
+    validation: ApiKeyValidation,
+    save_scope: ApiKeySaveScope,
+    key_display: ApiKeyDisplay,
 }
 
 impl ConfigurationView {
@@ -490,6 +881,9 @@ impl ConfigurationView {
             api_key_editor,
             state,
             load_credentials_task,
+            validation: ApiKeyValidation::default(),
+            save_scope: ApiKeySaveScope::new(window, cx),
+            key_display: ApiKeyDisplay::default(),
         }
     }
 
@@ -499,10 +893,37 @@ impl ConfigurationView {
             return;
         }
 
+        self.validation = ApiKeyValidation::Validating;
+        let workspace_scope = self.save_scope.workspace_scope();
         let state = self.state.clone();
-        cx.spawn_in(window, async move |_, cx| {
+        cx.spawn_in(window, async move |this, cx| {
+            let result = state
+                .update(cx, |state, cx| {
+                    state.set_api_key(api_key, workspace_scope, cx)
+                })?
+                .await;
+            this.update(cx, |this, cx| {
+                this.validation = ApiKeyValidation::from_result(&result);
+                if result.is_ok() {
+                    this.key_display.finish_replacing();
+                }
+                cx.notify();
+            })
+        })
+        .detach_and_log_err(cx);
+
+        cx.notify();
+    }
+
+    fn reset_api_key(&mut self, window: &mut Window, cx: &mut Context<Self>) {
+        self.api_key_editor
+            .update(cx, |editor, cx| editor.set_text("", window, cx));
+
+        let workspace = self.api_keys(cx).1.workspace().cloned();
+        let state = self.state.clone();
+        cx.spawn(async move |_, cx| {
             state
-                .update(cx, |state, cx| state.set_api_key(api_key, cx))?
+                .update(cx, |state, cx| state.reset_api_key(workspace, cx))?
                 .await
         })
         .detach_and_log_err(cx);
@@ -535,14 +956,31 @@ impl ConfigurationView {
         )
     }
 
+    /// The keys that the view shows, which are the workspace's own if it has any.
+    fn api_keys(&self, cx: &App) -> (ApiKeys, ApiKeySource) {
+        let state = self.state.read(cx);
+        state.workspace_api_keys.for_display(
+            &state.api_keys,
+            &state.api_key_source,
+            self.save_scope.workspace(),
+        )
+    }
+
     fn should_render_editor(&self, cx: &Context<Self>) -> bool {
-        !self.state.read(cx).api_key_from_env
+        !self.state.read(cx).is_authenticated() || self.key_display.is_replacing()
     }
 }
 
 impl Render for ConfigurationView {
     fn render(&mut self, _: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let env_var_set = self.state.read(cx).api_key_from_env;
+        let (api_keys, api_key_source) = self.api_keys(cx);
+        let rejected = self.state.read(cx).api_key_status.is_rejected();
+        let env_var = AllLanguageModelSettings::get_global(cx)
+            .grok
+            .api
+            .env_var(XAI_API_KEY_VAR)
+            .to_string();
+        let key_is_external = api_key_source.is_external();
 
         if self.load_credentials_task.is_some() {
             div().child(Label::new("Loading credentials...")).into_any()
@@ -577,21 +1015,48 @@ impl Render for ConfigurationView {
                         .rounded_sm()
                         .child(self.render_api_key_editor(cx)),
                 )
+                .children(self.save_scope.render(cx, |this| &mut this.save_scope))
+                .children(self.key_display.render_cancel(cx, |this| &mut this.key_display))
+                .children(self.validation.render())
                 .child(
                     Label::new(
-                        format!("You can also assign the {XAI_API_KEY_VAR} environment variable and restart Zed."),
+                        format!("You can also assign the {env_var} environment variable and restart Zed."),
                     )
                     .size(LabelSize::Small).color(Color::Muted),
                 )
                 .into_any()
         } else {
-            v_flex()
+            h_flex()
+                .mt_1()
+                .p_1()
+                .justify_between()
+                .rounded_md()
+                .border_1()
+                .border_color(cx.theme().colors().border)
+                .bg(cx.theme().colors().background)
+                .child(
+                    h_flex()
+                        .gap_1()
+                        .child(Icon::new(IconName::Check).color(Color::Success))
+                        .child(self.key_display.render(
+                            PROVIDER_ID,
+                            &api_keys,
+                            rejected,
+                            &api_key_source,
+                            cx,
+                            |this| &mut this.key_display,
+                        )),
+                )
                 .child(
-                    Label::new(if env_var_set {
-                        format!("You're using the Grok API key from the {XAI_API_KEY_VAR} environment variable.")
-                    } else {
-                        "You're authenticated with the Grok API.".to_string()
-                    })
+                    Button::new("reset-key", "Reset Key")
+                        .label_size(LabelSize::Small)
+                        .icon(Some(IconName::Trash))
+                        .icon_size(IconSize::Small)
+                        .icon_position(IconPosition::Start)
+                        .disabled(key_is_external)
+                        .on_click(
+                            cx.listener(|this, _, window, cx| this.reset_api_key(window, cx)),
+                        ),
                 )
                 .into_any()
         }
@@ -27,20 +27,26 @@ use std::{collections::BTreeMap, sync::Arc};
 use ui::{ButtonLike, Indicator, List, prelude::*};
 use util::ResultExt;
 
-use crate::AllLanguageModelSettings;
+use crate::live_usage::estimate_live_usage;
+use crate::partial_tool_use::partial_tool_use;
+use crate::provider::open_ai::stop_reason_for_finish_reason;
+use crate::reasoning::normalize_reasoning;
+use crate::request_log::RequestLogRecorder;
 use crate::ui::InstructionListItem;
+use crate::{AllLanguageModelSettings, SamplingParameters, SystemPromptOverrides};
 
 const LMSTUDIO_DOWNLOAD_URL: &str = "https://lmstudio.ai/download";
 const LMSTUDIO_CATALOG_URL: &str = "https://lmstudio.ai/models";
 const LMSTUDIO_SITE: &str = "https://lmstudio.ai/";
 
-const PROVIDER_ID: &str = "lmstudio";
+pub(crate) const PROVIDER_ID: &str = "lmstudio";
 const PROVIDER_NAME: &str = "LM Studio";
 
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct LmStudioSettings {
     pub api_url: String,
     pub available_models: Vec<AvailableModel>,
+    pub system_prompt: SystemPromptOverrides,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -48,7 +54,22 @@ pub struct AvailableModel {
     pub name: String,
     pub display_name: Option<String>,
     pub max_tokens: usize,
+    /// Whether the model can call tools. Can also be written as `supports_tools`.
+    #[serde(alias = "supports_tools")]
     pub supports_tool_calls: bool,
+    /// Whether the model reasons before it responds, like DeepSeek R1 and Qwen 3.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub supports_thinking: Option<bool>,
+    /// Whether the model can call several tools in one response.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub supports_parallel_tool_calls: Option<bool>,
+    /// Sampling parameters for requests to this model.
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
 }
 
 pub struct LmStudioLanguageModelProvider {
@@ -203,14 +224,28 @@ impl LanguageModelProvider for LmStudioLanguageModelProvider {
             );
         }
 
+        let settings = &AllLanguageModelSettings::get_global(cx).lmstudio;
         models
             .into_values()
             .map(|model| {
+                let available_model = settings
+                    .available_models
+                    .iter()
+                    .find(|available_model| available_model.name == model.name);
                 Arc::new(LmStudioLanguageModel {
                     id: LanguageModelId::from(model.name.clone()),
                     model: model.clone(),
+                    supports_thinking: available_model
+                        .and_then(|available_model| available_model.supports_thinking)
+                        .unwrap_or(false),
+                    supports_parallel_tool_calls: available_model
+                        .and_then(|available_model| available_model.supports_parallel_tool_calls)
+                        .unwrap_or(false),
                     http_client: self.http_client.clone(),
-                    request_limiter: RateLimiter::new(4),
+                    request_limiter: RateLimiter::new(
+                        AllLanguageModelSettings::get_global(cx)
+                            .max_concurrent_requests(PROVIDER_ID),
+                    ),
                 }) as Arc<dyn LanguageModel>
             })
             .collect()
@@ -246,6 +281,8 @@ impl LanguageModelProvider for LmStudioLanguageModelProvider {
 pub struct LmStudioLanguageModel {
     id: LanguageModelId,
     model: lmstudio::Model,
+    supports_thinking: bool,
+    supports_parallel_tool_calls: bool,
     http_client: Arc<dyn HttpClient>,
     request_limiter: RateLimiter,
 }
@@ -266,8 +303,22 @@ impl LmStudioLanguageModel {
                             },
                             Role::System => ChatMessage::System { content: text },
                         }),
+                    MessageContent::Document(document) => {
+                        let content = document.to_text();
+                        messages.push(match message.role {
+                            Role::User => ChatMessage::User { content },
+                            Role::Assistant => ChatMessage::Assistant {
+                                content: Some(content),
+                                tool_calls: Vec::new(),
+                            },
+                            Role::System => ChatMessage::System { content },
+                        })
+                    }
                     MessageContent::RedactedThinking(_) => {}
-                    MessageContent::Image(_) => {}
+                    MessageContent::Image(_)
+                    | MessageContent::Pdf(_)
+                    | MessageContent::Audio(_)
+                    | MessageContent::Video(_) => {}
                     MessageContent::ToolUse(tool_use) => {
                         let tool_call = lmstudio::ToolCall {
                             id: tool_use.id.to_string(),
@@ -292,17 +343,17 @@ impl LmStudioLanguageModel {
                         }
                     }
                     MessageContent::ToolResult(tool_result) => {
-                        match &tool_result.content {
-                            LanguageModelToolResultContent::Text(text) => {
-                                messages.push(lmstudio::ChatMessage::Tool {
-                                    content: text.to_string(),
-                                    tool_call_id: tool_result.tool_use_id.to_string(),
-                                });
-                            }
+                        let content = match &tool_result.content {
+                            LanguageModelToolResultContent::Text(text) => text.to_string(),
+                            // Every tool call needs a result, even when its image can't be sent.
                             LanguageModelToolResultContent::Image(_) => {
-                                // no support for images for now
+                                "[Image omitted because this model can't see images]".to_string()
                             }
                         };
+                        messages.push(lmstudio::ChatMessage::Tool {
+                            content,
+                            tool_call_id: tool_result.tool_use_id.to_string(),
+                        });
                     }
                 }
             }
@@ -312,12 +363,20 @@ impl LmStudioLanguageModel {
             model: self.model.name.clone(),
             messages,
             stream: true,
+            stream_options: Some(lmstudio::StreamOptions {
+                include_usage: true,
+            }),
             max_tokens: Some(-1),
             stop: Some(request.stop),
             // In LM Studio you can configure specific settings you'd like to use for your model.
             // For example Qwen3 is recommended to be used with 0.7 temperature.
             // It would be a bad UX to silently override these settings from Zed, so we pass no temperature as a default.
             temperature: request.temperature.or(None),
+            seed: request.seed,
+            top_p: request.top_p,
+            top_k: request.top_k,
+            frequency_penalty: request.frequency_penalty,
+            presence_penalty: request.presence_penalty,
             tools: request
                 .tools
                 .into_iter()
@@ -333,6 +392,15 @@ impl LmStudioLanguageModel {
                 LanguageModelToolChoice::Auto => lmstudio::ToolChoice::Auto,
                 LanguageModelToolChoice::Any => lmstudio::ToolChoice::Required,
                 LanguageModelToolChoice::None => lmstudio::ToolChoice::None,
+                LanguageModelToolChoice::Tool(name) => {
+                    lmstudio::ToolChoice::Other(lmstudio::ToolDefinition::Function {
+                        function: lmstudio::FunctionDefinition {
+                            name,
+                            description: None,
+                            parameters: None,
+                        },
+                    })
+                }
             }),
         }
     }
@@ -388,6 +456,7 @@ impl LanguageModel for LmStudioLanguageModel {
                 LanguageModelToolChoice::Auto => true,
                 LanguageModelToolChoice::Any => true,
                 LanguageModelToolChoice::None => true,
+                LanguageModelToolChoice::Tool(_) => true,
             }
     }
 
@@ -395,6 +464,14 @@ impl LanguageModel for LmStudioLanguageModel {
         false
     }
 
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.supports_parallel_tool_calls
+    }
+
+    fn supports_thinking(&self) -> bool {
+        self.supports_thinking
+    }
+
     fn telemetry_id(&self) -> String {
         format!("lmstudio/{}", self.model.id())
     }
@@ -429,13 +506,34 @@ impl LanguageModel for LmStudioLanguageModel {
             BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
         >,
     > {
+        let model_id = self.id();
+        let sampling = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings
+                .lmstudio
+                .available_models
+                .iter()
+                .find(|model| model_id.0 == model.name)
+                .map(|model| model.sampling)
+        })
+        .flatten()
+        .unwrap_or_default();
+        let system_prompt = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings.lmstudio.system_prompt.clone()
+        })
+        .unwrap_or_default();
+        let request = system_prompt.apply(sampling.apply(request));
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
         let request = self.to_lmstudio_request(request);
         let completions = self.stream_completion(request, cx);
-        async move {
-            let mapper = LmStudioEventMapper::new();
-            Ok(mapper.map_stream(completions.await?).boxed())
-        }
-        .boxed()
+        request_log.track(
+            async move {
+                let mapper = LmStudioEventMapper::new();
+                Ok(estimate_live_usage(normalize_reasoning(
+                    mapper.map_stream(completions.await?).boxed(),
+                )))
+            }
+            .boxed(),
+        )
     }
 }
 
@@ -467,13 +565,37 @@ impl LmStudioEventMapper {
         &mut self,
         event: ResponseStreamEvent,
     ) -> Vec<Result<LanguageModelCompletionEvent, LanguageModelCompletionError>> {
+        let mut events = Vec::new();
+        if let Some(usage) = event.usage {
+            events.push(Ok(LanguageModelCompletionEvent::UsageUpdate(
+                language_model::TokenUsage {
+                    input_tokens: usage.prompt_tokens,
+                    output_tokens: usage.completion_tokens,
+                    cache_creation_input_tokens: 0,
+                    cache_read_input_tokens: 0,
+                    reasoning_tokens: 0,
+                },
+            )));
+        }
+
         let Some(choice) = event.choices.into_iter().next() else {
-            return vec![Err(LanguageModelCompletionError::Other(anyhow!(
-                "Response contained no choices"
-            )))];
+            if events.is_empty() {
+                events.push(Err(LanguageModelCompletionError::Other(anyhow!(
+                    "Response contained no choices"
+                ))));
+            }
+            return events;
         };
-
-        let mut events = Vec::new();
+        if let Some(reasoning) = choice
+            .delta
+            .reasoning_content
+            .filter(|text| !text.is_empty())
+        {
+            events.push(Ok(LanguageModelCompletionEvent::Thinking {
+                text: reasoning,
+                signature: None,
+            }));
+        }
         if let Some(content) = choice.delta.content {
             events.push(Ok(LanguageModelCompletionEvent::Text(content)));
         }
@@ -498,17 +620,17 @@ impl LmStudioEventMapper {
                         }
                     }
 
-                    if let Some(arguments) = function.arguments {
+                    if let Some(arguments) = function.arguments.filter(|args| !args.is_empty()) {
                         entry.arguments.push_str(&arguments);
+                        events.extend(
+                            partial_tool_use(&entry.id, &entry.name, &entry.arguments).map(Ok),
+                        );
                     }
                 }
             }
         }
 
         match choice.finish_reason.as_deref() {
-            Some("stop") => {
-                events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::EndTurn)));
-            }
             Some("tool_calls") => {
                 events.extend(self.tool_calls_by_index.drain().map(|(_, tool_call)| {
                     match serde_json::Value::from_str(&tool_call.arguments) {
@@ -532,9 +654,13 @@ impl LmStudioEventMapper {
 
                 events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::ToolUse)));
             }
-            Some(stop_reason) => {
-                log::error!("Unexpected OpenAI stop_reason: {stop_reason:?}",);
-                events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::EndTurn)));
+            Some(finish_reason) => {
+                let stop_reason =
+                    stop_reason_for_finish_reason(finish_reason).unwrap_or_else(|| {
+                        log::error!("Unexpected LM Studio stop_reason: {finish_reason:?}");
+                        StopReason::EndTurn
+                    });
+                events.push(Ok(LanguageModelCompletionEvent::Stop(stop_reason)));
             }
             None => {}
         }
@@ -0,0 +1,1169 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context as _, Result};
+use collections::{HashMap, HashSet};
+use futures::{AsyncReadExt as _, AsyncWriteExt as _, FutureExt, StreamExt, future::BoxFuture};
+use gpui::{AnyView, App, AsyncApp, Context, Entity, Subscription, Task};
+use http_client::{AsyncBody, HttpClient, http::header::CONTENT_LENGTH};
+use language_model::{
+    AuthenticateError, ImageLimits, LanguageModel, LanguageModelCompletionError,
+    LanguageModelCompletionEvent, LanguageModelId, LanguageModelName, LanguageModelProvider,
+    LanguageModelProviderId, LanguageModelProviderName, LanguageModelProviderState,
+    LanguageModelRequest, LanguageModelToolChoice, RateLimiter,
+};
+use open_ai::{ResponseStreamEvent, stream_completion};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SettingsStore};
+use sha2::{Digest as _, Sha256};
+use smol::fs;
+use ui::{Indicator, List, prelude::*};
+use util::ResultExt;
+
+use crate::live_usage::estimate_live_usage;
+use crate::model_family::ModelFamily;
+use crate::provider::open_ai::{
+    MAX_IMAGE_BYTES, OPEN_AI_IMAGE_LIMITS, OpenAiEventMapper, count_open_ai_tokens, into_open_ai,
+};
+use crate::reasoning::normalize_reasoning;
+use crate::request_log::RequestLogRecorder;
+use crate::ui::InstructionListItem;
+use crate::{AllLanguageModelSettings, SamplingParameters, SystemPromptOverrides};
+
+pub(crate) const PROVIDER_ID: &str = "local";
+const PROVIDER_NAME: &str = "Local (Experimental)";
+
+const MISTRAL_RS_SITE: &str = "https://github.com/EricLBuehler/mistral.rs";
+const MISTRAL_RS_BINARY_NAME: &str = "mistralrs-server";
+
+/// How long to wait for the runtime to load a model before giving up.
+const SERVER_STARTUP_TIMEOUT: Duration = Duration::from_secs(300);
+/// How many times to start the runtime for a model before giving up. Another process can take
+/// the port that the runtime is given before the runtime binds it, in which case the runtime
+/// exits and is started again on another port.
+const MAX_SERVER_START_ATTEMPTS: usize = 3;
+const SERVER_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const DOWNLOAD_PROGRESS_INTERVAL: u64 = 8 * 1024 * 1024;
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct LocalSettings {
+    /// Path to the `mistralrs-server` binary. When unset, it is looked up on the `PATH`.
+    pub runtime_path: Option<PathBuf>,
+    /// Whether to run inference on the GPU. When unset, the GPU is used if one is detected.
+    pub use_gpu: Option<bool>,
+    pub available_models: Vec<AvailableModel>,
+    pub system_prompt: SystemPromptOverrides,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct AvailableModel {
+    /// The model's name. For safetensors models without a `path`, this is the Hugging Face repository id.
+    pub name: String,
+    /// The model's name in Zed's UI, such as in the model selector dropdown menu in the assistant panel.
+    pub display_name: Option<String>,
+    /// The format of the model weights.
+    #[serde(default)]
+    pub format: ModelFormat,
+    /// A URL to download a GGUF file from, such as a Hugging Face `resolve` link.
+    pub url: Option<String>,
+    /// The SHA-256 of the file at `url`, in hex, which the download is checked against.
+    #[serde(default)]
+    pub sha256: Option<String>,
+    /// A path to weights that already exist on disk: a GGUF file, or a directory of safetensors files.
+    pub path: Option<PathBuf>,
+    /// The model's context window size.
+    pub max_tokens: usize,
+    pub max_output_tokens: Option<u32>,
+    /// Whether the model supports tools.
+    pub supports_tools: Option<bool>,
+    /// Whether the model takes images as input.
+    pub supports_images: Option<bool>,
+    /// Whether the model reasons before it responds, like DeepSeek R1 and Qwen 3.
+    #[serde(default)]
+    pub supports_thinking: Option<bool>,
+    /// Whether the model can call several tools in one response.
+    #[serde(default)]
+    pub supports_parallel_tool_calls: Option<bool>,
+    /// Sampling parameters for requests to this model.
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelFormat {
+    #[default]
+    Gguf,
+    Safetensors,
+}
+
+impl AvailableModel {
+    fn display_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Returns where the weights for this model live on disk, if they are managed by Zed or
+    /// configured explicitly.
+    fn weights_path(&self) -> Option<PathBuf> {
+        if let Some(path) = self.path.clone() {
+            return Some(path);
+        }
+        let url = self.url.as_ref()?;
+        let file_name = url
+            .split(['?', '#'])
+            .next()?
+            .rsplit('/')
+            .next()
+            .filter(|name| !name.is_empty())?;
+        Some(paths::local_models_dir().join(file_name))
+    }
+
+    fn to_open_ai_model(&self) -> open_ai::Model {
+        open_ai::Model::Custom {
+            name: self.name.clone(),
+            display_name: self.display_name.clone(),
+            max_tokens: self.max_tokens,
+            max_output_tokens: self.max_output_tokens,
+            max_completion_tokens: self.max_output_tokens,
+            system_prompt_role: None,
+        }
+    }
+}
+
+/// The hardware the runtime will use for inference.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Accelerator {
+    Metal,
+    Cuda,
+    Cpu,
+}
+
+impl Accelerator {
+    fn detect() -> Self {
+        if cfg!(all(target_os = "macos", target_arch = "aarch64")) {
+            return Self::Metal;
+        }
+
+        let has_nvidia_gpu = util::command::new_std_command("nvidia-smi")
+            .arg("--list-gpus")
+            .output()
+            .map_or(false, |output| {
+                output.status.success() && !output.stdout.is_empty()
+            });
+        if has_nvidia_gpu {
+            Self::Cuda
+        } else {
+            Self::Cpu
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Self::Metal => "Metal",
+            Self::Cuda => "CUDA",
+            Self::Cpu => "CPU",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum DownloadStatus {
+    Downloading { downloaded: u64, total: Option<u64> },
+    Failed(SharedString),
+}
+
+struct LocalServer {
+    model_name: String,
+    api_url: String,
+    child: smol::process::Child,
+    ready: bool,
+    /// Cloned into the [`ServerLease`] of every request to this server.
+    lease: Arc<()>,
+}
+
+impl LocalServer {
+    /// Whether a request is still using this server, so that it can't be replaced by another
+    /// model's yet.
+    fn in_use(&self) -> bool {
+        Arc::strong_count(&self.lease) > 1
+    }
+}
+
+/// A runtime process that a request is using. Only one model's runtime runs at a time, so that
+/// two models never compete for GPU memory, and this keeps requests to other models from
+/// replacing it until the request finishes.
+struct ServerLease {
+    api_url: String,
+    _lease: Arc<()>,
+}
+
+/// The next step towards a runtime process that serves a model.
+enum ServerStatus {
+    Ready(ServerLease),
+    Loading(ServerLease),
+    /// Another model's runtime is still being used.
+    Busy,
+}
+
+pub struct LocalLanguageModelProvider {
+    http_client: Arc<dyn HttpClient>,
+    state: Entity<State>,
+}
+
+pub struct State {
+    http_client: Arc<dyn HttpClient>,
+    runtime_path: Option<PathBuf>,
+    accelerator: Option<Accelerator>,
+    installed_models: HashSet<String>,
+    downloads: HashMap<String, DownloadStatus>,
+    download_tasks: HashMap<String, Task<()>>,
+    server: Option<LocalServer>,
+    /// Shared by all local models, since they're served by the same runtime process.
+    request_limiter: RateLimiter,
+    request_limit: usize,
+    _detect_task: Task<()>,
+    _subscription: Subscription,
+}
+
+impl State {
+    fn is_authenticated(&self) -> bool {
+        self.runtime_path.is_some()
+    }
+
+    fn authenticate(&mut self, cx: &mut Context<Self>) -> Task<Result<(), AuthenticateError>> {
+        if self.is_authenticated() {
+            return Task::ready(Ok(()));
+        }
+
+        let configured_path = AllLanguageModelSettings::get_global(cx)
+            .local
+            .runtime_path
+            .clone();
+        cx.spawn(async move |this, cx| {
+            let runtime_path = cx
+                .background_spawn(async move {
+                    if let Some(path) = configured_path {
+                        return Ok(path.is_file().then_some(path));
+                    }
+                    anyhow::Ok(which::which(MISTRAL_RS_BINARY_NAME).ok())
+                })
+                .await?
+                .ok_or(AuthenticateError::CredentialsNotFound)?;
+
+            this.update(cx, |this, cx| {
+                this.runtime_path = Some(runtime_path);
+                cx.notify();
+            })?;
+            Ok(())
+        })
+    }
+
+    fn refresh_installed_models(&mut self, cx: &mut Context<Self>) {
+        let models = AllLanguageModelSettings::get_global(cx)
+            .local
+            .available_models
+            .clone();
+        cx.spawn(async move |this, cx| {
+            let mut installed_models = HashSet::default();
+            for model in models {
+                if let Some(path) = model.weights_path() {
+                    if fs::metadata(&path).await.is_ok() {
+                        installed_models.insert(model.name);
+                    }
+                } else if model.format == ModelFormat::Safetensors {
+                    // The runtime fetches safetensors weights from Hugging Face by itself.
+                    installed_models.insert(model.name);
+                }
+            }
+            this.update(cx, |this, cx| {
+                this.installed_models = installed_models;
+                cx.notify();
+            })
+            .ok();
+        })
+        .detach();
+    }
+
+    fn download_model(&mut self, model: AvailableModel, cx: &mut Context<Self>) {
+        let (Some(url), Some(destination)) = (model.url.clone(), model.weights_path()) else {
+            return;
+        };
+
+        let name = model.name;
+        let sha256 = model.sha256;
+        let http_client = self.http_client.clone();
+        self.downloads.insert(
+            name.clone(),
+            DownloadStatus::Downloading {
+                downloaded: 0,
+                total: None,
+            },
+        );
+        cx.notify();
+
+        let task = cx.spawn({
+            let name = name.clone();
+            async move |this, cx| {
+                let result = async {
+                    if let Some(parent) = destination.parent() {
+                        fs::create_dir_all(parent).await?;
+                    }
+
+                    let response = http_client
+                        .get(&url, AsyncBody::default(), true)
+                        .await
+                        .with_context(|| format!("failed to download {url}"))?;
+                    anyhow::ensure!(
+                        response.status().is_success(),
+                        "failed to download {url}: {}",
+                        response.status()
+                    );
+                    let total = response
+                        .headers()
+                        .get(CONTENT_LENGTH)
+                        .and_then(|value| value.to_str().ok()?.parse().ok());
+
+                    let mut partial = PartialDownload {
+                        path: destination.with_extension("partial"),
+                        finished: false,
+                    };
+                    let mut file = fs::File::create(&partial.path).await?;
+                    let mut body = response.into_body();
+                    let mut buffer = vec![0; 64 * 1024];
+                    let mut hasher = Sha256::new();
+                    let mut downloaded = 0u64;
+                    let mut last_reported = 0u64;
+                    loop {
+                        let bytes_read = body.read(&mut buffer).await?;
+                        if bytes_read == 0 {
+                            break;
+                        }
+                        file.write_all(&buffer[..bytes_read]).await?;
+                        hasher.update(&buffer[..bytes_read]);
+                        downloaded += bytes_read as u64;
+
+                        if downloaded - last_reported >= DOWNLOAD_PROGRESS_INTERVAL {
+                            last_reported = downloaded;
+                            this.update(cx, |this, cx| {
+                                this.downloads.insert(
+                                    name.clone(),
+                                    DownloadStatus::Downloading { downloaded, total },
+                                );
+                                cx.notify();
+                            })?;
+                        }
+                    }
+                    file.flush().await?;
+                    check_download(downloaded, total, sha256.as_deref(), &hasher.finalize())
+                        .with_context(|| format!("failed to download {url}"))?;
+                    fs::rename(&partial.path, &destination).await?;
+                    partial.finished = true;
+                    anyhow::Ok(())
+                }
+                .await;
+
+                this.update(cx, |this, cx| {
+                    this.download_tasks.remove(&name);
+                    match result {
+                        Ok(()) => {
+                            this.downloads.remove(&name);
+                            this.installed_models.insert(name);
+                        }
+                        Err(error) => {
+                            log::error!("failed to download local model {name}: {error:?}");
+                            this.downloads
+                                .insert(name, DownloadStatus::Failed(error.to_string().into()));
+                        }
+                    }
+                    cx.notify();
+                })
+                .ok();
+            }
+        });
+        self.download_tasks.insert(name, task);
+    }
+
+    fn delete_model(&mut self, model: AvailableModel, cx: &mut Context<Self>) {
+        if model.path.is_some() {
+            // Never delete weights that the user pointed us at.
+            return;
+        }
+        let Some(path) = model.weights_path() else {
+            return;
+        };
+
+        if self
+            .server
+            .as_ref()
+            .map_or(false, |server| server.model_name == model.name)
+        {
+            self.server = None;
+        }
+
+        cx.spawn(async move |this, cx| {
+            fs::remove_file(&path).await?;
+            this.update(cx, |this, cx| {
+                this.installed_models.remove(&model.name);
+                cx.notify();
+            })
+        })
+        .detach_and_log_err(cx);
+    }
+
+    /// Makes sure a runtime process is serving the given model, returning a lease on it once it
+    /// is ready to accept requests.
+    fn ensure_server(
+        &mut self,
+        model: &AvailableModel,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<ServerLease>> {
+        let model = model.clone();
+        let http_client = self.http_client.clone();
+        cx.spawn(async move |this, cx| {
+            let mut start_attempts = 0;
+            let mut loading_since = None;
+            loop {
+                let status = this.update(cx, |this, cx| {
+                    this.server_status(&model, &mut start_attempts, cx)
+                })??;
+                let lease = match status {
+                    ServerStatus::Ready(lease) => return Ok(lease),
+                    ServerStatus::Loading(lease) => lease,
+                    ServerStatus::Busy => {
+                        cx.background_executor().timer(SERVER_POLL_INTERVAL).await;
+                        continue;
+                    }
+                };
+
+                let models_url = format!("{}/models", lease.api_url);
+                if let Ok(response) = http_client
+                    .get(&models_url, AsyncBody::default(), false)
+                    .await
+                {
+                    if response.status().is_success() {
+                        this.update(cx, |this, _| {
+                            if let Some(server) = this.server.as_mut() {
+                                server.ready = true;
+                            }
+                        })?;
+                        return Ok(lease);
+                    }
+                }
+                if loading_since.get_or_insert_with(Instant::now).elapsed() > SERVER_STARTUP_TIMEOUT
+                {
+                    anyhow::bail!(
+                        "timed out waiting for {MISTRAL_RS_BINARY_NAME} to load the model"
+                    );
+                }
+                cx.background_executor().timer(SERVER_POLL_INTERVAL).await;
+            }
+        })
+    }
+
+    fn server_status(
+        &mut self,
+        model: &AvailableModel,
+        start_attempts: &mut usize,
+        cx: &mut Context<Self>,
+    ) -> Result<ServerStatus> {
+        if let Some(server) = self.server.as_mut() {
+            if server.model_name != model.name {
+                if server.in_use() {
+                    return Ok(ServerStatus::Busy);
+                }
+            } else if matches!(server.child.try_status(), Ok(Some(_))) {
+                self.server = None;
+                if *start_attempts >= MAX_SERVER_START_ATTEMPTS {
+                    anyhow::bail!(
+                        "the {MISTRAL_RS_BINARY_NAME} process exited while loading the model"
+                    );
+                }
+            } else {
+                let lease = ServerLease {
+                    api_url: server.api_url.clone(),
+                    _lease: server.lease.clone(),
+                };
+                return Ok(if server.ready {
+                    ServerStatus::Ready(lease)
+                } else {
+                    ServerStatus::Loading(lease)
+                });
+            }
+        }
+
+        *start_attempts += 1;
+        let server = self.start_server(model, cx)?;
+        let lease = ServerLease {
+            api_url: server.api_url.clone(),
+            _lease: server.lease.clone(),
+        };
+        self.server = Some(server);
+        Ok(ServerStatus::Loading(lease))
+    }
+
+    /// Starts a runtime process for the given model, replacing the previous one, which must not
+    /// be in use.
+    fn start_server(
+        &mut self,
+        model: &AvailableModel,
+        cx: &mut Context<Self>,
+    ) -> Result<LocalServer> {
+        let runtime_path = self
+            .runtime_path
+            .clone()
+            .with_context(|| format!("{MISTRAL_RS_BINARY_NAME} was not found"))?;
+        let use_gpu = AllLanguageModelSettings::get_global(cx)
+            .local
+            .use_gpu
+            .unwrap_or_else(|| self.accelerator.map_or(false, |a| a != Accelerator::Cpu));
+
+        // Drop the previous process before starting a new one, so that two models never
+        // compete for GPU memory.
+        self.server = None;
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")?
+            .local_addr()?
+            .port();
+        let mut command = util::command::new_smol_command(runtime_path);
+        command.arg("--port").arg(port.to_string());
+        if !use_gpu {
+            command.arg("--cpu");
+        }
+
+        match model.format {
+            ModelFormat::Gguf => {
+                let weights_path = model
+                    .weights_path()
+                    .context("GGUF models must specify a `url` or a `path`")?;
+                if !self.installed_models.contains(&model.name) {
+                    anyhow::bail!(
+                        "{} has not been downloaded yet. Download it from the Local provider's configuration.",
+                        model.display_name()
+                    );
+                }
+                let directory = weights_path.parent().unwrap_or(Path::new("."));
+                let file_name = weights_path
+                    .file_name()
+                    .context("invalid GGUF model path")?;
+                command
+                    .arg("gguf")
+                    .arg("-m")
+                    .arg(directory)
+                    .arg("-f")
+                    .arg(file_name);
+            }
+            ModelFormat::Safetensors => {
+                let model_id = model
+                    .path
+                    .as_ref()
+                    .map(|path| path.as_os_str().to_owned())
+                    .unwrap_or_else(|| model.name.clone().into());
+                command.arg("plain").arg("-m").arg(model_id);
+            }
+        }
+
+        let child = command
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("failed to start {MISTRAL_RS_BINARY_NAME}"))?;
+
+        Ok(LocalServer {
+            model_name: model.name.clone(),
+            api_url: format!("http://127.0.0.1:{port}/v1"),
+            child,
+            ready: false,
+            lease: Arc::new(()),
+        })
+    }
+}
+
+/// A file that a model is being downloaded into, which is deleted if the download fails or is
+/// cancelled before it's moved into place.
+struct PartialDownload {
+    path: PathBuf,
+    finished: bool,
+}
+
+impl Drop for PartialDownload {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        if let Err(error) = std::fs::remove_file(&self.path) {
+            if error.kind() != std::io::ErrorKind::NotFound {
+                log::error!("failed to delete {}: {error}", self.path.display());
+            }
+        }
+    }
+}
+
+/// Checks that a download has the length that the server announced, and the SHA-256 that the
+/// model is configured with.
+fn check_download(
+    downloaded: u64,
+    total: Option<u64>,
+    expected_sha256: Option<&str>,
+    sha256: &[u8],
+) -> Result<()> {
+    if let Some(total) = total {
+        anyhow::ensure!(
+            downloaded == total,
+            "the download stopped after {downloaded} of {total} bytes"
+        );
+    }
+    if let Some(expected_sha256) = expected_sha256 {
+        let sha256 = sha256
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        anyhow::ensure!(
+            sha256.eq_ignore_ascii_case(expected_sha256.trim()),
+            "the file's SHA-256 is {sha256}, but {expected_sha256} was expected"
+        );
+    }
+    Ok(())
+}
+
+impl LocalLanguageModelProvider {
+    pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut App) -> Self {
+        let state = cx.new(|cx| {
+            let subscription = cx.observe_global::<SettingsStore>({
+                let mut settings = AllLanguageModelSettings::get_global(cx).local.clone();
+                move |this: &mut State, cx| {
+                    let request_limit = AllLanguageModelSettings::get_global(cx)
+                        .max_concurrent_requests(PROVIDER_ID);
+                    if this.request_limit != request_limit {
+                        this.request_limit = request_limit;
+                        this.request_limiter = RateLimiter::new(request_limit);
+                    }
+                    let new_settings = &AllLanguageModelSettings::get_global(cx).local;
+                    if &settings != new_settings {
+                        if settings.runtime_path != new_settings.runtime_path {
+                            this.runtime_path = None;
+                            this.server = None;
+                        }
+                        settings = new_settings.clone();
+                        this.refresh_installed_models(cx);
+                        cx.notify();
+                    }
+                }
+            });
+
+            let detect_task = cx.spawn(async move |this, cx| {
+                let accelerator = cx
+                    .background_spawn(async move { Accelerator::detect() })
+                    .await;
+                this.update(cx, |this, cx| {
+                    this.accelerator = Some(accelerator);
+                    cx.notify();
+                })
+                .ok();
+            });
+
+            let request_limit =
+                AllLanguageModelSettings::get_global(cx).max_concurrent_requests(PROVIDER_ID);
+            let mut state = State {
+                http_client: http_client.clone(),
+                runtime_path: None,
+                accelerator: None,
+                installed_models: HashSet::default(),
+                downloads: HashMap::default(),
+                download_tasks: HashMap::default(),
+                server: None,
+                request_limiter: RateLimiter::new(request_limit),
+                request_limit,
+                _detect_task: detect_task,
+                _subscription: subscription,
+            };
+            state.refresh_installed_models(cx);
+            state
+        });
+
+        Self { http_client, state }
+    }
+
+    fn create_language_model(&self, model: AvailableModel, cx: &App) -> Arc<dyn LanguageModel> {
+        Arc::new(LocalLanguageModel {
+            id: LanguageModelId::from(model.name.clone()),
+            open_ai_model: model.to_open_ai_model(),
+            family: ModelFamily::infer(&model.name),
+            model,
+            state: self.state.clone(),
+            http_client: self.http_client.clone(),
+            request_limiter: self.state.read(cx).request_limiter.clone(),
+        })
+    }
+}
+
+impl LanguageModelProviderState for LocalLanguageModelProvider {
+    type ObservableEntity = State;
+
+    fn observable_entity(&self) -> Option<Entity<Self::ObservableEntity>> {
+        Some(self.state.clone())
+    }
+}
+
+impl LanguageModelProvider for LocalLanguageModelProvider {
+    fn id(&self) -> LanguageModelProviderId {
+        LanguageModelProviderId(PROVIDER_ID.into())
+    }
+
+    fn name(&self) -> LanguageModelProviderName {
+        LanguageModelProviderName(PROVIDER_NAME.into())
+    }
+
+    fn icon(&self) -> IconName {
+        IconName::Server
+    }
+
+    fn default_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        self.provided_models(cx).into_iter().next()
+    }
+
+    fn default_fast_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        self.default_model(cx)
+    }
+
+    fn provided_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
+        let installed_models = &self.state.read(cx).installed_models;
+        AllLanguageModelSettings::get_global(cx)
+            .local
+            .available_models
+            .iter()
+            .filter(|model| installed_models.contains(&model.name))
+            .map(|model| self.create_language_model(model.clone(), cx))
+            .collect()
+    }
+
+    fn is_authenticated(&self, cx: &App) -> bool {
+        self.state.read(cx).is_authenticated()
+    }
+
+    fn authenticate(&self, cx: &mut App) -> Task<Result<(), AuthenticateError>> {
+        self.state.update(cx, |state, cx| state.authenticate(cx))
+    }
+
+    fn configuration_view(&self, window: &mut Window, cx: &mut App) -> AnyView {
+        cx.new(|cx| ConfigurationView::new(self.state.clone(), window, cx))
+            .into()
+    }
+
+    fn reset_credentials(&self, cx: &mut App) -> Task<Result<()>> {
+        self.state.update(cx, |state, cx| {
+            state.server = None;
+            state.runtime_path = None;
+            cx.notify();
+        });
+        Task::ready(Ok(()))
+    }
+}
+
+pub struct LocalLanguageModel {
+    id: LanguageModelId,
+    model: AvailableModel,
+    open_ai_model: open_ai::Model,
+    family: Option<ModelFamily>,
+    state: Entity<State>,
+    http_client: Arc<dyn HttpClient>,
+    request_limiter: RateLimiter,
+}
+
+impl LocalLanguageModel {
+    fn stream_completion(
+        &self,
+        request: open_ai::Request,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<futures::stream::BoxStream<'static, Result<ResponseStreamEvent>>>>
+    {
+        let http_client = self.http_client.clone();
+        let state = self.state.clone();
+        let model = self.model.clone();
+        let request_limiter = self.request_limiter.clone();
+        // The runtime is only started or switched to another model once the request gets its
+        // turn, so queued requests don't replace the process that running ones use.
+        let task = cx.spawn(async move |cx| {
+            let response = request_limiter
+                .stream(async move {
+                    let server = state
+                        .update(cx, |state, cx| state.ensure_server(&model, cx))?
+                        .await?;
+                    let response =
+                        stream_completion(http_client.as_ref(), &server.api_url, "", request)
+                            .await?;
+                    Ok(response.map(move |event| {
+                        let _ = &server;
+                        event
+                    }))
+                })
+                .await?;
+            anyhow::Ok(response.boxed())
+        });
+
+        task.boxed()
+    }
+}
+
+impl LanguageModel for LocalLanguageModel {
+    fn id(&self) -> LanguageModelId {
+        self.id.clone()
+    }
+
+    fn name(&self) -> LanguageModelName {
+        LanguageModelName::from(self.model.display_name().to_string())
+    }
+
+    fn provider_id(&self) -> LanguageModelProviderId {
+        LanguageModelProviderId(PROVIDER_ID.into())
+    }
+
+    fn provider_name(&self) -> LanguageModelProviderName {
+        LanguageModelProviderName(PROVIDER_NAME.into())
+    }
+
+    fn supports_tools(&self) -> bool {
+        self.model
+            .supports_tools
+            .unwrap_or_else(|| self.family.is_some_and(|family| family.supports_tools))
+    }
+
+    fn supports_images(&self) -> bool {
+        self.model
+            .supports_images
+            .unwrap_or_else(|| self.family.is_some_and(|family| family.supports_images))
+    }
+
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.model.supports_parallel_tool_calls.unwrap_or(false)
+    }
+
+    fn supports_thinking(&self) -> bool {
+        self.model
+            .supports_thinking
+            .unwrap_or_else(|| self.family.is_some_and(|family| family.supports_thinking))
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        OPEN_AI_IMAGE_LIMITS
+    }
+
+    fn supports_tool_choice(&self, choice: LanguageModelToolChoice) -> bool {
+        match choice {
+            LanguageModelToolChoice::Auto | LanguageModelToolChoice::None => self.supports_tools(),
+            LanguageModelToolChoice::Any | LanguageModelToolChoice::Tool(_) => false,
+        }
+    }
+
+    fn telemetry_id(&self) -> String {
+        format!("local/{}", self.model.name)
+    }
+
+    fn max_token_count(&self) -> usize {
+        self.model.max_tokens
+    }
+
+    fn max_output_tokens(&self) -> Option<u32> {
+        self.model.max_output_tokens
+    }
+
+    fn count_tokens(
+        &self,
+        request: LanguageModelRequest,
+        cx: &App,
+    ) -> BoxFuture<'static, Result<usize>> {
+        count_open_ai_tokens(request, self.open_ai_model.clone(), cx)
+    }
+
+    fn stream_completion(
+        &self,
+        request: LanguageModelRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<
+        'static,
+        Result<
+            futures::stream::BoxStream<
+                'static,
+                Result<LanguageModelCompletionEvent, LanguageModelCompletionError>,
+            >,
+        >,
+    > {
+        let model_id = self.id();
+        let sampling = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings
+                .local
+                .available_models
+                .iter()
+                .find(|model| model_id.0 == model.name)
+                .map(|model| model.sampling)
+        })
+        .flatten()
+        .unwrap_or_default();
+        let system_prompt = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings.local.system_prompt.clone()
+        })
+        .unwrap_or_default();
+        let request = system_prompt.apply(sampling.apply(request));
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
+        let mut request = into_open_ai(
+            request,
+            &self.open_ai_model,
+            self.max_output_tokens(),
+            MAX_IMAGE_BYTES,
+        );
+        request.response_format = None;
+        let completions = self.stream_completion(request, cx);
+        request_log.track(
+            async move {
+                let mapper = OpenAiEventMapper::new();
+                Ok(estimate_live_usage(normalize_reasoning(
+                    mapper.map_stream(completions.await?).boxed(),
+                )))
+            }
+            .boxed(),
+        )
+    }
+}
+
+struct ConfigurationView {
+    state: Entity<State>,
+    loading_runtime_task: Option<Task<()>>,
+}
+
+impl ConfigurationView {
+    fn new(state: Entity<State>, window: &mut Window, cx: &mut Context<Self>) -> Self {
+        cx.observe(&state, |_, _, cx| {
+            cx.notify();
+        })
+        .detach();
+
+        let loading_runtime_task = Some(cx.spawn_in(window, {
+            let state = state.clone();
+            async move |this, cx| {
+                if let Some(task) = state
+                    .update(cx, |state, cx| state.authenticate(cx))
+                    .log_err()
+                {
+                    // We don't log an error, because a missing runtime is also an error.
+                    let _ = task.await;
+                }
+                this.update(cx, |this, cx| {
+                    this.loading_runtime_task = None;
+                    cx.notify();
+                })
+                .log_err();
+            }
+        }));
+
+        Self {
+            state,
+            loading_runtime_task,
+        }
+    }
+
+    fn render_runtime_status(&self, cx: &mut Context<Self>) -> impl IntoElement {
+        let state = self.state.read(cx);
+        match state.runtime_path.as_ref() {
+            Some(runtime_path) => h_flex()
+                .gap_2()
+                .child(Indicator::dot().color(Color::Success))
+                .child(
+                    Label::new(format!("Using {}", runtime_path.display()))
+                        .size(LabelSize::Small),
+                )
+                .into_any_element(),
+            None => List::new()
+                .child(InstructionListItem::new(
+                    "Install the runtime from",
+                    Some("mistral.rs"),
+                    Some(MISTRAL_RS_SITE),
+                ))
+                .child(InstructionListItem::text_only(format!(
+                    "Make sure `{MISTRAL_RS_BINARY_NAME}` is on your PATH, or set `language_models.local.runtime_path`"
+                )))
+                .into_any_element(),
+        }
+    }
+
+    fn render_model(&self, model: &AvailableModel, cx: &mut Context<Self>) -> impl IntoElement {
+        let state = self.state.read(cx);
+        let name = model.name.clone();
+        let status = match state.downloads.get(&model.name) {
+            Some(DownloadStatus::Downloading { downloaded, total }) => {
+                let progress = match total {
+                    Some(total) if *total > 0 => {
+                        format!("Downloading… {}%", downloaded.saturating_mul(100) / total)
+                    }
+                    _ => format!("Downloading… {} MB", downloaded / (1024 * 1024)),
+                };
+                Label::new(progress)
+                    .size(LabelSize::Small)
+                    .color(Color::Muted)
+                    .into_any_element()
+            }
+            Some(DownloadStatus::Failed(error)) => h_flex()
+                .gap_1()
+                .child(
+                    Label::new(error.clone())
+                        .size(LabelSize::Small)
+                        .color(Color::Error),
+                )
+                .child(
+                    Button::new(SharedString::from(format!("retry-{name}")), "Retry")
+                        .label_size(LabelSize::Small)
+                        .on_click(cx.listener({
+                            let model = model.clone();
+                            move |this, _, _, cx| {
+                                this.state
+                                    .update(cx, |state, cx| state.download_model(model.clone(), cx))
+                            }
+                        })),
+                )
+                .into_any_element(),
+            None if state.installed_models.contains(&model.name) => h_flex()
+                .gap_1()
+                .child(Indicator::dot().color(Color::Success))
+                .child(Label::new("Ready").size(LabelSize::Small))
+                .when(model.path.is_none() && model.url.is_some(), |this| {
+                    this.child(
+                        Button::new(SharedString::from(format!("delete-{name}")), "Delete")
+                            .label_size(LabelSize::Small)
+                            .icon(IconName::Trash)
+                            .icon_size(IconSize::Small)
+                            .icon_position(IconPosition::Start)
+                            .on_click(cx.listener({
+                                let model = model.clone();
+                                move |this, _, _, cx| {
+                                    this.state.update(cx, |state, cx| {
+                                        state.delete_model(model.clone(), cx)
+                                    })
+                                }
+                            })),
+                    )
+                })
+                .into_any_element(),
+            None if model.url.is_some() => {
+                Button::new(SharedString::from(format!("download-{name}")), "Download")
+                    .label_size(LabelSize::Small)
+                    .icon(IconName::Download)
+                    .icon_size(IconSize::Small)
+                    .icon_position(IconPosition::Start)
+                    .on_click(cx.listener({
+                        let model = model.clone();
+                        move |this, _, _, cx| {
+                            this.state
+                                .update(cx, |state, cx| state.download_model(model.clone(), cx))
+                        }
+                    }))
+                    .into_any_element()
+            }
+            None => Label::new("Weights not found")
+                .size(LabelSize::Small)
+                .color(Color::Warning)
+                .into_any_element(),
+        };
+
+        h_flex()
+            .w_full()
+            .justify_between()
+            .child(Label::new(model.display_name().to_string()))
+            .child(status)
+    }
+}
+
+impl Render for ConfigurationView {
+    fn render(&mut self, _: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
+        if self.loading_runtime_task.is_some() {
+            return div().child(Label::new("Looking for runtime...")).into_any();
+        }
+
+        let accelerator = self
+            .state
+            .read(cx)
+            .accelerator
+            .map_or("Detecting…", |accelerator| accelerator.label());
+        let models = AllLanguageModelSettings::get_global(cx)
+            .local
+            .available_models
+            .clone();
+
+        v_flex()
+            .gap_2()
+            .child(Label::new(
+                "Run GGUF or safetensors models on this machine, without Ollama or LM Studio.",
+            ))
+            .child(self.render_runtime_status(cx))
+            .child(
+                Label::new(format!("Acceleration: {accelerator}"))
+                    .size(LabelSize::Small)
+                    .color(Color::Muted),
+            )
+            .map(|this| {
+                if models.is_empty() {
+                    this.child(
+                        Label::new(
+                            "Add models to `language_models.local.available_models` in your settings to get started.",
+                        )
+                        .size(LabelSize::Small)
+                        .color(Color::Muted),
+                    )
+                } else {
+                    this.children(models.iter().map(|model| self.render_model(model, cx)))
+                }
+            })
+            .into_any()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weights_path() {
+        let model = AvailableModel {
+            name: "qwen".into(),
+            display_name: None,
+            format: ModelFormat::Gguf,
+            url: Some(
+                "https://huggingface.co/org/repo/resolve/main/qwen-q4.gguf?download=true".into(),
+            ),
+            sha256: None,
+            path: None,
+            max_tokens: 32768,
+            max_output_tokens: None,
+            supports_tools: None,
+            supports_images: None,
+            supports_thinking: None,
+            supports_parallel_tool_calls: None,
+            sampling: SamplingParameters::default(),
+        };
+        assert_eq!(
+            model.weights_path(),
+            Some(paths::local_models_dir().join("qwen-q4.gguf"))
+        );
+
+        let model = AvailableModel {
+            path: Some(PathBuf::from("/models/qwen.gguf")),
+            ..model
+        };
+        assert_eq!(
+            model.weights_path(),
+            Some(PathBuf::from("/models/qwen.gguf"))
+        );
+    }
+
+    #[test]
+    fn test_check_download() {
+        let sha256 = Sha256::digest(b"weights");
+        let hex = "9a129038d9a00aed0cf6a7ea059ca50a813449061ab87848cf1a13eafdf33b2c";
+        assert!(check_download(7, Some(7), None, &sha256).is_ok());
+        assert!(check_download(5, Some(7), None, &sha256).is_err());
+        assert!(check_download(7, None, Some(hex), &sha256).is_ok());
+        assert!(check_download(7, None, Some(&hex.to_uppercase()), &sha256).is_ok());
+        assert!(check_download(7, None, Some(&hex.replace('9', '8')), &sha256).is_err());
+    }
+}
@@ -7,13 +7,13 @@ use futures::{FutureExt, StreamExt, future::BoxFuture};
 use gpui::{
     AnyView, App, AsyncApp, Context, Entity, FontStyle, Subscription, Task, TextStyle, WhiteSpace,
 };
-use http_client::HttpClient;
+use http_client::{HttpClient, http};
 use language_model::{
     AuthenticateError, LanguageModel, LanguageModelCompletionError, LanguageModelCompletionEvent,
     LanguageModelId, LanguageModelName, LanguageModelProvider, LanguageModelProviderId,
     LanguageModelProviderName, LanguageModelProviderState, LanguageModelRequest,
     LanguageModelToolChoice, LanguageModelToolResultContent, LanguageModelToolUse, MessageContent,
-    RateLimiter, Role, StopReason,
+    ModelPrice, RateLimiter, Role, StopReason, StructuredOutput,
 };
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
@@ -25,17 +25,43 @@ use theme::ThemeSettings;
 use ui::{Icon, IconName, List, Tooltip, prelude::*};
 use util::ResultExt;
 
-use crate::{AllLanguageModelSettings, ui::InstructionListItem};
+use crate::api_key::{
+    ApiKeyDisplay, ApiKeySaveScope, ApiKeySource, ApiKeyStatus, ApiKeyValidation, ApiKeys,
+    CredentialsScope, WorkspaceApiKeys, WorkspaceScope, carry_over_api_key, load_api_keys,
+    refresh_api_keys, validate_api_keys,
+};
+use crate::key_usage::KeyUsageRecorder;
+use crate::live_usage::estimate_live_usage;
+use crate::moderation::check_moderation;
+use crate::normalize_messages::{MessageConstraints, normalize_messages};
+use crate::output_tokens::OutputTokenClamp;
+use crate::partial_tool_use::partial_tool_use;
+use crate::pricing::model_price;
+use crate::provider::open_ai::stop_reason_for_finish_reason;
+use crate::reasoning::normalize_reasoning;
+use crate::request_log::RequestLogRecorder;
+use crate::spend_limits::check_spend_limits;
+use crate::stream_timeouts::StreamTimeouts;
+use crate::{
+    AllLanguageModelSettings, ProviderApiSettings, SamplingParameters, ui::InstructionListItem,
+};
 
 use std::collections::HashMap;
 use std::pin::Pin;
 
-const PROVIDER_ID: &str = "mistral";
-const PROVIDER_NAME: &str = "Mistral";
+pub(crate) const PROVIDER_ID: &str = "mistral";
+pub(crate) const PROVIDER_NAME: &str = "Mistral";
+
+pub(crate) fn api_key_validation_request(api_url: &str, api_key: &str) -> http::request::Builder {
+    http::Request::builder()
+        .uri(format!("{api_url}/models"))
+        .header("Authorization", format!("Bearer {api_key}"))
+}
 
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct MistralSettings {
     pub api_url: String,
+    pub api: ProviderApiSettings,
     pub available_models: Vec<AvailableModel>,
     pub needs_setting_migration: bool,
 }
@@ -47,7 +73,24 @@ pub struct AvailableModel {
     pub max_tokens: usize,
     pub max_output_tokens: Option<u32>,
     pub max_completion_tokens: Option<u32>,
+    /// Whether the model can call tools.
+    ///
+    /// Default: false
     pub supports_tools: Option<bool>,
+    /// Whether the model reasons before it responds, like Magistral models.
+    ///
+    /// Default: whether the model's name says it's a Magistral model
+    #[serde(default)]
+    pub supports_thinking: Option<bool>,
+    /// Whether the model can call several tools in one response. When false, requests ask the
+    /// model to call one tool at a time.
+    ///
+    /// Default: true
+    #[serde(default)]
+    pub supports_parallel_tool_calls: Option<bool>,
+    /// Sampling parameters for requests to this model.
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
 }
 
 pub struct MistralLanguageModelProvider {
@@ -56,54 +99,146 @@ pub struct MistralLanguageModelProvider {
 }
 
 pub struct State {
-    api_key: Option<String>,
-    api_key_from_env: bool,
+    http_client: Arc<dyn HttpClient>,
+    api_url: String,
+    api_keys: ApiKeys,
+    api_key_source: ApiKeySource,
+    workspace_api_keys: WorkspaceApiKeys,
+    api_key_status: ApiKeyStatus,
+    /// The account's fine-tuned models, which are offered alongside the built-in ones.
+    fine_tuned_models: Vec<mistral::ModelCard>,
+    /// The API URL that `fine_tuned_models` were fetched from.
+    fine_tuned_models_url: Option<String>,
+    _api_key_refresh_task: Option<Task<()>>,
+    _fetch_fine_tuned_models_task: Option<Task<()>>,
     _subscription: Subscription,
+    _credentials_scope_subscription: Subscription,
 }
 
 const MISTRAL_API_KEY_VAR: &str = "MISTRAL_API_KEY";
 
 impl State {
     fn is_authenticated(&self) -> bool {
-        self.api_key.is_some()
+        !self.api_keys.is_empty() || self.workspace_api_keys.has_any()
     }
 
-    fn reset_api_key(&self, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn reset_api_key(
+        &self,
+        workspace: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<()>> {
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .mistral
             .api_url
             .clone();
+        if let Some(workspace) = workspace {
+            let task = self
+                .workspace_api_keys
+                .reset(&workspace, PROVIDER_ID, &api_url, cx);
+            cx.notify();
+            return task;
+        }
+        let credentials_scope = CredentialsScope::global(cx);
+        let credentials_urls = [
+            credentials_scope.api_key_url(&self.api_key_source, PROVIDER_ID, &api_url),
+            credentials_scope.legacy_api_key_url(&self.api_key_source, &api_url),
+        ];
         cx.spawn(async move |this, cx| {
-            credentials_provider
-                .delete_credentials(&api_url, &cx)
-                .await
-                .log_err();
+            for credentials_url in &credentials_urls {
+                credentials_provider
+                    .delete_credentials(credentials_url, &cx)
+                    .await
+                    .log_err();
+            }
             this.update(cx, |this, cx| {
-                this.api_key = None;
-                this.api_key_from_env = false;
+                this.api_keys = ApiKeys::default();
+                this.api_key_status.set_rejected(false, cx);
+                this.api_key_source = ApiKeySource::CredentialsStore;
+                this._api_key_refresh_task = None;
                 cx.notify();
             })
         })
     }
 
-    fn set_api_key(&mut self, api_key: String, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn set_api_key(
+        &mut self,
+        api_key: String,
+        workspace_scope: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<Option<SharedString>>> {
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .mistral
             .api_url
             .clone();
+        let http_client = self.http_client.clone();
+        let source = workspace_scope.map_or(
+            ApiKeySource::CredentialsStore,
+            ApiKeySource::WorkspaceCredentialsStore,
+        );
+        let credentials_url =
+            CredentialsScope::global(cx).api_key_url(&source, PROVIDER_ID, &api_url);
         cx.spawn(async move |this, cx| {
+            let warning =
+                validate_api_keys(http_client.as_ref(), &ApiKeys::parse(&api_key), |api_key| {
+                    api_key_validation_request(&api_url, api_key)
+                })
+                .await?;
+
             credentials_provider
-                .write_credentials(&api_url, "Bearer", api_key.as_bytes(), &cx)
+                .write_credentials(&credentials_url, "Bearer", api_key.as_bytes(), &cx)
                 .await?;
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
+                match source.workspace() {
+                    Some(workspace) => this
+                        .workspace_api_keys
+                        .set(workspace, Some(ApiKeys::parse(&api_key))),
+                    None => {
+                        this.api_keys = ApiKeys::parse(&api_key);
+                        this.api_key_source = source;
+                    }
+                }
+                this.api_key_status.set_rejected(false, cx);
                 cx.notify();
-            })
+            })?;
+            Ok(warning)
         })
     }
 
+    /// Reloads the keys saved for a new `api_url`, carrying the current key over if none was
+    /// saved for it yet.
+    fn update_api_url(&mut self, cx: &mut Context<Self>) {
+        let api_url = AllLanguageModelSettings::get_global(cx)
+            .mistral
+            .api_url
+            .clone();
+        if self.api_url == api_url {
+            return;
+        }
+        let previous_api_url = std::mem::replace(&mut self.api_url, api_url);
+        self.workspace_api_keys.clear();
+        if self.api_key_source.is_external() {
+            return;
+        }
+
+        let carry_over = carry_over_api_key(
+            &self.api_key_source,
+            PROVIDER_ID,
+            &previous_api_url,
+            &self.api_url,
+            cx,
+        );
+        self.api_keys = ApiKeys::default();
+        cx.spawn(async move |this, cx| {
+            carry_over.await;
+            if let Ok(task) = this.update(cx, |this, cx| this.authenticate(cx)) {
+                task.await.ok();
+            }
+        })
+        .detach();
+    }
+
     fn authenticate(&self, cx: &mut Context<Self>) -> Task<Result<(), AuthenticateError>> {
         if self.is_authenticated() {
             return Task::ready(Ok(()));
@@ -114,50 +249,148 @@ impl State {
             .mistral
             .api_url
             .clone();
+        let api_settings = AllLanguageModelSettings::get_global(cx).mistral.api.clone();
+        let credentials_scope = CredentialsScope::global(cx);
+        let load_workspace_api_keys =
+            self.workspace_api_keys
+                .load_open_workspaces(PROVIDER_ID, &api_url, cx);
         cx.spawn(async move |this, cx| {
-            let (api_key, from_env) = if let Ok(api_key) = std::env::var(MISTRAL_API_KEY_VAR) {
-                (api_key, true)
-            } else {
-                let (_, api_key) = credentials_provider
-                    .read_credentials(&api_url, &cx)
-                    .await?
-                    .ok_or(AuthenticateError::CredentialsNotFound)?;
-                (
-                    String::from_utf8(api_key).context("invalid {PROVIDER_NAME} API key")?,
-                    false,
-                )
+            let api_keys = load_api_keys(
+                MISTRAL_API_KEY_VAR,
+                &api_settings,
+                credentials_provider,
+                PROVIDER_ID,
+                &api_url,
+                credentials_scope,
+                &cx,
+            )
+            .await;
+            let has_workspace_api_keys = load_workspace_api_keys.await;
+            let (api_keys, source) = match api_keys {
+                Err(AuthenticateError::CredentialsNotFound) if has_workspace_api_keys => {
+                    this.update(cx, |_, cx| cx.notify())?;
+                    return Ok(());
+                }
+                api_keys => api_keys?,
             };
+
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
-                this.api_key_from_env = from_env;
+                this._api_key_refresh_task =
+                    refresh_api_keys(&source, &api_keys, &api_settings, cx, |this, api_keys| {
+                        this.api_keys = api_keys
+                    });
+                this.api_keys = api_keys;
+                this.api_key_source = source;
                 cx.notify();
             })?;
 
             Ok(())
         })
     }
+
+    /// Fetches the account's fine-tuned models once it can be authenticated with.
+    fn fetch_fine_tuned_models(&mut self, cx: &mut Context<Self>) {
+        if self.api_keys.is_empty() || self.fine_tuned_models_url.as_ref() == Some(&self.api_url) {
+            return;
+        }
+        self.fine_tuned_models_url = Some(self.api_url.clone());
+        let http_client = self.http_client.clone();
+        let api_url = self.api_url.clone();
+        let api_keys = self.api_keys.clone();
+        let rotation = AllLanguageModelSettings::get_global(cx)
+            .mistral
+            .api
+            .api_key_rotation;
+        self._fetch_fine_tuned_models_task = Some(cx.spawn(async move |this, cx| {
+            let models = async {
+                let api_key = api_keys
+                    .next_for_request(rotation)
+                    .context("Missing Mistral API Key")?;
+                mistral::list_models(http_client.as_ref(), &api_url, &api_key).await
+            };
+            match models.await {
+                Ok(models) => {
+                    this.update(cx, |this, cx| {
+                        this.fine_tuned_models = models
+                            .into_iter()
+                            .filter(|model| model.is_fine_tuned() && !model.archived)
+                            .collect();
+                        cx.notify();
+                    })
+                    .ok();
+                }
+                Err(error) => log::debug!("failed to list Mistral models: {error:#}"),
+            }
+        }));
+    }
 }
 
 impl MistralLanguageModelProvider {
     pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut App) -> Self {
         let state = cx.new(|cx| State {
-            api_key: None,
-            api_key_from_env: false,
-            _subscription: cx.observe_global::<SettingsStore>(|_this: &mut State, cx| {
+            http_client: http_client.clone(),
+            api_url: AllLanguageModelSettings::get_global(cx)
+                .mistral
+                .api_url
+                .clone(),
+            api_keys: ApiKeys::default(),
+            api_key_source: ApiKeySource::default(),
+            workspace_api_keys: WorkspaceApiKeys::default(),
+            api_key_status: ApiKeyStatus::new(cx, |state| &mut state.api_key_status),
+            fine_tuned_models: Vec::new(),
+            fine_tuned_models_url: None,
+            _api_key_refresh_task: None,
+            _fetch_fine_tuned_models_task: None,
+            _subscription: cx.observe_global::<SettingsStore>(|this: &mut State, cx| {
+                this.update_api_url(cx);
                 cx.notify();
             }),
+            _credentials_scope_subscription: cx.observe_global::<CredentialsScope>(
+                |this: &mut State, cx| {
+                    this.workspace_api_keys.clear();
+                    if !this.api_key_source.is_external() {
+                        this.api_keys = ApiKeys::default();
+                        this.api_key_status.set_rejected(false, cx);
+                        this.authenticate(cx).detach();
+                        cx.notify();
+                    }
+                },
+            ),
         });
+        cx.observe(&state, |state, cx| {
+            state.update(cx, |state, cx| state.fetch_fine_tuned_models(cx));
+        })
+        .detach();
 
         Self { http_client, state }
     }
 
-    fn create_language_model(&self, model: mistral::Model) -> Arc<dyn LanguageModel> {
+    fn create_language_model(&self, model: mistral::Model, cx: &App) -> Arc<dyn LanguageModel> {
+        let available_model = AllLanguageModelSettings::get_global(cx)
+            .mistral
+            .available_models
+            .iter()
+            .find(|available_model| available_model.name == model.id());
+        let supports_thinking = available_model
+            .and_then(|available_model| available_model.supports_thinking)
+            .unwrap_or_else(|| {
+                open_ai::models::FineTunedModelId::parse(model.id())
+                    .map_or(model.id(), |fine_tuned| fine_tuned.base_model)
+                    .starts_with("magistral")
+            });
+        let supports_parallel_tool_calls = available_model
+            .and_then(|available_model| available_model.supports_parallel_tool_calls)
+            .unwrap_or(true);
         Arc::new(MistralLanguageModel {
             id: LanguageModelId::from(model.id().to_string()),
             model,
+            supports_thinking,
+            supports_parallel_tool_calls,
             state: self.state.clone(),
             http_client: self.http_client.clone(),
-            request_limiter: RateLimiter::new(4),
+            request_limiter: RateLimiter::new(
+                AllLanguageModelSettings::get_global(cx).max_concurrent_requests(PROVIDER_ID),
+            ),
         })
     }
 }
@@ -183,12 +416,12 @@ impl LanguageModelProvider for MistralLanguageModelProvider {
         IconName::AiMistral
     }
 
-    fn default_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(mistral::Model::default()))
+    fn default_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(mistral::Model::default(), cx))
     }
 
-    fn default_fast_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(mistral::Model::default_fast()))
+    fn default_fast_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(mistral::Model::default_fast(), cx))
     }
 
     fn provided_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
@@ -201,6 +434,38 @@ impl LanguageModelProvider for MistralLanguageModelProvider {
             }
         }
 
+        // Add the account's fine-tuned models, which are like the models they were trained from
+        for model in &self.state.read(cx).fine_tuned_models {
+            let base_model = model
+                .root
+                .as_deref()
+                .and_then(|root| mistral::Model::from_id(root).ok());
+            let Some(max_tokens) = model
+                .max_context_length
+                .or_else(|| base_model.as_ref().map(|model| model.max_token_count()))
+            else {
+                continue;
+            };
+            models.insert(
+                model.id.clone(),
+                mistral::Model::Custom {
+                    name: model.id.clone(),
+                    display_name: model.name.clone().filter(|name| !name.is_empty()).or_else(
+                        || {
+                            model
+                                .root
+                                .as_ref()
+                                .map(|root| format!("{root} (fine-tuned)"))
+                        },
+                    ),
+                    max_tokens,
+                    max_output_tokens: None,
+                    max_completion_tokens: None,
+                    supports_tools: Some(model.capabilities.function_calling),
+                },
+            );
+        }
+
         // Override with available models from settings
         for model in &AllLanguageModelSettings::get_global(cx)
             .mistral
@@ -221,15 +486,7 @@ impl LanguageModelProvider for MistralLanguageModelProvider {
 
         models
             .into_values()
-            .map(|model| {
-                Arc::new(MistralLanguageModel {
-                    id: LanguageModelId::from(model.id().to_string()),
-                    model,
-                    state: self.state.clone(),
-                    http_client: self.http_client.clone(),
-                    request_limiter: RateLimiter::new(4),
-                }) as Arc<dyn LanguageModel>
-            })
+            .map(|model| self.create_language_model(model, cx))
             .collect()
     }
 
@@ -237,6 +494,10 @@ impl LanguageModelProvider for MistralLanguageModelProvider {
         self.state.read(cx).is_authenticated()
     }
 
+    fn credentials_rejected(&self, cx: &App) -> bool {
+        self.state.read(cx).api_key_status.is_rejected()
+    }
+
     fn authenticate(&self, cx: &mut App) -> Task<Result<(), AuthenticateError>> {
         self.state.update(cx, |state, cx| state.authenticate(cx))
     }
@@ -247,13 +508,16 @@ impl LanguageModelProvider for MistralLanguageModelProvider {
     }
 
     fn reset_credentials(&self, cx: &mut App) -> Task<Result<()>> {
-        self.state.update(cx, |state, cx| state.reset_api_key(cx))
+        self.state
+            .update(cx, |state, cx| state.reset_api_key(None, cx))
     }
 }
 
 pub struct MistralLanguageModel {
     id: LanguageModelId,
     model: mistral::Model,
+    supports_thinking: bool,
+    supports_parallel_tool_calls: bool,
     state: gpui::Entity<State>,
     http_client: Arc<dyn HttpClient>,
     request_limiter: RateLimiter,
@@ -262,26 +526,47 @@ pub struct MistralLanguageModel {
 impl MistralLanguageModel {
     fn stream_completion(
         &self,
-        request: mistral::Request,
+        mut request: mistral::Request,
+        output_tokens: OutputTokenClamp,
+        usage: KeyUsageRecorder,
+        workspace: Option<WorkspaceScope>,
         cx: &AsyncApp,
     ) -> BoxFuture<
         'static,
         Result<futures::stream::BoxStream<'static, Result<mistral::StreamResponse>>>,
     > {
         let http_client = self.http_client.clone();
-        let Ok((api_key, api_url)) = cx.read_entity(&self.state, |state, cx| {
+        let Ok((api_keys, rotation, api_url)) = cx.read_entity(&self.state, |state, cx| {
             let settings = &AllLanguageModelSettings::get_global(cx).mistral;
-            (state.api_key.clone(), settings.api_url.clone())
+            (
+                state.workspace_api_keys.for_request(
+                    &state.api_keys,
+                    &state.api_key_status,
+                    &state.api_key_source,
+                    workspace.as_ref(),
+                    PROVIDER_ID,
+                    &settings.api_url,
+                    cx,
+                ),
+                settings.api.api_key_rotation,
+                settings.api_url.clone(),
+            )
         }) else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
 
         let future = self.request_limiter.stream(async move {
-            let api_key = api_key.context("Missing Mistral API Key")?;
-            let request =
-                mistral::stream_completion(http_client.as_ref(), &api_url, &api_key, request);
-            let response = request.await?;
-            Ok(response)
+            let api_keys = api_keys.await;
+            if let Some(max_tokens) = request.max_tokens {
+                request.max_tokens = Some(output_tokens.apply(max_tokens).await);
+            }
+            let (http_client, api_url, request) = (http_client.as_ref(), &api_url, &request);
+            api_keys
+                .with_rotation(rotation, "Mistral", &usage, |api_key| async move {
+                    mistral::stream_completion(http_client, api_url, &api_key, request.clone())
+                        .await
+                })
+                .await
         });
 
         async move { Ok(future.await?.boxed()) }.boxed()
@@ -313,6 +598,18 @@ impl LanguageModel for MistralLanguageModel {
         self.model.supports_tools()
     }
 
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.supports_parallel_tool_calls
+    }
+
+    fn supports_thinking(&self) -> bool {
+        self.supports_thinking
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        StructuredOutput::JsonObject
+    }
+
     fn supports_images(&self) -> bool {
         false
     }
@@ -321,6 +618,11 @@ impl LanguageModel for MistralLanguageModel {
         format!("mistral/{}", self.model.id())
     }
 
+    fn price(&self, cx: &App) -> Option<ModelPrice> {
+        let settings = AllLanguageModelSettings::get_global(cx);
+        model_price(settings, PROVIDER_ID, &self.id.0)
+    }
+
     fn max_token_count(&self) -> usize {
         self.model.max_token_count()
     }
@@ -365,19 +667,95 @@ impl LanguageModel for MistralLanguageModel {
             BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
         >,
     > {
-        let request = into_mistral(
+        let model_id = self.id();
+        let sampling = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings
+                .mistral
+                .available_models
+                .iter()
+                .find(|model| model_id.0 == model.name)
+                .map(|model| model.sampling)
+        })
+        .flatten()
+        .unwrap_or_default();
+        let system_prompt = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings.mistral.api.system_prompt.clone()
+        })
+        .unwrap_or_default();
+        let mut request = system_prompt.apply(sampling.apply(request));
+        if !self.supports_parallel_tool_calls {
+            request.parallel_tool_calls = Some(false);
+        }
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
+        let moderation = check_moderation(&request, cx);
+        let output_tokens = OutputTokenClamp::new(self, &request, cx);
+        let prediction = request
+            .prediction
+            .clone()
+            .filter(|_| self.model.supports_prediction());
+        let workspace = request.workspace.clone();
+        let mut request = into_mistral(
             request,
             self.model.id().to_string(),
             self.max_output_tokens(),
         );
-        let stream = self.stream_completion(request, cx);
+        if let Some(content) = prediction {
+            // Predictions can't be combined with tools, which the model can't call anyway when
+            // its tool choice is none.
+            if request.tools.is_empty()
+                || matches!(request.tool_choice, Some(mistral::ToolChoice::None))
+            {
+                request.tools.clear();
+                request.tool_choice = None;
+                request.parallel_tool_calls = None;
+                request.prediction = Some(mistral::Prediction::Content { content });
+            }
+        }
+        let usage =
+            KeyUsageRecorder::new(PROVIDER_ID, self.id.0.to_string(), workspace.clone(), cx);
+        let timeouts = StreamTimeouts::new(|settings| &settings.mistral.api, cx);
+        let spend_limits = check_spend_limits(PROVIDER_ID, cx);
+        let stream = self.stream_completion(request, output_tokens, usage.clone(), workspace, cx);
+
+        request_log.track(
+            async move {
+                spend_limits?;
+                moderation.await?;
+                let stream = stream.await?;
+                let mapper = MistralEventMapper::new();
+                let events =
+                    estimate_live_usage(normalize_reasoning(mapper.map_stream(stream).boxed()));
+                Ok(usage.track(timeouts.apply(events)))
+            }
+            .boxed(),
+        )
+    }
+}
 
-        async move {
-            let stream = stream.await?;
-            let mapper = MistralEventMapper::new();
-            Ok(mapper.map_stream(stream).boxed())
+/// Adds text to the conversation. Mistral rejects consecutive messages with the same role, so
+/// text that's split around images or thinking is joined back together.
+fn push_text(messages: &mut Vec<mistral::RequestMessage>, role: Role, text: String) {
+    match (messages.last_mut(), role) {
+        (Some(mistral::RequestMessage::User { content }), Role::User)
+        | (Some(mistral::RequestMessage::System { content }), Role::System) => {
+            content.push_str("\n\n");
+            content.push_str(&text);
         }
-        .boxed()
+        (Some(mistral::RequestMessage::Assistant { content, .. }), Role::Assistant) => {
+            match content {
+                Some(content) => {
+                    content.push_str("\n\n");
+                    content.push_str(&text);
+                }
+                None => *content = Some(text),
+            }
+        }
+        (_, Role::User) => messages.push(mistral::RequestMessage::User { content: text }),
+        (_, Role::Assistant) => messages.push(mistral::RequestMessage::Assistant {
+            content: Some(text),
+            tool_calls: Vec::new(),
+        }),
+        (_, Role::System) => messages.push(mistral::RequestMessage::System { content: text }),
     }
 }
 
@@ -387,22 +765,26 @@ pub fn into_mistral(
     max_output_tokens: Option<u32>,
 ) -> mistral::Request {
     let stream = true;
+    let response_format = request
+        .response_schema
+        .as_ref()
+        .map(|_| mistral::ResponseFormat::JsonObject);
 
-    let mut messages = Vec::new();
-    for message in request.messages {
+    let mut messages: Vec<mistral::RequestMessage> = Vec::new();
+    for message in normalize_messages(request.messages, MessageConstraints::STRICT) {
         for content in message.content {
             match content {
-                MessageContent::Text(text) | MessageContent::Thinking { text, .. } => messages
-                    .push(match message.role {
-                        Role::User => mistral::RequestMessage::User { content: text },
-                        Role::Assistant => mistral::RequestMessage::Assistant {
-                            content: Some(text),
-                            tool_calls: Vec::new(),
-                        },
-                        Role::System => mistral::RequestMessage::System { content: text },
-                    }),
+                MessageContent::Text(text) | MessageContent::Thinking { text, .. } => {
+                    push_text(&mut messages, message.role, text)
+                }
+                MessageContent::Document(document) => {
+                    push_text(&mut messages, message.role, document.to_text())
+                }
                 MessageContent::RedactedThinking(_) => {}
-                MessageContent::Image(_) => {}
+                MessageContent::Image(_)
+                | MessageContent::Pdf(_)
+                | MessageContent::Audio(_)
+                | MessageContent::Video(_) => {}
                 MessageContent::ToolUse(tool_use) => {
                     let tool_call = mistral::ToolCall {
                         id: tool_use.id.to_string(),
@@ -450,7 +832,11 @@ pub fn into_mistral(
         stream,
         max_tokens: max_output_tokens,
         temperature: request.temperature,
-        response_format: None,
+        random_seed: request.seed,
+        top_p: request.top_p,
+        frequency_penalty: request.frequency_penalty,
+        presence_penalty: request.presence_penalty,
+        response_format,
         tool_choice: match request.tool_choice {
             Some(LanguageModelToolChoice::Auto) if !request.tools.is_empty() => {
                 Some(mistral::ToolChoice::Auto)
@@ -459,14 +845,24 @@ pub fn into_mistral(
                 Some(mistral::ToolChoice::Any)
             }
             Some(LanguageModelToolChoice::None) => Some(mistral::ToolChoice::None),
+            Some(LanguageModelToolChoice::Tool(name)) if !request.tools.is_empty() => Some(
+                mistral::ToolChoice::Function(mistral::ToolDefinition::Function {
+                    function: mistral::FunctionDefinition {
+                        name,
+                        description: None,
+                        parameters: None,
+                    },
+                }),
+            ),
             _ if !request.tools.is_empty() => Some(mistral::ToolChoice::Auto),
             _ => None,
         },
         parallel_tool_calls: if !request.tools.is_empty() {
-            Some(false)
+            Some(request.parallel_tool_calls.unwrap_or(false))
         } else {
             None
         },
+        stop: request.stop,
         tools: request
             .tools
             .into_iter()
@@ -478,6 +874,7 @@ pub fn into_mistral(
                 },
             })
             .collect(),
+        prediction: None,
     }
 }
 
@@ -509,13 +906,37 @@ impl MistralEventMapper {
         &mut self,
         event: mistral::StreamResponse,
     ) -> Vec<Result<LanguageModelCompletionEvent, LanguageModelCompletionError>> {
+        let mut events = Vec::new();
+        if let Some(usage) = event.usage.as_ref() {
+            events.push(Ok(LanguageModelCompletionEvent::UsageUpdate(
+                language_model::TokenUsage {
+                    input_tokens: usage.prompt_tokens,
+                    output_tokens: usage.completion_tokens,
+                    ..Default::default()
+                },
+            )));
+        }
+
         let Some(choice) = event.choices.first() else {
-            return vec![Err(LanguageModelCompletionError::Other(anyhow!(
-                "Response contained no choices"
-            )))];
+            if events.is_empty() {
+                events.push(Err(LanguageModelCompletionError::Other(anyhow!(
+                    "Response contained no choices"
+                ))));
+            }
+            return events;
         };
 
-        let mut events = Vec::new();
+        if let Some(reasoning) = choice
+            .delta
+            .reasoning_content
+            .clone()
+            .filter(|text| !text.is_empty())
+        {
+            events.push(Ok(LanguageModelCompletionEvent::Thinking {
+                text: reasoning,
+                signature: None,
+            }));
+        }
         if let Some(content) = choice.delta.content.clone() {
             events.push(Ok(LanguageModelCompletionEvent::Text(content)));
         }
@@ -533,8 +954,15 @@ impl MistralEventMapper {
                         entry.name = name;
                     }
 
-                    if let Some(arguments) = function.arguments.clone() {
-                        entry.arguments.push_str(&arguments);
+                    if let Some(arguments) = function
+                        .arguments
+                        .as_deref()
+                        .filter(|args| !args.is_empty())
+                    {
+                        entry.arguments.push_str(arguments);
+                        events.extend(
+                            partial_tool_use(&entry.id, &entry.name, &entry.arguments).map(Ok),
+                        );
                     }
                 }
             }
@@ -542,16 +970,17 @@ impl MistralEventMapper {
 
         if let Some(finish_reason) = choice.finish_reason.as_deref() {
             match finish_reason {
-                "stop" => {
-                    events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::EndTurn)));
-                }
                 "tool_calls" => {
                     events.extend(self.process_tool_calls());
                     events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::ToolUse)));
                 }
-                unexpected => {
-                    log::error!("Unexpected Mistral stop_reason: {unexpected:?}");
-                    events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::EndTurn)));
+                finish_reason => {
+                    let stop_reason =
+                        stop_reason_for_finish_reason(finish_reason).unwrap_or_else(|| {
+                            log::error!("Unexpected Mistral stop_reason: {finish_reason:?}");
+                            StopReason::EndTurn
+                        });
+                    events.push(Ok(LanguageModelCompletionEvent::Stop(stop_reason)));
                 }
             }
         }
@@ -606,6 +1035,9 @@ struct ConfigurationView {
     api_key_editor: Entity<Editor>,
     state: gpui::Entity<State>,
     load_credentials_task: Option<Task<()>>,
+    validation: ApiKeyValidation,
+    save_scope: ApiKeySaveScope,
+    key_display: ApiKeyDisplay,
 }
 
 impl ConfigurationView {
@@ -644,6 +1076,9 @@ impl ConfigurationView {
             api_key_editor,
             state,
             load_credentials_task,
+            validation: ApiKeyValidation::default(),
+            save_scope: ApiKeySaveScope::new(window, cx),
+            key_display: ApiKeyDisplay::default(),
         }
     }
 
@@ -653,11 +1088,22 @@ impl ConfigurationView {
             return;
         }
 
+        self.validation = ApiKeyValidation::Validating;
+        let workspace_scope = self.save_scope.workspace_scope();
         let state = self.state.clone();
-        cx.spawn_in(window, async move |_, cx| {
-            state
-                .update(cx, |state, cx| state.set_api_key(api_key, cx))?
-                .await
+        cx.spawn_in(window, async move |this, cx| {
+            let result = state
+                .update(cx, |state, cx| {
+                    state.set_api_key(api_key, workspace_scope, cx)
+                })?
+                .await;
+            this.update(cx, |this, cx| {
+                this.validation = ApiKeyValidation::from_result(&result);
+                if result.is_ok() {
+                    this.key_display.finish_replacing();
+                }
+                cx.notify();
+            })
         })
         .detach_and_log_err(cx);
 
@@ -668,9 +1114,12 @@ impl ConfigurationView {
         self.api_key_editor
             .update(cx, |editor, cx| editor.set_text("", window, cx));
 
+        let workspace = self.api_keys(cx).1.workspace().cloned();
         let state = self.state.clone();
         cx.spawn_in(window, async move |_, cx| {
-            state.update(cx, |state, cx| state.reset_api_key(cx))?.await
+            state
+                .update(cx, |state, cx| state.reset_api_key(workspace, cx))?
+                .await
         })
         .detach_and_log_err(cx);
 
@@ -702,14 +1151,31 @@ impl ConfigurationView {
         )
     }
 
+    /// The keys that the view shows, which are the workspace's own if it has any.
+    fn api_keys(&self, cx: &App) -> (ApiKeys, ApiKeySource) {
+        let state = self.state.read(cx);
+        state.workspace_api_keys.for_display(
+            &state.api_keys,
+            &state.api_key_source,
+            self.save_scope.workspace(),
+        )
+    }
+
     fn should_render_editor(&self, cx: &mut Context<Self>) -> bool {
-        !self.state.read(cx).is_authenticated()
+        !self.state.read(cx).is_authenticated() || self.key_display.is_replacing()
     }
 }
 
 impl Render for ConfigurationView {
     fn render(&mut self, _: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let env_var_set = self.state.read(cx).api_key_from_env;
+        let (api_keys, api_key_source) = self.api_keys(cx);
+        let rejected = self.state.read(cx).api_key_status.is_rejected();
+        let env_var = AllLanguageModelSettings::get_global(cx)
+            .mistral
+            .api
+            .env_var(MISTRAL_API_KEY_VAR)
+            .to_string();
+        let key_is_external = api_key_source.is_external();
 
         if self.load_credentials_task.is_some() {
             div().child(Label::new("Loading credentials...")).into_any()
@@ -744,9 +1210,12 @@ impl Render for ConfigurationView {
                         .rounded_sm()
                         .child(self.render_api_key_editor(cx)),
                 )
+                .children(self.save_scope.render(cx, |this| &mut this.save_scope))
+                .children(self.key_display.render_cancel(cx, |this| &mut this.key_display))
+                .children(self.validation.render())
                 .child(
                     Label::new(
-                        format!("You can also assign the {MISTRAL_API_KEY_VAR} environment variable and restart Zed."),
+                        format!("You can also assign the {env_var} environment variable and restart Zed."),
                     )
                     .size(LabelSize::Small).color(Color::Muted),
                 )
@@ -764,11 +1233,14 @@ impl Render for ConfigurationView {
                     h_flex()
                         .gap_1()
                         .child(Icon::new(IconName::Check).color(Color::Success))
-                        .child(Label::new(if env_var_set {
-                            format!("API key set in {MISTRAL_API_KEY_VAR} environment variable.")
-                        } else {
-                            "API key configured.".to_string()
-                        })),
+                        .child(self.key_display.render(
+                            PROVIDER_ID,
+                            &api_keys,
+                            rejected,
+                            &api_key_source,
+                            cx,
+                            |this| &mut this.key_display,
+                        )),
                 )
                 .child(
                     Button::new("reset-key", "Reset Key")
@@ -776,11 +1248,13 @@ impl Render for ConfigurationView {
                         .icon(Some(IconName::Trash))
                         .icon_size(IconSize::Small)
                         .icon_position(IconPosition::Start)
-                        .disabled(env_var_set)
-                        .when(env_var_set, |this| {
-                            this.tooltip(Tooltip::text(format!("To reset your API key, unset the {MISTRAL_API_KEY_VAR} environment variable.")))
+                        .disabled(key_is_external)
+                        .when(key_is_external, |this| {
+                            this.tooltip(Tooltip::text(api_key_source.reset_hint()))
                         })
-                        .on_click(cx.listener(|this, _, window, cx| this.reset_api_key(window, cx))),
+                        .on_click(
+                            cx.listener(|this, _, window, cx| this.reset_api_key(window, cx)),
+                        ),
                 )
                 .into_any()
         }
@@ -812,13 +1286,26 @@ mod tests {
                 },
             ],
             temperature: Some(0.7),
+            seed: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            logit_bias: Default::default(),
+            top_p: None,
+            top_k: None,
+            candidates: None,
+            logprobs: None,
+            response_schema: None,
+            parallel_tool_calls: None,
+            prediction: None,
+            batch: false,
+            workspace: None,
             tools: Vec::new(),
             tool_choice: None,
             thread_id: None,
             prompt_id: None,
             intent: None,
             mode: None,
-            stop: Vec::new(),
+            stop: vec!["<|end|>".to_string()],
         };
 
         let model_name = "mistral-medium-latest".to_string();
@@ -831,6 +1318,7 @@ mod tests {
         assert!(mistral_request.stream);
         assert!(mistral_request.tools.is_empty());
         assert!(mistral_request.tool_choice.is_none());
+        assert_eq!(mistral_request.stop, vec!["<|end|>".to_string()]);
 
         assert_eq!(mistral_request.messages.len(), 2);
 
@@ -1,12 +1,12 @@
 use anyhow::{Result, anyhow};
 use futures::{FutureExt, StreamExt, future::BoxFuture, stream::BoxStream};
 use futures::{Stream, TryFutureExt, stream};
-use gpui::{AnyView, App, AsyncApp, Context, Subscription, Task};
+use gpui::{AnyView, App, AsyncApp, Context, ImageFormat, Subscription, Task};
 use http_client::HttpClient;
 use language_model::{
-    AuthenticateError, LanguageModelCompletionError, LanguageModelCompletionEvent,
+    AuthenticateError, ImageLimits, LanguageModelCompletionError, LanguageModelCompletionEvent,
     LanguageModelRequestTool, LanguageModelToolChoice, LanguageModelToolUse,
-    LanguageModelToolUseId, StopReason,
+    LanguageModelToolUseId, MessageContent, StopReason,
 };
 use language_model::{
     LanguageModel, LanguageModelId, LanguageModelName, LanguageModelProvider,
@@ -26,20 +26,24 @@ use std::{collections::BTreeMap, sync::Arc};
 use ui::{ButtonLike, Indicator, List, prelude::*};
 use util::ResultExt;
 
-use crate::AllLanguageModelSettings;
+use crate::live_usage::estimate_live_usage;
+use crate::reasoning::normalize_reasoning;
+use crate::request_log::RequestLogRecorder;
 use crate::ui::InstructionListItem;
+use crate::{AllLanguageModelSettings, SamplingParameters, SystemPromptOverrides};
 
 const OLLAMA_DOWNLOAD_URL: &str = "https://ollama.com/download";
 const OLLAMA_LIBRARY_URL: &str = "https://ollama.com/library";
 const OLLAMA_SITE: &str = "https://ollama.com/";
 
-const PROVIDER_ID: &str = "ollama";
+pub(crate) const PROVIDER_ID: &str = "ollama";
 const PROVIDER_NAME: &str = "Ollama";
 
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct OllamaSettings {
     pub api_url: String,
     pub available_models: Vec<AvailableModel>,
+    pub system_prompt: SystemPromptOverrides,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
@@ -52,8 +56,27 @@ pub struct AvailableModel {
     pub max_tokens: usize,
     /// The number of seconds to keep the connection open after the last request
     pub keep_alive: Option<KeepAlive>,
-    /// Whether the model supports tools
+    /// Whether the model supports tools. Defaults to whether Ollama reports that it has the
+    /// `tools` capability.
     pub supports_tools: Option<bool>,
+    /// Whether the model takes images as input, like LLaVA and Gemma 3.
+    ///
+    /// Default: whether Ollama reports that it has the `vision` capability
+    #[serde(default)]
+    pub supports_images: Option<bool>,
+    /// Whether the model reasons before it responds, like DeepSeek R1 and Qwen 3.
+    ///
+    /// Default: whether Ollama reports that it has the `thinking` capability
+    #[serde(default)]
+    pub supports_thinking: Option<bool>,
+    /// Whether the model can call several tools in one response.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub supports_parallel_tool_calls: Option<bool>,
+    /// Sampling parameters for requests to this model.
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
 }
 
 pub struct OllamaLanguageModelProvider {
@@ -94,12 +117,16 @@ impl State {
                     async move {
                         let name = model.name.as_str();
                         let capabilities = show_model(http_client.as_ref(), &api_url, name).await?;
-                        let ollama_model = ollama::Model::new(
-                            name,
-                            None,
-                            None,
-                            Some(capabilities.supports_tools()),
-                        );
+                        let ollama_model = ollama::Model {
+                            supports_images: Some(capabilities.supports_vision()),
+                            supports_thinking: Some(capabilities.supports_thinking()),
+                            ..ollama::Model::new(
+                                name,
+                                None,
+                                None,
+                                Some(capabilities.supports_tools()),
+                            )
+                        };
                         Ok(ollama_model)
                     }
                 });
@@ -205,12 +232,23 @@ impl LanguageModelProvider for OllamaLanguageModelProvider {
             models.insert(model.name.clone(), model.clone());
         }
 
-        // Override with available models from settings
+        // Override with available models from settings, keeping the capabilities that Ollama
+        // reports for the ones that settings leave out.
         for model in AllLanguageModelSettings::get_global(cx)
             .ollama
             .available_models
             .iter()
         {
+            let reported = models.get(&model.name);
+            let supports_tools = model
+                .supports_tools
+                .or_else(|| reported.and_then(|reported| reported.supports_tools));
+            let supports_images = model
+                .supports_images
+                .or_else(|| reported.and_then(|reported| reported.supports_images));
+            let supports_thinking = model
+                .supports_thinking
+                .or_else(|| reported.and_then(|reported| reported.supports_thinking));
             models.insert(
                 model.name.clone(),
                 ollama::Model {
@@ -218,7 +256,10 @@ impl LanguageModelProvider for OllamaLanguageModelProvider {
                     display_name: model.display_name.clone(),
                     max_tokens: model.max_tokens,
                     keep_alive: model.keep_alive.clone(),
-                    supports_tools: model.supports_tools,
+                    supports_tools,
+                    supports_images,
+                    supports_thinking,
+                    supports_parallel_tool_calls: model.supports_parallel_tool_calls,
                 },
             );
         }
@@ -230,7 +271,10 @@ impl LanguageModelProvider for OllamaLanguageModelProvider {
                     id: LanguageModelId::from(model.name.clone()),
                     model: model.clone(),
                     http_client: self.http_client.clone(),
-                    request_limiter: RateLimiter::new(4),
+                    request_limiter: RateLimiter::new(
+                        AllLanguageModelSettings::get_global(cx)
+                            .max_concurrent_requests(PROVIDER_ID),
+                    ),
                 }) as Arc<dyn LanguageModel>
             })
             .collect()
@@ -279,9 +323,22 @@ impl OllamaLanguageModel {
                 .messages
                 .into_iter()
                 .map(|msg| match msg.role {
-                    Role::User => ChatMessage::User {
-                        content: msg.string_contents(),
-                    },
+                    Role::User => {
+                        let images = msg
+                            .content
+                            .iter()
+                            .filter_map(|content| match content {
+                                MessageContent::Image(image) if self.supports_images() => {
+                                    Some(image.source.to_string())
+                                }
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>();
+                        ChatMessage::User {
+                            content: msg.string_contents(),
+                            images: (!images.is_empty()).then_some(images),
+                        }
+                    }
                     Role::Assistant => ChatMessage::Assistant {
                         content: msg.string_contents(),
                         tool_calls: None,
@@ -297,6 +354,9 @@ impl OllamaLanguageModel {
                 num_ctx: Some(self.model.max_tokens),
                 stop: Some(request.stop),
                 temperature: request.temperature.or(Some(1.0)),
+                seed: request.seed.map(|seed| seed as i64),
+                top_p: request.top_p,
+                top_k: request.top_k,
                 ..Default::default()
             }),
             tools: request.tools.into_iter().map(tool_into_ollama).collect(),
@@ -326,7 +386,25 @@ impl LanguageModel for OllamaLanguageModel {
     }
 
     fn supports_images(&self) -> bool {
-        false
+        self.model.supports_images.unwrap_or(false)
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        ImageLimits {
+            formats: &[ImageFormat::Png, ImageFormat::Jpeg],
+            // Local models are only limited by their context window.
+            max_images: None,
+            max_total_bytes: None,
+            ..ImageLimits::default()
+        }
+    }
+
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.model.supports_parallel_tool_calls.unwrap_or(false)
+    }
+
+    fn supports_thinking(&self) -> bool {
+        self.model.supports_thinking.unwrap_or(false)
     }
 
     fn supports_tool_choice(&self, choice: LanguageModelToolChoice) -> bool {
@@ -334,6 +412,7 @@ impl LanguageModel for OllamaLanguageModel {
             LanguageModelToolChoice::Auto => false,
             LanguageModelToolChoice::Any => false,
             LanguageModelToolChoice::None => false,
+            LanguageModelToolChoice::Tool(_) => false,
         }
     }
 
@@ -372,6 +451,23 @@ impl LanguageModel for OllamaLanguageModel {
             BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
         >,
     > {
+        let model_id = self.id();
+        let sampling = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings
+                .ollama
+                .available_models
+                .iter()
+                .find(|model| model_id.0 == model.name)
+                .map(|model| model.sampling)
+        })
+        .flatten()
+        .unwrap_or_default();
+        let system_prompt = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings.ollama.system_prompt.clone()
+        })
+        .unwrap_or_default();
+        let request = system_prompt.apply(sampling.apply(request));
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
         let request = self.to_ollama_request(request);
 
         let http_client = self.http_client.clone();
@@ -379,16 +475,18 @@ impl LanguageModel for OllamaLanguageModel {
             let settings = &AllLanguageModelSettings::get_global(cx).ollama;
             settings.api_url.clone()
         }) else {
-            return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
+            return request_log
+                .track(futures::future::ready(Err(anyhow!("App state dropped"))).boxed());
         };
 
         let future = self.request_limiter.stream(async move {
             let stream = stream_chat_completion(http_client.as_ref(), &api_url, request).await?;
-            let stream = map_to_language_model_completion_events(stream);
-            Ok(stream)
+            Ok(estimate_live_usage(normalize_reasoning(
+                map_to_language_model_completion_events(stream).boxed(),
+            )))
         });
 
-        future.map_ok(|f| f.boxed()).boxed()
+        request_log.track(future.map_ok(|f| f.boxed()).boxed())
     }
 }
 
@@ -424,7 +522,7 @@ fn map_to_language_model_completion_events(
             let mut events = Vec::new();
 
             match delta.message {
-                ChatMessage::User { content } => {
+                ChatMessage::User { content, .. } => {
                     events.push(Ok(LanguageModelCompletionEvent::Text(content)));
                 }
                 ChatMessage::System { content } => {
@@ -465,6 +563,10 @@ fn map_to_language_model_completion_events(
                 if state.used_tools {
                     state.used_tools = false;
                     events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::ToolUse)));
+                } else if delta.done_reason.as_deref() == Some("length") {
+                    events.push(Ok(LanguageModelCompletionEvent::Stop(
+                        StopReason::MaxTokens,
+                    )));
                 } else {
                     events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::EndTurn)));
                 }
@@ -3,19 +3,25 @@ use collections::{BTreeMap, HashMap};
 use credentials_provider::CredentialsProvider;
 use editor::{Editor, EditorElement, EditorStyle};
 use futures::Stream;
-use futures::{FutureExt, StreamExt, future::BoxFuture};
+use futures::{FutureExt, StreamExt, future::BoxFuture, stream::BoxStream};
 use gpui::{
-    AnyView, App, AsyncApp, Context, Entity, FontStyle, Subscription, Task, TextStyle, WhiteSpace,
+    AnyView, App, AsyncApp, Context, Entity, FontStyle, ImageFormat, Subscription, Task, TextStyle,
+    WhiteSpace,
 };
-use http_client::HttpClient;
+use http_client::{HttpClient, http};
 use language_model::{
-    AuthenticateError, LanguageModel, LanguageModelCompletionError, LanguageModelCompletionEvent,
-    LanguageModelId, LanguageModelName, LanguageModelProvider, LanguageModelProviderId,
-    LanguageModelProviderName, LanguageModelProviderState, LanguageModelRequest,
-    LanguageModelToolChoice, LanguageModelToolResultContent, LanguageModelToolUse, MessageContent,
-    RateLimiter, Role, StopReason,
+    AuthenticateError, DocumentLimits, ImageLimits, LanguageModel, LanguageModelCompletionError,
+    LanguageModelCompletionEvent, LanguageModelId, LanguageModelImage, LanguageModelName,
+    LanguageModelProvider, LanguageModelProviderId, LanguageModelProviderName,
+    LanguageModelProviderState, LanguageModelRequest, LanguageModelRequestMessage,
+    LanguageModelToolChoice, LanguageModelToolResultContent, LanguageModelToolSchemaFormat,
+    LanguageModelToolUse, MessageContent, ModelPrice, RateLimiter, Role, StopReason,
+    StructuredOutput, TokenLogprob, TopLogprob,
+};
+use open_ai::realtime::ClientEvent;
+use open_ai::{
+    Account, ImageUrl, Model, ResponseStreamEvent, SystemPromptRole, stream_completion_for_account,
 };
-use open_ai::{ImageUrl, Model, ResponseStreamEvent, stream_completion};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use settings::{Settings, SettingsStore};
@@ -27,18 +33,71 @@ use theme::ThemeSettings;
 use ui::{Icon, IconName, List, Tooltip, prelude::*};
 use util::ResultExt;
 
-use crate::{AllLanguageModelSettings, ui::InstructionListItem};
+use crate::api_key::{
+    ApiKeyDisplay, ApiKeyRotation, ApiKeySaveScope, ApiKeySource, ApiKeyStatus, ApiKeyValidation,
+    ApiKeys, CredentialsScope, WorkspaceApiKeys, WorkspaceScope, carry_over_api_key, load_api_keys,
+    refresh_api_keys, validate_api_keys,
+};
+use crate::batch::{BatchApi, BatchQueue, BatchSettings};
+use crate::key_usage::KeyUsageRecorder;
+use crate::live_usage::estimate_live_usage;
+use crate::model_family::ModelFamily;
+use crate::moderation::check_moderation;
+use crate::output_tokens::OutputTokenClamp;
+use crate::partial_tool_use::partial_tool_use;
+use crate::pricing::model_price;
+use crate::prompt_template::PromptTemplate;
+use crate::realtime::RealtimeSession;
+use crate::reasoning::normalize_reasoning;
+use crate::request_log::RequestLogRecorder;
+use crate::spend_limits::check_spend_limits;
+use crate::stream_timeouts::StreamTimeouts;
+use crate::{
+    AllLanguageModelSettings, ProviderApiSettings, ReasoningSettings, SamplingParameters,
+    ui::InstructionListItem,
+};
+use entra_id::EntraIdCredentials;
+use realtime::{RealtimeEventMapper, RealtimeRequest, into_realtime};
+use responses::{ResponseChains, ResponsesEventMapper, into_responses};
+
+pub(crate) const PROVIDER_ID: &str = "openai";
+pub(crate) const PROVIDER_NAME: &str = "OpenAI";
 
-const PROVIDER_ID: &str = "openai";
-const PROVIDER_NAME: &str = "OpenAI";
+pub(crate) fn api_key_validation_request(api_url: &str, api_key: &str) -> http::request::Builder {
+    http::Request::builder()
+        .uri(format!("{api_url}/models"))
+        .header("Authorization", format!("Bearer {api_key}"))
+}
+
+mod entra_id;
+mod realtime;
+mod responses;
 
 #[derive(Default, Clone, Debug, PartialEq)]
 pub struct OpenAiSettings {
     pub api_url: String,
+    pub api: ProviderApiSettings,
+    pub entra_id: Option<EntraIdSettings>,
+    pub organization_id: Option<String>,
+    pub project_id: Option<String>,
     pub available_models: Vec<AvailableModel>,
     pub needs_setting_migration: bool,
 }
 
+/// Authenticates Azure OpenAI requests with Microsoft Entra ID tokens instead of an API key.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum EntraIdSettings {
+    /// Uses a service principal. The client secret is read from the `AZURE_CLIENT_SECRET`
+    /// environment variable.
+    ClientCredentials {
+        tenant_id: String,
+        client_id: String,
+    },
+    /// Uses the account signed in with `az login`.
+    AzureCli { tenant_id: Option<String> },
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
 pub struct AvailableModel {
     pub name: String,
@@ -46,61 +105,347 @@ pub struct AvailableModel {
     pub max_tokens: usize,
     pub max_output_tokens: Option<u32>,
     pub max_completion_tokens: Option<u32>,
+    /// The role that the model takes its instructions in: `system`, `developer` for reasoning
+    /// models that reject system messages, or `user` for models that take neither.
+    ///
+    /// Default: the role of the built-in model with the same name, or else system
+    #[serde(default)]
+    pub system_prompt_role: Option<SystemPromptRole>,
+    /// Whether to stream completions from this model. Turn this off for models and gateways
+    /// that only support blocking completions; the whole response then arrives at once.
+    ///
+    /// Default: true
+    #[serde(default)]
+    pub stream: Option<bool>,
+    /// How the server constrains responses to a schema.
+    ///
+    /// Default: json_schema
+    #[serde(default)]
+    pub response_schema_format: Option<ResponseSchemaFormat>,
+    /// Whether tools are sent in strict mode, so that the model's tool calls always follow their
+    /// input schemas. Schemas are adapted to the subset of JSON schema that strict mode
+    /// supports, which makes optional parameters nullable. Only set this for servers that
+    /// support strict mode.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub strict_tools: Option<bool>,
+    /// Whether the model may call several tools in one response. When set, the
+    /// `parallel_tool_calls` parameter is sent with requests that include tools, so only set
+    /// this for servers that accept it. Set it to false for models that misbehave when calling
+    /// tools in parallel.
+    #[serde(default, alias = "parallel_tool_calls")]
+    pub supports_parallel_tool_calls: Option<bool>,
+    /// How much the model reasons before it responds.
+    #[serde(default)]
+    pub reasoning: ReasoningSettings,
+    /// Sends requests to this model through OpenAI's Responses API instead of chat completions.
+    #[serde(default)]
+    pub responses_api: Option<ResponsesApiSettings>,
+    /// How requests to this model are sent: `http`, or `realtime` for models that are only
+    /// served over Realtime API WebSocket sessions, like `gpt-4o-realtime-preview`. Realtime
+    /// sessions take precedence over `responses_api`.
+    ///
+    /// Default: "realtime" if the model's name contains `realtime`, or else "http"
+    #[serde(default)]
+    pub transport: Option<Transport>,
+    /// Sends requests to this model's raw `/completions` endpoint with the conversation written
+    /// into a single prompt in this format, for base models and older local models that aren't
+    /// served with a chat template: `chatml`, `llama3`, `alpaca`, or a `custom` Handlebars
+    /// template. Such models can't call tools or see images. Takes precedence over `transport`
+    /// and `responses_api`.
+    #[serde(default)]
+    pub prompt_template: Option<PromptTemplate>,
+    /// Parameters to add to the body of every request to this model, for features that Zed
+    /// doesn't support directly, like vLLM's `min_p` or `guided_regex`. They replace any
+    /// parameter of the same name.
+    #[serde(default)]
+    pub extra_body: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Whether the model can call tools. Turn this off for self-hosted models that weren't
+    /// trained to, so that they aren't offered any.
+    ///
+    /// Default: whether the server's model listing says it takes `tools`, like OpenRouter's
+    /// does, or else true
+    #[serde(default)]
+    pub supports_tools: Option<bool>,
+    /// Whether the model takes images as input, so that attached images are sent to it.
+    ///
+    /// Default: whether the server's model listing says it takes images, like OpenRouter's
+    /// does, or else whether the built-in model with the same name does
+    #[serde(default)]
+    pub supports_images: Option<bool>,
+    /// Whether the model reasons before it responds. The `reasoning` settings are only sent to
+    /// models that do.
+    ///
+    /// Default: whether the built-in model with the same name does, or `reasoning` is set
+    #[serde(default)]
+    pub supports_thinking: Option<bool>,
+    /// Whether the model takes audio as input, like `gpt-4o-audio-preview`. Audio isn't sent to
+    /// models that use the Responses API, which doesn't take it.
+    ///
+    /// Default: whether the model's name says it's an audio model
+    #[serde(default)]
+    pub supports_audio: Option<bool>,
+    /// Sampling parameters for requests to this model.
+    #[serde(flatten)]
+    pub sampling: SamplingParameters,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    /// Streams each response over its own HTTP request, as server-sent events.
+    Http,
+    /// Streams each response over a Realtime API WebSocket session.
+    Realtime,
+}
+
+/// How requests are sent through the Responses API.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ResponsesApiSettings {
+    /// Tools that OpenAI runs itself, which the model can call alongside Zed's tools.
+    #[serde(default)]
+    pub built_in_tools: Vec<BuiltInTool>,
+    /// Whether OpenAI stores responses, so that each request in a thread only sends what's new
+    /// since the thread's last response.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub store: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BuiltInTool {
+    /// Searches the web.
+    WebSearch,
+    /// Searches files that were uploaded to OpenAI's vector stores.
+    FileSearch {
+        vector_store_ids: Vec<String>,
+        #[serde(default)]
+        max_num_results: Option<u32>,
+    },
+}
+
+/// How an OpenAI-compatible server constrains responses to a schema.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseSchemaFormat {
+    /// OpenAI's `json_schema` response format, in strict mode.
+    #[default]
+    JsonSchema,
+    /// vLLM's `guided_json` parameter.
+    GuidedJson,
+    /// The `json_object` response format, for servers that can only constrain responses to JSON
+    /// objects. The schema is described in the prompt instead.
+    JsonObject,
+    /// The server can't constrain responses, so schemas are ignored.
+    None,
 }
 
 pub struct OpenAiLanguageModelProvider {
     http_client: Arc<dyn HttpClient>,
     state: gpui::Entity<State>,
+    response_chains: ResponseChains,
+    batch_queue: BatchQueue<OpenAiBatchApi>,
 }
 
 pub struct State {
-    api_key: Option<String>,
-    api_key_from_env: bool,
+    http_client: Arc<dyn HttpClient>,
+    api_url: String,
+    api_keys: ApiKeys,
+    api_key_source: ApiKeySource,
+    workspace_api_keys: WorkspaceApiKeys,
+    api_key_status: ApiKeyStatus,
+    entra_id: Option<EntraIdSettings>,
+    entra_id_credentials: Option<Arc<EntraIdCredentials>>,
+    entra_id_error: Option<SharedString>,
+    /// What the server's `/models` listing says about each model, by ID.
+    listed_models: HashMap<String, open_ai::models::ModelObject>,
+    /// The API URL that `listed_models` were fetched from.
+    listed_models_url: Option<String>,
+    _api_key_refresh_task: Option<Task<()>>,
+    _fetch_listed_models_task: Option<Task<()>>,
     _subscription: Subscription,
+    _credentials_scope_subscription: Subscription,
 }
 
 const OPENAI_API_KEY_VAR: &str = "OPENAI_API_KEY";
 
+/// The credentials of the OpenAI provider, for OpenAI's APIs other than chat completions, like
+/// text-to-speech, to authenticate with.
+#[derive(Clone)]
+pub(crate) struct OpenAiAuth {
+    pub http_client: Arc<dyn HttpClient>,
+    pub api_url: String,
+    pub account: Account,
+    api_keys: ApiKeys,
+    rotation: ApiKeyRotation,
+    entra_id_credentials: Option<Arc<EntraIdCredentials>>,
+}
+
+impl OpenAiAuth {
+    pub fn read(state: &Entity<State>, cx: &AsyncApp) -> Result<Self> {
+        cx.read_entity(state, Self::new)
+    }
+
+    pub fn new(state: &State, cx: &App) -> Self {
+        let settings = &AllLanguageModelSettings::get_global(cx).openai;
+        Self {
+            http_client: state.http_client.clone(),
+            api_url: settings.api_url.clone(),
+            account: Account {
+                organization_id: settings.organization_id.clone(),
+                project_id: settings.project_id.clone(),
+            },
+            api_keys: state.api_key_status.track(&state.api_keys),
+            rotation: settings.api.api_key_rotation,
+            entra_id_credentials: state.entra_id_credentials.clone(),
+        }
+    }
+
+    /// The token to send in the `Authorization` header of the next request.
+    pub async fn bearer_token(&self) -> Result<String> {
+        if let Some(credentials) = &self.entra_id_credentials {
+            return credentials.access_token(self.http_client.as_ref()).await;
+        }
+        self.api_keys
+            .next_for_request(self.rotation)
+            .context("Missing OpenAI API Key")
+    }
+
+    pub fn record_error(&self, token: &str, error: &anyhow::Error) {
+        if self.entra_id_credentials.is_none() {
+            self.api_keys.record_error(token, error);
+        }
+    }
+}
+
 impl State {
     fn is_authenticated(&self) -> bool {
-        self.api_key.is_some()
+        if self.entra_id.is_some() {
+            self.entra_id_credentials.is_some()
+        } else {
+            !self.api_keys.is_empty() || self.workspace_api_keys.has_any()
+        }
     }
 
-    fn reset_api_key(&self, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn reset_api_key(
+        &self,
+        workspace: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<()>> {
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .openai
             .api_url
             .clone();
+        if let Some(workspace) = workspace {
+            let task = self
+                .workspace_api_keys
+                .reset(&workspace, PROVIDER_ID, &api_url, cx);
+            cx.notify();
+            return task;
+        }
+        let credentials_scope = CredentialsScope::global(cx);
+        let credentials_urls = [
+            credentials_scope.api_key_url(&self.api_key_source, PROVIDER_ID, &api_url),
+            credentials_scope.legacy_api_key_url(&self.api_key_source, &api_url),
+        ];
         cx.spawn(async move |this, cx| {
-            credentials_provider
-                .delete_credentials(&api_url, &cx)
-                .await
-                .log_err();
+            for credentials_url in &credentials_urls {
+                credentials_provider
+                    .delete_credentials(credentials_url, &cx)
+                    .await
+                    .log_err();
+            }
             this.update(cx, |this, cx| {
-                this.api_key = None;
-                this.api_key_from_env = false;
+                this.api_keys = ApiKeys::default();
+                this.api_key_status.set_rejected(false, cx);
+                this.api_key_source = ApiKeySource::CredentialsStore;
+                this._api_key_refresh_task = None;
                 cx.notify();
             })
         })
     }
 
-    fn set_api_key(&mut self, api_key: String, cx: &mut Context<Self>) -> Task<Result<()>> {
+    fn set_api_key(
+        &mut self,
+        api_key: String,
+        workspace_scope: Option<WorkspaceScope>,
+        cx: &mut Context<Self>,
+    ) -> Task<Result<Option<SharedString>>> {
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .openai
             .api_url
             .clone();
+        let http_client = self.http_client.clone();
+        let source = workspace_scope.map_or(
+            ApiKeySource::CredentialsStore,
+            ApiKeySource::WorkspaceCredentialsStore,
+        );
+        let credentials_url =
+            CredentialsScope::global(cx).api_key_url(&source, PROVIDER_ID, &api_url);
         cx.spawn(async move |this, cx| {
+            let warning =
+                validate_api_keys(http_client.as_ref(), &ApiKeys::parse(&api_key), |api_key| {
+                    api_key_validation_request(&api_url, api_key)
+                })
+                .await?;
+
             credentials_provider
-                .write_credentials(&api_url, "Bearer", api_key.as_bytes(), &cx)
+                .write_credentials(&credentials_url, "Bearer", api_key.as_bytes(), &cx)
                 .await
                 .log_err();
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
+                match source.workspace() {
+                    Some(workspace) => this
+                        .workspace_api_keys
+                        .set(workspace, Some(ApiKeys::parse(&api_key))),
+                    None => {
+                        this.api_keys = ApiKeys::parse(&api_key);
+                        this.api_key_source = source;
+                    }
+                }
+                this.api_key_status.set_rejected(false, cx);
                 cx.notify();
-            })
+            })?;
+            Ok(warning)
+        })
+    }
+
+    /// Reloads the keys saved for a new `api_url`, carrying the current key over if none was
+    /// saved for it yet.
+    fn update_api_url(&mut self, cx: &mut Context<Self>) {
+        let api_url = AllLanguageModelSettings::get_global(cx)
+            .openai
+            .api_url
+            .clone();
+        if self.api_url == api_url {
+            return;
+        }
+        let previous_api_url = std::mem::replace(&mut self.api_url, api_url);
+        self.workspace_api_keys.clear();
+        if self.api_key_source.is_external() {
+            return;
+        }
+
+        let carry_over = carry_over_api_key(
+            &self.api_key_source,
+            PROVIDER_ID,
+            &previous_api_url,
+            &self.api_url,
+            cx,
+        );
+        self.api_keys = ApiKeys::default();
+        cx.spawn(async move |this, cx| {
+            carry_over.await;
+            if let Ok(task) = this.update(cx, |this, cx| this.authenticate(cx)) {
+                task.await.ok();
+            }
         })
+        .detach();
     }
 
     fn authenticate(&self, cx: &mut Context<Self>) -> Task<Result<(), AuthenticateError>> {
@@ -108,55 +453,238 @@ impl State {
             return Task::ready(Ok(()));
         }
 
+        if let Some(entra_id) = self.entra_id.clone() {
+            let http_client = self.http_client.clone();
+            return cx.spawn(async move |this, cx| {
+                let credentials = EntraIdCredentials::new(entra_id);
+                let result = credentials.access_token(http_client.as_ref()).await;
+                this.update(cx, |this, cx| {
+                    match &result {
+                        Ok(_) => {
+                            this.entra_id_credentials = Some(Arc::new(credentials));
+                            this.entra_id_error = None;
+                        }
+                        Err(error) => this.entra_id_error = Some(format!("{error:#}").into()),
+                    }
+                    cx.notify();
+                })?;
+                result?;
+                Ok(())
+            });
+        }
+
         let credentials_provider = <dyn CredentialsProvider>::global(cx);
         let api_url = AllLanguageModelSettings::get_global(cx)
             .openai
             .api_url
             .clone();
+        let api_settings = AllLanguageModelSettings::get_global(cx).openai.api.clone();
+        let credentials_scope = CredentialsScope::global(cx);
+        let load_workspace_api_keys =
+            self.workspace_api_keys
+                .load_open_workspaces(PROVIDER_ID, &api_url, cx);
         cx.spawn(async move |this, cx| {
-            let (api_key, from_env) = if let Ok(api_key) = std::env::var(OPENAI_API_KEY_VAR) {
-                (api_key, true)
-            } else {
-                let (_, api_key) = credentials_provider
-                    .read_credentials(&api_url, &cx)
-                    .await?
-                    .ok_or(AuthenticateError::CredentialsNotFound)?;
-                (
-                    String::from_utf8(api_key).context("invalid {PROVIDER_NAME} API key")?,
-                    false,
-                )
+            let api_keys = load_api_keys(
+                OPENAI_API_KEY_VAR,
+                &api_settings,
+                credentials_provider,
+                PROVIDER_ID,
+                &api_url,
+                credentials_scope,
+                &cx,
+            )
+            .await;
+            let has_workspace_api_keys = load_workspace_api_keys.await;
+            let (api_keys, source) = match api_keys {
+                Err(AuthenticateError::CredentialsNotFound) if has_workspace_api_keys => {
+                    this.update(cx, |_, cx| cx.notify())?;
+                    return Ok(());
+                }
+                api_keys => api_keys?,
             };
+
             this.update(cx, |this, cx| {
-                this.api_key = Some(api_key);
-                this.api_key_from_env = from_env;
+                this._api_key_refresh_task =
+                    refresh_api_keys(&source, &api_keys, &api_settings, cx, |this, api_keys| {
+                        this.api_keys = api_keys
+                    });
+                this.api_keys = api_keys;
+                this.api_key_source = source;
                 cx.notify();
             })?;
 
             Ok(())
         })
     }
+
+    /// Fetches the server's model listing once it can be authenticated with, since servers like
+    /// OpenRouter report which of their models take images and tools there.
+    fn fetch_listed_models(&mut self, cx: &mut Context<Self>) {
+        if !self.is_authenticated() || self.listed_models_url.as_ref() == Some(&self.api_url) {
+            return;
+        }
+        self.listed_models_url = Some(self.api_url.clone());
+        self._fetch_listed_models_task = Some(cx.spawn(async move |this, cx| {
+            let models = async {
+                let state = this.upgrade().context("OpenAI provider was dropped")?;
+                let auth = OpenAiAuth::read(&state, cx)?;
+                let token = auth.bearer_token().await?;
+                open_ai::models::list_models(
+                    auth.http_client.as_ref(),
+                    &auth.api_url,
+                    &token,
+                    &auth.account,
+                )
+                .await
+            };
+            match models.await {
+                Ok(models) => {
+                    this.update(cx, |this, cx| {
+                        this.listed_models = models
+                            .into_iter()
+                            .map(|model| (model.id.clone(), model))
+                            .collect();
+                        cx.notify();
+                    })
+                    .ok();
+                }
+                // Not every OpenAI-compatible server lists its models, and the ones that don't
+                // are still usable.
+                Err(error) => log::debug!("failed to list OpenAI models: {error:#}"),
+            }
+        }));
+    }
 }
 
 impl OpenAiLanguageModelProvider {
     pub fn new(http_client: Arc<dyn HttpClient>, cx: &mut App) -> Self {
         let state = cx.new(|cx| State {
-            api_key: None,
-            api_key_from_env: false,
-            _subscription: cx.observe_global::<SettingsStore>(|_this: &mut State, cx| {
+            http_client: http_client.clone(),
+            api_url: AllLanguageModelSettings::get_global(cx)
+                .openai
+                .api_url
+                .clone(),
+            api_keys: ApiKeys::default(),
+            api_key_source: ApiKeySource::default(),
+            workspace_api_keys: WorkspaceApiKeys::default(),
+            api_key_status: ApiKeyStatus::new(cx, |state| &mut state.api_key_status),
+            entra_id: AllLanguageModelSettings::get_global(cx)
+                .openai
+                .entra_id
+                .clone(),
+            entra_id_credentials: None,
+            entra_id_error: None,
+            listed_models: HashMap::default(),
+            listed_models_url: None,
+            _api_key_refresh_task: None,
+            _fetch_listed_models_task: None,
+            _subscription: cx.observe_global::<SettingsStore>(|this: &mut State, cx| {
+                let entra_id = &AllLanguageModelSettings::get_global(cx).openai.entra_id;
+                if this.entra_id != *entra_id {
+                    this.entra_id = entra_id.clone();
+                    this.entra_id_credentials = None;
+                    this.entra_id_error = None;
+                    this.authenticate(cx).detach();
+                }
+                this.update_api_url(cx);
                 cx.notify();
             }),
+            _credentials_scope_subscription: cx.observe_global::<CredentialsScope>(
+                |this: &mut State, cx| {
+                    this.workspace_api_keys.clear();
+                    if !this.api_key_source.is_external() {
+                        this.api_keys = ApiKeys::default();
+                        this.api_key_status.set_rejected(false, cx);
+                        this.authenticate(cx).detach();
+                        cx.notify();
+                    }
+                },
+            ),
         });
+        cx.observe(&state, |state, cx| {
+            state.update(cx, |state, cx| state.fetch_listed_models(cx));
+        })
+        .detach();
 
-        Self { http_client, state }
+        Self {
+            http_client,
+            state,
+            response_chains: ResponseChains::default(),
+            batch_queue: BatchQueue::default(),
+        }
     }
 
-    fn create_language_model(&self, model: open_ai::Model) -> Arc<dyn LanguageModel> {
+    /// The provider's state, whose credentials other OpenAI services share.
+    pub(crate) fn state(&self) -> Entity<State> {
+        self.state.clone()
+    }
+
+    fn create_language_model(&self, model: open_ai::Model, cx: &App) -> Arc<dyn LanguageModel> {
+        let available_model = AllLanguageModelSettings::get_global(cx)
+            .openai
+            .available_models
+            .iter()
+            .find(|available_model| available_model.name == model.id());
+        let response_schema_format = available_model
+            .and_then(|available_model| available_model.response_schema_format)
+            .unwrap_or_default();
+        let strict_tools = available_model
+            .and_then(|available_model| available_model.strict_tools)
+            .unwrap_or(false);
+        let prompt_template =
+            available_model.and_then(|available_model| available_model.prompt_template.clone());
+        let listed_model = self.state.read(cx).listed_models.get(model.id());
+        let built_in = built_in_model(model.id());
+        let family = ModelFamily::infer(model.id());
+        let supports_tools = available_model
+            .and_then(|available_model| available_model.supports_tools)
+            .or_else(|| listed_model.and_then(|listed_model| listed_model.supports_tools()))
+            .or_else(|| family.map(|family| family.supports_tools))
+            .unwrap_or(true);
+        let supports_images = available_model
+            .and_then(|available_model| available_model.supports_images)
+            .or_else(|| listed_model.and_then(|listed_model| listed_model.supports_images()))
+            .or_else(|| built_in.as_ref().map(|model| model.supports_images()))
+            .unwrap_or_else(|| family.is_some_and(|family| family.supports_images));
+        let supports_thinking = available_model
+            .and_then(|available_model| available_model.supports_thinking)
+            .unwrap_or_else(|| {
+                built_in
+                    .as_ref()
+                    .map(|model| model.supports_reasoning())
+                    .unwrap_or_else(|| family.is_some_and(|family| family.supports_thinking))
+                    || available_model
+                        .is_some_and(|available_model| available_model.reasoning.effort.is_some())
+            });
+        let supports_parallel_tool_calls = available_model
+            .and_then(|available_model| available_model.supports_parallel_tool_calls)
+            .unwrap_or_else(|| model.supports_parallel_tool_calls());
+        // The Responses API doesn't take audio.
+        let supports_audio = available_model
+            .and_then(|available_model| available_model.supports_audio)
+            .unwrap_or_else(|| model.supports_audio())
+            && available_model
+                .is_none_or(|available_model| available_model.responses_api.is_none());
+        // Prompts only have room for text.
+        let completion_style = prompt_template.is_some();
         Arc::new(OpenAiLanguageModel {
             id: LanguageModelId::from(model.id().to_string()),
             model,
+            response_schema_format,
+            strict_tools,
+            prompt_template,
+            supports_tools: supports_tools && !completion_style,
+            supports_images: supports_images && !completion_style,
+            supports_thinking,
+            supports_parallel_tool_calls,
+            supports_audio: supports_audio && !completion_style,
             state: self.state.clone(),
             http_client: self.http_client.clone(),
-            request_limiter: RateLimiter::new(4),
+            response_chains: self.response_chains.clone(),
+            batch_queue: self.batch_queue.clone(),
+            request_limiter: RateLimiter::new(
+                AllLanguageModelSettings::get_global(cx).max_concurrent_requests(PROVIDER_ID),
+            ),
         })
     }
 }
@@ -182,12 +710,12 @@ impl LanguageModelProvider for OpenAiLanguageModelProvider {
         IconName::AiOpenAi
     }
 
-    fn default_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(open_ai::Model::default()))
+    fn default_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(open_ai::Model::default(), cx))
     }
 
-    fn default_fast_model(&self, _cx: &App) -> Option<Arc<dyn LanguageModel>> {
-        Some(self.create_language_model(open_ai::Model::default_fast()))
+    fn default_fast_model(&self, cx: &App) -> Option<Arc<dyn LanguageModel>> {
+        Some(self.create_language_model(open_ai::Model::default_fast(), cx))
     }
 
     fn provided_models(&self, cx: &App) -> Vec<Arc<dyn LanguageModel>> {
@@ -200,6 +728,28 @@ impl LanguageModelProvider for OpenAiLanguageModelProvider {
             }
         }
 
+        // Add the account's fine-tuned models, which are like the models they were trained from
+        for id in self.state.read(cx).listed_models.keys() {
+            let Some(fine_tuned) = open_ai::models::FineTunedModelId::parse(id) else {
+                continue;
+            };
+            let Some(base_model) = built_in_model(id) else {
+                continue;
+            };
+            let name = fine_tuned.suffix.unwrap_or("fine-tuned");
+            models.insert(
+                id.clone(),
+                open_ai::Model::Custom {
+                    name: id.clone(),
+                    display_name: Some(format!("{} ({name})", fine_tuned.base_model)),
+                    max_tokens: base_model.max_token_count(),
+                    max_output_tokens: base_model.max_output_tokens(),
+                    max_completion_tokens: None,
+                    system_prompt_role: Some(base_model.system_prompt_role()),
+                },
+            );
+        }
+
         // Override with available models from settings
         for model in &AllLanguageModelSettings::get_global(cx)
             .openai
@@ -213,13 +763,16 @@ impl LanguageModelProvider for OpenAiLanguageModelProvider {
                     max_tokens: model.max_tokens,
                     max_output_tokens: model.max_output_tokens,
                     max_completion_tokens: model.max_completion_tokens,
+                    system_prompt_role: model.system_prompt_role.or_else(|| {
+                        built_in_model(&model.name).map(|model| model.system_prompt_role())
+                    }),
                 },
             );
         }
 
         models
             .into_values()
-            .map(|model| self.create_language_model(model))
+            .map(|model| self.create_language_model(model, cx))
             .collect()
     }
 
@@ -227,6 +780,10 @@ impl LanguageModelProvider for OpenAiLanguageModelProvider {
         self.state.read(cx).is_authenticated()
     }
 
+    fn credentials_rejected(&self, cx: &App) -> bool {
+        self.state.read(cx).api_key_status.is_rejected()
+    }
+
     fn authenticate(&self, cx: &mut App) -> Task<Result<(), AuthenticateError>> {
         self.state.update(cx, |state, cx| state.authenticate(cx))
     }
@@ -237,42 +794,336 @@ impl LanguageModelProvider for OpenAiLanguageModelProvider {
     }
 
     fn reset_credentials(&self, cx: &mut App) -> Task<Result<()>> {
-        self.state.update(cx, |state, cx| state.reset_api_key(cx))
+        self.state
+            .update(cx, |state, cx| state.reset_api_key(None, cx))
     }
 }
 
 pub struct OpenAiLanguageModel {
     id: LanguageModelId,
     model: open_ai::Model,
+    response_schema_format: ResponseSchemaFormat,
+    strict_tools: bool,
+    prompt_template: Option<PromptTemplate>,
+    supports_tools: bool,
+    supports_images: bool,
+    supports_thinking: bool,
+    supports_parallel_tool_calls: bool,
+    supports_audio: bool,
     state: gpui::Entity<State>,
     http_client: Arc<dyn HttpClient>,
+    response_chains: ResponseChains,
+    batch_queue: BatchQueue<OpenAiBatchApi>,
     request_limiter: RateLimiter,
 }
 
+/// OpenAI's Batch API, used with one API key. Hosts that don't implement it, like Groq, fail
+/// to submit batches.
+struct OpenAiBatchApi {
+    http_client: Arc<dyn HttpClient>,
+    api_url: String,
+    api_key: String,
+    account: Account,
+}
+
+impl BatchApi for OpenAiBatchApi {
+    type Request = open_ai::Request;
+    type Response = open_ai::Response;
+
+    fn submit(
+        &self,
+        requests: Vec<(String, open_ai::Request)>,
+    ) -> BoxFuture<'static, Result<String>> {
+        let http_client = self.http_client.clone();
+        let api_url = self.api_url.clone();
+        let api_key = self.api_key.clone();
+        let account = self.account.clone();
+        async move {
+            let batch = open_ai::batches::create_batch(
+                http_client.as_ref(),
+                &api_url,
+                &api_key,
+                &account,
+                &requests,
+            )
+            .await?;
+            Ok(batch.id)
+        }
+        .boxed()
+    }
+
+    fn results(
+        &self,
+        batch_id: &str,
+    ) -> BoxFuture<'static, Result<Option<HashMap<String, Result<open_ai::Response>>>>> {
+        let http_client = self.http_client.clone();
+        let api_url = self.api_url.clone();
+        let api_key = self.api_key.clone();
+        let account = self.account.clone();
+        let batch_id = batch_id.to_string();
+        async move {
+            let batch = open_ai::batches::retrieve_batch(
+                http_client.as_ref(),
+                &api_url,
+                &api_key,
+                &account,
+                &batch_id,
+            )
+            .await?;
+            if !batch.is_finished() {
+                return Ok(None);
+            }
+            if let Some(errors) = &batch.errors {
+                for error in &errors.data {
+                    log::error!("OpenAI batch {batch_id} failed: {}", error.message);
+                }
+            }
+            let lines = open_ai::batches::batch_output(
+                http_client.as_ref(),
+                &api_url,
+                &api_key,
+                &account,
+                &batch,
+            )
+            .await?;
+            Ok(Some(
+                lines
+                    .into_iter()
+                    .map(|line| (line.custom_id.clone(), line.into_result()))
+                    .collect(),
+            ))
+        }
+        .boxed()
+    }
+}
+
+/// A request to one of the APIs that OpenAI models are used through.
+#[derive(Clone)]
+enum ApiRequest {
+    ChatCompletions(open_ai::Request),
+    /// A completion of a prompt that the conversation is written into with a [`PromptTemplate`].
+    TextCompletions(open_ai::CompletionRequest),
+    Responses(open_ai::responses::Request, ResponsesEventMapper),
+    /// A response over a WebSocket session, which is opened with the provider's settings and
+    /// whose socket is driven by the Tokio runtime.
+    Realtime(RealtimeRequest, ProviderApiSettings, tokio::runtime::Handle),
+}
+
+impl ApiRequest {
+    async fn clamp_output_tokens(&mut self, output_tokens: OutputTokenClamp) {
+        let max_output_tokens = match self {
+            Self::ChatCompletions(request) => &mut request.max_tokens,
+            Self::TextCompletions(request) => &mut request.max_tokens,
+            Self::Responses(request, _) => &mut request.max_output_tokens,
+            Self::Realtime(request, _, _) => &mut request.session.max_response_output_tokens,
+        };
+        if let Some(max_tokens) = *max_output_tokens {
+            *max_output_tokens = Some(output_tokens.apply(max_tokens).await);
+        }
+    }
+
+    async fn send(
+        self,
+        client: &dyn HttpClient,
+        api_url: &str,
+        api_key: &str,
+        account: &Account,
+    ) -> Result<
+        BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+    > {
+        match self {
+            Self::ChatCompletions(request) => {
+                let events =
+                    stream_completion_for_account(client, api_url, api_key, account, request)
+                        .await?;
+                Ok(OpenAiEventMapper::new().map_stream(events).boxed())
+            }
+            Self::TextCompletions(request) => {
+                let events =
+                    open_ai::stream_text_completion(client, api_url, api_key, account, request)
+                        .await?;
+                Ok(OpenAiEventMapper::new().map_stream(events).boxed())
+            }
+            Self::Responses(request, mapper) => {
+                let events =
+                    open_ai::responses::stream_response(client, api_url, api_key, account, request)
+                        .await?;
+                Ok(mapper.map_stream(events).boxed())
+            }
+            Self::Realtime(request, api_settings, tokio) => {
+                let mut session = RealtimeSession::connect(
+                    &open_ai::realtime::realtime_url(api_url, &request.model),
+                    &open_ai::realtime::realtime_headers(api_key, account),
+                    &api_settings,
+                    client.proxy().cloned(),
+                    tokio,
+                )
+                .await?;
+                session
+                    .send(&ClientEvent::SessionUpdate {
+                        session: request.session,
+                    })
+                    .await?;
+                for item in request.items {
+                    session
+                        .send(&ClientEvent::ConversationItemCreate { item })
+                        .await?;
+                }
+                session
+                    .send(&ClientEvent::ResponseCreate { response: None })
+                    .await?;
+                Ok(RealtimeEventMapper::new()
+                    .map_stream(session.into_response_events())
+                    .boxed())
+            }
+        }
+    }
+}
+
 impl OpenAiLanguageModel {
     fn stream_completion(
         &self,
-        request: open_ai::Request,
+        mut request: ApiRequest,
+        output_tokens: OutputTokenClamp,
+        usage: KeyUsageRecorder,
+        workspace: Option<WorkspaceScope>,
         cx: &AsyncApp,
-    ) -> BoxFuture<'static, Result<futures::stream::BoxStream<'static, Result<ResponseStreamEvent>>>>
-    {
+    ) -> BoxFuture<
+        'static,
+        Result<
+            BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+        >,
+    > {
         let http_client = self.http_client.clone();
-        let Ok((api_key, api_url)) = cx.read_entity(&self.state, |state, cx| {
-            let settings = &AllLanguageModelSettings::get_global(cx).openai;
-            (state.api_key.clone(), settings.api_url.clone())
-        }) else {
+        let Ok((entra_id_credentials, api_keys, rotation, api_url, account)) =
+            cx.read_entity(&self.state, |state, cx| {
+                let settings = &AllLanguageModelSettings::get_global(cx).openai;
+                (
+                    state.entra_id_credentials.clone(),
+                    state.workspace_api_keys.for_request(
+                        &state.api_keys,
+                        &state.api_key_status,
+                        &state.api_key_source,
+                        workspace.as_ref(),
+                        PROVIDER_ID,
+                        &settings.api_url,
+                        cx,
+                    ),
+                    settings.api.api_key_rotation,
+                    settings.api_url.clone(),
+                    Account {
+                        organization_id: settings.organization_id.clone(),
+                        project_id: settings.project_id.clone(),
+                    },
+                )
+            })
+        else {
             return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
         };
 
         let future = self.request_limiter.stream(async move {
-            let api_key = api_key.context("Missing OpenAI API Key")?;
-            let request = stream_completion(http_client.as_ref(), &api_url, &api_key, request);
-            let response = request.await?;
-            Ok(response)
+            request.clamp_output_tokens(output_tokens).await;
+            if let Some(credentials) = entra_id_credentials {
+                let access_token = credentials.access_token(http_client.as_ref()).await?;
+                return request
+                    .send(http_client.as_ref(), &api_url, &access_token, &account)
+                    .await;
+            }
+
+            let api_keys = api_keys.await;
+            let (http_client, api_url, account) = (http_client.as_ref(), &api_url, &account);
+            let request = &request;
+            api_keys
+                .with_rotation(rotation, "OpenAI", &usage, |api_key| async move {
+                    request
+                        .clone()
+                        .send(http_client, api_url, &api_key, account)
+                        .await
+                })
+                .await
         });
 
         async move { Ok(future.await?.boxed()) }.boxed()
     }
+
+    /// Sends `request` in the next batch, and streams its response once the batch finishes.
+    fn batch_completion(
+        &self,
+        mut request: open_ai::Request,
+        output_tokens: OutputTokenClamp,
+        settings: BatchSettings,
+        usage: KeyUsageRecorder,
+        workspace: Option<WorkspaceScope>,
+        cx: &AsyncApp,
+    ) -> BoxFuture<
+        'static,
+        Result<
+            BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+        >,
+    > {
+        let http_client = self.http_client.clone();
+        let batch_queue = self.batch_queue.clone();
+        let executor = cx.background_executor().clone();
+        let Ok((api_keys, rotation, api_url, account)) =
+            cx.read_entity(&self.state, |state, cx| {
+                let settings = &AllLanguageModelSettings::get_global(cx).openai;
+                (
+                    state.workspace_api_keys.for_request(
+                        &state.api_keys,
+                        &state.api_key_status,
+                        &state.api_key_source,
+                        workspace.as_ref(),
+                        PROVIDER_ID,
+                        &settings.api_url,
+                        cx,
+                    ),
+                    settings.api.api_key_rotation,
+                    settings.api_url.clone(),
+                    Account {
+                        organization_id: settings.organization_id.clone(),
+                        project_id: settings.project_id.clone(),
+                    },
+                )
+            })
+        else {
+            return futures::future::ready(Err(anyhow!("App state dropped"))).boxed();
+        };
+        request.stream = false;
+        request.stream_options = None;
+
+        async move {
+            let api_keys = api_keys.await;
+            if let Some(max_tokens) = request.max_tokens {
+                request.max_tokens = Some(output_tokens.apply(max_tokens).await);
+            }
+            let (http_client, api_url, account) = (&http_client, &api_url, &account);
+            let (batch_queue, executor, request) = (&batch_queue, &executor, &request);
+            let response = api_keys
+                .with_rotation(rotation, "OpenAI", &usage, |api_key| async move {
+                    let api = OpenAiBatchApi {
+                        http_client: http_client.clone(),
+                        api_url: api_url.clone(),
+                        api_key: api_key.clone(),
+                        account: account.clone(),
+                    };
+                    batch_queue
+                        .enqueue(
+                            format!("{api_url}\n{api_key}"),
+                            api,
+                            request.clone(),
+                            settings,
+                            executor,
+                        )
+                        .await
+                })
+                .await?;
+            let event = open_ai::adapt_response_to_stream(response);
+            Ok(OpenAiEventMapper::new()
+                .map_stream(futures::stream::iter([Ok(event)]).boxed())
+                .boxed())
+        }
+        .boxed()
+    }
 }
 
 impl LanguageModel for OpenAiLanguageModel {
@@ -293,18 +1144,69 @@ impl LanguageModel for OpenAiLanguageModel {
     }
 
     fn supports_tools(&self) -> bool {
-        true
+        self.supports_tools
     }
 
     fn supports_images(&self) -> bool {
-        false
+        self.supports_images
+    }
+
+    fn image_limits(&self) -> ImageLimits {
+        OPEN_AI_IMAGE_LIMITS
+    }
+
+    fn document_limits(&self) -> Option<DocumentLimits> {
+        // PDFs are sent to the model as both text and images of their pages.
+        self.supports_images.then(DocumentLimits::default)
+    }
+
+    fn supports_audio(&self) -> bool {
+        self.supports_audio
     }
 
     fn supports_tool_choice(&self, choice: LanguageModelToolChoice) -> bool {
         match choice {
-            LanguageModelToolChoice::Auto => true,
-            LanguageModelToolChoice::Any => true,
-            LanguageModelToolChoice::None => true,
+            LanguageModelToolChoice::Auto
+            | LanguageModelToolChoice::Any
+            | LanguageModelToolChoice::None
+            | LanguageModelToolChoice::Tool(_) => self.supports_tools,
+        }
+    }
+
+    fn tool_input_format(&self) -> LanguageModelToolSchemaFormat {
+        if self.strict_tools {
+            LanguageModelToolSchemaFormat::JsonSchemaStrict
+        } else {
+            LanguageModelToolSchemaFormat::JsonSchema
+        }
+    }
+
+    fn supports_parallel_tool_calls(&self) -> bool {
+        self.supports_parallel_tool_calls
+    }
+
+    fn supports_thinking(&self) -> bool {
+        self.supports_thinking
+    }
+
+    fn supports_candidates(&self) -> bool {
+        self.prompt_template.is_none()
+    }
+
+    fn supports_logprobs(&self) -> bool {
+        self.prompt_template.is_none()
+    }
+
+    fn structured_output(&self) -> StructuredOutput {
+        if self.prompt_template.is_some() {
+            return StructuredOutput::Unsupported;
+        }
+        match self.response_schema_format {
+            ResponseSchemaFormat::JsonSchema | ResponseSchemaFormat::GuidedJson => {
+                StructuredOutput::Native
+            }
+            ResponseSchemaFormat::JsonObject => StructuredOutput::JsonObject,
+            ResponseSchemaFormat::None => StructuredOutput::Unsupported,
         }
     }
 
@@ -312,6 +1214,11 @@ impl LanguageModel for OpenAiLanguageModel {
         format!("openai/{}", self.model.id())
     }
 
+    fn price(&self, cx: &App) -> Option<ModelPrice> {
+        let settings = AllLanguageModelSettings::get_global(cx);
+        model_price(settings, PROVIDER_ID, &self.id.0)
+    }
+
     fn max_token_count(&self) -> usize {
         self.model.max_token_count()
     }
@@ -341,25 +1248,343 @@ impl LanguageModel for OpenAiLanguageModel {
             >,
         >,
     > {
-        let request = into_open_ai(request, &self.model, self.max_output_tokens());
-        let completions = self.stream_completion(request, cx);
-        async move {
-            let mapper = OpenAiEventMapper::new();
-            Ok(mapper.map_stream(completions.await?).boxed())
+        let model_id = self.id();
+        let (
+            sampling,
+            stream,
+            parallel_tool_calls,
+            reasoning,
+            responses_api,
+            extra_body,
+            transport,
+        ) = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings
+                .openai
+                .available_models
+                .iter()
+                .find(|model| model_id.0 == model.name)
+                .map(|model| {
+                    (
+                        model.sampling,
+                        model.stream.unwrap_or(true),
+                        model.supports_parallel_tool_calls,
+                        model.reasoning,
+                        model.responses_api.clone(),
+                        model.extra_body.clone(),
+                        model.transport,
+                    )
+                })
+        })
+        .flatten()
+        .unwrap_or((
+            SamplingParameters::default(),
+            true,
+            None,
+            ReasoningSettings::default(),
+            None,
+            None,
+            None,
+        ));
+        let transport = transport.unwrap_or(if self.model.id().contains("realtime") {
+            Transport::Realtime
+        } else {
+            Transport::Http
+        });
+        let system_prompt = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            settings.openai.api.system_prompt.clone()
+        })
+        .unwrap_or_default();
+        let request = system_prompt.apply(sampling.apply(request));
+        let request_log = RequestLogRecorder::new(self.provider_id(), self.id(), &request, cx);
+        let moderation = check_moderation(&request, cx);
+        let output_tokens = OutputTokenClamp::new(self, &request, cx);
+        // Entra ID tokens expire long before batches finish, so those requests are sent directly.
+        let batch = BatchSettings::for_request(&request, cx).filter(|_| {
+            cx.read_entity(&self.state, |state, _| state.entra_id_credentials.is_none())
+                .unwrap_or(false)
+        });
+        let requested_parallel_tool_calls = request.parallel_tool_calls;
+        let workspace = request.workspace.clone();
+        let parallel_tool_calls = |has_tools: bool| {
+            parallel_tool_calls
+                .filter(|_| has_tools)
+                .map(|parallel_tool_calls| {
+                    parallel_tool_calls && requested_parallel_tool_calls.unwrap_or(true)
+                })
+        };
+        let realtime = match transport {
+            Transport::Realtime => cx
+                .update(|cx| {
+                    (
+                        gpui_tokio::Tokio::handle(cx),
+                        AllLanguageModelSettings::get_global(cx).openai.api.clone(),
+                    )
+                })
+                .ok(),
+            Transport::Http => None,
+        };
+        let request = if let Some(prompt_template) = &self.prompt_template {
+            let request = into_text_completion(
+                request,
+                &self.model,
+                self.max_output_tokens(),
+                prompt_template,
+            );
+            let mut request = match request {
+                Ok(request) => request,
+                Err(error) => {
+                    return request_log.track(futures::future::ready(Err(error)).boxed());
+                }
+            };
+            if !stream {
+                request.stream = false;
+                request.stream_options = None;
+            }
+            request.extra_body = extra_body.unwrap_or_default();
+            ApiRequest::TextCompletions(request)
+        } else if let Some((tokio, api_settings)) = realtime {
+            ApiRequest::Realtime(
+                into_realtime(request, &self.model, self.max_output_tokens()),
+                api_settings,
+                tokio,
+            )
+        } else if let Some(responses_api) = responses_api {
+            let thread_id = request.thread_id.clone();
+            let mut request = into_responses(
+                request,
+                &self.model,
+                self.max_output_tokens(),
+                &responses_api,
+                reasoning
+                    .effort
+                    .filter(|_| self.supports_thinking)
+                    .map(Into::into),
+            );
+            if let Some(parallel_tool_calls) = parallel_tool_calls(!request.tools.is_empty()) {
+                request.parallel_tool_calls = Some(parallel_tool_calls);
+            }
+            for tool in &mut request.tools {
+                if let open_ai::responses::Tool::Function { strict, .. } = tool {
+                    *strict = self.strict_tools;
+                }
+            }
+            match self.response_schema_format {
+                ResponseSchemaFormat::JsonSchema | ResponseSchemaFormat::GuidedJson => {}
+                ResponseSchemaFormat::JsonObject => {
+                    if let Some(text) = &mut request.text {
+                        text.format = open_ai::responses::TextFormat::JsonObject;
+                    }
+                }
+                ResponseSchemaFormat::None => request.text = None,
+            }
+            request.extra_body = extra_body.unwrap_or_default();
+            let mapper = self
+                .response_chains
+                .continue_thread(thread_id, &mut request);
+            ApiRequest::Responses(request, mapper)
+        } else {
+            let mut request = into_open_ai(
+                request,
+                &self.model,
+                self.max_output_tokens(),
+                MAX_IMAGE_BYTES,
+            );
+            if !stream {
+                request.stream = false;
+                request.stream_options = None;
+            }
+            if self.supports_thinking {
+                request.reasoning_effort = reasoning.effort.map(Into::into);
+            }
+            if let Some(parallel_tool_calls) = parallel_tool_calls(!request.tools.is_empty()) {
+                request.parallel_tool_calls = Some(parallel_tool_calls);
+            }
+            if self.strict_tools {
+                for open_ai::ToolDefinition::Function { function } in &mut request.tools {
+                    function.strict = Some(true);
+                }
+            }
+            match self.response_schema_format {
+                ResponseSchemaFormat::JsonSchema => {}
+                ResponseSchemaFormat::GuidedJson => {
+                    request.guided_json = match request.response_format.take() {
+                        Some(open_ai::ResponseFormat::JsonSchema { json_schema }) => {
+                            Some(json_schema.schema)
+                        }
+                        _ => None,
+                    };
+                }
+                ResponseSchemaFormat::JsonObject => {
+                    if request.response_format.is_some() {
+                        request.response_format = Some(open_ai::ResponseFormat::JsonObject);
+                    }
+                }
+                ResponseSchemaFormat::None => request.response_format = None,
+            }
+            request.extra_body = extra_body.unwrap_or_default();
+            ApiRequest::ChatCompletions(request)
+        };
+        let usage =
+            KeyUsageRecorder::new(PROVIDER_ID, self.id.0.to_string(), workspace.clone(), cx);
+        let timeouts = StreamTimeouts::new(|settings| &settings.openai.api, cx);
+        let spend_limits = check_spend_limits(PROVIDER_ID, cx);
+        let (completions, usage) = match (request, batch) {
+            // Batches can take hours, so they don't hold one of the model's concurrent requests.
+            (ApiRequest::ChatCompletions(request), Some(batch)) => {
+                let usage = usage.batched();
+                (
+                    self.batch_completion(
+                        request,
+                        output_tokens,
+                        batch,
+                        usage.clone(),
+                        workspace,
+                        cx,
+                    ),
+                    usage,
+                )
+            }
+            (request, _) => (
+                self.stream_completion(request, output_tokens, usage.clone(), workspace, cx),
+                usage,
+            ),
+        };
+        let strict_tools = self.strict_tools;
+        request_log.track(
+            async move {
+                spend_limits?;
+                moderation.await?;
+                let mut events = completions.await?;
+                if strict_tools {
+                    events = remove_null_arguments(events);
+                }
+                let events = estimate_live_usage(normalize_reasoning(events));
+                Ok(usage.track(timeouts.apply(events)))
+            }
+            .boxed(),
+        )
+    }
+}
+
+/// Strict mode makes optional tool parameters nullable, so the nulls that the model passes for
+/// the ones it leaves out are removed, since tools expect them to be missing.
+fn remove_null_arguments(
+    events: BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+) -> BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>> {
+    fn remove_nulls(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(object) => {
+                object.retain(|_, value| !value.is_null());
+                object.values_mut().for_each(remove_nulls);
+            }
+            serde_json::Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+            _ => {}
         }
+    }
+
+    events
+        .map(|event| match event {
+            Ok(LanguageModelCompletionEvent::ToolUse(mut tool_use)) => {
+                remove_nulls(&mut tool_use.input);
+                Ok(LanguageModelCompletionEvent::ToolUse(tool_use))
+            }
+            event => event,
+        })
         .boxed()
+}
+
+/// The built-in model with the given ID, or that the fine-tuned model with the given ID was trained
+/// from, whose capabilities it shares.
+fn built_in_model(id: &str) -> Option<open_ai::Model> {
+    if let Some(fine_tuned) = open_ai::models::FineTunedModelId::parse(id) {
+        return open_ai::Model::from_id(fine_tuned.base_model)
+            .or_else(|_| open_ai::Model::from_id(fine_tuned.base_model_family()))
+            .ok();
     }
+    open_ai::Model::from_id(id).ok()
 }
 
-pub fn into_open_ai(
+/// The largest image that OpenAI accepts.
+pub(crate) const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// OpenAI scales images down to fit within 2048x2048 pixels, so larger ones only take longer to
+/// upload. Requests can have up to 500 images, taking up 50 MB together.
+pub(crate) const OPEN_AI_IMAGE_LIMITS: ImageLimits = ImageLimits {
+    max_dimension: 2048,
+    max_bytes: MAX_IMAGE_BYTES,
+    max_images: Some(500),
+    max_total_bytes: Some(50 * 1024 * 1024),
+    formats: &[
+        ImageFormat::Png,
+        ImageFormat::Jpeg,
+        ImageFormat::Gif,
+        ImageFormat::Webp,
+    ],
+};
+
+/// Writes the conversation of `request` into a prompt with `template`, for the `/completions`
+/// endpoint.
+fn into_text_completion(
     request: LanguageModelRequest,
     model: &Model,
     max_output_tokens: Option<u32>,
+    template: &PromptTemplate,
+) -> Result<open_ai::CompletionRequest> {
+    let prompt = template.render(&request)?;
+    let mut stop = template.stop_sequences();
+    stop.extend(request.stop);
+    Ok(open_ai::CompletionRequest {
+        model: model.id().into(),
+        prompt,
+        stream: true,
+        stream_options: Some(open_ai::StreamOptions {
+            include_usage: true,
+        }),
+        max_tokens: max_output_tokens,
+        stop,
+        temperature: request.temperature,
+        seed: request.seed,
+        top_p: request.top_p,
+        frequency_penalty: request.frequency_penalty,
+        presence_penalty: request.presence_penalty,
+        prediction: None,
+        rewrite_speculation: None,
+        extra_body: serde_json::Map::new(),
+    })
+}
+
+/// Converts `request` for OpenAI's chat completions API, or for another provider that's
+/// compatible with it. Images larger than `max_image_bytes` are left out.
+pub fn into_open_ai(
+    mut request: LanguageModelRequest,
+    model: &Model,
+    max_output_tokens: Option<u32>,
+    max_image_bytes: usize,
 ) -> open_ai::Request {
     let stream = !model.id().starts_with("o1-");
 
+    let prediction = request.prediction.take().filter(|_| {
+        model.supports_prediction()
+            && request.candidates.unwrap_or(1) <= 1
+            && request.logprobs.is_none()
+            && request.frequency_penalty.is_none()
+            && request.presence_penalty.is_none()
+            && (request.tools.is_empty()
+                || request.tool_choice == Some(LanguageModelToolChoice::None))
+    });
+    if prediction.is_some() {
+        // Predictions can't be combined with tools, which the model can't call anyway.
+        request.tools.clear();
+        request.tool_choice = None;
+    }
+    let system_prompt_role = model.system_prompt_role();
+    if system_prompt_role == SystemPromptRole::User {
+        fold_system_messages(&mut request.messages);
+    }
+
     let mut messages = Vec::new();
     for message in request.messages {
+        let mut tool_result_images = Vec::new();
         for content in message.content {
             match content {
                 MessageContent::Text(text) | MessageContent::Thinking { text, .. } => {
@@ -369,19 +1594,43 @@ pub fn into_open_ai(
                         &mut messages,
                     )
                 }
+                MessageContent::Document(document) => add_message_content_part(
+                    open_ai::MessagePart::Text {
+                        text: document.to_text(),
+                    },
+                    message.role,
+                    &mut messages,
+                ),
                 MessageContent::RedactedThinking(_) => {}
                 MessageContent::Image(image) => {
                     add_message_content_part(
-                        open_ai::MessagePart::Image {
-                            image_url: ImageUrl {
-                                url: image.to_base64_url(),
-                                detail: None,
-                            },
-                        },
+                        image_part(&image, max_image_bytes),
                         message.role,
                         &mut messages,
                     );
                 }
+                MessageContent::Pdf(pdf) => add_message_content_part(
+                    open_ai::MessagePart::File {
+                        file: open_ai::FileData {
+                            file_data: pdf.to_base64_url(),
+                            filename: pdf.name,
+                        },
+                    },
+                    message.role,
+                    &mut messages,
+                ),
+                MessageContent::Audio(audio) => add_message_content_part(
+                    open_ai::MessagePart::InputAudio {
+                        input_audio: open_ai::InputAudio {
+                            data: audio.source.to_string(),
+                            format: audio.format.as_str().to_string(),
+                        },
+                    },
+                    message.role,
+                    &mut messages,
+                ),
+                // OpenAI models don't take video, so `check_videos` replaces it with a note.
+                MessageContent::Video(_) => {}
                 MessageContent::ToolUse(tool_use) => {
                     let tool_call = open_ai::ToolCall {
                         id: tool_use.id.to_string(),
@@ -406,41 +1655,72 @@ pub fn into_open_ai(
                     }
                 }
                 MessageContent::ToolResult(tool_result) => {
-                    let content = match &tool_result.content {
-                        LanguageModelToolResultContent::Text(text) => {
-                            vec![open_ai::MessagePart::Text {
-                                text: text.to_string(),
-                            }]
-                        }
+                    let text = match &tool_result.content {
+                        LanguageModelToolResultContent::Text(text) => text.to_string(),
+                        // Tool messages can only hold text, so images are sent in a user message
+                        // after the message's tool results, which have to directly follow the
+                        // tool calls.
                         LanguageModelToolResultContent::Image(image) => {
-                            vec![open_ai::MessagePart::Image {
-                                image_url: ImageUrl {
-                                    url: image.to_base64_url(),
-                                    detail: None,
-                                },
-                            }]
+                            tool_result_images.push(image_part(image, max_image_bytes));
+                            "[The image this tool returned follows]".to_string()
                         }
                     };
 
                     messages.push(open_ai::RequestMessage::Tool {
-                        content: content.into(),
+                        content: vec![open_ai::MessagePart::Text { text }].into(),
                         tool_call_id: tool_result.tool_use_id.to_string(),
                     });
                 }
             }
         }
+        for image in tool_result_images {
+            add_message_content_part(image, Role::User, &mut messages);
+        }
+    }
+    if system_prompt_role == SystemPromptRole::Developer {
+        for message in &mut messages {
+            if let open_ai::RequestMessage::System { content } = message {
+                let content = std::mem::replace(content, open_ai::MessageContent::empty());
+                *message = open_ai::RequestMessage::Developer { content };
+            }
+        }
     }
 
     open_ai::Request {
         model: model.id().into(),
         messages,
         stream,
+        stream_options: stream.then_some(open_ai::StreamOptions {
+            include_usage: true,
+        }),
         stop: request.stop,
         temperature: request.temperature.unwrap_or(1.0),
+        seed: request.seed,
+        top_p: request.top_p,
+        frequency_penalty: request.frequency_penalty,
+        presence_penalty: request.presence_penalty,
+        logit_bias: request.logit_bias,
+        response_format: request.response_schema.map(|response_schema| {
+            open_ai::ResponseFormat::JsonSchema {
+                json_schema: open_ai::JsonSchemaFormat {
+                    name: response_schema.name,
+                    description: response_schema.description,
+                    schema: response_schema.schema,
+                    strict: true,
+                },
+            }
+        }),
+        guided_json: None,
+        reasoning_effort: None,
+        prediction: prediction.map(|content| open_ai::Prediction::Content { content }),
+        n: request.candidates.filter(|&candidates| candidates > 1),
+        logprobs: request.logprobs.map(|_| true),
+        top_logprobs: request.logprobs.filter(|&top_logprobs| top_logprobs > 0),
         max_tokens: max_output_tokens,
         parallel_tool_calls: if model.supports_parallel_tool_calls() && !request.tools.is_empty() {
-            // Disable parallel tool calls, as the Agent currently expects a maximum of one per turn.
-            Some(false)
+            // Unless the request allows them, disable parallel tool calls, as the Agent currently
+            // expects a maximum of one per turn.
+            Some(request.parallel_tool_calls.unwrap_or(false))
         } else {
             None
         },
@@ -452,6 +1732,7 @@ pub fn into_open_ai(
                     name: tool.name,
                     description: Some(tool.description),
                     parameters: Some(tool.input_schema),
+                    strict: None,
                 },
             })
             .collect(),
@@ -459,7 +1740,73 @@ pub fn into_open_ai(
             LanguageModelToolChoice::Auto => open_ai::ToolChoice::Auto,
             LanguageModelToolChoice::Any => open_ai::ToolChoice::Required,
             LanguageModelToolChoice::None => open_ai::ToolChoice::None,
+            LanguageModelToolChoice::Tool(name) => {
+                open_ai::ToolChoice::Other(open_ai::ToolDefinition::Function {
+                    function: open_ai::FunctionDefinition {
+                        name,
+                        description: None,
+                        parameters: None,
+                        strict: None,
+                    },
+                })
+            }
         }),
+        extra_body: serde_json::Map::new(),
+    }
+}
+
+/// Moves the content of the system messages to the start of the first user message, for models
+/// that don't take instructions in their own messages.
+fn fold_system_messages(messages: &mut Vec<LanguageModelRequestMessage>) {
+    let mut instructions = Vec::new();
+    messages.retain_mut(|message| {
+        if message.role != Role::System {
+            return true;
+        }
+        instructions.append(&mut message.content);
+        false
+    });
+    if instructions.is_empty() {
+        return;
+    }
+    match messages
+        .iter_mut()
+        .find(|message| message.role == Role::User)
+    {
+        Some(message) => {
+            message.content.splice(0..0, instructions);
+        }
+        None => messages.insert(
+            0,
+            LanguageModelRequestMessage {
+                role: Role::User,
+                content: instructions,
+                cache: false,
+            },
+        ),
+    }
+}
+
+/// An image as a data URI, or a note in its place when it's larger than `max_image_bytes`, since
+/// the whole request would be rejected otherwise.
+fn image_part(image: &LanguageModelImage, max_image_bytes: usize) -> open_ai::MessagePart {
+    if image.byte_len() > max_image_bytes {
+        log::warn!(
+            "left out a {} byte image, which is larger than the {max_image_bytes} bytes the provider accepts",
+            image.byte_len()
+        );
+        return open_ai::MessagePart::Text {
+            text: format!(
+                "[An image was left out because it's larger than the {} MB that this model accepts.]",
+                max_image_bytes / (1024 * 1024)
+            ),
+        };
+    }
+    open_ai::MessagePart::Image {
+        image_url: ImageUrl {
+            url: image.to_base64_url(),
+            detail: None,
+        },
     }
 }
 
@@ -498,13 +1845,17 @@ fn add_message_content_part(
 }
 
 pub struct OpenAiEventMapper {
-    tool_calls_by_index: HashMap<usize, RawToolCall>,
+    /// The tool calls being streamed, by candidate and then by index.
+    tool_calls_by_index: HashMap<u32, HashMap<usize, RawToolCall>>,
+    /// The candidate that the last events were emitted for.
+    candidate: u32,
 }
 
 impl OpenAiEventMapper {
     pub fn new() -> Self {
         Self {
             tool_calls_by_index: HashMap::default(),
+            candidate: 0,
         }
     }
 
@@ -525,75 +1876,147 @@ impl OpenAiEventMapper {
         &mut self,
         event: ResponseStreamEvent,
     ) -> Vec<Result<LanguageModelCompletionEvent, LanguageModelCompletionError>> {
-        let Some(choice) = event.choices.first() else {
-            return vec![Err(LanguageModelCompletionError::Other(anyhow!(
-                "Response contained no choices"
-            )))];
-        };
-
         let mut events = Vec::new();
-        if let Some(content) = choice.delta.content.clone() {
-            events.push(Ok(LanguageModelCompletionEvent::Text(content)));
+        if let Some(usage) = event.usage.as_ref() {
+            events.push(Ok(LanguageModelCompletionEvent::UsageUpdate(
+                language_model::TokenUsage {
+                    input_tokens: usage.uncached_prompt_tokens(),
+                    output_tokens: usage.completion_tokens,
+                    cache_creation_input_tokens: 0,
+                    cache_read_input_tokens: usage.cached_prompt_tokens(),
+                    reasoning_tokens: usage.reasoning_tokens(),
+                },
+            )));
+        }
+
+        if event.choices.is_empty() {
+            if events.is_empty() {
+                events.push(Err(LanguageModelCompletionError::Other(anyhow!(
+                    "Response contained no choices"
+                ))));
+            }
+            return events;
         }
 
-        if let Some(tool_calls) = choice.delta.tool_calls.as_ref() {
-            for tool_call in tool_calls {
-                let entry = self.tool_calls_by_index.entry(tool_call.index).or_default();
+        for choice in event.choices {
+            if choice.index != self.candidate {
+                self.candidate = choice.index;
+                events.push(Ok(LanguageModelCompletionEvent::Candidate {
+                    index: choice.index,
+                }));
+            }
 
-                if let Some(tool_id) = tool_call.id.clone() {
+            if let Some(reasoning) = choice
+                .delta
+                .reasoning_content
+                .filter(|text| !text.is_empty())
+            {
+                events.push(Ok(LanguageModelCompletionEvent::Thinking {
+                    text: reasoning,
+                    signature: None,
+                }));
+            }
+            if let Some(content) = choice.delta.content {
+                events.push(Ok(LanguageModelCompletionEvent::Text(content)));
+            }
+
+            if let Some(logprobs) = choice
+                .logprobs
+                .and_then(|logprobs| logprobs.content)
+                .filter(|logprobs| !logprobs.is_empty())
+            {
+                events.push(Ok(LanguageModelCompletionEvent::Logprobs(
+                    logprobs
+                        .into_iter()
+                        .map(|logprob| TokenLogprob {
+                            token: logprob.token,
+                            logprob: logprob.logprob,
+                            top_logprobs: logprob
+                                .top_logprobs
+                                .into_iter()
+                                .map(|top| TopLogprob {
+                                    token: top.token,
+                                    logprob: top.logprob,
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                )));
+            }
+
+            let tool_calls_by_index = self.tool_calls_by_index.entry(choice.index).or_default();
+            for tool_call in choice.delta.tool_calls.into_iter().flatten() {
+                let entry = tool_calls_by_index.entry(tool_call.index).or_default();
+
+                if let Some(tool_id) = tool_call.id {
                     entry.id = tool_id;
                 }
 
-                if let Some(function) = tool_call.function.as_ref() {
-                    if let Some(name) = function.name.clone() {
+                if let Some(function) = tool_call.function {
+                    if let Some(name) = function.name {
                         entry.name = name;
                     }
 
-                    if let Some(arguments) = function.arguments.clone() {
+                    if let Some(arguments) = function.arguments.filter(|args| !args.is_empty()) {
                         entry.arguments.push_str(&arguments);
+                        events.extend(
+                            partial_tool_use(&entry.id, &entry.name, &entry.arguments).map(Ok),
+                        );
                     }
                 }
             }
-        }
 
-        match choice.finish_reason.as_deref() {
-            Some("stop") => {
-                events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::EndTurn)));
-            }
-            Some("tool_calls") => {
-                events.extend(self.tool_calls_by_index.drain().map(|(_, tool_call)| {
-                    match serde_json::Value::from_str(&tool_call.arguments) {
-                        Ok(input) => Ok(LanguageModelCompletionEvent::ToolUse(
-                            LanguageModelToolUse {
-                                id: tool_call.id.clone().into(),
-                                name: tool_call.name.as_str().into(),
-                                is_input_complete: true,
-                                input,
-                                raw_input: tool_call.arguments.clone(),
-                            },
-                        )),
-                        Err(error) => Err(LanguageModelCompletionError::BadInputJson {
-                            id: tool_call.id.into(),
-                            tool_name: tool_call.name.as_str().into(),
-                            raw_input: tool_call.arguments.into(),
-                            json_parse_error: error.to_string(),
-                        }),
-                    }
-                }));
+            match choice.finish_reason.as_deref() {
+                Some("tool_calls") => {
+                    events.extend(tool_calls_by_index.drain().map(|(_, tool_call)| {
+                        match serde_json::Value::from_str(&tool_call.arguments) {
+                            Ok(input) => Ok(LanguageModelCompletionEvent::ToolUse(
+                                LanguageModelToolUse {
+                                    id: tool_call.id.clone().into(),
+                                    name: tool_call.name.as_str().into(),
+                                    is_input_complete: true,
+                                    input,
+                                    raw_input: tool_call.arguments.clone(),
+                                },
+                            )),
+                            Err(error) => Err(LanguageModelCompletionError::BadInputJson {
+                                id: tool_call.id.into(),
+                                tool_name: tool_call.name.as_str().into(),
+                                raw_input: tool_call.arguments.into(),
+                                json_parse_error: error.to_string(),
+                            }),
+                        }
+                    }));
 
-                events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::ToolUse)));
-            }
-            Some(stop_reason) => {
-                log::error!("Unexpected OpenAI stop_reason: {stop_reason:?}",);
-                events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::EndTurn)));
+                    events.push(Ok(LanguageModelCompletionEvent::Stop(StopReason::ToolUse)));
+                }
+                Some(finish_reason) => {
+                    let stop_reason =
+                        stop_reason_for_finish_reason(finish_reason).unwrap_or_else(|| {
+                            log::error!("Unexpected OpenAI stop_reason: {finish_reason:?}");
+                            StopReason::EndTurn
+                        });
+                    events.push(Ok(LanguageModelCompletionEvent::Stop(stop_reason)));
+                }
+                None => {}
             }
-            None => {}
         }
 
         events
     }
 }
 
+/// The stop reason for a `finish_reason` of an OpenAI-compatible API.
+pub(crate) fn stop_reason_for_finish_reason(finish_reason: &str) -> Option<StopReason> {
+    match finish_reason {
+        "stop" => Some(StopReason::EndTurn),
+        "length" | "model_length" => Some(StopReason::MaxTokens),
+        "tool_calls" => Some(StopReason::ToolUse),
+        "content_filter" => Some(StopReason::ContentFilter),
+        _ => None,
+    }
+}
+
 #[derive(Default)]
 struct RawToolCall {
     id: String,
@@ -607,32 +2030,78 @@ pub fn count_open_ai_tokens(
     cx: &App,
 ) -> BoxFuture<'static, Result<usize>> {
     cx.background_spawn(async move {
-        let messages = request
-            .messages
-            .into_iter()
-            .map(|message| tiktoken_rs::ChatCompletionRequestMessage {
+        let mut tokens_from_attachments = 0;
+        let mut messages = Vec::new();
+        for message in request.messages {
+            let mut contents = String::new();
+            for content in message.content {
+                match content {
+                    MessageContent::Text(text) | MessageContent::Thinking { text, .. } => {
+                        contents.push_str(&text)
+                    }
+                    MessageContent::Document(document) => contents.push_str(&document.to_text()),
+                    MessageContent::RedactedThinking(_)
+                    | MessageContent::Audio(_)
+                    | MessageContent::Video(_) => {}
+                    MessageContent::Image(image) => {
+                        tokens_from_attachments += open_ai_image_tokens(&image)
+                    }
+                    MessageContent::Pdf(pdf) => tokens_from_attachments += pdf.estimate_tokens(),
+                    MessageContent::ToolUse(tool_use) => {
+                        contents.push_str(&tool_use.name);
+                        contents.push_str(&serde_json::to_string(&tool_use.input)?);
+                    }
+                    MessageContent::ToolResult(tool_result) => match tool_result.content {
+                        LanguageModelToolResultContent::Text(text) => contents.push_str(&text),
+                        LanguageModelToolResultContent::Image(image) => {
+                            tokens_from_attachments += open_ai_image_tokens(&image)
+                        }
+                    },
+                }
+            }
+            messages.push(tiktoken_rs::ChatCompletionRequestMessage {
                 role: match message.role {
                     Role::User => "user".into(),
                     Role::Assistant => "assistant".into(),
                     Role::System => "system".into(),
                 },
-                content: Some(message.string_contents()),
+                content: Some(contents),
                 name: None,
                 function_call: None,
-            })
-            .collect::<Vec<_>>();
+            });
+        }
 
-        match model {
-            Model::Custom { max_tokens, .. } => {
-                let model = if max_tokens >= 100_000 {
+        // Tool definitions are added to the model's system prompt, so they're counted as a system
+        // message with each tool's name, description, and parameters.
+        if !request.tools.is_empty() {
+            let mut definitions = String::new();
+            for tool in &request.tools {
+                definitions.push_str(&tool.name);
+                definitions.push_str(&tool.description);
+                definitions.push_str(&serde_json::to_string(&tool.input_schema)?);
+            }
+            messages.push(tiktoken_rs::ChatCompletionRequestMessage {
+                role: "system".into(),
+                content: Some(definitions),
+                name: None,
+                function_call: None,
+            });
+        }
+
+        let tiktoken_model = match &model {
+            Model::Custom {
+                name, max_tokens, ..
+            } => {
+                if let Some(family) = ModelFamily::infer(name) {
+                    family.tokenizer.tiktoken_model()
+                } else if *max_tokens >= 100_000 {
                     // If the max tokens is 100k or more, it is likely the o200k_base tokenizer from gpt4o
                     "gpt-4o"
                 } else {
                     // Otherwise fallback to gpt-4, since only cl100k_base and o200k_base are
                     // supported with this tiktoken method
                     "gpt-4"
-                };
-                tiktoken_rs::num_tokens_from_messages(model, &messages)
+                }
             }
             // Currently supported by tiktoken_rs
             // Sometimes tiktoken-rs is behind on model support. If that is the case, make a new branch
@@ -651,16 +2120,38 @@ pub fn count_open_ai_tokens(
             | Model::O1Mini
             | Model::O3
             | Model::O3Mini
-            | Model::O4Mini => tiktoken_rs::num_tokens_from_messages(model.id(), &messages),
-        }
+            | Model::O4Mini => model.id(),
+        };
+        tiktoken_rs::num_tokens_from_messages(tiktoken_model, &messages)
+            .map(|tokens| tokens + tokens_from_attachments)
     })
     .boxed()
 }
 
+/// Estimates the tokens that OpenAI counts for an image, which are 85 plus 170 for each 512x512
+/// tile of the image, once it's scaled down to fit within 2048x2048 and then to a shortest side
+/// of 768 pixels.
+fn open_ai_image_tokens(image: &LanguageModelImage) -> usize {
+    let width = image.size.width.0.unsigned_abs() as f64;
+    let height = image.size.height.0.unsigned_abs() as f64;
+    if width == 0. || height == 0. {
+        return 85;
+    }
+    let scale = (2048. / width.max(height)).min(1.);
+    let (width, height) = (width * scale, height * scale);
+    let scale = (768. / width.min(height)).min(1.);
+    let (width, height) = (width * scale, height * scale);
+    let tiles = (width / 512.).ceil() as usize * (height / 512.).ceil() as usize;
+    85 + 170 * tiles
+}
+
 struct ConfigurationView {
     api_key_editor: Entity<Editor>,
     state: gpui::Entity<State>,
     load_credentials_task: Option<Task<()>>,
+    validation: ApiKeyValidation,
+    save_scope: ApiKeySaveScope,
+    key_display: ApiKeyDisplay,
 }
 
 impl ConfigurationView {
@@ -699,6 +2190,9 @@ impl ConfigurationView {
             api_key_editor,
             state,
             load_credentials_task,
+            validation: ApiKeyValidation::default(),
+            save_scope: ApiKeySaveScope::new(window, cx),
+            key_display: ApiKeyDisplay::default(),
         }
     }
 
@@ -708,11 +2202,22 @@ impl ConfigurationView {
             return;
         }
 
+        self.validation = ApiKeyValidation::Validating;
+        let workspace_scope = self.save_scope.workspace_scope();
         let state = self.state.clone();
-        cx.spawn_in(window, async move |_, cx| {
-            state
-                .update(cx, |state, cx| state.set_api_key(api_key, cx))?
-                .await
+        cx.spawn_in(window, async move |this, cx| {
+            let result = state
+                .update(cx, |state, cx| {
+                    state.set_api_key(api_key, workspace_scope, cx)
+                })?
+                .await;
+            this.update(cx, |this, cx| {
+                this.validation = ApiKeyValidation::from_result(&result);
+                if result.is_ok() {
+                    this.key_display.finish_replacing();
+                }
+                cx.notify();
+            })
         })
         .detach_and_log_err(cx);
 
@@ -723,9 +2228,12 @@ impl ConfigurationView {
         self.api_key_editor
             .update(cx, |editor, cx| editor.set_text("", window, cx));
 
+        let workspace = self.api_keys(cx).1.workspace().cloned();
         let state = self.state.clone();
         cx.spawn_in(window, async move |_, cx| {
-            state.update(cx, |state, cx| state.reset_api_key(cx))?.await
+            state
+                .update(cx, |state, cx| state.reset_api_key(workspace, cx))?
+                .await
         })
         .detach_and_log_err(cx);
 
@@ -757,17 +2265,67 @@ impl ConfigurationView {
         )
     }
 
+    /// The keys that the view shows, which are the workspace's own if it has any.
+    fn api_keys(&self, cx: &App) -> (ApiKeys, ApiKeySource) {
+        let state = self.state.read(cx);
+        state.workspace_api_keys.for_display(
+            &state.api_keys,
+            &state.api_key_source,
+            self.save_scope.workspace(),
+        )
+    }
+
     fn should_render_editor(&self, cx: &mut Context<Self>) -> bool {
-        !self.state.read(cx).is_authenticated()
+        !self.state.read(cx).is_authenticated() || self.key_display.is_replacing()
     }
 }
 
 impl Render for ConfigurationView {
     fn render(&mut self, _: &mut Window, cx: &mut Context<Self>) -> impl IntoElement {
-        let env_var_set = self.state.read(cx).api_key_from_env;
+        let (api_keys, api_key_source) = self.api_keys(cx);
+        let rejected = self.state.read(cx).api_key_status.is_rejected();
+        let state = self.state.read(cx);
+        let env_var = AllLanguageModelSettings::get_global(cx)
+            .openai
+            .api
+            .env_var(OPENAI_API_KEY_VAR)
+            .to_string();
+        let key_is_external = api_key_source.is_external();
+        let entra_id = state.entra_id.clone();
+        let entra_id_error = state.entra_id_error.clone();
+        let entra_id_authenticated = state.entra_id_credentials.is_some();
 
         if self.load_credentials_task.is_some() {
             div().child(Label::new("Loading credentials...")).into_any()
+        } else if let Some(entra_id) = entra_id {
+            let description = match entra_id {
+                EntraIdSettings::ClientCredentials { client_id, .. } => {
+                    format!("Using Microsoft Entra ID with service principal {client_id}.")
+                }
+                EntraIdSettings::AzureCli { .. } => {
+                    "Using Microsoft Entra ID with the Azure CLI account.".to_string()
+                }
+            };
+            h_flex()
+                .mt_1()
+                .p_1()
+                .gap_1()
+                .rounded_md()
+                .border_1()
+                .border_color(cx.theme().colors().border)
+                .bg(cx.theme().colors().background)
+                .map(|this| {
+                    if entra_id_authenticated {
+                        this.child(Icon::new(IconName::Check).color(Color::Success))
+                            .child(Label::new(description))
+                    } else {
+                        this.child(Icon::new(IconName::Warning).color(Color::Warning))
+                            .child(Label::new(entra_id_error.unwrap_or_else(|| {
+                                "Waiting for a Microsoft Entra ID token...".into()
+                            })))
+                    }
+                })
+                .into_any()
         } else if self.should_render_editor(cx) {
             v_flex()
                 .size_full()
@@ -799,9 +2357,12 @@ impl Render for ConfigurationView {
                         .rounded_sm()
                         .child(self.render_api_key_editor(cx)),
                 )
+                .children(self.save_scope.render(cx, |this| &mut this.save_scope))
+                .children(self.key_display.render_cancel(cx, |this| &mut this.key_display))
+                .children(self.validation.render())
                 .child(
                     Label::new(
-                        format!("You can also assign the {OPENAI_API_KEY_VAR} environment variable and restart Zed."),
+                        format!("You can also assign the {env_var} environment variable and restart Zed."),
                     )
                     .size(LabelSize::Small).color(Color::Muted),
                 )
@@ -825,11 +2386,14 @@ impl Render for ConfigurationView {
                     h_flex()
                         .gap_1()
                         .child(Icon::new(IconName::Check).color(Color::Success))
-                        .child(Label::new(if env_var_set {
-                            format!("API key set in {OPENAI_API_KEY_VAR} environment variable.")
-                        } else {
-                            "API key configured.".to_string()
-                        })),
+                        .child(self.key_display.render(
+                            PROVIDER_ID,
+                            &api_keys,
+                            rejected,
+                            &api_key_source,
+                            cx,
+                            |this| &mut this.key_display,
+                        )),
                 )
                 .child(
                     Button::new("reset-key", "Reset Key")
@@ -837,11 +2401,13 @@ impl Render for ConfigurationView {
                         .icon(Some(IconName::Trash))
                         .icon_size(IconSize::Small)
                         .icon_position(IconPosition::Start)
-                        .disabled(env_var_set)
-                        .when(env_var_set, |this| {
-                            this.tooltip(Tooltip::text(format!("To reset your API key, unset the {OPENAI_API_KEY_VAR} environment variable.")))
+                        .disabled(key_is_external)
+                        .when(key_is_external, |this| {
+                            this.tooltip(Tooltip::text(api_key_source.reset_hint()))
                         })
-                        .on_click(cx.listener(|this, _, window, cx| this.reset_api_key(window, cx))),
+                        .on_click(
+                            cx.listener(|this, _, window, cx| this.reset_api_key(window, cx)),
+                        ),
                 )
                 .into_any()
         }
@@ -851,7 +2417,7 @@ impl Render for ConfigurationView {
 #[cfg(test)]
 mod tests {
     use gpui::TestAppContext;
-    use language_model::LanguageModelRequestMessage;
+    use language_model::{LanguageModelRequestMessage, LanguageModelRequestTool};
 
     use super::*;
 
@@ -871,6 +2437,19 @@ mod tests {
             tool_choice: None,
             stop: vec![],
             temperature: None,
+            seed: None,
+            frequency_penalty: None,
+            presence_penalty: None,
+            logit_bias: Default::default(),
+            top_p: None,
+            top_k: None,
+            candidates: None,
+            logprobs: None,
+            response_schema: None,
+            parallel_tool_calls: None,
+            prediction: None,
+            batch: false,
+            workspace: None,
         };
 
         // Validate that all models are supported by tiktoken-rs
@@ -886,4 +2465,352 @@ mod tests {
             assert!(count > 0);
         }
     }
+
+    #[gpui::test]
+    fn test_count_tokens_for_tools_and_images(cx: &TestAppContext) {
+        let image = |width, height| LanguageModelImage {
+            source: "".into(),
+            size: gpui::size(gpui::DevicePixels(width), gpui::DevicePixels(height)),
+        };
+        assert_eq!(open_ai_image_tokens(&image(1024, 1024)), 765);
+        assert_eq!(open_ai_image_tokens(&image(2048, 4096)), 1105);
+        assert_eq!(open_ai_image_tokens(&image(256, 256)), 255);
+
+        let count = |content: Vec<MessageContent>, tools| {
+            let request = LanguageModelRequest {
+                messages: vec![LanguageModelRequestMessage {
+                    role: Role::User,
+                    content,
+                    cache: false,
+                }],
+                tools,
+                ..Default::default()
+            };
+            cx.executor()
+                .block(count_open_ai_tokens(
+                    request,
+                    Model::FourOmni,
+                    &cx.app.borrow(),
+                ))
+                .unwrap()
+        };
+        let text = count(vec!["What's this?".into()], Vec::new());
+        assert_eq!(
+            count(
+                vec![
+                    "What's this?".into(),
+                    MessageContent::Image(image(1024, 1024))
+                ],
+                Vec::new()
+            ),
+            text + 765
+        );
+        let tool = LanguageModelRequestTool {
+            name: "read_file".into(),
+            description: "Reads a file in the project.".into(),
+            input_schema: serde_json::json!({
+                "type": "object",
+                "properties": {"path": {"type": "string"}},
+            }),
+        };
+        assert!(count(vec!["What's this?".into()], vec![tool]) > text + 10);
+    }
+
+    #[test]
+    fn test_usage_chunk() {
+        let event: ResponseStreamEvent = serde_json::from_str(
+            r#"{"id":"chatcmpl-1","object":"chat.completion.chunk","created":1,"model":"grok-3","choices":[],"usage":{"prompt_tokens":1200,"completion_tokens":30,"total_tokens":1230,"prompt_tokens_details":{"cached_tokens":1000}}}"#,
+        )
+        .unwrap();
+        let events = OpenAiEventMapper::new().map_event(event);
+        assert!(matches!(
+            events.as_slice(),
+            [Ok(LanguageModelCompletionEvent::UsageUpdate(
+                language_model::TokenUsage {
+                    input_tokens: 200,
+                    output_tokens: 30,
+                    cache_creation_input_tokens: 0,
+                    cache_read_input_tokens: 1000,
+                    reasoning_tokens: 0,
+                }
+            ))]
+        ));
+
+        let event: ResponseStreamEvent = serde_json::from_str(
+            r#"{"usage":{"prompt_tokens":12,"completion_tokens":3,"total_tokens":15}}"#,
+        )
+        .unwrap();
+        assert_eq!(OpenAiEventMapper::new().map_event(event).len(), 1);
+    }
+
+    #[test]
+    fn test_candidates() {
+        let mut mapper = OpenAiEventMapper::new();
+        let mut events = Vec::new();
+        for chunk in [
+            r#"{"choices":[{"index":0,"delta":{"role":"assistant","content":"Hello"}}]}"#,
+            r#"{"choices":[{"index":1,"delta":{"role":"assistant","content":"Hi"}}]}"#,
+            r#"{"choices":[{"index":0,"delta":{},"finish_reason":"stop"}]}"#,
+            r#"{"choices":[{"index":1,"delta":{},"finish_reason":"stop"}]}"#,
+        ] {
+            let event: ResponseStreamEvent = serde_json::from_str(chunk).unwrap();
+            events.extend(mapper.map_event(event).into_iter().map(Result::unwrap));
+        }
+        assert!(matches!(
+            events.as_slice(),
+            [
+                LanguageModelCompletionEvent::Text(first),
+                LanguageModelCompletionEvent::Candidate { index: 1 },
+                LanguageModelCompletionEvent::Text(second),
+                LanguageModelCompletionEvent::Candidate { index: 0 },
+                LanguageModelCompletionEvent::Stop(StopReason::EndTurn),
+                LanguageModelCompletionEvent::Candidate { index: 1 },
+                LanguageModelCompletionEvent::Stop(StopReason::EndTurn),
+            ] if first == "Hello" && second == "Hi"
+        ));
+    }
+
+    #[test]
+    fn test_logprobs() {
+        let event: ResponseStreamEvent = serde_json::from_str(
+            r#"{"choices":[{"index":0,"delta":{"content":"Hi"},"logprobs":{"content":[{"token":"Hi","logprob":-0.25,"bytes":[72,105],"top_logprobs":[{"token":"Hi","logprob":-0.25},{"token":"Hello","logprob":-1.5}]}]}}]}"#,
+        )
+        .unwrap();
+        let events = OpenAiEventMapper::new().map_event(event);
+        assert_eq!(
+            events.into_iter().map(Result::unwrap).collect::<Vec<_>>(),
+            vec![
+                LanguageModelCompletionEvent::Text("Hi".into()),
+                LanguageModelCompletionEvent::Logprobs(vec![TokenLogprob {
+                    token: "Hi".into(),
+                    logprob: -0.25,
+                    top_logprobs: vec![
+                        TopLogprob {
+                            token: "Hi".into(),
+                            logprob: -0.25,
+                        },
+                        TopLogprob {
+                            token: "Hello".into(),
+                            logprob: -1.5,
+                        },
+                    ],
+                }]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tool_choice() {
+        let tool_choice = |choice| {
+            let request = into_open_ai(
+                LanguageModelRequest {
+                    tool_choice: Some(choice),
+                    ..Default::default()
+                },
+                &Model::FourOmni,
+                None,
+                MAX_IMAGE_BYTES,
+            );
+            serde_json::to_value(request.tool_choice).unwrap()
+        };
+        assert_eq!(
+            tool_choice(LanguageModelToolChoice::Any),
+            serde_json::json!("required")
+        );
+        assert_eq!(
+            tool_choice(LanguageModelToolChoice::Tool("edit_file".into())),
+            serde_json::json!({"type": "function", "function": {"name": "edit_file"}})
+        );
+    }
+
+    #[test]
+    fn test_tool_result_images() {
+        let tool_result = |id: &str| {
+            MessageContent::ToolResult(language_model::LanguageModelToolResult {
+                tool_use_id: id.into(),
+                tool_name: "screenshot".into(),
+                is_error: false,
+                content: LanguageModelToolResultContent::Image(LanguageModelImage {
+                    source: "iVBORw0KGgo=".into(),
+                    size: gpui::size(gpui::DevicePixels(1), gpui::DevicePixels(1)),
+                }),
+                output: None,
+            })
+        };
+        let tool_use = |id: &str| {
+            MessageContent::ToolUse(language_model::LanguageModelToolUse {
+                id: id.into(),
+                name: "screenshot".into(),
+                raw_input: "{}".into(),
+                input: serde_json::json!({}),
+                is_input_complete: true,
+            })
+        };
+        let request = into_open_ai(
+            LanguageModelRequest {
+                messages: vec![
+                    LanguageModelRequestMessage {
+                        role: Role::Assistant,
+                        content: vec![tool_use("call_1"), tool_use("call_2")],
+                        cache: false,
+                    },
+                    LanguageModelRequestMessage {
+                        role: Role::User,
+                        content: vec![tool_result("call_1"), tool_result("call_2")],
+                        cache: false,
+                    },
+                ],
+                ..Default::default()
+            },
+            &Model::FourOmni,
+            None,
+            MAX_IMAGE_BYTES,
+        );
+        let messages = serde_json::to_value(request.messages).unwrap();
+        let roles = messages
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|message| message["role"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(roles, ["assistant", "tool", "tool", "user"]);
+        assert_eq!(
+            messages[1]["content"],
+            "[The image this tool returned follows]"
+        );
+        assert_eq!(messages[3]["content"][0]["type"], "image_url");
+        assert_eq!(
+            messages[3]["content"][1]["image_url"]["url"],
+            "data:image/png;base64,iVBORw0KGgo="
+        );
+    }
+
+    #[test]
+    fn test_prediction() {
+        let prediction = |tool_choice, model| {
+            let request = into_open_ai(
+                LanguageModelRequest {
+                    tools: vec![language_model::LanguageModelRequestTool {
+                        name: "edit_file".into(),
+                        description: "Edits a file".into(),
+                        input_schema: serde_json::json!({"type": "object"}),
+                    }],
+                    tool_choice,
+                    prediction: Some("fn main() {}".into()),
+                    ..Default::default()
+                },
+                &model,
+                None,
+                MAX_IMAGE_BYTES,
+            );
+            request.prediction.map(|_| request.tools.is_empty())
+        };
+        assert_eq!(
+            prediction(Some(LanguageModelToolChoice::None), Model::FourPointOne),
+            Some(true)
+        );
+        assert_eq!(
+            prediction(Some(LanguageModelToolChoice::Auto), Model::FourPointOne),
+            None
+        );
+        assert_eq!(
+            prediction(Some(LanguageModelToolChoice::None), Model::O3),
+            None
+        );
+    }
+    #[test]
+    fn test_system_prompt_role() {
+        let messages = |model| {
+            let request = into_open_ai(
+                LanguageModelRequest {
+                    messages: vec![
+                        LanguageModelRequestMessage {
+                            role: Role::System,
+                            content: vec!["Be brief.".into()],
+                            cache: false,
+                        },
+                        LanguageModelRequestMessage {
+                            role: Role::User,
+                            content: vec!["Hi".into()],
+                            cache: false,
+                        },
+                    ],
+                    ..Default::default()
+                },
+                &model,
+                None,
+                MAX_IMAGE_BYTES,
+            );
+            serde_json::to_value(request.messages).unwrap()
+        };
+        assert_eq!(
+            messages(Model::FourPointOne),
+            serde_json::json!([
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": "Hi"},
+            ])
+        );
+        assert_eq!(
+            messages(Model::O3),
+            serde_json::json!([
+                {"role": "developer", "content": "Be brief."},
+                {"role": "user", "content": "Hi"},
+            ])
+        );
+        assert_eq!(
+            messages(Model::O1Mini),
+            serde_json::json!([
+                {"role": "user", "content": [
+                    {"type": "text", "text": "Be brief."},
+                    {"type": "text", "text": "Hi"},
+                ]},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_fine_tuned_models() {
+        assert_eq!(
+            built_in_model("ft:gpt-4o-mini-2024-07-18:acme:support-bot:9xK2pLq1"),
+            Some(Model::FourOmniMini)
+        );
+        assert_eq!(
+            built_in_model("ft:gpt-4.1-2025-04-14:acme::9xK2pLq1"),
+            Some(Model::FourPointOne)
+        );
+        assert_eq!(built_in_model("ft:davinci-002:acme::9xK2pLq1"), None);
+        assert_eq!(built_in_model("gpt-4o"), Some(Model::FourOmni));
+    }
+
+    #[test]
+    fn test_images() {
+        let image = LanguageModelImage {
+            source: "/9j/4AAQSkZJRgABAQAAAQABAAD".into(),
+            size: gpui::size(gpui::DevicePixels(1), gpui::DevicePixels(1)),
+        };
+        let parts = |max_image_bytes| {
+            let request = into_open_ai(
+                LanguageModelRequest {
+                    messages: vec![LanguageModelRequestMessage {
+                        role: Role::User,
+                        content: vec!["What's this?".into(), MessageContent::Image(image.clone())],
+                        cache: false,
+                    }],
+                    ..Default::default()
+                },
+                &Model::FourPointOne,
+                None,
+                max_image_bytes,
+            );
+            serde_json::to_value(&request.messages[0]).unwrap()["content"][1].clone()
+        };
+        assert_eq!(
+            parts(MAX_IMAGE_BYTES),
+            serde_json::json!({
+                "type": "image_url",
+                "image_url": {"url": "data:image/jpeg;base64,/9j/4AAQSkZJRgABAQAAAQABAAD"},
+            })
+        );
+        assert_eq!(parts(8)["type"], "text");
+    }
 }
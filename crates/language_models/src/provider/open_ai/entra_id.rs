@@ -0,0 +1,112 @@
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+use futures::lock::Mutex;
+use http_client::HttpClient;
+use serde::Deserialize;
+use util::command::new_smol_command;
+
+use super::EntraIdSettings;
+use crate::oauth::{self, OAuthConfig, OAuthToken};
+
+const CLIENT_SECRET_VAR: &str = "AZURE_CLIENT_SECRET";
+const COGNITIVE_SERVICES_RESOURCE: &str = "https://cognitiveservices.azure.com";
+/// Older versions of the Azure CLI only report the expiry in local time, in which case the token
+/// is fetched again after this long.
+const FALLBACK_TOKEN_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AzureCliToken {
+    access_token: String,
+    #[serde(rename = "expires_on")]
+    expires_on: Option<u64>,
+}
+
+/// Hands out Microsoft Entra ID access tokens for Azure OpenAI, refreshing them as they expire.
+pub struct EntraIdCredentials {
+    settings: EntraIdSettings,
+    token: Mutex<Option<OAuthToken>>,
+}
+
+impl EntraIdCredentials {
+    pub fn new(settings: EntraIdSettings) -> Self {
+        Self {
+            settings,
+            token: Mutex::new(None),
+        }
+    }
+
+    pub async fn access_token(&self, http_client: &dyn HttpClient) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref().filter(|token| !token.is_expired()) {
+            return Ok(token.access_token.clone());
+        }
+
+        let new_token = match &self.settings {
+            EntraIdSettings::ClientCredentials {
+                tenant_id,
+                client_id,
+            } => client_credentials_token(http_client, tenant_id, client_id).await?,
+            EntraIdSettings::AzureCli { tenant_id } => {
+                azure_cli_token(tenant_id.as_deref()).await?
+            }
+        };
+        let access_token = new_token.access_token.clone();
+        *token = Some(new_token);
+        Ok(access_token)
+    }
+}
+
+async fn client_credentials_token(
+    http_client: &dyn HttpClient,
+    tenant_id: &str,
+    client_id: &str,
+) -> Result<OAuthToken> {
+    let client_secret = std::env::var(CLIENT_SECRET_VAR)
+        .with_context(|| format!("{CLIENT_SECRET_VAR} is not set"))?;
+    let config = OAuthConfig {
+        client_id: client_id.to_string(),
+        client_secret: Some(client_secret),
+        token_url: format!("https://login.microsoftonline.com/{tenant_id}/oauth2/v2.0/token"),
+        scopes: vec![format!("{COGNITIVE_SERVICES_RESOURCE}/.default")],
+    };
+    oauth::client_credentials_token(http_client, &config).await
+}
+
+async fn azure_cli_token(tenant_id: Option<&str>) -> Result<OAuthToken> {
+    let mut command = new_smol_command("az");
+    command.args([
+        "account",
+        "get-access-token",
+        "--resource",
+        COGNITIVE_SERVICES_RESOURCE,
+        "--output",
+        "json",
+    ]);
+    if let Some(tenant_id) = tenant_id {
+        command.args(["--tenant", tenant_id]);
+    }
+
+    let output = command
+        .output()
+        .await
+        .context("failed to run the Azure CLI")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "`az account get-access-token` failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+
+    let token: AzureCliToken = serde_json::from_slice(&output.stdout)
+        .context("unexpected output from `az account get-access-token`")?;
+    Ok(OAuthToken {
+        access_token: token.access_token,
+        refresh_token: None,
+        expires_at: Some(
+            token
+                .expires_on
+                .unwrap_or_else(|| oauth::unix_now() + FALLBACK_TOKEN_LIFETIME.as_secs()),
+        ),
+    })
+}
@@ -0,0 +1,367 @@
+use std::str::FromStr as _;
+
+use anyhow::anyhow;
+use collections::HashMap;
+use futures::{Stream, StreamExt as _};
+use language_model::{
+    LanguageModelCompletionError, LanguageModelCompletionEvent, LanguageModelRequest,
+    LanguageModelToolChoice, LanguageModelToolResultContent, LanguageModelToolUse, MessageContent,
+    Role, StopReason, TokenUsage,
+};
+use open_ai::Model;
+use open_ai::realtime::{
+    ConversationItem, ItemContent, Modality, OutputItem, Response, ServerEvent, SessionConfig, Tool,
+};
+
+use crate::partial_tool_use::partial_tool_use;
+
+/// The lowest and highest temperatures that the Realtime API accepts.
+const TEMPERATURE_RANGE: (f32, f32) = (0.6, 1.2);
+
+/// A completion request to send over a Realtime session: the session's configuration, and the
+/// conversation to respond to.
+#[derive(Clone)]
+pub(super) struct RealtimeRequest {
+    pub model: String,
+    pub session: SessionConfig,
+    pub items: Vec<ConversationItem>,
+}
+
+pub(super) fn into_realtime(
+    request: LanguageModelRequest,
+    model: &Model,
+    max_output_tokens: Option<u32>,
+) -> RealtimeRequest {
+    let mut instructions = Vec::new();
+    let mut items = Vec::new();
+    for message in request.messages {
+        for content in message.content {
+            match content {
+                MessageContent::Text(text) => {
+                    push_message_content(text, message.role, &mut instructions, &mut items)
+                }
+                MessageContent::Document(document) => push_message_content(
+                    document.to_text(),
+                    message.role,
+                    &mut instructions,
+                    &mut items,
+                ),
+                // Realtime sessions only take text and streamed audio, so models that use them
+                // don't support attachments. Thinking isn't sent back either.
+                MessageContent::Thinking { .. }
+                | MessageContent::RedactedThinking(_)
+                | MessageContent::Image(_)
+                | MessageContent::Pdf(_)
+                | MessageContent::Audio(_)
+                | MessageContent::Video(_) => {}
+                MessageContent::ToolUse(tool_use) => items.push(ConversationItem::FunctionCall {
+                    call_id: tool_use.id.to_string(),
+                    name: tool_use.name.to_string(),
+                    arguments: serde_json::to_string(&tool_use.input).unwrap_or_default(),
+                }),
+                MessageContent::ToolResult(tool_result) => {
+                    let output = match tool_result.content {
+                        LanguageModelToolResultContent::Text(text) => text.to_string(),
+                        LanguageModelToolResultContent::Image(_) => {
+                            "[Image omitted because this model can't see images]".to_string()
+                        }
+                    };
+                    items.push(ConversationItem::FunctionCallOutput {
+                        call_id: tool_result.tool_use_id.to_string(),
+                        output,
+                    });
+                }
+            }
+        }
+    }
+
+    RealtimeRequest {
+        model: model.id().into(),
+        session: SessionConfig {
+            modalities: vec![Modality::Text],
+            instructions: Some(instructions.join("\n\n")).filter(|text| !text.is_empty()),
+            tools: request
+                .tools
+                .into_iter()
+                .map(|tool| Tool::Function {
+                    name: tool.name,
+                    description: tool.description,
+                    parameters: tool.input_schema,
+                })
+                .collect(),
+            tool_choice: request.tool_choice.map(|choice| match choice {
+                LanguageModelToolChoice::Auto => "auto".into(),
+                LanguageModelToolChoice::Any => "required".into(),
+                LanguageModelToolChoice::None => "none".into(),
+                LanguageModelToolChoice::Tool(name) => name,
+            }),
+            temperature: request
+                .temperature
+                .map(|temperature| temperature.clamp(TEMPERATURE_RANGE.0, TEMPERATURE_RANGE.1)),
+            max_response_output_tokens: max_output_tokens,
+            ..SessionConfig::default()
+        },
+        items,
+    }
+}
+
+/// Adds text to the conversation, merging it into the previous message if it has the same role.
+/// System messages become the session's instructions instead.
+fn push_message_content(
+    text: String,
+    role: Role,
+    instructions: &mut Vec<String>,
+    items: &mut Vec<ConversationItem>,
+) {
+    let (role, content) = match role {
+        Role::System => {
+            instructions.push(text);
+            return;
+        }
+        Role::User => (open_ai::Role::User, ItemContent::InputText { text }),
+        Role::Assistant => (open_ai::Role::Assistant, ItemContent::Text { text }),
+    };
+    if let Some(ConversationItem::Message {
+        role: last_role,
+        content: last_content,
+    }) = items.last_mut()
+    {
+        if *last_role == role {
+            last_content.push(content);
+            return;
+        }
+    }
+    items.push(ConversationItem::Message {
+        role,
+        content: vec![content],
+    });
+}
+
+pub(super) struct RealtimeEventMapper {
+    /// The function calls being streamed, by item id.
+    function_calls: HashMap<String, RawFunctionCall>,
+    called_function: bool,
+}
+
+impl RealtimeEventMapper {
+    pub(super) fn new() -> Self {
+        Self {
+            function_calls: HashMap::default(),
+            called_function: false,
+        }
+    }
+
+    pub(super) fn map_stream(
+        mut self,
+        events: futures::stream::BoxStream<'static, anyhow::Result<ServerEvent>>,
+    ) -> impl Stream<Item = Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>
+    {
+        events.flat_map(move |event| {
+            futures::stream::iter(match event {
+                Ok(event) => self.map_event(event),
+                Err(error) => vec![Err(LanguageModelCompletionError::Other(anyhow!(error)))],
+            })
+        })
+    }
+
+    fn map_event(
+        &mut self,
+        event: ServerEvent,
+    ) -> Vec<Result<LanguageModelCompletionEvent, LanguageModelCompletionError>> {
+        match event {
+            ServerEvent::TextDelta { delta, .. }
+            | ServerEvent::AudioTranscriptDelta { delta, .. } => {
+                vec![Ok(LanguageModelCompletionEvent::Text(delta))]
+            }
+            ServerEvent::OutputItemAdded {
+                item:
+                    OutputItem::FunctionCall {
+                        id, call_id, name, ..
+                    },
+            } => {
+                self.function_calls.insert(
+                    id,
+                    RawFunctionCall {
+                        call_id,
+                        name,
+                        arguments: String::new(),
+                    },
+                );
+                Vec::new()
+            }
+            ServerEvent::FunctionCallArgumentsDelta { item_id, delta, .. } => {
+                let Some(function_call) = self.function_calls.get_mut(&item_id) else {
+                    return Vec::new();
+                };
+                function_call.arguments.push_str(&delta);
+                partial_tool_use(
+                    &function_call.call_id,
+                    &function_call.name,
+                    &function_call.arguments,
+                )
+                .map(Ok)
+                .into_iter()
+                .collect()
+            }
+            ServerEvent::OutputItemDone {
+                item:
+                    OutputItem::FunctionCall {
+                        id,
+                        call_id,
+                        name,
+                        arguments,
+                    },
+            } => {
+                self.function_calls.remove(&id);
+                self.called_function = true;
+                match serde_json::Value::from_str(&arguments) {
+                    Ok(input) => vec![Ok(LanguageModelCompletionEvent::ToolUse(
+                        LanguageModelToolUse {
+                            id: call_id.into(),
+                            name: name.into(),
+                            is_input_complete: true,
+                            input,
+                            raw_input: arguments,
+                        },
+                    ))],
+                    Err(error) => vec![Err(LanguageModelCompletionError::BadInputJson {
+                        id: call_id.into(),
+                        tool_name: name.into(),
+                        raw_input: arguments.into(),
+                        json_parse_error: error.to_string(),
+                    })],
+                }
+            }
+            ServerEvent::ResponseDone { response } => self.finish(response),
+            ServerEvent::Error { error } => vec![Err(LanguageModelCompletionError::Other(
+                anyhow!("OpenAI realtime session failed: {}", error.message),
+            ))],
+            ServerEvent::SessionCreated
+            | ServerEvent::SessionUpdated
+            | ServerEvent::SpeechStarted { .. }
+            | ServerEvent::SpeechStopped { .. }
+            | ServerEvent::ResponseCreated { .. }
+            | ServerEvent::AudioDelta { .. }
+            | ServerEvent::OutputItemAdded { .. }
+            | ServerEvent::OutputItemDone { .. }
+            | ServerEvent::Other => Vec::new(),
+        }
+    }
+
+    fn finish(
+        &mut self,
+        response: Response,
+    ) -> Vec<Result<LanguageModelCompletionEvent, LanguageModelCompletionError>> {
+        let reason = response
+            .status_details
+            .as_ref()
+            .and_then(|details| details.reason.as_deref());
+        let stop_reason = match (response.status.as_str(), reason) {
+            ("failed", _) => {
+                let message = response
+                    .status_details
+                    .and_then(|details| details.error)
+                    .map(|error| error.message)
+                    .unwrap_or_else(|| "the response failed".into());
+                return vec![Err(LanguageModelCompletionError::Other(anyhow!(
+                    "OpenAI response failed: {message}"
+                )))];
+            }
+            ("incomplete", Some("max_output_tokens")) => StopReason::MaxTokens,
+            ("incomplete", Some("content_filter")) => StopReason::ContentFilter,
+            _ if self.called_function => StopReason::ToolUse,
+            _ => StopReason::EndTurn,
+        };
+        let mut events = Vec::new();
+        if let Some(usage) = response.usage {
+            events.push(Ok(LanguageModelCompletionEvent::UsageUpdate(TokenUsage {
+                input_tokens: usage.uncached_input_tokens(),
+                output_tokens: usage.output_tokens,
+                cache_creation_input_tokens: 0,
+                cache_read_input_tokens: usage.cached_input_tokens(),
+                reasoning_tokens: 0,
+            })));
+        }
+        events.push(Ok(LanguageModelCompletionEvent::Stop(stop_reason)));
+        events
+    }
+}
+
+struct RawFunctionCall {
+    call_id: String,
+    name: String,
+    arguments: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use language_model::{LanguageModelRequestMessage, LanguageModelToolResult};
+
+    use super::*;
+
+    #[test]
+    fn test_into_realtime() {
+        let request = LanguageModelRequest {
+            messages: vec![
+                LanguageModelRequestMessage {
+                    role: Role::System,
+                    content: vec![MessageContent::Text("Be brief.".into())],
+                    cache: false,
+                },
+                LanguageModelRequestMessage {
+                    role: Role::User,
+                    content: vec![MessageContent::Text("Read main.rs".into())],
+                    cache: false,
+                },
+                LanguageModelRequestMessage {
+                    role: Role::Assistant,
+                    content: vec![MessageContent::ToolUse(LanguageModelToolUse {
+                        id: "call_1".into(),
+                        name: "read_file".into(),
+                        raw_input: r#"{"path":"main.rs"}"#.into(),
+                        input: serde_json::json!({"path": "main.rs"}),
+                        is_input_complete: true,
+                    })],
+                    cache: false,
+                },
+                LanguageModelRequestMessage {
+                    role: Role::User,
+                    content: vec![MessageContent::ToolResult(LanguageModelToolResult {
+                        tool_use_id: "call_1".into(),
+                        tool_name: "read_file".into(),
+                        is_error: false,
+                        content: LanguageModelToolResultContent::Text("fn main() {}".into()),
+                        output: None,
+                    })],
+                    cache: false,
+                },
+            ],
+            temperature: Some(0.2),
+            ..Default::default()
+        };
+        let request = into_realtime(request, &Model::FourOmni, Some(1024));
+        assert_eq!(request.session.instructions.as_deref(), Some("Be brief."));
+        assert_eq!(request.session.temperature, Some(0.6));
+        assert_eq!(request.session.max_response_output_tokens, Some(1024));
+        assert_eq!(
+            request.items,
+            vec![
+                ConversationItem::Message {
+                    role: open_ai::Role::User,
+                    content: vec![ItemContent::InputText {
+                        text: "Read main.rs".into(),
+                    }],
+                },
+                ConversationItem::FunctionCall {
+                    call_id: "call_1".into(),
+                    name: "read_file".into(),
+                    arguments: r#"{"path":"main.rs"}"#.into(),
+                },
+                ConversationItem::FunctionCallOutput {
+                    call_id: "call_1".into(),
+                    output: "fn main() {}".into(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,673 @@
+use std::hash::{DefaultHasher, Hash as _, Hasher as _};
+use std::str::FromStr as _;
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use collections::HashMap;
+use futures::{Stream, StreamExt as _};
+use language_model::{
+    LanguageModelCompletionError, LanguageModelCompletionEvent, LanguageModelRequest,
+    LanguageModelToolChoice, LanguageModelToolResultContent, LanguageModelToolUse, MessageContent,
+    Role, StopReason, TokenUsage,
+};
+use open_ai::responses::{
+    InputContent, InputItem, NamedFunction, OutputContent, OutputItem, Reasoning, ReasoningSummary,
+    Request, Response, StreamEvent, TextConfig, TextFormat, Tool, ToolChoice,
+};
+use open_ai::{Model, SystemPromptRole};
+use parking_lot::Mutex;
+
+use super::{BuiltInTool, ResponsesApiSettings, fold_system_messages};
+use crate::partial_tool_use::partial_tool_use;
+
+pub(super) fn into_responses(
+    mut request: LanguageModelRequest,
+    model: &Model,
+    max_output_tokens: Option<u32>,
+    settings: &ResponsesApiSettings,
+    reasoning_effort: Option<open_ai::ReasoningEffort>,
+) -> Request {
+    let system_prompt_role = model.system_prompt_role();
+    if system_prompt_role == SystemPromptRole::User {
+        fold_system_messages(&mut request.messages);
+    }
+    let mut input = Vec::new();
+    for message in request.messages {
+        for content in message.content {
+            match content {
+                MessageContent::Text(text) => {
+                    let content = match message.role {
+                        Role::Assistant => InputContent::OutputText { text },
+                        Role::User | Role::System => InputContent::InputText { text },
+                    };
+                    push_message_content(content, message.role, &mut input);
+                }
+                MessageContent::Document(document) => push_message_content(
+                    InputContent::InputText {
+                        text: document.to_text(),
+                    },
+                    message.role,
+                    &mut input,
+                ),
+                // The reasoning of earlier responses can only be sent back by continuing from
+                // them, as it's encrypted.
+                MessageContent::Thinking { .. } | MessageContent::RedactedThinking(_) => {}
+                MessageContent::Image(image) => push_message_content(
+                    InputContent::InputImage {
+                        image_url: image.to_base64_url(),
+                    },
+                    message.role,
+                    &mut input,
+                ),
+                MessageContent::Pdf(pdf) => push_message_content(
+                    InputContent::InputFile {
+                        file_data: pdf.to_base64_url(),
+                        filename: pdf.name,
+                    },
+                    message.role,
+                    &mut input,
+                ),
+                // The Responses API doesn't take audio or video, so models that use it don't
+                // support them.
+                MessageContent::Audio(_) | MessageContent::Video(_) => {}
+                MessageContent::ToolUse(tool_use) => input.push(InputItem::FunctionCall {
+                    call_id: tool_use.id.to_string(),
+                    name: tool_use.name.to_string(),
+                    arguments: serde_json::to_string(&tool_use.input).unwrap_or_default(),
+                }),
+                MessageContent::ToolResult(tool_result) => match tool_result.content {
+                    LanguageModelToolResultContent::Text(text) => {
+                        input.push(InputItem::FunctionCallOutput {
+                            call_id: tool_result.tool_use_id.to_string(),
+                            output: text.to_string(),
+                        })
+                    }
+                    // Function call outputs are text, so images are sent after them instead.
+                    LanguageModelToolResultContent::Image(image) => {
+                        input.push(InputItem::FunctionCallOutput {
+                            call_id: tool_result.tool_use_id.to_string(),
+                            output: String::new(),
+                        });
+                        push_message_content(
+                            InputContent::InputImage {
+                                image_url: image.to_base64_url(),
+                            },
+                            Role::User,
+                            &mut input,
+                        );
+                    }
+                },
+            }
+        }
+    }
+
+    let mut tools = request
+        .tools
+        .into_iter()
+        .map(|tool| Tool::Function {
+            name: tool.name,
+            description: Some(tool.description),
+            parameters: Some(tool.input_schema),
+            strict: false,
+        })
+        .collect::<Vec<_>>();
+    let parallel_tool_calls = if model.supports_parallel_tool_calls() && !tools.is_empty() {
+        Some(request.parallel_tool_calls.unwrap_or(false))
+    } else {
+        None
+    };
+    tools.extend(settings.built_in_tools.iter().map(|tool| match tool {
+        BuiltInTool::WebSearch => Tool::WebSearch,
+        BuiltInTool::FileSearch {
+            vector_store_ids,
+            max_num_results,
+        } => Tool::FileSearch {
+            vector_store_ids: vector_store_ids.clone(),
+            max_num_results: *max_num_results,
+        },
+    }));
+    if system_prompt_role == SystemPromptRole::Developer {
+        for item in &mut input {
+            if let InputItem::Message { role, .. } = item {
+                if *role == open_ai::Role::System {
+                    *role = open_ai::Role::Developer;
+                }
+            }
+        }
+    }
+
+    Request {
+        model: model.id().into(),
+        input,
+        stream: true,
+        store: settings.store,
+        previous_response_id: None,
+        max_output_tokens,
+        temperature: request.temperature,
+        top_p: request.top_p,
+        tools,
+        tool_choice: request.tool_choice.map(|choice| match choice {
+            LanguageModelToolChoice::Auto => ToolChoice::Auto,
+            LanguageModelToolChoice::Any => ToolChoice::Required,
+            LanguageModelToolChoice::None => ToolChoice::None,
+            LanguageModelToolChoice::Tool(name) => {
+                ToolChoice::Function(NamedFunction::Function { name })
+            }
+        }),
+        parallel_tool_calls,
+        reasoning: reasoning_effort.map(|effort| Reasoning {
+            effort: Some(effort),
+            summary: Some(ReasoningSummary::Auto),
+        }),
+        text: request.response_schema.map(|response_schema| TextConfig {
+            format: TextFormat::JsonSchema {
+                name: response_schema.name,
+                description: response_schema.description,
+                schema: response_schema.schema,
+                strict: true,
+            },
+        }),
+        extra_body: serde_json::Map::new(),
+    }
+}
+
+fn push_message_content(content: InputContent, role: Role, input: &mut Vec<InputItem>) {
+    let role = match role {
+        Role::User => open_ai::Role::User,
+        Role::Assistant => open_ai::Role::Assistant,
+        Role::System => open_ai::Role::System,
+    };
+    match input.last_mut() {
+        Some(InputItem::Message {
+            role: last_role,
+            content: last_content,
+        }) if *last_role == role => last_content.push(content),
+        _ => input.push(InputItem::Message {
+            role,
+            content: vec![content],
+        }),
+    }
+}
+
+/// How many threads' last responses are remembered, since threads are never closed.
+const MAX_RESPONSE_CHAINS: usize = 256;
+
+/// The last stored response of each thread, so that the thread's next request can continue from
+/// it instead of sending the whole conversation again. The least recently used threads are
+/// forgotten first.
+#[derive(Clone, Default)]
+pub(super) struct ResponseChains(Arc<Mutex<HashMap<String, ResponseChain>>>);
+
+struct ResponseChain {
+    model: String,
+    response_id: String,
+    /// The conversation up to and including the response.
+    input: InputPrefix,
+    used_at: Instant,
+}
+
+/// The length and hash of the start of a conversation, which is all that's needed to tell whether
+/// a later request continues it.
+#[derive(Clone, Default)]
+struct InputPrefix {
+    len: usize,
+    hasher: DefaultHasher,
+}
+
+impl InputPrefix {
+    fn push(&mut self, item: &InputItem) {
+        item.hash(&mut self.hasher);
+        self.len += 1;
+    }
+
+    fn matches(&self, other: &InputPrefix) -> bool {
+        self.len == other.len && self.hasher.finish() == other.hasher.finish()
+    }
+}
+
+impl ResponseChains {
+    /// Continues `request` from the thread's last stored response, so that only the input after
+    /// it is sent, if the request's conversation starts with the response's.
+    ///
+    /// Returns the mapper for the response, which stores it as the thread's last response.
+    pub(super) fn continue_thread(
+        &self,
+        thread_id: Option<String>,
+        request: &mut Request,
+    ) -> ResponsesEventMapper {
+        let Some(thread_id) = thread_id.filter(|_| request.store) else {
+            return ResponsesEventMapper::new(None);
+        };
+
+        // A trailing assistant message is extended by the response's text, so it's only hashed
+        // once the response completes.
+        let output = match request.input.last() {
+            Some(item @ InputItem::Message { role, .. }) if *role == open_ai::Role::Assistant => {
+                vec![item.clone()]
+            }
+            _ => Vec::new(),
+        };
+        let chain = self
+            .0
+            .lock()
+            .get(&thread_id)
+            .filter(|chain| chain.model == request.model)
+            .map(|chain| (chain.input.clone(), chain.response_id.clone()));
+        let mut input = InputPrefix::default();
+        let mut previous_response = None;
+        for item in &request.input[..request.input.len() - output.len()] {
+            if let Some((chain_input, response_id)) = &chain {
+                if input.matches(chain_input) {
+                    previous_response = Some((input.len, response_id.clone()));
+                }
+            }
+            input.push(item);
+        }
+        if let Some((len, response_id)) = previous_response {
+            request.input.drain(..len);
+            request.previous_response_id = Some(response_id);
+        }
+
+        ResponsesEventMapper::new(Some(PendingChain {
+            chains: self.clone(),
+            thread_id,
+            model: request.model.clone(),
+            input,
+            output,
+        }))
+    }
+
+    fn insert(&self, thread_id: String, chain: ResponseChain) {
+        let mut chains = self.0.lock();
+        chains.insert(thread_id, chain);
+        if chains.len() > MAX_RESPONSE_CHAINS {
+            let least_recently_used = chains
+                .iter()
+                .min_by_key(|(_, chain)| chain.used_at)
+                .map(|(thread_id, _)| thread_id.clone());
+            if let Some(thread_id) = least_recently_used {
+                chains.remove(&thread_id);
+            }
+        }
+    }
+}
+
+/// A response that's stored as its thread's last response once it completes.
+#[derive(Clone)]
+struct PendingChain {
+    chains: ResponseChains,
+    thread_id: String,
+    model: String,
+    /// The request's conversation, up to the items in `output`.
+    input: InputPrefix,
+    /// The items that the response added to the conversation.
+    output: Vec<InputItem>,
+}
+
+#[derive(Clone)]
+pub(super) struct ResponsesEventMapper {
+    /// The function calls being streamed, by output index.
+    function_calls: HashMap<usize, RawFunctionCall>,
+    called_function: bool,
+    chain: Option<PendingChain>,
+}
+
+impl ResponsesEventMapper {
+    fn new(chain: Option<PendingChain>) -> Self {
+        Self {
+            function_calls: HashMap::default(),
+            called_function: false,
+            chain,
+        }
+    }
+
+    pub(super) fn map_stream(
+        mut self,
+        events: futures::stream::BoxStream<'static, anyhow::Result<StreamEvent>>,
+    ) -> impl Stream<Item = Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>
+    {
+        events.flat_map(move |event| {
+            futures::stream::iter(match event {
+                Ok(event) => self.map_event(event),
+                Err(error) => vec![Err(LanguageModelCompletionError::Other(anyhow!(error)))],
+            })
+        })
+    }
+
+    fn map_event(
+        &mut self,
+        event: StreamEvent,
+    ) -> Vec<Result<LanguageModelCompletionEvent, LanguageModelCompletionError>> {
+        match event {
+            StreamEvent::OutputItemAdded {
+                output_index,
+                item: OutputItem::FunctionCall { call_id, name, .. },
+            } => {
+                self.function_calls.insert(
+                    output_index,
+                    RawFunctionCall {
+                        call_id,
+                        name,
+                        arguments: String::new(),
+                    },
+                );
+                Vec::new()
+            }
+            StreamEvent::FunctionCallArgumentsDelta {
+                output_index,
+                delta,
+            } => {
+                let Some(function_call) = self.function_calls.get_mut(&output_index) else {
+                    return Vec::new();
+                };
+                function_call.arguments.push_str(&delta);
+                partial_tool_use(
+                    &function_call.call_id,
+                    &function_call.name,
+                    &function_call.arguments,
+                )
+                .map(Ok)
+                .into_iter()
+                .collect()
+            }
+            StreamEvent::OutputTextDelta { delta, .. } => {
+                vec![Ok(LanguageModelCompletionEvent::Text(delta))]
+            }
+            StreamEvent::ReasoningSummaryTextDelta { delta, .. } => {
+                vec![Ok(LanguageModelCompletionEvent::Thinking {
+                    text: delta,
+                    signature: None,
+                })]
+            }
+            StreamEvent::OutputItemDone { output_index, item } => {
+                self.function_calls.remove(&output_index);
+                self.push_output(&item);
+                match item {
+                    OutputItem::FunctionCall {
+                        call_id,
+                        name,
+                        arguments,
+                    } => {
+                        self.called_function = true;
+                        match serde_json::Value::from_str(&arguments) {
+                            Ok(input) => vec![Ok(LanguageModelCompletionEvent::ToolUse(
+                                LanguageModelToolUse {
+                                    id: call_id.into(),
+                                    name: name.into(),
+                                    is_input_complete: true,
+                                    input,
+                                    raw_input: arguments,
+                                },
+                            ))],
+                            Err(error) => vec![Err(LanguageModelCompletionError::BadInputJson {
+                                id: call_id.into(),
+                                tool_name: name.into(),
+                                raw_input: arguments.into(),
+                                json_parse_error: error.to_string(),
+                            })],
+                        }
+                    }
+                    OutputItem::Message { content } => content
+                        .into_iter()
+                        .filter_map(|content| match content {
+                            OutputContent::Refusal { refusal } => {
+                                Some(Ok(LanguageModelCompletionEvent::Text(refusal)))
+                            }
+                            OutputContent::OutputText { .. } | OutputContent::Other => None,
+                        })
+                        .collect(),
+                    OutputItem::WebSearchCall { .. }
+                    | OutputItem::FileSearchCall { .. }
+                    | OutputItem::Other => Vec::new(),
+                }
+            }
+            StreamEvent::Completed { response } => {
+                let stop_reason = if self.called_function {
+                    StopReason::ToolUse
+                } else {
+                    StopReason::EndTurn
+                };
+                self.finish(response, stop_reason)
+            }
+            StreamEvent::Incomplete { response } => {
+                let stop_reason = match response
+                    .incomplete_details
+                    .as_ref()
+                    .map(|details| details.reason.as_str())
+                {
+                    Some("max_output_tokens") => StopReason::MaxTokens,
+                    Some("content_filter") => StopReason::ContentFilter,
+                    _ => StopReason::EndTurn,
+                };
+                self.finish(response, stop_reason)
+            }
+            StreamEvent::Failed { response } => {
+                let message = response
+                    .error
+                    .map(|error| error.message)
+                    .unwrap_or_else(|| "the response failed".into());
+                vec![Err(LanguageModelCompletionError::Other(anyhow!(
+                    "OpenAI response failed: {message}"
+                )))]
+            }
+            StreamEvent::Error { message, .. } => vec![Err(LanguageModelCompletionError::Other(
+                anyhow!("OpenAI response failed: {message}"),
+            ))],
+            StreamEvent::Created { .. }
+            | StreamEvent::OutputItemAdded { .. }
+            | StreamEvent::Other => Vec::new(),
+        }
+    }
+
+    /// Adds an output item to the conversation that's stored for the response's thread, in the
+    /// form that the thread's next request sends it in.
+    fn push_output(&mut self, item: &OutputItem) {
+        let Some(chain) = self.chain.as_mut() else {
+            return;
+        };
+        match item {
+            OutputItem::Message { content } => {
+                for content in content {
+                    if let OutputContent::OutputText { text } = content {
+                        push_message_content(
+                            InputContent::OutputText { text: text.clone() },
+                            Role::Assistant,
+                            &mut chain.output,
+                        );
+                    }
+                }
+            }
+            OutputItem::FunctionCall {
+                call_id,
+                name,
+                arguments,
+            } => chain.output.push(InputItem::FunctionCall {
+                call_id: call_id.clone(),
+                name: name.clone(),
+                arguments: serde_json::Value::from_str(arguments)
+                    .map(|arguments| arguments.to_string())
+                    .unwrap_or_else(|_| arguments.clone()),
+            }),
+            OutputItem::WebSearchCall { .. }
+            | OutputItem::FileSearchCall { .. }
+            | OutputItem::Other => {}
+        }
+    }
+
+    fn finish(
+        &mut self,
+        response: Response,
+        stop_reason: StopReason,
+    ) -> Vec<Result<LanguageModelCompletionEvent, LanguageModelCompletionError>> {
+        if let Some(mut chain) = self.chain.take() {
+            for item in &chain.output {
+                chain.input.push(item);
+            }
+            chain.chains.insert(
+                chain.thread_id,
+                ResponseChain {
+                    model: chain.model,
+                    response_id: response.id,
+                    input: chain.input,
+                    used_at: Instant::now(),
+                },
+            );
+        }
+        let mut events = Vec::new();
+        if let Some(usage) = response.usage {
+            events.push(Ok(LanguageModelCompletionEvent::UsageUpdate(TokenUsage {
+                input_tokens: usage.uncached_input_tokens(),
+                output_tokens: usage.output_tokens,
+                cache_creation_input_tokens: 0,
+                cache_read_input_tokens: usage.cached_input_tokens(),
+                reasoning_tokens: usage.reasoning_tokens(),
+            })));
+        }
+        events.push(Ok(LanguageModelCompletionEvent::Stop(stop_reason)));
+        events
+    }
+}
+
+#[derive(Clone)]
+struct RawFunctionCall {
+    call_id: String,
+    name: String,
+    arguments: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use language_model::{LanguageModelRequestMessage, LanguageModelToolResult};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_response_chains() {
+        let settings = ResponsesApiSettings {
+            built_in_tools: vec![BuiltInTool::WebSearch],
+            store: true,
+        };
+        let mut messages = vec![LanguageModelRequestMessage {
+            role: Role::User,
+            content: vec![MessageContent::Text("What's in main.rs?".into())],
+            cache: false,
+        }];
+        let request = |messages: &Vec<LanguageModelRequestMessage>| {
+            into_responses(
+                LanguageModelRequest {
+                    thread_id: Some("thread".into()),
+                    messages: messages.clone(),
+                    ..Default::default()
+                },
+                &Model::FourPointOne,
+                None,
+                &settings,
+                None,
+            )
+        };
+        let chains = ResponseChains::default();
+
+        let mut first_request = request(&messages);
+        let mut mapper = chains.continue_thread(Some("thread".into()), &mut first_request);
+        assert_eq!(first_request.previous_response_id, None);
+        let mut events = Vec::new();
+        for event in [
+            r#"{"type":"response.created","response":{"id":"resp_1"}}"#,
+            r#"{"type":"response.output_text.delta","output_index":0,"delta":"Let me look."}"#,
+            r#"{"type":"response.output_item.done","output_index":0,"item":{"type":"message","content":[{"type":"output_text","text":"Let me look."}]}}"#,
+            r#"{"type":"response.output_item.added","output_index":1,"item":{"type":"function_call","call_id":"call_1","name":"read_file","arguments":""}}"#,
+            r#"{"type":"response.function_call_arguments.delta","output_index":1,"delta":"{\"path\": \"main"}"#,
+            r#"{"type":"response.output_item.done","output_index":1,"item":{"type":"function_call","call_id":"call_1","name":"read_file","arguments":"{\"path\": \"main.rs\"}"}}"#,
+            r#"{"type":"response.completed","response":{"id":"resp_1","usage":{"input_tokens":10,"output_tokens":5}}}"#,
+        ] {
+            events.extend(
+                mapper
+                    .map_event(serde_json::from_str(event).unwrap())
+                    .into_iter()
+                    .map(Result::unwrap),
+            );
+        }
+        let tool_use = LanguageModelToolUse {
+            id: "call_1".into(),
+            name: "read_file".into(),
+            is_input_complete: true,
+            input: serde_json::json!({"path": "main.rs"}),
+            raw_input: r#"{"path": "main.rs"}"#.into(),
+        };
+        assert_eq!(
+            events,
+            vec![
+                LanguageModelCompletionEvent::Text("Let me look.".into()),
+                LanguageModelCompletionEvent::ToolUse(LanguageModelToolUse {
+                    is_input_complete: false,
+                    input: serde_json::json!({"path": "main"}),
+                    raw_input: r#"{"path": "main"#.into(),
+                    ..tool_use.clone()
+                }),
+                LanguageModelCompletionEvent::ToolUse(tool_use.clone()),
+                LanguageModelCompletionEvent::UsageUpdate(TokenUsage {
+                    input_tokens: 10,
+                    output_tokens: 5,
+                    ..Default::default()
+                }),
+                LanguageModelCompletionEvent::Stop(StopReason::ToolUse),
+            ]
+        );
+
+        messages.push(LanguageModelRequestMessage {
+            role: Role::Assistant,
+            content: vec![
+                MessageContent::Text("Let me look.".into()),
+                MessageContent::ToolUse(tool_use),
+            ],
+            cache: false,
+        });
+        messages.push(LanguageModelRequestMessage {
+            role: Role::User,
+            content: vec![MessageContent::ToolResult(LanguageModelToolResult {
+                tool_use_id: "call_1".into(),
+                tool_name: "read_file".into(),
+                is_error: false,
+                content: LanguageModelToolResultContent::Text("fn main() {}".into()),
+                output: None,
+            })],
+            cache: false,
+        });
+        let mut second_request = request(&messages);
+        chains.continue_thread(Some("thread".into()), &mut second_request);
+        assert_eq!(
+            second_request.previous_response_id.as_deref(),
+            Some("resp_1")
+        );
+        assert_eq!(
+            second_request.input,
+            vec![InputItem::FunctionCallOutput {
+                call_id: "call_1".into(),
+                output: "fn main() {}".into(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_response_chains_forget_least_recently_used() {
+        let chains = ResponseChains::default();
+        let now = Instant::now();
+        for ix in 0..=MAX_RESPONSE_CHAINS {
+            chains.insert(
+                format!("thread-{ix}"),
+                ResponseChain {
+                    model: "gpt-4.1".into(),
+                    response_id: format!("resp_{ix}"),
+                    input: InputPrefix::default(),
+                    used_at: now + Duration::from_secs(ix as u64),
+                },
+            );
+        }
+        let chains = chains.0.lock();
+        assert_eq!(chains.len(), MAX_RESPONSE_CHAINS);
+        assert!(!chains.contains_key("thread-0"));
+        assert!(chains.contains_key(&format!("thread-{MAX_RESPONSE_CHAINS}")));
+    }
+}
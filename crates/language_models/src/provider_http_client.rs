@@ -0,0 +1,878 @@
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{self, Poll, ready};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use futures::future::{self, BoxFuture, Either};
+use futures::{AsyncRead, AsyncReadExt as _, FutureExt as _};
+use gpui::{App, BackgroundExecutor, Task};
+use gpui_tokio::Tokio;
+use http_client::http::header::CONTENT_TYPE;
+use http_client::http::{HeaderMap, HeaderName, HeaderValue};
+use http_client::{AsyncBody, HttpClient, Inner, Request, Response, StatusCode, Uri, Url};
+use http_client_tls::ClientConfig;
+use language_model::{LanguageModelCompletionError, LanguageModelKnownError, LanguageModelTimeout};
+use parking_lot::{Mutex, RwLock};
+use rand::Rng as _;
+use release_channel::AppVersion;
+use reqwest_client::ReqwestClient;
+use settings::{Settings as _, SettingsStore};
+
+use crate::api_key::expand_home_dir;
+use crate::request_budget::{RequestBudget, estimate_tokens};
+use crate::{AllLanguageModelSettings, ProviderApiSettings};
+
+/// The HTTP client that a provider sends its requests with, which applies the provider's own
+/// `proxy`, TLS, `headers`, and `query_params` settings on top of Zed's client, holds back
+/// requests that would exceed its `requests_per_minute` or `tokens_per_minute`, gives up on
+/// requests that exceed `connect_timeout_secs`, retries requests that fail with transient
+/// errors, watches event streams for stalls, and turns the error responses it recognizes into
+/// [`LanguageModelCompletionError`]s.
+pub(crate) struct ProviderHttpClient {
+    client: Arc<dyn HttpClient>,
+    executor: BackgroundExecutor,
+    options: RwLock<RequestOptions>,
+    budget: Arc<Mutex<RequestBudget>>,
+}
+
+/// What the settings add to every request that the provider sends.
+struct RequestOptions {
+    headers: Vec<(HeaderName, HeaderValue)>,
+    query_params: Vec<(String, String)>,
+    max_retries: u32,
+    connect_timeout: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    requests_per_minute: Option<u32>,
+    tokens_per_minute: Option<u64>,
+}
+
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Requests aren't retried if the provider asks to wait longer than this, since the user is
+/// better off seeing the error.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+impl ProviderHttpClient {
+    /// Creates the client for the provider whose settings `api_settings` selects. The headers and
+    /// query parameters follow changes to the settings, while a new proxy or certificate takes
+    /// effect after restarting Zed.
+    pub(crate) fn new(
+        http_client: Arc<dyn HttpClient>,
+        api_settings: fn(&AllLanguageModelSettings) -> &ProviderApiSettings,
+        cx: &mut App,
+    ) -> Arc<dyn HttpClient> {
+        let settings = api_settings(AllLanguageModelSettings::get_global(cx));
+        let this = Arc::new(Self {
+            client: dedicated_http_client(http_client, settings, cx),
+            executor: cx.background_executor().clone(),
+            options: RwLock::new(RequestOptions::new(settings)),
+            budget: Arc::default(),
+        });
+
+        let weak_this = Arc::downgrade(&this);
+        cx.observe_global::<SettingsStore>(move |cx| {
+            if let Some(this) = weak_this.upgrade() {
+                let settings = api_settings(AllLanguageModelSettings::get_global(cx));
+                *this.options.write() = RequestOptions::new(settings);
+            }
+        })
+        .detach();
+
+        this
+    }
+}
+
+impl HttpClient for ProviderHttpClient {
+    fn send(
+        &self,
+        mut req: Request<AsyncBody>,
+    ) -> BoxFuture<'static, anyhow::Result<Response<AsyncBody>>> {
+        let options = self.options.read();
+        if let Err(error) = options.apply(&mut req) {
+            return future::ready(Err(error)).boxed();
+        }
+        let max_retries = options.max_retries;
+        let connect_timeout = options.connect_timeout;
+        let idle_timeout = options.idle_timeout;
+        let requests_per_minute = options.requests_per_minute;
+        let tokens_per_minute = options.tokens_per_minute;
+        drop(options);
+        let has_budget = requests_per_minute.is_some() || tokens_per_minute.is_some();
+        if max_retries == 0 && connect_timeout.is_none() && idle_timeout.is_none() && !has_budget {
+            let response = self.client.send(req);
+            return async move { recognize_error_response(response.await?).await }.boxed();
+        }
+
+        let client = self.client.clone();
+        let executor = self.executor.clone();
+        let budget = self.budget.clone();
+        async move {
+            let mut attempt = 0;
+            loop {
+                let copy = if attempt < max_retries {
+                    try_clone_request(&req)
+                } else {
+                    None
+                };
+                if has_budget {
+                    wait_for_budget(
+                        &budget,
+                        &req,
+                        requests_per_minute,
+                        tokens_per_minute,
+                        &executor,
+                    )
+                    .await;
+                }
+                let mut response =
+                    send_with_timeout(client.as_ref(), req, &executor, connect_timeout).await?;
+                let delay = if attempt < max_retries && !response.status().is_success() {
+                    let mut body = Vec::new();
+                    response.body_mut().read_to_end(&mut body).await?;
+                    let delay = retry_delay(&response, &body, attempt);
+                    *response.body_mut() = AsyncBody::from(body);
+                    delay
+                } else {
+                    None
+                };
+                match (copy, delay) {
+                    (Some(retry), Some(delay)) => {
+                        log::warn!(
+                            "language model request failed with {}, retrying in {delay:?}",
+                            response.status()
+                        );
+                        executor.timer(delay).await;
+                        attempt += 1;
+                        req = retry;
+                    }
+                    (copy, _) => {
+                        let response = recognize_error_response(response).await?;
+                        return Ok(match idle_timeout {
+                            Some(idle_timeout) => watch_for_stalls(
+                                response,
+                                copy.map(|copy| (copy, client)),
+                                executor,
+                                idle_timeout,
+                            ),
+                            None => response,
+                        });
+                    }
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn proxy(&self) -> Option<&Url> {
+        self.client.proxy()
+    }
+
+    fn type_name(&self) -> &'static str {
+        self.client.type_name()
+    }
+}
+
+/// Providers with their own `proxy` or certificates get a separate client, since corporate
+/// networks often route AI endpoints differently from the rest of Zed's traffic, and self-hosted
+/// models are often served behind an internal PKI.
+fn dedicated_http_client(
+    http_client: Arc<dyn HttpClient>,
+    settings: &ProviderApiSettings,
+    cx: &App,
+) -> Arc<dyn HttpClient> {
+    if settings.proxy.is_none() && !has_certificates(settings) {
+        return http_client;
+    }
+
+    let proxy_url = match settings.proxy.as_deref().map(str::parse::<Url>) {
+        Some(Ok(proxy_url)) => Some(proxy_url),
+        Some(Err(error)) => {
+            log::error!("invalid language model provider proxy: {error}");
+            return http_client;
+        }
+        None => http_client.proxy().cloned(),
+    };
+    let tls_config = match tls_config(settings) {
+        Ok(tls_config) => tls_config,
+        Err(error) => {
+            log::error!("invalid language model provider certificates: {error:#}");
+            return http_client;
+        }
+    };
+
+    let user_agent = format!(
+        "Zed/{} ({}; {})",
+        AppVersion::global(cx),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let _guard = Tokio::handle(cx).enter();
+    match ReqwestClient::proxy_user_agent_and_tls(proxy_url, &user_agent, tls_config) {
+        Ok(client) => Arc::new(client),
+        Err(error) => {
+            log::error!("failed to create a language model provider HTTP client: {error:#}");
+            http_client
+        }
+    }
+}
+
+fn has_certificates(settings: &ProviderApiSettings) -> bool {
+    settings.ca_cert.is_some() || settings.client_cert.is_some() || settings.client_key.is_some()
+}
+
+/// The TLS configuration for the provider's `ca_cert`, `client_cert`, and `client_key`, or
+/// Zed's usual one if it has none.
+pub(crate) fn tls_config(settings: &ProviderApiSettings) -> Result<ClientConfig> {
+    if !has_certificates(settings) {
+        return Ok(http_client_tls::tls_config());
+    }
+    let ca_cert = settings.ca_cert.as_deref().map(expand_home_dir);
+    let client_cert = settings.client_cert.as_deref().map(expand_home_dir);
+    let client_key = settings.client_key.as_deref().map(expand_home_dir);
+    http_client_tls::tls_config_with_certificates(
+        ca_cert.as_deref(),
+        client_cert.as_deref(),
+        client_key.as_deref(),
+    )
+}
+
+impl RequestOptions {
+    fn new(settings: &ProviderApiSettings) -> Self {
+        let headers = settings
+            .headers
+            .iter()
+            .filter_map(|(name, value)| {
+                let header = HeaderName::try_from(name.as_str())
+                    .map_err(anyhow::Error::from)
+                    .and_then(|name| Ok((name, HeaderValue::try_from(value.as_str())?)));
+                match header {
+                    Ok(header) => Some(header),
+                    Err(error) => {
+                        log::error!(
+                            "ignoring invalid language model request header {name:?}: {error}"
+                        );
+                        None
+                    }
+                }
+            })
+            .collect();
+        let query_params = settings
+            .query_params
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        Self {
+            headers,
+            query_params,
+            max_retries: settings.max_retries(),
+            connect_timeout: settings.connect_timeout(),
+            idle_timeout: settings.idle_timeout(),
+            requests_per_minute: settings.requests_per_minute(),
+            tokens_per_minute: settings.tokens_per_minute(),
+        }
+    }
+
+    fn apply(&self, req: &mut Request<AsyncBody>) -> Result<()> {
+        for (name, value) in &self.headers {
+            req.headers_mut().insert(name.clone(), value.clone());
+        }
+        if !self.query_params.is_empty() {
+            *req.uri_mut() = with_query_params(req.uri(), &self.query_params)?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds `query_params` to `uri`, replacing the parameters of the same name that it already had.
+fn with_query_params(uri: &Uri, query_params: &[(String, String)]) -> Result<Uri> {
+    let mut url = Url::parse(&uri.to_string())?;
+    set_query_params(&mut url, query_params);
+    Ok(url.as_str().parse()?)
+}
+
+/// Adds `query_params` to `url`, replacing the parameters of the same name that it already had.
+pub(crate) fn set_query_params(url: &mut Url, query_params: &[(String, String)]) {
+    let existing_params = url
+        .query_pairs()
+        .filter(|(name, _)| !query_params.iter().any(|(param, _)| param == name))
+        .map(|(name, value)| (name.into_owned(), value.into_owned()))
+        .collect::<Vec<_>>();
+    url.query_pairs_mut()
+        .clear()
+        .extend_pairs(existing_params)
+        .extend_pairs(query_params);
+}
+
+/// Waits until sending `req` fits within the provider's `requests_per_minute` and
+/// `tokens_per_minute`.
+async fn wait_for_budget(
+    budget: &Mutex<RequestBudget>,
+    req: &Request<AsyncBody>,
+    requests_per_minute: Option<u32>,
+    tokens_per_minute: Option<u64>,
+    executor: &BackgroundExecutor,
+) {
+    let tokens = match &req.body().0 {
+        Inner::Bytes(bytes) => estimate_tokens(bytes.get_ref().len()),
+        Inner::Empty | Inner::AsyncReader(_) => 0,
+    };
+    loop {
+        let reserved = budget.lock().try_reserve(
+            tokens,
+            requests_per_minute,
+            tokens_per_minute,
+            Instant::now(),
+        );
+        match reserved {
+            Ok(()) => return,
+            Err(delay) => {
+                log::info!(
+                    "holding language model request for {delay:?} to stay within the provider's rate limits"
+                );
+                executor.timer(delay).await;
+            }
+        }
+    }
+}
+
+/// Sends `req`, failing with [`LanguageModelTimeout::Connect`] if the provider hasn't responded
+/// within `timeout`.
+async fn send_with_timeout(
+    client: &dyn HttpClient,
+    req: Request<AsyncBody>,
+    executor: &BackgroundExecutor,
+    timeout: Option<Duration>,
+) -> Result<Response<AsyncBody>> {
+    let Some(timeout) = timeout else {
+        return client.send(req).await;
+    };
+    match future::select(client.send(req), executor.timer(timeout)).await {
+        Either::Left((response, _)) => response,
+        Either::Right(_) => Err(anyhow!(LanguageModelKnownError::Timeout {
+            kind: LanguageModelTimeout::Connect,
+            after: timeout,
+        })),
+    }
+}
+
+/// Ends an event stream with a [`LanguageModelTimeout::Idle`] timeout when the provider sends
+/// nothing for `idle_timeout`, not even the keep-alive comments that it sends while the model is
+/// thinking. When the stream stalls or drops before sending anything, it's reconnected once by
+/// sending `reconnect` again, since nothing of it has been read. `reconnect` is only given while
+/// the request has retries left, so that a copy of every request isn't kept just for this.
+fn watch_for_stalls(
+    mut response: Response<AsyncBody>,
+    reconnect: Option<(Request<AsyncBody>, Arc<dyn HttpClient>)>,
+    executor: BackgroundExecutor,
+    idle_timeout: Duration,
+) -> Response<AsyncBody> {
+    let is_event_stream = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .is_some_and(|content_type| content_type.starts_with("text/event-stream"));
+    if !is_event_stream {
+        return response;
+    }
+    let body = std::mem::take(response.body_mut());
+    *response.body_mut() = AsyncBody::from_reader(StallWatchedBody {
+        body,
+        timer: executor.timer(idle_timeout),
+        idle_timeout,
+        executor,
+        reconnect,
+        reconnecting: None,
+    });
+    response
+}
+
+struct StallWatchedBody {
+    body: AsyncBody,
+    timer: Task<()>,
+    idle_timeout: Duration,
+    executor: BackgroundExecutor,
+    /// The request to send again if the stream fails before anything was read from it.
+    reconnect: Option<(Request<AsyncBody>, Arc<dyn HttpClient>)>,
+    reconnecting: Option<Task<Result<Response<AsyncBody>>>>,
+}
+
+impl StallWatchedBody {
+    fn try_reconnect(&mut self, reason: &str) -> bool {
+        let Some((request, client)) = self.reconnect.take() else {
+            return false;
+        };
+        log::warn!("language model event stream {reason} before sending anything, reconnecting");
+        // Closing the abandoned connection first stops the provider from generating, and billing,
+        // a response that nobody reads.
+        self.body = AsyncBody::empty();
+        self.reconnecting = Some(self.executor.spawn(async move {
+            let response = recognize_error_response(client.send(request).await?).await?;
+            if !response.status().is_success() {
+                return Err(anyhow!("reconnecting failed with {}", response.status()));
+            }
+            Ok(response)
+        }));
+        true
+    }
+}
+
+impl AsyncRead for StallWatchedBody {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(reconnecting) = this.reconnecting.as_mut() {
+                let response = ready!(reconnecting.poll_unpin(cx));
+                this.reconnecting = None;
+                this.body = response.map_err(io::Error::other)?.into_body();
+                this.timer = this.executor.timer(this.idle_timeout);
+            }
+
+            match Pin::new(&mut this.body).poll_read(cx, buf) {
+                Poll::Ready(Ok(read)) => {
+                    if read > 0 {
+                        this.reconnect = None;
+                    }
+                    this.timer = this.executor.timer(this.idle_timeout);
+                    return Poll::Ready(Ok(read));
+                }
+                Poll::Ready(Err(error)) => {
+                    if this.try_reconnect("failed") {
+                        continue;
+                    }
+                    return Poll::Ready(Err(error));
+                }
+                Poll::Pending => {}
+            }
+
+            ready!(this.timer.poll_unpin(cx));
+            if this.try_reconnect("stalled") {
+                continue;
+            }
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                LanguageModelKnownError::Timeout {
+                    kind: LanguageModelTimeout::Idle,
+                    after: this.idle_timeout,
+                },
+            )));
+        }
+    }
+}
+
+/// Copies a request so that it can be sent again, unless its body is streamed.
+fn try_clone_request(req: &Request<AsyncBody>) -> Option<Request<AsyncBody>> {
+    let body = match &req.body().0 {
+        Inner::Empty => AsyncBody::empty(),
+        Inner::Bytes(bytes) => AsyncBody::from_bytes(bytes.get_ref().clone()),
+        Inner::AsyncReader(_) => return None,
+    };
+    let mut clone = Request::new(body);
+    *clone.method_mut() = req.method().clone();
+    *clone.uri_mut() = req.uri().clone();
+    *clone.version_mut() = req.version();
+    *clone.headers_mut() = req.headers().clone();
+    *clone.extensions_mut() = req.extensions().clone();
+    Some(clone)
+}
+
+/// How long to wait before retrying a request that got `response` with `body`, or `None` if it
+/// shouldn't be retried. Providers answer 429 both when they are rate limited and when the key
+/// ran out of quota, and only the former goes away by waiting.
+fn retry_delay(response: &Response<AsyncBody>, body: &[u8], attempt: u32) -> Option<Duration> {
+    if matches!(
+        completion_error(response.status(), response.headers(), body),
+        Some(
+            LanguageModelCompletionError::QuotaExceeded
+                | LanguageModelCompletionError::InvalidApiKey
+        )
+    ) {
+        return None;
+    }
+
+    let is_transient = matches!(
+        response.status(),
+        StatusCode::REQUEST_TIMEOUT
+            | StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::INTERNAL_SERVER_ERROR
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    ) || response.status().as_u16() == 529;
+    let headers = response.headers();
+    if !is_transient
+        || headers
+            .get("x-should-retry")
+            .is_some_and(|value| value.as_bytes() == b"false")
+    {
+        return None;
+    }
+
+    match retry_after(headers) {
+        Some(delay) => (delay <= MAX_RETRY_DELAY).then_some(delay),
+        None => {
+            let backoff = INITIAL_RETRY_DELAY
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(MAX_RETRY_DELAY);
+            Some(backoff.mul_f64(rand::thread_rng().gen_range(0.5..=1.0)))
+        }
+    }
+}
+
+/// Fails with the matching [`LanguageModelCompletionError`] if `response` is an error that
+/// [`completion_error`] recognizes, so that the UI can explain it instead of showing the body the
+/// provider sent. Any other response is passed on unchanged.
+async fn recognize_error_response(
+    mut response: Response<AsyncBody>,
+) -> Result<Response<AsyncBody>> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+
+    let mut body = Vec::new();
+    response.body_mut().read_to_end(&mut body).await?;
+    if let Some(error) = completion_error(response.status(), response.headers(), &body) {
+        log::warn!(
+            "language model request failed with {}: {}",
+            response.status(),
+            String::from_utf8_lossy(&body)
+        );
+        return Err(anyhow!(error));
+    }
+    *response.body_mut() = AsyncBody::from(body);
+    Ok(response)
+}
+
+/// Maps an error response to a [`LanguageModelCompletionError`] by its status and by the `code`,
+/// `type`, `status`, or detail `reason` of the error in its body, since providers fill in
+/// different ones.
+fn completion_error(
+    status: StatusCode,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Option<LanguageModelCompletionError> {
+    let body = serde_json::from_slice::<serde_json::Value>(body).unwrap_or_default();
+    let error = body
+        .get("error")
+        .filter(|error| error.is_object())
+        .unwrap_or(&body);
+    let reasons = error
+        .get("details")
+        .and_then(|details| details.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|detail| detail.get("reason")?.as_str());
+    let codes = ["code", "type", "status"]
+        .into_iter()
+        .filter_map(|field| error.get(field)?.as_str())
+        .chain(reasons)
+        .collect::<Vec<_>>();
+    let has_code = |expected: &[&str]| codes.iter().any(|code| expected.contains(code));
+
+    if has_code(&["context_length_exceeded"]) {
+        Some(LanguageModelCompletionError::ContextLengthExceeded)
+    } else if status == StatusCode::UNAUTHORIZED
+        || has_code(&[
+            "invalid_api_key",
+            "authentication_error",
+            "UNAUTHENTICATED",
+            "API_KEY_INVALID",
+        ])
+    {
+        Some(LanguageModelCompletionError::InvalidApiKey)
+    } else if status == StatusCode::SERVICE_UNAVAILABLE
+        || status.as_u16() == 529
+        || has_code(&["overloaded_error", "UNAVAILABLE"])
+    {
+        Some(LanguageModelCompletionError::Overloaded)
+    } else if status == StatusCode::PAYMENT_REQUIRED || has_code(&["insufficient_quota"]) {
+        Some(LanguageModelCompletionError::QuotaExceeded)
+    } else if status == StatusCode::TOO_MANY_REQUESTS
+        || has_code(&[
+            "rate_limit_exceeded",
+            "rate_limit_error",
+            "RESOURCE_EXHAUSTED",
+        ])
+    {
+        Some(LanguageModelCompletionError::RateLimited {
+            retry_after: retry_after(headers),
+        })
+    } else {
+        None
+    }
+}
+
+/// Reads how long the provider asked to wait before retrying, from `retry-after-ms` or from
+/// `retry-after` in seconds or as an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    if let Some(millis) = headers
+        .get("retry-after-ms")
+        .and_then(|value| value.to_str().ok()?.trim().parse::<f64>().ok())
+    {
+        return Duration::try_from_secs_f64(millis / 1000.).ok();
+    }
+    let value = headers.get("retry-after")?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(secs).ok();
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now())
+        .to_std()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::{self, Stream, StreamExt as _, TryStreamExt as _};
+    use gpui::TestAppContext;
+    use http_client::FakeHttpClient;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+    fn event_stream(
+        chunks: impl Stream<Item = io::Result<Vec<u8>>> + Unpin + Send + Sync + 'static,
+    ) -> Response<AsyncBody> {
+        Response::builder()
+            .header(CONTENT_TYPE, "text/event-stream")
+            .body(AsyncBody::from_reader(chunks.into_async_read()))
+            .unwrap()
+    }
+
+    fn read_body(
+        response: Response<AsyncBody>,
+        executor: &BackgroundExecutor,
+    ) -> Task<(Vec<u8>, io::Result<usize>)> {
+        executor.spawn(async move {
+            let mut body = Vec::new();
+            let result = response.into_body().read_to_end(&mut body).await;
+            (body, result)
+        })
+    }
+
+    fn reconnect_counting(
+        connections: Arc<AtomicUsize>,
+    ) -> Option<(Request<AsyncBody>, Arc<dyn HttpClient>)> {
+        let client: Arc<dyn HttpClient> = FakeHttpClient::create(move |_| {
+            connections.fetch_add(1, Ordering::SeqCst);
+            async move {
+                Ok(event_stream(stream::iter([Ok(
+                    b"data: reconnected\n\n".to_vec()
+                )])))
+            }
+        });
+        Some((Request::new(AsyncBody::from("{}")), client))
+    }
+
+    #[gpui::test]
+    async fn test_reconnect_when_stalled_before_first_byte(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let stalled_connection = Arc::new(());
+        let is_stalled_connection_open = Arc::downgrade(&stalled_connection);
+        let response = event_stream(stream::pending::<io::Result<Vec<u8>>>().map(move |chunk| {
+            let _ = &stalled_connection;
+            chunk
+        }));
+        let open_on_reconnect = Arc::new(Mutex::new(Vec::new()));
+        let client: Arc<dyn HttpClient> = FakeHttpClient::create({
+            let open_on_reconnect = open_on_reconnect.clone();
+            move |_| {
+                open_on_reconnect
+                    .lock()
+                    .push(is_stalled_connection_open.upgrade().is_some());
+                async move { Ok(event_stream(stream::iter([Ok(b"data: done\n\n".to_vec())]))) }
+            }
+        });
+
+        let response = watch_for_stalls(
+            response,
+            Some((Request::new(AsyncBody::from("{}")), client)),
+            executor.clone(),
+            IDLE_TIMEOUT,
+        );
+        let body = read_body(response, &executor);
+        executor.run_until_parked();
+        assert!(open_on_reconnect.lock().is_empty());
+
+        executor.advance_clock(IDLE_TIMEOUT);
+        executor.run_until_parked();
+        let (body, result) = body.await;
+        assert!(result.is_ok());
+        assert_eq!(body, b"data: done\n\n");
+        assert_eq!(*open_on_reconnect.lock(), vec![false]);
+    }
+
+    #[gpui::test]
+    async fn test_idle_timeout_after_data(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let response = event_stream(
+            stream::iter([Ok(b"data: partial\n\n".to_vec())]).chain(stream::pending()),
+        );
+
+        let response = watch_for_stalls(
+            response,
+            reconnect_counting(connections.clone()),
+            executor.clone(),
+            IDLE_TIMEOUT,
+        );
+        let body = read_body(response, &executor);
+        executor.run_until_parked();
+        executor.advance_clock(IDLE_TIMEOUT);
+        executor.run_until_parked();
+        let (body, result) = body.await;
+        assert_eq!(body, b"data: partial\n\n");
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+    }
+
+    #[gpui::test]
+    async fn test_body_error_after_data(cx: &mut TestAppContext) {
+        let executor = cx.executor();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let response = event_stream(stream::iter([
+            Ok(b"data: partial\n\n".to_vec()),
+            Err(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                "connection reset",
+            )),
+        ]));
+
+        let response = watch_for_stalls(
+            response,
+            reconnect_counting(connections.clone()),
+            executor.clone(),
+            IDLE_TIMEOUT,
+        );
+        let body = read_body(response, &executor);
+        executor.run_until_parked();
+        let (body, result) = body.await;
+        assert_eq!(body, b"data: partial\n\n");
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(connections.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_with_query_params() {
+        let query_params = [("api-version".to_string(), "2024-10-21".to_string())];
+        let uri = Uri::from_static("https://example.openai.azure.com/openai/chat/completions");
+        assert_eq!(
+            with_query_params(&uri, &query_params).unwrap().to_string(),
+            "https://example.openai.azure.com/openai/chat/completions?api-version=2024-10-21"
+        );
+
+        let uri = Uri::from_static("https://example.com/v1/models?api-version=old&key=abc");
+        assert_eq!(
+            with_query_params(&uri, &query_params).unwrap().to_string(),
+            "https://example.com/v1/models?key=abc&api-version=2024-10-21"
+        );
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let response = |status: u16, headers: &[(&'static str, &'static str)]| {
+            let mut response = Response::builder().status(status);
+            for (name, value) in headers {
+                response = response.header(*name, *value);
+            }
+            response.body(AsyncBody::empty()).unwrap()
+        };
+
+        assert_eq!(retry_delay(&response(400, &[]), b"", 0), None);
+        assert_eq!(
+            retry_delay(&response(429, &[("retry-after", "7")]), b"", 0),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(
+            retry_delay(&response(529, &[("retry-after-ms", "250")]), b"", 0),
+            Some(Duration::from_millis(250))
+        );
+        assert_eq!(
+            retry_delay(&response(503, &[("retry-after", "3600")]), b"", 0),
+            None
+        );
+        assert_eq!(
+            retry_delay(&response(500, &[("x-should-retry", "false")]), b"", 0),
+            None
+        );
+        // Waiting doesn't give a key more quota.
+        assert_eq!(
+            retry_delay(
+                &response(429, &[("retry-after", "7")]),
+                br#"{"error":{"message":"You exceeded your current quota.","type":"insufficient_quota","code":"insufficient_quota"}}"#,
+                0
+            ),
+            None
+        );
+
+        let backoff = retry_delay(&response(500, &[]), b"", 2).unwrap();
+        assert!(backoff >= Duration::from_secs(2) && backoff <= Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_completion_error() {
+        let error = |status: u16, headers: &[(&'static str, &'static str)], body: &str| {
+            let mut header_map = HeaderMap::new();
+            for (name, value) in headers {
+                header_map.insert(*name, HeaderValue::from_static(*value));
+            }
+            let status = StatusCode::from_u16(status).unwrap();
+            completion_error(status, &header_map, body.as_bytes())
+        };
+
+        assert!(matches!(
+            error(
+                400,
+                &[],
+                r#"{"error":{"message":"This model's maximum context length is 128000 tokens.","type":"invalid_request_error","code":"context_length_exceeded"}}"#
+            ),
+            Some(LanguageModelCompletionError::ContextLengthExceeded)
+        ));
+        assert!(matches!(
+            error(
+                429,
+                &[("retry-after", "20")],
+                r#"{"type":"error","error":{"type":"rate_limit_error","message":"Too many requests"}}"#
+            ),
+            Some(LanguageModelCompletionError::RateLimited { retry_after: Some(after) })
+                if after == Duration::from_secs(20)
+        ));
+        assert!(matches!(
+            error(
+                400,
+                &[],
+                r#"{"error":{"code":400,"message":"API key not valid.","status":"INVALID_ARGUMENT","details":[{"reason":"API_KEY_INVALID"}]}}"#
+            ),
+            Some(LanguageModelCompletionError::InvalidApiKey)
+        ));
+        assert!(matches!(
+            error(529, &[], "overloaded"),
+            Some(LanguageModelCompletionError::Overloaded)
+        ));
+        assert!(matches!(
+            error(
+                429,
+                &[],
+                r#"{"error":{"message":"You exceeded your current quota.","code":"insufficient_quota"}}"#
+            ),
+            Some(LanguageModelCompletionError::QuotaExceeded)
+        ));
+        assert!(matches!(
+            error(
+                402,
+                &[],
+                r#"{"error":{"message":"Insufficient Balance","type":"unknown_error"}}"#
+            ),
+            Some(LanguageModelCompletionError::QuotaExceeded)
+        ));
+        assert!(matches!(
+            error(400, &[], r#"{"error":{"message":"Invalid tool schema"}}"#),
+            None
+        ));
+    }
+}
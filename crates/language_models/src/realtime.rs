@@ -0,0 +1,224 @@
+use std::pin::Pin;
+use std::str::FromStr as _;
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result, anyhow};
+use async_tungstenite::tungstenite::{
+    Message,
+    client::IntoClientRequest,
+    http::{HeaderName, HeaderValue},
+    protocol::{CloseFrame, frame::coding::CloseCode},
+};
+use client::{AsyncReadWrite, connect_proxy_stream};
+use futures::stream::BoxStream;
+use futures::{Sink, SinkExt as _, StreamExt as _};
+use open_ai::realtime::{ClientEvent, ServerEvent};
+use tokio::net::TcpStream;
+use url::Url;
+
+use crate::ProviderApiSettings;
+use crate::provider_http_client::{set_query_params, tls_config};
+
+/// A WebSocket session with a realtime API, like OpenAI's Realtime API, which streams text and
+/// audio both ways over one connection instead of a request per response.
+///
+/// Sessions last until they're closed, and keep their conversation between responses.
+pub struct RealtimeSession {
+    sink: Pin<Box<dyn Sink<Message, Error = anyhow::Error> + Send>>,
+    events: BoxStream<'static, Result<Message>>,
+    /// The assistant's audio that's streaming in, as `(item_id, content_index)`, which is cut off
+    /// when the user interrupts.
+    current_audio: Option<(String, usize)>,
+}
+
+impl RealtimeSession {
+    /// Connects to the `wss://` or `ws://` endpoint at `url` with `headers`, like
+    /// `Authorization`, and the provider's `proxy`, certificates, `headers`, and `query_params`
+    /// from `settings`. Without a `proxy` in `settings`, the connection goes through
+    /// `default_proxy`, like Zed's.
+    ///
+    /// The socket is driven by the Tokio runtime that `tokio` is a handle to.
+    pub async fn connect(
+        url: &str,
+        headers: &[(&'static str, String)],
+        settings: &ProviderApiSettings,
+        default_proxy: Option<Url>,
+        tokio: tokio::runtime::Handle,
+    ) -> Result<Self> {
+        let mut url = Url::parse(url).with_context(|| format!("invalid realtime url {url}"))?;
+        if !settings.query_params.is_empty() {
+            let query_params = settings
+                .query_params
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect::<Vec<_>>();
+            set_query_params(&mut url, &query_params);
+        }
+        let (host, port) = url
+            .host_str()
+            .map(str::to_string)
+            .zip(url.port_or_known_default())
+            .with_context(|| format!("missing host in realtime url {url}"))?;
+        let proxy = match settings.proxy.as_deref() {
+            Some(proxy) => Some(
+                Url::parse(proxy)
+                    .with_context(|| format!("invalid language model provider proxy {proxy}"))?,
+            ),
+            None => default_proxy,
+        };
+        let tls_config = tls_config(settings)?;
+
+        let mut request = IntoClientRequest::into_client_request(url.as_str())?;
+        let request_headers = request.headers_mut();
+        for (name, value) in headers {
+            request_headers.insert(*name, HeaderValue::from_str(value)?);
+        }
+        for (name, value) in &settings.headers {
+            request_headers.insert(
+                HeaderName::from_str(name)
+                    .with_context(|| format!("invalid language model request header {name:?}"))?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+
+        let stream = tokio
+            .spawn(async move {
+                let stream: Box<dyn AsyncReadWrite> = match proxy {
+                    Some(proxy) => connect_proxy_stream(&proxy, (host.as_str(), port)).await?,
+                    None => Box::new(TcpStream::connect((host.as_str(), port)).await?),
+                };
+                let (stream, _) =
+                    async_tungstenite::tokio::client_async_tls_with_connector_and_config(
+                        request,
+                        stream,
+                        Some(Arc::new(tls_config).into()),
+                        None,
+                    )
+                    .await?;
+                anyhow::Ok(stream)
+            })
+            .await?
+            .context("failed to open realtime session")?;
+        let (sink, events) = stream.split();
+        Ok(Self {
+            sink: Box::pin(sink.sink_map_err(|error| anyhow!(error))),
+            events: events.map(|message| Ok(message?)).boxed(),
+            current_audio: None,
+        })
+    }
+
+    pub async fn send(&mut self, event: &ClientEvent) -> Result<()> {
+        let event = serde_json::to_string(event)?;
+        self.sink.send(Message::Text(event.into())).await
+    }
+
+    /// The next event from the server, or `None` once the session is closed.
+    pub async fn next_event(&mut self) -> Option<Result<ServerEvent>> {
+        loop {
+            let message = match self.events.next().await? {
+                Ok(message) => message,
+                Err(error) => return Some(Err(error)),
+            };
+            let event = match message {
+                Message::Text(text) => serde_json::from_str::<ServerEvent>(&text),
+                Message::Binary(bytes) => serde_json::from_slice::<ServerEvent>(&bytes),
+                Message::Close(frame) => {
+                    return frame
+                        .filter(|frame| !frame.reason.is_empty())
+                        .map(|frame| Err(anyhow!("realtime session closed: {}", frame.reason)));
+                }
+                // Pings are answered by the WebSocket itself.
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => continue,
+            };
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => return Some(Err(anyhow!(error))),
+            };
+            match &event {
+                ServerEvent::AudioDelta {
+                    item_id,
+                    content_index,
+                    ..
+                } => self.current_audio = Some((item_id.clone(), *content_index)),
+                ServerEvent::ResponseDone { .. } => self.current_audio = None,
+                _ => {}
+            }
+            return Some(Ok(event));
+        }
+    }
+
+    pub async fn send_text(&mut self, text: String) -> Result<()> {
+        self.send(&ClientEvent::ConversationItemCreate {
+            item: open_ai::realtime::ConversationItem::Message {
+                role: open_ai::Role::User,
+                content: vec![open_ai::realtime::ItemContent::InputText { text }],
+            },
+        })
+        .await?;
+        self.send(&ClientEvent::ResponseCreate { response: None })
+            .await
+    }
+
+    /// Streams base64-encoded audio of what the user is saying, in the session's input format.
+    pub async fn append_audio(&mut self, audio: String) -> Result<()> {
+        self.send(&ClientEvent::InputAudioBufferAppend { audio })
+            .await
+    }
+
+    /// Ends what the user is saying and asks for a response, for sessions without turn
+    /// detection.
+    pub async fn commit_audio(&mut self) -> Result<()> {
+        self.send(&ClientEvent::InputAudioBufferCommit).await?;
+        self.send(&ClientEvent::ResponseCreate { response: None })
+            .await
+    }
+
+    /// Stops the response that's streaming in, like when the user starts talking over it.
+    ///
+    /// `audio_end_ms` is how much of the assistant's audio was played, so that what wasn't heard
+    /// is dropped from the conversation.
+    pub async fn interrupt(&mut self, audio_end_ms: Option<u64>) -> Result<()> {
+        self.send(&ClientEvent::ResponseCancel).await?;
+        if let Some(((item_id, content_index), audio_end_ms)) =
+            self.current_audio.take().zip(audio_end_ms)
+        {
+            self.send(&ClientEvent::ConversationItemTruncate {
+                item_id,
+                content_index,
+                audio_end_ms,
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn close(mut self) -> Result<()> {
+        self.sink
+            .send(Message::Close(Some(CloseFrame {
+                code: CloseCode::Normal,
+                reason: "".into(),
+            })))
+            .await?;
+        self.sink.close().await
+    }
+
+    /// The events of the response that was just asked for, through the one that finishes it, after
+    /// which the session is closed.
+    pub fn into_response_events(self) -> BoxStream<'static, Result<ServerEvent>> {
+        futures::stream::unfold(Some(self), |session| async move {
+            let mut session = session?;
+            let event = session.next_event().await?;
+            let finished = matches!(
+                event,
+                Ok(ServerEvent::ResponseDone { .. } | ServerEvent::Error { .. }) | Err(_)
+            );
+            if finished {
+                session.close().await.ok();
+                Some((event, None))
+            } else {
+                Some((event, Some(session)))
+            }
+        })
+        .boxed()
+    }
+}
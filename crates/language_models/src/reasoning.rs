@@ -0,0 +1,263 @@
+use collections::HashMap;
+use futures::StreamExt as _;
+use futures::stream::{self, BoxStream};
+use language_model::{LanguageModelCompletionError, LanguageModelCompletionEvent};
+
+/// A rough number of bytes per token, used to estimate reasoning tokens when the provider doesn't
+/// report them.
+const BYTES_PER_TOKEN: usize = 4;
+
+const THINK_START_TAG: &str = "<think>";
+const THINK_END_TAG: &str = "</think>";
+
+/// Normalizes the reasoning in a completion into [`LanguageModelCompletionEvent::Thinking`]
+/// events.
+///
+/// Reasoning models served by OpenAI-compatible hosts, like Qwen and DeepSeek R1, often open their
+/// response with their reasoning wrapped in `<think>` tags instead of reporting it separately. That
+/// reasoning is moved from the text into thinking events. Only a `<think>` tag at the start of the
+/// response is recognized, so that tags which are part of the answer are left alone.
+///
+/// When the provider reports usage without saying how many tokens went to reasoning, they're
+/// estimated from the thinking that was streamed.
+pub(crate) fn normalize_reasoning(
+    events: BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+) -> BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>> {
+    stream::unfold(
+        Some((events, ReasoningNormalizer::default())),
+        |state| async move {
+            let (mut events, mut normalizer) = state?;
+            match events.next().await {
+                Some(Ok(event)) => {
+                    let events_out = normalizer.push_event(event).into_iter().map(Ok);
+                    Some((events_out.collect::<Vec<_>>(), Some((events, normalizer))))
+                }
+                Some(Err(error)) => Some((vec![Err(error)], Some((events, normalizer)))),
+                None => Some((normalizer.finish().into_iter().map(Ok).collect(), None)),
+            }
+        },
+    )
+    .flat_map(stream::iter)
+    .boxed()
+}
+
+#[derive(Default)]
+struct ReasoningNormalizer {
+    /// The think tag parser of each candidate completion.
+    parsers: HashMap<u32, ThinkTagParser>,
+    candidate: u32,
+    reasoning_bytes: usize,
+}
+
+impl ReasoningNormalizer {
+    fn push_event(
+        &mut self,
+        event: LanguageModelCompletionEvent,
+    ) -> Vec<LanguageModelCompletionEvent> {
+        let mut events = match event {
+            LanguageModelCompletionEvent::Text(text) => {
+                self.parsers.entry(self.candidate).or_default().push(&text)
+            }
+            LanguageModelCompletionEvent::Candidate { index } => {
+                self.candidate = index;
+                vec![event]
+            }
+            LanguageModelCompletionEvent::Stop(_) => {
+                let mut events = self
+                    .parsers
+                    .get_mut(&self.candidate)
+                    .and_then(ThinkTagParser::finish)
+                    .into_iter()
+                    .collect::<Vec<_>>();
+                events.push(event);
+                events
+            }
+            LanguageModelCompletionEvent::UsageUpdate(mut usage) => {
+                if usage.reasoning_tokens == 0 {
+                    usage.reasoning_tokens =
+                        ((self.reasoning_bytes / BYTES_PER_TOKEN) as u32).min(usage.output_tokens);
+                }
+                vec![LanguageModelCompletionEvent::UsageUpdate(usage)]
+            }
+            event => vec![event],
+        };
+        for event in &mut events {
+            if let LanguageModelCompletionEvent::Thinking { text, .. } = event {
+                self.reasoning_bytes += text.len();
+            }
+        }
+        events
+    }
+
+    /// The text that was held back when the completion ended.
+    fn finish(&mut self) -> Vec<LanguageModelCompletionEvent> {
+        let mut parsers = self.parsers.drain().collect::<Vec<_>>();
+        parsers.sort_by_key(|(candidate, _)| *candidate);
+        let mut events = Vec::new();
+        for (candidate, mut parser) in parsers {
+            let Some(event) = parser.finish() else {
+                continue;
+            };
+            if candidate != self.candidate {
+                self.candidate = candidate;
+                events.push(LanguageModelCompletionEvent::Candidate { index: candidate });
+            }
+            events.push(event);
+        }
+        events
+    }
+}
+
+#[derive(Default)]
+enum ThinkTagState {
+    /// Nothing but whitespace and what may be the start of a `<think>` tag has been streamed.
+    #[default]
+    Start,
+    Thinking,
+    /// The `</think>` tag was just streamed, and the whitespace after it is skipped.
+    AfterThinking,
+    Text,
+}
+
+#[derive(Default)]
+struct ThinkTagParser {
+    state: ThinkTagState,
+    /// Streamed text that hasn't been emitted yet, because it could be part of a tag.
+    pending: String,
+}
+
+impl ThinkTagParser {
+    fn push(&mut self, text: &str) -> Vec<LanguageModelCompletionEvent> {
+        self.pending.push_str(text);
+        let mut events = Vec::new();
+        loop {
+            match self.state {
+                ThinkTagState::Start => {
+                    let trimmed = self.pending.trim_start();
+                    if let Some(thinking) = trimmed.strip_prefix(THINK_START_TAG) {
+                        self.pending = thinking.to_string();
+                        self.state = ThinkTagState::Thinking;
+                    } else if THINK_START_TAG.starts_with(trimmed) {
+                        break;
+                    } else {
+                        self.state = ThinkTagState::Text;
+                    }
+                }
+                ThinkTagState::Thinking => {
+                    if let Some(end) = self.pending.find(THINK_END_TAG) {
+                        let thinking = self.pending[..end].to_string();
+                        self.pending.drain(..end + THINK_END_TAG.len());
+                        events.extend(thinking_event(thinking));
+                        self.state = ThinkTagState::AfterThinking;
+                    } else {
+                        let held_back = partial_suffix_len(&self.pending, THINK_END_TAG);
+                        let thinking = self
+                            .pending
+                            .drain(..self.pending.len() - held_back)
+                            .collect::<String>();
+                        events.extend(thinking_event(thinking));
+                        break;
+                    }
+                }
+                ThinkTagState::AfterThinking => {
+                    let trimmed = self.pending.trim_start();
+                    if trimmed.is_empty() {
+                        self.pending.clear();
+                        break;
+                    }
+                    self.pending = trimmed.to_string();
+                    self.state = ThinkTagState::Text;
+                }
+                ThinkTagState::Text => {
+                    if !self.pending.is_empty() {
+                        events.push(LanguageModelCompletionEvent::Text(std::mem::take(
+                            &mut self.pending,
+                        )));
+                    }
+                    break;
+                }
+            }
+        }
+        events
+    }
+
+    fn finish(&mut self) -> Option<LanguageModelCompletionEvent> {
+        let pending = std::mem::take(&mut self.pending);
+        match self.state {
+            ThinkTagState::Thinking => thinking_event(pending),
+            ThinkTagState::Start | ThinkTagState::Text if !pending.is_empty() => {
+                Some(LanguageModelCompletionEvent::Text(pending))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn thinking_event(text: String) -> Option<LanguageModelCompletionEvent> {
+    (!text.is_empty()).then_some(LanguageModelCompletionEvent::Thinking {
+        text,
+        signature: None,
+    })
+}
+
+/// The length of the longest suffix of `text` that `tag` starts with.
+fn partial_suffix_len(text: &str, tag: &str) -> usize {
+    (1..tag.len())
+        .rev()
+        .find(|&len| text.ends_with(&tag[..len]))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use language_model::{StopReason, TokenUsage};
+
+    use super::*;
+
+    #[test]
+    fn test_normalize_reasoning() {
+        let mut normalizer = ReasoningNormalizer::default();
+        let mut events = Vec::new();
+        for chunk in [
+            "\n<thi",
+            "nk>Let me ",
+            "think.</th",
+            "ink>\n\n",
+            "The answer",
+            " is <think>.",
+        ] {
+            events.extend(normalizer.push_event(LanguageModelCompletionEvent::Text(chunk.into())));
+        }
+        events.extend(
+            normalizer.push_event(LanguageModelCompletionEvent::UsageUpdate(TokenUsage {
+                output_tokens: 20,
+                ..Default::default()
+            })),
+        );
+        events
+            .extend(normalizer.push_event(LanguageModelCompletionEvent::Stop(StopReason::EndTurn)));
+        events.extend(normalizer.finish());
+
+        assert_eq!(
+            events,
+            vec![
+                LanguageModelCompletionEvent::Thinking {
+                    text: "Let me ".into(),
+                    signature: None,
+                },
+                LanguageModelCompletionEvent::Thinking {
+                    text: "think.".into(),
+                    signature: None,
+                },
+                LanguageModelCompletionEvent::Text("The answer".into()),
+                LanguageModelCompletionEvent::Text(" is <think>.".into()),
+                LanguageModelCompletionEvent::UsageUpdate(TokenUsage {
+                    output_tokens: 20,
+                    reasoning_tokens: 3,
+                    ..Default::default()
+                }),
+                LanguageModelCompletionEvent::Stop(StopReason::EndTurn),
+            ]
+        );
+    }
+}
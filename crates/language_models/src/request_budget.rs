@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// The requests sent to a provider in the last minute, for holding back requests that would go
+/// over its `requests_per_minute` or `tokens_per_minute` until enough earlier ones have aged out.
+#[derive(Default)]
+pub(crate) struct RequestBudget {
+    sent: VecDeque<(Instant, u64)>,
+}
+
+impl RequestBudget {
+    /// Records a request of about `tokens` tokens as sent at `now` if it fits within the limits,
+    /// or returns how long to wait before trying again. A request larger than `tokens_per_minute`
+    /// is let through once nothing else was sent in the last minute, so that it can't wait forever.
+    pub(crate) fn try_reserve(
+        &mut self,
+        tokens: u64,
+        requests_per_minute: Option<u32>,
+        tokens_per_minute: Option<u64>,
+        now: Instant,
+    ) -> Result<(), Duration> {
+        while let Some((sent_at, _)) = self.sent.front() {
+            if now.saturating_duration_since(*sent_at) < WINDOW {
+                break;
+            }
+            self.sent.pop_front();
+        }
+
+        let over_requests =
+            requests_per_minute.is_some_and(|limit| self.sent.len() >= limit as usize);
+        let used_tokens = self.sent.iter().map(|(_, tokens)| tokens).sum::<u64>();
+        let over_tokens = tokens_per_minute
+            .is_some_and(|limit| !self.sent.is_empty() && used_tokens + tokens > limit);
+        if over_requests || over_tokens {
+            if let Some((oldest, _)) = self.sent.front() {
+                return Err((*oldest + WINDOW).saturating_duration_since(now));
+            }
+        }
+
+        self.sent.push_back((now, tokens));
+        Ok(())
+    }
+}
+
+/// Roughly how many tokens a request body of `len` bytes uses, at about 4 bytes per token.
+pub(crate) fn estimate_tokens(len: usize) -> u64 {
+    (len as u64).div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_budget() {
+        let start = Instant::now();
+        let mut budget = RequestBudget::default();
+        assert_eq!(budget.try_reserve(10, Some(2), None, start), Ok(()));
+        assert_eq!(
+            budget.try_reserve(10, Some(2), None, start + Duration::from_secs(20)),
+            Ok(())
+        );
+        assert_eq!(
+            budget.try_reserve(10, Some(2), None, start + Duration::from_secs(30)),
+            Err(Duration::from_secs(30))
+        );
+        assert_eq!(
+            budget.try_reserve(10, Some(2), None, start + Duration::from_secs(60)),
+            Ok(())
+        );
+
+        let mut budget = RequestBudget::default();
+        assert_eq!(budget.try_reserve(5_000, None, Some(1_000), start), Ok(()));
+        assert_eq!(
+            budget.try_reserve(100, None, Some(1_000), start + Duration::from_secs(45)),
+            Err(Duration::from_secs(15))
+        );
+        assert_eq!(
+            budget.try_reserve(600, None, Some(1_000), start + Duration::from_secs(61)),
+            Ok(())
+        );
+        assert_eq!(
+            budget.try_reserve(400, None, Some(1_000), start + Duration::from_secs(62)),
+            Ok(())
+        );
+        assert_eq!(
+            budget.try_reserve(1, None, Some(1_000), start + Duration::from_secs(63)),
+            Err(Duration::from_secs(58))
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::{AsyncReadExt as _, FutureExt as _};
+use gpui::{App, AsyncApp};
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings as _;
+
+use crate::AllLanguageModelSettings;
+use crate::service_registry::{Service, ServiceRegistry};
+
+mod cohere;
+mod jina;
+mod voyage;
+
+pub use cohere::CohereRerankProvider;
+pub use jina::JinaRerankProvider;
+pub use voyage::VoyageRerankProvider;
+
+/// Which service reorders retrieved documents by how relevant they are to a query.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct RerankSettings {
+    /// The service to rerank documents with: `cohere`, `voyage`, or `jina`.
+    ///
+    /// Default: "cohere"
+    pub provider: Option<String>,
+    /// The model to rerank documents with, such as `rerank-v3.5`, `rerank-2`, or
+    /// `jina-reranker-v2-base-multilingual`.
+    ///
+    /// Default: the provider's default model
+    pub model: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RerankRequest {
+    pub query: String,
+    pub documents: Vec<String>,
+    /// How many of the most relevant documents to return, or all of them.
+    pub top_n: Option<usize>,
+    /// The model to rerank with, or the provider's default model.
+    pub model: Option<String>,
+}
+
+/// A document's position in [`RerankRequest::documents`] and how relevant it is to the query.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct RankedDocument {
+    pub index: usize,
+    pub relevance_score: f32,
+}
+
+/// A service that orders documents by how relevant they are to a query.
+pub trait RerankProvider: Service {
+    /// Ranks the request's documents, most relevant first.
+    fn rerank(
+        &self,
+        request: RerankRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Vec<RankedDocument>>>;
+}
+
+/// The services that can rerank documents.
+pub type RerankProviders = ServiceRegistry<dyn RerankProvider>;
+
+impl RerankProviders {
+    /// Ranks `documents` by their relevance to `query` with the provider and model chosen in
+    /// the `rerank` settings.
+    pub fn rerank(
+        query: String,
+        documents: Vec<String>,
+        top_n: Option<usize>,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Vec<RankedDocument>>> {
+        let provider = cx.update(|cx| {
+            let settings = AllLanguageModelSettings::get_global(cx).rerank.clone();
+            let provider = Self::global(cx).chosen(
+                settings.provider.as_deref(),
+                cohere::PROVIDER_ID,
+                "rerank",
+            )?;
+            anyhow::Ok((provider, settings))
+        });
+        let (provider, settings) = match provider.and_then(|provider| provider) {
+            Ok(provider) => provider,
+            Err(error) => return futures::future::ready(Err(error)).boxed(),
+        };
+        if documents.is_empty() {
+            return futures::future::ready(Ok(Vec::new())).boxed();
+        }
+        provider.rerank(
+            RerankRequest {
+                query,
+                documents,
+                top_n,
+                model: settings.model,
+            },
+            cx,
+        )
+    }
+}
+
+pub(crate) fn init(http_client: Arc<dyn HttpClient>, cx: &mut App) {
+    RerankProviders::set_global(
+        vec![
+            Arc::new(CohereRerankProvider::new(http_client.clone())),
+            Arc::new(VoyageRerankProvider::new(http_client.clone())),
+            Arc::new(JinaRerankProvider::new(http_client)),
+        ],
+        cx,
+    );
+}
+
+/// Posts `body` to a rerank endpoint, returning the response's body. Cohere, Voyage, and Jina
+/// all take their keys as bearer tokens.
+async fn send_rerank_request(
+    http_client: &dyn HttpClient,
+    uri: &str,
+    api_key: &str,
+    body: &impl Serialize,
+    service_name: &str,
+) -> Result<String> {
+    let http_request = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {api_key}"))
+        .body(AsyncBody::from(serde_json::to_string(body)?))?;
+    let mut response = http_client.send(http_request).await?;
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    anyhow::ensure!(
+        response.status().is_success(),
+        "Failed to rerank documents with {service_name}: {} {}",
+        response.status(),
+        body,
+    );
+    Ok(body)
+}
+
+/// Orders `documents` from most to least relevant, since not every service sorts them.
+fn most_relevant_first(mut documents: Vec<RankedDocument>) -> Vec<RankedDocument> {
+    documents.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+    documents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_most_relevant_first() {
+        let documents = most_relevant_first(vec![
+            RankedDocument {
+                index: 0,
+                relevance_score: 0.2,
+            },
+            RankedDocument {
+                index: 1,
+                relevance_score: 0.9,
+            },
+            RankedDocument {
+                index: 2,
+                relevance_score: 0.5,
+            },
+        ]);
+        let indices = documents
+            .iter()
+            .map(|document| document.index)
+            .collect::<Vec<_>>();
+        assert_eq!(indices, [1, 2, 0]);
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result, anyhow};
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::AsyncApp;
+use http_client::HttpClient;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    RankedDocument, RerankProvider, RerankRequest, most_relevant_first, send_rerank_request,
+};
+use crate::api_key::ServiceApiKeys;
+use crate::service_registry::Service;
+
+pub(crate) const PROVIDER_ID: &str = "cohere";
+const API_URL: &str = "https://api.cohere.com";
+const API_KEY_VAR: &str = "COHERE_API_KEY";
+const DEFAULT_MODEL: &str = "rerank-v3.5";
+
+#[derive(Serialize)]
+struct Request<'a> {
+    model: &'a str,
+    query: &'a str,
+    documents: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_n: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    results: Vec<RankedDocument>,
+}
+
+/// Reranks documents with Cohere Rerank, whose key is read from `COHERE_API_KEY` or the
+/// credentials store.
+pub struct CohereRerankProvider {
+    http_client: Arc<dyn HttpClient>,
+    api_keys: ServiceApiKeys,
+}
+
+impl CohereRerankProvider {
+    pub fn new(http_client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            http_client,
+            api_keys: ServiceApiKeys::new(API_KEY_VAR, PROVIDER_ID, API_URL),
+        }
+    }
+}
+
+impl Service for CohereRerankProvider {
+    fn id(&self) -> &'static str {
+        PROVIDER_ID
+    }
+
+    fn name(&self) -> &'static str {
+        "Cohere"
+    }
+}
+
+impl RerankProvider for CohereRerankProvider {
+    fn rerank(
+        &self,
+        request: RerankRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Vec<RankedDocument>>> {
+        let http_client = self.http_client.clone();
+        let api_keys = self.api_keys.load(cx);
+        async move {
+            let api_keys = api_keys.await?;
+            let api_key = api_keys
+                .current()
+                .ok_or_else(|| anyhow!("Missing Cohere API Key"))?;
+            let body = send_rerank_request(
+                http_client.as_ref(),
+                &format!("{API_URL}/v2/rerank"),
+                api_key,
+                &Request {
+                    model: request.model.as_deref().unwrap_or(DEFAULT_MODEL),
+                    query: &request.query,
+                    documents: &request.documents,
+                    top_n: request.top_n,
+                },
+                "Cohere",
+            )
+            .await?;
+            let response: Response =
+                serde_json::from_str(&body).context("failed to parse Cohere response")?;
+            Ok(most_relevant_first(response.results))
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result, anyhow};
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::AsyncApp;
+use http_client::HttpClient;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    RankedDocument, RerankProvider, RerankRequest, most_relevant_first, send_rerank_request,
+};
+use crate::api_key::ServiceApiKeys;
+use crate::service_registry::Service;
+
+const PROVIDER_ID: &str = "jina";
+const API_URL: &str = "https://api.jina.ai/v1";
+const API_KEY_VAR: &str = "JINA_API_KEY";
+const DEFAULT_MODEL: &str = "jina-reranker-v2-base-multilingual";
+
+#[derive(Serialize)]
+struct Request<'a> {
+    model: &'a str,
+    query: &'a str,
+    documents: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_n: Option<usize>,
+    /// Leaves the documents' text out of the response, since only their indices are needed.
+    return_documents: bool,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    results: Vec<RankedDocument>,
+}
+
+/// Reranks documents with Jina AI's rerankers, whose key is read from `JINA_API_KEY` or the
+/// credentials store.
+pub struct JinaRerankProvider {
+    http_client: Arc<dyn HttpClient>,
+    api_keys: ServiceApiKeys,
+}
+
+impl JinaRerankProvider {
+    pub fn new(http_client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            http_client,
+            api_keys: ServiceApiKeys::new(API_KEY_VAR, PROVIDER_ID, API_URL),
+        }
+    }
+}
+
+impl Service for JinaRerankProvider {
+    fn id(&self) -> &'static str {
+        PROVIDER_ID
+    }
+
+    fn name(&self) -> &'static str {
+        "Jina AI"
+    }
+}
+
+impl RerankProvider for JinaRerankProvider {
+    fn rerank(
+        &self,
+        request: RerankRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Vec<RankedDocument>>> {
+        let http_client = self.http_client.clone();
+        let api_keys = self.api_keys.load(cx);
+        async move {
+            let api_keys = api_keys.await?;
+            let api_key = api_keys
+                .current()
+                .ok_or_else(|| anyhow!("Missing Jina AI API Key"))?;
+            let body = send_rerank_request(
+                http_client.as_ref(),
+                &format!("{API_URL}/rerank"),
+                api_key,
+                &Request {
+                    model: request.model.as_deref().unwrap_or(DEFAULT_MODEL),
+                    query: &request.query,
+                    documents: &request.documents,
+                    top_n: request.top_n,
+                    return_documents: false,
+                },
+                "Jina AI",
+            )
+            .await?;
+            let response: Response =
+                serde_json::from_str(&body).context("failed to parse Jina AI response")?;
+            Ok(most_relevant_first(response.results))
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result, anyhow};
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::AsyncApp;
+use http_client::HttpClient;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    RankedDocument, RerankProvider, RerankRequest, most_relevant_first, send_rerank_request,
+};
+use crate::api_key::ServiceApiKeys;
+use crate::service_registry::Service;
+
+const PROVIDER_ID: &str = "voyage";
+const API_URL: &str = "https://api.voyageai.com/v1";
+const API_KEY_VAR: &str = "VOYAGE_API_KEY";
+const DEFAULT_MODEL: &str = "rerank-2";
+
+#[derive(Serialize)]
+struct Request<'a> {
+    model: &'a str,
+    query: &'a str,
+    documents: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_k: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    data: Vec<RankedDocument>,
+}
+
+/// Reranks documents with Voyage AI's rerankers, using the same key as its embedding models.
+pub struct VoyageRerankProvider {
+    http_client: Arc<dyn HttpClient>,
+    api_keys: ServiceApiKeys,
+}
+
+impl VoyageRerankProvider {
+    pub fn new(http_client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            http_client,
+            api_keys: ServiceApiKeys::new(API_KEY_VAR, PROVIDER_ID, API_URL),
+        }
+    }
+}
+
+impl Service for VoyageRerankProvider {
+    fn id(&self) -> &'static str {
+        PROVIDER_ID
+    }
+
+    fn name(&self) -> &'static str {
+        "Voyage AI"
+    }
+}
+
+impl RerankProvider for VoyageRerankProvider {
+    fn rerank(
+        &self,
+        request: RerankRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Vec<RankedDocument>>> {
+        let http_client = self.http_client.clone();
+        let api_keys = self.api_keys.load(cx);
+        async move {
+            let api_keys = api_keys.await?;
+            let api_key = api_keys
+                .current()
+                .ok_or_else(|| anyhow!("Missing Voyage AI API Key"))?;
+            let body = send_rerank_request(
+                http_client.as_ref(),
+                &format!("{API_URL}/rerank"),
+                api_key,
+                &Request {
+                    model: request.model.as_deref().unwrap_or(DEFAULT_MODEL),
+                    query: &request.query,
+                    documents: &request.documents,
+                    top_k: request.top_n,
+                },
+                "Voyage AI",
+            )
+            .await?;
+            let response: Response =
+                serde_json::from_str(&body).context("failed to parse Voyage AI response")?;
+            Ok(most_relevant_first(response.data))
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let body = r#"{
+            "object": "list",
+            "data": [
+                {"relevance_score": 0.4, "index": 1},
+                {"relevance_score": 0.8, "index": 0}
+            ],
+            "model": "rerank-2",
+            "usage": {"total_tokens": 26}
+        }"#;
+        let response: Response = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            response.data,
+            [
+                RankedDocument {
+                    index: 1,
+                    relevance_score: 0.4,
+                },
+                RankedDocument {
+                    index: 0,
+                    relevance_score: 0.8,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use collections::HashMap;
+use fs::{Fs, RemoveOptions};
+use futures::StreamExt as _;
+use futures::stream::BoxStream;
+use gpui::{App, AsyncApp, BackgroundExecutor, Global};
+use language_model::{
+    LanguageModelCompletionError, LanguageModelCompletionEvent, LanguageModelId,
+    LanguageModelProviderId, LanguageModelRequest,
+};
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings as _;
+use sha2::{Digest as _, Sha256};
+
+use crate::AllLanguageModelSettings;
+use crate::configure::ConfigurableProvider;
+use crate::provider::{lmstudio, ollama};
+
+const DEFAULT_TTL_HOURS: u64 = 24;
+const DEFAULT_MAX_SIZE_MB: u64 = 100;
+
+/// Replays the completions of requests identical to earlier ones instead of sending them again,
+/// which makes evals and demos repeatable and retries over a flaky network instant.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ResponseCacheSettings {
+    /// Whether to cache completions and replay them for identical requests.
+    ///
+    /// Default: false
+    pub enabled: Option<bool>,
+    /// How many hours a completion is replayed for.
+    ///
+    /// Default: 24
+    pub ttl_hours: Option<u64>,
+    /// How large the cache can grow, in megabytes, before the oldest completions are removed.
+    ///
+    /// Default: 100
+    pub max_size_mb: Option<u64>,
+}
+
+impl ResponseCacheSettings {
+    fn ttl(&self) -> Duration {
+        Duration::from_secs(self.ttl_hours.unwrap_or(DEFAULT_TTL_HOURS) * 60 * 60)
+    }
+
+    fn max_size(&self) -> u64 {
+        self.max_size_mb.unwrap_or(DEFAULT_MAX_SIZE_MB) * 1024 * 1024
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedCompletion {
+    created_at: SystemTime,
+    events: Vec<LanguageModelCompletionEvent>,
+}
+
+struct CacheFile {
+    created_at: SystemTime,
+    size: u64,
+}
+
+#[derive(Default)]
+struct ResponseCacheState {
+    /// The cached completions by key, which is the name of the file they're saved in.
+    files: HashMap<String, CacheFile>,
+}
+
+#[derive(Clone)]
+struct ResponseCache {
+    state: Arc<Mutex<ResponseCacheState>>,
+    fs: Arc<dyn Fs>,
+    executor: BackgroundExecutor,
+}
+
+struct GlobalResponseCache(ResponseCache);
+
+impl Global for GlobalResponseCache {}
+
+pub(crate) fn init(fs: Arc<dyn Fs>, cx: &mut App) {
+    let cache = ResponseCache {
+        state: Arc::default(),
+        fs,
+        executor: cx.background_executor().clone(),
+    };
+    cx.set_global(GlobalResponseCache(cache.clone()));
+    cx.background_spawn(async move {
+        let Ok(mut paths) = cache.fs.read_dir(&cache_dir()).await else {
+            return;
+        };
+        while let Some(path) = paths.next().await {
+            let Ok(path) = path else {
+                continue;
+            };
+            let Some(key) = path.file_stem().and_then(|stem| stem.to_str()).filter(|_| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            }) else {
+                continue;
+            };
+            if let Ok(Some(metadata)) = cache.fs.metadata(&path).await {
+                cache.state.lock().files.insert(
+                    key.to_string(),
+                    CacheFile {
+                        created_at: metadata.mtime.timestamp_for_user(),
+                        size: metadata.len,
+                    },
+                );
+            }
+        }
+    })
+    .detach();
+}
+
+fn cache_dir() -> PathBuf {
+    paths::data_dir().join("language_model_response_cache")
+}
+
+fn cache_file_path(key: &str) -> PathBuf {
+    cache_dir().join(format!("{key}.json"))
+}
+
+/// Identifies a request by everything that affects its completion, leaving out the thread and
+/// prompt it was made for, which differ between otherwise identical runs.
+fn cache_key(
+    provider: &LanguageModelProviderId,
+    model: &LanguageModelId,
+    endpoint: &str,
+    request: &LanguageModelRequest,
+) -> Option<String> {
+    let request = LanguageModelRequest {
+        thread_id: None,
+        prompt_id: None,
+        ..request.clone()
+    };
+    let request = serde_json::to_string(&request).ok()?;
+    let mut hasher = Sha256::new();
+    for part in [
+        provider.0.as_bytes(),
+        model.0.as_bytes(),
+        endpoint.as_bytes(),
+        request.as_bytes(),
+    ] {
+        hasher.update(part);
+        hasher.update([0]);
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// The URL that the provider sends requests to, along with the `headers` and `query_params` that
+/// the settings add to them, since a gateway can route the same model name to another deployment
+/// by any of them.
+fn endpoint(
+    provider: &LanguageModelProviderId,
+    settings: &AllLanguageModelSettings,
+) -> Option<String> {
+    let (api_url, api_settings) = match provider.0.as_ref() {
+        ollama::PROVIDER_ID => (settings.ollama.api_url.as_str(), None),
+        lmstudio::PROVIDER_ID => (settings.lmstudio.api_url.as_str(), None),
+        id => match ConfigurableProvider::find(id) {
+            Some(provider) => (
+                provider.api_url(settings),
+                Some(provider.api_settings(settings)),
+            ),
+            None => ("", None),
+        },
+    };
+    let headers = api_settings
+        .map(|api_settings| api_settings.headers.iter().collect::<BTreeMap<_, _>>())
+        .unwrap_or_default();
+    let query_params = api_settings
+        .map(|api_settings| api_settings.query_params.iter().collect::<BTreeMap<_, _>>())
+        .unwrap_or_default();
+    serde_json::to_string(&(api_url, headers, query_params)).ok()
+}
+
+/// Whether an event is replayed from the cache. Usage isn't, so that replayed completions don't
+/// count towards what the provider is paid.
+fn is_replayed(event: &LanguageModelCompletionEvent) -> bool {
+    match event {
+        LanguageModelCompletionEvent::Text(_)
+        | LanguageModelCompletionEvent::Thinking { .. }
+        | LanguageModelCompletionEvent::RedactedThinking { .. }
+        | LanguageModelCompletionEvent::ToolUse(_)
+        | LanguageModelCompletionEvent::StartMessage { .. }
+        | LanguageModelCompletionEvent::Candidate { .. }
+        | LanguageModelCompletionEvent::Logprobs(_)
+        | LanguageModelCompletionEvent::Citations(_)
+        | LanguageModelCompletionEvent::GuardrailIntervention(_)
+        | LanguageModelCompletionEvent::ExecutableCode { .. }
+        | LanguageModelCompletionEvent::CodeExecutionResult { .. }
+        | LanguageModelCompletionEvent::Stop(_) => true,
+        LanguageModelCompletionEvent::UsageUpdate(_)
+        | LanguageModelCompletionEvent::StatusUpdate(_)
+        | LanguageModelCompletionEvent::Fallback { .. }
+        | LanguageModelCompletionEvent::UploadProgress { .. } => false,
+    }
+}
+
+impl ResponseCache {
+    async fn load(&self, key: &str, ttl: Duration) -> Option<Vec<LanguageModelCompletionEvent>> {
+        let created_at = self.state.lock().files.get(key)?.created_at;
+        if !created_at.elapsed().is_ok_and(|age| age <= ttl) {
+            return None;
+        }
+        let text = self.fs.load(&cache_file_path(key)).await.ok()?;
+        let cached = serde_json::from_str::<CachedCompletion>(&text).ok()?;
+        Some(cached.events)
+    }
+
+    fn save(&self, key: String, events: Vec<LanguageModelCompletionEvent>, max_size: u64) {
+        let created_at = SystemTime::now();
+        let text = match serde_json::to_string(&CachedCompletion { created_at, events }) {
+            Ok(text) => text,
+            Err(error) => {
+                log::error!("failed to serialize cached language model response: {error}");
+                return;
+            }
+        };
+
+        let mut state = self.state.lock();
+        state.files.insert(
+            key.clone(),
+            CacheFile {
+                created_at,
+                size: text.len() as u64,
+            },
+        );
+        let mut size = state.files.values().map(|file| file.size).sum::<u64>();
+        let mut files = state
+            .files
+            .iter()
+            .map(|(key, file)| (file.created_at, file.size, key.clone()))
+            .collect::<Vec<_>>();
+        files.sort();
+        let mut evicted = Vec::new();
+        for (_, file_size, evicted_key) in files {
+            if size <= max_size {
+                break;
+            }
+            size -= file_size;
+            state.files.remove(&evicted_key);
+            evicted.push(evicted_key);
+        }
+        drop(state);
+        let is_kept = !evicted.contains(&key);
+
+        let fs = self.fs.clone();
+        self.executor
+            .spawn(async move {
+                if let Err(error) = fs.create_dir(&cache_dir()).await {
+                    log::error!("failed to create the language model response cache: {error}");
+                    return;
+                }
+                for evicted_key in evicted {
+                    fs.remove_file(&cache_file_path(&evicted_key), RemoveOptions::default())
+                        .await
+                        .ok();
+                }
+                if is_kept {
+                    if let Err(error) = fs.atomic_write(cache_file_path(&key), text).await {
+                        log::error!("failed to cache language model response: {error}");
+                    }
+                }
+            })
+            .detach();
+    }
+}
+
+/// Replays a request's completion from the response cache when an identical request was made
+/// before, and otherwise caches the completion once it finishes.
+#[derive(Default)]
+pub(crate) struct ResponseCacheRecorder {
+    cache: Option<(ResponseCache, String, ResponseCacheSettings)>,
+}
+
+impl ResponseCacheRecorder {
+    pub(crate) fn new(
+        provider: &LanguageModelProviderId,
+        model: &LanguageModelId,
+        request: &LanguageModelRequest,
+        cx: &AsyncApp,
+    ) -> Self {
+        // Completions are cached when they stop, which happens once per candidate.
+        if request.candidates.is_some_and(|candidates| candidates > 1) {
+            return Self::default();
+        }
+        let Some((settings, endpoint)) =
+            AllLanguageModelSettings::try_read_global(cx, |settings| {
+                (settings.response_cache, endpoint(provider, settings))
+            })
+            .filter(|(settings, _)| settings.enabled.unwrap_or(false))
+        else {
+            return Self::default();
+        };
+        let cache = cx
+            .try_read_global(|cache: &GlobalResponseCache, _| cache.0.clone())
+            .zip(endpoint.and_then(|endpoint| cache_key(provider, model, &endpoint, request)))
+            .map(|(cache, key)| (cache, key, settings));
+        Self { cache }
+    }
+
+    /// The events of the cached completion, if there's one that hasn't expired.
+    pub(crate) async fn replay(&self) -> Option<Vec<LanguageModelCompletionEvent>> {
+        let (cache, key, settings) = self.cache.as_ref()?;
+        cache.load(key, settings.ttl()).await
+    }
+
+    /// Caches the completion once it stops without an error.
+    pub(crate) fn record(
+        self,
+        events: BoxStream<
+            'static,
+            Result<LanguageModelCompletionEvent, LanguageModelCompletionError>,
+        >,
+    ) -> BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>
+    {
+        let Some((cache, key, settings)) = self.cache else {
+            return events;
+        };
+        let mut recorded = Some(Vec::new());
+        events
+            .inspect(move |event| match event {
+                Ok(event) => {
+                    if let Some(recorded) = recorded.as_mut().filter(|_| is_replayed(event)) {
+                        recorded.push(event.clone());
+                    }
+                    if matches!(event, LanguageModelCompletionEvent::Stop(_)) {
+                        if let Some(recorded) = recorded.take() {
+                            cache.save(key.clone(), recorded, settings.max_size());
+                        }
+                    }
+                }
+                Err(_) => recorded = None,
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use language_model::{LanguageModelRequestMessage, MessageContent, Role};
+
+    use super::*;
+
+    #[test]
+    fn test_cache_key() {
+        let provider = LanguageModelProviderId("openai".into());
+        let model = LanguageModelId("gpt-4.1".into());
+        let request = LanguageModelRequest {
+            thread_id: Some("first-thread".into()),
+            messages: vec![LanguageModelRequestMessage {
+                role: Role::User,
+                content: vec![MessageContent::Text("Hello".into())],
+                cache: false,
+            }],
+            ..Default::default()
+        };
+        let rerun = LanguageModelRequest {
+            thread_id: Some("second-thread".into()),
+            ..request.clone()
+        };
+        let warmer = LanguageModelRequest {
+            temperature: Some(1.),
+            ..request.clone()
+        };
+        let other_model = LanguageModelId("gpt-4.1-mini".into());
+
+        let endpoint = "https://api.openai.com/v1";
+
+        let key = cache_key(&provider, &model, endpoint, &request);
+        assert!(key.is_some());
+        assert_eq!(key, cache_key(&provider, &model, endpoint, &rerun));
+        assert_ne!(key, cache_key(&provider, &model, endpoint, &warmer));
+        assert_ne!(key, cache_key(&provider, &other_model, endpoint, &request));
+        assert_ne!(
+            key,
+            cache_key(
+                &provider,
+                &model,
+                "https://gateway.example.com/v1",
+                &request
+            )
+        );
+    }
+
+    #[test]
+    fn test_endpoint() {
+        let provider = LanguageModelProviderId("openai".into());
+        let mut settings = AllLanguageModelSettings::default();
+        settings.openai.api_url = "https://gateway.example.com/v1".into();
+        let default_endpoint = endpoint(&provider, &settings);
+
+        settings
+            .openai
+            .api
+            .headers
+            .insert("X-Deployment".into(), "canary".into());
+        let canary = endpoint(&provider, &settings);
+        assert_ne!(canary, default_endpoint);
+
+        settings
+            .openai
+            .api
+            .query_params
+            .insert("api-version".into(), "2025-01-01".into());
+        assert_ne!(endpoint(&provider, &settings), canary);
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use gpui::{App, Global};
+
+/// A service that isn't a language model, like one that reads text aloud, which the settings
+/// choose by its ID.
+pub trait Service: Send + Sync {
+    fn id(&self) -> &'static str;
+    fn name(&self) -> &'static str;
+}
+
+/// The services of one kind, like every text-to-speech service, which are registered when
+/// language models are initialized.
+pub struct ServiceRegistry<S: ?Sized> {
+    services: Vec<Arc<S>>,
+}
+
+impl<S: ?Sized> Clone for ServiceRegistry<S> {
+    fn clone(&self) -> Self {
+        Self {
+            services: self.services.clone(),
+        }
+    }
+}
+
+impl<S: ?Sized> Default for ServiceRegistry<S> {
+    fn default() -> Self {
+        Self {
+            services: Vec::new(),
+        }
+    }
+}
+
+impl<S: ?Sized + 'static> Global for ServiceRegistry<S> {}
+
+impl<S: ?Sized + Service + 'static> ServiceRegistry<S> {
+    pub fn global(cx: &App) -> Self {
+        cx.try_global::<Self>().cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn set_global(services: Vec<Arc<S>>, cx: &mut App) {
+        cx.set_global(Self { services });
+    }
+
+    pub fn services(&self) -> &[Arc<S>] {
+        &self.services
+    }
+
+    pub fn service(&self, id: &str) -> Option<Arc<S>> {
+        self.services
+            .iter()
+            .find(|service| service.id() == id)
+            .cloned()
+    }
+
+    /// The service chosen in the settings, or the one with `default_id` if none is.
+    pub(crate) fn chosen(&self, id: Option<&str>, default_id: &str, kind: &str) -> Result<Arc<S>> {
+        let id = id.unwrap_or(default_id);
+        self.service(id)
+            .with_context(|| format!("unknown {kind} provider {id:?}"))
+    }
+}
@@ -1,16 +1,26 @@
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc, time::Duration};
 
 use anyhow::Result;
+use collections::{HashMap, HashSet};
 use gpui::App;
-use language_model::LanguageModelCacheConfiguration;
+use language_model::{
+    LanguageModelCacheConfiguration, LanguageModelProviderId, LanguageModelRequest,
+    LanguageModelRequestMessage, MessageContent, ModelPrice, Role, ZED_CLOUD_PROVIDER_ID,
+};
 use project::Fs;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use settings::{Settings, SettingsSources, update_settings_file};
 
+use crate::api_key::ApiKeyRotation;
+use crate::batch::BatchSettings;
+use crate::embedding::EmbeddingSettings;
+use crate::image_generation::ImageGenerationSettings;
+use crate::moderation::ModerationSettings;
 use crate::provider::{
     self,
     anthropic::AnthropicSettings,
+    balanced::BalancedSettings,
     bedrock::AmazonBedrockSettings,
     cloud::{self, ZedDotDevSettings},
     copilot_chat::CopilotChatSettings,
@@ -18,10 +28,16 @@ use crate::provider::{
     google::GoogleSettings,
     grok::GrokSettings,
     lmstudio::LmStudioSettings,
+    local::LocalSettings,
     mistral::MistralSettings,
     ollama::OllamaSettings,
     open_ai::OpenAiSettings,
 };
+use crate::rerank::RerankSettings;
+use crate::response_cache::ResponseCacheSettings;
+use crate::speech::SpeechSettings;
+use crate::spend_limits::SpendLimits;
+use crate::transcription::TranscriptionSettings;
 
 /// Initializes the language model settings.
 pub fn init(fs: Arc<dyn Fs>, cx: &mut App) {
@@ -66,9 +82,41 @@ pub struct AllLanguageModelSettings {
     pub google: GoogleSettings,
     pub copilot_chat: CopilotChatSettings,
     pub lmstudio: LmStudioSettings,
+    pub local: LocalSettings,
     pub deepseek: DeepSeekSettings,
     pub mistral: MistralSettings,
     pub grok: GrokSettings,
+    pub balanced: BalancedSettings,
+    pub credentials_profile: Option<String>,
+    pub credentials_profiles: Vec<String>,
+    pub disabled_providers: HashSet<LanguageModelProviderId>,
+    pub max_concurrent_requests: Option<usize>,
+    pub max_concurrent_requests_by_provider: HashMap<LanguageModelProviderId, usize>,
+    pub response_cache: ResponseCacheSettings,
+    pub batch: BatchSettings,
+    pub speech: SpeechSettings,
+    pub transcription: TranscriptionSettings,
+    pub image_generation: ImageGenerationSettings,
+    pub embedding: EmbeddingSettings,
+    pub rerank: RerankSettings,
+    pub moderation: ModerationSettings,
+    pub fallback_models: HashMap<String, Vec<String>>,
+    pub model_aliases: HashMap<String, String>,
+}
+
+impl AllLanguageModelSettings {
+    /// How many requests each of the provider's models can have in flight at once.
+    pub fn max_concurrent_requests(&self, provider_id: &'static str) -> usize {
+        let max_concurrent_requests = match self
+            .max_concurrent_requests_by_provider
+            .get(&LanguageModelProviderId(provider_id.into()))
+        {
+            Some(max_concurrent_requests) => *max_concurrent_requests,
+            None if provider_id == provider::local::PROVIDER_ID => 1,
+            None => self.max_concurrent_requests.unwrap_or(4),
+        };
+        max_concurrent_requests.max(1)
+    }
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
@@ -77,6 +125,7 @@ pub struct AllLanguageModelSettingsContent {
     pub bedrock: Option<AmazonBedrockSettingsContent>,
     pub ollama: Option<OllamaSettingsContent>,
     pub lmstudio: Option<LmStudioSettingsContent>,
+    pub local: Option<LocalSettingsContent>,
     pub openai: Option<OpenAiSettingsContent>,
     #[serde(rename = "zed.dev")]
     pub zed_dot_dev: Option<ZedDotDevSettingsContent>,
@@ -85,6 +134,53 @@ pub struct AllLanguageModelSettingsContent {
     pub copilot_chat: Option<CopilotChatSettingsContent>,
     pub mistral: Option<MistralSettingsContent>,
     pub grok: Option<GrokSettingsContent>,
+    pub balanced: Option<BalancedSettingsContent>,
+    /// The named set of saved API keys to use, e.g. "work" or "personal". Keys entered while a
+    /// profile is selected are only used with that profile. Other provider settings are shared
+    /// by every profile.
+    ///
+    /// Default: null
+    pub credentials_profile: Option<String>,
+    /// The credentials profiles to offer when switching profiles.
+    ///
+    /// Default: []
+    pub credentials_profiles: Option<Vec<String>>,
+    /// How many requests to send to each provider at once. Requests beyond this wait for an
+    /// earlier one to finish. Doesn't apply to the `local` provider, which runs one request at a
+    /// time unless its own `max_concurrent_requests` is set.
+    ///
+    /// Default: 4
+    pub max_concurrent_requests: Option<usize>,
+    /// Replays the completions of requests identical to earlier ones instead of sending them
+    /// again.
+    pub response_cache: Option<ResponseCacheSettings>,
+    /// Sends requests whose responses aren't needed right away, like thread summaries and eval
+    /// judgements, through providers' batch APIs at a lower price.
+    pub batch: Option<BatchSettings>,
+    /// The service that reads text aloud, like agent responses.
+    pub speech: Option<SpeechSettings>,
+    /// The service that turns speech into text, like voice prompts.
+    pub transcription: Option<TranscriptionSettings>,
+    /// The model that generates images.
+    pub image_generation: Option<ImageGenerationSettings>,
+    /// The service that embeds text for semantic features.
+    pub embedding: Option<EmbeddingSettings>,
+    /// The service that reorders retrieved documents by their relevance to a query.
+    pub rerank: Option<RerankSettings>,
+    /// Checks prompts against an organization's policy before they're sent to a paid provider.
+    pub moderation: Option<ModerationSettings>,
+    /// The models to retry a request on, in order, when the model it was sent to fails, by the
+    /// model that they're fallbacks for. Models are given as `provider/model`, like
+    /// `"grok/grok-3-latest": ["openai/gpt-4.1", "ollama/llama3.2:latest"]`.
+    ///
+    /// Default: {}
+    pub fallback_models: Option<HashMap<String, Vec<String>>>,
+    /// Stable names for models, which can be selected with the `alias` provider and are resolved
+    /// whenever a request is sent. Models are given as `provider/model`, like
+    /// `"default-coder": "grok/grok-3-latest"`.
+    ///
+    /// Default: {}
+    pub model_aliases: Option<HashMap<String, String>>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
@@ -99,7 +195,9 @@ impl AnthropicSettingsContent {
         match self {
             AnthropicSettingsContent::Legacy(content) => (
                 AnthropicSettingsContentV1 {
+                    common: ProviderCommonSettingsContent::default(),
                     api_url: content.api_url,
+                    api: ProviderApiSettingsContent::default(),
                     available_models: content.available_models.map(|models| {
                         models
                             .into_iter()
@@ -130,11 +228,18 @@ impl AnthropicSettingsContent {
                                     default_temperature,
                                     extra_beta_headers,
                                     mode: Some(mode.into()),
+                                    reasoning: ReasoningSettings::default(),
+                                    supports_tools: None,
+                                    supports_images: None,
+                                    supports_thinking: None,
+                                    supports_parallel_tool_calls: None,
+                                    sampling: SamplingParameters::default(),
                                 }),
                                 _ => None,
                             })
                             .collect()
                     }),
+                    automatic_caching: None,
                 },
                 true,
             ),
@@ -158,42 +263,430 @@ pub enum VersionedAnthropicSettingsContent {
     V1(AnthropicSettingsContentV1),
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct AnthropicSettingsContentV1 {
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
     pub api_url: Option<String>,
+    #[serde(flatten)]
+    pub api: ProviderApiSettingsContent,
     pub available_models: Option<Vec<provider::anthropic::AvailableModel>>,
+    /// Settings for placing prompt cache breakpoints automatically.
+    pub automatic_caching: Option<provider::anthropic::AutomaticCachingSettings>,
+}
+
+/// Settings shared by every provider that sends requests itself.
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct ProviderCommonSettingsContent {
+    /// Whether to offer this provider's models. Disabled providers aren't authenticated at
+    /// startup or shown in the model picker.
+    ///
+    /// Default: true
+    pub enabled: Option<bool>,
+    /// How many requests to send to this provider at once, overriding the global
+    /// `max_concurrent_requests`.
+    pub max_concurrent_requests: Option<usize>,
+}
+
+/// Settings shared by the providers that authenticate with an API key.
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct ProviderApiSettingsContent {
+    /// How to choose between API keys when several are configured for the provider.
+    ///
+    /// Default: failover
+    pub api_key_rotation: Option<ApiKeyRotation>,
+    /// A command whose output is used as the API key, instead of storing the key in the system's
+    /// credentials store. For example, `["op", "read", "op://Private/OpenAI/credential"]`.
+    ///
+    /// For short-lived keys the command can print `{"api_key": "...", "expires_in": 3600}`
+    /// instead, or use `expires_at` in seconds since the Unix epoch, and it will be run again
+    /// before the key expires.
+    pub api_key_command: Option<Vec<String>>,
+    /// How often to run `api_key_command` again to get a fresh key, in seconds.
+    ///
+    /// Default: the command only runs again when its key is about to expire
+    pub api_key_command_refresh_secs: Option<u64>,
+    /// A file to read the API key from, such as a mounted secret. Zed reloads the key whenever
+    /// the file changes.
+    pub api_key_file: Option<PathBuf>,
+    /// The environment variable to read the API key from, instead of the provider's usual one.
+    /// For example, `"XAI_API_KEY_WORK"`, so that wrapper scripts can inject different keys.
+    pub api_key_env_var: Option<String>,
+    /// An HTTP, HTTPS, or SOCKS proxy to send this provider's requests through, instead of the
+    /// `proxy` used for the rest of Zed's traffic. For example, `"socks5://localhost:1080"`.
+    ///
+    /// Changing it takes effect after restarting Zed.
+    pub proxy: Option<String>,
+    /// Headers to add to every request sent to this provider, such as the tokens and tenant ids
+    /// that LLM gateways require. They replace any header of the same name that Zed sets.
+    pub headers: Option<HashMap<String, String>>,
+    /// Query parameters to add to every request sent to this provider, such as the
+    /// `api-version` that Azure requires, or a gateway's routing parameters.
+    pub query_params: Option<HashMap<String, String>>,
+    /// How many times to retry a request that failed because the provider was rate limiting,
+    /// overloaded, or briefly unavailable, waiting longer before each attempt.
+    ///
+    /// Default: 3
+    pub max_retries: Option<u32>,
+    /// How long to wait for the provider to respond to a request, in seconds, before giving up.
+    /// `0` waits indefinitely.
+    ///
+    /// Default: 0
+    pub connect_timeout_secs: Option<u64>,
+    /// How long to wait for a response to start generating text or tool calls, in seconds,
+    /// before giving up. `0` waits indefinitely.
+    ///
+    /// Default: 0
+    pub first_token_timeout_secs: Option<u64>,
+    /// How long a streaming response can go without sending anything, in seconds, before it's
+    /// considered stalled and stopped. The keep-alive comments that providers send while the model
+    /// is thinking count as sending something. `0` waits indefinitely.
+    ///
+    /// Default: 300
+    pub idle_timeout_secs: Option<u64>,
+    /// How many requests to send to this provider per minute, across all of its models. Requests
+    /// beyond this wait until they fit, instead of being rejected by the provider.
+    ///
+    /// Default: unlimited
+    pub requests_per_minute: Option<u32>,
+    /// How many tokens to send to this provider per minute, across all of its models, estimated
+    /// from the size of each request. Requests beyond this wait until they fit, instead of being
+    /// rejected by the provider.
+    ///
+    /// Default: unlimited
+    pub tokens_per_minute: Option<u64>,
+    /// A PEM file with certificate authorities to trust in addition to the system's, for
+    /// endpoints behind an internal PKI.
+    ///
+    /// Changing it takes effect after restarting Zed.
+    pub ca_cert: Option<PathBuf>,
+    /// A PEM file with the client certificate to present to endpoints that require mutual TLS.
+    /// Requires `client_key`.
+    ///
+    /// Changing it takes effect after restarting Zed.
+    pub client_cert: Option<PathBuf>,
+    /// A PEM file with the private key for `client_cert`.
+    pub client_key: Option<PathBuf>,
+    /// What each model costs, by model id, for estimating the spend of each API key.
+    pub model_prices: Option<HashMap<String, ModelPrice>>,
+    /// How much the provider's requests can cost per day and per month before Zed refuses to send
+    /// more.
+    pub spend_limits: Option<SpendLimits>,
+    /// Instructions to add before and after the system prompt of every request sent to this
+    /// provider.
+    pub system_prompt: Option<SystemPromptOverrides>,
+}
+
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct ProviderApiSettings {
+    pub api_key_rotation: ApiKeyRotation,
+    pub api_key_command: Option<Vec<String>>,
+    pub api_key_command_refresh: Option<Duration>,
+    pub api_key_file: Option<PathBuf>,
+    pub api_key_env_var: Option<String>,
+    pub proxy: Option<String>,
+    pub headers: HashMap<String, String>,
+    pub query_params: HashMap<String, String>,
+    pub max_retries: Option<u32>,
+    pub connect_timeout_secs: Option<u64>,
+    pub first_token_timeout_secs: Option<u64>,
+    pub idle_timeout_secs: Option<u64>,
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u64>,
+    pub ca_cert: Option<PathBuf>,
+    pub client_cert: Option<PathBuf>,
+    pub client_key: Option<PathBuf>,
+    pub model_prices: HashMap<String, ModelPrice>,
+    pub spend_limits: SpendLimits,
+    pub system_prompt: SystemPromptOverrides,
+}
+
+impl ProviderApiSettings {
+    /// The environment variable that the API key is read from, which is `default` unless
+    /// `api_key_env_var` overrides it.
+    pub fn env_var<'a>(&'a self, default: &'a str) -> &'a str {
+        self.api_key_env_var.as_deref().unwrap_or(default)
+    }
+
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries.unwrap_or(3)
+    }
+
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        timeout_from_secs(self.connect_timeout_secs.unwrap_or(0))
+    }
+
+    pub fn first_token_timeout(&self) -> Option<Duration> {
+        timeout_from_secs(self.first_token_timeout_secs.unwrap_or(0))
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        timeout_from_secs(self.idle_timeout_secs.unwrap_or(300))
+    }
+
+    pub fn requests_per_minute(&self) -> Option<u32> {
+        self.requests_per_minute.filter(|limit| *limit > 0)
+    }
+
+    pub fn tokens_per_minute(&self) -> Option<u64> {
+        self.tokens_per_minute.filter(|limit| *limit > 0)
+    }
+
+    fn merge(&mut self, content: Option<&ProviderApiSettingsContent>) {
+        let Some(content) = content else {
+            return;
+        };
+        if let Some(api_key_rotation) = content.api_key_rotation {
+            self.api_key_rotation = api_key_rotation;
+        }
+        if let Some(api_key_command) = content.api_key_command.clone() {
+            self.api_key_command = Some(api_key_command);
+        }
+        if let Some(refresh_secs) = content
+            .api_key_command_refresh_secs
+            .filter(|secs| *secs > 0)
+        {
+            self.api_key_command_refresh = Some(Duration::from_secs(refresh_secs));
+        }
+        if let Some(api_key_file) = content.api_key_file.clone() {
+            self.api_key_file = Some(api_key_file);
+        }
+        if let Some(api_key_env_var) = content
+            .api_key_env_var
+            .clone()
+            .filter(|env_var| !env_var.is_empty())
+        {
+            self.api_key_env_var = Some(api_key_env_var);
+        }
+        if let Some(proxy) = content.proxy.clone().filter(|proxy| !proxy.is_empty()) {
+            self.proxy = Some(proxy);
+        }
+        if let Some(headers) = content.headers.clone() {
+            self.headers.extend(headers);
+        }
+        if let Some(query_params) = content.query_params.clone() {
+            self.query_params.extend(query_params);
+        }
+        if let Some(max_retries) = content.max_retries {
+            self.max_retries = Some(max_retries);
+        }
+        if let Some(connect_timeout_secs) = content.connect_timeout_secs {
+            self.connect_timeout_secs = Some(connect_timeout_secs);
+        }
+        if let Some(first_token_timeout_secs) = content.first_token_timeout_secs {
+            self.first_token_timeout_secs = Some(first_token_timeout_secs);
+        }
+        if let Some(idle_timeout_secs) = content.idle_timeout_secs {
+            self.idle_timeout_secs = Some(idle_timeout_secs);
+        }
+        if let Some(requests_per_minute) = content.requests_per_minute {
+            self.requests_per_minute = Some(requests_per_minute);
+        }
+        if let Some(tokens_per_minute) = content.tokens_per_minute {
+            self.tokens_per_minute = Some(tokens_per_minute);
+        }
+        if let Some(ca_cert) = content.ca_cert.clone() {
+            self.ca_cert = Some(ca_cert);
+        }
+        if let Some(client_cert) = content.client_cert.clone() {
+            self.client_cert = Some(client_cert);
+        }
+        if let Some(client_key) = content.client_key.clone() {
+            self.client_key = Some(client_key);
+        }
+        if let Some(model_prices) = content.model_prices.clone() {
+            self.model_prices.extend(model_prices);
+        }
+        if let Some(spend_limits) = content.spend_limits {
+            self.spend_limits = spend_limits;
+        }
+        if let Some(system_prompt) = content.system_prompt.clone() {
+            self.system_prompt = system_prompt;
+        }
+    }
+}
+
+fn timeout_from_secs(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Sampling parameters that an `available_models` entry sets for its model, which apply to every
+/// request that doesn't set its own. An entry with the same `name` as a built-in model overrides
+/// that model's parameters too.
+#[derive(Default, Clone, Copy, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct SamplingParameters {
+    /// Only sample from the most likely tokens whose probabilities add up to `top_p`.
+    pub top_p: Option<f32>,
+    /// Only sample from the `top_k` most likely tokens. Not supported by OpenAI-compatible
+    /// providers.
+    pub top_k: Option<u32>,
+}
+
+impl SamplingParameters {
+    pub(crate) fn apply(self, mut request: LanguageModelRequest) -> LanguageModelRequest {
+        request.top_p = request.top_p.or(self.top_p);
+        request.top_k = request.top_k.or(self.top_k);
+        request
+    }
+}
+
+/// Instructions that are added to the system prompt of every request sent to a provider, such as
+/// safety text that a vendor requires, or formatting hints for a local model.
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct SystemPromptOverrides {
+    /// Text to add before the system prompt.
+    pub prefix: Option<String>,
+    /// Text to add after the system prompt.
+    pub suffix: Option<String>,
+}
+
+impl SystemPromptOverrides {
+    /// Adds the prefix to the start of the request's system prompt and the suffix to its end,
+    /// adding a system prompt when the request has none.
+    pub(crate) fn apply(&self, mut request: LanguageModelRequest) -> LanguageModelRequest {
+        let prefix = self.prefix.as_deref().filter(|prefix| !prefix.is_empty());
+        let suffix = self.suffix.as_deref().filter(|suffix| !suffix.is_empty());
+        if prefix.is_none() && suffix.is_none() {
+            return request;
+        }
+
+        let system_end = request
+            .messages
+            .iter()
+            .take_while(|message| message.role == Role::System)
+            .count();
+        if system_end == 0 {
+            let text = prefix.into_iter().chain(suffix).collect::<Vec<_>>();
+            request.messages.insert(
+                0,
+                LanguageModelRequestMessage {
+                    role: Role::System,
+                    content: vec![MessageContent::Text(text.join("\n\n"))],
+                    cache: false,
+                },
+            );
+            return request;
+        }
+
+        if let Some(prefix) = prefix {
+            let content = &mut request.messages[0].content;
+            match content.first_mut() {
+                Some(MessageContent::Text(text)) => text.insert_str(0, &format!("{prefix}\n\n")),
+                _ => content.insert(0, MessageContent::Text(prefix.to_string())),
+            }
+        }
+        if let Some(suffix) = suffix {
+            let content = &mut request.messages[system_end - 1].content;
+            match content.last_mut() {
+                Some(MessageContent::Text(text)) => {
+                    text.push_str("\n\n");
+                    text.push_str(suffix);
+                }
+                _ => content.push(MessageContent::Text(suffix.to_string())),
+            }
+        }
+        request
+    }
+}
+
+/// How much an `available_models` entry's model reasons before it responds. Each provider uses
+/// the setting that its API takes, and ignores the other.
+#[derive(Default, Clone, Copy, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct ReasoningSettings {
+    /// How much effort the model puts into reasoning. Used by OpenAI's o-series models and xAI's
+    /// thinking models, which only support `low` and `high`.
+    pub effort: Option<ReasoningEffort>,
+    /// The maximum number of tokens the model may spend reasoning. Used by Anthropic's extended
+    /// thinking, where it must be lower than the model's `max_output_tokens`, and by Gemini's
+    /// thinking budget, where 0 turns thinking off.
+    pub budget_tokens: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+impl From<ReasoningEffort> for open_ai::ReasoningEffort {
+    fn from(effort: ReasoningEffort) -> Self {
+        match effort {
+            ReasoningEffort::Low => Self::Low,
+            ReasoningEffort::Medium => Self::Medium,
+            ReasoningEffort::High => Self::High,
+        }
+    }
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct AmazonBedrockSettingsContent {
-    available_models: Option<Vec<provider::bedrock::AvailableModel>>,
-    endpoint_url: Option<String>,
-    region: Option<String>,
-    profile: Option<String>,
-    authentication_method: Option<provider::bedrock::BedrockAuthMethod>,
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
+    pub available_models: Option<Vec<provider::bedrock::AvailableModel>>,
+    pub endpoint_url: Option<String>,
+    pub region: Option<String>,
+    pub profile: Option<String>,
+    pub authentication_method: Option<provider::bedrock::BedrockAuthMethod>,
+    /// Instructions to add before and after the system prompt of every request sent to this
+    /// provider.
+    pub system_prompt: Option<SystemPromptOverrides>,
+    /// A guardrail to apply to every request sent to this provider.
+    pub guardrail: Option<provider::bedrock::BedrockGuardrail>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct OllamaSettingsContent {
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
     pub api_url: Option<String>,
     pub available_models: Option<Vec<provider::ollama::AvailableModel>>,
+    /// Instructions to add before and after the system prompt of every request sent to this
+    /// provider.
+    pub system_prompt: Option<SystemPromptOverrides>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct LmStudioSettingsContent {
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
     pub api_url: Option<String>,
     pub available_models: Option<Vec<provider::lmstudio::AvailableModel>>,
+    /// Instructions to add before and after the system prompt of every request sent to this
+    /// provider.
+    pub system_prompt: Option<SystemPromptOverrides>,
+}
+
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct LocalSettingsContent {
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
+    pub runtime_path: Option<PathBuf>,
+    pub use_gpu: Option<bool>,
+    pub available_models: Option<Vec<provider::local::AvailableModel>>,
+    /// Instructions to add before and after the system prompt of every request sent to this
+    /// provider.
+    pub system_prompt: Option<SystemPromptOverrides>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct DeepseekSettingsContent {
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
     pub api_url: Option<String>,
+    #[serde(flatten)]
+    pub api: ProviderApiSettingsContent,
     pub available_models: Option<Vec<provider::deepseek::AvailableModel>>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct MistralSettingsContent {
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
     pub api_url: Option<String>,
+    #[serde(flatten)]
+    pub api: ProviderApiSettingsContent,
     pub available_models: Option<Vec<provider::mistral::AvailableModel>>,
 }
 
@@ -209,7 +702,12 @@ impl OpenAiSettingsContent {
         match self {
             OpenAiSettingsContent::Legacy(content) => (
                 OpenAiSettingsContentV1 {
+                    common: ProviderCommonSettingsContent::default(),
                     api_url: content.api_url,
+                    api: ProviderApiSettingsContent::default(),
+                    entra_id: None,
+                    organization_id: None,
+                    project_id: None,
                     available_models: content.available_models.map(|models| {
                         models
                             .into_iter()
@@ -220,12 +718,28 @@ impl OpenAiSettingsContent {
                                     max_tokens,
                                     max_output_tokens,
                                     max_completion_tokens,
+                                    system_prompt_role,
                                 } => Some(provider::open_ai::AvailableModel {
                                     name,
                                     max_tokens,
                                     max_output_tokens,
                                     display_name,
                                     max_completion_tokens,
+                                    system_prompt_role,
+                                    stream: None,
+                                    response_schema_format: None,
+                                    supports_parallel_tool_calls: None,
+                                    reasoning: ReasoningSettings::default(),
+                                    responses_api: None,
+                                    transport: None,
+                                    prompt_template: None,
+                                    strict_tools: None,
+                                    extra_body: None,
+                                    supports_tools: None,
+                                    supports_images: None,
+                                    supports_thinking: None,
+                                    supports_audio: None,
+                                    sampling: SamplingParameters::default(),
                                 }),
                                 _ => None,
                             })
@@ -254,32 +768,72 @@ pub enum VersionedOpenAiSettingsContent {
     V1(OpenAiSettingsContentV1),
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct OpenAiSettingsContentV1 {
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
     pub api_url: Option<String>,
+    #[serde(flatten)]
+    pub api: ProviderApiSettingsContent,
+    /// Authenticate with Microsoft Entra ID tokens instead of an API key, for Azure OpenAI
+    /// deployments that disable key-based authentication.
+    pub entra_id: Option<provider::open_ai::EntraIdSettings>,
+    /// The organization that requests are billed to, sent as the `OpenAI-Organization` header.
+    pub organization_id: Option<String>,
+    /// The project that requests are billed to, sent as the `OpenAI-Project` header.
+    pub project_id: Option<String>,
     pub available_models: Option<Vec<provider::open_ai::AvailableModel>>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct GoogleSettingsContent {
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
     pub api_url: Option<String>,
+    #[serde(flatten)]
+    pub api: ProviderApiSettingsContent,
+    /// How to authenticate with the Gemini API.
+    ///
+    /// Default: api_key
+    pub authentication: Option<provider::google::GoogleAuthentication>,
     pub available_models: Option<Vec<provider::google::AvailableModel>>,
+    /// Settings for caching the start of long conversations with Gemini's context caching.
+    pub context_caching: Option<provider::google::ContextCachingSettings>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct ZedDotDevSettingsContent {
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
     available_models: Option<Vec<cloud::AvailableModel>>,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
-pub struct CopilotChatSettingsContent {}
+pub struct CopilotChatSettingsContent {
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
+}
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
 pub struct GrokSettingsContent {
+    #[serde(flatten)]
+    pub common: ProviderCommonSettingsContent,
     pub api_url: Option<String>,
+    #[serde(flatten)]
+    pub api: ProviderApiSettingsContent,
     pub available_models: Option<Vec<provider::grok::AvailableModel>>,
 }
 
+#[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq, JsonSchema)]
+pub struct BalancedSettingsContent {
+    /// Whether to offer this provider's models.
+    ///
+    /// Default: true
+    pub enabled: Option<bool>,
+    /// The models whose requests are spread across several backends.
+    pub available_models: Option<Vec<provider::balanced::AvailableModel>>,
+}
+
 impl settings::Settings for AllLanguageModelSettings {
     const KEY: Option<&'static str> = Some("language_models");
 
@@ -297,6 +851,70 @@ impl settings::Settings for AllLanguageModelSettings {
         let mut settings = AllLanguageModelSettings::default();
 
         for value in sources.defaults_and_customizations() {
+            if let Some(profile) = value.credentials_profile.clone() {
+                settings.credentials_profile = Some(profile).filter(|profile| !profile.is_empty());
+            }
+            merge(
+                &mut settings.credentials_profiles,
+                value.credentials_profiles.clone(),
+            );
+            if let Some(max_concurrent_requests) = value.max_concurrent_requests {
+                settings.max_concurrent_requests = Some(max_concurrent_requests);
+            }
+            if let Some(response_cache) = value.response_cache {
+                let current = &mut settings.response_cache;
+                current.enabled = response_cache.enabled.or(current.enabled);
+                current.ttl_hours = response_cache.ttl_hours.or(current.ttl_hours);
+                current.max_size_mb = response_cache.max_size_mb.or(current.max_size_mb);
+            }
+            if let Some(batch) = value.batch {
+                let current = &mut settings.batch;
+                current.enabled = batch.enabled.or(current.enabled);
+                current.group_window_secs = batch.group_window_secs.or(current.group_window_secs);
+                current.poll_interval_secs =
+                    batch.poll_interval_secs.or(current.poll_interval_secs);
+            }
+            if let Some(speech) = value.speech.clone() {
+                let current = &mut settings.speech;
+                current.provider = speech.provider.or(current.provider.take());
+                current.voice = speech.voice.or(current.voice.take());
+                current.model = speech.model.or(current.model.take());
+            }
+            if let Some(transcription) = value.transcription.clone() {
+                let current = &mut settings.transcription;
+                current.provider = transcription.provider.or(current.provider.take());
+                current.model = transcription.model.or(current.model.take());
+                current.language = transcription.language.or(current.language.take());
+            }
+            if let Some(image_generation) = value.image_generation.clone() {
+                let current = &mut settings.image_generation;
+                current.model = image_generation.model.or(current.model.take());
+            }
+            if let Some(embedding) = value.embedding.clone() {
+                let current = &mut settings.embedding;
+                current.provider = embedding.provider.or(current.provider.take());
+                current.model = embedding.model.or(current.model.take());
+            }
+            if let Some(rerank) = value.rerank.clone() {
+                let current = &mut settings.rerank;
+                current.provider = rerank.provider.or(current.provider.take());
+                current.model = rerank.model.or(current.model.take());
+            }
+            if let Some(moderation) = value.moderation.clone() {
+                let current = &mut settings.moderation;
+                current.enabled = moderation.enabled.or(current.enabled);
+                current.checker = moderation.checker.or(current.checker);
+                current.action = moderation.action.or(current.action);
+                current.blocked_patterns = moderation
+                    .blocked_patterns
+                    .or(current.blocked_patterns.take());
+                current.categories = moderation.categories.or(current.categories.take());
+            }
+            merge(&mut settings.fallback_models, value.fallback_models.clone());
+            if let Some(model_aliases) = value.model_aliases.clone() {
+                settings.model_aliases.extend(model_aliases);
+            }
+
             // Anthropic
             let (anthropic, upgraded) = match value.anthropic.clone().map(|s| s.upgrade()) {
                 Some((content, upgraded)) => (Some(content), upgraded),
@@ -315,6 +933,14 @@ impl settings::Settings for AllLanguageModelSettings {
                 &mut settings.anthropic.available_models,
                 anthropic.as_ref().and_then(|s| s.available_models.clone()),
             );
+            settings
+                .anthropic
+                .api
+                .merge(anthropic.as_ref().map(|s| &s.api));
+            merge(
+                &mut settings.anthropic.automatic_caching,
+                anthropic.as_ref().and_then(|s| s.automatic_caching),
+            );
 
             // Bedrock
             let bedrock = value.bedrock.clone();
@@ -334,6 +960,14 @@ impl settings::Settings for AllLanguageModelSettings {
                 &mut settings.bedrock.endpoint,
                 bedrock.as_ref().map(|s| s.endpoint_url.clone()),
             );
+            merge(
+                &mut settings.bedrock.system_prompt,
+                bedrock.as_ref().and_then(|s| s.system_prompt.clone()),
+            );
+            merge(
+                &mut settings.bedrock.guardrail,
+                bedrock.as_ref().map(|s| s.guardrail.clone()),
+            );
 
             // Ollama
             let ollama = value.ollama.clone();
@@ -346,6 +980,10 @@ impl settings::Settings for AllLanguageModelSettings {
                 &mut settings.ollama.available_models,
                 ollama.as_ref().and_then(|s| s.available_models.clone()),
             );
+            merge(
+                &mut settings.ollama.system_prompt,
+                ollama.as_ref().and_then(|s| s.system_prompt.clone()),
+            );
 
             // LM Studio
             let lmstudio = value.lmstudio.clone();
@@ -358,6 +996,30 @@ impl settings::Settings for AllLanguageModelSettings {
                 &mut settings.lmstudio.available_models,
                 lmstudio.as_ref().and_then(|s| s.available_models.clone()),
             );
+            merge(
+                &mut settings.lmstudio.system_prompt,
+                lmstudio.as_ref().and_then(|s| s.system_prompt.clone()),
+            );
+
+            // Local
+            let local = value.local.clone();
+
+            merge(
+                &mut settings.local.runtime_path,
+                local.as_ref().map(|s| s.runtime_path.clone()),
+            );
+            merge(
+                &mut settings.local.use_gpu,
+                local.as_ref().map(|s| s.use_gpu),
+            );
+            merge(
+                &mut settings.local.available_models,
+                local.as_ref().and_then(|s| s.available_models.clone()),
+            );
+            merge(
+                &mut settings.local.system_prompt,
+                local.as_ref().and_then(|s| s.system_prompt.clone()),
+            );
 
             // DeepSeek
             let deepseek = value.deepseek.clone();
@@ -370,6 +1032,10 @@ impl settings::Settings for AllLanguageModelSettings {
                 &mut settings.deepseek.available_models,
                 deepseek.as_ref().and_then(|s| s.available_models.clone()),
             );
+            settings
+                .deepseek
+                .api
+                .merge(deepseek.as_ref().map(|s| &s.api));
 
             // OpenAI
             let (openai, upgraded) = match value.openai.clone().map(|s| s.upgrade()) {
@@ -389,6 +1055,16 @@ impl settings::Settings for AllLanguageModelSettings {
                 &mut settings.openai.available_models,
                 openai.as_ref().and_then(|s| s.available_models.clone()),
             );
+            settings.openai.api.merge(openai.as_ref().map(|s| &s.api));
+            if let Some(entra_id) = openai.as_ref().and_then(|s| s.entra_id.clone()) {
+                settings.openai.entra_id = Some(entra_id);
+            }
+            if let Some(organization_id) = openai.as_ref().and_then(|s| s.organization_id.clone()) {
+                settings.openai.organization_id = Some(organization_id);
+            }
+            if let Some(project_id) = openai.as_ref().and_then(|s| s.project_id.clone()) {
+                settings.openai.project_id = Some(project_id);
+            }
             merge(
                 &mut settings.zed_dot_dev.available_models,
                 value
@@ -407,6 +1083,18 @@ impl settings::Settings for AllLanguageModelSettings {
                     .as_ref()
                     .and_then(|s| s.available_models.clone()),
             );
+            settings
+                .google
+                .api
+                .merge(value.google.as_ref().map(|s| &s.api));
+            merge(
+                &mut settings.google.authentication,
+                value.google.as_ref().and_then(|s| s.authentication),
+            );
+            merge(
+                &mut settings.google.context_caching,
+                value.google.as_ref().and_then(|s| s.context_caching),
+            );
 
             // Mistral
             let mistral = value.mistral.clone();
@@ -418,6 +1106,7 @@ impl settings::Settings for AllLanguageModelSettings {
                 &mut settings.mistral.available_models,
                 mistral.as_ref().and_then(|s| s.available_models.clone()),
             );
+            settings.mistral.api.merge(mistral.as_ref().map(|s| &s.api));
 
             // Grok
             let grok = value.grok.clone();
@@ -429,6 +1118,92 @@ impl settings::Settings for AllLanguageModelSettings {
                 &mut settings.grok.available_models,
                 grok.as_ref().and_then(|s| s.available_models.clone()),
             );
+            settings.grok.api.merge(grok.as_ref().map(|s| &s.api));
+
+            // Balanced
+            let balanced = value.balanced.clone();
+            merge(
+                &mut settings.balanced.available_models,
+                balanced.as_ref().and_then(|s| s.available_models.clone()),
+            );
+
+            let provider_settings = [
+                (
+                    provider::anthropic::PROVIDER_ID,
+                    anthropic.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    provider::bedrock::PROVIDER_ID,
+                    bedrock.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    provider::ollama::PROVIDER_ID,
+                    ollama.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    provider::lmstudio::PROVIDER_ID,
+                    lmstudio.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    provider::local::PROVIDER_ID,
+                    local.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    provider::deepseek::PROVIDER_ID,
+                    deepseek.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    provider::open_ai::PROVIDER_ID,
+                    openai.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    ZED_CLOUD_PROVIDER_ID,
+                    value.zed_dot_dev.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    provider::google::PROVIDER_ID,
+                    value.google.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    provider::copilot_chat::PROVIDER_ID,
+                    value.copilot_chat.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    provider::mistral::PROVIDER_ID,
+                    mistral.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    provider::grok::PROVIDER_ID,
+                    grok.as_ref().map(|s| s.common.clone()),
+                ),
+                (
+                    provider::balanced::PROVIDER_ID,
+                    balanced.as_ref().map(|s| ProviderCommonSettingsContent {
+                        enabled: s.enabled,
+                        max_concurrent_requests: None,
+                    }),
+                ),
+            ];
+            for (provider_id, common) in provider_settings {
+                let Some(common) = common else {
+                    continue;
+                };
+                let provider_id = LanguageModelProviderId(provider_id.into());
+                if let Some(max_concurrent_requests) = common.max_concurrent_requests {
+                    settings
+                        .max_concurrent_requests_by_provider
+                        .insert(provider_id.clone(), max_concurrent_requests);
+                }
+                match common.enabled {
+                    Some(true) => {
+                        settings.disabled_providers.remove(&provider_id);
+                    }
+                    Some(false) => {
+                        settings.disabled_providers.insert(provider_id);
+                    }
+                    None => {}
+                }
+            }
         }
 
         Ok(settings)
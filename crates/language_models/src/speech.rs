@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream};
+use futures::{AsyncReadExt as _, FutureExt as _, StreamExt as _};
+use gpui::{App, AsyncApp, Entity};
+use http_client::{AsyncBody, HttpClient};
+use language_model::AudioFormat;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings as _;
+
+use crate::AllLanguageModelSettings;
+use crate::provider::open_ai::State as OpenAiState;
+use crate::service_registry::{Service, ServiceRegistry};
+
+mod elevenlabs;
+mod open_ai;
+
+pub use elevenlabs::ElevenLabsSpeechProvider;
+pub use open_ai::OpenAiSpeechProvider;
+
+/// How much of a response's body is read at a time while streaming audio.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Which service reads text aloud, and with what voice.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SpeechSettings {
+    /// The service to read text aloud with, either `openai` or `elevenlabs`.
+    ///
+    /// Default: "openai"
+    pub provider: Option<String>,
+    /// The voice to speak with, such as `alloy` for OpenAI or a voice ID for ElevenLabs.
+    ///
+    /// Default: the provider's default voice
+    pub voice: Option<String>,
+    /// The model to generate speech with, such as `gpt-4o-mini-tts` or `eleven_flash_v2_5`.
+    ///
+    /// Default: the provider's default model
+    pub model: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpeechRequest {
+    pub text: String,
+    /// The voice to speak with, or the provider's default voice.
+    pub voice: Option<String>,
+    /// The model to generate speech with, or the provider's default model.
+    pub model: Option<String>,
+}
+
+/// Audio that's streamed as it's generated, so that playback can start before all of it is.
+pub struct Speech {
+    pub format: AudioFormat,
+    pub chunks: BoxStream<'static, Result<Vec<u8>>>,
+}
+
+/// A service that reads text aloud.
+pub trait SpeechProvider: Service {
+    fn synthesize(
+        &self,
+        request: SpeechRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Speech>>;
+}
+
+/// The services that can read text aloud.
+pub type SpeechProviders = ServiceRegistry<dyn SpeechProvider>;
+
+impl SpeechProviders {
+    /// Reads `text` aloud with the provider, voice, and model chosen in the `speech` settings.
+    pub fn synthesize(text: String, cx: &AsyncApp) -> BoxFuture<'static, Result<Speech>> {
+        let provider = cx.update(|cx| {
+            let settings = AllLanguageModelSettings::get_global(cx).speech.clone();
+            let provider = Self::global(cx).chosen(
+                settings.provider.as_deref(),
+                open_ai::PROVIDER_ID,
+                "speech",
+            )?;
+            anyhow::Ok((provider, settings))
+        });
+        let (provider, settings) = match provider.and_then(|provider| provider) {
+            Ok(provider) => provider,
+            Err(error) => return futures::future::ready(Err(error)).boxed(),
+        };
+        provider.synthesize(
+            SpeechRequest {
+                text,
+                voice: settings.voice,
+                model: settings.model,
+            },
+            cx,
+        )
+    }
+}
+
+pub(crate) fn init(
+    open_ai_state: Entity<OpenAiState>,
+    http_client: Arc<dyn HttpClient>,
+    cx: &mut App,
+) {
+    SpeechProviders::set_global(
+        vec![
+            Arc::new(OpenAiSpeechProvider::new(open_ai_state)),
+            Arc::new(ElevenLabsSpeechProvider::new(http_client)),
+        ],
+        cx,
+    );
+}
+
+/// Streams `body` in chunks as it arrives, stopping after the first error.
+fn body_chunks(body: AsyncBody) -> BoxStream<'static, Result<Vec<u8>>> {
+    stream::unfold(Some(body), |body| async move {
+        let mut body = body?;
+        let mut chunk = vec![0; CHUNK_SIZE];
+        match body.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(len) => {
+                chunk.truncate(len);
+                Some((Ok(chunk), Some(body)))
+            }
+            Err(error) => Some((Err(error.into()), None)),
+        }
+    })
+    .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[gpui::test]
+    async fn test_body_chunks() {
+        let audio = (0..CHUNK_SIZE * 2 + 100)
+            .map(|ix| ix as u8)
+            .collect::<Vec<_>>();
+        let chunks = body_chunks(AsyncBody::from(audio.clone()))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert!(chunks.iter().all(|chunk| chunk.len() <= CHUNK_SIZE));
+        assert_eq!(chunks.concat(), audio);
+    }
+}
@@ -0,0 +1,115 @@
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use futures::future::BoxFuture;
+use futures::{AsyncReadExt as _, FutureExt as _};
+use gpui::AsyncApp;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use language_model::AudioFormat;
+use serde::{Deserialize, Serialize};
+
+use super::{Speech, SpeechProvider, SpeechRequest, body_chunks};
+use crate::api_key::ServiceApiKeys;
+use crate::service_registry::Service;
+
+const PROVIDER_ID: &str = "elevenlabs";
+const API_URL: &str = "https://api.elevenlabs.io";
+const API_KEY_VAR: &str = "ELEVENLABS_API_KEY";
+const DEFAULT_MODEL: &str = "eleven_flash_v2_5";
+/// The "Rachel" voice from ElevenLabs' default voice library.
+const DEFAULT_VOICE_ID: &str = "21m00Tcm4TlvDq8N6WOS";
+const OUTPUT_FORMAT: &str = "mp3_44100_128";
+
+#[derive(Serialize)]
+struct TextToSpeechRequest {
+    text: String,
+    model_id: String,
+}
+
+/// Reads text aloud with ElevenLabs, whose key is read from `ELEVENLABS_API_KEY` or the
+/// credentials store.
+pub struct ElevenLabsSpeechProvider {
+    http_client: Arc<dyn HttpClient>,
+    api_keys: ServiceApiKeys,
+}
+
+impl ElevenLabsSpeechProvider {
+    pub fn new(http_client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            http_client,
+            api_keys: ServiceApiKeys::new(API_KEY_VAR, PROVIDER_ID, API_URL),
+        }
+    }
+}
+
+impl Service for ElevenLabsSpeechProvider {
+    fn id(&self) -> &'static str {
+        PROVIDER_ID
+    }
+
+    fn name(&self) -> &'static str {
+        "ElevenLabs"
+    }
+}
+
+impl SpeechProvider for ElevenLabsSpeechProvider {
+    fn synthesize(
+        &self,
+        request: SpeechRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Speech>> {
+        let http_client = self.http_client.clone();
+        let api_keys = self.api_keys.load(cx);
+        async move {
+            let api_keys = api_keys.await?;
+            let api_key = api_keys
+                .current()
+                .ok_or_else(|| anyhow!("Missing ElevenLabs API Key"))?;
+            let voice_id = request.voice.as_deref().unwrap_or(DEFAULT_VOICE_ID);
+            let body = TextToSpeechRequest {
+                text: request.text,
+                model_id: request.model.unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            };
+            let request = HttpRequest::builder()
+                .method(Method::POST)
+                .uri(format!(
+                    "{API_URL}/v1/text-to-speech/{voice_id}/stream?output_format={OUTPUT_FORMAT}"
+                ))
+                .header("Content-Type", "application/json")
+                .header("xi-api-key", api_key)
+                .body(AsyncBody::from(serde_json::to_string(&body)?))?;
+            let mut response = http_client.send(request).await?;
+            if !response.status().is_success() {
+                let mut body = String::new();
+                response.body_mut().read_to_string(&mut body).await?;
+                return Err(error_from_response(response.status(), &body));
+            }
+            Ok(Speech {
+                format: AudioFormat::Mp3,
+                chunks: body_chunks(response.into_body()),
+            })
+        }
+        .boxed()
+    }
+}
+
+fn error_from_response(status: http_client::StatusCode, body: &str) -> anyhow::Error {
+    #[derive(Deserialize)]
+    struct ErrorResponse {
+        detail: ErrorDetail,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum ErrorDetail {
+        Message { message: String },
+        Text(String),
+    }
+
+    match serde_json::from_str::<ErrorResponse>(body) {
+        Ok(ErrorResponse {
+            detail: ErrorDetail::Message { message } | ErrorDetail::Text(message),
+        }) => anyhow!("Failed to generate speech with ElevenLabs: {message}"),
+        Err(_) => anyhow!("Failed to generate speech with ElevenLabs: {status} {body}"),
+    }
+}
@@ -0,0 +1,72 @@
+use anyhow::Result;
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::{AsyncApp, Entity};
+use language_model::AudioFormat;
+
+use super::{Speech, SpeechProvider, SpeechRequest, body_chunks};
+use crate::provider::open_ai::{OpenAiAuth, State};
+use crate::service_registry::Service;
+
+pub(crate) const PROVIDER_ID: &str = "openai";
+
+/// Reads text aloud with OpenAI's text-to-speech models, using the OpenAI provider's
+/// credentials.
+pub struct OpenAiSpeechProvider {
+    state: Entity<State>,
+}
+
+impl OpenAiSpeechProvider {
+    pub fn new(state: Entity<State>) -> Self {
+        Self { state }
+    }
+}
+
+impl Service for OpenAiSpeechProvider {
+    fn id(&self) -> &'static str {
+        PROVIDER_ID
+    }
+
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+}
+
+impl SpeechProvider for OpenAiSpeechProvider {
+    fn synthesize(
+        &self,
+        request: SpeechRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<Speech>> {
+        let auth = OpenAiAuth::read(&self.state, cx);
+        async move {
+            let auth = auth?;
+            let token = auth.bearer_token().await?;
+            let body = open_ai::audio::create_speech(
+                auth.http_client.as_ref(),
+                &auth.api_url,
+                &token,
+                &auth.account,
+                open_ai::audio::SpeechRequest {
+                    model: request
+                        .model
+                        .unwrap_or_else(|| open_ai::audio::DEFAULT_SPEECH_MODEL.to_string()),
+                    input: request.text,
+                    voice: request
+                        .voice
+                        .unwrap_or_else(|| open_ai::audio::DEFAULT_VOICE.to_string()),
+                    response_format: AudioFormat::Mp3.as_str().to_string(),
+                    instructions: None,
+                    speed: None,
+                },
+            )
+            .await
+            .inspect_err(|error| auth.record_error(&token, error))?;
+            Ok(Speech {
+                format: AudioFormat::Mp3,
+                chunks: body_chunks(body),
+            })
+        }
+        .boxed()
+    }
+}
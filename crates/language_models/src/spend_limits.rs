@@ -0,0 +1,331 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local};
+use collections::HashSet;
+use fs::Fs;
+use gpui::{App, AsyncApp, BackgroundExecutor, Global};
+use language_model::{LanguageModelKnownError, SpendLimitPeriod};
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings as _;
+
+use crate::configure::ConfigurableProvider;
+use crate::warnings::{LanguageModelWarning, LanguageModelWarnings};
+use crate::{AllLanguageModelSettings, OverrideSpendLimits};
+
+const DEFAULT_WARN_AT_PERCENT: u32 = 80;
+
+/// Caps on what a provider's requests cost, in US dollars, so that a runaway agent can't run up a
+/// surprise bill. Spend is estimated from the models' prices, so requests to models whose price
+/// isn't known don't count towards the limits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct SpendLimits {
+    /// The most that the provider's requests can cost per day.
+    pub daily: Option<f64>,
+    /// The most that the provider's requests can cost per calendar month.
+    pub monthly: Option<f64>,
+    /// The percentage of a limit at which Zed warns that it's getting close.
+    ///
+    /// Default: 80
+    pub warn_at_percent: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SpendStatus {
+    WithinLimits,
+    NearLimit {
+        period: SpendLimitPeriod,
+        spent: f64,
+        limit: f64,
+    },
+    OverLimit {
+        period: SpendLimitPeriod,
+        spent: f64,
+        limit: f64,
+    },
+}
+
+impl SpendLimits {
+    fn status(&self, spent_today: f64, spent_this_month: f64) -> SpendStatus {
+        let warn_at = self.warn_at_percent.unwrap_or(DEFAULT_WARN_AT_PERCENT) as f64 / 100.;
+        let mut status = SpendStatus::WithinLimits;
+        for (period, limit, spent) in [
+            (SpendLimitPeriod::Day, self.daily, spent_today),
+            (SpendLimitPeriod::Month, self.monthly, spent_this_month),
+        ] {
+            let Some(limit) = limit else {
+                continue;
+            };
+            if spent >= limit {
+                return SpendStatus::OverLimit {
+                    period,
+                    spent,
+                    limit,
+                };
+            }
+            if spent >= limit * warn_at && status == SpendStatus::WithinLimits {
+                status = SpendStatus::NearLimit {
+                    period,
+                    spent,
+                    limit,
+                };
+            }
+        }
+        status
+    }
+}
+
+/// What a provider's requests cost on one day.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct DailySpend {
+    /// The day, like `2025-06-14`.
+    day: String,
+    provider: String,
+    cost: f64,
+}
+
+#[derive(Default)]
+struct SpendLedgerState {
+    entries: Vec<DailySpend>,
+    /// Whether the spend saved by previous sessions was read, before which nothing is saved so
+    /// that it isn't overwritten.
+    loaded: bool,
+    /// The day on which the user chose to go over the limits.
+    overridden_on: Option<String>,
+    /// The days on which each provider was warned that it's close to a limit, so that the warning
+    /// is only shown once a day.
+    warned: HashSet<(String, SpendLimitPeriod, String)>,
+}
+
+/// What each provider's requests cost this month, by day, for enforcing their `spend_limits`.
+#[derive(Clone, Default)]
+pub struct SpendLedger {
+    state: Arc<Mutex<SpendLedgerState>>,
+    persistence: Option<(Arc<dyn Fs>, BackgroundExecutor)>,
+}
+
+struct GlobalSpendLedger(SpendLedger);
+
+impl Global for GlobalSpendLedger {}
+
+pub(crate) fn init(fs: Arc<dyn Fs>, cx: &mut App) {
+    let ledger = SpendLedger {
+        state: Arc::default(),
+        persistence: Some((fs.clone(), cx.background_executor().clone())),
+    };
+    cx.set_global(GlobalSpendLedger(ledger.clone()));
+    cx.on_action(|_: &OverrideSpendLimits, cx| SpendLedger::global(cx).override_limits());
+    cx.background_spawn(async move {
+        let saved = match fs.load(&spend_file_path()).await {
+            Ok(text) => serde_json::from_str::<Vec<DailySpend>>(&text).unwrap_or_else(|error| {
+                log::error!("failed to read language model spend: {error}");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        let mut state = ledger.state.lock();
+        let recorded = std::mem::replace(&mut state.entries, saved);
+        for entry in &recorded {
+            add_spend(&mut state.entries, entry);
+        }
+        state.loaded = true;
+        let should_save = !recorded.is_empty();
+        drop(state);
+        if should_save {
+            ledger.save();
+        }
+    })
+    .detach();
+}
+
+fn spend_file_path() -> PathBuf {
+    paths::data_dir().join("language_model_spend.json")
+}
+
+fn add_spend(entries: &mut Vec<DailySpend>, spend: &DailySpend) {
+    match entries
+        .iter_mut()
+        .find(|entry| entry.day == spend.day && entry.provider == spend.provider)
+    {
+        Some(entry) => entry.cost += spend.cost,
+        None => entries.push(spend.clone()),
+    }
+}
+
+fn day(now: DateTime<Local>) -> String {
+    now.format("%Y-%m-%d").to_string()
+}
+
+impl SpendLedger {
+    pub fn global(cx: &App) -> Self {
+        cx.try_global::<GlobalSpendLedger>()
+            .map(|ledger| ledger.0.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn read_global(cx: &AsyncApp) -> Self {
+        cx.try_read_global(|ledger: &GlobalSpendLedger, _| ledger.0.clone())
+            .unwrap_or_default()
+    }
+
+    /// What the provider's requests cost in the day or month that `now` falls in.
+    pub fn spent(&self, provider: &str, period: SpendLimitPeriod, now: DateTime<Local>) -> f64 {
+        let prefix = match period {
+            SpendLimitPeriod::Day => day(now),
+            SpendLimitPeriod::Month => now.format("%Y-%m").to_string(),
+        };
+        self.state
+            .lock()
+            .entries
+            .iter()
+            .filter(|entry| entry.provider == provider && entry.day.starts_with(&prefix))
+            .map(|entry| entry.cost)
+            .sum()
+    }
+
+    /// Lets requests through for the rest of the day, even when their provider is over its limits.
+    pub fn override_limits(&self) {
+        self.state.lock().overridden_on = Some(day(Local::now()));
+    }
+
+    pub(crate) fn record(&self, provider: &str, cost: f64) {
+        let now = Local::now();
+        let month = now.format("%Y-%m").to_string();
+        let mut state = self.state.lock();
+        state.entries.retain(|entry| entry.day.starts_with(&month));
+        add_spend(
+            &mut state.entries,
+            &DailySpend {
+                day: day(now),
+                provider: provider.to_string(),
+                cost,
+            },
+        );
+        let loaded = state.loaded;
+        drop(state);
+        if loaded {
+            self.save();
+        }
+    }
+
+    fn is_overridden(&self, now: DateTime<Local>) -> bool {
+        self.state.lock().overridden_on == Some(day(now))
+    }
+
+    /// Whether to warn that the provider is close to its limit for `period`, which is only done
+    /// once a day.
+    fn should_warn(&self, provider: &str, period: SpendLimitPeriod, now: DateTime<Local>) -> bool {
+        self.state
+            .lock()
+            .warned
+            .insert((provider.to_string(), period, day(now)))
+    }
+
+    fn save(&self) {
+        let Some((fs, executor)) = self.persistence.clone() else {
+            return;
+        };
+        let text = match serde_json::to_string_pretty(&self.state.lock().entries) {
+            Ok(text) => text,
+            Err(error) => {
+                log::error!("failed to serialize language model spend: {error}");
+                return;
+            }
+        };
+        executor
+            .spawn(async move {
+                if let Err(error) = fs.atomic_write(spend_file_path(), text).await {
+                    log::error!("failed to save language model spend: {error}");
+                }
+            })
+            .detach();
+    }
+}
+
+/// Fails with [`LanguageModelKnownError::SpendLimitReached`] once the provider's spend reaches one
+/// of its `spend_limits`, unless the user chose to go over them today, and warns once a day when
+/// it gets close to one.
+pub(crate) fn check_spend_limits(provider: &'static str, cx: &AsyncApp) -> Result<()> {
+    let Some(configurable_provider) = ConfigurableProvider::find(provider) else {
+        return Ok(());
+    };
+    let Some(limits) = AllLanguageModelSettings::try_read_global(cx, |settings| {
+        configurable_provider.api_settings(settings).spend_limits
+    }) else {
+        return Ok(());
+    };
+    let ledger = SpendLedger::read_global(cx);
+    let now = Local::now();
+    let status = limits.status(
+        ledger.spent(provider, SpendLimitPeriod::Day, now),
+        ledger.spent(provider, SpendLimitPeriod::Month, now),
+    );
+    match status {
+        SpendStatus::WithinLimits => Ok(()),
+        SpendStatus::NearLimit {
+            period,
+            spent,
+            limit,
+        } => {
+            if ledger.should_warn(provider, period, now) {
+                let message = format!(
+                    "{} has spent about ${spent:.2} of its ${limit:.2} {period} limit.",
+                    configurable_provider.name
+                );
+                let warning = LanguageModelWarning::NearSpendLimit { provider, message };
+                if let Err(error) = cx.update(|cx| LanguageModelWarnings::emit(warning, cx)) {
+                    log::error!("failed to warn about the spend limit: {error}");
+                }
+            }
+            Ok(())
+        }
+        SpendStatus::OverLimit { .. } if ledger.is_overridden(now) => Ok(()),
+        SpendStatus::OverLimit {
+            period,
+            spent,
+            limit,
+        } => Err(anyhow!(LanguageModelKnownError::SpendLimitReached {
+            period,
+            spent,
+            limit,
+        })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spend_status() {
+        let limits = SpendLimits {
+            daily: Some(10.),
+            monthly: Some(100.),
+            warn_at_percent: None,
+        };
+        assert_eq!(limits.status(1., 10.), SpendStatus::WithinLimits);
+        assert_eq!(
+            limits.status(8., 10.),
+            SpendStatus::NearLimit {
+                period: SpendLimitPeriod::Day,
+                spent: 8.,
+                limit: 10.,
+            }
+        );
+        assert_eq!(
+            limits.status(8., 100.),
+            SpendStatus::OverLimit {
+                period: SpendLimitPeriod::Month,
+                spent: 100.,
+                limit: 100.,
+            }
+        );
+        assert_eq!(
+            SpendLimits::default().status(1_000., 1_000.),
+            SpendStatus::WithinLimits
+        );
+    }
+}
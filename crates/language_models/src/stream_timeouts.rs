@@ -0,0 +1,125 @@
+use std::time::{Duration, Instant};
+
+use futures::StreamExt as _;
+use futures::future::{self, Either};
+use futures::stream::{self, BoxStream};
+use gpui::{AsyncApp, BackgroundExecutor};
+use language_model::{
+    LanguageModelCompletionError, LanguageModelCompletionEvent, LanguageModelKnownError,
+    LanguageModelTimeout,
+};
+use settings::Settings as _;
+
+use crate::{AllLanguageModelSettings, ProviderApiSettings};
+
+/// Ends a completion with a [`LanguageModelKnownError::Timeout`] when the provider takes longer
+/// than `first_token_timeout_secs` to start generating, instead of leaving the request hanging,
+/// and with a [`LanguageModelKnownError::StreamInterrupted`] that keeps the text generated so far
+/// when the connection drops or stalls partway through.
+///
+/// Stalls are detected on the response body by the provider's HTTP client, which also sees the
+/// keep-alive comments that providers send while the model is thinking.
+pub(crate) struct StreamTimeouts {
+    first_token: Option<Duration>,
+    executor: BackgroundExecutor,
+}
+
+impl StreamTimeouts {
+    /// Reads the timeouts of the provider whose settings `api_settings` selects.
+    pub(crate) fn new(
+        api_settings: fn(&AllLanguageModelSettings) -> &ProviderApiSettings,
+        cx: &AsyncApp,
+    ) -> Self {
+        let first_token = AllLanguageModelSettings::try_read_global(cx, |settings| {
+            api_settings(settings).first_token_timeout()
+        })
+        .flatten();
+        Self {
+            first_token,
+            executor: cx.background_executor().clone(),
+        }
+    }
+
+    pub(crate) fn apply(
+        self,
+        events: BoxStream<
+            'static,
+            Result<LanguageModelCompletionEvent, LanguageModelCompletionError>,
+        >,
+    ) -> BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>
+    {
+        let started_at = Instant::now();
+        let state = TimeoutState {
+            events,
+            received_token: false,
+            partial_text: String::new(),
+        };
+        stream::unfold(Some(state), move |state| {
+            let timer = state
+                .as_ref()
+                .filter(|state| !state.received_token)
+                .and(self.first_token)
+                .map(|first_token| {
+                    let remaining = first_token.saturating_sub(started_at.elapsed());
+                    (first_token, self.executor.timer(remaining))
+                });
+            async move {
+                let mut state = state?;
+                let event = match timer {
+                    Some((after, timer)) => {
+                        match future::select(state.events.next(), timer).await {
+                            Either::Left((event, _)) => event,
+                            Either::Right(_) => {
+                                let error = LanguageModelKnownError::Timeout {
+                                    kind: LanguageModelTimeout::FirstToken,
+                                    after,
+                                };
+                                return Some((Err(anyhow::Error::new(error).into()), None));
+                            }
+                        }
+                    }
+                    None => state.events.next().await,
+                }?;
+                match &event {
+                    Ok(LanguageModelCompletionEvent::Text(text)) => {
+                        state.received_token = true;
+                        state.partial_text.push_str(text);
+                    }
+                    Ok(
+                        LanguageModelCompletionEvent::Thinking { .. }
+                        | LanguageModelCompletionEvent::RedactedThinking { .. }
+                        | LanguageModelCompletionEvent::ToolUse(_),
+                    ) => {
+                        state.received_token = true;
+                    }
+                    Err(error) if state.received_token && is_interruption(error) => {
+                        let error = LanguageModelKnownError::StreamInterrupted {
+                            partial_text: std::mem::take(&mut state.partial_text),
+                            reason: error.to_string(),
+                        };
+                        return Some((Err(anyhow::Error::new(error).into()), None));
+                    }
+                    _ => {}
+                }
+                Some((event, Some(state)))
+            }
+        })
+        .boxed()
+    }
+}
+
+/// Whether `error` means that the connection to the provider dropped or stalled, rather than
+/// that the provider reported an error.
+fn is_interruption(error: &LanguageModelCompletionError) -> bool {
+    let LanguageModelCompletionError::Other(error) = error else {
+        return false;
+    };
+    error.chain().any(|cause| cause.is::<std::io::Error>())
+}
+
+struct TimeoutState {
+    events: BoxStream<'static, Result<LanguageModelCompletionEvent, LanguageModelCompletionError>>,
+    received_token: bool,
+    /// The text generated so far, which is kept in the error if the response is interrupted.
+    partial_text: String,
+}
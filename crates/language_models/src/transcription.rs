@@ -0,0 +1,124 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::{App, AsyncApp, Entity};
+use http_client::HttpClient;
+use language_model::AudioFormat;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use settings::Settings as _;
+
+use crate::AllLanguageModelSettings;
+use crate::provider::open_ai::State as OpenAiState;
+use crate::service_registry::{Service, ServiceRegistry};
+
+mod deepgram;
+mod groq;
+mod open_ai;
+
+pub use deepgram::DeepgramTranscriptionProvider;
+pub use groq::GroqTranscriptionProvider;
+pub use open_ai::OpenAiTranscriptionProvider;
+
+/// Which service turns speech into text, like voice prompts.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TranscriptionSettings {
+    /// The service to transcribe speech with: `openai`, `groq`, or `deepgram`.
+    ///
+    /// Default: "openai"
+    pub provider: Option<String>,
+    /// The model to transcribe speech with, such as `gpt-4o-transcribe`, `whisper-large-v3`, or
+    /// `nova-3`.
+    ///
+    /// Default: the provider's default model
+    pub model: Option<String>,
+    /// The language that's spoken, as an ISO-639-1 code like `en`. Transcriptions are more
+    /// accurate and arrive sooner when it's given.
+    ///
+    /// Default: detected from the audio
+    pub language: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TranscriptionRequest {
+    pub audio: Vec<u8>,
+    pub format: AudioFormat,
+    /// The model to transcribe with, or the provider's default model.
+    pub model: Option<String>,
+    pub language: Option<String>,
+    /// Text that the speech follows on from, which helps with names and jargon. Ignored by
+    /// providers that don't take one.
+    pub prompt: Option<String>,
+}
+
+impl TranscriptionRequest {
+    fn file_name(&self) -> String {
+        format!("audio.{}", self.format.as_str())
+    }
+}
+
+/// A service that turns speech into text.
+pub trait TranscriptionProvider: Service {
+    fn transcribe(
+        &self,
+        request: TranscriptionRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>>;
+}
+
+/// The services that can transcribe speech.
+pub type TranscriptionProviders = ServiceRegistry<dyn TranscriptionProvider>;
+
+impl TranscriptionProviders {
+    /// Transcribes `audio` with the provider, model, and language chosen in the `transcription`
+    /// settings.
+    pub fn transcribe(
+        audio: Vec<u8>,
+        format: AudioFormat,
+        prompt: Option<String>,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>> {
+        let provider = cx.update(|cx| {
+            let settings = AllLanguageModelSettings::get_global(cx)
+                .transcription
+                .clone();
+            let provider = Self::global(cx).chosen(
+                settings.provider.as_deref(),
+                open_ai::PROVIDER_ID,
+                "transcription",
+            )?;
+            anyhow::Ok((provider, settings))
+        });
+        let (provider, settings) = match provider.and_then(|provider| provider) {
+            Ok(provider) => provider,
+            Err(error) => return futures::future::ready(Err(error)).boxed(),
+        };
+        provider.transcribe(
+            TranscriptionRequest {
+                audio,
+                format,
+                model: settings.model,
+                language: settings.language,
+                prompt,
+            },
+            cx,
+        )
+    }
+}
+
+pub(crate) fn init(
+    open_ai_state: Entity<OpenAiState>,
+    http_client: Arc<dyn HttpClient>,
+    cx: &mut App,
+) {
+    TranscriptionProviders::set_global(
+        vec![
+            Arc::new(OpenAiTranscriptionProvider::new(open_ai_state)),
+            Arc::new(GroqTranscriptionProvider::new(http_client.clone())),
+            Arc::new(DeepgramTranscriptionProvider::new(http_client)),
+        ],
+        cx,
+    );
+}
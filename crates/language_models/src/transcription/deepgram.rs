@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result, anyhow};
+use futures::future::BoxFuture;
+use futures::{AsyncReadExt as _, FutureExt as _};
+use gpui::AsyncApp;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::Deserialize;
+
+use super::{TranscriptionProvider, TranscriptionRequest};
+use crate::api_key::ServiceApiKeys;
+use crate::service_registry::Service;
+
+const PROVIDER_ID: &str = "deepgram";
+const API_URL: &str = "https://api.deepgram.com";
+const API_KEY_VAR: &str = "DEEPGRAM_API_KEY";
+const DEFAULT_MODEL: &str = "nova-3";
+
+#[derive(Deserialize)]
+struct ListenResponse {
+    results: ListenResults,
+}
+
+#[derive(Deserialize)]
+struct ListenResults {
+    channels: Vec<Channel>,
+}
+
+#[derive(Deserialize)]
+struct Channel {
+    alternatives: Vec<Alternative>,
+}
+
+#[derive(Deserialize)]
+struct Alternative {
+    transcript: String,
+}
+
+/// Transcribes speech with Deepgram, whose key is read from `DEEPGRAM_API_KEY` or the
+/// credentials store.
+pub struct DeepgramTranscriptionProvider {
+    http_client: Arc<dyn HttpClient>,
+    api_keys: ServiceApiKeys,
+}
+
+impl DeepgramTranscriptionProvider {
+    pub fn new(http_client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            http_client,
+            api_keys: ServiceApiKeys::new(API_KEY_VAR, PROVIDER_ID, API_URL),
+        }
+    }
+}
+
+impl Service for DeepgramTranscriptionProvider {
+    fn id(&self) -> &'static str {
+        PROVIDER_ID
+    }
+
+    fn name(&self) -> &'static str {
+        "Deepgram"
+    }
+}
+
+impl TranscriptionProvider for DeepgramTranscriptionProvider {
+    fn transcribe(
+        &self,
+        request: TranscriptionRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>> {
+        let http_client = self.http_client.clone();
+        let api_keys = self.api_keys.load(cx);
+        async move {
+            let api_keys = api_keys.await?;
+            let api_key = api_keys
+                .current()
+                .ok_or_else(|| anyhow!("Missing Deepgram API Key"))?;
+
+            let model = request.model.as_deref().unwrap_or(DEFAULT_MODEL);
+            let mut uri = format!("{API_URL}/v1/listen?model={model}&smart_format=true");
+            match &request.language {
+                Some(language) => uri.push_str(&format!("&language={language}")),
+                None => uri.push_str("&detect_language=true"),
+            }
+            let http_request = HttpRequest::builder()
+                .method(Method::POST)
+                .uri(uri)
+                .header("Content-Type", request.format.mime_type())
+                .header("Authorization", format!("Token {api_key}"))
+                .body(AsyncBody::from(request.audio))?;
+            let mut response = http_client.send(http_request).await?;
+            let mut body = String::new();
+            response.body_mut().read_to_string(&mut body).await?;
+            anyhow::ensure!(
+                response.status().is_success(),
+                "Failed to transcribe audio with Deepgram: {} {}",
+                response.status(),
+                body,
+            );
+            transcript_from_response(&body)
+        }
+        .boxed()
+    }
+}
+
+fn transcript_from_response(body: &str) -> Result<String> {
+    let response: ListenResponse =
+        serde_json::from_str(body).context("failed to parse Deepgram response")?;
+    Ok(response
+        .results
+        .channels
+        .into_iter()
+        .filter_map(|channel| channel.alternatives.into_iter().next())
+        .map(|alternative| alternative.transcript)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transcript_from_response() {
+        let body = r#"{
+            "metadata": {"request_id": "1"},
+            "results": {
+                "channels": [
+                    {"alternatives": [
+                        {"transcript": "Rename this function.", "confidence": 0.98},
+                        {"transcript": "Rename these functions.", "confidence": 0.4}
+                    ]}
+                ]
+            }
+        }"#;
+        assert_eq!(
+            transcript_from_response(body).unwrap(),
+            "Rename this function."
+        );
+        assert!(transcript_from_response("{}").is_err());
+    }
+}
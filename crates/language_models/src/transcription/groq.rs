@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use anyhow::{Result, anyhow};
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::AsyncApp;
+use http_client::HttpClient;
+use open_ai::Account;
+
+use super::{TranscriptionProvider, TranscriptionRequest};
+use crate::api_key::ServiceApiKeys;
+use crate::service_registry::Service;
+
+const PROVIDER_ID: &str = "groq";
+const API_URL: &str = "https://api.groq.com/openai/v1";
+const API_KEY_VAR: &str = "GROQ_API_KEY";
+const DEFAULT_MODEL: &str = "whisper-large-v3-turbo";
+
+/// Transcribes speech with the Whisper models that Groq hosts behind an OpenAI-compatible API.
+pub struct GroqTranscriptionProvider {
+    http_client: Arc<dyn HttpClient>,
+    api_keys: ServiceApiKeys,
+}
+
+impl GroqTranscriptionProvider {
+    pub fn new(http_client: Arc<dyn HttpClient>) -> Self {
+        Self {
+            http_client,
+            api_keys: ServiceApiKeys::new(API_KEY_VAR, PROVIDER_ID, API_URL),
+        }
+    }
+}
+
+impl Service for GroqTranscriptionProvider {
+    fn id(&self) -> &'static str {
+        PROVIDER_ID
+    }
+
+    fn name(&self) -> &'static str {
+        "Groq"
+    }
+}
+
+impl TranscriptionProvider for GroqTranscriptionProvider {
+    fn transcribe(
+        &self,
+        request: TranscriptionRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>> {
+        let http_client = self.http_client.clone();
+        let api_keys = self.api_keys.load(cx);
+        async move {
+            let api_keys = api_keys.await?;
+            let api_key = api_keys
+                .current()
+                .ok_or_else(|| anyhow!("Missing Groq API Key"))?;
+            let transcription = open_ai::audio::create_transcription(
+                http_client.as_ref(),
+                API_URL,
+                api_key,
+                &Account::default(),
+                open_ai::audio::TranscriptionRequest {
+                    model: request
+                        .model
+                        .clone()
+                        .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+                    file_name: request.file_name(),
+                    content_type: request.format.mime_type().to_string(),
+                    language: request.language,
+                    prompt: request.prompt,
+                    audio: request.audio,
+                },
+            )
+            .await?;
+            Ok(transcription.text)
+        }
+        .boxed()
+    }
+}
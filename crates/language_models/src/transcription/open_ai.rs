@@ -0,0 +1,68 @@
+use anyhow::Result;
+use futures::FutureExt as _;
+use futures::future::BoxFuture;
+use gpui::{AsyncApp, Entity};
+
+use super::{TranscriptionProvider, TranscriptionRequest};
+use crate::provider::open_ai::{OpenAiAuth, State};
+use crate::service_registry::Service;
+
+pub(crate) const PROVIDER_ID: &str = "openai";
+const DEFAULT_MODEL: &str = "gpt-4o-transcribe";
+
+/// Transcribes speech with OpenAI's Whisper and `gpt-4o-transcribe` models, using the OpenAI
+/// provider's credentials.
+pub struct OpenAiTranscriptionProvider {
+    state: Entity<State>,
+}
+
+impl OpenAiTranscriptionProvider {
+    pub fn new(state: Entity<State>) -> Self {
+        Self { state }
+    }
+}
+
+impl Service for OpenAiTranscriptionProvider {
+    fn id(&self) -> &'static str {
+        PROVIDER_ID
+    }
+
+    fn name(&self) -> &'static str {
+        "OpenAI"
+    }
+}
+
+impl TranscriptionProvider for OpenAiTranscriptionProvider {
+    fn transcribe(
+        &self,
+        request: TranscriptionRequest,
+        cx: &AsyncApp,
+    ) -> BoxFuture<'static, Result<String>> {
+        let auth = OpenAiAuth::read(&self.state, cx);
+        async move {
+            let auth = auth?;
+            let token = auth.bearer_token().await?;
+            let transcription = open_ai::audio::create_transcription(
+                auth.http_client.as_ref(),
+                &auth.api_url,
+                &token,
+                &auth.account,
+                open_ai::audio::TranscriptionRequest {
+                    model: request
+                        .model
+                        .clone()
+                        .unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+                    file_name: request.file_name(),
+                    content_type: request.format.mime_type().to_string(),
+                    language: request.language,
+                    prompt: request.prompt,
+                    audio: request.audio,
+                },
+            )
+            .await
+            .inspect_err(|error| auth.record_error(&token, error))?;
+            Ok(transcription.text)
+        }
+        .boxed()
+    }
+}
@@ -0,0 +1,258 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{DateTime, Local, TimeDelta, Utc};
+use fs::Fs;
+use gpui::{App, AsyncApp, BackgroundExecutor, Global};
+use language_model::TokenUsage;
+use parking_lot::Mutex;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// How long the usage of each request is kept.
+const RETENTION: TimeDelta = TimeDelta::days(92);
+
+/// The tokens that one request used.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub started_at: DateTime<Utc>,
+    pub provider: String,
+    pub model: String,
+    /// What the request was made for, like `user_prompt` or `inline_assist`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub feature: Option<String>,
+    #[serde(flatten)]
+    pub usage: TokenUsage,
+    /// The estimated cost in US dollars, if the model's price was known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+impl UsageExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+}
+
+#[derive(Default)]
+struct UsageHistoryState {
+    records: Vec<UsageRecord>,
+    /// Whether the records saved by previous sessions were read, before which nothing is saved so
+    /// that they aren't overwritten.
+    loaded: bool,
+    version: usize,
+}
+
+/// The usage of every request sent to a language model in the last three months, for auditing
+/// what the assistant used.
+#[derive(Clone, Default)]
+pub struct UsageHistory {
+    state: Arc<Mutex<UsageHistoryState>>,
+    persistence: Option<(Arc<dyn Fs>, BackgroundExecutor)>,
+}
+
+struct GlobalUsageHistory(UsageHistory);
+
+impl Global for GlobalUsageHistory {}
+
+pub(crate) fn init(fs: Arc<dyn Fs>, cx: &mut App) {
+    let history = UsageHistory {
+        state: Arc::default(),
+        persistence: Some((fs.clone(), cx.background_executor().clone())),
+    };
+    cx.set_global(GlobalUsageHistory(history.clone()));
+    cx.background_spawn(async move {
+        let saved = match fs.load(&history_file_path()).await {
+            Ok(text) => serde_json::from_str::<Vec<UsageRecord>>(&text).unwrap_or_else(|error| {
+                log::error!("failed to read language model usage history: {error}");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        let mut state = history.state.lock();
+        let recorded = std::mem::replace(&mut state.records, saved);
+        let should_save = !recorded.is_empty();
+        state.records.extend(recorded);
+        state.loaded = true;
+        state.version += 1;
+        drop(state);
+        if should_save {
+            history.save();
+        }
+    })
+    .detach();
+}
+
+fn history_file_path() -> PathBuf {
+    paths::data_dir().join("language_model_usage_history.json")
+}
+
+impl UsageHistory {
+    pub fn global(cx: &App) -> Self {
+        cx.try_global::<GlobalUsageHistory>()
+            .map(|history| history.0.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn read_global(cx: &AsyncApp) -> Self {
+        cx.try_read_global(|history: &GlobalUsageHistory, _| history.0.clone())
+            .unwrap_or_default()
+    }
+
+    /// Calls `f` with the recorded requests, oldest first.
+    pub fn read<R>(&self, f: impl FnOnce(&[UsageRecord]) -> R) -> R {
+        f(&self.state.lock().records)
+    }
+
+    /// Changes whenever a request is recorded.
+    pub fn version(&self) -> usize {
+        self.state.lock().version
+    }
+
+    pub(crate) fn record(&self, record: UsageRecord) {
+        let mut state = self.state.lock();
+        state.records.push(record);
+        state.version += 1;
+        let loaded = state.loaded;
+        drop(state);
+        if loaded {
+            self.save();
+        }
+    }
+
+    fn save(&self) {
+        let Some((fs, executor)) = self.persistence.clone() else {
+            return;
+        };
+        let mut state = self.state.lock();
+        let cutoff = Utc::now() - RETENTION;
+        state.records.retain(|record| record.started_at >= cutoff);
+        let text = match serde_json::to_string(&state.records) {
+            Ok(text) => text,
+            Err(error) => {
+                log::error!("failed to serialize language model usage history: {error}");
+                return;
+            }
+        };
+        drop(state);
+        executor
+            .spawn(async move {
+                if let Err(error) = fs.atomic_write(history_file_path(), text).await {
+                    log::error!("failed to save language model usage history: {error}");
+                }
+            })
+            .detach();
+    }
+}
+
+fn to_csv(records: &[UsageRecord]) -> String {
+    let mut csv = String::from(
+        "started_at,provider,model,feature,input_tokens,output_tokens,cache_creation_input_tokens,cache_read_input_tokens,reasoning_tokens,cost_usd\n",
+    );
+    for record in records {
+        let fields = [
+            record
+                .started_at
+                .with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+            record.provider.clone(),
+            record.model.clone(),
+            record.feature.clone().unwrap_or_default(),
+            record.usage.input_tokens.to_string(),
+            record.usage.output_tokens.to_string(),
+            record.usage.cache_creation_input_tokens.to_string(),
+            record.usage.cache_read_input_tokens.to_string(),
+            record.usage.reasoning_tokens.to_string(),
+            record
+                .cost
+                .map(|cost| format!("{cost:.6}"))
+                .unwrap_or_default(),
+        ];
+        let row = fields
+            .iter()
+            .map(|field| {
+                if field.contains([',', '"', '\n']) {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field.clone()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push_str(&row);
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Writes the usage of every recorded request to a file and reveals it.
+pub fn export_usage_history(format: UsageExportFormat, fs: Arc<dyn Fs>, cx: &mut App) {
+    let records = UsageHistory::global(cx).read(|records| records.to_vec());
+    let text = match format {
+        UsageExportFormat::Csv => to_csv(&records),
+        UsageExportFormat::Json => match serde_json::to_string_pretty(&records) {
+            Ok(text) => text,
+            Err(error) => {
+                log::error!("failed to serialize language model usage history: {error}");
+                return;
+            }
+        },
+    };
+    let path = paths::data_dir().join(format!(
+        "language_model_usage_history.{}",
+        format.extension()
+    ));
+    cx.spawn(async move |cx| {
+        fs.atomic_write(path.clone(), text).await?;
+        cx.update(|cx| cx.reveal_path(&path))
+    })
+    .detach_and_log_err(cx);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_history_csv() {
+        let record = UsageRecord {
+            started_at: Utc::now(),
+            provider: "openai".into(),
+            model: "gpt-4.1".into(),
+            feature: Some("user_prompt".into()),
+            usage: TokenUsage {
+                input_tokens: 1_000,
+                output_tokens: 200,
+                cache_creation_input_tokens: 0,
+                cache_read_input_tokens: 500,
+                reasoning_tokens: 0,
+            },
+            cost: Some(0.00385),
+        };
+        let row = to_csv(&[record.clone()])
+            .lines()
+            .nth(1)
+            .map(ToString::to_string);
+        assert_eq!(
+            row.as_deref()
+                .and_then(|row| row.split_once(','))
+                .map(|(_, rest)| rest),
+            Some("openai,gpt-4.1,user_prompt,1000,200,0,500,0,0.003850")
+        );
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(serde_json::from_str::<UsageRecord>(&json).unwrap(), record);
+    }
+}
@@ -0,0 +1,41 @@
+use gpui::{App, AppContext as _, Entity, EventEmitter, Global};
+
+/// A warning about a request that was still sent, for the UI to show to the user.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LanguageModelWarning {
+    /// A provider's spend is getting close to one of its `spend_limits`.
+    NearSpendLimit {
+        provider: &'static str,
+        message: String,
+    },
+    /// A prompt was sent even though moderation flagged it.
+    ModerationFlagged { message: String },
+}
+
+/// Emits the [`LanguageModelWarning`]s raised while sending requests.
+pub struct LanguageModelWarnings;
+
+impl EventEmitter<LanguageModelWarning> for LanguageModelWarnings {}
+
+struct GlobalLanguageModelWarnings(Entity<LanguageModelWarnings>);
+
+impl Global for GlobalLanguageModelWarnings {}
+
+pub(crate) fn init(cx: &mut App) {
+    let warnings = cx.new(|_| LanguageModelWarnings);
+    cx.set_global(GlobalLanguageModelWarnings(warnings));
+}
+
+impl LanguageModelWarnings {
+    pub fn global(cx: &App) -> Option<Entity<Self>> {
+        cx.try_global::<GlobalLanguageModelWarnings>()
+            .map(|warnings| warnings.0.clone())
+    }
+
+    pub(crate) fn emit(warning: LanguageModelWarning, cx: &mut App) {
+        match Self::global(cx) {
+            Some(warnings) => warnings.update(cx, |_, cx| cx.emit(warning)),
+            None => log::warn!("{warning:?}"),
+        }
+    }
+}
@@ -83,11 +83,12 @@ impl Model {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
+#[serde(rename_all = "lowercase")]
 pub enum ToolChoice {
     Auto,
     Required,
     None,
+    #[serde(untagged)]
     Other(ToolDefinition),
 }
 
@@ -101,7 +102,9 @@ pub enum ToolDefinition {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FunctionDefinition {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub parameters: Option<Value>,
 }
 
@@ -151,17 +154,35 @@ pub struct ChatCompletionRequest {
     pub messages: Vec<ChatMessage>,
     pub stream: bool,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stop: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
     #[serde(skip_serializing_if = "Vec::is_empty")]
     pub tools: Vec<ToolDefinition>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<ToolChoice>,
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StreamOptions {
+    /// Whether to send the request's token usage in a final chunk without any choices.
+    pub include_usage: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ChatResponse {
     pub id: String,
@@ -224,6 +245,7 @@ pub struct ResponseStreamEvent {
     pub created: u32,
     pub model: String,
     pub object: String,
+    #[serde(default)]
     pub choices: Vec<ChoiceDelta>,
     pub usage: Option<Usage>,
 }
@@ -278,6 +300,9 @@ pub struct ResponseMessageDelta {
     pub content: Option<String>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCallChunk>>,
+    /// The reasoning of reasoning models, when LM Studio is set to report it separately.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
 }
 
 pub async fn complete(
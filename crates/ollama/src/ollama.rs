@@ -38,6 +38,9 @@ pub struct Model {
     pub max_tokens: usize,
     pub keep_alive: Option<KeepAlive>,
     pub supports_tools: Option<bool>,
+    pub supports_images: Option<bool>,
+    pub supports_thinking: Option<bool>,
+    pub supports_parallel_tool_calls: Option<bool>,
 }
 
 fn get_max_tokens(name: &str) -> usize {
@@ -76,6 +79,9 @@ impl Model {
             max_tokens: max_tokens.unwrap_or_else(|| get_max_tokens(name)),
             keep_alive: Some(KeepAlive::indefinite()),
             supports_tools,
+            supports_images: None,
+            supports_thinking: None,
+            supports_parallel_tool_calls: None,
         }
     }
 
@@ -101,6 +107,9 @@ pub enum ChatMessage {
     },
     User {
         content: String,
+        /// Base64-encoded images, for multimodal models.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        images: Option<Vec<String>>,
     },
     System {
         content: String,
@@ -158,6 +167,8 @@ pub struct ChatOptions {
     pub stop: Option<Vec<String>>,
     pub temperature: Option<f32>,
     pub top_p: Option<f32>,
+    pub top_k: Option<u32>,
+    pub seed: Option<i64>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -167,7 +178,6 @@ pub struct ChatResponseDelta {
     #[allow(unused)]
     pub created_at: String,
     pub message: ChatMessage,
-    #[allow(unused)]
     pub done_reason: Option<String>,
     #[allow(unused)]
     pub done: bool,
@@ -215,6 +225,14 @@ impl ModelShow {
         // .contains expects &String, which would require an additional allocation
         self.capabilities.iter().any(|v| v == "tools")
     }
+
+    pub fn supports_vision(&self) -> bool {
+        self.capabilities.iter().any(|v| v == "vision")
+    }
+
+    pub fn supports_thinking(&self) -> bool {
+        self.capabilities.iter().any(|v| v == "thinking")
+    }
 }
 
 pub async fn complete(
@@ -367,6 +385,46 @@ pub async fn preload_model(client: Arc<dyn HttpClient>, api_url: &str, model: &s
     }
 }
 
+#[derive(Serialize)]
+struct EmbedRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+
+#[derive(Deserialize)]
+pub struct EmbedResponse {
+    pub embeddings: Vec<Vec<f32>>,
+}
+
+/// Embeds `input` with `model`, which has to be an embedding model like `nomic-embed-text`.
+pub async fn embed(
+    client: &dyn HttpClient,
+    api_url: &str,
+    model: &str,
+    input: &[String],
+) -> Result<EmbedResponse> {
+    let uri = format!("{api_url}/api/embed");
+    let request = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .body(AsyncBody::from(serde_json::to_string(&EmbedRequest {
+            model,
+            input,
+        })?))?;
+
+    let mut response = client.send(request).await?;
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    anyhow::ensure!(
+        response.status().is_success(),
+        "Failed to connect to Ollama API: {} {}",
+        response.status(),
+        body,
+    );
+    serde_json::from_str(&body).context("Unable to parse Ollama embed response")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -520,6 +578,8 @@ mod tests {
 
         let result: ModelShow = serde_json::from_value(response).unwrap();
         assert!(result.supports_tools());
+        assert!(!result.supports_vision());
+        assert!(!result.supports_thinking());
         assert!(result.capabilities.contains(&"tools".to_string()));
         assert!(result.capabilities.contains(&"completion".to_string()));
     }
@@ -0,0 +1,186 @@
+use anyhow::{Result, anyhow};
+use futures::AsyncReadExt;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::Account;
+
+pub const DEFAULT_SPEECH_MODEL: &str = "gpt-4o-mini-tts";
+pub const DEFAULT_VOICE: &str = "alloy";
+
+#[derive(Debug, Serialize)]
+pub struct SpeechRequest {
+    pub model: String,
+    pub input: String,
+    pub voice: String,
+    /// One of `mp3`, `opus`, `aac`, `flac`, `wav`, or `pcm`.
+    pub response_format: String,
+    /// How the voice should sound, e.g. "Speak calmly". Only supported by `gpt-4o-mini-tts`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed: Option<f32>,
+}
+
+/// Requests speech for `request.input`, returning the response's body so that the audio can be
+/// played while the rest of it is still being generated.
+pub async fn create_speech(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    account: &Account,
+    request: SpeechRequest,
+) -> Result<AsyncBody> {
+    let uri = format!("{api_url}/audio/speech");
+    let mut request_builder = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key));
+    for (name, value) in account.headers() {
+        request_builder = request_builder.header(name, value);
+    }
+
+    let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request)?))?;
+    let mut response = client.send(request).await?;
+    if response.status().is_success() {
+        return Ok(response.into_body());
+    }
+
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    Err(error_from_response(
+        "generate speech",
+        response.status(),
+        &body,
+    ))
+}
+
+#[derive(Debug)]
+pub struct TranscriptionRequest {
+    pub model: String,
+    pub audio: Vec<u8>,
+    /// The audio's file name, whose extension tells the API which format the audio is in.
+    pub file_name: String,
+    pub content_type: String,
+    /// The language spoken in the audio, as an ISO-639-1 code like `en`.
+    pub language: Option<String>,
+    /// Text that the audio follows on from, such as earlier messages or names that occur in it.
+    pub prompt: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Transcription {
+    pub text: String,
+}
+
+/// Transcribes speech with `/audio/transcriptions`, which Groq's OpenAI-compatible API serves
+/// too.
+pub async fn create_transcription(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    account: &Account,
+    request: TranscriptionRequest,
+) -> Result<Transcription> {
+    let mut fields = vec![("model", request.model.as_str())];
+    if let Some(language) = request.language.as_deref() {
+        fields.push(("language", language));
+    }
+    if let Some(prompt) = request.prompt.as_deref() {
+        fields.push(("prompt", prompt));
+    }
+    let (boundary, body) = multipart_body(
+        &fields,
+        &request.file_name,
+        &request.content_type,
+        &request.audio,
+    );
+
+    let uri = format!("{api_url}/audio/transcriptions");
+    let mut request_builder = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header(
+            "Content-Type",
+            format!("multipart/form-data; boundary={boundary}"),
+        )
+        .header("Authorization", format!("Bearer {}", api_key));
+    for (name, value) in account.headers() {
+        request_builder = request_builder.header(name, value);
+    }
+
+    let mut response = client
+        .send(request_builder.body(AsyncBody::from(body))?)
+        .await?;
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    if !response.status().is_success() {
+        return Err(error_from_response(
+            "transcribe audio",
+            response.status(),
+            &body,
+        ));
+    }
+    Ok(serde_json::from_str(&body)?)
+}
+
+/// Encodes `fields` and a `file` field as `multipart/form-data`, returning the boundary that
+/// separates them.
+fn multipart_body(
+    fields: &[(&str, &str)],
+    file_name: &str,
+    content_type: &str,
+    file: &[u8],
+) -> (String, Vec<u8>) {
+    let mut boundary = "zed-audio-upload".to_string();
+    while file
+        .windows(boundary.len())
+        .any(|window| window == boundary.as_bytes())
+    {
+        boundary.push('-');
+    }
+
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{boundary}\r\n\
+                 Content-Disposition: form-data; name=\"{name}\"\r\n\r\n\
+                 {value}\r\n"
+            )
+            .as_bytes(),
+        );
+    }
+    body.extend_from_slice(
+        format!(
+            "--{boundary}\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\n\
+             Content-Type: {content_type}\r\n\r\n"
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(file);
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    (boundary, body)
+}
+
+fn error_from_response(action: &str, status: StatusCode, body: &str) -> anyhow::Error {
+    #[derive(Deserialize)]
+    struct OpenAiResponse {
+        error: OpenAiError,
+    }
+
+    #[derive(Deserialize)]
+    struct OpenAiError {
+        message: String,
+    }
+
+    match serde_json::from_str::<OpenAiResponse>(body) {
+        Ok(response) if !response.error.message.is_empty() => anyhow!(
+            "Failed to {action} with OpenAI API: {}",
+            response.error.message,
+        ),
+        _ => anyhow!("Failed to {action} with OpenAI API: {status} {body}"),
+    }
+}
@@ -0,0 +1,267 @@
+use anyhow::{Context as _, Result, anyhow};
+use futures::AsyncReadExt;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::{Account, Request, Response, with_extra_body};
+
+/// The endpoint that batched requests are sent to, relative to the API's root, which batch APIs
+/// expect to include the version even when the API URL does too.
+const CHAT_COMPLETIONS_ENDPOINT: &str = "/v1/chat/completions";
+
+/// One line of a batch's input file.
+#[derive(Debug, Serialize)]
+struct BatchInputLine<'a> {
+    custom_id: &'a str,
+    method: &'static str,
+    url: &'static str,
+    body: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct File {
+    id: String,
+}
+
+#[derive(Debug, Serialize)]
+struct CreateBatch<'a> {
+    input_file_id: &'a str,
+    endpoint: &'static str,
+    completion_window: &'static str,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Batch {
+    pub id: String,
+    /// One of `validating`, `failed`, `in_progress`, `finalizing`, `completed`, `expired`,
+    /// `cancelling`, or `cancelled`.
+    pub status: String,
+    #[serde(default)]
+    pub output_file_id: Option<String>,
+    /// The file with the results of requests that failed.
+    #[serde(default)]
+    pub error_file_id: Option<String>,
+    #[serde(default)]
+    pub errors: Option<BatchErrors>,
+}
+
+impl Batch {
+    /// Whether the batch won't make any more progress, so its results can be downloaded.
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            "failed" | "completed" | "expired" | "cancelled"
+        )
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchErrors {
+    #[serde(default)]
+    pub data: Vec<BatchError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchError {
+    #[serde(default)]
+    pub code: Option<String>,
+    pub message: String,
+}
+
+/// One line of a batch's output or error file.
+#[derive(Debug, Deserialize)]
+pub struct BatchOutputLine {
+    pub custom_id: String,
+    #[serde(default)]
+    pub response: Option<BatchResponse>,
+    #[serde(default)]
+    pub error: Option<BatchError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BatchResponse {
+    pub status_code: u16,
+    pub body: serde_json::Value,
+}
+
+impl BatchOutputLine {
+    /// The completion that the request was answered with, or why it failed.
+    pub fn into_result(self) -> Result<Response> {
+        if let Some(error) = self.error {
+            anyhow::bail!("batched request failed: {}", error.message);
+        }
+        let response = self
+            .response
+            .context("batched request has neither a response nor an error")?;
+        if response.status_code != 200 {
+            anyhow::bail!(
+                "batched request failed: {} {}",
+                response.status_code,
+                response.body
+            );
+        }
+        serde_json::from_value(response.body).context("failed to parse batched response")
+    }
+}
+
+/// Uploads `requests`, by their custom IDs, and starts a batch job that completes them within
+/// a day.
+pub async fn create_batch(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    account: &Account,
+    requests: &[(String, Request)],
+) -> Result<Batch> {
+    let mut input = String::new();
+    for (custom_id, request) in requests {
+        input.push_str(&serde_json::to_string(&BatchInputLine {
+            custom_id,
+            method: "POST",
+            url: CHAT_COMPLETIONS_ENDPOINT,
+            body: with_extra_body(request, &request.extra_body)?,
+        })?);
+        input.push('\n');
+    }
+
+    let mut boundary = "zed-batch-input".to_string();
+    while input.contains(&boundary) {
+        boundary.push('-');
+    }
+    let body = format!(
+        "--{boundary}\r\n\
+         Content-Disposition: form-data; name=\"purpose\"\r\n\r\n\
+         batch\r\n\
+         --{boundary}\r\n\
+         Content-Disposition: form-data; name=\"file\"; filename=\"batch.jsonl\"\r\n\
+         Content-Type: application/jsonl\r\n\r\n\
+         {input}\r\n\
+         --{boundary}--\r\n"
+    );
+    let file: File = send(
+        client,
+        authorized(Method::POST, format!("{api_url}/files"), api_key, account)
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(AsyncBody::from(body))?,
+    )
+    .await
+    .context("failed to upload batch input")?;
+
+    let create_batch = CreateBatch {
+        input_file_id: &file.id,
+        endpoint: CHAT_COMPLETIONS_ENDPOINT,
+        completion_window: "24h",
+    };
+    send(
+        client,
+        authorized(Method::POST, format!("{api_url}/batches"), api_key, account)
+            .header("Content-Type", "application/json")
+            .body(AsyncBody::from(serde_json::to_string(&create_batch)?))?,
+    )
+    .await
+    .context("failed to create batch")
+}
+
+pub async fn retrieve_batch(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    account: &Account,
+    batch_id: &str,
+) -> Result<Batch> {
+    send(
+        client,
+        authorized(
+            Method::GET,
+            format!("{api_url}/batches/{batch_id}"),
+            api_key,
+            account,
+        )
+        .body(AsyncBody::empty())?,
+    )
+    .await
+}
+
+/// The lines of a finished batch's output and error files.
+pub async fn batch_output(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    account: &Account,
+    batch: &Batch,
+) -> Result<Vec<BatchOutputLine>> {
+    let mut lines = Vec::new();
+    for file_id in batch.output_file_id.iter().chain(&batch.error_file_id) {
+        let request = authorized(
+            Method::GET,
+            format!("{api_url}/files/{file_id}/content"),
+            api_key,
+            account,
+        )
+        .body(AsyncBody::empty())?;
+        let body = read_body(client, request).await?;
+        for line in body.lines().filter(|line| !line.trim().is_empty()) {
+            lines.push(serde_json::from_str(line).context("failed to parse batch output")?);
+        }
+    }
+    Ok(lines)
+}
+
+fn authorized(
+    method: Method,
+    uri: String,
+    api_key: &str,
+    account: &Account,
+) -> http_client::http::request::Builder {
+    let mut request_builder = HttpRequest::builder()
+        .method(method)
+        .uri(uri)
+        .header("Authorization", format!("Bearer {}", api_key));
+    for (name, value) in account.headers() {
+        request_builder = request_builder.header(name, value);
+    }
+    request_builder
+}
+
+async fn send<T: DeserializeOwned>(
+    client: &dyn HttpClient,
+    request: HttpRequest<AsyncBody>,
+) -> Result<T> {
+    let body = read_body(client, request).await?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+async fn read_body(client: &dyn HttpClient, request: HttpRequest<AsyncBody>) -> Result<String> {
+    let mut response = client.send(request).await?;
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    if response.status().is_success() {
+        return Ok(body);
+    }
+
+    #[derive(Deserialize)]
+    struct OpenAiResponse {
+        error: OpenAiError,
+    }
+
+    #[derive(Deserialize)]
+    struct OpenAiError {
+        message: String,
+    }
+
+    match serde_json::from_str::<OpenAiResponse>(&body) {
+        Ok(response) if !response.error.message.is_empty() => Err(anyhow!(
+            "Failed to connect to OpenAI API: {}",
+            response.error.message,
+        )),
+        _ => anyhow::bail!(
+            "Failed to connect to OpenAI API: {} {}",
+            response.status(),
+            body,
+        ),
+    }
+}
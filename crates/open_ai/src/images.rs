@@ -0,0 +1,80 @@
+use anyhow::{Result, anyhow};
+use futures::AsyncReadExt;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::Account;
+
+/// A request to `/images/generations`, which xAI's API serves too.
+#[derive(Debug, Serialize)]
+pub struct ImageGenerationRequest {
+    pub model: String,
+    pub prompt: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    /// Like `1024x1024`. Not supported by xAI.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<String>,
+    /// `b64_json` to get the images themselves rather than URLs. `gpt-image-1` always returns
+    /// them, and rejects this parameter.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImageGenerationResponse {
+    pub data: Vec<GeneratedImage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GeneratedImage {
+    #[serde(default)]
+    pub b64_json: Option<String>,
+    /// The prompt the image was generated from, for models that rewrite prompts.
+    #[serde(default)]
+    pub revised_prompt: Option<String>,
+}
+
+pub async fn create_image(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    account: &Account,
+    request: ImageGenerationRequest,
+) -> Result<ImageGenerationResponse> {
+    let uri = format!("{api_url}/images/generations");
+    let mut request_builder = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key));
+    for (name, value) in account.headers() {
+        request_builder = request_builder.header(name, value);
+    }
+
+    let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request)?))?;
+    let mut response = client.send(request).await?;
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    if response.status().is_success() {
+        return Ok(serde_json::from_str(&body)?);
+    }
+
+    #[derive(Deserialize)]
+    struct OpenAiResponse {
+        error: OpenAiError,
+    }
+
+    #[derive(Deserialize)]
+    struct OpenAiError {
+        message: String,
+    }
+
+    match serde_json::from_str::<OpenAiResponse>(&body) {
+        Ok(response) if !response.error.message.is_empty() => Err(anyhow!(
+            "Failed to generate image: {}",
+            response.error.message,
+        )),
+        _ => anyhow::bail!("Failed to generate image: {} {}", response.status(), body),
+    }
+}
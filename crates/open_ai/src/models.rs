@@ -0,0 +1,132 @@
+use anyhow::{Result, anyhow};
+use futures::AsyncReadExt;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::Deserialize;
+
+use crate::Account;
+
+#[derive(Debug, Deserialize)]
+pub struct ModelsResponse {
+    pub data: Vec<ModelObject>,
+}
+
+/// A model listed by `/models`. OpenAI only lists models' IDs, but compatible servers like
+/// OpenRouter describe what each model takes too.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ModelObject {
+    pub id: String,
+    #[serde(default)]
+    pub architecture: Option<ModelArchitecture>,
+    /// The request parameters that the model accepts, like `tools` and `response_format`.
+    #[serde(default)]
+    pub supported_parameters: Option<Vec<String>>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ModelArchitecture {
+    /// What the model takes as input, like `text`, `image`, and `file`.
+    #[serde(default)]
+    pub input_modalities: Vec<String>,
+}
+
+impl ModelObject {
+    /// Whether the model takes images as input, if the server says.
+    pub fn supports_images(&self) -> Option<bool> {
+        let architecture = self.architecture.as_ref()?;
+        Some(
+            architecture
+                .input_modalities
+                .iter()
+                .any(|modality| modality == "image"),
+        )
+    }
+
+    /// Whether the model can call tools, if the server says.
+    pub fn supports_tools(&self) -> Option<bool> {
+        let parameters = self.supported_parameters.as_ref()?;
+        Some(parameters.iter().any(|parameter| parameter == "tools"))
+    }
+}
+
+pub async fn list_models(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    account: &Account,
+) -> Result<Vec<ModelObject>> {
+    let uri = format!("{api_url}/models");
+    let mut request_builder = HttpRequest::builder()
+        .method(Method::GET)
+        .uri(uri)
+        .header("Accept", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key));
+    for (name, value) in account.headers() {
+        request_builder = request_builder.header(name, value);
+    }
+
+    let mut response = client
+        .send(request_builder.body(AsyncBody::default())?)
+        .await?;
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Failed to list models: {} {}",
+            response.status(),
+            body
+        ));
+    }
+    let response: ModelsResponse = serde_json::from_str(&body)?;
+    Ok(response.data)
+}
+
+/// A fine-tuned model's ID, like `ft:gpt-4o-mini-2024-07-18:acme:support-bot:9xK2pLq1`. Mistral's
+/// fine-tuned models start with `ft:` and the base model too, like
+/// `ft:open-mistral-7b:587a6b29:20240514:7e773925`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FineTunedModelId<'a> {
+    /// The model that was fine-tuned, like `gpt-4o-mini-2024-07-18`.
+    pub base_model: &'a str,
+    /// The name given to an OpenAI fine-tuning job, if any, like `support-bot`.
+    pub suffix: Option<&'a str>,
+}
+
+impl<'a> FineTunedModelId<'a> {
+    pub fn parse(id: &'a str) -> Option<Self> {
+        let mut parts = id.strip_prefix("ft:")?.split(':');
+        let base_model = parts.next().filter(|base_model| !base_model.is_empty())?;
+        let suffix = parts.nth(1).filter(|suffix| !suffix.is_empty());
+        Some(Self { base_model, suffix })
+    }
+
+    /// The base model without the date of its snapshot, like `gpt-4o-mini`, which is how
+    /// built-in models are named.
+    pub fn base_model_family(&self) -> &'a str {
+        let is_date = |date: &str, lengths: &[usize]| {
+            let parts = date.split('-').collect::<Vec<_>>();
+            parts.len() == lengths.len()
+                && parts.iter().zip(lengths).all(|(part, len)| {
+                    part.len() == *len && part.bytes().all(|byte| byte.is_ascii_digit())
+                })
+        };
+        // Snapshots are dated like `-2024-07-18`, or like `-0125` for older models.
+        for lengths in [&[4, 2, 2][..], &[4][..]] {
+            let date_len = lengths.iter().sum::<usize>() + lengths.len();
+            let Some(split) = self.base_model.len().checked_sub(date_len) else {
+                continue;
+            };
+            let (Some(base), Some(date)) =
+                (self.base_model.get(..split), self.base_model.get(split..))
+            else {
+                continue;
+            };
+            if date
+                .strip_prefix('-')
+                .is_some_and(|date| is_date(date, lengths))
+            {
+                return base;
+            }
+        }
+        self.base_model
+    }
+}
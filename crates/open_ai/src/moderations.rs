@@ -0,0 +1,84 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Result, anyhow};
+use futures::AsyncReadExt;
+use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
+use serde::{Deserialize, Serialize};
+
+use crate::Account;
+
+pub const DEFAULT_MODERATION_MODEL: &str = "omni-moderation-latest";
+
+#[derive(Debug, Serialize)]
+pub struct ModerationRequest {
+    pub model: String,
+    pub input: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModerationResponse {
+    pub results: Vec<ModerationResult>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModerationResult {
+    pub flagged: bool,
+    /// Whether the input falls into each category, like `harassment` or `self-harm/intent`.
+    #[serde(default)]
+    pub categories: BTreeMap<String, bool>,
+}
+
+impl ModerationResult {
+    /// The categories that the input was flagged for.
+    pub fn flagged_categories(&self) -> impl Iterator<Item = &str> {
+        self.categories
+            .iter()
+            .filter(|(_, flagged)| **flagged)
+            .map(|(category, _)| category.as_str())
+    }
+}
+
+/// Classifies `request.input` with `/moderations`, which is free to call.
+pub async fn create_moderation(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    account: &Account,
+    request: ModerationRequest,
+) -> Result<ModerationResponse> {
+    let uri = format!("{api_url}/moderations");
+    let mut request_builder = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key));
+    for (name, value) in account.headers() {
+        request_builder = request_builder.header(name, value);
+    }
+
+    let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request)?))?;
+    let mut response = client.send(request).await?;
+    let mut body = String::new();
+    response.body_mut().read_to_string(&mut body).await?;
+    if response.status().is_success() {
+        return Ok(serde_json::from_str(&body)?);
+    }
+
+    #[derive(Deserialize)]
+    struct OpenAiResponse {
+        error: OpenAiError,
+    }
+
+    #[derive(Deserialize)]
+    struct OpenAiError {
+        message: String,
+    }
+
+    match serde_json::from_str::<OpenAiResponse>(&body) {
+        Ok(response) if !response.error.message.is_empty() => Err(anyhow!(
+            "Failed to moderate prompt: {}",
+            response.error.message,
+        )),
+        _ => anyhow::bail!("Failed to moderate prompt: {} {}", response.status(), body),
+    }
+}
@@ -8,23 +8,53 @@ use http_client::{AsyncBody, HttpClient, Method, Request as HttpRequest};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::{
+    collections::BTreeMap,
     convert::TryFrom,
     future::{self, Future},
 };
 use strum::EnumIter;
 
+pub mod audio;
+pub mod batches;
+pub mod images;
+pub mod models;
+pub mod moderations;
+pub mod realtime;
+pub mod responses;
+
 pub const OPEN_AI_API_URL: &str = "https://api.openai.com/v1";
 
+/// The organization and project that requests are billed to, for API keys that have access to
+/// several of them.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Account {
+    pub organization_id: Option<String>,
+    pub project_id: Option<String>,
+}
+
+impl Account {
+    fn headers(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("OpenAI-Organization", self.organization_id.as_deref()),
+            ("OpenAI-Project", self.project_id.as_deref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value?)))
+    }
+}
+
 fn is_none_or_empty<T: AsRef<[U]>, U>(opt: &Option<T>) -> bool {
     opt.as_ref().map_or(true, |v| v.as_ref().is_empty())
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Eq, PartialEq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Role {
     User,
     Assistant,
     System,
+    /// Instructions for reasoning models, which take them in place of system messages.
+    Developer,
     Tool,
 }
 
@@ -36,6 +66,7 @@ impl TryFrom<String> for Role {
             "user" => Ok(Self::User),
             "assistant" => Ok(Self::Assistant),
             "system" => Ok(Self::System),
+            "developer" => Ok(Self::Developer),
             "tool" => Ok(Self::Tool),
             _ => anyhow::bail!("invalid role '{value}'"),
         }
@@ -48,6 +79,7 @@ impl From<Role> for String {
             Role::User => "user".to_owned(),
             Role::Assistant => "assistant".to_owned(),
             Role::System => "system".to_owned(),
+            Role::Developer => "developer".to_owned(),
             Role::Tool => "tool".to_owned(),
         }
     }
@@ -94,9 +126,27 @@ pub enum Model {
         max_tokens: usize,
         max_output_tokens: Option<u32>,
         max_completion_tokens: Option<u32>,
+        /// How the model takes the instructions in system messages.
+        #[serde(default)]
+        system_prompt_role: Option<SystemPromptRole>,
     },
 }
 
+/// The role that a model takes its instructions in.
+#[cfg_attr(feature = "schemars", derive(schemars::JsonSchema))]
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SystemPromptRole {
+    /// In `system` messages.
+    #[default]
+    System,
+    /// In `developer` messages, which replace system messages for reasoning models.
+    Developer,
+    /// At the start of the first user message, for models that take neither system nor
+    /// developer messages.
+    User,
+}
+
 impl Model {
     pub fn default_fast() -> Self {
         Self::FourPointOneMini
@@ -210,37 +260,183 @@ impl Model {
             _ => false,
         }
     }
+
+    /// The role that the model takes its instructions in. Requests with system messages are
+    /// rejected by models that don't take them.
+    pub fn system_prompt_role(&self) -> SystemPromptRole {
+        match self {
+            Self::O1Preview | Self::O1Mini => SystemPromptRole::User,
+            Self::O1 | Self::O3Mini | Self::O3 | Self::O4Mini => SystemPromptRole::Developer,
+            Self::Custom {
+                system_prompt_role, ..
+            } => system_prompt_role.unwrap_or_default(),
+            _ => SystemPromptRole::System,
+        }
+    }
+
+    /// Whether the model takes images as input.
+    pub fn supports_images(&self) -> bool {
+        matches!(
+            self,
+            Self::FourTurbo
+                | Self::FourOmni
+                | Self::FourOmniMini
+                | Self::FourPointOne
+                | Self::FourPointOneMini
+                | Self::FourPointOneNano
+                | Self::O1
+                | Self::O3
+                | Self::O4Mini
+        )
+    }
+
+    /// Whether the model reasons before it responds, and takes a `reasoning_effort`.
+    pub fn supports_reasoning(&self) -> bool {
+        matches!(self, Self::O1 | Self::O3 | Self::O3Mini | Self::O4Mini)
+    }
+
+    /// Whether the model takes audio as input. None of the built-in models do, but audio models
+    /// like `gpt-4o-audio-preview` can be added as custom models.
+    pub fn supports_audio(&self) -> bool {
+        let id = self.id();
+        id.starts_with("gpt-4o-audio") || id.starts_with("gpt-4o-mini-audio")
+    }
+
+    /// Returns whether the given model supports predicted outputs, through the `prediction`
+    /// parameter.
+    pub fn supports_prediction(&self) -> bool {
+        matches!(
+            self,
+            Self::FourOmni
+                | Self::FourOmniMini
+                | Self::FourPointOne
+                | Self::FourPointOneMini
+                | Self::FourPointOneNano
+        )
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Request {
     pub model: String,
     pub messages: Vec<RequestMessage>,
     pub stream: bool,
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub max_tokens: Option<u32>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub stop: Vec<String>,
     pub temperature: f32,
     #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub logit_bias: BTreeMap<u32, f32>,
+    /// How many completions to generate for the request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    /// Whether to return the log probabilities of the generated tokens.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<bool>,
+    /// How many of the most likely tokens to return the log probabilities of at each position.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_logprobs: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub tool_choice: Option<ToolChoice>,
     /// Whether to enable parallel function calling during tool use.
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub parallel_tool_calls: Option<bool>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tools: Vec<ToolDefinition>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<ResponseFormat>,
+    /// A JSON schema that vLLM constrains the response to, for servers that don't support
+    /// `json_schema` response formats.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub guided_json: Option<Value>,
+    /// How much effort reasoning models put into reasoning.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<ReasoningEffort>,
+    /// Text that the response is expected to mostly repeat, which makes it faster to generate.
+    /// Can't be combined with tools, `n` or `logprobs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prediction: Option<Prediction>,
+    /// Parameters to send beyond the ones above, for features of OpenAI-compatible servers that
+    /// aren't supported directly. They replace any parameter of the same name.
+    #[serde(skip)]
+    pub extra_body: serde_json::Map<String, Value>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReasoningEffort {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    Text,
+    JsonObject,
+    JsonSchema { json_schema: JsonSchemaFormat },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonSchemaFormat {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub schema: Value,
+    /// Whether the response must follow the schema exactly, which requires the schema to be in
+    /// the subset of JSON schema that OpenAI supports.
+    #[serde(default)]
+    pub strict: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StreamOptions {
+    /// Whether to send the request's token usage in a final chunk without any choices.
+    pub include_usage: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompletionRequest {
     pub model: String,
     pub prompt: String,
-    pub max_tokens: u32,
-    pub temperature: f32,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub prediction: Option<Prediction>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub rewrite_speculation: Option<bool>,
+    /// Parameters to send beyond the ones above, for features of OpenAI-compatible servers that
+    /// aren't supported directly. They replace any parameter of the same name.
+    #[serde(skip)]
+    pub extra_body: serde_json::Map<String, Value>,
 }
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
@@ -249,12 +445,13 @@ pub enum Prediction {
     Content { content: String },
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(untagged)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ToolChoice {
     Auto,
     Required,
     None,
+    #[serde(untagged)]
     Other(ToolDefinition),
 }
 
@@ -268,11 +465,17 @@ pub enum ToolDefinition {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct FunctionDefinition {
     pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub parameters: Option<Value>,
+    /// Whether the arguments must follow the parameters schema exactly, which requires the
+    /// schema to be in the subset of JSON schema that OpenAI supports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strict: Option<bool>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 #[serde(tag = "role", rename_all = "lowercase")]
 pub enum RequestMessage {
     Assistant {
@@ -286,6 +489,9 @@ pub enum RequestMessage {
     System {
         content: MessageContent,
     },
+    Developer {
+        content: MessageContent,
+    },
     Tool {
         content: MessageContent,
         tool_call_id: String,
@@ -312,7 +518,9 @@ impl MessageContent {
             }
             MessageContent::Multipart(parts) if parts.is_empty() => match part {
                 MessagePart::Text { text } => *self = MessageContent::Plain(text),
-                MessagePart::Image { .. } => *self = MessageContent::Multipart(vec![part]),
+                MessagePart::Image { .. }
+                | MessagePart::File { .. }
+                | MessagePart::InputAudio { .. } => *self = MessageContent::Multipart(vec![part]),
             },
             MessageContent::Multipart(parts) => parts.push(part),
         }
@@ -336,6 +544,10 @@ pub enum MessagePart {
     Text { text: String },
     #[serde(rename = "image_url")]
     Image { image_url: ImageUrl },
+    #[serde(rename = "file")]
+    File { file: FileData },
+    #[serde(rename = "input_audio")]
+    InputAudio { input_audio: InputAudio },
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
@@ -345,20 +557,36 @@ pub struct ImageUrl {
     pub detail: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+/// A file sent inline with a message, like a PDF.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct FileData {
+    pub filename: String,
+    /// The file as a base64 data URI.
+    pub file_data: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct InputAudio {
+    /// The base64-encoded audio.
+    pub data: String,
+    /// `wav` or `mp3`.
+    pub format: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct ToolCall {
     pub id: String,
     #[serde(flatten)]
     pub content: ToolCallContent,
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 #[serde(tag = "type", rename_all = "lowercase")]
 pub enum ToolCallContent {
     Function { function: FunctionContent },
 }
 
-#[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct FunctionContent {
     pub name: String,
     pub arguments: String,
@@ -370,6 +598,10 @@ pub struct ResponseMessageDelta {
     pub content: Option<String>,
     #[serde(default, skip_serializing_if = "is_none_or_empty")]
     pub tool_calls: Option<Vec<ToolCallChunk>>,
+    /// The reasoning of reasoning models on servers that report it separately from the content,
+    /// like vLLM and DeepSeek's API.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
@@ -394,6 +626,44 @@ pub struct Usage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt_tokens_details: Option<PromptTokensDetails>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion_tokens_details: Option<CompletionTokensDetails>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PromptTokensDetails {
+    /// How many of the prompt tokens were read from the provider's prompt cache.
+    #[serde(default)]
+    pub cached_tokens: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CompletionTokensDetails {
+    /// How many of the completion tokens the model spent reasoning.
+    #[serde(default)]
+    pub reasoning_tokens: u32,
+}
+
+impl Usage {
+    /// The prompt tokens that weren't read from the prompt cache.
+    pub fn uncached_prompt_tokens(&self) -> u32 {
+        self.prompt_tokens
+            .saturating_sub(self.cached_prompt_tokens())
+    }
+
+    pub fn cached_prompt_tokens(&self) -> u32 {
+        self.prompt_tokens_details
+            .as_ref()
+            .map_or(0, |details| details.cached_tokens)
+    }
+
+    pub fn reasoning_tokens(&self) -> u32 {
+        self.completion_tokens_details
+            .as_ref()
+            .map_or(0, |details| details.reasoning_tokens)
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -401,6 +671,29 @@ pub struct ChoiceDelta {
     pub index: u32,
     pub delta: ResponseMessageDelta,
     pub finish_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<ChoiceLogprobs>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ChoiceLogprobs {
+    /// The log probabilities of the tokens of the content.
+    #[serde(default)]
+    pub content: Option<Vec<TokenLogprob>>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f32,
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f32,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -412,8 +705,13 @@ pub enum ResponseStreamResult {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ResponseStreamEvent {
+    #[serde(default)]
     pub created: u32,
+    #[serde(default)]
     pub model: String,
+    /// Empty in the final chunk that only reports the request's usage, which some
+    /// OpenAI-compatible servers send without `choices` at all.
+    #[serde(default)]
     pub choices: Vec<ChoiceDelta>,
     pub usage: Option<Usage>,
 }
@@ -430,7 +728,24 @@ pub struct CompletionResponse {
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CompletionChoice {
+    #[serde(default)]
+    pub index: u32,
     pub text: String,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+/// A chunk of a streamed text completion.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CompletionStreamEvent {
+    #[serde(default)]
+    pub created: u64,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub choices: Vec<CompletionChoice>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -448,6 +763,8 @@ pub struct Choice {
     pub index: u32,
     pub message: RequestMessage,
     pub finish_reason: Option<String>,
+    #[serde(default)]
+    pub logprobs: Option<ChoiceLogprobs>,
 }
 
 pub async fn complete(
@@ -455,17 +772,32 @@ pub async fn complete(
     api_url: &str,
     api_key: &str,
     request: Request,
+) -> Result<Response> {
+    complete_for_account(client, api_url, api_key, &Account::default(), request).await
+}
+
+pub async fn complete_for_account(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    account: &Account,
+    request: Request,
 ) -> Result<Response> {
     let uri = format!("{api_url}/chat/completions");
-    let request_builder = HttpRequest::builder()
+    let mut request_builder = HttpRequest::builder()
         .method(Method::POST)
         .uri(uri)
         .header("Content-Type", "application/json")
         .header("Authorization", format!("Bearer {}", api_key));
+    for (name, value) in account.headers() {
+        request_builder = request_builder.header(name, value);
+    }
 
     let mut request_body = request;
     request_body.stream = false;
+    request_body.stream_options = None;
 
+    let request_body = with_extra_body(&request_body, &request_body.extra_body)?;
     let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request_body)?))?;
     let mut response = client.send(request).await?;
 
@@ -551,7 +883,118 @@ pub async fn complete_text(
     }
 }
 
-fn adapt_response_to_stream(response: Response) -> ResponseStreamEvent {
+/// Streams a text completion of `request.prompt` from `/completions`, which servers of
+/// completion-style models serve, as chat completion events, so that they're handled like chat
+/// completions. When `request.stream` is false, the whole completion arrives as one event.
+pub async fn stream_text_completion(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    account: &Account,
+    request: CompletionRequest,
+) -> Result<BoxStream<'static, Result<ResponseStreamEvent>>> {
+    let uri = format!("{api_url}/completions");
+    let mut request_builder = HttpRequest::builder()
+        .method(Method::POST)
+        .uri(uri)
+        .header("Content-Type", "application/json")
+        .header("Authorization", format!("Bearer {}", api_key));
+    for (name, value) in account.headers() {
+        request_builder = request_builder.header(name, value);
+    }
+
+    let stream = request.stream;
+    let request_body = with_extra_body(&request, &request.extra_body)?;
+    let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request_body)?))?;
+    let mut response = client.send(request).await?;
+    if !response.status().is_success() {
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+
+        #[derive(Deserialize)]
+        struct OpenAiResponse {
+            error: OpenAiError,
+        }
+
+        #[derive(Deserialize)]
+        struct OpenAiError {
+            message: String,
+        }
+
+        match serde_json::from_str::<OpenAiResponse>(&body) {
+            Ok(response) if !response.error.message.is_empty() => anyhow::bail!(
+                "Failed to connect to OpenAI API: {}",
+                response.error.message,
+            ),
+            _ => anyhow::bail!(
+                "Failed to connect to OpenAI API: {} {}",
+                response.status(),
+                body,
+            ),
+        }
+    }
+
+    if !stream {
+        let mut body = String::new();
+        response.body_mut().read_to_string(&mut body).await?;
+        let response: CompletionResponse = serde_json::from_str(&body)?;
+        let event = adapt_completion_to_stream(CompletionStreamEvent {
+            created: response.created,
+            model: response.model,
+            choices: response.choices,
+            usage: Some(response.usage),
+        });
+        return Ok(stream::once(future::ready(Ok(event))).boxed());
+    }
+
+    let reader = BufReader::new(response.into_body());
+    Ok(reader
+        .lines()
+        .filter_map(|line| async move {
+            match line {
+                Ok(line) => {
+                    let line = line.strip_prefix("data: ")?;
+                    if line == "[DONE]" {
+                        return None;
+                    }
+                    match serde_json::from_str::<CompletionStreamEvent>(line) {
+                        Ok(event) => Some(Ok(adapt_completion_to_stream(event))),
+                        Err(error) => Some(Err(anyhow!(error))),
+                    }
+                }
+                Err(error) => Some(Err(anyhow!(error))),
+            }
+        })
+        .boxed())
+}
+
+/// Turns a chunk of a text completion into a chat completion chunk whose content is its text.
+pub fn adapt_completion_to_stream(event: CompletionStreamEvent) -> ResponseStreamEvent {
+    ResponseStreamEvent {
+        created: event.created as u32,
+        model: event.model,
+        choices: event
+            .choices
+            .into_iter()
+            .map(|choice| ChoiceDelta {
+                index: choice.index,
+                delta: ResponseMessageDelta {
+                    role: None,
+                    content: Some(choice.text),
+                    tool_calls: None,
+                    reasoning_content: None,
+                },
+                finish_reason: choice.finish_reason,
+                logprobs: None,
+            })
+            .collect(),
+        usage: event.usage,
+    }
+}
+
+/// Turns a complete response into a single stream event, so that non-streaming completions are
+/// handled like streaming ones.
+pub fn adapt_response_to_stream(response: Response) -> ResponseStreamEvent {
     ResponseStreamEvent {
         created: response.created as u32,
         model: response.model,
@@ -563,6 +1006,7 @@ fn adapt_response_to_stream(response: Response) -> ResponseStreamEvent {
                     RequestMessage::Assistant { content, .. } => content.as_ref(),
                     RequestMessage::User { content } => Some(content),
                     RequestMessage::System { content } => Some(content),
+                    RequestMessage::Developer { content } => Some(content),
                     RequestMessage::Tool { content, .. } => Some(content),
                 };
 
@@ -573,30 +1017,56 @@ fn adapt_response_to_stream(response: Response) -> ResponseStreamEvent {
                         for part in parts {
                             match part {
                                 MessagePart::Text { text } => text_content.push_str(&text),
-                                MessagePart::Image { .. } => {}
+                                MessagePart::Image { .. }
+                                | MessagePart::File { .. }
+                                | MessagePart::InputAudio { .. } => {}
                             }
                         }
                     }
                     None => {}
                 };
 
+                let (role, tool_calls) = match choice.message {
+                    RequestMessage::Assistant { tool_calls, .. } => (Role::Assistant, tool_calls),
+                    RequestMessage::User { .. } => (Role::User, Vec::new()),
+                    RequestMessage::System { .. } => (Role::System, Vec::new()),
+                    RequestMessage::Developer { .. } => (Role::Developer, Vec::new()),
+                    RequestMessage::Tool { .. } => (Role::Tool, Vec::new()),
+                };
+                let tool_calls = tool_calls
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, tool_call)| {
+                        let ToolCallContent::Function { function } = tool_call.content;
+                        ToolCallChunk {
+                            index,
+                            id: Some(tool_call.id),
+                            function: Some(FunctionChunk {
+                                name: Some(function.name),
+                                arguments: Some(function.arguments),
+                            }),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+
                 ChoiceDelta {
                     index: choice.index,
                     delta: ResponseMessageDelta {
-                        role: Some(match choice.message {
-                            RequestMessage::Assistant { .. } => Role::Assistant,
-                            RequestMessage::User { .. } => Role::User,
-                            RequestMessage::System { .. } => Role::System,
-                            RequestMessage::Tool { .. } => Role::Tool,
-                        }),
+                        role: Some(role),
                         content: if text_content.is_empty() {
                             None
                         } else {
                             Some(text_content)
                         },
-                        tool_calls: None,
+                        reasoning_content: None,
+                        tool_calls: if tool_calls.is_empty() {
+                            None
+                        } else {
+                            Some(tool_calls)
+                        },
                     },
                     finish_reason: choice.finish_reason,
+                    logprobs: choice.logprobs,
                 }
             })
             .collect(),
@@ -610,20 +1080,34 @@ pub async fn stream_completion(
     api_key: &str,
     request: Request,
 ) -> Result<BoxStream<'static, Result<ResponseStreamEvent>>> {
-    if request.model.starts_with("o1") {
-        let response = complete(client, api_url, api_key, request).await;
+    stream_completion_for_account(client, api_url, api_key, &Account::default(), request).await
+}
+
+pub async fn stream_completion_for_account(
+    client: &dyn HttpClient,
+    api_url: &str,
+    api_key: &str,
+    account: &Account,
+    request: Request,
+) -> Result<BoxStream<'static, Result<ResponseStreamEvent>>> {
+    if !request.stream || request.model.starts_with("o1") {
+        let response = complete_for_account(client, api_url, api_key, account, request).await;
         let response_stream_event = response.map(adapt_response_to_stream);
         return Ok(stream::once(future::ready(response_stream_event)).boxed());
     }
 
     let uri = format!("{api_url}/chat/completions");
-    let request_builder = HttpRequest::builder()
+    let mut request_builder = HttpRequest::builder()
         .method(Method::POST)
         .uri(uri)
         .header("Content-Type", "application/json")
         .header("Authorization", format!("Bearer {}", api_key));
+    for (name, value) in account.headers() {
+        request_builder = request_builder.header(name, value);
+    }
 
-    let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request)?))?;
+    let request_body = with_extra_body(&request, &request.extra_body)?;
+    let request = request_builder.body(AsyncBody::from(serde_json::to_string(&request_body)?))?;
     let mut response = client.send(request).await?;
     if response.status().is_success() {
         let reader = BufReader::new(response.into_body());
@@ -686,9 +1170,18 @@ pub enum OpenAiEmbeddingModel {
     TextEmbedding3Large,
 }
 
+impl OpenAiEmbeddingModel {
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::TextEmbedding3Small => "text-embedding-3-small",
+            Self::TextEmbedding3Large => "text-embedding-3-large",
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct OpenAiEmbeddingRequest<'a> {
-    model: OpenAiEmbeddingModel,
+    model: &'a str,
     input: Vec<&'a str>,
 }
 
@@ -700,13 +1193,30 @@ pub struct OpenAiEmbeddingResponse {
 #[derive(Deserialize)]
 pub struct OpenAiEmbedding {
     pub embedding: Vec<f32>,
+    /// The position of the embedded text in the request.
+    #[serde(default)]
+    pub index: usize,
+}
+
+/// Serializes `request` with the parameters in `extra_body`, which replace any of the same name.
+pub(crate) fn with_extra_body(
+    request: &impl Serialize,
+    extra_body: &serde_json::Map<String, Value>,
+) -> Result<Value> {
+    let mut body = serde_json::to_value(request)?;
+    if let Value::Object(body) = &mut body {
+        body.extend(extra_body.clone());
+    }
+    Ok(body)
 }
 
+/// Embeds `texts` with `/embeddings`, which xAI's OpenAI-compatible API serves too.
 pub fn embed<'a>(
     client: &dyn HttpClient,
     api_url: &str,
     api_key: &str,
-    model: OpenAiEmbeddingModel,
+    account: &Account,
+    model: &str,
     texts: impl IntoIterator<Item = &'a str>,
 ) -> impl 'static + Future<Output = Result<OpenAiEmbeddingResponse>> {
     let uri = format!("{api_url}/embeddings");
@@ -715,13 +1225,17 @@ pub fn embed<'a>(
         model,
         input: texts.into_iter().collect(),
     };
-    let body = AsyncBody::from(serde_json::to_string(&request).unwrap());
-    let request = HttpRequest::builder()
+    let mut request_builder = HttpRequest::builder()
         .method(Method::POST)
         .uri(uri)
         .header("Content-Type", "application/json")
-        .header("Authorization", format!("Bearer {}", api_key))
-        .body(body)
+        .header("Authorization", format!("Bearer {}", api_key));
+    for (name, value) in account.headers() {
+        request_builder = request_builder.header(name, value);
+    }
+    let request = serde_json::to_string(&request)
+        .map_err(anyhow::Error::from)
+        .and_then(|body| Ok(request_builder.body(AsyncBody::from(body))?))
         .map(|request| client.send(request));
 
     async move {
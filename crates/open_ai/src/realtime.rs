@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{Account, Role};
+
+pub const DEFAULT_REALTIME_MODEL: &str = "gpt-4o-realtime-preview";
+
+/// The WebSocket URL of a Realtime session with `model`, like
+/// `wss://api.openai.com/v1/realtime?model=gpt-4o-realtime-preview`.
+pub fn realtime_url(api_url: &str, model: &str) -> String {
+    let api_url = api_url.trim_end_matches('/');
+    let api_url = if let Some(rest) = api_url.strip_prefix("https://") {
+        format!("wss://{rest}")
+    } else if let Some(rest) = api_url.strip_prefix("http://") {
+        format!("ws://{rest}")
+    } else {
+        api_url.to_string()
+    };
+    format!("{api_url}/realtime?model={model}")
+}
+
+/// The headers of a Realtime session's WebSocket handshake.
+pub fn realtime_headers(api_key: &str, account: &Account) -> Vec<(&'static str, String)> {
+    let mut headers = vec![
+        ("Authorization", format!("Bearer {api_key}")),
+        ("OpenAI-Beta", "realtime=v1".to_string()),
+    ];
+    headers.extend(
+        account
+            .headers()
+            .map(|(name, value)| (name, value.to_string())),
+    );
+    headers
+}
+
+/// An event that the client sends over a Realtime session.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum ClientEvent {
+    #[serde(rename = "session.update")]
+    SessionUpdate { session: SessionConfig },
+    #[serde(rename = "conversation.item.create")]
+    ConversationItemCreate { item: ConversationItem },
+    /// Adds base64-encoded audio, in the session's input format, to what the user is saying.
+    #[serde(rename = "input_audio_buffer.append")]
+    InputAudioBufferAppend { audio: String },
+    /// Ends what the user is saying, for sessions without turn detection.
+    #[serde(rename = "input_audio_buffer.commit")]
+    InputAudioBufferCommit,
+    #[serde(rename = "input_audio_buffer.clear")]
+    InputAudioBufferClear,
+    #[serde(rename = "response.create")]
+    ResponseCreate {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        response: Option<ResponseConfig>,
+    },
+    /// Stops the response that's in progress.
+    #[serde(rename = "response.cancel")]
+    ResponseCancel,
+    /// Cuts an assistant's audio off where playback stopped, so that the model knows what the
+    /// user didn't hear.
+    #[serde(rename = "conversation.item.truncate")]
+    ConversationItemTruncate {
+        item_id: String,
+        content_index: usize,
+        audio_end_ms: u64,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Modality {
+    Text,
+    Audio,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SessionConfig {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modalities: Vec<Modality>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instructions: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voice: Option<String>,
+    /// `pcm16`, `g711_ulaw`, or `g711_alaw`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_audio_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_audio_format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub turn_detection: Option<TurnDetection>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<Tool>,
+    /// `auto`, `none`, `required`, or the name of a function.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<String>,
+    /// Between 0.6 and 1.2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_response_output_tokens: Option<u32>,
+}
+
+/// Detects when the user starts and stops speaking, so that responses are created without
+/// committing the audio buffer.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TurnDetection {
+    ServerVad {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        silence_duration_ms: Option<u32>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Tool {
+    Function {
+        name: String,
+        description: String,
+        parameters: Value,
+    },
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ResponseConfig {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modalities: Vec<Modality>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConversationItem {
+    Message {
+        role: Role,
+        content: Vec<ItemContent>,
+    },
+    FunctionCall {
+        call_id: String,
+        name: String,
+        arguments: String,
+    },
+    FunctionCallOutput {
+        call_id: String,
+        output: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ItemContent {
+    InputText {
+        text: String,
+    },
+    /// Base64-encoded audio, in the session's input format.
+    InputAudio {
+        audio: String,
+    },
+    /// Text that the assistant said earlier in the conversation.
+    Text {
+        text: String,
+    },
+}
+
+/// An event that the server sends over a Realtime session.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum ServerEvent {
+    #[serde(rename = "error")]
+    Error { error: RealtimeError },
+    #[serde(rename = "session.created")]
+    SessionCreated,
+    #[serde(rename = "session.updated")]
+    SessionUpdated,
+    #[serde(rename = "input_audio_buffer.speech_started")]
+    SpeechStarted {
+        item_id: String,
+        audio_start_ms: u64,
+    },
+    #[serde(rename = "input_audio_buffer.speech_stopped")]
+    SpeechStopped { item_id: String, audio_end_ms: u64 },
+    #[serde(rename = "response.created")]
+    ResponseCreated { response: Response },
+    #[serde(rename = "response.text.delta")]
+    TextDelta { item_id: String, delta: String },
+    /// Base64-encoded audio, in the session's output format.
+    #[serde(rename = "response.audio.delta")]
+    AudioDelta {
+        item_id: String,
+        content_index: usize,
+        delta: String,
+    },
+    /// Text of what the assistant is saying, as it's said.
+    #[serde(rename = "response.audio_transcript.delta")]
+    AudioTranscriptDelta { item_id: String, delta: String },
+    #[serde(rename = "response.function_call_arguments.delta")]
+    FunctionCallArgumentsDelta {
+        item_id: String,
+        call_id: String,
+        delta: String,
+    },
+    #[serde(rename = "response.output_item.added")]
+    OutputItemAdded { item: OutputItem },
+    #[serde(rename = "response.output_item.done")]
+    OutputItemDone { item: OutputItem },
+    #[serde(rename = "response.done")]
+    ResponseDone { response: Response },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RealtimeError {
+    pub message: String,
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputItem {
+    FunctionCall {
+        id: String,
+        call_id: String,
+        name: String,
+        #[serde(default)]
+        arguments: String,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Response {
+    pub id: String,
+    /// `in_progress`, `completed`, `cancelled`, `incomplete`, or `failed`.
+    pub status: String,
+    #[serde(default)]
+    pub status_details: Option<StatusDetails>,
+    #[serde(default)]
+    pub usage: Option<Usage>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatusDetails {
+    /// Why the response was cut short, like `max_output_tokens` or `turn_detected`.
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub error: Option<RealtimeError>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Usage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    #[serde(default)]
+    pub input_token_details: Option<InputTokenDetails>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InputTokenDetails {
+    #[serde(default)]
+    pub cached_tokens: u32,
+}
+
+impl Usage {
+    pub fn cached_input_tokens(&self) -> u32 {
+        self.input_token_details
+            .as_ref()
+            .map_or(0, |details| details.cached_tokens)
+    }
+
+    pub fn uncached_input_tokens(&self) -> u32 {
+        self.input_tokens.saturating_sub(self.cached_input_tokens())
+    }
+}
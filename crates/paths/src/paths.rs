@@ -362,6 +362,14 @@ pub fn supermaven_dir() -> &'static PathBuf {
     SUPERMAVEN_DIR.get_or_init(|| data_dir().join("supermaven"))
 }
 
+/// Returns the path to the local language models directory.
+///
+/// This is where model weights used by the in-process local provider are downloaded to.
+pub fn local_models_dir() -> &'static PathBuf {
+    static LOCAL_MODELS_DIR: OnceLock<PathBuf> = OnceLock::new();
+    LOCAL_MODELS_DIR.get_or_init(|| data_dir().join("models"))
+}
+
 /// Returns the path to the default Prettier directory.
 pub fn default_prettier_dir() -> &'static PathBuf {
     static DEFAULT_PRETTIER_DIR: OnceLock<PathBuf> = OnceLock::new();
@@ -12,6 +12,7 @@ use reqwest::{
     redirect,
 };
 use smol::future::FutureExt;
+use util::defer;
 
 const DEFAULT_CAPACITY: usize = 4096;
 static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
@@ -45,6 +46,16 @@ impl ReqwestClient {
     }
 
     pub fn proxy_and_user_agent(proxy: Option<Url>, agent: &str) -> anyhow::Result<Self> {
+        Self::proxy_user_agent_and_tls(proxy, agent, http_client_tls::tls_config())
+    }
+
+    /// Like [`Self::proxy_and_user_agent`], but with a custom TLS configuration, e.g. one that
+    /// trusts an internal certificate authority.
+    pub fn proxy_user_agent_and_tls(
+        proxy: Option<Url>,
+        agent: &str,
+        tls_config: http_client_tls::ClientConfig,
+    ) -> anyhow::Result<Self> {
         let mut map = HeaderMap::new();
         map.insert(http::header::USER_AGENT, HeaderValue::from_str(agent)?);
         let mut client = Self::builder().default_headers(map);
@@ -67,9 +78,7 @@ impl ReqwestClient {
             client_has_proxy = false;
         };
 
-        let client = client
-            .use_preconfigured_tls(http_client_tls::tls_config())
-            .build()?;
+        let client = client.use_preconfigured_tls(tls_config).build()?;
         let mut client: ReqwestClient = client.into();
         client.proxy = client_has_proxy.then_some(proxy).flatten();
         Ok(client)
@@ -243,10 +252,12 @@ impl http_client::HttpClient for ReqwestClient {
 
         let handle = self.handle.clone();
         async move {
-            let mut response = handle
-                .spawn(async { request.send().await })
-                .await?
-                .map_err(redact_error)?;
+            // Dropping the returned future aborts the request, instead of leaving it running on the
+            // Tokio runtime until the server responds.
+            let task = handle.spawn(async { request.send().await });
+            let abort_handle = task.abort_handle();
+            let _abort_on_drop = defer(move || abort_handle.abort());
+            let mut response = task.await?.map_err(redact_error)?;
 
             let headers = mem::take(response.headers_mut());
             let mut builder = http::Response::builder()
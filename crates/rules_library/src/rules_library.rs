@@ -934,6 +934,19 @@ impl RulesLibrary {
                                     tool_choice: None,
                                     stop: Vec::new(),
                                     temperature: None,
+                                    seed: None,
+                                    frequency_penalty: None,
+                                    presence_penalty: None,
+                                    logit_bias: Default::default(),
+                                    top_p: None,
+                                    top_k: None,
+                                    candidates: None,
+                                    logprobs: None,
+                                    response_schema: None,
+                                    parallel_tool_calls: None,
+                                    prediction: None,
+                                    batch: false,
+                                    workspace: None,
                                 },
                                 cx,
                             )
@@ -34,7 +34,8 @@ impl EmbeddingProvider for OpenAiEmbeddingProvider {
             self.client.as_ref(),
             &self.api_url,
             &self.api_key,
-            self.model,
+            &open_ai::Account::default(),
+            self.model.id(),
             texts.iter().map(|to_embed| to_embed.text),
         );
         async move {
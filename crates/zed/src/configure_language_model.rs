@@ -0,0 +1,145 @@
+use std::io::{self, IsTerminal as _, Read as _, Write as _};
+use std::process;
+use std::sync::Arc;
+
+use anyhow::{Context as _, Result};
+use client::ProxySettings;
+use gpui::{Application, SemanticVersion, UpdateGlobal as _};
+use gpui_tokio::Tokio;
+use http_client::{Url, read_proxy_from_env};
+use language_models::AllLanguageModelSettings;
+use language_models::api_key::ApiKeys;
+use language_models::configure::{CONFIGURABLE_PROVIDERS, ConfigurableProvider};
+use reqwest_client::ReqwestClient;
+use settings::{Settings as _, SettingsStore};
+
+/// Saves a language model provider's API key entered in the terminal, for machines where the
+/// provider's configuration view can't be opened. When stdin isn't a terminal, the key is read
+/// from it instead, so that it can be piped in by scripts.
+pub fn run(provider_id: &str, app_version: SemanticVersion) {
+    let Some(provider) = ConfigurableProvider::find(provider_id) else {
+        let provider_ids = CONFIGURABLE_PROVIDERS
+            .iter()
+            .map(|provider| provider.id)
+            .collect::<Vec<_>>()
+            .join(", ");
+        eprintln!(
+            "Unknown language model provider {provider_id:?}. Expected one of: {provider_ids}"
+        );
+        process::exit(1);
+    };
+
+    let api_keys = match read_api_keys(provider) {
+        Ok(api_keys) if !api_keys.is_empty() => api_keys,
+        Ok(_) => {
+            eprintln!("No API key was entered.");
+            process::exit(1);
+        }
+        Err(error) => {
+            eprintln!("Failed to read the API key: {error:#}");
+            process::exit(1);
+        }
+    };
+
+    Application::headless().run(move |cx| {
+        release_channel::init(app_version, cx);
+        gpui_tokio::init(cx);
+        settings::init(cx);
+        client::init_settings(cx);
+        AllLanguageModelSettings::register(cx);
+
+        if let Ok(user_settings) = std::fs::read_to_string(paths::settings_file()) {
+            if let Err(error) = SettingsStore::update_global(cx, |store, cx| {
+                store.set_user_settings(&user_settings, cx)
+            }) {
+                eprintln!("Ignoring invalid settings file: {error:#}");
+            }
+        }
+
+        let proxy_url = ProxySettings::get_global(cx)
+            .proxy
+            .as_ref()
+            .and_then(|proxy| proxy.parse::<Url>().ok())
+            .or_else(read_proxy_from_env);
+        let user_agent = format!(
+            "Zed/{} ({}; {})",
+            app_version,
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+        let http = {
+            let _guard = Tokio::handle(cx).enter();
+            ReqwestClient::proxy_and_user_agent(proxy_url, &user_agent)
+        };
+        match http {
+            Ok(http) => cx.set_http_client(Arc::new(http)),
+            Err(error) => {
+                eprintln!("Failed to start the HTTP client: {error:#}");
+                process::exit(1);
+            }
+        }
+
+        let save = provider.save_api_keys(api_keys, cx);
+        cx.spawn(async move |_| match save.await {
+            Ok(warning) => {
+                if let Some(warning) = warning {
+                    eprintln!("{warning}");
+                }
+                println!("Saved the {} API key.", provider.name);
+                process::exit(0);
+            }
+            Err(error) => {
+                eprintln!("Failed to save the {} API key: {error:#}", provider.name);
+                process::exit(1);
+            }
+        })
+        .detach();
+    });
+}
+
+fn read_api_keys(provider: &ConfigurableProvider) -> Result<ApiKeys> {
+    let mut stdin = io::stdin();
+    if !stdin.is_terminal() {
+        let mut input = String::new();
+        stdin.read_to_string(&mut input)?;
+        return Ok(ApiKeys::parse(&input));
+    }
+
+    eprint!("{} API key: ", provider.name);
+    io::stderr().flush()?;
+    let input = {
+        let _echo = hide_input();
+        let mut input = String::new();
+        stdin
+            .read_line(&mut input)
+            .context("failed to read stdin")?;
+        input
+    };
+    eprintln!();
+    Ok(ApiKeys::parse(&input))
+}
+
+/// Stops the terminal from echoing what's typed until the returned guard is dropped.
+#[cfg(unix)]
+fn hide_input() -> Option<impl Drop> {
+    use nix::sys::termios::{LocalFlags, SetArg, Termios, tcgetattr, tcsetattr};
+
+    struct RestoreTerminal(Termios);
+
+    impl Drop for RestoreTerminal {
+        fn drop(&mut self) {
+            tcsetattr(io::stdin(), SetArg::TCSANOW, &self.0).ok();
+        }
+    }
+
+    let original = tcgetattr(io::stdin()).ok()?;
+    let mut hidden = original.clone();
+    hidden.local_flags.remove(LocalFlags::ECHO);
+    tcsetattr(io::stdin(), SetArg::TCSANOW, &hidden).ok()?;
+    Some(RestoreTerminal(original))
+}
+
+#[cfg(not(unix))]
+fn hide_input() -> Option<()> {
+    None
+}
@@ -1,6 +1,7 @@
 // Disable command line from opening on release mode
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod configure_language_model;
 mod reliability;
 mod zed;
 
@@ -50,9 +51,9 @@ use welcome::{BaseKeymap, FIRST_OPEN, show_welcome_view};
 use workspace::{AppState, SerializedWorkspaceLocation, WorkspaceSettings, WorkspaceStore};
 use zed::{
     OpenListener, OpenRequest, app_menus, build_window_options, derive_paths_with_position,
-    handle_cli_connection, handle_keymap_file_changes, handle_settings_changed,
-    handle_settings_file_changes, initialize_workspace, inline_completion_registry,
-    open_paths_with_positions,
+    handle_cli_connection, handle_keychain_fallback, handle_keymap_file_changes,
+    handle_settings_changed, handle_settings_file_changes, initialize_workspace,
+    inline_completion_registry, open_paths_with_positions,
 };
 
 #[cfg(feature = "mimalloc")]
@@ -247,6 +248,11 @@ Error: Running Zed as root or via sudo is unsupported.
         return;
     }
 
+    if let Some(provider_id) = &args.configure_language_model {
+        configure_language_model::run(provider_id, app_version);
+        return;
+    }
+
     log::info!("========== starting zed ==========");
 
     let app = Application::new().with_assets(Assets);
@@ -370,6 +376,7 @@ Error: Running Zed as root or via sudo is unsupported.
             handle_settings_changed,
         );
         handle_keymap_file_changes(user_keymap_file_rx, cx);
+        handle_keychain_fallback(cx);
         client::init_settings(cx);
         let user_agent = format!(
             "Zed/{} ({}; {})",
@@ -1050,6 +1057,11 @@ struct Args {
     #[arg(long)]
     system_specs: bool,
 
+    /// Saves an API key for a language model provider (e.g. `anthropic` or `openai`) entered in
+    /// the terminal, or piped in through stdin, without opening a window.
+    #[arg(long, value_name = "PROVIDER")]
+    configure_language_model: Option<String>,
+
     /// Used for SSH/Git password authentication, to remove the need for netcat as a dependency,
     /// by having Zed act like netcat communicating over a Unix socket.
     #[arg(long, hide = true)]
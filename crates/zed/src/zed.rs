@@ -17,6 +17,7 @@ use assistant_context_editor::AgentPanelDelegate;
 use breadcrumbs::Breadcrumbs;
 use client::zed_urls;
 use collections::VecDeque;
+use credentials_provider::KeychainFallback;
 use debugger_ui::debugger_panel::DebugPanel;
 use editor::ProposedChangesEditorToolbar;
 use editor::{Editor, MultiBuffer, scroll::Autoscroll};
@@ -40,7 +41,7 @@ use paths::{
     local_debug_file_relative_path, local_settings_file_relative_path,
     local_tasks_file_relative_path,
 };
-use project::{DirectoryLister, ProjectItem};
+use project::{DirectoryLister, Project, ProjectItem};
 use project_panel::ProjectPanel;
 use prompt_store::PromptBuilder;
 use quick_action_bar::QuickActionBar;
@@ -261,6 +262,26 @@ pub fn initialize_workspace(
                 .unwrap_or(true)
         });
 
+        let window_id = window.window_handle().window_id();
+        let set_workspace_scope = move |project: &Entity<Project>, cx: &mut App| {
+            let scope = language_models::api_key::workspace_scope(project.read(cx), cx);
+            language_models::api_key::set_window_workspace_scope(window_id, scope, cx);
+        };
+        set_workspace_scope(workspace.project(), cx);
+        cx.subscribe(workspace.project(), move |_, project, event, cx| {
+            if matches!(
+                event,
+                project::Event::WorktreeAdded(_) | project::Event::WorktreeRemoved(_)
+            ) {
+                set_workspace_scope(&project, cx);
+            }
+        })
+        .detach();
+        cx.on_release(move |_, cx| {
+            language_models::api_key::set_window_workspace_scope(window_id, None, cx)
+        })
+        .detach();
+
         initialize_panels(prompt_builder.clone(), window, cx);
         register_actions(app_state.clone(), workspace, window, cx);
 
@@ -1443,6 +1464,32 @@ pub fn handle_settings_changed(error: Option<anyhow::Error>, cx: &mut App) {
     }
 }
 
+/// Tells the user, once per session, when their credentials were stored in the encrypted file
+/// because the system keychain couldn't be accessed.
+pub fn handle_keychain_fallback(cx: &mut App) {
+    struct KeychainFallbackNotification;
+    let mut notified = false;
+    cx.observe_global::<KeychainFallback>(move |cx| {
+        if notified {
+            return;
+        }
+        notified = true;
+        let fallback = cx.global::<KeychainFallback>();
+        let message = format!(
+            "The system keychain couldn't be accessed, so credentials are stored in {} instead, \
+            encrypted with ZED_CREDENTIALS_PASSPHRASE.\n\n{}",
+            fallback.path.display(),
+            fallback.error
+        );
+        show_app_notification(
+            NotificationId::unique::<KeychainFallbackNotification>(),
+            cx,
+            move |cx| cx.new(|cx| MessageNotification::new(message.clone(), cx)),
+        );
+    })
+    .detach();
+}
+
 pub fn open_new_ssh_project_from_project(
     workspace: &mut Workspace,
     paths: Vec<PathBuf>,